@@ -0,0 +1,53 @@
+use super::*;
+use distinst::auto::{
+    EraseOption, InstallOption, InstallOptions, TargetStrategy, MEETS_REQUIREMENTS,
+};
+use errors::DistinstError;
+
+/// Wipes the device which the target strategy selects, and applies the default layout to it.
+pub(crate) fn erase(
+    disks: &mut Disks,
+    target: &str,
+    password: Option<String>,
+    required: u64,
+) -> Result<(), DistinstError> {
+    let disk = select_target(&target.parse()?, required)?;
+    eprintln!("distinst: configuring {} to be erased", disk.get_device_path().display());
+    let option = EraseOption {
+        device:  disk.get_device_path().to_path_buf(),
+        model:   disk.get_model().to_owned(),
+        sectors: disk.get_sectors(),
+        flags:   MEETS_REQUIREMENTS,
+    };
+
+    InstallOption::Erase { option: &option, password }.apply(disks)?;
+    Ok(())
+}
+
+/// Obtains the disk which the strategy selects from the disks of the system.
+fn select_target(strategy: &TargetStrategy, required: u64) -> Result<Disk, DistinstError> {
+    if let Some(selector) = strategy.selector() {
+        return Disk::from_selector(selector).map_err(Into::into);
+    }
+
+    let disks = Disks::probe_devices()?;
+    let path = strategy.select(&disks, required)?.get_device_path().to_path_buf();
+    eprintln!("distinst: {} selected {}", strategy, path.display());
+    Disk::from_name(&path).map_err(Into::into)
+}
+
+/// Reinstalls to an existing install, whose root partition has the given UUID.
+pub(crate) fn refresh(disks: &mut Disks, root: &str, required: u64) -> Result<(), DistinstError> {
+    eprintln!("distinst: configuring refresh of {}", root);
+    *disks = Disks::probe_devices()?;
+
+    let options = InstallOptions::new(disks, required, 0);
+    let option = options
+        .refresh_options
+        .iter()
+        .find(|option| option.root_part == root)
+        .ok_or_else(|| DistinstError::RefreshNotFound { root: root.into() })?;
+
+    InstallOption::Refresh(option).apply(disks)?;
+    Ok(())
+}
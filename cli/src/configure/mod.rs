@@ -1,3 +1,4 @@
+mod auto;
 mod decrypt;
 mod lvm;
 mod moved;
@@ -6,20 +7,42 @@ mod removed;
 mod reuse;
 mod table;
 
-use self::{decrypt::*, lvm::*, moved::*, new::*, removed::*, reuse::*, table::*};
+use self::{auto::*, decrypt::*, lvm::*, moved::*, new::*, removed::*, reuse::*, table::*};
 
 use super::*;
 use errors::DistinstError;
 
-pub(crate) fn configure_disks(matches: &ArgMatches) -> Result<Disks, DistinstError> {
+pub(crate) fn configure_disks(
+    matches: &ArgMatches,
+    config: &Config,
+) -> Result<Disks, DistinstError> {
     let mut disks = Disks::default();
 
+    if let Some(devices) = matches.values_of("protect") {
+        let devices = devices.map(str::parse).collect::<Result<Vec<ProtectedDevice>, _>>()?;
+        protect_devices(devices);
+    }
+
+    if let Some(device) = matches.value_of("erase") {
+        let password = matches.value_of("encrypt-password").map(String::from);
+        let required = estimated_install_size(config).total_sectors();
+        erase(&mut disks, device, password, required)?;
+        return Ok(disks);
+    }
+
+    if let Some(root) = matches.value_of("refresh") {
+        let required = estimated_install_size(config).total_sectors();
+        refresh(&mut disks, root, required)?;
+        repair_gpt(&mut disks, matches)?;
+        return Ok(disks);
+    }
+
     {
         let disks = &mut disks;
 
         for block in matches.values_of("disk").unwrap() {
             eprintln!("distinst: adding {} to disks configuration", block);
-            disks.add(Disk::from_name(block)?);
+            disks.add(Disk::from_selector(&block.parse()?)?);
         }
 
         tables(disks, matches.values_of("table"))
@@ -42,9 +65,21 @@ pub(crate) fn configure_disks(matches: &ArgMatches) -> Result<Disks, DistinstErr
         eprintln!("distinst: disks configured");
     }
 
+    repair_gpt(&mut disks, matches)?;
     Ok(disks)
 }
 
+/// Repairs the backup GPT headers of the configured disks, if they are damaged, and if asked to.
+fn repair_gpt(disks: &mut Disks, matches: &ArgMatches) -> Result<(), DistinstError> {
+    if matches.is_present("repair-gpt") {
+        for (device, damage) in disks.repair_gpt()? {
+            eprintln!("distinst: repaired {}: {}", device.display(), damage);
+        }
+    }
+
+    Ok(())
+}
+
 fn initialize_logical(disks: &mut Disks) -> Result<(), DistinstError> {
     eprintln!("distinst: initializing LVM groups");
     disks.initialize_volume_groups().map_err(|why| DistinstError::InitializeVolumes { why })
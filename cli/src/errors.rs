@@ -1,4 +1,5 @@
 use super::*;
+use distinst::auto::InstallOptionError;
 
 #[derive(Debug, Fail)]
 pub(crate) enum DistinstError {
@@ -14,12 +15,22 @@ pub(crate) enum DistinstError {
     InvalidPartitionType,
     #[fail(display = "decryption argument requires four values")]
     DecryptArgs,
-    #[fail(display = "disk at '{}' could not be found", disk)]
-    DiskNotFound { disk: String },
     #[fail(display = "no block argument provided")]
     NoBlockArg,
     #[fail(display = "argument '{}' is not a number", arg)]
     ArgNaN { arg: String },
+    #[fail(
+        display = "'{}' is not a valid I/O class. Must be 'idle', 'best-effort[:LEVEL]', or \
+                   'realtime[:LEVEL]'.",
+        class
+    )]
+    InvalidIoClass { class: String },
+    #[fail(display = "'{}' is not a valid network argument: {}", arg, why)]
+    InvalidNetwork { arg: String, why: &'static str },
+    #[fail(display = "'{}' is not a valid network mount argument: {}", arg, why)]
+    InvalidNetworkMount { arg: String, why: &'static str },
+    #[fail(display = "'{}' is not a valid old boot policy: {}", policy, why)]
+    InvalidOldBoots { policy: String, why: &'static str },
     #[fail(display = "partition '{}' was not found", partition)]
     PartitionNotFound { partition: i32 },
     #[fail(display = "four arguments must be supplied to the move operation")]
@@ -60,8 +71,24 @@ pub(crate) enum DistinstError {
     LvmPartitionAdd { why: DiskError },
     #[fail(display = "unable to initialize volume groups: {}", why)]
     InitializeVolumes { why: DiskError },
+    #[fail(display = "unable to apply install option: {}", why)]
+    InstallOption { why: InstallOptionError },
+    #[fail(display = "no install to refresh was found with the root partition '{}'", root)]
+    RefreshNotFound { root: String },
+    #[fail(display = "unable to read layout file '{}': {}", path, why)]
+    LayoutRead { path: String, why: io::Error },
+    #[fail(display = "layout is not valid: {}", why)]
+    LayoutInvalid { why: String },
+    #[fail(display = "helper request is not valid: {}", why)]
+    HelperRequest { why: String },
+    #[fail(display = "helper I/O error: {}", why)]
+    HelperIo { why: io::Error },
 }
 
 impl From<DiskError> for DistinstError {
     fn from(why: DiskError) -> DistinstError { DistinstError::Disk { why } }
 }
+
+impl From<InstallOptionError> for DistinstError {
+    fn from(why: InstallOptionError) -> DistinstError { DistinstError::InstallOption { why } }
+}
@@ -0,0 +1,167 @@
+//! A root helper, which performs the privileged operations of an unprivileged frontend.
+//!
+//! The frontend runs as the user, and launches `pkexec distinst helper SOCKET` when the user
+//! is ready to modify their disks. Once the socket exists, the frontend connects to it, and
+//! writes a single request as a line of JSON:
+//!
+//! ```json
+//! {"version": 1, "request": "partition", "layout": {"block": "/dev/sdb", "new-table": "/dev/sdb:gpt"}}
+//! ```
+//!
+//! A `partition` request is performed as `distinst partition apply`, and an `install` request
+//! as `distinst`. The `layout` is a JSON object of the command's arguments, as accepted by
+//! `--layout`. The helper replies with a line of JSON for each status update and error, as
+//! printed with `--json`, followed by a final line with the exit status, such as
+//! `{"exit": 0}`. Closing the connection before then cancels the operation.
+//!
+//! The socket must not exist beforehand, and the frontend should create it within a directory
+//! that only it may write to, which it removes afterwards. Anyone may connect to the socket, but
+//! the helper refuses every connection except those of root and the user which invoked pkexec.
+
+use super::*;
+use serde_json::Value;
+use std::{
+    io::{BufRead, BufReader, Write},
+    mem,
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    process::{Command, Stdio},
+};
+
+/// The version of the protocol, which is incremented whenever it changes incompatibly.
+pub(crate) const PROTOCOL_VERSION: u64 = 1;
+
+/// Serves a single request on a socket created at `path`, and returns the exit status.
+pub(crate) fn serve(path: &str) -> i32 {
+    let client = env::var("PKEXEC_UID").ok().and_then(|uid| uid.parse().ok()).unwrap_or(0);
+
+    let mut stream = match accept(path, client) {
+        Ok(stream) => stream,
+        Err(why) => {
+            eprintln!("distinst: helper: {}", why);
+            return 1;
+        }
+    };
+
+    let status = match run(&mut stream) {
+        Ok(status) => status,
+        Err(why) => {
+            eprintln!("distinst: helper: {}", why);
+            let _ = writeln!(stream, "{}", json!({ "error": why.to_string() }));
+            1
+        }
+    };
+
+    let _ = writeln!(stream, "{}", json!({ "exit": status }));
+    status
+}
+
+/// Creates the socket, and waits for the client to connect to it.
+///
+/// The path is chosen by the client, so nothing at it is removed, and its permissions are not
+/// changed after it was created, as it may have been replaced by then. The socket is instead
+/// created connectable by anyone, and each peer is identified by its credentials.
+fn accept(path: &str, client: libc::uid_t) -> io::Result<UnixStream> {
+    let umask = unsafe { libc::umask(0o111) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    let listener = listener?;
+
+    loop {
+        let (stream, _) = listener.accept()?;
+        match peer_uid(&stream)? {
+            uid if uid == client || uid == 0 => return Ok(stream),
+            uid => eprintln!("distinst: helper: refused connection from uid {}", uid),
+        }
+    }
+}
+
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if result == 0 {
+        Ok(cred.uid)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Reads the request, and forwards the output of the command which performs it.
+fn run(stream: &mut UnixStream) -> Result<i32, DistinstError> {
+    let mut request = String::new();
+    BufReader::new(&*stream)
+        .read_line(&mut request)
+        .map_err(|why| DistinstError::HelperIo { why })?;
+
+    let request: Value = serde_json::from_str(&request)
+        .map_err(|why| DistinstError::HelperRequest { why: why.to_string() })?;
+
+    match request["version"].as_u64() {
+        Some(PROTOCOL_VERSION) => (),
+        version => {
+            return Err(DistinstError::HelperRequest {
+                why: format!("unsupported protocol version: {:?}", version),
+            })
+        }
+    }
+
+    let layout = match request["layout"] {
+        Value::Object(_) => request["layout"].to_string(),
+        _ => {
+            return Err(DistinstError::HelperRequest { why: "layout is not an object".into() })
+        }
+    };
+
+    // The layout is written to the command's standard input, as it may contain passwords.
+    let exe = env::current_exe().map_err(|why| DistinstError::HelperIo { why })?;
+    let mut command = Command::new(exe);
+    match request["request"].as_str() {
+        Some("install") => command.args(&["--json", "--layout", "/dev/stdin"]),
+        Some("partition") => command.args(&["--json", "partition", "apply", "/dev/stdin"]),
+        other => {
+            return Err(DistinstError::HelperRequest {
+                why: format!("unknown request: {:?}", other),
+            })
+        }
+    };
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|why| DistinstError::HelperIo { why })?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(layout.as_bytes())
+        .map_err(|why| DistinstError::HelperIo { why })?;
+
+    let mut cancelled = false;
+    for line in BufReader::new(child.stdout.take().expect("stdout is piped")).lines() {
+        let line = line.map_err(|why| DistinstError::HelperIo { why })?;
+        if !cancelled && writeln!(stream, "{}", line).is_err() {
+            eprintln!("distinst: helper: client disconnected, cancelling");
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+            }
+            cancelled = true;
+        }
+    }
+
+    let status = child.wait().map_err(|why| DistinstError::HelperIo { why })?;
+    Ok(status.code().unwrap_or(1))
+}
@@ -5,11 +5,17 @@ extern crate failure;
 extern crate failure_derive;
 extern crate libc;
 extern crate pbr;
+#[macro_use]
+extern crate serde_json;
 
 mod configure;
 mod errors;
+mod helper;
+mod output;
+mod partition;
+mod repair;
 
-use clap::{App, Arg, ArgMatches, Values};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand, Values};
 use configure::*;
 use distinst::{timezones::Timezones, *};
 use errors::DistinstError;
@@ -18,7 +24,8 @@ use pbr::ProgressBar;
 
 use std::{
     cell::RefCell,
-    io,
+    env, fs, io,
+    net::IpAddr,
     path::{Path, PathBuf},
     process::exit,
     rc::Rc,
@@ -26,7 +33,157 @@ use std::{
 };
 
 fn main() {
-    let matches = App::new("distinst")
+    let args = match expand_layout(env::args().collect()) {
+        Ok(args) => args,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            exit(1);
+        }
+    };
+
+    let app = App::new("distinst")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("lists the disks and partitions on the system")
+                .arg(
+                    Arg::with_name("target")
+                        .long("target")
+                        .help("shows the disk which an --erase strategy would select, instead")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("helper")
+                .about("performs the privileged operations requested by a frontend, over a socket")
+                .arg(
+                    Arg::with_name("SOCKET")
+                        .help("the path of the socket to create and serve")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("partition")
+                .about("partitions disks, without installing a system to them")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("apply")
+                        .about("applies the layout described by a JSON file")
+                        .arg(
+                            Arg::with_name("LAYOUT")
+                                .help("a JSON object of layout arguments, such as \"new\"")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("repair-boot")
+                .about("reinstalls the bootloader and initramfs of an existing install")
+                .arg(
+                    Arg::with_name("ROOT")
+                        .help("the root partition of the install, or its LUKS partition")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("decrypt")
+                        .long("decrypt")
+                        .help("unlocks the LUKS partition, as PV:pass=PASSWORD or PV:keyfile=PATH")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rescue-shell")
+                .about("opens a shell within an existing install, with its file systems mounted")
+                .arg(
+                    Arg::with_name("ROOT")
+                        .help("the root partition of the install, or its LUKS partition")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("decrypt")
+                        .long("decrypt")
+                        .help("unlocks the LUKS partition, as PV:pass=PASSWORD or PV:keyfile=PATH")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("repair-fstab")
+                .about("regenerates the fstab and crypttab of an existing install")
+                .arg(
+                    Arg::with_name("ROOT")
+                        .help("the root partition of the install, or its LUKS partition")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("decrypt")
+                        .long("decrypt")
+                        .help("unlocks the LUKS partition, as PV:pass=PASSWORD or PV:keyfile=PATH")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("write")
+                        .long("write")
+                        .help("writes the changes, rather than only showing them"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("encrypt-root")
+                .about("encrypts the root of an existing install in place, or resumes doing so")
+                .arg(
+                    Arg::with_name("ROOT")
+                        .help("the root partition of the install")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("encrypt")
+                        .long("encrypt")
+                        .help("the passphrase of the root, and the name of its unlocked volume, \
+                               as PV:pass=PASSWORD")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reencrypt")
+                .about("reencrypts a LUKS2 volume with another cipher, or resumes doing so")
+                .arg(
+                    Arg::with_name("DEVICE")
+                        .help("the LUKS partition to reencrypt")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("decrypt")
+                        .long("decrypt")
+                        .help("unlocks the LUKS partition, as PV:pass=PASSWORD")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("cipher")
+                        .long("cipher")
+                        .help("the cipher to reencrypt with, such as aes-xts-plain64")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("key-size")
+                        .long("key-size")
+                        .help("the size of the volume key in bits, such as 512")
+                        .takes_value(true),
+                ),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("prints machine-readable output, as one JSON object per line"),
+        )
+        .arg(
+            Arg::with_name("layout")
+                .long("layout")
+                .help("reads additional arguments from a file, one or more per line, or as JSON")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("username")
                 .long("username")
@@ -41,6 +198,36 @@ fn main() {
                 .requires("username")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("password_hash")
+                .long("password-hash")
+                .help("set the password for the username from its crypt hash")
+                .requires("username")
+                .conflicts_with("password")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("groups")
+                .long("groups")
+                .help("comma-separated groups of the user, in place of adm,sudo,lpadmin")
+                .requires("username")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sudo")
+                .long("sudo")
+                .help("whether the user may use sudo, and whether sudo asks for its password")
+                .requires("username")
+                .possible_values(&["password", "nopasswd", "none"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("expires")
+                .long("expires")
+                .help("disables the account after this date, given as YYYY-MM-DD")
+                .requires("username")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("realname")
                 .long("realname")
@@ -82,7 +269,10 @@ fn main() {
             Arg::with_name("keyboard")
                 .short("k")
                 .long("keyboard")
-                .help("define the keyboard configuration to use")
+                .help(
+                    "define the keyboard configuration to use, as a layout, model, and variant; \
+                     several layouts and variants may be separated by commas, as in XKB",
+                )
                 .takes_value(true)
                 .min_values(1)
                 .max_values(3)
@@ -108,129 +298,603 @@ fn main() {
             Arg::with_name("disk")
                 .short("b")
                 .long("block")
-                .help("defines a disk that will be manipulated in the installation process")
+                .help(
+                    "defines a disk that will be manipulated in the installation process, by its \
+                     path, or by serial=ID, wwn=ID, or model=NAME",
+                )
                 .takes_value(true)
                 .multiple(true)
-                .required(true),
+                .required_unless_one(&["erase", "refresh", "container", "wsl-tarball"]),
         )
         .arg(
-            Arg::with_name("table")
-                .short("t")
-                .long("new-table")
+            Arg::with_name("container")
+                .long("container")
                 .help(
-                    "defines a new partition table to apply to the disk, clobbering it in the \
-                     process",
+                    "installs to this directory, for systemd-nspawn or LXC, without partitioning \
+                     or installing a bootloader",
                 )
-                .multiple(true)
+                .conflicts_with_all(&["disk", "erase", "refresh"])
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("new")
-                .short("n")
-                .long("new")
-                .help("defines a new partition that will be created on the disk")
-                .multiple(true)
+            Arg::with_name("wsl-tarball")
+                .long("wsl-tarball")
+                .help(
+                    "exports the configured image to this tarball, for `wsl --import`, rather \
+                     than installing it; a .tar.gz or .tar.xz extension compresses it",
+                )
+                .conflicts_with_all(&["disk", "erase", "refresh", "container"])
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("use")
-                .short("u")
-                .long("use")
-                .help("defines to reuse an existing partition on the disk")
+            Arg::with_name("subvolume")
+                .long("subvolume")
+                .help("creates the --container directory as a Btrfs subvolume")
+                .requires("container"),
+        )
+        .arg(
+            Arg::with_name("erase")
+                .long("erase")
+                .help(
+                    "wipes the given disk, or the disk which a strategy such as \
+                     'bus=nvme,empty,largest' selects, and installs to it with the default layout",
+                )
+                .conflicts_with_all(&["disk", "refresh"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("encrypt-password")
+                .long("encrypt-password")
+                .help("encrypts the root partition created by --erase with the given password")
+                .requires("erase")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("refresh")
+                .long("refresh")
+                .help("reinstalls over the existing install whose root partition has this UUID")
+                .conflicts_with("disk")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("keep-old-root")
+                .long("keep-old-root")
+                .help("keeps the previous root file system in /linux.old when refreshing")
+                .requires("refresh"),
+        )
+        .arg(
+            Arg::with_name("restore-packages")
+                .long("restore-packages")
+                .help("reinstalls the packages of the previous install when refreshing")
+                .requires("refresh"),
+        )
+        .arg(
+            Arg::with_name("simulate")
+                .long("simulate")
+                .help("prints the operations that would be performed, without performing them"),
+        )
+        .arg(
+            Arg::with_name("preserve")
+                .long("preserve")
+                .help("preserves a path from the previous install when refreshing")
                 .takes_value(true)
                 .multiple(true),
         )
         .arg(
-            Arg::with_name("test")
-                .long("test")
-                .help("simply test whether the provided arguments pass the partitioning stage"),
+            Arg::with_name("migrate-windows")
+                .long("migrate-windows")
+                .help("migrates a Windows user's data, given the Windows partition and user")
+                .value_names(&["DEVICE", "USER"])
+                .number_of_values(2),
         )
         .arg(
-            Arg::with_name("hardware-support")
-                .long("hardware-support")
-                .help("install hardware support packages based on detected hardware"),
+            Arg::with_name("extract-threads")
+                .long("extract-threads")
+                .help("limits the number of threads which decompress a squashfs image")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("modify-boot")
-                .long("modify-boot")
-                .help("modify the boot order after installing"),
+            Arg::with_name("btrfs-recompress")
+                .long("btrfs-recompress")
+                .help("compresses the extracted files of the Btrfs file systems which are reused"),
         )
         .arg(
-            Arg::with_name("force-bios")
-                .long("force-bios")
-                .help("performs a BIOS installation even if the running system is EFI"),
+            Arg::with_name("temp-dir")
+                .long("temp-dir")
+                .help("creates the chroot mount point and temporary files in this directory")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("force-efi")
-                .long("force-efi")
-                .help("performs an EFI installation even if the running system is BIOS"),
+            Arg::with_name("snapshots")
+                .long("snapshots")
+                .help("installs a tool which takes periodic snapshots of the root file system")
+                .possible_values(&["snapper", "timeshift"])
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("no-efi-vars")
-                .long("no-efi-vars")
-                .help("disables mounting of the efivars directory"),
+            Arg::with_name("apt-mirror")
+                .long("apt-mirror")
+                .help("replaces the image's apt sources with this archive mirror")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("delete")
-                .short("d")
-                .long("delete")
-                .help("defines to delete the specified partitions")
+            Arg::with_name("apt-components")
+                .long("apt-components")
+                .help("the components of the apt mirror to enable, such as main and universe")
+                .requires("apt-mirror")
                 .takes_value(true)
                 .multiple(true),
         )
         .arg(
-            Arg::with_name("move")
-                .short("m")
-                .long("move")
-                .help("defines to move and/or resize an existing partition")
+            Arg::with_name("apt-no-updates")
+                .long("apt-no-updates")
+                .help("disables the -updates pocket of the apt mirror")
+                .requires("apt-mirror"),
+        )
+        .arg(
+            Arg::with_name("apt-proposed")
+                .long("apt-proposed")
+                .help("enables the -proposed pocket of the apt mirror")
+                .requires("apt-mirror"),
+        )
+        .arg(
+            Arg::with_name("ntp-server")
+                .long("ntp-server")
+                .help("synchronizes the clock of the installed system with this NTP server")
                 .takes_value(true)
                 .multiple(true),
         )
         .arg(
-            Arg::with_name("logical")
-                .long("logical")
-                .help("creates a partition on a LVM volume group")
+            Arg::with_name("rtc")
+                .long("rtc")
+                .help(
+                    "keeps the hardware clock in local time or UTC; by default, local time is \
+                     kept if Windows remains installed",
+                )
+                .possible_values(&["local", "utc"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("firewall")
+                .long("firewall")
+                .help("enables ufw, with this policy towards incoming connections")
+                .possible_values(&["allow", "deny", "reject"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("console-font")
+                .long("console-font")
+                .help("sets the font of the consoles, as FACE:SIZE, such as Terminus:16x32")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("numlock")
+                .long("numlock")
+                .help("turns numlock on or off at boot, on the consoles and the login screen")
+                .possible_values(&["on", "off"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("serial-console")
+                .long("serial-console")
+                .help(
+                    "boots with the console on this serial port, as PORT[,BAUD] such as \
+                     ttyS0,115200, and starts a getty on it",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hidpi")
+                .long("hidpi")
+                .help("scales the desktop and enlarges the console font if a HiDPI panel is found"),
+        )
+        .arg(
+            Arg::with_name("kernel-flavor")
+                .long("kernel-flavor")
+                .help(
+                    "the flavor of the image's kernels which boots by default, such as 'oem', or \
+                     'auto' to choose it by the hardware",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("initramfs-kernel")
+                .long("initramfs-kernel")
+                .help("a kernel release whose initramfs is generated, in place of all kernels")
                 .takes_value(true)
                 .multiple(true),
         )
         .arg(
-            Arg::with_name("logical-modify")
-                .long("logical-modify")
-                .help("modifies an existing LVM volume group")
+            Arg::with_name("initramfs-compression")
+                .long("initramfs-compression")
+                .help("the compression of the initramfs")
+                .takes_value(true)
+                .possible_values(&["gzip", "lz4", "xz", "zstd"]),
+        )
+        .arg(
+            Arg::with_name("keyboard-options")
+                .long("keyboard-options")
+                .help("XKB options which switch between several layouts, such as \
+                       grp:alt_shift_toggle")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("esp-cleanup")
+                .long("esp-cleanup")
+                .help("removes the ESP directories of OSes which no longer exist on any disk"),
+        )
+        .arg(
+            Arg::with_name("remove-other-kernels")
+                .long("remove-other-kernels")
+                .help("removes the kernels of the flavors which were not selected")
+                .requires("kernel-flavor"),
+        )
+        .arg(
+            Arg::with_name("autologin")
+                .long("autologin")
+                .help("logs the user in automatically, through the display manager of the image")
+                .requires("username"),
+        )
+        .arg(
+            Arg::with_name("live-settings")
+                .long("live-settings")
+                .help("copies the desktop settings of the live user with this home to the user")
+                .takes_value(true)
+                .value_name("HOME")
+                .requires("username"),
+        )
+        .arg(
+            Arg::with_name("old-boots")
+                .long("old-boots")
+                .help(
+                    "what becomes of the ESP boot files of an install replaced from recovery: \
+                     'remove', 'keep:N' to keep the N most recent, 'dry-run[:N]', or 'skip'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ssh")
+                .long("ssh")
+                .help("installs and enables an OpenSSH server"),
+        )
+        .arg(
+            Arg::with_name("ssh-key")
+                .long("ssh-key")
+                .help("authorizes this public key to log in as the created user over SSH")
+                .requires("ssh")
                 .takes_value(true)
                 .multiple(true),
         )
         .arg(
-            Arg::with_name("logical-remove")
-                .long("logical-remove")
-                .help("removes an existing LVM logical volume")
+            Arg::with_name("cloud-init")
+                .long("cloud-init")
+                .help(
+                    "seeds the image with cloud-init, which creates the users and sets the \
+                     hostname on first boot",
+                )
+                .requires("image-mode"),
+        )
+        .arg(
+            Arg::with_name("cloud-init-datasource")
+                .long("cloud-init-datasource")
+                .help("searches this cloud-init datasource, such as NoCloud or Ec2, in order")
+                .requires("cloud-init")
                 .takes_value(true)
                 .multiple(true),
         )
         .arg(
-            Arg::with_name("logical-remove-all")
-                .long("logical-remove-all")
-                .help("TODO")
+            Arg::with_name("cloud-init-config")
+                .long("cloud-init-config")
+                .help("installs this cloud-config file to /etc/cloud/cloud.cfg.d")
+                .requires("cloud-init")
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("decrypt")
-                .long("decrypt")
-                .help("decrypts an existing LUKS partition")
+            Arg::with_name("net-static")
+                .long("net-static")
+                .help("assigns a static address to an interface: IFACE,ADDRESS/PREFIX[,GATEWAY]")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("net-bond")
+                .long("net-bond")
+                .help("defines a bond of ethernet interfaces: NAME,MODE,IFACE[+IFACE...]")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("net-vlan")
+                .long("net-vlan")
+                .help("defines a VLAN on an interface: NAME,ID,LINK")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("net-dns")
+                .long("net-dns")
+                .help("a DNS server of the interfaces with static addresses")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("net-search")
+                .long("net-search")
+                .help("a search domain of the interfaces with static addresses")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("net-mount")
+                .long("net-mount")
+                .help("adds an NFS or CIFS share to the fstab: nfs|cifs,SOURCE,TARGET[,OPTION...]")
                 .takes_value(true)
                 .multiple(true),
         )
+        .arg(
+            Arg::with_name("net-mount-credentials")
+                .long("net-mount-credentials")
+                .help("mounts a CIFS share with an account: TARGET,USERNAME,PASSWORD[,DOMAIN]")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("join-domain")
+                .long("join-domain")
+                .help("joins a directory domain with realmd once the installed system is online")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("join-user")
+                .long("join-user")
+                .help("an account which may join computers to the domain: USERNAME,PASSWORD")
+                .takes_value(true)
+                .requires("join-domain")
+                .conflicts_with("join-otp"),
+        )
+        .arg(
+            Arg::with_name("join-otp")
+                .long("join-otp")
+                .help("a one-time password which was issued for this computer by the domain")
+                .takes_value(true)
+                .requires("join-domain"),
+        )
+        .arg(
+            Arg::with_name("join-ou")
+                .long("join-ou")
+                .help("the organizational unit which the computer account is created in")
+                .takes_value(true)
+                .requires("join-domain"),
+        )
+        .arg(
+            Arg::with_name("recovery-key")
+                .long("recovery-key")
+                .help("adds a random recovery key to each encrypted volume, and prints it"),
+        )
+        .arg(
+            Arg::with_name("recovery-key-file")
+                .long("recovery-key-file")
+                .help("writes the recovery keys to this file, such as one on a removable device")
+                .takes_value(true)
+                .requires("recovery-key"),
+        )
+        .arg(
+            Arg::with_name("initrd-keyfile")
+                .long("initrd-keyfile")
+                .help(
+                    "unlocks the encrypted volumes with a keyfile in the initramfs, once GRUB \
+                     has unlocked an encrypted /boot",
+                ),
+        )
+        .arg(
+            Arg::with_name("benchmark-target")
+                .long("benchmark-target")
+                .help("warns if writes to the target media are too slow before extracting to it"),
+        )
+        .arg(
+            Arg::with_name("read-only-root")
+                .long("read-only-root")
+                .help("installs a read-only root verified by dm-verity, with a writable overlay")
+                .value_names(&["HASH_PARTITION", "OVERLAY_PARTITION"])
+                .number_of_values(2),
+        )
+        .arg(
+            Arg::with_name("telemetry-endpoint")
+                .long("telemetry-endpoint")
+                .help("opts in to posting an anonymous summary of the install to this URL")
+                .value_name("URL")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("vendor-name")
+                .long("vendor-name")
+                .help("writes the name of the system's vendor to /etc/os-release")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("vendor-url")
+                .long("vendor-url")
+                .help("writes the website of the system's vendor to /etc/os-release")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dconf-defaults")
+                .long("dconf-defaults")
+                .help("installs a dconf keyfile of default settings for every user")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("wallpaper")
+                .long("wallpaper")
+                .help("installs an image as the default wallpaper")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("eula")
+                .long("eula")
+                .help("installs the text of the vendor's license agreement")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("oem-packages")
+                .long("oem-packages")
+                .help("installs the vendor's .deb packages in this directory, such as drivers")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("nice")
+                .long("nice")
+                .help("runs the heavy steps of the install at this niceness, from -20 to 19")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ionice")
+                .long("ionice")
+                .help(
+                    "runs the heavy steps of the install in this I/O class: idle, \
+                     best-effort[:LEVEL], or realtime[:LEVEL]",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("test")
+                .long("test")
+                .help("simply test whether the provided arguments pass the partitioning stage"),
+        )
+        .arg(
+            Arg::with_name("hardware-support")
+                .long("hardware-support")
+                .help("install hardware support packages based on detected hardware"),
+        )
+        .arg(
+            Arg::with_name("modify-boot")
+                .long("modify-boot")
+                .help("modify the boot order after installing"),
+        )
+        .arg(
+            Arg::with_name("factory-image")
+                .long("factory-image")
+                .help("capture the installed system to the recovery partition for factory resets"),
+        )
+        .arg(
+            Arg::with_name("image-mode")
+                .long("image-mode")
+                .help("defers machine-specific configuration to first boot, for cloning"),
+        )
+        .arg(
+            Arg::with_name("force-bios")
+                .long("force-bios")
+                .help("performs a BIOS installation even if the running system is EFI"),
+        )
+        .arg(
+            Arg::with_name("force-efi")
+                .long("force-efi")
+                .help("performs an EFI installation even if the running system is BIOS"),
+        )
+        .arg(
+            Arg::with_name("boot-timeout")
+                .long("boot-timeout")
+                .help(
+                    "shows the boot menu for this many seconds, or boots at once if 0; by \
+                     default, it is shown for 5 seconds if another system remains installed",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("boot-default")
+                .long("boot-default")
+                .help("boots the installed system by default, or the entry which was booted last")
+                .possible_values(&["installed", "last-booted"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-efi-vars")
+                .long("no-efi-vars")
+                .help("disables mounting of the efivars directory"),
+        )
         .arg(
             Arg::with_name("run-ubuntu-drivers")
                 .long("run-ubuntu-drivers")
                 .help("use ubuntu-drivers to find drivers then install in the chroot, some may have proprietary licenses")
-        )
-        .get_matches();
+        );
+
+    let matches = layout_args().into_iter().fold(app, App::arg).get_matches_from(args);
 
     if let Err(err) = distinst::log(|_level, _message| {}) {
         eprintln!("Failed to initialize logging: {}", err);
     }
 
+    let as_json = matches.is_present("json");
+
+    if let Some(list) = matches.subcommand_matches("list") {
+        // Without access to the devices, list what can be found without opening them.
+        let probed = match Disks::probe_devices() {
+            Err(DiskError::PermissionDenied { .. }) => Disks::probe_devices_unprivileged(),
+            probed => probed,
+        };
+
+        match probed {
+            Ok(disks) => {
+                match list.value_of("target") {
+                    Some(target) => exit(output::print_target(&disks, target, as_json)),
+                    None => output::print_disks(&disks, as_json),
+                }
+                exit(0);
+            }
+            Err(why) => {
+                eprintln!("distinst: {}", why);
+                exit(1);
+            }
+        }
+    }
+
+    if let Some(helper) = matches.subcommand_matches("helper") {
+        exit(helper::serve(helper.value_of("SOCKET").unwrap()));
+    }
+
+    if let Some(apply) =
+        matches.subcommand_matches("partition").and_then(|sub| sub.subcommand_matches("apply"))
+    {
+        let as_json = as_json || apply.is_present("json");
+        exit(partition::apply(apply.value_of("LAYOUT").unwrap(), as_json));
+    }
+
+    if let Some(repair) = matches.subcommand_matches("repair-boot") {
+        let as_json = as_json || repair.is_present("json");
+        let root = repair.value_of("ROOT").unwrap();
+        exit(repair::repair_boot(root, repair.value_of("decrypt"), as_json));
+    }
+
+    if let Some(rescue) = matches.subcommand_matches("rescue-shell") {
+        let root = rescue.value_of("ROOT").unwrap();
+        exit(repair::rescue_shell(root, rescue.value_of("decrypt")));
+    }
+
+    if let Some(repair) = matches.subcommand_matches("repair-fstab") {
+        let root = repair.value_of("ROOT").unwrap();
+        let write = repair.is_present("write");
+        exit(repair::repair_fstab(root, repair.value_of("decrypt"), write));
+    }
+
+    if let Some(encrypt) = matches.subcommand_matches("encrypt-root") {
+        let root = encrypt.value_of("ROOT").unwrap();
+        exit(repair::encrypt_root(root, encrypt.value_of("encrypt").unwrap(), as_json));
+    }
+
+    if let Some(reencrypt) = matches.subcommand_matches("reencrypt") {
+        let device = reencrypt.value_of("DEVICE").unwrap();
+        let key_size = match reencrypt.value_of("key-size").map(str::parse).transpose() {
+            Ok(key_size) => key_size,
+            Err(why) => {
+                eprintln!("distinst: invalid key size: {}", why);
+                exit(1);
+            }
+        };
+
+        let cipher = Cipher { name: reencrypt.value_of("cipher").unwrap().into(), key_size };
+        exit(repair::reencrypt(device, reencrypt.value_of("decrypt").unwrap(), &cipher, as_json));
+    }
+
     let squashfs = matches.value_of("squashfs").unwrap();
     let hostname = matches.value_of("hostname").unwrap();
     let mut keyboard = matches.values_of("keyboard").unwrap();
@@ -262,8 +926,9 @@ fn main() {
         let profile_icon = matches.value_of("profile_icon").map(String::from);
 
         let realname = matches.value_of("realname").map(String::from);
+        let password_hash = matches.value_of("password_hash").map(String::from);
         let password = matches.value_of("password").map(String::from).or_else(|| {
-            if unsafe { libc::isatty(0) } == 0 {
+            if password_hash.is_none() && unsafe { libc::isatty(0) } == 0 {
                 let mut pass = String::new();
                 io::stdin().read_line(&mut pass).unwrap();
                 pass.pop();
@@ -273,9 +938,222 @@ fn main() {
             }
         });
 
-        UserAccountCreate { realname, username, password, profile_icon }
+        let groups = matches
+            .value_of("groups")
+            .map(|groups| groups.split(',').filter(|g| !g.is_empty()).map(String::from).collect());
+        let sudo = matches.value_of("sudo").and_then(|sudo| sudo.parse().ok()).unwrap_or_default();
+        let expires = matches.value_of("expires").map(String::from);
+
+        UserAccountCreate {
+            realname,
+            username,
+            password,
+            password_hash,
+            profile_icon,
+            groups,
+            sudo,
+            expires,
+        }
     });
 
+    fn take_optional_string(argument: Option<&str>) -> Option<String> {
+        argument.map(String::from).and_then(|x| if x.is_empty() { None } else { Some(x) })
+    }
+
+    let extract_threads = match matches.value_of("extract-threads") {
+        Some(threads) => match threads.parse::<usize>() {
+            Ok(threads) => Some(threads),
+            Err(_) => {
+                eprintln!("distinst: {}", DistinstError::ArgNaN { arg: threads.into() });
+                exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let old_boots = match matches.value_of("old-boots") {
+        Some(policy) => match policy.parse::<OldBootPolicy>() {
+            Ok(policy) => policy,
+            Err(why) => {
+                let why = DistinstError::InvalidOldBoots { policy: policy.into(), why };
+                eprintln!("distinst: {}", why);
+                exit(1);
+            }
+        },
+        None => OldBootPolicy::default(),
+    };
+
+    let priority = match parse_priority(&matches) {
+        Ok(priority) => priority,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            exit(1);
+        }
+    };
+
+    let network = match parse_network(&matches) {
+        Ok(network) => network,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            exit(1);
+        }
+    };
+
+    let network_mounts = match parse_network_mounts(&matches) {
+        Ok(mounts) => mounts,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            exit(1);
+        }
+    };
+
+    let boot_timeout = match matches.value_of("boot-timeout") {
+        Some(timeout) => match timeout.parse::<u32>() {
+            Ok(timeout) => Some(timeout),
+            Err(_) => {
+                eprintln!("distinst: {}", DistinstError::ArgNaN { arg: timeout.into() });
+                exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let serial_console = match matches.value_of("serial-console").map(str::parse::<SerialConsole>) {
+        Some(Ok(serial)) => Some(serial),
+        Some(Err(why)) => {
+            eprintln!("distinst: {}", why);
+            exit(1);
+        }
+        None => None,
+    };
+
+    let keyboard_layout = keyboard.next().unwrap();
+    let keyboard_model = take_optional_string(keyboard.next());
+    let mut keyboard_layouts = keyboard_layouts(keyboard_layout, keyboard.next()).into_iter();
+    let keyboard_layout = keyboard_layouts.next().unwrap_or_default();
+    let keyboard_layouts = keyboard_layouts.collect();
+
+    let config = Config {
+        flags:             install_flags(&matches),
+        hostname:          hostname.into(),
+        keyboard_layout:   keyboard_layout.layout,
+        keyboard_model:    keyboard_model,
+        keyboard_variant:  keyboard_layout.variant,
+        keyboard_layouts:  keyboard_layouts,
+        keyboard_options:  take_optional_string(matches.value_of("keyboard-options")),
+        old_root:          matches.value_of("refresh").map(String::from),
+        lang:              lang.into(),
+        remove:            remove.into(),
+        squashfs:          squashfs.into(),
+        preserved_paths:   matches
+            .values_of("preserve")
+            .map_or(Vec::new(), |paths| paths.map(PathBuf::from).collect()),
+        windows_migration: matches.values_of("migrate-windows").map(|mut values| {
+            WindowsMigration {
+                device: values.next().map(PathBuf::from).unwrap(),
+                user:   values.next().map(String::from).unwrap(),
+            }
+        }),
+        extract_threads,
+        btrfs_recompress:  matches.is_present("btrfs-recompress"),
+        priority,
+        temp_dir:          matches.value_of("temp-dir").map(PathBuf::from),
+        snapshots:         matches.value_of("snapshots").and_then(|tool| tool.parse().ok()),
+        branding:          branding(&matches),
+        oem_packages:      matches.value_of("oem-packages").map(PathBuf::from),
+        apt_sources:       matches.value_of("apt-mirror").map(|mirror| AptSources {
+            components: matches
+                .values_of("apt-components")
+                .map_or(Vec::new(), |components| components.map(String::from).collect()),
+            updates: !matches.is_present("apt-no-updates"),
+            proposed: matches.is_present("apt-proposed"),
+            ..AptSources::new(mirror)
+        }),
+        ntp_servers:       matches
+            .values_of("ntp-server")
+            .map_or(Vec::new(), |servers| servers.map(String::from).collect()),
+        local_rtc:         matches.value_of("rtc").map(|rtc| rtc == "local"),
+        firewall:          matches.value_of("firewall").and_then(|policy| policy.parse().ok()),
+        ssh_server:        if matches.is_present("ssh") {
+            Some(SshServer {
+                authorized_keys: matches
+                    .values_of("ssh-key")
+                    .map_or(Vec::new(), |keys| keys.map(String::from).collect()),
+            })
+        } else {
+            None
+        },
+        cloud_init:        if matches.is_present("cloud-init") {
+            Some(CloudInit {
+                datasources: matches
+                    .values_of("cloud-init-datasource")
+                    .map_or(Vec::new(), |sources| sources.map(String::from).collect()),
+                config:      matches.value_of("cloud-init-config").map(PathBuf::from),
+            })
+        } else {
+            None
+        },
+        console:           console_settings(&matches, serial_console),
+        hidpi:             matches.is_present("hidpi"),
+        autologin:         matches.is_present("autologin"),
+        network,
+        network_mounts,
+        directory:         matches.value_of("join-domain").map(|domain| DirectoryEnrollment {
+            domain:      domain.into(),
+            credential:  match matches.value_of("join-user") {
+                Some(account) => {
+                    let mut account = account.splitn(2, ',');
+                    JoinCredential::Password {
+                        user:     account.next().map(String::from).unwrap_or_default(),
+                        password: account.next().map(String::from).unwrap_or_default(),
+                    }
+                }
+                None => JoinCredential::OneTimePassword(
+                    matches.value_of("join-otp").map(String::from).unwrap_or_default(),
+                ),
+            },
+            computer_ou: matches.value_of("join-ou").map(String::from),
+        }),
+        recovery_key:      matches.is_present("recovery-key"),
+        recovery_key_file: matches.value_of("recovery-key-file").map(PathBuf::from),
+        read_only_root:    matches.values_of("read-only-root").map(|mut values| ReadOnlyRoot {
+            hash_partition:    values.next().map(PathBuf::from).unwrap(),
+            overlay_partition: values.next().map(PathBuf::from).unwrap(),
+        }),
+        partition_sizes:   PartitionSizes::default(),
+        old_boots,
+        bootloader:        if matches.is_present("force-bios") {
+            BootloaderOverride::Bios
+        } else if matches.is_present("force-efi") {
+            BootloaderOverride::Efi
+        } else {
+            BootloaderOverride::Detect
+        },
+        boot_menu:         BootMenu {
+            timeout: boot_timeout,
+            default: matches
+                .value_of("boot-default")
+                .and_then(|default| default.parse().ok())
+                .unwrap_or_default(),
+        },
+        kernel:            matches.value_of("kernel-flavor").map(|flavor| KernelSelection {
+            flavor:        if flavor == "auto" { None } else { Some(flavor.into()) },
+            remove_others: matches.is_present("remove-other-kernels"),
+        }),
+        initramfs:         InitramfsOptions {
+            kernels:     matches
+                .values_of("initramfs-kernel")
+                .map_or(Vec::new(), |kernels| kernels.map(String::from).collect()),
+            compression: matches
+                .value_of("initramfs-compression")
+                .and_then(|compression| compression.parse().ok()),
+        },
+        esp_cleanup:       matches.is_present("esp-cleanup"),
+        live_settings:     matches.value_of("live-settings").map(PathBuf::from),
+        initrd_keyfile:    matches.is_present("initrd-keyfile"),
+        benchmark_target:  matches.is_present("benchmark-target"),
+    };
+
     let pb_opt: Rc<RefCell<Option<ProgressBar<io::Stdout>>>> = Rc::new(RefCell::new(None));
 
     let res = {
@@ -284,6 +1162,11 @@ fn main() {
         {
             let pb_opt = pb_opt.clone();
             installer.on_error(move |error| {
+                if as_json {
+                    output::print_error_json(error);
+                    return;
+                }
+
                 if let Some(mut pb) = pb_opt.borrow_mut().take() {
                     pb.finish_println("");
                 }
@@ -292,16 +1175,57 @@ fn main() {
             });
         }
 
+        installer.on_warning(move |warning| {
+            if as_json {
+                output::print_warning_json(warning);
+                return;
+            }
+
+            match *warning {
+                Warning::LowBattery(percent) => eprintln!(
+                    "distinst: warning: running on battery with {}% remaining; connect AC power",
+                    percent
+                ),
+                Warning::SlowMedia(rate) => eprintln!(
+                    "distinst: warning: the target media writes at {} KiB/s; the install may \
+                     take hours, and faster media is recommended",
+                    rate
+                ),
+                Warning::EfiVariablesFull(kib) => eprintln!(
+                    "distinst: warning: the EFI variables hold {} KiB, or crash dumps; the boot \
+                     entry may not fit, so remove stale dump-* variables or entries",
+                    kib
+                ),
+                Warning::ClockBehind(days) => eprintln!(
+                    "distinst: warning: the clock is {} days behind the image; set the time so \
+                     that packages may be downloaded",
+                    days
+                ),
+                Warning::ClockAhead(days) => eprintln!(
+                    "distinst: warning: the clock is {} days ahead of the image; set the time so \
+                     that packages may be downloaded",
+                    days
+                ),
+            }
+        });
+
         {
             let pb_opt = pb_opt.clone();
             let mut step_opt = None;
+            let mut substep_opt = None;
             installer.on_status(move |status| {
+                if as_json {
+                    output::print_status_json(status);
+                    return;
+                }
+
                 if step_opt != Some(status.step) {
                     if let Some(mut pb) = pb_opt.borrow_mut().take() {
                         pb.finish_println("");
                     }
 
                     step_opt = Some(status.step);
+                    substep_opt = None;
 
                     let mut pb = ProgressBar::new(100);
                     pb.show_speed = false;
@@ -313,11 +1237,40 @@ fn main() {
                         Step::Extract => "Extracting filesystem ",
                         Step::Configure => "Configuring installation",
                         Step::Bootloader => "Installing bootloader ",
+                        Step::FactoryImage => "Capturing factory image ",
+                        Step::Migrate => "Migrating user data ",
+                        Step::Export => "Exporting tarball ",
                     });
                     *pb_opt.borrow_mut() = Some(pb);
                 }
 
                 if let Some(ref mut pb) = *pb_opt.borrow_mut() {
+                    if substep_opt != status.substep {
+                        substep_opt = status.substep;
+                        if let Some(substep) = status.substep {
+                            pb.message(match substep {
+                                Substep::Fstab => "Configuring: fstab ",
+                                Substep::Locales => "Configuring: locales ",
+                                Substep::Packages => "Configuring: packages ",
+                                Substep::Drivers => "Configuring: drivers ",
+                                Substep::Recovery => "Configuring: recovery ",
+                                Substep::Bootloader => "Configuring: bootloader ",
+                                Substep::Keyboard => "Configuring: keyboard ",
+                                Substep::Initramfs => "Configuring: initramfs ",
+                                Substep::LuksBenchmark => "Partitioning: benchmarking encryption ",
+                                Substep::LuksFormat => "Partitioning: encrypting ",
+                                Substep::LuksOpen => "Partitioning: unlocking ",
+                            });
+                        }
+                    }
+
+                    if let Some(files) = status.files {
+                        pb.message(&format!(
+                            "Extracting filesystem ({}/{} files) ",
+                            files.processed, files.total
+                        ));
+                    }
+
                     pb.set(status.percent as u64);
                 }
             });
@@ -331,66 +1284,47 @@ fn main() {
             installer.set_user_callback(move || user_account.clone());
         }
 
-        let disks = match configure_disks(&matches) {
-            Ok(disks) => disks,
-            Err(why) => {
-                eprintln!("distinst: {}", why);
-                exit(1);
+        if let Some(endpoint) = matches.value_of("telemetry-endpoint") {
+            installer.set_telemetry_reporter(HttpReporter::new(endpoint));
+        }
+
+        let res = match (matches.value_of("container"), matches.value_of("wsl-tarball")) {
+            (Some(target), _) => {
+                configure_signal_handling();
+                let subvolume = matches.is_present("subvolume");
+                installer.install_container(Path::new(target), subvolume, &config)
             }
+            (None, Some(tarball)) => {
+                configure_signal_handling();
+                installer.export_wsl(Path::new(tarball), &config)
+            }
+            (None, None) => install_to_disks(&mut installer, &matches, &config, as_json),
         };
 
-        configure_signal_handling();
-
-        if matches.is_present("test") {
-            PARTITIONING_TEST.store(true, Ordering::Relaxed);
+        if let Some(stats) = installer.extract_stats() {
+            eprintln!(
+                "distinst: extracted {} MiB in {} seconds ({} MiB/s)",
+                stats.bytes / 1_048_576,
+                stats.duration.as_secs(),
+                stats.throughput() / 1_048_576
+            );
         }
 
-        if matches.is_present("force-bios") {
-            FORCE_BOOTLOADER.store(1, Ordering::Relaxed);
-        } else if matches.is_present("force-efi") {
-            FORCE_BOOTLOADER.store(2, Ordering::Relaxed);
+        for package in installer.oem_packages() {
+            let status = if package.installed { "installed" } else { "failed to install" };
+            eprintln!("distinst: vendor package {}: {}", package.name, status);
         }
 
-        if matches.is_present("no-efi-vars") {
-            NO_EFI_VARIABLES.store(true, Ordering::Relaxed);
+        for key in installer.recovery_keys() {
+            eprintln!(
+                "distinst: recovery key of {} (key slot {}): {}",
+                key.device.display(),
+                key.key_slot,
+                key.key
+            );
         }
 
-        fn take_optional_string(argument: Option<&str>) -> Option<String> {
-            argument.map(String::from).and_then(|x| if x.is_empty() { None } else { Some(x) })
-        }
-
-        // The lock is an `OwnedFd`, which on drop will close / unlock the inhibitor.
-        let _inhibit_suspend = match distinst::dbus_interfaces::LoginManager::new() {
-            Ok(manager) => match manager.connect().inhibit_suspend(
-                "Distinst Installer",
-                "prevent suspension while installing a distribution",
-            ) {
-                Ok(lock) => Some(lock),
-                Err(why) => {
-                    eprintln!("distinst: failed to inhibit suspend: {}", why);
-                    None
-                }
-            },
-            Err(why) => {
-                eprintln!("distinst: failed to get logind dbus connection: {}", why);
-                None
-            }
-        };
-
-        installer.install(
-            disks,
-            &Config {
-                flags:            install_flags(&matches),
-                hostname:         hostname.into(),
-                keyboard_layout:  keyboard.next().map(String::from).unwrap(),
-                keyboard_model:   take_optional_string(keyboard.next()),
-                keyboard_variant: take_optional_string(keyboard.next()),
-                old_root:         None,
-                lang:             lang.into(),
-                remove:           remove.into(),
-                squashfs:         squashfs.into(),
-            },
-        )
+        res
     };
 
     if let Some(mut pb) = pb_opt.borrow_mut().take() {
@@ -399,11 +1333,15 @@ fn main() {
 
     let status = match res {
         Ok(()) => {
-            println!("install was successful");
+            if !as_json {
+                println!("install was successful");
+            }
             0
         }
         Err(err) => {
-            println!("install failed: {}", err);
+            if !as_json {
+                println!("install failed: {}", err);
+            }
             1
         }
     };
@@ -411,6 +1349,345 @@ fn main() {
     exit(status);
 }
 
+/// Configures the disks which the arguments describe, and installs to them.
+fn install_to_disks(
+    installer: &mut Installer,
+    matches: &ArgMatches,
+    config: &Config,
+    as_json: bool,
+) -> io::Result<()> {
+    let disks = match configure_disks(matches, config) {
+        Ok(disks) => disks,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            exit(1);
+        }
+    };
+
+    if matches.is_present("simulate") {
+        match disks.plan() {
+            Ok(plan) => {
+                output::print_plan(&plan, as_json);
+                exit(0);
+            }
+            Err(why) => {
+                eprintln!("distinst: {}", why);
+                exit(1);
+            }
+        }
+    }
+
+    configure_signal_handling();
+
+    if matches.is_present("test") {
+        PARTITIONING_TEST.store(true, Ordering::Relaxed);
+    }
+
+    if matches.is_present("no-efi-vars") {
+        NO_EFI_VARIABLES.store(true, Ordering::Relaxed);
+    }
+
+    installer.install(disks, config)
+}
+
+/// Arguments which describe the changes to make to the disks' layout.
+fn layout_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("table")
+            .short("t")
+            .long("new-table")
+            .help(
+                "defines a new partition table to apply to the disk, clobbering it in the \
+                 process",
+            )
+            .multiple(true)
+            .takes_value(true),
+        Arg::with_name("new")
+            .short("n")
+            .long("new")
+            .help("defines a new partition that will be created on the disk")
+            .multiple(true)
+            .takes_value(true),
+        Arg::with_name("use")
+            .short("u")
+            .long("use")
+            .help("defines to reuse an existing partition on the disk")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("delete")
+            .short("d")
+            .long("delete")
+            .help("defines to delete the specified partitions")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("move")
+            .short("m")
+            .long("move")
+            .help("defines to move and/or resize an existing partition")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("logical")
+            .long("logical")
+            .help("creates a partition on a LVM volume group")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("logical-modify")
+            .long("logical-modify")
+            .help("modifies an existing LVM volume group")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("logical-remove")
+            .long("logical-remove")
+            .help("removes an existing LVM logical volume")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("logical-remove-all")
+            .long("logical-remove-all")
+            .help("TODO")
+            .takes_value(true),
+        Arg::with_name("decrypt")
+            .long("decrypt")
+            .help("decrypts an existing LUKS partition")
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("protect")
+            .long("protect")
+            .help(
+                "refuses to modify a disk or partition, given by its path, by serial=ID, wwn=ID, \
+                 or model=NAME, or by label=NAME",
+            )
+            .takes_value(true)
+            .multiple(true),
+        Arg::with_name("repair-gpt")
+            .long("repair-gpt")
+            .help("rewrites damaged backup GPT headers of the disks which the layout modifies"),
+    ]
+}
+
+/// Collects the branding options, if any were given.
+fn branding(matches: &ArgMatches) -> Option<Branding> {
+    let branding = Branding {
+        vendor_name: matches.value_of("vendor-name").map(String::from),
+        vendor_url:  matches.value_of("vendor-url").map(String::from),
+        dconf:       matches.value_of("dconf-defaults").map(PathBuf::from),
+        wallpaper:   matches.value_of("wallpaper").map(PathBuf::from),
+        eula:        matches.value_of("eula").map(PathBuf::from),
+    };
+
+    let given = branding.vendor_name.is_some()
+        || branding.vendor_url.is_some()
+        || branding.dconf.is_some()
+        || branding.wallpaper.is_some()
+        || branding.eula.is_some();
+
+    if given {
+        Some(branding)
+    } else {
+        None
+    }
+}
+
+/// Pairs the comma-separated layouts of XKB, such as `us,ru`, with their variants, such as
+/// `,phonetic`.
+fn keyboard_layouts(layouts: &str, variants: Option<&str>) -> Vec<KeyboardLayout> {
+    let mut variants = variants.unwrap_or("").split(',');
+    layouts
+        .split(',')
+        .map(|layout| KeyboardLayout {
+            layout:  layout.into(),
+            variant: variants.next().filter(|variant| !variant.is_empty()).map(String::from),
+        })
+        .collect()
+}
+
+/// Parses `--console-font` and `--numlock`; the console is only configured if either, or a
+/// serial console, was given.
+fn console_settings(
+    matches: &ArgMatches,
+    serial: Option<SerialConsole>,
+) -> Option<ConsoleSettings> {
+    let mut font = matches.value_of("console-font").map(|font| font.splitn(2, ':'));
+    let font_face = font.as_mut().and_then(|font| font.next()).filter(|face| !face.is_empty());
+    let font_size = font.as_mut().and_then(|font| font.next()).filter(|size| !size.is_empty());
+    let numlock = matches.value_of("numlock").map(|numlock| numlock == "on");
+
+    if font_face.is_none() && font_size.is_none() && numlock.is_none() && serial.is_none() {
+        return None;
+    }
+
+    Some(ConsoleSettings {
+        font_face: font_face.map(String::from),
+        font_size: font_size.map(String::from),
+        numlock,
+        serial,
+    })
+}
+
+/// Parses `--nice` and `--ionice`; the priority is only changed if either was given.
+fn parse_priority(matches: &ArgMatches) -> Result<Option<Priority>, DistinstError> {
+    let nice = match matches.value_of("nice") {
+        Some(nice) => {
+            Some(nice.parse::<i32>().map_err(|_| DistinstError::ArgNaN { arg: nice.into() })?)
+        }
+        None => None,
+    };
+
+    let io_class = match matches.value_of("ionice") {
+        Some(class) => Some(parse_io_class(class)?),
+        None => None,
+    };
+
+    if nice.is_none() && io_class.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(Priority { nice: nice.unwrap_or(0), io_class }))
+}
+
+fn parse_network(matches: &ArgMatches) -> Result<Option<NetworkConfig>, DistinstError> {
+    let values = |arg: &str| matches.values_of(arg).into_iter().flatten();
+    let mut network = NetworkConfig::default();
+
+    for arg in values("net-bond") {
+        let invalid = |why| DistinstError::InvalidNetwork { arg: arg.into(), why };
+        let fields: Vec<&str> = arg.split(',').collect();
+        let (name, mode, members) = match fields.as_slice() {
+            [name, mode, members] => (*name, *mode, *members),
+            _ => return Err(invalid("expected NAME,MODE,IFACE[+IFACE...]")),
+        };
+
+        let members: Vec<String> = members.split('+').map(String::from).collect();
+        for member in &members {
+            network.interface(member);
+        }
+
+        network.interface(name).kind =
+            InterfaceKind::Bond { interfaces: members, mode: mode.into() };
+    }
+
+    for arg in values("net-vlan") {
+        let invalid = |why| DistinstError::InvalidNetwork { arg: arg.into(), why };
+        let fields: Vec<&str> = arg.split(',').collect();
+        let (name, id, link) = match fields.as_slice() {
+            [name, id, link] => (*name, *id, *link),
+            _ => return Err(invalid("expected NAME,ID,LINK")),
+        };
+
+        let id = id.parse::<u16>().map_err(|_| invalid("the VLAN ID is not a number"))?;
+        network.interface(name).kind = InterfaceKind::Vlan { id, link: link.into() };
+    }
+
+    for arg in values("net-static") {
+        let invalid = |why| DistinstError::InvalidNetwork { arg: arg.into(), why };
+        let fields: Vec<&str> = arg.split(',').collect();
+        let (name, address, gateway) = match fields.as_slice() {
+            [name, address] => (*name, *address, None),
+            [name, address, gateway] => (*name, *address, Some(*gateway)),
+            _ => return Err(invalid("expected IFACE,ADDRESS/PREFIX[,GATEWAY]")),
+        };
+
+        let mut address = address.splitn(2, '/');
+        let ip = address.next().and_then(|ip| ip.parse::<IpAddr>().ok());
+        let prefix = address.next().and_then(|prefix| prefix.parse::<u8>().ok());
+        let address = match (ip, prefix) {
+            (Some(ip), Some(prefix)) => (ip, prefix),
+            _ => return Err(invalid("the address must be an IP address and a prefix length")),
+        };
+
+        let gateway = match gateway {
+            Some(gateway) => {
+                let gateway = gateway.parse::<IpAddr>();
+                Some(gateway.map_err(|_| invalid("the gateway is not an IP address"))?)
+            }
+            None => None,
+        };
+
+        let iface = network.interface(name);
+        iface.addresses.push(address);
+        iface.gateways.extend(gateway);
+    }
+
+    let mut nameservers: Vec<IpAddr> = Vec::new();
+    for arg in values("net-dns") {
+        let invalid = |why| DistinstError::InvalidNetwork { arg: arg.into(), why };
+        nameservers.push(arg.parse().map_err(|_| invalid("the DNS server is not an IP address"))?);
+    }
+
+    let search: Vec<String> = values("net-search").map(String::from).collect();
+    for iface in network.interfaces.iter_mut().filter(|iface| !iface.addresses.is_empty()) {
+        iface.nameservers = nameservers.clone();
+        iface.search = search.clone();
+    }
+
+    if network.interfaces.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(network))
+    }
+}
+
+fn parse_network_mounts(matches: &ArgMatches) -> Result<Vec<NetworkMount>, DistinstError> {
+    let values = |arg: &str| matches.values_of(arg).into_iter().flatten();
+    let mut mounts = Vec::new();
+
+    for arg in values("net-mount") {
+        let invalid = |why| DistinstError::InvalidNetworkMount { arg: arg.into(), why };
+        let mut fields = arg.split(',');
+        let (protocol, source, target) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(protocol), Some(source), Some(target)) => (protocol, source, target),
+            _ => return Err(invalid("expected nfs|cifs,SOURCE,TARGET[,OPTION...]")),
+        };
+
+        mounts.push(NetworkMount {
+            protocol:    protocol.parse().map_err(invalid)?,
+            source:      source.into(),
+            target:      PathBuf::from(target),
+            options:     fields.map(String::from).collect(),
+            credentials: None,
+        });
+    }
+
+    for arg in values("net-mount-credentials") {
+        let invalid = |why| DistinstError::InvalidNetworkMount { arg: arg.into(), why };
+        let fields: Vec<&str> = arg.split(',').collect();
+        let (target, username, password, domain) = match fields.as_slice() {
+            [target, username, password] => (*target, *username, *password, None),
+            [target, username, password, domain] => (*target, *username, *password, Some(*domain)),
+            _ => return Err(invalid("expected TARGET,USERNAME,PASSWORD[,DOMAIN]")),
+        };
+
+        let mount = mounts
+            .iter_mut()
+            .find(|mount| mount.target == Path::new(target))
+            .ok_or_else(|| invalid("no share is mounted at the target"))?;
+
+        mount.credentials = Some(CifsCredentials {
+            username: username.into(),
+            password: password.into(),
+            domain:   domain.map(String::from),
+        });
+    }
+
+    Ok(mounts)
+}
+
+fn parse_io_class(class: &str) -> Result<IoClass, DistinstError> {
+    let invalid = || DistinstError::InvalidIoClass { class: class.into() };
+    let mut fields = class.splitn(2, ':');
+    let name = fields.next().unwrap_or("");
+    let level = match fields.next() {
+        Some(level) => level.parse::<u8>().ok().filter(|&level| level < 8).ok_or_else(invalid)?,
+        None => 4,
+    };
+
+    match name {
+        "idle" => Ok(IoClass::Idle),
+        "best-effort" => Ok(IoClass::BestEffort(level)),
+        "realtime" => Ok(IoClass::RealTime(level)),
+        _ => Err(invalid()),
+    }
+}
+
 fn install_flags(matches: &ArgMatches) -> u8 {
     let mut flags = 0;
 
@@ -429,9 +1706,68 @@ fn install_flags(matches: &ArgMatches) -> u8 {
         0
     };
 
+    flags += if matches.occurrences_of("factory-image") != 0 {
+        distinst::CAPTURE_FACTORY_IMAGE
+    } else {
+        0
+    };
+
+    flags += if matches.occurrences_of("image-mode") != 0 { distinst::IMAGE_MODE } else { 0 };
+
+    flags += if matches.occurrences_of("keep-old-root") != 0 { distinst::KEEP_OLD_ROOT } else { 0 };
+
+    flags += if matches.occurrences_of("restore-packages") != 0 {
+        distinst::RESTORE_PACKAGES
+    } else {
+        0
+    };
+
     flags
 }
 
+/// Replaces each `--layout FILE` argument with the arguments that are listed in the file.
+///
+/// Arguments are separated by whitespace, and lines beginning with `#` are ignored. A file which
+/// holds a JSON object is instead read in the same manner as the layouts of `partition apply`.
+fn expand_layout(args: Vec<String>) -> Result<Vec<String>, DistinstError> {
+    let mut expanded = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg != "--layout" {
+            expanded.push(arg);
+            continue;
+        }
+
+        let path = match args.next() {
+            Some(path) => path,
+            // Let clap report the missing value.
+            None => {
+                expanded.push(arg);
+                break;
+            }
+        };
+
+        let layout = fs::read_to_string(&path)
+            .map_err(|why| DistinstError::LayoutRead { path: path.clone(), why })?;
+
+        // Layouts may also be given as JSON, as `partition apply` accepts them.
+        if layout.trim_start().starts_with('{') {
+            expanded.extend(partition::layout_to_args(&layout)?.into_iter().skip(1));
+            continue;
+        }
+
+        layout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .flat_map(str::split_whitespace)
+            .for_each(|arg| expanded.push(arg.to_owned()));
+    }
+
+    Ok(expanded)
+}
+
 fn configure_signal_handling() {
     extern "C" fn handler(signal: i32) {
         match signal {
@@ -552,7 +1888,7 @@ fn parse_flags(flags: &str) -> Vec<PartitionFlag> {
 }
 
 fn find_disk_mut<'a>(disks: &'a mut Disks, block: &str) -> Result<&'a mut Disk, DistinstError> {
-    disks.find_disk_mut(block).ok_or_else(|| DistinstError::DiskNotFound { disk: block.into() })
+    disks.select_disk_mut(&block.parse()?).map_err(Into::into)
 }
 
 fn find_partition_mut(
@@ -0,0 +1,154 @@
+//! Human-readable and machine-readable output for the CLI.
+
+use distinst::*;
+use serde_json::Value;
+
+fn file_system(fs: Option<FileSystem>) -> Option<&'static str> { fs.map(Into::into) }
+
+fn print_partition(partition: &PartitionInfo) {
+    println!(
+        "  {}: {} - {} {} {}{}",
+        partition.get_device_path().display(),
+        partition.start_sector,
+        partition.end_sector,
+        file_system(partition.filesystem).unwrap_or("unformatted"),
+        partition.name.as_ref().map_or("", String::as_str),
+        partition
+            .mount_point
+            .as_ref()
+            .map_or(String::new(), |mount| format!(" (mounted at {})", mount.display())),
+    );
+}
+
+/// Lists the physical and logical devices, and their partitions.
+pub(crate) fn print_disks(disks: &Disks, as_json: bool) {
+    if as_json {
+        println!("{}", disks.to_json());
+        return;
+    }
+
+    for disk in disks.get_physical_devices() {
+        println!(
+            "{}: {} ({} MiB)",
+            disk.get_device_path().display(),
+            disk.get_model(),
+            disk.get_sectors() * disk.get_logical_block_size() / 1_048_576
+        );
+
+        disk.get_partitions().iter().for_each(print_partition);
+    }
+
+    for device in disks.get_logical_devices() {
+        println!("{}: volume group {}", device.get_device_path().display(), device.volume_group);
+        device.get_partitions().iter().for_each(print_partition);
+    }
+}
+
+/// Lists the operations that the installer will perform.
+pub(crate) fn print_plan(plan: &[PlannedOperation], as_json: bool) {
+    if as_json {
+        let operations = plan
+            .iter()
+            .map(|operation| {
+                json!({
+                    "kind": format!("{:?}", operation.kind),
+                    "device": operation.device,
+                    "start_sector": operation.start_sector,
+                    "end_sector": operation.end_sector,
+                    "file_system": file_system(operation.file_system),
+                    "data_loss": operation.data_loss,
+                    "description": operation.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        println!("{}", Value::Array(operations));
+        return;
+    }
+
+    for operation in plan {
+        println!("{}{}", operation, if operation.data_loss { " (data will be lost)" } else { "" });
+    }
+}
+
+/// Prints a status update as a single line of JSON.
+pub(crate) fn print_status_json(status: &Status) {
+    println!(
+        "{}",
+        json!({
+            "step": format!("{:?}", status.step),
+            "substep": status.substep.map(|substep| format!("{:?}", substep)),
+            "percent": status.percent,
+            "overall": status.overall,
+            "files_processed": status.files.map(|files| files.processed),
+            "files_total": status.files.map(|files| files.total),
+        })
+    );
+}
+
+/// Prints an error as a single line of JSON.
+pub(crate) fn print_error_json(error: &Error) {
+    println!(
+        "{}",
+        json!({
+            "step": format!("{:?}", error.step),
+            "error": error.err.to_string(),
+            "code": format!("{:?}", error.code()),
+            "device": error.device(),
+        })
+    );
+}
+
+/// Prints a warning as a single line of JSON.
+pub(crate) fn print_warning_json(warning: &Warning) {
+    let value = match *warning {
+        Warning::LowBattery(percent) => json!({ "warning": "LowBattery", "percent": percent }),
+        Warning::SlowMedia(rate) => json!({ "warning": "SlowMedia", "kib_per_sec": rate }),
+        Warning::EfiVariablesFull(kib) => json!({ "warning": "EfiVariablesFull", "kib": kib }),
+        Warning::ClockBehind(days) => json!({ "warning": "ClockBehind", "days": days }),
+        Warning::ClockAhead(days) => json!({ "warning": "ClockAhead", "days": days }),
+    };
+
+    println!("{}", value);
+}
+
+/// Prints the disk which an `--erase` strategy would select, without erasing it, and returns
+/// the exit status.
+pub(crate) fn print_target(disks: &Disks, target: &str, as_json: bool) -> i32 {
+    let required = estimated_install_size(&Config::default()).total_sectors();
+    let selected = target
+        .parse::<auto::TargetStrategy>()
+        .and_then(|strategy| strategy.select(disks, required).map(|disk| (strategy, disk)));
+
+    match selected {
+        Ok((strategy, disk)) if as_json => {
+            println!(
+                "{}",
+                json!({
+                    "strategy": strategy.to_string(),
+                    "path": disk.get_device_path(),
+                    "model": disk.get_model(),
+                    "serial": disk.get_serial(),
+                    "wwn": disk.get_wwn(),
+                    "bus": disk.get_bus(),
+                    "sectors": disk.get_sectors(),
+                })
+            );
+            0
+        }
+        Ok((strategy, disk)) => {
+            println!(
+                "{} selects {}: {} ({} MiB)",
+                strategy,
+                disk.get_device_path().display(),
+                disk.get_model(),
+                disk.get_sectors() * disk.get_logical_block_size() / 1_048_576
+            );
+            0
+        }
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            1
+        }
+    }
+}
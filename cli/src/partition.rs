@@ -0,0 +1,105 @@
+//! Partitions disks from a JSON layout, without installing a system to them.
+
+use super::*;
+use serde_json::{Map, Value};
+
+/// Applies the layout in the given file, and returns the exit status.
+pub(crate) fn apply(path: &str, as_json: bool) -> i32 {
+    let disks = match read_layout(path) {
+        Ok(disks) => disks,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            return 1;
+        }
+    };
+
+    let mut installer = Installer::default();
+
+    installer.on_error(move |error| {
+        if as_json {
+            output::print_error_json(error);
+        } else {
+            eprintln!("Error: {:?}", error);
+        }
+    });
+
+    installer.on_status(move |status| {
+        if as_json {
+            output::print_status_json(status);
+        }
+    });
+
+    configure_signal_handling();
+
+    match installer.partition_only(disks) {
+        Ok(()) => {
+            if !as_json {
+                println!("partitioning was successful");
+            }
+            0
+        }
+        Err(why) => {
+            if !as_json {
+                println!("partitioning failed: {}", why);
+            }
+            1
+        }
+    }
+}
+
+fn read_layout(path: &str) -> Result<Disks, DistinstError> {
+    let layout = fs::read_to_string(path)
+        .map_err(|why| DistinstError::LayoutRead { path: path.into(), why })?;
+
+    let app = App::new("distinst partition apply").arg(
+        Arg::with_name("disk")
+            .long("block")
+            .takes_value(true)
+            .multiple(true)
+            .required(true),
+    );
+
+    let matches = layout_args()
+        .into_iter()
+        .fold(app, App::arg)
+        .get_matches_from_safe(layout_to_args(&layout)?)
+        .map_err(|why| DistinstError::LayoutInvalid { why: why.message })?;
+
+    configure_disks(&matches, &Config::default())
+}
+
+/// Converts a layout into the arguments that describe it on the command line.
+///
+/// Keys are the long names of the layout arguments, such as `block`, `new`, or `logical`. Each
+/// value is a string or an array of strings, written as they would be on the command line, or
+/// `true` for arguments which do not take a value.
+pub(crate) fn layout_to_args(layout: &str) -> Result<Vec<String>, DistinstError> {
+    let layout: Map<String, Value> = serde_json::from_str(layout)
+        .map_err(|why| DistinstError::LayoutInvalid { why: why.to_string() })?;
+
+    let mut args = vec!["distinst".to_owned()];
+    for (key, value) in layout {
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+
+        for value in values {
+            match value {
+                Value::String(value) => {
+                    args.push(format!("--{}", key));
+                    args.push(value);
+                }
+                Value::Bool(true) => args.push(format!("--{}", key)),
+                Value::Bool(false) => (),
+                _ => {
+                    return Err(DistinstError::LayoutInvalid {
+                        why: format!("values of '{}' must be strings or booleans", key),
+                    })
+                }
+            }
+        }
+    }
+
+    Ok(args)
+}
@@ -0,0 +1,209 @@
+//! Repairs an existing install, without reinstalling it.
+
+use super::*;
+
+/// Repairs the install on `root`, which is decrypted if `decrypt` is given as `PV:KEY`, and
+/// returns the exit status.
+pub(crate) fn repair_boot(root: &str, decrypt: Option<&str>, as_json: bool) -> i32 {
+    let encryption = match decrypt.map(parse_encryption).transpose() {
+        Ok(encryption) => encryption,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            return 1;
+        }
+    };
+
+    let mut installer = reporting_installer(as_json);
+    match installer.repair_bootloader(Path::new(root), encryption.as_ref()) {
+        Ok(()) => {
+            if !as_json {
+                println!("the boot of the install was repaired");
+            }
+            0
+        }
+        Err(why) => {
+            if !as_json {
+                println!("repair failed: {}", why);
+            }
+            1
+        }
+    }
+}
+
+/// Encrypts the root of the install on `root` in place, with the passphrase and volume name of
+/// `encrypt`, given as `PV:KEY`, and returns the exit status.
+pub(crate) fn encrypt_root(root: &str, encrypt: &str, as_json: bool) -> i32 {
+    let encryption = match parse_encryption(encrypt) {
+        Ok(encryption) => encryption,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            return 1;
+        }
+    };
+
+    let mut installer = reporting_installer(as_json);
+    match installer.encrypt_root(Path::new(root), &encryption) {
+        Ok(()) => {
+            if !as_json {
+                println!("the root of the install was encrypted");
+            }
+            0
+        }
+        Err(why) => {
+            if !as_json {
+                println!("encryption failed: {}; run this again to resume it", why);
+            }
+            1
+        }
+    }
+}
+
+/// Reencrypts the LUKS volume on `device` with `cipher`, unlocking it with `decrypt`, given as
+/// `PV:KEY`, and returns the exit status.
+pub(crate) fn reencrypt(device: &str, decrypt: &str, cipher: &Cipher, as_json: bool) -> i32 {
+    let encryption = match parse_encryption(decrypt) {
+        Ok(encryption) => encryption,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            return 1;
+        }
+    };
+
+    let mut installer = reporting_installer(as_json);
+    match installer.reencrypt(Path::new(device), &encryption, cipher) {
+        Ok(()) => {
+            if !as_json {
+                println!("the volume was reencrypted with {}", cipher.name);
+            }
+            0
+        }
+        Err(why) => {
+            if !as_json {
+                println!("reencryption failed: {}; run this again to resume it", why);
+            }
+            1
+        }
+    }
+}
+
+/// Opens a login shell within the install on `root`, and returns the exit status of the shell.
+pub(crate) fn rescue_shell(root: &str, decrypt: Option<&str>) -> i32 {
+    let encryption = match decrypt.map(parse_encryption).transpose() {
+        Ok(encryption) => encryption,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            return 1;
+        }
+    };
+
+    let session = match RescueSession::open(Path::new(root), encryption.as_ref()) {
+        Ok(session) => session,
+        Err(why) => {
+            eprintln!("distinst: unable to open the install: {}", why);
+            return 1;
+        }
+    };
+
+    let name = &session.os_release().pretty_name;
+    eprintln!("distinst: opening a shell in {}; exit the shell to unmount it", name);
+    let status = match session.shell() {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(why) => {
+            eprintln!("distinst: unable to run the shell: {}", why);
+            1
+        }
+    };
+
+    match session.close() {
+        Ok(()) => status,
+        Err(why) => {
+            eprintln!("distinst: unable to unmount the install: {}", why);
+            1
+        }
+    }
+}
+
+/// Shows how the fstab and crypttab of the install on `root` would be regenerated, and writes
+/// them if `write` is set. Returns the exit status.
+pub(crate) fn repair_fstab(root: &str, decrypt: Option<&str>, write: bool) -> i32 {
+    let encryption = match decrypt.map(parse_encryption).transpose() {
+        Ok(encryption) => encryption,
+        Err(why) => {
+            eprintln!("distinst: {}", why);
+            return 1;
+        }
+    };
+
+    let session = match RescueSession::open(Path::new(root), encryption.as_ref()) {
+        Ok(session) => session,
+        Err(why) => {
+            eprintln!("distinst: unable to open the install: {}", why);
+            return 1;
+        }
+    };
+
+    let status = match session.regenerate_fstabs() {
+        Ok(ref changes) if changes.is_empty() => {
+            eprintln!("distinst: the fstab and crypttab are up to date");
+            0
+        }
+        Ok(changes) => {
+            print!("{}", changes.diff());
+            if !write {
+                eprintln!("distinst: nothing was written; pass --write to apply the changes");
+                0
+            } else if let Err(why) = changes.write() {
+                eprintln!("distinst: unable to write the tables: {}", why);
+                1
+            } else {
+                0
+            }
+        }
+        Err(why) => {
+            eprintln!("distinst: unable to regenerate the tables: {}", why);
+            1
+        }
+    };
+
+    match session.close() {
+        Ok(()) => status,
+        Err(why) => {
+            eprintln!("distinst: unable to unmount the install: {}", why);
+            1
+        }
+    }
+}
+
+/// An installer which reports its errors, and its status if `as_json` is set.
+fn reporting_installer(as_json: bool) -> Installer {
+    let mut installer = Installer::default();
+
+    installer.on_error(move |error| {
+        if as_json {
+            output::print_error_json(error);
+        } else {
+            eprintln!("Error: {:?}", error);
+        }
+    });
+
+    installer.on_status(move |status| {
+        if as_json {
+            output::print_status_json(status);
+        }
+    });
+
+    configure_signal_handling();
+    installer
+}
+
+fn parse_encryption(decrypt: &str) -> Result<LvmEncryption, DistinstError> {
+    let mut fields = decrypt.splitn(2, ':');
+    let (pv, key) = match (fields.next(), fields.next()) {
+        (Some(pv), Some(key)) if !pv.is_empty() => (pv, key),
+        _ => return Err(DistinstError::DecryptArgs),
+    };
+
+    let (mut pass, mut keydata) = (None, None);
+    parse_key(key, &mut pass, &mut keydata)?;
+    Ok(LvmEncryption::new(pv.into(), pass, keydata))
+}
@@ -11,10 +11,13 @@
 
 use std::{
     path::Path,
+    str::FromStr,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
 /// Force the installation to perform either a BIOS or EFI installation.
+///
+/// Prefer `BootloaderOverride`, which the installer validates against the disks and firmware.
 pub static FORCE_BOOTLOADER: AtomicUsize = AtomicUsize::new(0);
 
 /// Bootloader type
@@ -37,6 +40,11 @@ impl Bootloader {
             _ => (),
         }
 
+        Bootloader::firmware()
+    }
+
+    /// The mode which the firmware booted the running system in, regardless of any override.
+    pub fn firmware() -> Bootloader {
         if Path::new("/sys/firmware/efi").is_dir() {
             Bootloader::Efi
         } else {
@@ -44,3 +52,87 @@ impl Bootloader {
         }
     }
 }
+
+/// The program which loads the kernel, whose abilities constrain where `/boot` may be.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KernelLoader {
+    /// Reads the kernel from `/boot`, with its modules for LVM and RAID.
+    Grub,
+    /// Only reads the ESP, to which kernelstub copies the kernel and initramfs.
+    SystemdBoot,
+}
+
+impl KernelLoader {
+    /// The loader which installs of the distribution named `os_name` use. Pop!_OS boots EFI
+    /// installs with systemd-boot, and everything else is booted with GRUB.
+    pub fn of(bootloader: Bootloader, os_name: &str) -> KernelLoader {
+        if bootloader == Bootloader::Efi && os_name == "Pop!_OS" {
+            KernelLoader::SystemdBoot
+        } else {
+            KernelLoader::Grub
+        }
+    }
+}
+
+/// Overrides the bootloader which would be detected from the firmware.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BootloaderOverride {
+    /// Installs for the mode which the firmware booted the running system in.
+    Detect,
+    Bios,
+    Efi,
+}
+
+impl Default for BootloaderOverride {
+    fn default() -> Self { BootloaderOverride::Detect }
+}
+
+impl BootloaderOverride {
+    /// The bootloader which is forced, if any.
+    pub fn forced(self) -> Option<Bootloader> {
+        match self {
+            BootloaderOverride::Detect => None,
+            BootloaderOverride::Bios => Some(Bootloader::Bios),
+            BootloaderOverride::Efi => Some(Bootloader::Efi),
+        }
+    }
+
+    /// Forces `Bootloader::detect` to return the chosen bootloader, until the returned guard is
+    /// dropped, which restores the override that it replaced. `Detect` leaves an override which
+    /// was stored in `FORCE_BOOTLOADER` as it is.
+    pub fn apply(self) -> AppliedOverride {
+        let previous = FORCE_BOOTLOADER.load(Ordering::SeqCst);
+        let value = match self {
+            BootloaderOverride::Detect => previous,
+            BootloaderOverride::Bios => 1,
+            BootloaderOverride::Efi => 2,
+        };
+
+        FORCE_BOOTLOADER.store(value, Ordering::SeqCst);
+        AppliedOverride { previous }
+    }
+}
+
+/// Restores the previous value of `FORCE_BOOTLOADER` when dropped, so that an override which
+/// was chosen for one install does not carry over to the next.
+#[must_use]
+pub struct AppliedOverride {
+    previous: usize,
+}
+
+impl Drop for AppliedOverride {
+    fn drop(&mut self) { FORCE_BOOTLOADER.store(self.previous, Ordering::SeqCst); }
+}
+
+impl FromStr for BootloaderOverride {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "detect" => Ok(BootloaderOverride::Detect),
+            "bios" => Ok(BootloaderOverride::Bios),
+            "efi" => Ok(BootloaderOverride::Efi),
+            _ => Err("expected 'detect', 'bios', or 'efi'"),
+        }
+    }
+}
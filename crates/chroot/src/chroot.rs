@@ -6,6 +6,7 @@ use std::{
 };
 use sys_mount::*;
 use crate::command::Command;
+use misc::unmount;
 
 /// Defines the location where a `chroot` will be performed, as well as storing
 /// handles to all of the binding mounts that the chroot requires.
@@ -75,10 +76,20 @@ impl<'a> Chroot<'a> {
         command
     }
 
-    /// Return true if the filesystem was unmounted, false if it was already
-    /// unmounted
+    /// Unmounts the binding mounts of the chroot.
+    ///
+    /// Unless `lazy` is set, a mount which is busy is retried before it is unmounted lazily,
+    /// and the processes which hold it are logged.
     pub fn unmount(&mut self, lazy: bool) -> Result<()> {
-        let flags = if lazy { UnmountFlags::DETACH } else { UnmountFlags::empty() };
+        if !lazy {
+            for target in &["sys", "run", "proc", "dev/pts", "dev"] {
+                unmount::unmount(&self.path.join(target))?;
+            }
+
+            return Ok(());
+        }
+
+        let flags = UnmountFlags::DETACH;
         self.sys_mount.unmount(flags)?;
         self.run_mount.unmount(flags)?;
         self.proc_mount.unmount(flags)?;
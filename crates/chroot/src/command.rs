@@ -1,12 +1,14 @@
 use libc;
+use misc::journal;
 use std::{
-    ffi::OsStr,
+    cell::RefCell,
+    ffi::{OsStr, OsString},
     fs::File,
     io::{self, BufRead, BufReader, Error, ErrorKind, Write},
     os::unix::io::{FromRawFd, IntoRawFd},
     process::{self, Child, ExitStatus, Stdio},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -14,14 +16,18 @@ use unicode_segmentation::UnicodeSegmentation;
 pub struct Command<'a> {
     cmd:   process::Command,
     stdin: Option<&'a str>,
+    /// The program and its arguments, for the command journal.
+    argv:  Vec<OsString>,
 }
 
 impl<'a> Command<'a> {
     pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
-        Command { cmd: process::Command::new(program), stdin: None }
+        let argv = vec![program.as_ref().to_owned()];
+        Command { cmd: process::Command::new(program), stdin: None, argv }
     }
 
     pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command<'a> {
+        self.argv.push(arg.as_ref().to_owned());
         self.cmd.arg(arg);
         self
     }
@@ -30,7 +36,9 @@ impl<'a> Command<'a> {
         &mut self,
         args: I,
     ) -> &mut Command<'a> {
-        self.cmd.args(args);
+        for arg in args {
+            self.arg(arg);
+        }
         self
     }
 
@@ -67,20 +75,33 @@ impl<'a> Command<'a> {
         }
     }
 
+    fn record(&self, started: Instant, status: Option<i32>, output: &str) {
+        journal::record(&self.argv[0], &self.argv[1..], started, status, output);
+    }
+
     pub fn run_with_stdout(&mut self) -> io::Result<String> {
         let cmd = format!("{:?}", self.cmd);
         info!("running {}", cmd);
 
         self.cmd.stdout(Stdio::piped());
 
+        let started = Instant::now();
         let mut child = self.cmd.spawn().map_err(|why| {
+            self.record(started, None, "");
             Error::new(why.kind(), format!("failed to spawn process {}: {}", cmd, why))
         })?;
 
         self.stdin_redirect(&mut child)?;
 
-        child
-            .wait_with_output()
+        let output = child.wait_with_output();
+        match output {
+            Ok(ref output) => {
+                self.record(started, output.status.code(), &String::from_utf8_lossy(&output.stdout))
+            }
+            Err(_) => self.record(started, None, ""),
+        }
+
+        output
             .map_err(|why| {
                 Error::new(why.kind(), format!("failed to get output of {}: {}", cmd, why))
             })
@@ -108,10 +129,33 @@ impl<'a> Command<'a> {
         let cmd = format!("{:?}", self.cmd);
         info!("running {}", cmd);
 
+        let started = Instant::now();
         let mut child = self.cmd.spawn().map_err(|why| {
+            self.record(started, None, "");
             Error::new(why.kind(), format!("failed to spawn process {}: {}", cmd, why))
         })?;
 
+        // Retains the end of the output for the command journal.
+        let output = RefCell::new(String::new());
+        let capture = |line: &str| {
+            let mut output = output.borrow_mut();
+            output.push_str(line);
+            output.push('\n');
+            if output.len() > 2 * journal::OUTPUT_LIMIT {
+                *output = journal::truncate(&output).to_owned();
+            }
+        };
+
+        let info = |line: &str| {
+            capture(line);
+            info(line)
+        };
+
+        let error = |line: &str| {
+            capture(line);
+            error(line)
+        };
+
         self.stdin_redirect(&mut child)?;
 
         let mut stdout_buffer = String::new();
@@ -123,7 +167,10 @@ impl<'a> Command<'a> {
         loop {
             thread::sleep(Duration::from_millis(16));
             match child.try_wait()? {
-                Some(status) => return status_as_result(status, &cmd),
+                Some(status) => {
+                    self.record(started, status.code(), &output.borrow());
+                    return status_as_result(status, &cmd);
+                }
                 None => {
                     if let Some(ref mut stdout) = stdout {
                         non_blocking_line_reading(stdout, &mut stdout_buffer, &info)?;
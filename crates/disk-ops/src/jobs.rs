@@ -0,0 +1,170 @@
+//! Runs the jobs which prepare devices, such as formatting them, in parallel, with each job
+//! waiting only for the jobs that it depends on, such as a LUKS volume being opened before the
+//! file system within it is created.
+
+use rayon;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+type Action<'a, E> = Box<dyn FnOnce() -> Result<(), E> + Send + 'a>;
+
+/// A job, which begins once each of the jobs of `after` have completed.
+pub struct Job<'a, E> {
+    /// Describes the job in the logs, such as the device that it formats.
+    pub name:   String,
+    /// The indexes of the jobs which must complete first. Each must precede this job.
+    pub after:  Vec<usize>,
+    action:     Action<'a, E>,
+}
+
+impl<'a, E> Job<'a, E> {
+    pub fn new<F>(name: impl Into<String>, after: Vec<usize>, action: F) -> Self
+    where
+        F: FnOnce() -> Result<(), E> + Send + 'a,
+    {
+        Job { name: name.into(), after, action: Box::new(action) }
+    }
+}
+
+impl<'a, E> fmt::Debug for Job<'a, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Job").field("name", &self.name).field("after", &self.after).finish()
+    }
+}
+
+/// The state of the jobs, which is shared by the threads that run them.
+struct Jobs<'a, E> {
+    names:      Vec<String>,
+    actions:    Vec<Mutex<Option<Action<'a, E>>>>,
+    /// The dependencies of each job which have yet to complete.
+    pending:    Vec<AtomicUsize>,
+    dependents: Vec<Vec<usize>>,
+    error:      Mutex<Option<E>>,
+}
+
+/// Runs the jobs on the rayon pool, and returns the first error of a job. Once a job has
+/// failed, no further jobs are started, and the jobs that are running are waited for.
+///
+/// # Panics
+///
+/// If a job depends on itself or on a job which follows it, as the jobs could not complete.
+pub fn run_jobs<E: Send>(jobs: Vec<Job<E>>) -> Result<(), E> {
+    let mut dependents = vec![Vec::new(); jobs.len()];
+    for (id, job) in jobs.iter().enumerate() {
+        for &dependency in &job.after {
+            assert!(dependency < id, "job {} must follow the jobs that it depends on", job.name);
+            dependents[dependency].push(id);
+        }
+    }
+
+    let ready: Vec<usize> =
+        jobs.iter().enumerate().filter(|(_, job)| job.after.is_empty()).map(|(id, _)| id).collect();
+
+    let mut state = Jobs {
+        names:   Vec::with_capacity(jobs.len()),
+        actions: Vec::with_capacity(jobs.len()),
+        pending: Vec::with_capacity(jobs.len()),
+        dependents,
+        error:   Mutex::new(None),
+    };
+
+    for job in jobs {
+        state.names.push(job.name);
+        state.pending.push(AtomicUsize::new(job.after.len()));
+        state.actions.push(Mutex::new(Some(job.action)));
+    }
+
+    let state = &state;
+    rayon::scope(|scope| {
+        for id in ready {
+            scope.spawn(move |scope| run(scope, state, id));
+        }
+    });
+
+    match state.error.lock().unwrap().take() {
+        Some(why) => Err(why),
+        None => Ok(()),
+    }
+}
+
+fn run<'s, 'a: 's, E: Send>(scope: &rayon::Scope<'s>, state: &'s Jobs<'a, E>, id: usize) {
+    if state.error.lock().unwrap().is_some() {
+        return;
+    }
+
+    let action = match state.actions[id].lock().unwrap().take() {
+        Some(action) => action,
+        None => return,
+    };
+
+    debug!("starting {}", state.names[id]);
+    if let Err(why) = action() {
+        error!("{} failed", state.names[id]);
+        let mut error = state.error.lock().unwrap();
+        if error.is_none() {
+            *error = Some(why);
+        }
+
+        return;
+    }
+
+    for &dependent in &state.dependents[id] {
+        if state.pending[dependent].fetch_sub(1, Ordering::SeqCst) == 1 {
+            scope.spawn(move |scope| run(scope, state, dependent));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_follow_their_dependencies() {
+        let order = Mutex::new(Vec::new());
+        let recorded = &order;
+        let record = move |id: usize| {
+            move || -> Result<(), ()> {
+                recorded.lock().unwrap().push(id);
+                Ok(())
+            }
+        };
+
+        let jobs = vec![
+            Job::new("luks", Vec::new(), record(0)),
+            Job::new("vg", vec![0], record(1)),
+            Job::new("esp", Vec::new(), record(2)),
+            Job::new("root", vec![1], record(3)),
+            Job::new("home", vec![1, 2], record(4)),
+        ];
+
+        assert_eq!(run_jobs(jobs), Ok(()));
+
+        let order = order.into_inner().unwrap();
+        let position = |id| order.iter().position(|&job| job == id).unwrap();
+        assert_eq!(order.len(), 5);
+        assert!(position(0) < position(1));
+        assert!(position(1) < position(3));
+        assert!(position(1) < position(4) && position(2) < position(4));
+    }
+
+    #[test]
+    fn dependents_of_failed_jobs_are_skipped() {
+        let ran = AtomicUsize::new(0);
+        let jobs = vec![
+            Job::new("luks", Vec::new(), || Err("wrong passphrase")),
+            Job::new("root", vec![0], || {
+                ran.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }),
+        ];
+
+        assert_eq!(run_jobs(jobs), Err("wrong passphrase"));
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
+}
@@ -17,12 +17,13 @@ extern crate tempdir;
 mod mklabel;
 mod mkpart;
 mod mvpart;
+mod jobs;
 mod ops;
 pub mod parted;
 mod resize;
 mod rmpart;
 
-pub use self::{mklabel::*, mkpart::*, mvpart::*, ops::*, resize::*, rmpart::*};
+pub use self::{jobs::*, mklabel::*, mkpart::*, mvpart::*, ops::*, resize::*, rmpart::*};
 
 const MEBIBYTE: u64 = 1_048_576;
 const MEGABYTE: u64 = 1_000_000;
@@ -1,4 +1,6 @@
-use disk_types::{BlockDeviceExt, FileSystem, PartitionExt, PartitionType, SectorExt};
+use disk_types::{
+    BlockDeviceExt, FileSystem, FormatOptions, PartitionExt, PartitionType, SectorExt,
+};
 use libparted::{
     Device, FileSystemType as PedFileSystem, Geometry, Partition as PedPartition, PartitionFlag,
     PartitionType as PedPartitionType,
@@ -29,6 +31,8 @@ pub struct PartitionCreate {
     pub flags:        Vec<PartitionFlag>,
     /// Defines the label to apply
     pub label:        Option<String>,
+    /// Options to apply when the file system is formatted.
+    pub mkfs_options: FormatOptions,
 }
 
 impl BlockDeviceExt for PartitionCreate {
@@ -1,12 +1,11 @@
 //! Contains source code for applying physical disk operations to disks.
 
 use super::*;
-use disk_types::{FileSystem, PartitionTable, PartitionType};
-use external::{blockdev, mkfs};
+use disk_types::{FileSystem, FormatOptions, PartitionTable, PartitionType};
+use external::{blockdev, mkfs_with};
 use libparted::{Device, Disk as PedDisk, Partition as PedPartition};
 use mkpart::PartitionCreate;
 use parted::*;
-use rayon::prelude::*;
 use resize::PartitionChange;
 use std::{
     io,
@@ -186,6 +185,7 @@ impl<'a> ChangePartitions<'a> {
                             kind,
                             flags,
                             label,
+                            mkfs_options: FormatOptions::default(),
                         },
                     )?;
 
@@ -209,7 +209,7 @@ impl<'a> ChangePartitions<'a> {
 pub struct CreatePartitions<'a> {
     device_path:       &'a Path,
     create_partitions: Vec<PartitionCreate>,
-    format_partitions: Vec<(PathBuf, FileSystem)>,
+    format_partitions: Vec<(PathBuf, FileSystem, FormatOptions)>,
 }
 
 impl<'a> CreatePartitions<'a> {
@@ -234,6 +234,7 @@ impl<'a> CreatePartitions<'a> {
                     partition
                         .file_system
                         .expect("file system does not exist when creating partition"),
+                    partition.mkfs_options.clone(),
                 ));
             }
         }
@@ -284,23 +285,30 @@ pub fn get_partition_id_and_path(path: &Path, start_sector: i64) -> io::Result<(
 
 /// The final stage of disk operations, where all partitions to be formatted can be
 /// formatted in parallel.
-pub struct FormatPartitions(pub Vec<(PathBuf, FileSystem)>);
+pub struct FormatPartitions(pub Vec<(PathBuf, FileSystem, FormatOptions)>);
 
 impl FormatPartitions {
-    /// Finally, format all of the modified and created partitions.
-    pub fn format(self) -> io::Result<()> {
-        info!("executing format operations");
+    /// The jobs which format the partitions, which depend on no other jobs.
+    pub fn jobs(&self) -> Vec<Job<io::Error>> {
         self.0
-            .par_iter()
-            .map(|&(ref part, fs)| {
-                info!("formatting {} with {:?}", part.display(), fs);
-                mkfs(part, fs).map_err(|why| {
-                    io::Error::new(
-                        why.kind(),
-                        format!("failed to format {} with {}: {}", part.display(), fs, why),
-                    )
+            .iter()
+            .map(|&(ref part, fs, ref options)| {
+                Job::new(format!("formatting {}", part.display()), Vec::new(), move || {
+                    info!("formatting {} with {:?}", part.display(), fs);
+                    mkfs_with(part, fs, options).map_err(|why| {
+                        io::Error::new(
+                            why.kind(),
+                            format!("failed to format {} with {}: {}", part.display(), fs, why),
+                        )
+                    })
                 })
             })
-            .collect::<io::Result<()>>()
+            .collect()
+    }
+
+    /// Finally, format all of the modified and created partitions.
+    pub fn format(self) -> io::Result<()> {
+        info!("executing format operations");
+        run_jobs(self.jobs())
     }
 }
@@ -1,12 +1,12 @@
 use self::FileSystem::*;
 use super::{move_partition, BlockCoordinates, OffsetCoordinates, MEBIBYTE, MEGABYTE};
 use disk_types::{FileSystem, PartitionType};
-use external::{blockdev, fsck};
+use external::{blockdev, exec, fsck};
 use libparted::PartitionFlag;
 use std::{
-    io::{self, Write},
+    ffi::OsString,
+    io,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
 };
 use sys_mount::*;
 use tempdir::TempDir;
@@ -108,10 +108,7 @@ pub fn resize_partition<P: AsRef<Path>>(
 ) -> io::Result<()> {
     info!("resizing {} to {}", path.as_ref().display(), size);
 
-    let mut resize_cmd = Command::new(cmd);
-    if !args.is_empty() {
-        resize_cmd.args(args);
-    }
+    let mut resize_args: Vec<OsString> = args.iter().map(OsString::from).collect();
 
     // Attempt to sync three times before returning an error.
     for attempt in 0..3 {
@@ -146,33 +143,26 @@ pub fn resize_partition<P: AsRef<Path>>(
         };
 
         if options & NO_SIZE != 0 {
-            resize_cmd.arg(&npath);
+            resize_args.push(npath.as_os_str().into());
         } else if options & SIZE_BEFORE_PATH != 0 {
-            resize_cmd.arg(size).arg(&npath);
+            resize_args.extend_from_slice(&[size.into(), npath.as_os_str().into()]);
         } else {
-            resize_cmd.arg(&npath).arg(size);
+            resize_args.extend_from_slice(&[npath.as_os_str().into(), size.into()]);
         };
 
-        let status = if options & NTFS != 0 {
+        let stdin = if options & NTFS != 0 {
             ntfs_dry_run(&npath, size)?;
-
-            info!("executing {:?}", resize_cmd);
-            let mut child = resize_cmd.stdin(Stdio::piped()).spawn()?;
-            child.stdin.as_mut().expect("failed to get stdin").write_all(b"y\n")?;
-            child.wait()?
+            Some(&b"y\n"[..])
         } else {
-            info!("executing {:?}", resize_cmd);
-            resize_cmd.status()?
+            None
         };
 
-        if status.success() {
-            Ok(())
-        } else {
-            Err(io::Error::new(
+        exec(cmd, stdin, None, &resize_args).map_err(|why| {
+            io::Error::new(
                 io::ErrorKind::Other,
-                format!("resize for {:?} failed with status: {}", path.as_ref().display(), status),
-            ))
-        }
+                format!("resize for {:?} failed: {}", path.as_ref().display(), why),
+            )
+        })
     })
 }
 
@@ -387,33 +377,11 @@ where
 }
 
 fn ntfs_dry_run(path: &Path, size: &str) -> io::Result<()> {
-    let mut consistency_check = Command::new("ntfsresize");
-    consistency_check.args(&["-f", "-f", "--no-action", "-s"]).arg(size).arg(path);
-
-    info!("executing {:?}", consistency_check);
-    let mut child = consistency_check.stdin(Stdio::piped()).spawn()?;
-    child.stdin.as_mut().expect("failed to get stdin").write_all(b"y\n")?;
-
-    let status = child.wait()?;
-    if status.success() {
-        Ok(())
-    } else {
-        Err(io::Error::new(io::ErrorKind::Other, format!("ntfsresize exited with {:?}", status)))
-    }
+    let args =
+        ["-f".into(), "-f".into(), "--no-action".into(), "-s".into(), size.into(), path.into()];
+    exec("ntfsresize", Some(b"y\n"), None, &args)
 }
 
 fn ntfs_consistency_check(path: &Path) -> io::Result<()> {
-    let mut consistency_check = Command::new("ntfsresize");
-    consistency_check.args(&["-i", "-f"]).arg(path);
-
-    info!("executing {:?}", consistency_check);
-    let mut child = consistency_check.stdin(Stdio::piped()).spawn()?;
-    child.stdin.as_mut().expect("failed to get stdin").write_all(b"y\n")?;
-
-    let status = child.wait()?;
-    if status.success() {
-        Ok(())
-    } else {
-        Err(io::Error::new(io::ErrorKind::Other, format!("ntfsresize exited with {:?}", status)))
-    }
+    exec("ntfsresize", Some(b"y\n"), None, &["-i".into(), "-f".into(), path.into()])
 }
@@ -1,4 +1,4 @@
-use std::{fmt, str::FromStr};
+use std::{fmt, path::PathBuf, str::FromStr};
 use sys_mount::FilesystemType as MountFS;
 
 /// Describes a file system format, such as ext4 or fat32.
@@ -129,3 +129,318 @@ impl From<FileSystem> for MountFS<'static> {
         })
     }
 }
+
+/// A kind of disk quota, which is tracked by the file system from the moment it is created.
+#[derive(Debug, PartialEq, Copy, Clone, Hash)]
+pub enum Quota {
+    User,
+    Group,
+    /// Quotas for directory trees, which are assigned project IDs.
+    Project,
+}
+
+/// An ext4 feature which may be enabled or disabled when the file system is created.
+#[derive(Debug, PartialEq, Copy, Clone, Hash)]
+pub enum Ext4Feature {
+    /// Block numbers of 64 bits, which allow file systems larger than 16 TiB.
+    Bit64,
+    /// Checksums of all metadata.
+    MetadataCsum,
+    /// Case-insensitive file names within directories which request them.
+    Casefold,
+    /// Per-directory encryption, through fscrypt.
+    Encrypt,
+}
+
+impl Ext4Feature {
+    /// The name of the feature, as it is given to `mke2fs -O`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Ext4Feature::Bit64 => "64bit",
+            Ext4Feature::MetadataCsum => "metadata_csum",
+            Ext4Feature::Casefold => "casefold",
+            Ext4Feature::Encrypt => "encrypt",
+        }
+    }
+
+    /// The earliest kernel release, as `(major, minor)`, which can mount a file system that
+    /// has the feature enabled.
+    pub fn min_kernel(self) -> (u32, u32) {
+        match self {
+            Ext4Feature::Bit64 => (2, 6),
+            Ext4Feature::MetadataCsum => (3, 6),
+            Ext4Feature::Casefold => (5, 2),
+            Ext4Feature::Encrypt => (4, 1),
+        }
+    }
+}
+
+impl FromStr for Ext4Feature {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let feature = match string {
+            "64bit" => Ext4Feature::Bit64,
+            "metadata_csum" => Ext4Feature::MetadataCsum,
+            "casefold" => Ext4Feature::Casefold,
+            "encrypt" => Ext4Feature::Encrypt,
+            _ => return Err("invalid ext4 feature name"),
+        };
+        Ok(feature)
+    }
+}
+
+/// Options for Btrfs file systems, which determine how they are mounted, and which are
+/// applied to them once they have been created.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BtrfsOptions {
+    /// The level of zstd compression, from 1 to 15, or `None` to store data uncompressed.
+    pub compression:    Option<u8>,
+    /// Enables quota groups, which track the space used by each subvolume, at some cost to
+    /// the performance of snapshots.
+    pub quotas:         bool,
+    /// Uses the free space tree, rather than the original free space cache.
+    pub space_cache_v2: bool,
+    /// Subvolumes, relative to the root of the file system, which are created with
+    /// copy-on-write disabled, for the disk images of virtual machines and for swap files.
+    pub nodatacow:      Vec<PathBuf>,
+}
+
+impl Default for BtrfsOptions {
+    fn default() -> Self {
+        BtrfsOptions {
+            compression:    Some(1),
+            quotas:         false,
+            space_cache_v2: true,
+            nodatacow:      Vec::new(),
+        }
+    }
+}
+
+impl BtrfsOptions {
+    /// The options which are given to the file system when it is mounted, excluding those
+    /// which are handled by the kernel's VFS, such as `noatime`.
+    pub fn mount_data(&self) -> String {
+        let mut options = Vec::new();
+        if let Some(level) = self.compression {
+            options.push(format!("compress=zstd:{}", level.max(1).min(15)));
+        }
+
+        if self.space_cache_v2 {
+            options.push("space_cache=v2".to_owned());
+        }
+
+        options.join(",")
+    }
+
+    /// The options of the file system's entry in the fstab.
+    pub fn fstab_options(&self) -> String {
+        let data = self.mount_data();
+        if data.is_empty() {
+            "noatime".to_owned()
+        } else {
+            ["noatime,", &data].concat()
+        }
+    }
+}
+
+/// Options for FAT file systems, such as those of ESPs.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct FatOptions {
+    /// The volume label, which must satisfy `fat_label_error`.
+    pub label:     Option<String>,
+    /// The volume ID, which is derived from the partition's GPT UUID if it is not given.
+    pub volume_id: Option<u32>,
+}
+
+/// Characters which DOS forbids in the names of files and volumes.
+const FAT_FORBIDDEN: &str = "\"*+,./:;<=>?[\\]|";
+
+/// The reason that `label` cannot be written to a FAT volume, if it cannot be. Labels are
+/// limited to 11 characters of uppercase ASCII, as firmwares and Windows compare them without
+/// regard to code pages or case.
+pub fn fat_label_error(label: &str) -> Option<&'static str> {
+    if label.is_empty() {
+        Some("FAT labels cannot be empty")
+    } else if label.len() > 11 {
+        Some("FAT labels cannot be longer than 11 characters")
+    } else if label.starts_with(' ') {
+        Some("FAT labels cannot begin with a space")
+    } else if label.chars().any(|c| !c.is_ascii() || c.is_ascii_control()) {
+        Some("FAT labels may only contain printable ASCII characters")
+    } else if label.chars().any(|c| c.is_ascii_lowercase()) {
+        Some("FAT labels cannot contain lowercase letters")
+    } else if label.chars().any(|c| FAT_FORBIDDEN.contains(c)) {
+        Some("FAT labels cannot contain any of the characters \"*+,./:;<=>?[\\]|")
+    } else {
+        None
+    }
+}
+
+/// The size of the clusters, in bytes, of a FAT file system of `size` bytes on a device with
+/// logical sectors of `sector_size` bytes.
+///
+/// Sizes follow the defaults of Windows, which firmwares are most tested against. `None` is
+/// returned if the file system would not have a number of clusters which its FAT type permits,
+/// as firmwares determine the type from the count of clusters rather than the boot sector.
+pub fn fat_cluster_size(kind: FileSystem, size: u64, sector_size: u64) -> Option<u64> {
+    const MIB: u64 = 1024 * 1024;
+    let (cluster, min, max): (u64, u64, u64) = match kind {
+        FileSystem::Fat16 => {
+            let cluster = match size {
+                s if s <= 32 * MIB => 512,
+                s if s <= 64 * MIB => 1024,
+                s if s <= 128 * MIB => 2048,
+                s if s <= 256 * MIB => 4096,
+                s if s <= 512 * MIB => 8192,
+                s if s <= 1024 * MIB => 16384,
+                s if s <= 2048 * MIB => 32768,
+                _ => 65536,
+            };
+            (cluster, 4085, 65524)
+        }
+        FileSystem::Fat32 => {
+            let cluster = match size {
+                s if s <= 260 * MIB => 512,
+                s if s <= 8192 * MIB => 4096,
+                s if s <= 16384 * MIB => 8192,
+                s if s <= 32768 * MIB => 16384,
+                _ => 32768,
+            };
+            (cluster, 65525, 0x0FFF_FFF4)
+        }
+        _ => return None,
+    };
+
+    // The reserved sectors, the root directory of FAT16, and the two copies of the FAT, which
+    // has an entry for every cluster, are not counted as clusters.
+    let (reserved, root_dir, entry) = match kind {
+        FileSystem::Fat16 => (sector_size, 16384, 2),
+        _ => (32 * sector_size, 0, 4),
+    };
+
+    let data = size.saturating_sub(reserved + root_dir);
+    let clusters = |cluster: u64| data / (cluster + 2 * entry);
+
+    let mut cluster = cluster.max(sector_size);
+    while clusters(cluster) > max && cluster < 65536 {
+        cluster *= 2;
+    }
+
+    let count = clusters(cluster);
+    if count >= min && count <= max {
+        Some(cluster)
+    } else {
+        None
+    }
+}
+
+/// Options which are applied when a file system is created.
+///
+/// Most are only supported by the ext family of file systems, and are ignored by others.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct FormatOptions {
+    /// The percentage of blocks which are reserved for root, rather than the default of 5%.
+    pub reserved_blocks: Option<u8>,
+    /// The number of bytes for which an inode is created, which limits the number of files.
+    pub inode_ratio:     Option<u32>,
+    /// The quotas to enable. These require ext4.
+    pub quotas:          Vec<Quota>,
+    /// Ext4 features which are enabled, or disabled if `false`, rather than left to the
+    /// defaults of `/etc/mke2fs.conf`.
+    pub features:        Vec<(Ext4Feature, bool)>,
+    /// Options which only apply to Btrfs.
+    pub btrfs:           BtrfsOptions,
+    /// Options which only apply to FAT file systems.
+    pub fat:             FatOptions,
+}
+
+impl FormatOptions {
+    /// Whether any options which are specific to the ext family of file systems are set.
+    pub fn has_ext_options(&self) -> bool {
+        self.reserved_blocks.is_some()
+            || self.inode_ratio.is_some()
+            || !self.quotas.is_empty()
+            || !self.features.is_empty()
+    }
+
+    /// Enables or disables an ext4 feature, replacing any previous choice for it.
+    pub fn set_feature(&mut self, feature: Ext4Feature, enabled: bool) {
+        self.features.retain(|&(existing, _)| existing != feature);
+        self.features.push((feature, enabled));
+    }
+
+    /// The enabled features which a kernel of the given `(major, minor)` release cannot mount.
+    pub fn unsupported_features(&self, kernel: (u32, u32)) -> Vec<Ext4Feature> {
+        self.features
+            .iter()
+            .filter(|&&(feature, enabled)| enabled && feature.min_kernel() > kernel)
+            .map(|&(feature, _)| feature)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ext4_features() {
+        let mut options = FormatOptions::default();
+        options.set_feature(Ext4Feature::Casefold, true);
+        options.set_feature(Ext4Feature::Encrypt, false);
+        options.set_feature(Ext4Feature::MetadataCsum, true);
+        options.set_feature(Ext4Feature::Encrypt, true);
+
+        assert_eq!(
+            options.features,
+            vec![
+                (Ext4Feature::Casefold, true),
+                (Ext4Feature::MetadataCsum, true),
+                (Ext4Feature::Encrypt, true),
+            ]
+        );
+
+        assert_eq!(
+            options.unsupported_features((4, 0)),
+            vec![Ext4Feature::Casefold, Ext4Feature::Encrypt]
+        );
+        assert_eq!(options.unsupported_features((4, 15)), vec![Ext4Feature::Casefold]);
+        assert!(options.unsupported_features((5, 4)).is_empty());
+    }
+
+    #[test]
+    fn btrfs_mount_options() {
+        let mut options = BtrfsOptions::default();
+        assert_eq!(options.mount_data(), "compress=zstd:1,space_cache=v2");
+        assert_eq!(options.fstab_options(), "noatime,compress=zstd:1,space_cache=v2");
+
+        options.compression = Some(20);
+        options.space_cache_v2 = false;
+        assert_eq!(options.fstab_options(), "noatime,compress=zstd:15");
+
+        options.compression = None;
+        assert_eq!(options.mount_data(), "");
+        assert_eq!(options.fstab_options(), "noatime");
+    }
+
+    #[test]
+    fn fat_constraints() {
+        assert_eq!(fat_label_error("EFI"), None);
+        assert_eq!(fat_label_error("POP_OS 2204"), None);
+        assert!(fat_label_error("").is_some());
+        assert!(fat_label_error("efi").is_some());
+        assert!(fat_label_error("SYSTEM.BOOT").is_some());
+        assert!(fat_label_error("ESP-PARTITION").is_some());
+
+        const MIB: u64 = 1024 * 1024;
+        assert_eq!(fat_cluster_size(FileSystem::Fat32, 512 * MIB, 512), Some(4096));
+        assert_eq!(fat_cluster_size(FileSystem::Fat32, 256 * MIB, 512), Some(512));
+        assert_eq!(fat_cluster_size(FileSystem::Fat32, 256 * MIB, 4096), None);
+        assert_eq!(fat_cluster_size(FileSystem::Fat32, 300 * MIB, 4096), Some(4096));
+        assert_eq!(fat_cluster_size(FileSystem::Fat16, 256 * MIB, 512), Some(4096));
+        assert_eq!(fat_cluster_size(FileSystem::Fat16, 2048 * MIB, 512), Some(65536));
+        assert_eq!(fat_cluster_size(FileSystem::Fat16, MIB, 512), None);
+        assert_eq!(fat_cluster_size(FileSystem::Ext4, 512 * MIB, 512), None);
+    }
+}
@@ -1,3 +1,4 @@
+extern crate distinst_utils as misc;
 #[macro_use]
 extern crate err_derive;
 #[macro_use]
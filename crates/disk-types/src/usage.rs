@@ -1,82 +1,48 @@
 use crate::fs::FileSystem;
+use misc::runner::{runner, CommandOutput};
 use std::{
+    ffi::OsString,
     io::{self, BufRead, Cursor},
     path::Path,
-    process::{Command, Stdio},
 };
 
 /// Executes a given file system's dump command to obtain the minimum shrink
 /// size
 pub fn sectors_used<P: AsRef<Path>>(part: P, fs: FileSystem) -> io::Result<u64> {
     use self::FileSystem::*;
+    let part = part.as_ref();
     match fs {
         Ext2 | Ext3 | Ext4 => {
-            let reader = Cursor::new(
-                Command::new("dumpe2fs")
-                    .arg("-h")
-                    .arg(part.as_ref())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::null())
-                    .output()?
-                    .stdout,
-            );
-
+            let reader = Cursor::new(capture("dumpe2fs", &["-h"], part)?.stdout);
             get_ext4_usage(reader.lines().skip(1))
         }
         Fat16 | Fat32 => {
-            let mut cmd = Command::new("fsck.fat")
-                .arg("-nv")
-                .arg(part.as_ref())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .output()?;
-
-            if !cmd.status.success() {
+            let mut cmd = capture("fsck.fat", &["-nv"], part)?;
+
+            if !cmd.success() {
                 // If a failure occurred, try to correct any fixable errors.
-                Command::new("fsck.fat")
-                    .arg("-fy")
-                    .arg(part.as_ref())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::null())
-                    .output()?;
+                capture("fsck.fat", &["-fy"], part)?;
 
                 // Then re-run the fsck command to get the status again.
-                cmd = Command::new("fsck.fat")
-                    .arg("-nv")
-                    .arg(part.as_ref())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::null())
-                    .output()?;
+                cmd = capture("fsck.fat", &["-nv"], part)?;
             }
 
             let reader = Cursor::new(cmd.stdout);
             get_fat_usage(reader.lines().skip(1))
         }
         Ntfs => {
-            let cmd = Command::new("ntfsresize")
-                .arg("--info")
-                .arg("--force")
-                .arg("--no-progress-bar")
-                .arg(part.as_ref())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .output()?;
+            let cmd = capture("ntfsresize", &["--info", "--force", "--no-progress-bar"], part)?;
 
+            let success = cmd.success();
             let reader = Cursor::new(cmd.stdout).lines().skip(1);
-            if cmd.status.success() {
+            if success {
                 get_ntfs_usage(reader)
             } else {
                 get_ntfs_size(reader)
             }
         }
         Btrfs => {
-            let cmd = Command::new("btrfs")
-                .arg("filesystem")
-                .arg("show")
-                .arg(part.as_ref())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .output()?;
+            let cmd = capture("btrfs", &["filesystem", "show"], part)?;
 
             let reader = Cursor::new(cmd.stdout).lines().skip(1);
             get_btrfs_usage(reader)
@@ -85,6 +51,13 @@ pub fn sectors_used<P: AsRef<Path>>(part: P, fs: FileSystem) -> io::Result<u64>
     }
 }
 
+/// Runs `cmd` on the partition with the journaled runner, capturing its standard output.
+fn capture(cmd: &str, args: &[&str], part: &Path) -> io::Result<CommandOutput> {
+    let args: Vec<OsString> =
+        args.iter().map(OsString::from).chain(Some(part.as_os_str().into())).collect();
+    runner().run(cmd, &args, None, true)
+}
+
 fn get_btrfs_usage<R: Iterator<Item = io::Result<String>>>(mut reader: R) -> io::Result<u64> {
     parse_field_as_unit(&mut reader, "Total devices", 6).map(|used| used / 512)
 }
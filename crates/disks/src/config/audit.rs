@@ -0,0 +1,253 @@
+//! An audit log of the destructive changes which are committed to disks, so that there is
+//! somewhere to begin recovering data after a disk was wiped by mistake.
+//!
+//! Each commit appends lines of JSON to `DISK_AUDIT_LOG`: the partition table as it was, in the
+//! format of `sfdisk --dump`, and each partition which is removed, moved, or formatted over,
+//! with a hint of how its entry in the table may be restored. Restoring an entry recovers a
+//! partition whose file system has not since been overwritten.
+
+use super::{Disk, DiskExt, PartitionInfo};
+use crate::external::sfdisk_dump;
+use disk_types::{BlockDeviceExt, PartitionTableExt};
+use operations::DiskOps;
+use serde_json::Value;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Where the audit log is appended to. Only root may write to `/var/log`, so that the log may
+/// not be replaced with a link to another file before it is appended to.
+pub const DISK_AUDIT_LOG: &str = "/var/log/distinst-disk-audit.jsonl";
+
+/// Appends the destructive changes of `ops`, which are about to be applied to `source`, to the
+/// audit log. The changes are not prevented if the log cannot be written.
+pub(crate) fn record(source: &Disk, ops: &DiskOps) {
+    let disk = source.get_device_path();
+    let dump = sfdisk_dump(disk).unwrap_or_else(|why| {
+        warn!("unable to dump the partition table of {}: {}", disk.display(), why);
+        String::new()
+    });
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let entries = entries(source, ops, &dump, time);
+    if entries.is_empty() {
+        return;
+    }
+
+    if let Err(why) = append(&entries) {
+        warn!("unable to write the disk audit log at {}: {}", DISK_AUDIT_LOG, why);
+    }
+}
+
+fn append(entries: &[Value]) -> io::Result<()> {
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&entry.to_string());
+        lines.push('\n');
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(DISK_AUDIT_LOG)?;
+
+    // A log which was created by another user may have been read, or written to, by them.
+    if file.metadata()?.uid() != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the disk audit log is not owned by root",
+        ));
+    }
+
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()
+}
+
+/// The audit entries of the changes of `ops` to `source`, whose table was dumped as `dump`.
+fn entries(source: &Disk, ops: &DiskOps, dump: &str, time: u64) -> Vec<Value> {
+    let disk = source.get_device_path();
+    let partitions = source.get_partitions();
+    let mut entries = Vec::new();
+
+    let removed = partitions.iter().filter(|part| {
+        ops.mklabel.is_some() || ops.remove_partitions.contains(&part.start_sector)
+    });
+
+    for part in removed {
+        entries.push(partition_entry("remove", disk, part, dump, time));
+    }
+
+    for change in &ops.change_partitions {
+        let part = match partitions.iter().find(|part| part.number == change.num) {
+            Some(part) => part,
+            None => continue,
+        };
+
+        if (part.start_sector, part.end_sector) == (change.start, change.end) {
+            continue;
+        }
+
+        let mut entry = partition_entry("resize", disk, part, dump, time);
+        entry["new_start_sector"] = json!(change.start);
+        entry["new_end_sector"] = json!(change.end);
+        entry["hint"] = json!(format!(
+            "data beyond the new end of a shrunk partition is lost; its previous geometry may be \
+             restored with: echo 'start={}, size={}' | sfdisk -N {} {}",
+            part.start_sector,
+            part.end_sector - part.start_sector + 1,
+            part.number,
+            disk.display()
+        ));
+        entries.push(entry);
+    }
+
+    for create in ops.create_partitions.iter().filter(|create| create.format) {
+        let overwritten: Vec<&Path> = partitions
+            .iter()
+            .filter(|part| {
+                part.filesystem.is_some()
+                    && part.start_sector <= create.end_sector
+                    && create.start_sector <= part.end_sector
+            })
+            .map(|part| part.get_device_path())
+            .collect();
+
+        if overwritten.is_empty() {
+            continue;
+        }
+
+        entries.push(json!({
+            "time": time,
+            "action": "format",
+            "disk": disk,
+            "start_sector": create.start_sector,
+            "end_sector": create.end_sector,
+            "file_system": create.file_system.map(<&'static str>::from),
+            "overwrites": overwritten,
+            "hint": "the file systems which this overwrites cannot be mounted again, but files \
+                     may be carved from them with tools such as photorec",
+        }));
+    }
+
+    if entries.is_empty() {
+        return entries;
+    }
+
+    let action = if ops.mklabel.is_some() { "mklabel" } else { "table" };
+    let table = json!({
+        "time": time,
+        "action": action,
+        "disk": disk,
+        "table": source.get_partition_table().map(|table| format!("{:?}", table)),
+        "new_table": ops.mklabel.map(|table| format!("{:?}", table)),
+        "dump": dump,
+        "hint": format!(
+            "every partition of the table may be restored by writing the dump to a file, and \
+             then running: sfdisk {} < file",
+            disk.display()
+        ),
+    });
+
+    entries.insert(0, table);
+    entries
+}
+
+fn partition_entry(
+    action: &str,
+    disk: &Path,
+    part: &PartitionInfo,
+    dump: &str,
+    time: u64,
+) -> Value {
+    let fields = dump_entry(dump, part.get_device_path());
+    let hint = match fields {
+        Some(fields) => format!("echo '{}' | sfdisk --append {}", fields, disk.display()),
+        None => format!(
+            "echo 'start={}, size={}' | sfdisk --append {}",
+            part.start_sector,
+            part.end_sector - part.start_sector + 1,
+            disk.display()
+        ),
+    };
+
+    json!({
+        "time": time,
+        "action": action,
+        "disk": disk,
+        "path": part.get_device_path(),
+        "number": part.number,
+        "start_sector": part.start_sector,
+        "end_sector": part.end_sector,
+        "type": fields.and_then(|fields| dump_field(fields, "type")),
+        "file_system": part.filesystem.map(<&'static str>::from),
+        "label": part.name,
+        "fs_label": part.identifiers.label,
+        "uuid": part.identifiers.uuid,
+        "part_uuid": part.identifiers.part_uuid,
+        "hint": hint,
+    })
+}
+
+/// The fields of the partition at `path` in a dump of sfdisk, such as
+/// `start=2048, size=1048576, type=C12A7328-F81F-11D2-BA4B-00A0C93EC93B`.
+fn dump_entry<'a>(dump: &'a str, path: &Path) -> Option<&'a str> {
+    let path = path.to_str()?;
+    dump.lines().find_map(|line| {
+        let mut entry = line.splitn(2, ':');
+        if entry.next()?.trim() == path {
+            entry.next().map(str::trim)
+        } else {
+            None
+        }
+    })
+}
+
+/// The value of a field of an entry in a dump of sfdisk.
+fn dump_field(fields: &str, key: &str) -> Option<String> {
+    fields.split(',').find_map(|field| {
+        let mut field = field.splitn(2, '=');
+        if field.next()?.trim() == key {
+            field.next().map(|value| value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "label: gpt\nlabel-id: 4A0F2E7C-3B1D-4C8E-9E55-0A7B6D1F2C3A\n\
+                        device: /dev/sda\nunit: sectors\n\n\
+                        /dev/sda1 : start=        2048, size=     1048576, \
+                        type=C12A7328-F81F-11D2-BA4B-00A0C93EC93B, name=\"EFI\"\n\
+                        /dev/sda2 : start=     1050624, size=    20971520, \
+                        type=0FC63DAF-8483-4772-8E79-3D69D8477DE4\n";
+
+    #[test]
+    fn sfdisk_dump_entries() {
+        let fields = dump_entry(DUMP, Path::new("/dev/sda2")).unwrap();
+        assert_eq!(
+            fields,
+            "start=     1050624, size=    20971520, type=0FC63DAF-8483-4772-8E79-3D69D8477DE4"
+        );
+        assert_eq!(
+            dump_field(fields, "type").as_deref(),
+            Some("0FC63DAF-8483-4772-8E79-3D69D8477DE4")
+        );
+        assert_eq!(dump_field(fields, "start").as_deref(), Some("1050624"));
+
+        let fields = dump_entry(DUMP, Path::new("/dev/sda1")).unwrap();
+        assert_eq!(dump_field(fields, "name").as_deref(), Some("EFI"));
+
+        assert_eq!(dump_entry(DUMP, Path::new("/dev/sda")), None);
+        assert_eq!(dump_entry(DUMP, Path::new("/dev/sda3")), None);
+    }
+}
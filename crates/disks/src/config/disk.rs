@@ -1,28 +1,35 @@
 use super::{
     super::{
-        serial::get_serial, BlockDeviceExt, DiskError, DiskExt, Disks, FileSystem, PartitionError,
-        PartitionFlag, PartitionInfo, PartitionTable, PartitionType,
+        privileges::{require_access, Capability},
+        serial::get_serial,
+        udev::Properties,
+        BlockDeviceExt, DiskError, DiskExt, Disks, FileSystem, PartitionError, PartitionFlag,
+        PartitionInfo, PartitionTable, PartitionType,
     },
+    audit,
     partitions::{FORMAT, REMOVE, SOURCE, SWAPPED},
+    protected,
     PVS,
 };
 use disk_types::{PartitionExt, PartitionTableExt, SectorExt};
 use crate::external::{is_encrypted, pvs};
 use libparted::{Device, DeviceType, Disk as PedDisk};
+use misc::namespace::THREAD_MOUNTS;
 use operations::{
     parted::{get_device, open_disk},
     *,
 };
 use partition_identity::PartitionID;
-use proc_mounts::{MOUNTS, SWAPS};
+use proc_mounts::{MountList, SWAPS};
 use rayon::prelude::*;
 use std::{
     collections::BTreeSet,
-    io,
+    fs, io,
     path::{Path, PathBuf},
     str,
 };
 use sys_mount::{unmount, UnmountFlags};
+use sysfs_class::{Block, SysClass};
 
 /// Detects a partition on the device, if it exists.
 /// Useful for detecting if a LUKS device has a file system.
@@ -36,7 +43,8 @@ pub fn detect_fs_on_device(path: &Path) -> Option<PartitionInfo> {
                     }
                 }
 
-                let mounts = MOUNTS.read().expect("failed to get mounts in Disk::new");
+                let mounts = MountList::new_from_file(THREAD_MOUNTS)
+                    .expect("failed to get mounts in Disk::new");
                 let swaps = SWAPS.read().expect("failed to get swaps in Disk::new");
 
                 match PartitionInfo::new_from_ped(&part) {
@@ -88,12 +96,18 @@ pub fn detect_fs_on_device(path: &Path) -> Option<PartitionInfo> {
 /// # Note
 ///
 /// The `device_path` field may be used for identification of the device in the system.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Disk {
     /// The model name of the device, assigned by the manufacturer.
     pub model_name:  String,
     /// A unique identifier to this disk.
     pub serial:      String,
+    /// The World Wide Name of the disk, if it has one, such as `0x5002538d403d649a`.
+    pub wwn:         String,
+    /// The bus which the disk is attached by, such as `ata`, `usb`, or `nvme`.
+    pub bus:         String,
+    /// Whether the disk is a spinning hard drive.
+    pub rotational:  bool,
     /// The location in the file system where the block device is located.
     pub device_path: PathBuf,
     /// Account for the possibility that the entire disk is a file system.
@@ -121,6 +135,8 @@ impl BlockDeviceExt for Disk {
     fn get_mount_point(&self) -> Option<&Path> { self.mount_point.as_deref() }
 
     fn is_read_only(&self) -> bool { self.read_only }
+
+    fn is_rotational(&self) -> bool { self.rotational }
 }
 
 impl SectorExt for Disk {
@@ -185,15 +201,28 @@ impl Disk {
             _ => None,
         });
 
-        let mounts = MOUNTS.read().expect("failed to get mounts in Disk::new");
+        // Encrypted devices and loop devices have no identifiers, as with their serials.
+        let identified = match device.type_() {
+            DeviceType::PED_DEVICE_DM | DeviceType::PED_DEVICE_LOOP => None,
+            _ => device_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| Block::new(name).ok().map(|block| (name.to_owned(), block))),
+        };
+
+        let mounts = MountList::new_from_file(THREAD_MOUNTS)
+            .expect("failed to get mounts in Disk::new");
         let swaps = SWAPS.read().expect("failed to get swaps in Disk::new");
 
-        Ok(Disk {
+        let mut probed = Disk {
             model_name,
             mount_point: mounts.get_mount_by_source(&device_path).map(|m| m.dest.clone()),
             device_path,
             file_system: None,
             serial,
+            wwn: String::new(),
+            bus: String::new(),
+            rotational: false,
             size,
             device_type,
             read_only,
@@ -226,7 +255,106 @@ impl Disk {
             } else {
                 Vec::new()
             },
-        })
+        };
+
+        if let Some((name, block)) = identified {
+            let properties = block.dev().map(|dev| Properties::of(dev.trim())).unwrap_or_default();
+            probed.identify(&name, &block, &properties);
+        }
+
+        Ok(probed)
+    }
+
+    /// Obtains the disk information of the named block device, such as `sda`, from sysfs and
+    /// the udev database. Unlike `Disk::new`, this does not require root.
+    pub fn new_from_sysfs(name: &str) -> Result<Disk, DiskError> {
+        info!("obtaining disk information from /sys/block/{}", name);
+        let device_path = Path::new("/dev").join(name);
+        let block = Block::new(name)
+            .map_err(|why| DiskError::DeviceGet { device: device_path.clone(), why })?;
+
+        let properties = Properties::of(block.dev()?.trim());
+        let sector_size = block.queue_logical_block_size().unwrap_or(512);
+        let table_type = properties.partition_table();
+
+        let device_info = |file: &str| {
+            fs::read_to_string(block.path().join("device").join(file))
+                .ok()
+                .map(|value| value.trim().to_owned())
+                .filter(|value| !value.is_empty())
+        };
+
+        let model_name = match (device_info("vendor"), device_info("model")) {
+            (Some(vendor), Some(model)) => [&vendor, " ", &model].concat(),
+            (None, Some(model)) => model,
+            _ => "Unknown".into(),
+        };
+
+        let device_type = if name.starts_with("nvme") {
+            "PED_DEVICE_NVME"
+        } else if name.starts_with("mmcblk") {
+            "PED_DEVICE_SDMMC"
+        } else if name.starts_with("vd") {
+            "PED_DEVICE_VIRTBLK"
+        } else if name.starts_with("md") {
+            "PED_DEVICE_MD"
+        } else {
+            "PED_DEVICE_SCSI"
+        };
+
+        let mounts = MountList::new_from_file(THREAD_MOUNTS)
+            .expect("failed to get mounts in Disk::new_from_sysfs");
+        let swaps = SWAPS.read().expect("failed to get swaps in Disk::new_from_sysfs");
+
+        let mut partitions = Vec::new();
+        if let Some(table) = table_type {
+            for child in block.children()? {
+                let part =
+                    PartitionInfo::new_from_sysfs(&child, table, sector_size, &mounts, &swaps)
+                        .map_err(|why| DiskError::MountsObtain { why })?;
+                partitions.extend(part);
+            }
+
+            partitions.sort_by_key(|part| part.start_sector);
+            for (ordering, part) in partitions.iter_mut().enumerate() {
+                part.ordering = ordering as i32;
+            }
+        }
+
+        let mut disk = Disk {
+            model_name,
+            mount_point: mounts.get_mount_by_source(&device_path).map(|m| m.dest.clone()),
+            device_path,
+            file_system: None,
+            serial: properties.get("ID_SERIAL").unwrap_or("").into(),
+            wwn: String::new(),
+            bus: String::new(),
+            rotational: false,
+            size: block.size()? * 512 / sector_size,
+            device_type: device_type.into(),
+            read_only: block.ro()? == 1,
+            table_type,
+            mklabel: false,
+            partitions,
+        };
+
+        disk.identify(name, &block, &properties);
+        Ok(disk)
+    }
+
+    /// Records the identifiers of the disk which udev and sysfs report, by which it may be
+    /// selected across boots.
+    fn identify(&mut self, name: &str, block: &Block, properties: &Properties) {
+        self.wwn = properties.wwn().unwrap_or("").into();
+        self.rotational = block.queue_rotational().ok() == Some(1);
+        self.bus = match properties.get("ID_BUS") {
+            Some(bus) => bus.into(),
+            // udev records no bus for NVMe, MMC, and virtio devices.
+            None if name.starts_with("nvme") => "nvme".into(),
+            None if name.starts_with("mmcblk") => "mmc".into(),
+            None if name.starts_with("vd") => "virtio".into(),
+            None => String::new(),
+        };
     }
 
     /// Obtains the disk that corresponds to a given device path.
@@ -234,6 +362,7 @@ impl Disk {
     /// The `name` of the device should be a path, such as `/dev/sda`. If the device could
     /// not be found, then `Err(DiskError::DeviceGet)` will be returned.
     pub fn from_name<P: AsRef<Path>>(name: P) -> Result<Disk, DiskError> {
+        require_access(name.as_ref(), Capability::DacReadSearch, "probing partition tables")?;
         get_device(name).map_err(Into::into).and_then(|mut device| Disk::new(&mut device, true))
     }
 
@@ -264,6 +393,12 @@ impl Disk {
     /// Returns the serial of the device, filled in by the manufacturer.
     pub fn get_serial(&self) -> &str { &self.serial }
 
+    /// Returns the World Wide Name of the device, which is empty if it has none.
+    pub fn get_wwn(&self) -> &str { &self.wwn }
+
+    /// Returns the bus which the device is attached by, which is empty if it is unknown.
+    pub fn get_bus(&self) -> &str { &self.bus }
+
     pub fn is_being_modified(&self) -> bool {
         self.partitions.iter().any(|x| {
             x.bitflags & REMOVE != 0
@@ -306,8 +441,8 @@ impl Disk {
 
         let swaps =
             SWAPS.read().expect("failed to get swaps in unmount_all_partitions_with_target");
-        let mountstab =
-            MOUNTS.read().expect("failed to get mounts in unmount_all_partitions_with_target");
+        let mountstab = MountList::new_from_file(THREAD_MOUNTS)
+            .expect("failed to get mounts in unmount_all_partitions_with_target");
 
         for partition in &mut self.partitions {
             partition.deactivate_if_swap(&swaps)?;
@@ -722,6 +857,7 @@ impl Disk {
                                         kind:         new.part_type,
                                         flags:        new.flags.clone(),
                                         label:        new.name.clone(),
+                                        mkfs_options: new.mkfs_options.clone(),
                                     });
                                 } else {
                                     change_partitions.push(PartitionChange {
@@ -769,6 +905,7 @@ impl Disk {
                 kind:         partition.part_type,
                 flags:        partition.flags.clone(),
                 label:        partition.name.clone(),
+                mkfs_options: partition.mkfs_options.clone(),
             });
         }
 
@@ -789,6 +926,9 @@ impl Disk {
                 if ops.is_empty() {
                     Ok(None)
                 } else {
+                    protected::check_disk(&source, self)?;
+                    self.verify_gpt()?;
+                    audit::record(&source, &ops);
                     let partitions_to_format = ops
                         .remove()
                         .and_then(|ops| ops.change())
@@ -815,8 +955,15 @@ impl Disk {
                 let mount = partition.target.as_ref().map(|ref path| path.to_path_buf());
                 let vg = partition.volume_group.as_ref().cloned();
                 let keyid = partition.key_id.as_ref().cloned();
-                if mount.is_some() || vg.is_some() || keyid.is_some() {
-                    Some((start, mount, vg, keyid))
+                // Some options, such as those of Btrfs, still apply once formatting is done.
+                let options = if partition.flag_is_enabled(FORMAT) {
+                    Some(partition.mkfs_options.clone())
+                } else {
+                    None
+                };
+
+                if mount.is_some() || vg.is_some() || keyid.is_some() || options.is_some() {
+                    Some((start, mount, vg, keyid, options))
                 } else {
                     None
                 }
@@ -827,7 +974,7 @@ impl Disk {
         *self = Disk::from_name_with_serial(&self.device_path, &self.serial)?;
 
         // Then re-add the critical information which was lost.
-        for (sector, mount, vg, keyid) in collected {
+        for (sector, mount, vg, keyid, options) in collected {
             info!("checking for mount target at {}", sector);
             let part = self
                 .get_partition_at(sector)
@@ -837,6 +984,9 @@ impl Disk {
             part.target = mount;
             part.volume_group = vg;
             part.key_id = keyid;
+            if let Some(options) = options {
+                part.mkfs_options = options;
+            }
         }
 
         Ok(())
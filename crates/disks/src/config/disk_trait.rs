@@ -3,7 +3,8 @@ use super::{
     partitions::REMOVE,
 };
 use disk_types::{BlockDeviceExt, PartitionExt, PartitionTableError, PartitionTableExt, SectorExt};
-use proc_mounts::MOUNTS;
+use misc::namespace::THREAD_MOUNTS;
+use proc_mounts::MountList;
 use std::path::{Path, PathBuf};
 use sysfs_class::{Block, SysClass};
 
@@ -43,7 +44,8 @@ pub trait DiskExt: BlockDeviceExt + SectorExt + PartitionTableExt {
     fn contains_mount(&self, mount: &str, parent: &Disks) -> bool {
         let check_sysfs = || {
             // check for partitions that linux found, but parted may not have
-            let mounts = MOUNTS.read().expect("failed to get mounts in DiskExt::contains_mount");
+            let mounts = MountList::new_from_file(THREAD_MOUNTS)
+                .expect("failed to get mounts in DiskExt::contains_mount");
 
             let name: String = self
                 .get_device_path()
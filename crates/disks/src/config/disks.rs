@@ -1,35 +1,57 @@
 use super::{
     super::{
-        Bootloader, DecryptionError, DiskError, DiskExt, FileSystem, LogicalDevice, PartitionFlag,
-        PartitionInfo,
+        privileges::{require_access, Capability},
+        Bootloader, CodedError, DecryptionError, DiskError, DiskExt, ErrorCode, FileSystem,
+        KernelLoader, LogicalDevice, PartitionFlag, PartitionInfo,
     },
     detect_fs_on_device, find_partition, find_partition_mut,
-    partitions::{FORMAT, REMOVE, SOURCE},
-    Disk, LvmEncryption, PartitionTable, PVS,
+    partitions::{FORMAT, OS, REMOVE, SOURCE},
+    lv_map_name, protected, Disk, LvmEncryption, PartitionTable, PVS,
+};
+use disk_types::{
+    fat_cluster_size, fat_label_error, BlockDeviceExt, PartitionExt, PartitionTableExt, SectorExt,
 };
-use disk_types::{BlockDeviceExt, PartitionExt, PartitionTableExt, SectorExt};
 use crate::external::{
     cryptsetup_close, cryptsetup_open, lvs, physical_volumes_to_deactivate, pvs, vgdeactivate,
-    CloseBy,
+    CloseBy, LuksObserver,
 };
 use itertools::Itertools;
 use libparted::{Device, DeviceType};
-use misc;
+use misc::{self, namespace::THREAD_MOUNTS};
+use once_cell::sync::Lazy;
+use operations::{run_jobs, Job};
 use partition_identity::PartitionID;
-use proc_mounts::{MountIter, MOUNTS, SWAPS};
+use proc_mounts::{MountIter, MountList, SWAPS};
 use rayon::{iter::IntoParallelRefIterator, prelude::*};
 use std::{
     collections::{BTreeMap, HashSet},
     ffi::OsString,
     fs, io,
     iter::{self, FromIterator},
-    os::unix::ffi::{OsStrExt, OsStringExt},
-    path::{Path, PathBuf},
-    str, thread,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::PermissionsExt,
+    },
+    path::{Component, Path, PathBuf},
+    str,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
     time::Duration,
 };
 use sys_mount::{swapoff, unmount, Mount, MountFlags, Mounts, Unmount, UnmountFlags};
 
+/// Held while a LUKS volume is formatted and opened. The key derivation of LUKS2 takes up to a
+/// gigabyte of memory, or half of what is available, for each volume, and so volumes which are
+/// formatted in parallel could together exhaust the memory of the live system.
+static LUKS_FORMAT: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// The size of the BIOS boot partition, into which GRUB embeds its core image on GPT disks.
+/// This is the smallest that is accepted, and the size which the planner creates.
+pub const BIOS_GRUB_SIZE: u64 = 1024 * 1024;
+
 /// A configuration of disks, both physical and logical.
 #[derive(Debug, Default, PartialEq)]
 pub struct Disks {
@@ -172,7 +194,7 @@ impl Disks {
             self.get_partitions().filter(|part| part.target.is_some() && part.filesystem.is_some());
 
         enum MountKind {
-            Direct { device: PathBuf, fs: &'static str },
+            Direct { device: PathBuf, fs: &'static str, flags: MountFlags, data: Option<String> },
             Bind { source: PathBuf },
         }
 
@@ -216,12 +238,22 @@ impl Disks {
                 let kind = if let Some(source) = target.mount_point.clone() {
                     MountKind::Bind { source }
                 } else {
-                    let fs = match target.filesystem.unwrap() {
-                        FileSystem::Fat16 | FileSystem::Fat32 => "vfat",
-                        fs => fs.into(),
+                    let (fs, flags, data) = match target.filesystem.unwrap() {
+                        FileSystem::Fat16 | FileSystem::Fat32 => {
+                            ("vfat", MountFlags::empty(), None)
+                        }
+                        // Mounted as it will be by the installed system, so that the files which
+                        // are extracted to it are compressed. Reused file systems keep the
+                        // options that they were created with.
+                        FileSystem::Btrfs if target.will_format() => {
+                            let data = target.mkfs_options.btrfs.mount_data();
+                            let data = if data.is_empty() { None } else { Some(data) };
+                            ("btrfs", MountFlags::NOATIME, data)
+                        }
+                        fs => (fs.into(), MountFlags::empty(), None),
                     };
 
-                    MountKind::Direct { device: target.device_path.clone(), fs }
+                    MountKind::Direct { device: target.device_path.clone(), fs, flags, data }
                 };
                 (target_mount, kind)
             })
@@ -239,9 +271,9 @@ impl Disks {
             }
 
             let mount = match kind {
-                MountKind::Direct { device, fs } => {
+                MountKind::Direct { device, fs, flags, data } => {
                     info!("mounting {:?} ({}) to {:?}", device, fs, target_mount);
-                    Mount::new(device, &target_mount, fs, MountFlags::empty(), None)?
+                    Mount::new(device, &target_mount, fs, flags, data.as_deref())?
                 }
                 MountKind::Bind { source } => {
                     info!("bind mounting {:?} to {:?}", source, target_mount);
@@ -250,6 +282,11 @@ impl Disks {
             };
 
             mounts.push(mount.into_unmount_drop(UnmountFlags::DETACH));
+
+            // A new file system's root is only writable by root, which would break /tmp.
+            if target_mount == base_dir.join("tmp") || target_mount == base_dir.join("var/tmp") {
+                fs::set_permissions(&target_mount, fs::Permissions::from_mode(0o1777))?;
+            }
         }
 
         Ok(Mounts(mounts))
@@ -375,7 +412,8 @@ impl Disks {
     /// Deactivates all device maps associated with the inner disks/partitions
     /// to be modified.
     pub fn deactivate_device_maps(&self) -> Result<(), DiskError> {
-        let mounts = MOUNTS.read().expect("failed to get mounts in deactivate_device_maps");
+        let mounts = MountList::new_from_file(THREAD_MOUNTS)
+            .expect("failed to get mounts in deactivate_device_maps");
         let swaps = SWAPS.read().expect("failed to get swaps in deactivate_device_maps");
         let umount = move |vg: &str| -> Result<(), DiskError> {
             for lv in lvs(vg).map_err(|why| DiskError::ExternalCommand { why })? {
@@ -455,7 +493,7 @@ impl Disks {
             enc: &LvmEncryption,
         ) -> Result<LogicalDevice, DecryptionError> {
             // Attempt to decrypt the device.
-            cryptsetup_open(path, &enc)
+            cryptsetup_open(path, &enc, &|_, _| ())
                 .map_err(|why| DecryptionError::Open { device: path.to_path_buf(), why })?;
 
             // Determine which VG the newly-decrypted device belongs to.
@@ -570,8 +608,24 @@ impl Disks {
 
     /// Probes for and returns disk information for every disk in the system.
     pub fn probe_devices() -> Result<Disks, DiskError> {
+        Self::probe_devices_cancellable(&AtomicBool::new(false))
+    }
+
+    /// Probes devices in the same manner as `probe_devices`, but checks `cancel` between each
+    /// device, and returns `DiskError::ProbeCancelled` once it has been set.
+    pub fn probe_devices_cancellable(cancel: &AtomicBool) -> Result<Disks, DiskError> {
+        // libparted silently omits the devices that it may not open.
+        for name in block_devices()? {
+            let device = Path::new("/dev").join(&name);
+            require_access(&device, Capability::DacReadSearch, "probing partition tables")?;
+        }
+
         let mut disks = Disks::default();
         for mut device in Device::devices(true) {
+            if cancel.load(Ordering::SeqCst) {
+                return Err(DiskError::ProbeCancelled);
+            }
+
             if let Some(name) = device.path().file_name().and_then(|x| x.to_str()) {
                 // Ignore CDROM devices
                 if name.starts_with("sr") || name.starts_with("scd") { continue }
@@ -588,9 +642,14 @@ impl Disks {
             }
         }
 
+        if cancel.load(Ordering::SeqCst) {
+            return Err(DiskError::ProbeCancelled);
+        }
+
         // Collect all of the extended partition information for each contained
         // partition in parallel.
-        let mounts = MOUNTS.read().expect("failed to get mounts in Disk::new");
+        let mounts = MountList::new_from_file(THREAD_MOUNTS)
+            .expect("failed to get mounts in Disk::new");
         let swaps = SWAPS.read().expect("failed to get swaps in Disk::new");
 
         unsafe {
@@ -608,6 +667,26 @@ impl Disks {
         Ok(disks)
     }
 
+    /// Probes the disks in the system from sysfs and the udev database, which does not require
+    /// root, so that frontends may display the disks before prompting for authentication.
+    ///
+    /// Volume groups and encrypted volumes are not detected, so the disks should be probed with
+    /// `probe_devices` once authorized, before they are configured.
+    pub fn probe_devices_unprivileged() -> Result<Disks, DiskError> {
+        let mut disks = Disks::default();
+        for name in block_devices()? {
+            let disk = Disk::new_from_sysfs(&name)?;
+
+            // Card readers without a card have no size.
+            if disk.size != 0 {
+                disks.add(disk);
+            }
+        }
+
+        disks.physical.sort_by(|a, b| a.device_path.cmp(&b.device_path));
+        Ok(disks)
+    }
+
     /// Locate a partition which contains the given file.
     ///
     /// ```rust
@@ -898,31 +977,61 @@ impl Disks {
         self.get_logical_devices().iter().any(|d| d.get_device_path() == device)
     }
 
+    fn device_is_encrypted(&self, device: &Path) -> bool {
+        self.get_logical_devices().iter().any(|d| {
+            d.get_device_path() == device && (d.encryption.is_some() || d.luks_parent.is_some())
+        })
+    }
+
     /// Validates that partitions are configured correctly.
     ///
     /// - EFI installs must contain a `/boot/efi` partition as Fat16 / Fat32
     /// - MBR installs on logical devices must have a `/boot` partition
     /// - Boot partitions must not be on a logical volume
     /// - EFI boot partitions must have the ESP flag set
-    pub fn verify_partitions(&self, bootloader: Bootloader) -> io::Result<()> {
+    /// - BIOS installs to GPT disks must have a BIOS boot partition
+    /// - systemd-boot, which only reads the ESP, requires an EFI install
+    /// - GRUB cannot read the kernel from an encrypted `/boot`, or root if there is none
+    /// - Neither the ESP, nor the `/boot` of a BIOS install, may be on a RAID array
+    /// - Mount targets must be absolute, and unique to a partition
+    /// - Protected devices must not be modified
+    /// - The backup GPT headers of the disks which are modified must be intact
+    ///
+    /// If `encrypted_boot` is set, GRUB may read the kernel from an encrypted volume, which GRUB
+    /// is configured to unlock.
+    pub fn verify_partitions(
+        &self,
+        bootloader: Bootloader,
+        loader: KernelLoader,
+        encrypted_boot: bool,
+    ) -> io::Result<()> {
+        self.verify_protected()?;
+        self.verify_gpt()?;
+        verify_targets(self.get_partitions().filter_map(|part| part.target.as_deref()))?;
+
         let (root_device, root) = self.find_partition(Path::new("/")).ok_or_else(|| {
-            io::Error::new(io::ErrorKind::InvalidInput, "root partition was not defined")
+            CodedError::new(ErrorCode::RootUndefined, None, "root partition was not defined")
+                .into_io(io::ErrorKind::InvalidInput)
         })?;
 
         use FileSystem::*;
         match root.filesystem {
             Some(Fat16) | Some(Fat32) | Some(Ntfs) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
+                return Err(CodedError::new(
+                    ErrorCode::RootFileSystemInvalid,
+                    Some(root.get_device_path()),
                     "root partition has invalid file system",
-                ));
+                )
+                .into_io(io::ErrorKind::InvalidInput));
             }
             Some(_) => (),
             None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
+                return Err(CodedError::new(
+                    ErrorCode::RootFileSystemInvalid,
+                    Some(root.get_device_path()),
                     "root partition does not have a file system",
-                ));
+                )
+                .into_io(io::ErrorKind::InvalidInput));
             }
         }
 
@@ -938,10 +1047,12 @@ impl Disks {
             let device = {
                 let (device, boot) =
                     self.find_partition(Path::new(partition)).ok_or_else(|| {
-                        io::Error::new(
-                            io::ErrorKind::InvalidInput,
+                        CodedError::new(
+                            ErrorCode::BootUndefined,
+                            None,
                             format!("{} partition was not defined", kind),
                         )
+                        .into_io(io::ErrorKind::InvalidInput)
                     })?;
 
                 let device = match self.find_disk(device) {
@@ -957,32 +1068,40 @@ impl Disks {
                 if is_efi {
                     // Check if the EFI partition is on a GPT disk.
                     if device.get_partition_table() != Some(PartitionTable::Gpt) {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidInput,
+                        return Err(CodedError::new(
+                            ErrorCode::EspNotGpt,
+                            Some(device.get_device_path()),
                             "EFI installs cannot be done on disks without a GPT partition layout.",
-                        ));
+                        )
+                        .into_io(io::ErrorKind::InvalidInput));
                     }
 
                     if !boot.flags.contains(&PartitionFlag::PED_PARTITION_ESP) {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidInput,
+                        return Err(CodedError::new(
+                            ErrorCode::EspFlagMissing,
+                            Some(boot.get_device_path()),
                             format!("{} partition did not have ESP flag set", kind),
-                        ));
+                        )
+                        .into_io(io::ErrorKind::InvalidInput));
                     }
 
                     match boot.filesystem {
                         Some(Fat16) | Some(Fat32) => (),
                         Some(_) => {
-                            return Err(io::Error::new(
-                                io::ErrorKind::InvalidInput,
+                            return Err(CodedError::new(
+                                ErrorCode::BootFileSystemInvalid,
+                                Some(boot.get_device_path()),
                                 format!("{} partition has invalid file system", kind),
-                            ));
+                            )
+                            .into_io(io::ErrorKind::InvalidInput));
                         }
                         None => {
-                            return Err(io::Error::new(
-                                io::ErrorKind::InvalidInput,
+                            return Err(CodedError::new(
+                                ErrorCode::BootFileSystemInvalid,
+                                Some(boot.get_device_path()),
                                 format!("{} partition does not have a file system", kind),
-                            ));
+                            )
+                            .into_io(io::ErrorKind::InvalidInput));
                         }
                     }
 
@@ -996,10 +1115,12 @@ impl Disks {
                             && (boot.get_sectors() * boot.get_logical_block_size()
                                 < REQUIRED_ESP_SIZE))
                     {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidInput,
+                        return Err(CodedError::new(
+                            ErrorCode::EspTooSmall,
+                            Some(boot.get_device_path()),
                             "the ESP partition must be at least 256 MiB in size",
-                        ));
+                        )
+                        .into_io(io::ErrorKind::InvalidInput));
                     }
                 }
 
@@ -1007,9 +1128,143 @@ impl Disks {
             };
 
             if device.is_logical() {
+                return Err(CodedError::new(
+                    ErrorCode::BootOnLogicalDevice,
+                    Some(device.get_device_path()),
+                    format!("{} partition cannot be on logical device", kind),
+                )
+                .into_io(io::ErrorKind::InvalidInput));
+            }
+        }
+
+        if bootloader == Bootloader::Bios {
+            self.verify_bios_grub()?;
+        }
+
+        self.verify_kernel_loader(bootloader, loader, root_device, encrypted_boot)
+    }
+
+    /// Ensures that the kernel loader can read the kernel from where it will be installed.
+    fn verify_kernel_loader(
+        &self,
+        bootloader: Bootloader,
+        loader: KernelLoader,
+        root_device: &Path,
+        encrypted_boot: bool,
+    ) -> io::Result<()> {
+        if loader == KernelLoader::SystemdBoot && bootloader != Bootloader::Efi {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "systemd-boot cannot be used by BIOS installs",
+            ));
+        }
+
+        let is_raid = |device: &Path| {
+            device.file_name().map_or(false, |name| name.to_string_lossy().starts_with("md"))
+        };
+
+        let boot = self.find_partition(Path::new("/boot"));
+        let boot_device = boot.map_or(root_device, |(device, _)| device);
+        let boot_path = boot.map_or(root_device, |(_, part)| part.get_device_path());
+
+        let boot_encrypted = self.device_is_encrypted(boot_device);
+        if loader == KernelLoader::Grub && boot_encrypted && !encrypted_boot {
+            return Err(CodedError::new(
+                ErrorCode::BootEncrypted,
+                Some(boot_path),
+                "GRUB cannot read the kernel from an encrypted volume: a separate, unencrypted \
+                 /boot partition is required",
+            )
+            .into_io(io::ErrorKind::InvalidInput));
+        }
+
+        let raid = if bootloader == Bootloader::Efi {
+            self.find_partition(Path::new("/boot/efi"))
+                .map(|(_, esp)| esp.get_device_path())
+                .filter(|&path| is_raid(path))
+                .map(|path| (path, "firmware cannot read an ESP which is on a RAID array"))
+        } else if loader == KernelLoader::Grub && is_raid(boot_path) {
+            Some((boot_path, "BIOS installs cannot boot from a /boot partition on a RAID array"))
+        } else {
+            None
+        };
+
+        if let Some((path, why)) = raid {
+            return Err(CodedError::new(ErrorCode::BootOnRaid, Some(path), why)
+                .into_io(io::ErrorKind::InvalidInput));
+        }
+
+        Ok(())
+    }
+
+    /// Ensures that the configuration modifies none of the devices which were protected with
+    /// `protect_devices`.
+    pub fn verify_protected(&self) -> io::Result<()> {
+        let physical = self.physical.iter().map(|disk| protected::check_disk(disk, disk));
+        let logical = self.logical.iter().map(protected::check_logical);
+        physical.chain(logical).collect::<Result<(), DiskError>>().map_err(|why| {
+            let device = match why {
+                DiskError::DeviceProtected { ref device, .. } => Some(device.as_path()),
+                _ => None,
+            };
+
+            CodedError::new(ErrorCode::DeviceProtected, device, why.to_string())
+                .into_io(io::ErrorKind::PermissionDenied)
+        })
+    }
+
+    /// Ensures that a BIOS install may boot from the disk which GRUB is installed to. GRUB
+    /// embeds its core image in a partition with the BIOS boot flag on GPT disks, and the
+    /// install would not boot without one of at least `BIOS_GRUB_SIZE`.
+    pub fn verify_bios_grub(&self) -> io::Result<()> {
+        let ((root_device, _), boot) = self.get_base_partitions(Bootloader::Bios);
+        let device = boot.map_or(root_device, |(device, _)| device);
+        let disk = self.find_disk(device).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unable to find the disk that the bootloader is installed to",
+            )
+        })?;
+
+        if disk.get_partition_table() != Some(PartitionTable::Gpt) {
+            return Ok(());
+        }
+
+        let sector_size = disk.get_logical_block_size();
+        let has_bios_grub = disk.get_partitions().iter().any(|part| {
+            !part.flag_is_enabled(REMOVE)
+                && part.flags.contains(&PartitionFlag::PED_PARTITION_BIOS_GRUB)
+                && part.get_sectors() * sector_size >= BIOS_GRUB_SIZE
+        });
+
+        if has_bios_grub {
+            Ok(())
+        } else {
+            Err(CodedError::new(
+                ErrorCode::BiosGrubMissing,
+                Some(disk.get_device_path()),
+                "BIOS installs to GPT disks require a BIOS boot partition of at least 1 MiB",
+            )
+            .into_io(io::ErrorKind::InvalidInput))
+        }
+    }
+
+    /// Ensures that the ext4 features enabled on the partitions to be formatted are supported
+    /// by the kernel of the given `(major, minor)` release, which the installed system boots.
+    pub fn verify_ext4_features(&self, kernel: (u32, u32)) -> io::Result<()> {
+        for partition in self.get_partitions().filter(|part| part.flag_is_enabled(FORMAT)) {
+            let unsupported = partition.mkfs_options.unsupported_features(kernel);
+            if !unsupported.is_empty() {
+                let features = unsupported.iter().map(|feature| feature.name()).join(", ");
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
-                    format!("{} partition cannot be on logical device", kind),
+                    format!(
+                        "{} cannot be formatted with {}, which Linux {}.{} does not support",
+                        partition.get_device_path().display(),
+                        features,
+                        kernel.0,
+                        kernel.1
+                    ),
                 ));
             }
         }
@@ -1017,6 +1272,75 @@ impl Disks {
         Ok(())
     }
 
+    /// Ensures that the FAT partitions to be formatted have valid labels, and sizes for which a
+    /// valid cluster size exists, as some firmwares refuse to boot from malformed ESPs.
+    pub fn verify_fat_partitions(&self) -> io::Result<()> {
+        for disk in self.get_physical_devices() {
+            // Partitions which are yet to be created have no device to query their sectors from.
+            let sector_size = disk.get_logical_block_size();
+            let fat_partitions = disk.get_partitions().iter().filter(|part| {
+                part.flag_is_enabled(FORMAT)
+                    && (part.filesystem == Some(FileSystem::Fat16)
+                        || part.filesystem == Some(FileSystem::Fat32))
+            });
+
+            for partition in fat_partitions {
+                let path = partition.get_device_path();
+                if let Some(ref label) = partition.mkfs_options.fat.label {
+                    if let Some(why) = fat_label_error(label) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("{} cannot be labeled {:?}: {}", path.display(), label, why),
+                        ));
+                    }
+                }
+
+                let size = partition.get_sectors() * sector_size;
+                let kind = partition.filesystem.unwrap();
+                if fat_cluster_size(kind, size, sector_size).is_none() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} is {} MiB, which is not a valid size for {} with {} byte sectors",
+                            path.display(),
+                            size / (1024 * 1024),
+                            kind,
+                            sector_size
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether Windows is installed on a partition which will be neither removed nor formatted,
+    /// and which will therefore be booted alongside the new install.
+    pub fn retains_windows(&self) -> bool {
+        self.get_partitions()
+            .filter(|part| !part.flag_is_enabled(REMOVE) && !part.flag_is_enabled(FORMAT))
+            .filter(|part| part.filesystem == Some(FileSystem::Ntfs))
+            .any(|part| match part.probe_os() {
+                Some(OS::Windows(_)) => true,
+                _ => false,
+            })
+    }
+
+    /// Whether any system, such as Windows or another Linux install, is installed on a partition
+    /// which will be neither removed nor formatted, and so remains to be booted from the menu.
+    pub fn retains_os(&self) -> bool {
+        self.get_partitions()
+            .filter(|part| !part.flag_is_enabled(REMOVE) && !part.flag_is_enabled(FORMAT))
+            .filter(|part| part.target.is_none())
+            .filter(|part| match part.filesystem {
+                Some(FileSystem::Swap) | Some(FileSystem::Luks) | Some(FileSystem::Lvm) => false,
+                Some(_) => true,
+                None => false,
+            })
+            .any(|part| part.probe_os().is_some())
+    }
+
     /// Loads existing logical volume data into memory, excluding encrypted volumes.
     pub fn initialize_volume_groups(&mut self) -> Result<(), DiskError> {
         let mut existing_devices: Vec<LogicalDevice> = Vec::new();
@@ -1100,20 +1424,29 @@ impl Disks {
     }
 
     /// Applies all logical device operations, which are to be performed after all physical disk
-    /// operations have completed.
+    /// operations have completed. The phases of the LUKS volumes which are formatted and opened
+    /// are reported to `observer`.
     ///
     /// TODO: We need to generate a diff of logical volume operations.
-    pub fn commit_logical_partitions(&mut self) -> Result<(), DiskError> {
+    pub fn commit_logical_partitions(&mut self, observer: LuksObserver) -> Result<(), DiskError> {
         // First we verify that we have a valid logical layout.
         for device in &self.logical {
             let volumes = self.find_volume_paths(&device.volume_group);
-            debug_assert!(!volumes.is_empty());
+            debug_assert!(device.is_source || !volumes.is_empty());
             if device.encryption.is_some() && volumes.len() > 1 {
                 return Err(DiskError::SameGroup);
             }
             device.validate()?;
         }
 
+        // Maps which already exist, such as those of an install which was unlocked, would
+        // abort the creation of the maps of the same names.
+        match self.resolve_dm_collisions() {
+            Ok(renames) if !renames.is_empty() => info!("renamed device maps: {:?}", renames),
+            Ok(_) => (),
+            Err(why) => warn!("unable to check the names of the new device maps: {}", why),
+        }
+
         // By default, the `device_path` field is not populated, so let's fix that.
         for device in &mut self.logical {
             for partition in
@@ -1124,8 +1457,8 @@ impl Disks {
                     continue;
                 }
                 let label = partition.name.as_ref().expect("logical partition should have name");
-                partition.device_path =
-                    PathBuf::from(format!("/dev/mapper/{}-{}", device.volume_group, label));
+                let map = lv_map_name(&device.volume_group, label);
+                partition.device_path = PathBuf::from(["/dev/mapper/", &map].concat());
             }
         }
 
@@ -1135,37 +1468,56 @@ impl Disks {
         // LUKS associations with LVM devices.
         let mut associations = Vec::new();
 
-        // Now we will apply the logical layout.
+        // Now we will apply the logical layout. Each device is created by one job, which
+        // encrypts its volume, and then creates its volume group and logical volumes. The
+        // logical volumes are formatted by jobs which follow it, so that the devices, and
+        // the file systems within them, are prepared in parallel.
+        let mut jobs = Vec::new();
         for (id, device) in self.logical.iter().enumerate() {
-            // Only create the device if it does not exist.
-            if !device.is_source {
-                let volumes: Vec<(&Path, &Path)> = self.find_volume_paths(&device.volume_group);
-                let mut device_path = None;
+            let volumes: Vec<(&Path, &Path)> = self.find_volume_paths(&device.volume_group);
+            if !device.is_source && device.encryption.is_some() {
+                associations.push((volumes[0].1.to_path_buf(), id));
+            }
 
-                if let Some(encryption) = device.encryption.as_ref() {
-                    encryption.encrypt(volumes[0].1)?;
-                    encryption.open(volumes[0].1)?;
-                    encryption.create_physical_volume()?;
-                    device_path =
-                        Some(PathBuf::from(["/dev/mapper/", &encryption.physical_volume].concat()));
+            let create = jobs.len();
+            let name = format!("creating the volume group {}", device.volume_group);
+            jobs.push(Job::new(name, Vec::new(), move || {
+                // Only create the device if it does not exist.
+                if !device.is_source {
+                    let mut device_path = None;
+
+                    if let Some(encryption) = device.encryption.as_ref() {
+                        {
+                            let _luks = LUKS_FORMAT.lock().unwrap_or_else(|lock| lock.into_inner());
+                            encryption.encrypt(volumes[0].1, observer)?;
+                            encryption.open(volumes[0].1, observer)?;
+                        }
 
-                    associations.push((volumes[0].1.to_path_buf(), id));
-                }
+                        encryption.create_physical_volume()?;
+                        device_path = Some(PathBuf::from(
+                            ["/dev/mapper/", &encryption.physical_volume].concat(),
+                        ));
+                    }
 
-                // Obtains an iterator which may produce one or more device paths.
-                let volumes: Box<dyn Iterator<Item = &Path>> = match device_path.as_ref() {
-                    // There will be only one volume, which we obtained from encryption.
-                    Some(path) => Box::new(iter::once(path.as_path())),
-                    // There may be more than one volume within a unencrypted LVM config.
-                    None => Box::new(volumes.into_iter().map(|(_, part)| part)),
-                };
+                    // Obtains an iterator which may produce one or more device paths.
+                    let volumes: Box<dyn Iterator<Item = &Path>> = match device_path.as_ref() {
+                        // There will be only one volume, which we obtained from encryption.
+                        Some(path) => Box::new(iter::once(path.as_path())),
+                        // There may be more than one volume within a unencrypted LVM config.
+                        None => Box::new(volumes.into_iter().map(|(_, part)| part)),
+                    };
 
-                device.create_volume_group(volumes)?;
-            }
+                    device.create_volume_group(volumes)?;
+                }
 
-            device.modify_partitions()?;
+                device.modify_partitions()
+            }));
+
+            jobs.extend(device.format_jobs(&[create]));
         }
 
+        run_jobs(jobs)?;
+
         for (luks_parent, id) in associations {
             let mut logical = &mut self.logical[id];
             info!("associating {:?} with {:?}", logical.device_path, luks_parent);
@@ -1190,9 +1542,25 @@ impl FromIterator<Disk> for Disks {
     }
 }
 
+/// The names of the block devices which may hold partition tables, omitting the devices that
+/// `probe_devices` ignores, as well as RAM disks.
+fn block_devices() -> io::Result<Vec<String>> {
+    let ignored = ["sr", "scd", "loop", "dm-", "ram", "zram"];
+    let mut names = Vec::new();
+    for entry in fs::read_dir("/sys/block")? {
+        if let Ok(name) = entry?.file_name().into_string() {
+            if !ignored.iter().any(|prefix| name.starts_with(prefix)) {
+                names.push(name);
+            }
+        }
+    }
+
+    Ok(names)
+}
+
 fn find_device_path_of_mount<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
     let path = path.as_ref();
-    for mount in MountIter::new()? {
+    for mount in MountIter::new_from_file(THREAD_MOUNTS)? {
         let mount = mount?;
         if mount.dest == path {
             return Ok(mount.source);
@@ -1201,3 +1569,46 @@ fn find_device_path_of_mount<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
 
     Err(io::Error::new(io::ErrorKind::NotFound, "mount not found"))
 }
+
+/// Ensures that each target is an absolute path without `..`, and is not shared with another
+/// partition. Mounts are ordered by their components, so that `/var` is mounted before
+/// `/var/log`, which is only correct if targets such as `/srv/../var/log` are rejected.
+fn verify_targets<'a, I: Iterator<Item = &'a Path>>(targets: I) -> io::Result<()> {
+    let mut seen = HashSet::new();
+    for target in targets {
+        let normalized =
+            target.is_absolute() && !target.components().any(|part| part == Component::ParentDir);
+
+        if !normalized {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} must be an absolute mount target without `..`", target.display()),
+            ));
+        }
+
+        if !seen.insert(target) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is the target of more than one partition", target.display()),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_targets() {
+        let targets = ["/", "/boot/efi", "/var", "/var/log", "/srv", "/opt", "/tmp"];
+        assert!(verify_targets(targets.iter().map(Path::new)).is_ok());
+
+        assert!(verify_targets(["/", "/var", "/var"].iter().map(Path::new)).is_err());
+        assert!(verify_targets(["/", "var/log"].iter().map(Path::new)).is_err());
+        assert!(verify_targets(["/", "/var/../srv"].iter().map(Path::new)).is_err());
+        assert!(verify_targets(["/", "/var/log", "/var//log/"].iter().map(Path::new)).is_err());
+    }
+}
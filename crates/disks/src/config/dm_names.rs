@@ -0,0 +1,202 @@
+//! The device-mapper names of the LUKS volumes and LVM volume groups which a configuration
+//! creates, which must not collide with the maps that already exist, as cryptsetup and LVM
+//! refuse to create a map whose name is taken. A live session may have a map of the same name
+//! open, such as the `cryptdata` volume of an existing install which was unlocked.
+
+use super::{super::Disks, partitions::REMOVE, DiskExt, LogicalDevice};
+use crate::external::{dmlist, pvs};
+use std::{collections::HashSet, io, path::PathBuf};
+
+/// The names which are taken by device-mapper maps and by LVM volume groups, against which
+/// the names of new maps are checked.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DmRegistry {
+    maps:   HashSet<String>,
+    groups: HashSet<String>,
+}
+
+impl DmRegistry {
+    /// Registers the maps which exist, and the volume groups of the physical volumes.
+    pub fn probe() -> io::Result<Self> {
+        Ok(DmRegistry {
+            maps:   dmlist()?.into_iter().collect(),
+            groups: pvs()?.into_iter().filter_map(|(_, group)| group).collect(),
+        })
+    }
+
+    /// Reserves the name of a map, returning `false` if it was already taken.
+    pub fn reserve_map(&mut self, name: &str) -> bool { self.maps.insert(name.to_owned()) }
+
+    /// Reserves the name of a volume group, and the maps of its logical volumes, returning
+    /// `false`, and reserving nothing, if any of them were already taken.
+    pub fn reserve_group(&mut self, group: &str, volumes: &[&str]) -> bool {
+        let maps: Vec<String> = volumes.iter().map(|volume| lv_map_name(group, volume)).collect();
+        let taken = self.groups.contains(group)
+            || self.maps.contains(&dm_escape(group))
+            || maps.iter().any(|map| self.maps.contains(map));
+
+        if taken {
+            return false;
+        }
+
+        self.groups.insert(group.to_owned());
+        self.maps.insert(dm_escape(group));
+        self.maps.extend(maps);
+        true
+    }
+
+    /// Reserves the first name of `prefix`, `prefix_1`, `prefix_2`, and so on, which is not
+    /// taken by a map.
+    pub fn unique_map(&mut self, prefix: &str) -> String {
+        let name = candidates(prefix).find(|name| !self.maps.contains(name)).unwrap();
+        self.maps.insert(name.clone());
+        name
+    }
+
+    /// Reserves the first name of `prefix`, `prefix_1`, `prefix_2`, and so on, which may be
+    /// given to a volume group with the logical volumes of `volumes`.
+    pub fn unique_group(&mut self, prefix: &str, volumes: &[&str]) -> String {
+        candidates(prefix).find(|name| self.reserve_group(name, volumes)).unwrap()
+    }
+}
+
+impl Disks {
+    /// The names which are taken by the maps and volume groups that exist, and by those which
+    /// this configuration creates.
+    pub fn dm_registry(&self) -> io::Result<DmRegistry> {
+        let mut registry = DmRegistry::probe()?;
+        for device in self.logical.iter().filter(|device| !device.is_source) {
+            registry.reserve_group(&device.volume_group, &volume_names(device));
+            if let Some(ref encryption) = device.encryption {
+                registry.reserve_map(&encryption.physical_volume);
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /// Generates a name for a new map from `prefix`, which collides with no map that exists,
+    /// nor with any that this configuration creates.
+    pub fn unique_dm_name(&self, prefix: &str) -> io::Result<String> {
+        self.dm_registry().map(|mut registry| registry.unique_map(prefix))
+    }
+
+    /// Renames the LUKS volumes and volume groups which this configuration creates, where their
+    /// names would collide with the maps which exist, or with each other. Returns the names
+    /// which were changed, with their new names.
+    pub fn resolve_dm_collisions(&mut self) -> io::Result<Vec<(String, String)>> {
+        let mut registry = DmRegistry::probe()?;
+        let mut renames = Vec::new();
+
+        for id in 0..self.logical.len() {
+            if self.logical[id].is_source {
+                continue;
+            }
+
+            let group = self.logical[id].volume_group.clone();
+            let volumes = volume_names(&self.logical[id]);
+            if !registry.reserve_group(&group, &volumes) {
+                let new_group = registry.unique_group(&group, &volumes);
+                info!("renaming the volume group {} to {}, as the name is taken", group, new_group);
+                self.rename_group(id, &group, &new_group);
+                renames.push((group, new_group));
+            }
+
+            let group = self.logical[id].volume_group.clone();
+            let volume = match self.logical[id].encryption {
+                Some(ref encryption) => encryption.physical_volume.clone(),
+                None => continue,
+            };
+
+            if !registry.reserve_map(&volume) {
+                let new = registry.unique_map(&volume);
+                info!("renaming the LUKS volume {} to {}, as the name is taken", volume, new);
+                self.rename_luks_volume(&group, &new);
+                renames.push((volume, new));
+            }
+        }
+
+        Ok(renames)
+    }
+
+    fn rename_group(&mut self, id: usize, old: &str, new: &str) {
+        let device = &mut self.logical[id];
+        device.volume_group = new.to_owned();
+        device.device_path = PathBuf::from(["/dev/mapper/", &dm_escape(new)].concat());
+
+        for disk in &mut self.physical {
+            for partition in disk.get_partitions_mut() {
+                if let Some((ref mut group, _)) = partition.volume_group {
+                    if group.as_str() == old {
+                        *group = new.to_owned();
+                    }
+                }
+            }
+        }
+    }
+
+    fn rename_luks_volume(&mut self, group: &str, new: &str) {
+        for device in self.logical.iter_mut().filter(|device| device.volume_group == group) {
+            if let Some(ref mut encryption) = device.encryption {
+                encryption.physical_volume = new.to_owned();
+            }
+        }
+
+        for disk in &mut self.physical {
+            for partition in disk.get_partitions_mut() {
+                if let Some((ref name, Some(ref mut encryption))) = partition.volume_group {
+                    if name == group {
+                        encryption.physical_volume = new.to_owned();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The name of the map of a logical volume, in which LVM doubles the hyphens of the names.
+pub fn lv_map_name(group: &str, volume: &str) -> String {
+    [dm_escape(group), dm_escape(volume)].join("-")
+}
+
+fn dm_escape(name: &str) -> String { name.replace('-', "--") }
+
+fn volume_names(device: &LogicalDevice) -> Vec<&str> {
+    device
+        .file_system
+        .iter()
+        .chain(device.partitions.iter())
+        .filter(|part| !part.flag_is_enabled(REMOVE))
+        .filter_map(|part| part.name.as_deref())
+        .collect()
+}
+
+fn candidates(prefix: &str) -> impl Iterator<Item = String> + '_ {
+    (0..).map(move |id| if id == 0 { prefix.to_owned() } else { format!("{}_{}", prefix, id) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_dm_names() {
+        let mut registry = DmRegistry {
+            maps:   ["cryptdata", "data-root", "cryptdata_1"].iter().map(|&s| s.into()).collect(),
+            groups: ["data"].iter().map(|&s| s.into()).collect(),
+        };
+
+        assert_eq!(registry.unique_map("cryptdata"), "cryptdata_2");
+        assert_eq!(registry.unique_map("cryptdata"), "cryptdata_3");
+        assert!(registry.reserve_map("cryptswap"));
+        assert!(!registry.reserve_map("cryptswap"));
+
+        assert!(!registry.reserve_group("data", &["home"]));
+        assert_eq!(registry.unique_group("data", &["root"]), "data_1");
+        assert!(!registry.reserve_group("data_1", &[]));
+        assert!(registry.reserve_group("my-vg", &["root"]));
+        assert!(registry.maps.contains("my--vg-root"));
+
+        assert_eq!(lv_map_name("pop-os", "root-fs"), "pop--os-root--fs");
+    }
+}
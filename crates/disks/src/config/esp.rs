@@ -0,0 +1,322 @@
+//! The directories which OSes install to an ESP, and which of them belong to OSes that no
+//! longer exist on any disk, so that their removal may free space on a small ESP which is
+//! reused by an install.
+//!
+//! Only the directories of Linux distributions are considered. Those of Windows, the firmware's
+//! fallback loader, and vendor tools are never removed, and nothing is removed if a partition
+//! which the install keeps is encrypted or otherwise cannot be inspected, as the OS within it
+//! may still boot from the ESP. A directory is only removed if it is attributed to an OS on a
+//! partition which the install removes or formats, so that those of OSes on disks which are
+//! not attached, or which could not be identified, are kept.
+
+use super::{
+    super::Disks,
+    partitions::{FORMAT, REMOVE, SOURCE},
+    OperationKind, PartitionInfo, PlannedOperation,
+};
+use disk_types::{BlockDeviceExt, FileSystem, PartitionExt};
+use os_detect::OS;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use sys_mount::*;
+use tempdir::TempDir;
+
+/// The directories which distributions install their bootloaders to, in lowercase.
+const DISTRIBUTION_DIRECTORIES: &[&str] = &[
+    "almalinux",
+    "arch",
+    "centos",
+    "debian",
+    "elementary",
+    "endeavouros",
+    "fedora",
+    "kali",
+    "linuxmint",
+    "manjaro",
+    "neon",
+    "opensuse",
+    "pop!_os",
+    "rocky",
+    "ubuntu",
+    "zorin",
+];
+
+/// A directory of the reused ESP which belongs to an OS that no longer exists on any disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleEspDirectory {
+    /// The name of the directory within `EFI`.
+    pub name: String,
+    /// The size of its files, in bytes.
+    pub size: u64,
+}
+
+impl StaleEspDirectory {
+    /// The path of the directory, relative to the root of the ESP.
+    pub fn path(&self) -> PathBuf { Path::new("EFI").join(&self.name) }
+}
+
+/// OSes on the disks, by the names that their ESP directories may have.
+#[derive(Debug, Default)]
+struct Oses {
+    /// Lowercase IDs and names of the Linux distributions.
+    names: Vec<String>,
+    /// File system UUIDs of the partitions.
+    uuids: Vec<String>,
+}
+
+impl Disks {
+    /// Finds the directories of the ESP which the install reuses that belong to OSes which
+    /// will not remain on any disk once the install's changes are applied.
+    ///
+    /// The ESP is mounted read-only to be inspected, as are the partitions which are kept.
+    pub fn stale_esp_directories(&self) -> Vec<StaleEspDirectory> {
+        let esp = match self.find_partition(Path::new("/boot/efi")) {
+            Some((_, esp)) if esp.flag_is_enabled(SOURCE) && !esp.flag_is_enabled(FORMAT) => esp,
+            _ => return Vec::new(),
+        };
+
+        let (remaining, replaced) = match self.esp_oses(esp.get_device_path()) {
+            Some(oses) => oses,
+            None => {
+                info!("keeping every ESP directory, as a kept partition cannot be inspected");
+                return Vec::new();
+            }
+        };
+
+        let device = esp.get_device_path();
+        let result = with_mounted(device, esp.mount_point.as_deref(), "vfat", |dir| {
+            let mut stale = Vec::new();
+            for entry in fs::read_dir(dir.join("EFI"))?.filter_map(Result::ok) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if entry.path().is_dir() && is_stale(&name, &remaining, &replaced) {
+                    stale.push(StaleEspDirectory { size: dir_size(&entry.path()), name });
+                }
+            }
+
+            Ok(stale)
+        });
+
+        result.unwrap_or_else(|why| {
+            warn!("unable to inspect the ESP at {}: {}", device.display(), why);
+            Vec::new()
+        })
+    }
+
+    /// Plans the removal of the directories of `stale_esp_directories`, which an install
+    /// performs before copying its kernels to the ESP, if it is configured to. The sectors of
+    /// each operation span the size of its directory.
+    pub fn plan_esp_cleanup(&self) -> Vec<PlannedOperation> {
+        self.stale_esp_directories()
+            .into_iter()
+            .map(|dir| PlannedOperation {
+                kind:         OperationKind::RemoveEspDirectory,
+                device:       Path::new("/boot/efi").join(dir.path()),
+                start_sector: 0,
+                end_sector:   (dir.size + 511) / 512,
+                file_system:  None,
+                data_loss:    true,
+            })
+            .collect()
+    }
+
+    /// The OSes of the partitions which remain, other than the ESP, and of those which the
+    /// install removes or formats. Disks which the install does not touch may have been removed
+    /// from the configuration, and so the devices are probed again for the OSes on them.
+    ///
+    /// Returns `None` if any partition which remains cannot be inspected.
+    fn esp_oses(&self, esp: &Path) -> Option<(Oses, Oses)> {
+        let (mut remaining, mut replaced) = (Oses::default(), Oses::default());
+        let (kept, removed): (Vec<&PartitionInfo>, Vec<&PartitionInfo>) = self
+            .get_partitions()
+            .filter(|part| part.flag_is_enabled(SOURCE) && part.get_device_path() != esp)
+            .partition(|part| !part.flag_is_enabled(REMOVE) && !part.flag_is_enabled(FORMAT));
+
+        for part in kept {
+            remaining.add(self, part)?;
+        }
+
+        for part in removed {
+            // The OS of a partition which cannot be inspected is not attributed its directory.
+            let _ = replaced.add(self, part);
+        }
+
+        let probed = Disks::probe_devices()
+            .map_err(|why| warn!("unable to probe the disks which the install keeps: {}", why))
+            .ok()?;
+
+        let known: Vec<&Path> = self.get_partitions().map(|part| part.get_device_path()).collect();
+        let untouched = probed.get_partitions().filter(|part| {
+            let path = part.get_device_path();
+            path != esp && !known.contains(&path)
+        });
+
+        for part in untouched {
+            remaining.add(&probed, part)?;
+        }
+
+        Some((remaining, replaced))
+    }
+}
+
+impl Oses {
+    /// Adds the OS of `part`, of `disks`, or returns `None` if it cannot be inspected.
+    fn add(&mut self, disks: &Disks, part: &PartitionInfo) -> Option<()> {
+        if let Some(ref uuid) = part.identifiers.uuid {
+            self.uuids.push(uuid.to_lowercase());
+        }
+
+        match part.filesystem {
+            Some(FileSystem::Luks) => {
+                let path = part.get_device_path();
+                let opened = disks
+                    .get_logical_devices()
+                    .iter()
+                    .any(|device| device.luks_parent.as_deref() == Some(path));
+                if !opened {
+                    return None;
+                }
+            }
+            Some(FileSystem::Lvm) => {
+                let active = disks.get_logical_devices().iter().any(|device| {
+                    part.original_vg.as_deref() == Some(device.volume_group.as_str())
+                });
+                if !active {
+                    return None;
+                }
+            }
+            Some(FileSystem::Swap) | None => (),
+            Some(_) => {
+                if let Some(OS::Linux { info, .. }) = probe_os(part) {
+                    self.names.push(info.id.to_lowercase());
+                    self.names.push(info.name.replace(' ', "_").to_lowercase());
+                    self.names.extend(info.id_like.split_whitespace().map(str::to_lowercase));
+                }
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Removes the directories of the ESP which is mounted at `esp`.
+pub fn remove_esp_directories(esp: &Path, directories: &[StaleEspDirectory]) {
+    for dir in directories {
+        let path = esp.join(dir.path());
+        info!("removing {}, which belongs to an OS that no longer exists", path.display());
+        if let Err(why) = fs::remove_dir_all(&path) {
+            warn!("unable to remove {}: {}", path.display(), why);
+        }
+    }
+}
+
+/// Whether the ESP directory `name` belongs to a Linux install which was `replaced`, and of
+/// which none remain.
+///
+/// kernelstub names the directories of its installs after the UUID of their root, and other
+/// distributions name theirs after themselves.
+fn is_stale(name: &str, remaining: &Oses, replaced: &Oses) -> bool {
+    let lowercase = name.to_lowercase();
+
+    if let Some((_, uuid)) = split_kernelstub(&lowercase) {
+        return replaced.uuids.iter().any(|existing| existing == uuid)
+            && !remaining.uuids.iter().any(|existing| existing == uuid);
+    }
+
+    DISTRIBUTION_DIRECTORIES.contains(&lowercase.as_str())
+        && replaced.names.iter().any(|existing| *existing == lowercase)
+        && !remaining.names.iter().any(|existing| *existing == lowercase)
+}
+
+/// Splits the name of a directory of kernelstub, such as `Pop_OS-<uuid>`, into the name of the
+/// distribution, and the UUID of its root.
+fn split_kernelstub(name: &str) -> Option<(&str, &str)> {
+    const UUID_LEN: usize = 36;
+    if name.len() <= UUID_LEN + 1 || !name.is_char_boundary(name.len() - UUID_LEN) {
+        return None;
+    }
+
+    let (prefix, uuid) = name.split_at(name.len() - UUID_LEN);
+    let is_uuid = uuid.char_indices().all(|(index, c)| match index {
+        8 | 13 | 18 | 23 => c == '-',
+        _ => c.is_ascii_hexdigit(),
+    });
+
+    match prefix.strip_suffix('-') {
+        Some(distribution) if is_uuid && !distribution.is_empty() => Some((distribution, uuid)),
+        _ => None,
+    }
+}
+
+/// Detects the OS of a partition, where it is already mounted, or else by mounting it.
+fn probe_os(part: &PartitionInfo) -> Option<OS> {
+    match part.mount_point {
+        Some(ref mount) => os_detect::detect_os_from_mount(part.get_device_path(), mount),
+        None => part.probe_os(),
+    }
+}
+
+/// Runs `func` with the file system of `device`, mounted read-only if it is not already.
+fn with_mounted<T, F: FnOnce(&Path) -> io::Result<T>>(
+    device: &Path,
+    mount_point: Option<&Path>,
+    fs: &str,
+    func: F,
+) -> io::Result<T> {
+    if let Some(mount) = mount_point {
+        return func(mount);
+    }
+
+    let tempdir = TempDir::new("distinst")?;
+    let _mount = Mount::new(device, tempdir.path(), fs, MountFlags::RDONLY, None)?
+        .into_unmount_drop(UnmountFlags::DETACH);
+
+    func(tempdir.path())
+}
+
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(ref metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_esp_directories() {
+        let uuid = "0b4a6c2e-9f1d-4e3a-8c7b-5d2f1e0a9b8c";
+        let replaced_uuid = "7e1f3a9c-2b4d-4c6e-8a0f-1d3b5c7e9f20";
+        let remaining = Oses {
+            names: vec!["pop".into(), "pop!_os".into(), "ubuntu".into(), "debian".into()],
+            uuids: vec![uuid.into()],
+        };
+        let replaced = Oses {
+            names: vec!["fedora".into(), "ubuntu".into()],
+            uuids: vec![replaced_uuid.into(), uuid.into()],
+        };
+
+        assert!(!is_stale("ubuntu", &remaining, &replaced));
+        assert!(is_stale("fedora", &remaining, &replaced));
+        assert!(!is_stale("arch", &remaining, &replaced));
+        assert!(!is_stale("Microsoft", &remaining, &replaced));
+        assert!(!is_stale("Boot", &remaining, &replaced));
+        assert!(!is_stale("Dell", &remaining, &replaced));
+        assert!(!is_stale(&["Pop_OS-", uuid].concat(), &remaining, &replaced));
+        assert!(is_stale(&["Pop_OS-", replaced_uuid].concat(), &remaining, &replaced));
+        assert!(!is_stale("Pop_OS-5d2f1e0a-9b8c-4e3a-8c7b-0b4a6c2e9f1d", &remaining, &replaced));
+
+        assert_eq!(split_kernelstub(&["pop_os-", uuid].concat()), Some(("pop_os", uuid)));
+        assert_eq!(split_kernelstub(&["-", uuid].concat()), None);
+        assert_eq!(split_kernelstub("pop_os-not-a-uuid-at-all-but-just-as-long"), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Detection, and repair, of damage to the backup GPT header at the end of a disk, which is
+//! left behind when an image is written to a larger disk, or when the end of the disk was
+//! overwritten. libparted only reports such damage when the table is written, by which time
+//! the install has already begun, so disks are checked before their layouts are changed.
+
+use super::{
+    super::{CodedError, DiskError, Disks, ErrorCode},
+    partitions::SOURCE,
+    Disk, PartitionTable,
+};
+use crate::external::sfdisk_relocate_gpt;
+use disk_types::{BlockDeviceExt, PartitionTableExt, SectorExt};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+const SIGNATURE: &[u8] = b"EFI PART";
+const HEADER_SIZE: usize = 92;
+
+/// The damage to the backup GPT header of a disk, whose primary header is intact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GptDamage {
+    /// The backup header is not at the end of the disk, as the disk is larger than the image
+    /// which was written to it.
+    BackupMisplaced,
+    /// There is no backup header where the primary header places it.
+    BackupMissing,
+    /// The backup header fails its checksum, or describes another disk.
+    BackupCorrupt,
+    /// The backup header describes a partition table which differs from the primary's.
+    TableMismatch,
+}
+
+impl fmt::Display for GptDamage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            GptDamage::BackupMisplaced => "the backup GPT header is not at the end of the disk",
+            GptDamage::BackupMissing => "the backup GPT header is missing",
+            GptDamage::BackupCorrupt => "the backup GPT header is corrupt",
+            GptDamage::TableMismatch => "the backup GPT table differs from the primary table",
+        })
+    }
+}
+
+impl Disk {
+    /// Reads the GPT headers of the disk, returning the damage to its backup header, if any.
+    pub fn gpt_damage(&self) -> io::Result<Option<GptDamage>> {
+        if self.get_partition_table() != Some(PartitionTable::Gpt) {
+            return Ok(None);
+        }
+
+        let sector_size = self.get_logical_block_size();
+        let last_lba = self.get_sectors().saturating_sub(1);
+        let mut device = File::open(self.get_device_path())?;
+        let primary = read_sector(&mut device, 1, sector_size)?;
+
+        let backup_lba = field(&primary, 32);
+        let backup = if backup_lba > 1 && backup_lba <= last_lba {
+            Some(read_sector(&mut device, backup_lba, sector_size)?)
+        } else {
+            None
+        };
+
+        Ok(inspect(&primary, backup.as_deref(), last_lba))
+    }
+
+    /// Rewrites the backup GPT header, and its table, at the end of the disk, from the primary.
+    pub fn repair_gpt(&mut self) -> Result<(), DiskError> {
+        info!("rewriting the backup GPT header of {}", self.get_device_path().display());
+        sfdisk_relocate_gpt(self.get_device_path()).map_err(|why| DiskError::GptRepair {
+            device: self.get_device_path().to_path_buf(),
+            why,
+        })
+    }
+
+    /// Disks whose tables are kept, in part, are written by libparted, which fails to commit a
+    /// table whose backup is damaged.
+    fn keeps_gpt(&self) -> bool {
+        !self.mklabel
+            && (self.is_being_modified()
+                || self.partitions.iter().any(|part| !part.flag_is_enabled(SOURCE)))
+    }
+
+    pub(crate) fn verify_gpt(&self) -> Result<(), DiskError> {
+        if !self.keeps_gpt() {
+            return Ok(());
+        }
+
+        match self.gpt_damage() {
+            Ok(Some(damage)) => {
+                Err(DiskError::GptDamaged { device: self.get_device_path().to_path_buf(), damage })
+            }
+            Ok(None) => Ok(()),
+            Err(why) => {
+                warn!("unable to read the GPT of {}: {}", self.get_device_path().display(), why);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Disks {
+    /// Repairs the backup GPT headers of the disks whose tables are to be modified, returning
+    /// the damage which was repaired on each.
+    pub fn repair_gpt(&mut self) -> Result<Vec<(PathBuf, GptDamage)>, DiskError> {
+        let mut repaired = Vec::new();
+        for disk in self.physical.iter_mut().filter(|disk| disk.keeps_gpt()) {
+            if let Some(damage) = disk.gpt_damage().map_err(|why| DiskError::GptRepair {
+                device: disk.get_device_path().to_path_buf(),
+                why,
+            })? {
+                warn!("{}: {}", disk.get_device_path().display(), damage);
+                disk.repair_gpt()?;
+                repaired.push((disk.get_device_path().to_path_buf(), damage));
+            }
+        }
+
+        Ok(repaired)
+    }
+
+    /// Ensures that libparted will be able to write the tables of the disks which are to be
+    /// modified, whose backup GPT headers must be intact.
+    pub fn verify_gpt(&self) -> io::Result<()> {
+        self.physical.iter().map(Disk::verify_gpt).collect::<Result<(), DiskError>>().map_err(
+            |why| {
+                let device = match why {
+                    DiskError::GptDamaged { ref device, .. } => Some(device.as_path()),
+                    _ => None,
+                };
+
+                CodedError::new(ErrorCode::GptDamaged, device, why.to_string())
+                    .into_io(io::ErrorKind::InvalidData)
+            },
+        )
+    }
+}
+
+fn read_sector(device: &mut File, lba: u64, sector_size: u64) -> io::Result<Vec<u8>> {
+    let mut sector = vec![0; sector_size as usize];
+    device.seek(SeekFrom::Start(lba * sector_size))?;
+    device.read_exact(&mut sector)?;
+    Ok(sector)
+}
+
+/// The damage to the backup header, given the primary header, the sector at which the primary
+/// places the backup, and the last sector of the disk. Disks whose primary header is invalid
+/// are not inspected, as libparted recovers them from the backup itself.
+fn inspect(primary: &[u8], backup: Option<&[u8]>, last_lba: u64) -> Option<GptDamage> {
+    if !valid(primary) {
+        return None;
+    }
+
+    if field(primary, 32) != last_lba {
+        return Some(GptDamage::BackupMisplaced);
+    }
+
+    let backup = match backup {
+        Some(backup) if backup.starts_with(SIGNATURE) => backup,
+        _ => return Some(GptDamage::BackupMissing),
+    };
+
+    let describes_disk = valid(backup)
+        && field(backup, 24) == field(primary, 32)
+        && field(backup, 32) == field(primary, 24)
+        && backup[56..72] == primary[56..72];
+
+    if !describes_disk {
+        Some(GptDamage::BackupCorrupt)
+    } else if backup[80..92] != primary[80..92] {
+        // The number, size, and checksum of the entries of each table.
+        Some(GptDamage::TableMismatch)
+    } else {
+        None
+    }
+}
+
+/// Whether a header has the GPT signature, and a checksum which matches its contents.
+fn valid(header: &[u8]) -> bool {
+    if header.len() < HEADER_SIZE || !header.starts_with(SIGNATURE) {
+        return false;
+    }
+
+    let size = u32_field(header, 12) as usize;
+    if size < HEADER_SIZE || size > header.len() {
+        return false;
+    }
+
+    let mut contents = header[..size].to_vec();
+    contents[16..20].copy_from_slice(&[0; 4]);
+    crc32(&contents) == u32_field(header, 16)
+}
+
+fn field(header: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&header[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn u32_field(header: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&header[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// The CRC-32 of GPT, which is that of zlib.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(my_lba: u64, alternate_lba: u64, entries_crc: u32) -> Vec<u8> {
+        let mut header = vec![0; 512];
+        header[..8].copy_from_slice(SIGNATURE);
+        header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&my_lba.to_le_bytes());
+        header[32..40].copy_from_slice(&alternate_lba.to_le_bytes());
+        header[56..72].copy_from_slice(&[0xAB; 16]);
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let crc = crc32(&header[..HEADER_SIZE]);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn backup_gpt_damage() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let last = 1_000_000;
+        let primary = header(1, last, 0x1234);
+        assert_eq!(inspect(&primary, Some(&header(last, 1, 0x1234)), last), None);
+        assert_eq!(
+            inspect(&primary, Some(&header(last, 1, 0x5678)), last),
+            Some(GptDamage::TableMismatch)
+        );
+        assert_eq!(inspect(&primary, Some(&[0; 512]), last), Some(GptDamage::BackupMissing));
+        assert_eq!(inspect(&primary, None, last), Some(GptDamage::BackupMissing));
+        assert_eq!(inspect(&primary, None, last * 2), Some(GptDamage::BackupMisplaced));
+
+        let mut corrupt = header(last, 1, 0x1234);
+        corrupt[40] ^= 0xFF;
+        assert_eq!(inspect(&primary, Some(&corrupt), last), Some(GptDamage::BackupCorrupt));
+        assert_eq!(inspect(&[0; 512], None, last), None);
+    }
+}
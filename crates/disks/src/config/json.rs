@@ -0,0 +1,157 @@
+//! A JSON export of probed disks, in the manner of `lsblk --json`, so that frontends which do
+//! not link the library may render disk pickers, and so that bug reports may include the exact
+//! layout of a system.
+
+use super::{Disk, DiskExt, Disks, LogicalDevice, PartitionInfo};
+use disk_types::{BlockDeviceExt, FileSystem, PartitionExt, PartitionTableExt, SectorExt};
+use os_detect::{detect_os_from_mount, OS};
+use serde_json::Value;
+use std::{fs, path::PathBuf};
+
+/// Incremented whenever a field of the export is removed or changes its meaning.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+impl Disks {
+    /// Exports the devices, and their partitions, as JSON.
+    ///
+    /// Unmounted partitions are mounted read-only to detect their OS, without replaying their
+    /// journals, and are otherwise left untouched.
+    pub fn to_json(&self) -> String {
+        let physical = self.get_physical_devices().iter().map(disk_json).collect::<Vec<_>>();
+        let logical = self.get_logical_devices().iter().map(logical_json).collect::<Vec<_>>();
+
+        json!({
+            "version": JSON_SCHEMA_VERSION,
+            "physical": physical,
+            "logical": logical,
+        })
+        .to_string()
+    }
+}
+
+fn disk_json(disk: &Disk) -> Value {
+    let sector_size = disk.get_logical_block_size();
+    json!({
+        "path": disk.get_device_path(),
+        "model": disk.get_model(),
+        "serial": disk.get_serial(),
+        "wwn": disk.get_wwn(),
+        "bus": disk.get_bus(),
+        "sectors": disk.get_sectors(),
+        "sector_size": sector_size,
+        "size": disk.get_sectors() * sector_size,
+        "table": disk.get_partition_table().map(|table| format!("{:?}", table)),
+        "read_only": disk.read_only,
+        "removable": disk.is_removable(),
+        "rotational": disk.is_rotational(),
+        "mount_point": disk.mount_point,
+        "holders": holders(disk),
+        "file_system": disk.file_system.as_ref().map(|fs| partition_json(fs, sector_size)),
+        "partitions": partitions_json(&disk.partitions, sector_size),
+    })
+}
+
+fn logical_json(device: &LogicalDevice) -> Value {
+    json!({
+        "path": device.get_device_path(),
+        "volume_group": device.volume_group,
+        "luks_parent": device.luks_parent,
+        "encrypted": device.luks_parent.is_some(),
+        "sectors": device.sectors,
+        "sector_size": device.sector_size,
+        "size": device.sectors * device.sector_size,
+        "mount_point": device.mount_point,
+        "holders": holders(device),
+        "file_system": device.file_system.as_ref().map(|fs| partition_json(fs, device.sector_size)),
+        "partitions": partitions_json(&device.partitions, device.sector_size),
+    })
+}
+
+fn partitions_json(partitions: &[PartitionInfo], sector_size: u64) -> Vec<Value> {
+    partitions.iter().map(|partition| partition_json(partition, sector_size)).collect()
+}
+
+fn partition_json(partition: &PartitionInfo, sector_size: u64) -> Value {
+    let sectors = partition.end_sector - partition.start_sector + 1;
+    json!({
+        "number": partition.number,
+        "path": partition.get_device_path(),
+        "start_sector": partition.start_sector,
+        "end_sector": partition.end_sector,
+        "size": sectors * sector_size,
+        "file_system": partition.filesystem.map(<&'static str>::from),
+        "label": partition.name,
+        "fs_label": partition.identifiers.label,
+        "uuid": partition.identifiers.uuid,
+        "part_uuid": partition.identifiers.part_uuid,
+        "flags": partition.flags.iter().map(|flag| format!("{:?}", flag)).collect::<Vec<_>>(),
+        "volume_group": partition.original_vg,
+        "mount_point": partition.mount_point,
+        "holders": holders(partition),
+        "os": probe_os(partition).as_ref().map(os_json),
+    })
+}
+
+fn os_json(os: &OS) -> Value {
+    match *os {
+        OS::Linux { ref info, ref kernels, encrypted, .. } => json!({
+            "kind": "linux",
+            "name": info.pretty_name,
+            "id": info.id,
+            "version": info.version_id,
+            "kernels": kernels,
+            "encrypted": encrypted,
+        }),
+        OS::Windows(ref name) => json!({ "kind": "windows", "name": name }),
+        OS::MacOs(ref name) => json!({ "kind": "macos", "name": name }),
+    }
+}
+
+/// The devices which are built upon this device, such as LUKS or LVM mappings.
+fn holders<D: BlockDeviceExt>(device: &D) -> Vec<PathBuf> {
+    let entries = match device.sys_block_path().join("holders").read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name();
+            let dm_name = entry.path().join("dm/name");
+            match fs::read_to_string(&dm_name) {
+                Ok(mapping) => PathBuf::from("/dev/mapper").join(mapping.trim()),
+                Err(_) => PathBuf::from("/dev").join(name),
+            }
+        })
+        .collect()
+}
+
+/// Inspects a partition which is already mounted where it is, rather than mounting it a second
+/// time. Otherwise, it is mounted read-only, without replaying its journal.
+fn probe_os(partition: &PartitionInfo) -> Option<OS> {
+    match partition.mount_point {
+        Some(ref mount) => detect_os_from_mount(&partition.device_path, mount),
+        None => match partition.filesystem? {
+            FileSystem::Swap | FileSystem::Luks | FileSystem::Lvm => None,
+            _ => partition.probe_os(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn os_json_kinds() {
+        assert_eq!(
+            os_json(&OS::Windows("Windows".into())),
+            json!({ "kind": "windows", "name": "Windows" })
+        );
+        assert_eq!(
+            os_json(&OS::MacOs("macOS 11".into())),
+            json!({ "kind": "macos", "name": "macOS 11" })
+        );
+    }
+}
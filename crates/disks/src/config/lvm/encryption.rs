@@ -1,7 +1,8 @@
-use crate::external::{cryptsetup_encrypt, cryptsetup_open, pvcreate};
+use crate::external::{cryptsetup_encrypt, cryptsetup_open, pvcreate, LuksObserver};
 use std::{
     fmt,
     path::{Path, PathBuf},
+    ptr,
 };
 use crate::DiskError;
 
@@ -11,14 +12,18 @@ pub struct LvmEncryption {
     pub physical_volume: String,
     pub password:        Option<String>,
     pub keydata:         Option<(String, Option<(PathBuf, PathBuf)>)>,
+    /// Formats the volume as LUKS1, rather than LUKS2, as GRUB can only unlock the former,
+    /// so that GRUB may read an encrypted `/boot` from the volume.
+    pub grub_readable:   bool,
 }
 
 impl fmt::Debug for LvmEncryption {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "LvmEncryption {{ physical_volume: {}, password: hidden, keydata: {:?} }}",
-            self.physical_volume, self.keydata
+            "LvmEncryption {{ physical_volume: {}, password: hidden, keydata: {:?}, \
+             grub_readable: {} }}",
+            self.physical_volume, self.keydata, self.grub_readable
         )
     }
 }
@@ -33,22 +38,35 @@ impl LvmEncryption {
             physical_volume,
             password: password.into(),
             keydata: keydata.into().map(|key| (key, None)),
+            grub_readable: false,
         }
     }
 
-    /// Encrypts a new partition with the settings stored in the structure.
-    pub fn encrypt(&self, device: &Path) -> Result<(), DiskError> {
-        cryptsetup_encrypt(device, self)
+    /// Encrypts a new partition with the settings stored in the structure, reporting the
+    /// phases of cryptsetup to `observer`.
+    pub fn encrypt(&self, device: &Path, observer: LuksObserver) -> Result<(), DiskError> {
+        cryptsetup_encrypt(device, self, observer)
             .map_err(|why| DiskError::Encryption { volume: device.into(), why })
     }
 
     /// Opens the previously-encrypted partition with the same settings used to
-    /// encrypt it.
-    pub fn open(&self, device: &Path) -> Result<(), DiskError> {
-        cryptsetup_open(device, self)
+    /// encrypt it, reporting the phases of cryptsetup to `observer`.
+    pub fn open(&self, device: &Path, observer: LuksObserver) -> Result<(), DiskError> {
+        cryptsetup_open(device, self, observer)
             .map_err(|why| DiskError::EncryptionOpen { volume: device.into(), why })
     }
 
+    /// Overwrites the password in memory before it is dropped, so that a copy which is no
+    /// longer needed does not linger in freed memory.
+    pub fn wipe_password(&mut self) {
+        if let Some(mut password) = self.password.take() {
+            // Safe, as zeroes are valid UTF-8.
+            for byte in unsafe { password.as_bytes_mut() } {
+                unsafe { ptr::write_volatile(byte, 0) };
+            }
+        }
+    }
+
     /// Creates a physical volume
     pub fn create_physical_volume(&self) -> Result<(), DiskError> {
         let path = ["/dev/mapper/", &self.physical_volume].concat();
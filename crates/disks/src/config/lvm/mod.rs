@@ -8,11 +8,13 @@ use super::{
     },
     get_size,
 };
-use disk_types::{BlockDeviceExt, PartitionTableExt, SectorExt};
+use disk_types::{BlockDeviceExt, FormatOptions, PartitionTableExt, SectorExt};
 pub use crate::external::deactivate_devices;
-use crate::external::{blkid_partition, lvcreate, lvremove, lvs, mkfs, vgactivate, vgcreate};
+use crate::external::{blkid_partition, lvcreate, lvremove, lvs, mkfs_with, vgactivate, vgcreate};
+use crate::operations::Job;
+use misc::namespace::THREAD_MOUNTS;
 use partition_identity::PartitionIdentifiers;
-use proc_mounts::MOUNTS;
+use proc_mounts::MountList;
 use std::{
     ffi::OsStr,
     path::{Path, PathBuf},
@@ -103,7 +105,8 @@ impl LogicalDevice {
         is_source: bool,
     ) -> LogicalDevice {
         let device_path = PathBuf::from(format!("/dev/mapper/{}", volume_group.replace("-", "--")));
-        let mounts = MOUNTS.read().expect("unable to get mounts within LogicalDevice::new");
+        let mounts = MountList::new_from_file(THREAD_MOUNTS)
+            .expect("unable to get mounts within LogicalDevice::new");
 
         eprintln!("Logical device of {} is {:?}", volume_group,device_path);
 
@@ -241,6 +244,7 @@ impl LogicalDevice {
                     volume_group: None,
                     key_id: None,
                     identifiers,
+                    mkfs_options: FormatOptions::default(),
                 };
 
                 start_sector += length + 1;
@@ -275,7 +279,8 @@ impl LogicalDevice {
         }
     }
 
-    /// Create & modify all logical volumes on the volume group, and format them.
+    /// Create & modify all logical volumes on the volume group. The volumes which are to be
+    /// formatted are formatted by the jobs of `format_jobs`, once this has completed.
     pub fn modify_partitions(&self) -> Result<(), DiskError> {
         let nparts = if self.partitions.is_empty() {
             if self.file_system.is_some() {
@@ -295,14 +300,16 @@ impl LogicalDevice {
             .chain(self.partitions.iter().enumerate());
 
         for (id, partition) in partitions {
-            let label =
-                partition.name.as_ref().expect("logical partitions should have names").as_str();
+            // The file system of a LUKS volume has no logical volume, and may lack a label.
+            let label = || {
+                partition.name.as_ref().expect("logical partitions should have names").as_str()
+            };
 
             // Don't create a partition if it already exists.
             if !partition.flag_is_enabled(SOURCE) {
                 lvcreate(
                     &self.volume_group,
-                    label,
+                    label(),
                     if id == nparts {
                         None
                     } else {
@@ -313,20 +320,34 @@ impl LogicalDevice {
             }
 
             if partition.flag_is_enabled(REMOVE) {
-                lvremove(&self.volume_group, label)
+                lvremove(&self.volume_group, label())
                     .map_err(|why| DiskError::PartitionRemove { partition: -1, why })?;
-            } else if partition.flag_is_enabled(FORMAT) {
-                if let Some(fs) = partition.filesystem {
-                    mkfs(&partition.device_path, fs).map_err(|why| {
-                        DiskError::new_partition_error(
-                            partition.device_path.clone(),
-                            PartitionError::PartitionFormat { why },
-                        )
-                    })?;
-                }
             }
         }
 
         Ok(())
     }
+
+    /// The jobs which format the logical volumes, each of which follows the jobs of `after`,
+    /// which create the volumes.
+    pub fn format_jobs(&self, after: &[usize]) -> Vec<Job<DiskError>> {
+        self.file_system
+            .iter()
+            .chain(self.partitions.iter())
+            .filter(|part| !part.flag_is_enabled(REMOVE) && part.flag_is_enabled(FORMAT))
+            .filter_map(|partition| {
+                let fs = partition.filesystem?;
+                let path = &partition.device_path;
+                Some(Job::new(format!("formatting {}", path.display()), after.to_vec(), move || {
+                    info!("formatting {} with {:?}", path.display(), fs);
+                    mkfs_with(path, fs, &partition.mkfs_options).map_err(|why| {
+                        DiskError::new_partition_error(
+                            path.clone(),
+                            PartitionError::PartitionFormat { why },
+                        )
+                    })
+                }))
+            })
+            .collect()
+    }
 }
@@ -1,18 +1,37 @@
 //! Contains source code related to the configuration of disks & partitions in
 //! the system.
 
+mod audit;
 mod disk;
 mod disk_trait;
 mod disks;
+mod dm_names;
+mod esp;
+mod gpt;
+mod json;
 mod lvm;
 mod partitions;
+mod plan;
+mod protected;
+mod reuse;
+mod selector;
+mod unlock;
 
 pub use self::{
+    audit::DISK_AUDIT_LOG,
     disk::*,
     disk_trait::{find_partition, find_partition_mut, DiskExt},
     disks::*,
+    dm_names::{lv_map_name, DmRegistry},
+    esp::{remove_esp_directories, StaleEspDirectory},
+    gpt::GptDamage,
+    json::JSON_SCHEMA_VERSION,
     lvm::*,
     partitions::*,
+    plan::*,
+    protected::{protect_devices, protected_devices, ProtectedDevice},
+    selector::DiskSelector,
+    unlock::{PassphraseRequest, UNLOCK_ATTEMPTS},
 };
 pub use disk_types::{PartitionTable, Sector};
 
@@ -52,6 +71,9 @@ mod tests {
                 mklabel:     false,
                 model_name:  "Test Disk".into(),
                 serial:      "Test Disk 123".into(),
+                wwn:         "".into(),
+                bus:         "".into(),
+                rotational:  false,
                 device_path: "/dev/sdz".into(),
                 file_system: None,
                 mount_point: None,
@@ -77,6 +99,7 @@ mod tests {
                         original_vg:  None,
                         volume_group: None,
                         identifiers:  PartitionIdentifiers::default(),
+                        mkfs_options: FormatOptions::default(),
                     },
                     PartitionInfo {
                         bitflags:     ACTIVE | BUSY | SOURCE,
@@ -95,6 +118,7 @@ mod tests {
                         original_vg:  None,
                         volume_group: None,
                         identifiers:  PartitionIdentifiers::default(),
+                        mkfs_options: FormatOptions::default(),
                     },
                     PartitionInfo {
                         bitflags:     SOURCE,
@@ -113,6 +137,7 @@ mod tests {
                         original_vg:  None,
                         volume_group: None,
                         identifiers:  PartitionIdentifiers::default(),
+                        mkfs_options: FormatOptions::default(),
                     },
                     PartitionInfo {
                         bitflags:     ACTIVE | SOURCE,
@@ -131,6 +156,7 @@ mod tests {
                         original_vg:  None,
                         volume_group: None,
                         identifiers:  PartitionIdentifiers::default(),
+                        mkfs_options: FormatOptions::default(),
                     },
                 ],
             }],
@@ -145,6 +171,9 @@ mod tests {
                 file_system: None,
                 model_name:  "Test Disk".into(),
                 serial:      "Test Disk 123".into(),
+                wwn:         "".into(),
+                bus:         "".into(),
+                rotational:  false,
                 device_path: "/dev/sdz".into(),
                 mount_point: None,
                 size:        1953525168,
@@ -198,6 +227,7 @@ mod tests {
                         format:       true,
                         label:        None,
                         path:         PathBuf::from("/dev/sdz"),
+                        mkfs_options: FormatOptions::default(),
                     },
                     PartitionCreate {
                         start_sector: 2048,
@@ -208,6 +238,7 @@ mod tests {
                         format:       true,
                         label:        None,
                         path:         PathBuf::from("/dev/sdz"),
+                        mkfs_options: FormatOptions::default(),
                     },
                     PartitionCreate {
                         start_sector: 1026_048,
@@ -218,12 +249,44 @@ mod tests {
                         format:       true,
                         label:        None,
                         path:         PathBuf::from("/dev/sdz"),
+                        mkfs_options: FormatOptions::default(),
                     },
                 ],
             }
         )
     }
 
+    #[test]
+    fn layout_plan() {
+        let source = get_default().physical.into_iter().next().unwrap();
+        let mut new = source.clone();
+        new.remove_partition(1).unwrap();
+        new.add_partition(boot_part(2048)).unwrap();
+
+        let plan = source.plan_against(&new).unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                PlannedOperation {
+                    kind:         OperationKind::Remove,
+                    device:       PathBuf::from("/dev/sdz1"),
+                    start_sector: 2048,
+                    end_sector:   1026047,
+                    file_system:  Some(FileSystem::Fat16),
+                    data_loss:    true,
+                },
+                PlannedOperation {
+                    kind:         OperationKind::Create,
+                    device:       PathBuf::from("/dev/sdz"),
+                    start_sector: 2048,
+                    end_sector:   1024_000 + 2047,
+                    file_system:  Some(FileSystem::Fat16),
+                    data_loss:    false,
+                },
+            ]
+        );
+    }
+
     #[test]
     fn partition_add() {
         // The default sample is maxed out, so any partition added should fail.
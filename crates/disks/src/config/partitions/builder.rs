@@ -1,6 +1,6 @@
 use super::{
-    FileSystem, LvmEncryption, PartitionFlag, PartitionIdentifiers, PartitionInfo, PartitionType,
-    FORMAT,
+    Ext4Feature, FileSystem, FormatOptions, LvmEncryption, PartitionFlag, PartitionIdentifiers,
+    PartitionInfo, PartitionType, Quota, FORMAT,
 };
 use std::path::PathBuf;
 
@@ -15,6 +15,7 @@ pub struct PartitionBuilder {
     pub mount:        Option<PathBuf>,
     pub volume_group: Option<(String, Option<LvmEncryption>)>,
     pub key_id:       Option<String>,
+    pub mkfs_options: FormatOptions,
 }
 
 impl PartitionBuilder {
@@ -30,6 +31,7 @@ impl PartitionBuilder {
             mount:        None,
             volume_group: None,
             key_id:       None,
+            mkfs_options: FormatOptions::default(),
         }
     }
 
@@ -81,6 +83,70 @@ impl PartitionBuilder {
         self
     }
 
+    /// Reserves a percentage of the file system's blocks for the root user.
+    pub fn reserved_blocks(mut self, percent: u8) -> PartitionBuilder {
+        self.mkfs_options.reserved_blocks = Some(percent);
+        self
+    }
+
+    /// Creates an inode for every `bytes` of the file system, which limits how many
+    /// files that it may hold.
+    pub fn inode_ratio(mut self, bytes: u32) -> PartitionBuilder {
+        self.mkfs_options.inode_ratio = Some(bytes);
+        self
+    }
+
+    /// Enables a kind of quota on the new file system.
+    pub fn quota(mut self, quota: Quota) -> PartitionBuilder {
+        if !self.mkfs_options.quotas.contains(&quota) {
+            self.mkfs_options.quotas.push(quota);
+        }
+        self
+    }
+
+    /// Enables, or disables, an ext4 feature on the new file system.
+    pub fn ext4_feature(mut self, feature: Ext4Feature, enabled: bool) -> PartitionBuilder {
+        self.mkfs_options.set_feature(feature, enabled);
+        self
+    }
+
+    /// Sets the level of zstd compression of a new Btrfs file system, or disables it.
+    pub fn btrfs_compression(mut self, level: Option<u8>) -> PartitionBuilder {
+        self.mkfs_options.btrfs.compression = level;
+        self
+    }
+
+    /// Enables quota groups on a new Btrfs file system.
+    pub fn btrfs_quotas(mut self, enabled: bool) -> PartitionBuilder {
+        self.mkfs_options.btrfs.quotas = enabled;
+        self
+    }
+
+    /// Defines whether a new Btrfs file system is mounted with `space_cache=v2`.
+    pub fn btrfs_space_cache_v2(mut self, enabled: bool) -> PartitionBuilder {
+        self.mkfs_options.btrfs.space_cache_v2 = enabled;
+        self
+    }
+
+    /// Creates a subvolume with copy-on-write disabled at the given path, relative to the
+    /// root of a new Btrfs file system.
+    pub fn btrfs_nodatacow(mut self, subvolume: PathBuf) -> PartitionBuilder {
+        self.mkfs_options.btrfs.nodatacow.push(subvolume);
+        self
+    }
+
+    /// Sets the volume label of a new FAT file system, which is validated before installing.
+    pub fn fat_label(mut self, label: String) -> PartitionBuilder {
+        self.mkfs_options.fat.label = Some(label);
+        self
+    }
+
+    /// Sets the volume ID of a new FAT file system, rather than deriving it from the partition.
+    pub fn fat_volume_id(mut self, id: u32) -> PartitionBuilder {
+        self.mkfs_options.fat.volume_id = Some(id);
+        self
+    }
+
     /// Builds a brand new Partition from the current state of the builder.
     pub fn build(self) -> PartitionInfo {
         PartitionInfo {
@@ -108,6 +174,7 @@ impl PartitionBuilder {
             volume_group: self.volume_group.clone(),
             key_id:       self.key_id,
             identifiers:  PartitionIdentifiers::default(),
+            mkfs_options: self.mkfs_options,
         }
     }
 }
@@ -2,10 +2,13 @@ mod builder;
 
 pub use self::builder::PartitionBuilder;
 use super::{
-    super::{LvmEncryption, PartitionError},
-    PVS,
+    super::{udev::Properties, LvmEncryption, PartitionError},
+    PartitionTable, PVS,
+};
+pub use disk_types::{
+    BlockDeviceExt, BtrfsOptions, Ext4Feature, FileSystem, FormatOptions, PartitionExt,
+    PartitionType, Quota, SectorExt,
 };
-pub use disk_types::{BlockDeviceExt, FileSystem, PartitionExt, PartitionType, SectorExt};
 use crate::external::{get_label, is_encrypted};
 use fstab_generate::BlockInfo;
 use libparted::{Partition, PartitionFlag};
@@ -13,11 +16,13 @@ pub use os_detect::OS;
 use partition_identity::PartitionIdentifiers;
 use proc_mounts::{MountList, SwapList};
 use std::{
+    borrow::Cow,
     io,
     path::{Path, PathBuf},
     str::FromStr,
 };
 use sys_mount::swapoff;
+use sysfs_class::{Block, SysClass};
 
 pub fn get_preferred_options(fs: FileSystem) -> &'static str {
     match fs {
@@ -84,6 +89,8 @@ pub struct PartitionInfo {
     pub key_id:       Option<String>,
     /// Possible identifiers for this partition.
     pub identifiers:  PartitionIdentifiers,
+    /// Options which are applied if the partition is formatted.
+    pub mkfs_options: FormatOptions,
 }
 
 impl BlockDeviceExt for PartitionInfo {
@@ -145,6 +152,77 @@ impl PartitionInfo {
             volume_group: None,
             key_id: None,
             identifiers,
+            mkfs_options: FormatOptions::default(),
+        }))
+    }
+
+    /// Obtains the partition information from sysfs and the udev database, which does not
+    /// require root. Logical volumes and most partition flags will not be known, however.
+    ///
+    /// Sysfs measures partitions in 512-byte units, which are converted to `sector_size` sectors.
+    pub fn new_from_sysfs(
+        block: &Block,
+        table: PartitionTable,
+        sector_size: u64,
+        mounts: &MountList,
+        swaps: &SwapList,
+    ) -> io::Result<Option<PartitionInfo>> {
+        let device_path = Path::new("/dev").join(block.id());
+        info!("obtaining partition information from {}", device_path.display());
+
+        let properties = Properties::of(block.dev()?.trim());
+        let entry_type = properties.get("ID_PART_ENTRY_TYPE").unwrap_or("");
+        let number = i32::from(block.partition()?);
+
+        let part_type = match table {
+            // Extended partitions only contain logical partitions, and are skipped by libparted.
+            PartitionTable::Msdos if ["0x5", "0xf", "0x85"].contains(&entry_type) => {
+                return Ok(None)
+            }
+            PartitionTable::Msdos if number > 4 => PartitionType::Logical,
+            _ => PartitionType::Primary,
+        };
+
+        let mut flags = Vec::new();
+        match entry_type {
+            "c12a7328-f81f-11d2-ba4b-00a0c93ec93b" | "0xef" => {
+                flags.push(PartitionFlag::PED_PARTITION_ESP)
+            }
+            "e3c9e316-0b5c-4db8-817d-f92df00215ae" => {
+                flags.push(PartitionFlag::PED_PARTITION_MSFT_RESERVED)
+            }
+            _ => (),
+        }
+
+        if properties.get("ID_PART_ENTRY_FLAGS") == Some("0x80") {
+            flags.push(PartitionFlag::PED_PARTITION_BOOT);
+        }
+
+        let start_sector = block.start()? * 512 / sector_size;
+        let mount_point = mounts.get_mount_by_source(&device_path).map(|m| m.dest.clone());
+        let swapped = swaps.get_swapped(&device_path);
+
+        Ok(Some(PartitionInfo {
+            bitflags: SOURCE
+                | ACTIVE
+                | if mount_point.is_some() || swapped { BUSY } else { 0 }
+                | if swapped { SWAPPED } else { 0 },
+            part_type,
+            mount_point,
+            target: None,
+            filesystem: properties.file_system(),
+            flags,
+            number,
+            ordering: -1,
+            name: properties.get("ID_FS_LABEL").map(String::from),
+            identifiers: PartitionIdentifiers::from_path(&device_path),
+            device_path,
+            start_sector,
+            end_sector: start_sector + block.size()? * 512 / sector_size - 1,
+            original_vg: None,
+            volume_group: None,
+            key_id: None,
+            mkfs_options: FormatOptions::default(),
         }))
     }
 
@@ -270,11 +348,17 @@ impl PartitionInfo {
             return None;
         }
 
+        let options = if fs == FileSystem::Btrfs && self.will_format() {
+            Cow::Owned(self.mkfs_options.btrfs.fstab_options())
+        } else {
+            Cow::Borrowed(get_preferred_options(fs))
+        };
+
         Some(BlockInfo::new(
             BlockInfo::get_partition_id(&self.device_path, fs)?,
             fs,
             self.target.as_deref(),
-            get_preferred_options(fs),
+            options,
         ))
     }
 }
@@ -331,6 +415,7 @@ mod tests {
             original_vg:  None,
             volume_group: None,
             identifiers:  PartitionIdentifiers::default(),
+            mkfs_options: FormatOptions::default(),
         }
     }
 
@@ -352,6 +437,7 @@ mod tests {
             original_vg:  None,
             volume_group: None,
             identifiers:  PartitionIdentifiers::default(),
+            mkfs_options: FormatOptions::default(),
         }
     }
 
@@ -378,8 +464,10 @@ mod tests {
                     physical_volume: "LUKS_PV".into(),
                     password:        Some("password".into()),
                     keydata:         None,
+                    grub_readable:   false,
                 }),
             )),
+            mkfs_options: FormatOptions::default(),
         }
     }
 
@@ -401,6 +489,7 @@ mod tests {
             original_vg:  None,
             volume_group: Some(("LVM_GROUP".into(), None)),
             identifiers:  PartitionIdentifiers::default(),
+            mkfs_options: FormatOptions::default(),
         }
     }
 
@@ -422,6 +511,7 @@ mod tests {
             original_vg:  None,
             volume_group: None,
             identifiers:  PartitionIdentifiers::default(),
+            mkfs_options: FormatOptions::default(),
         }
     }
 
@@ -0,0 +1,192 @@
+//! Describes the operations that will be performed on each device, so that frontends may
+//! present the plan that the installer will execute before committing to it.
+
+use super::{
+    super::{DiskError, Disks},
+    partitions::{FORMAT, SOURCE},
+    Disk,
+};
+use disk_types::FileSystem;
+use operations::DiskOps;
+use std::{fmt, path::PathBuf};
+
+/// The kind of operation to perform on a device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperationKind {
+    /// A new partition table will be written to the disk.
+    Mklabel,
+    /// An existing partition will be removed.
+    Remove,
+    /// An existing partition will be resized or moved.
+    Resize,
+    /// A new partition will be created and formatted.
+    Create,
+    /// A volume group will be created on the device.
+    CreateVolumeGroup,
+    /// A logical volume will be created and formatted.
+    CreateVolume,
+    /// An existing logical volume will be formatted.
+    Format,
+    /// A directory of an OS which no longer exists will be removed from the reused ESP.
+    RemoveEspDirectory,
+}
+
+/// An operation that the installer will perform on a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedOperation {
+    pub kind:         OperationKind,
+    /// The disk, partition, or logical device to be modified, or the ESP directory which is
+    /// removed, beneath `/boot/efi`.
+    pub device:       PathBuf,
+    pub start_sector: u64,
+    pub end_sector:   u64,
+    /// The file system that will be written, if any.
+    pub file_system:  Option<FileSystem>,
+    /// True if existing data will be lost.
+    pub data_loss:    bool,
+}
+
+impl fmt::Display for PlannedOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let device = self.device.display();
+        let size = (self.end_sector - self.start_sector) / 2048;
+        let fs = self.file_system.map_or("unformatted", Into::into);
+        match self.kind {
+            OperationKind::Mklabel => write!(f, "write a new partition table to {}", device),
+            OperationKind::Remove => write!(f, "remove {} ({} MiB)", device, size),
+            OperationKind::Resize => write!(
+                f,
+                "resize {} to sectors {} - {} ({} MiB)",
+                device, self.start_sector, self.end_sector, size
+            ),
+            OperationKind::Create => {
+                write!(f, "create a {} MiB {} partition on {}", size, fs, device)
+            }
+            OperationKind::CreateVolumeGroup => write!(f, "create volume group {}", device),
+            OperationKind::CreateVolume => {
+                write!(f, "create a {} MiB {} logical volume at {}", size, fs, device)
+            }
+            OperationKind::Format => write!(f, "format {} as {}", device, fs),
+            OperationKind::RemoveEspDirectory => {
+                write!(f, "remove {} from the ESP ({} MiB)", device, size)
+            }
+        }
+    }
+}
+
+impl Disk {
+    /// Plans the operations which will be performed when committing changes to this disk.
+    pub fn plan(&self) -> Result<Vec<PlannedOperation>, DiskError> {
+        Disk::from_name_with_serial(&self.device_path, &self.serial)
+            .and_then(|source| source.plan_against(self))
+    }
+
+    /// Plans the operations which will transform this source disk into the `new` disk.
+    pub fn plan_against(&self, new: &Disk) -> Result<Vec<PlannedOperation>, DiskError> {
+        self.diff(new).map(|ops| self.plan_from(&ops))
+    }
+
+    fn plan_from(&self, ops: &DiskOps) -> Vec<PlannedOperation> {
+        let mut plan = Vec::new();
+
+        if ops.mklabel.is_some() {
+            plan.push(PlannedOperation {
+                kind:         OperationKind::Mklabel,
+                device:       self.device_path.clone(),
+                start_sector: 0,
+                end_sector:   self.size,
+                file_system:  None,
+                data_loss:    true,
+            });
+        }
+
+        for &sector in &ops.remove_partitions {
+            let source = self.partitions.iter().find(|part| part.start_sector == sector);
+            plan.push(PlannedOperation {
+                kind:         OperationKind::Remove,
+                device:       source
+                    .map_or_else(|| self.device_path.clone(), |part| part.device_path.clone()),
+                start_sector: sector,
+                end_sector:   source.map_or(sector, |part| part.end_sector),
+                file_system:  source.and_then(|part| part.filesystem),
+                data_loss:    true,
+            });
+        }
+
+        for change in &ops.change_partitions {
+            plan.push(PlannedOperation {
+                kind:         OperationKind::Resize,
+                device:       change.path.clone(),
+                start_sector: change.start,
+                end_sector:   change.end,
+                file_system:  change.filesystem,
+                data_loss:    false,
+            });
+        }
+
+        for create in &ops.create_partitions {
+            plan.push(PlannedOperation {
+                kind:         OperationKind::Create,
+                device:       create.path.clone(),
+                start_sector: create.start_sector,
+                end_sector:   create.end_sector,
+                file_system:  create.file_system,
+                data_loss:    false,
+            });
+        }
+
+        plan
+    }
+}
+
+impl Disks {
+    /// Plans every operation that the installer will perform on the physical and logical
+    /// devices in this configuration, in the order that they will be performed.
+    pub fn plan(&self) -> Result<Vec<PlannedOperation>, DiskError> {
+        let mut plan = Vec::new();
+        for disk in &self.physical {
+            plan.extend(disk.plan()?);
+        }
+
+        for device in &self.logical {
+            if !device.is_source {
+                plan.push(PlannedOperation {
+                    kind:         OperationKind::CreateVolumeGroup,
+                    device:       PathBuf::from(["/dev/", &device.volume_group].concat()),
+                    start_sector: 0,
+                    end_sector:   device.sectors,
+                    file_system:  None,
+                    data_loss:    false,
+                });
+            }
+
+            for partition in &device.partitions {
+                let kind = if !partition.flag_is_enabled(SOURCE) {
+                    OperationKind::CreateVolume
+                } else if partition.flag_is_enabled(FORMAT) {
+                    OperationKind::Format
+                } else {
+                    continue;
+                };
+
+                let device_path = match (partition.flag_is_enabled(SOURCE), &partition.name) {
+                    (false, Some(name)) => {
+                        PathBuf::from(format!("/dev/mapper/{}-{}", device.volume_group, name))
+                    }
+                    _ => partition.device_path.clone(),
+                };
+
+                plan.push(PlannedOperation {
+                    kind,
+                    device: device_path,
+                    start_sector: partition.start_sector,
+                    end_sector: partition.end_sector,
+                    file_system: partition.filesystem,
+                    data_loss: kind == OperationKind::Format,
+                });
+            }
+        }
+
+        Ok(plan)
+    }
+}
@@ -0,0 +1,210 @@
+//! Devices which must never be modified, such as the data disks of a workstation, which
+//! unattended deployments register so that a layout which would wipe them is refused, however
+//! it was written. Configurations are checked against them as they are verified, and again as
+//! each disk is committed, against the partitions which are found on it.
+
+use super::{
+    super::{DiskError, LogicalDevice},
+    partitions::{FORMAT, REMOVE, SOURCE},
+    Disk, DiskExt, DiskSelector, PartitionInfo,
+};
+use once_cell::sync::Lazy;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::RwLock,
+};
+
+static PROTECTED: Lazy<RwLock<Vec<ProtectedDevice>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// A device which must not be modified.
+///
+/// Written as a path to a disk or a partition, as `serial=ID`, `wwn=ID`, or `model=NAME` for
+/// disks, or as `label=NAME` for the partitions, and the file systems, with that label.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtectedDevice {
+    Disk(DiskSelector),
+    Label(String),
+}
+
+impl ProtectedDevice {
+    fn protects_disk(&self, disk: &Disk) -> bool {
+        match self {
+            ProtectedDevice::Disk(selector) => selector.matches(disk),
+            ProtectedDevice::Label(_) => false,
+        }
+    }
+
+    fn protects_partition(&self, partition: &PartitionInfo) -> bool {
+        match self {
+            ProtectedDevice::Disk(DiskSelector::Path(path)) => {
+                partition.device_path == *path
+                    || fs::canonicalize(path).map_or(false, |path| partition.device_path == path)
+            }
+            ProtectedDevice::Disk(_) => false,
+            ProtectedDevice::Label(label) => {
+                partition.name.as_ref() == Some(label)
+                    || partition.identifiers.label.as_ref() == Some(label)
+            }
+        }
+    }
+}
+
+impl FromStr for ProtectedDevice {
+    type Err = DiskError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.strip_prefix("label=") {
+            Some("") => Err(DiskError::DiskSelectorEmpty { selector: input.to_owned() }),
+            Some(label) => Ok(ProtectedDevice::Label(label.to_owned())),
+            None => input.parse().map(ProtectedDevice::Disk),
+        }
+    }
+}
+
+impl fmt::Display for ProtectedDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtectedDevice::Disk(selector) => write!(f, "{}", selector),
+            ProtectedDevice::Label(label) => write!(f, "label={}", label),
+        }
+    }
+}
+
+/// Replaces the devices which must not be modified.
+pub fn protect_devices(devices: Vec<ProtectedDevice>) {
+    if !devices.is_empty() {
+        info!("protecting {:?} from modification", devices);
+    }
+
+    *PROTECTED.write().unwrap() = devices;
+}
+
+/// The devices which must not be modified.
+pub fn protected_devices() -> Vec<ProtectedDevice> { PROTECTED.read().unwrap().clone() }
+
+/// Fails if the configuration of a disk would modify a protected device which it holds.
+///
+/// The partitions of `source` are those which are on the disk, or, before the disk is
+/// committed, those of the configuration which already exist.
+pub(crate) fn check_disk(source: &Disk, new: &Disk) -> Result<(), DiskError> {
+    violation(&PROTECTED.read().unwrap(), source, new).map_or(Ok(()), Err)
+}
+
+/// Fails if a logical device would remove or format a protected volume.
+pub(crate) fn check_logical(device: &LogicalDevice) -> Result<(), DiskError> {
+    let protected = PROTECTED.read().unwrap();
+    let volumes = device.file_system.iter().chain(device.get_partitions());
+    for volume in volumes.filter(|volume| volume.flag_is_enabled(SOURCE)) {
+        if volume.flag_is_enabled(REMOVE | FORMAT) {
+            if let Some(rule) = protected.iter().find(|rule| rule.protects_partition(volume)) {
+                return Err(protected_error(&volume.device_path, rule));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn violation(protected: &[ProtectedDevice], source: &Disk, new: &Disk) -> Option<DiskError> {
+    if protected.is_empty() {
+        return None;
+    }
+
+    let modified = |original: &PartitionInfo| {
+        new.mklabel
+            || new
+                .get_partitions()
+                .iter()
+                .find(|part| part.flag_is_enabled(SOURCE) && part.number == original.number)
+                .map_or(true, |part| {
+                    part.flag_is_enabled(REMOVE | FORMAT)
+                        || part.start_sector != original.start_sector
+                        || part.end_sector != original.end_sector
+                })
+    };
+
+    let originals: Vec<&PartitionInfo> =
+        source.get_partitions().iter().filter(|part| part.flag_is_enabled(SOURCE)).collect();
+
+    for &original in &originals {
+        if let Some(rule) = protected.iter().find(|rule| rule.protects_partition(original)) {
+            if modified(original) {
+                return Some(protected_error(&original.device_path, rule));
+            }
+        }
+    }
+
+    let disk_modified = new.mklabel
+        || new.get_partitions().iter().any(|part| !part.flag_is_enabled(SOURCE))
+        || originals.iter().any(|original| modified(original));
+
+    if disk_modified {
+        if let Some(rule) = protected.iter().find(|rule| rule.protects_disk(source)) {
+            return Some(protected_error(&source.device_path, rule));
+        }
+    }
+
+    None
+}
+
+fn protected_error(device: &Path, rule: &ProtectedDevice) -> DiskError {
+    DiskError::DeviceProtected { device: device.to_path_buf(), rule: rule.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PartitionBuilder;
+    use disk_types::FileSystem;
+
+    fn data_disk() -> Disk {
+        let partition = |number: i32, start: u64, end: u64, label: &str| {
+            let mut partition = PartitionBuilder::new(start, end, FileSystem::Ext4)
+                .name(label.into())
+                .build();
+            partition.number = number;
+            partition.bitflags |= SOURCE;
+            partition.device_path = PathBuf::from(format!("/dev/sdb{}", number));
+            partition
+        };
+
+        Disk {
+            device_path: "/dev/sdb".into(),
+            serial:      "WDC_WD40EFRX_WD-WCC7K0123456".into(),
+            size:        7_814_037_168,
+            partitions:  vec![
+                partition(1, 2048, 1_000_000, "scratch"),
+                partition(2, 1_000_000, 7_814_000_000, "corporate"),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn protected_devices_are_not_modified() {
+        let source = data_disk();
+        let rules = |rules: &[&str]| -> Vec<ProtectedDevice> {
+            rules.iter().map(|rule| rule.parse().unwrap()).collect()
+        };
+
+        let mut formatted = source.clone();
+        formatted.partitions[0].bitflags |= FORMAT;
+        assert!(violation(&rules(&["label=corporate"]), &source, &formatted).is_none());
+        assert!(violation(&rules(&["/dev/sdb1"]), &source, &formatted).is_some());
+        assert!(violation(&rules(&["serial=WD-WCC7K0123456"]), &source, &formatted).is_some());
+
+        let mut resized = source.clone();
+        resized.partitions[1].end_sector -= 1024;
+        assert!(violation(&rules(&["label=corporate"]), &source, &resized).is_some());
+
+        let mut relabeled = source.clone();
+        relabeled.mklabel = true;
+        assert!(violation(&rules(&["/dev/sdb2"]), &source, &relabeled).is_some());
+
+        assert!(violation(&rules(&["serial=OTHER"]), &source, &relabeled).is_none());
+        assert!(violation(&rules(&["label=corporate", "/dev/sdb"]), &source, &source).is_none());
+        assert!("label=".parse::<ProtectedDevice>().is_err());
+    }
+}
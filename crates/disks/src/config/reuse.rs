@@ -0,0 +1,95 @@
+//! Installs into an existing LUKS volume, such as that of a previous install, without creating
+//! a new container in its place, so that its header and keyslots, and the passphrases which
+//! unlock it, are kept. Only the file system within the volume may be formatted.
+
+use super::{
+    super::{DecryptionError, Disks, FileSystem, LogicalDevice},
+    partitions::SOURCE,
+    DiskExt, LvmEncryption, PartitionBuilder,
+};
+use crate::external::cryptsetup_open;
+use disk_types::PartitionExt;
+use std::path::{Path, PathBuf};
+
+impl Disks {
+    /// Unlocks the LUKS partition at `path` with `enc`, unless it was already unlocked, and adds
+    /// its contents as a logical device, on which the targets of the install may be set.
+    ///
+    /// If `file_system` is given, the file system within the volume is replaced with a new one,
+    /// which is required of a volume that contains no file system. A volume which contains a
+    /// volume group is installed into by formatting its logical volumes instead.
+    pub fn reuse_encrypted(
+        &mut self,
+        path: &Path,
+        enc: &LvmEncryption,
+        file_system: Option<FileSystem>,
+    ) -> Result<(), DecryptionError> {
+        info!("reusing the LUKS partition at {:?}", path);
+        let unlocked =
+            self.logical.iter().any(|device| device.luks_parent.as_deref() == Some(path));
+        if !unlocked {
+            match self.decrypt_partition(path, enc) {
+                Ok(()) => (),
+                Err(DecryptionError::DecryptedLacksVG { .. }) if file_system.is_some() => {
+                    self.add_empty_volume(path, enc)?
+                }
+                Err(why) => return Err(why),
+            }
+        }
+
+        let fs = match file_system {
+            Some(fs) => fs,
+            None => return Ok(()),
+        };
+
+        let device = self
+            .logical
+            .iter_mut()
+            .find(|device| device.luks_parent.as_deref() == Some(path))
+            .ok_or_else(|| DecryptionError::LuksNotFound { device: path.to_path_buf() })?;
+
+        match device.file_system {
+            Some(ref mut inner) => {
+                info!("formatting the contents of {:?} with {:?}", path, fs);
+                inner.format_and_keep_name(fs);
+                Ok(())
+            }
+            None => Err(DecryptionError::ContainsVolumeGroup { device: path.to_path_buf() }),
+        }
+    }
+
+    /// Opens a LUKS volume which holds neither a volume group nor a file system, which
+    /// `decrypt_partition` closes again, and adds its map as a logical device to be formatted.
+    fn add_empty_volume(
+        &mut self,
+        path: &Path,
+        enc: &LvmEncryption,
+    ) -> Result<(), DecryptionError> {
+        let sectors = self
+            .get_physical_partitions()
+            .find(|part| part.get_device_path() == path)
+            .map(|part| part.get_sectors())
+            .ok_or_else(|| DecryptionError::LuksNotFound { device: path.to_path_buf() })?;
+
+        cryptsetup_open(path, enc, &|_, _| ())
+            .map_err(|why| DecryptionError::Open { device: path.to_path_buf(), why })?;
+
+        // The map exists already, so it is neither created nor given a path as a new volume is.
+        let mut inner = PartitionBuilder::new(0, sectors, None).build();
+        inner.bitflags = SOURCE;
+        inner.device_path = PathBuf::from(["/dev/mapper/", &enc.physical_volume].concat());
+
+        let mut device = LogicalDevice::new(
+            enc.physical_volume.clone(),
+            Some(enc.clone()),
+            sectors,
+            512,
+            true,
+        );
+
+        device.set_file_system(inner);
+        device.set_luks_parent(path.to_path_buf());
+        self.logical.push(device);
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Disks may be selected by the identifiers which their manufacturers assign, such as their
+//! serials and WWNs, rather than by their device paths, which depend on the order in which the
+//! kernel probed them, and which change across boots. Unattended installs to servers with many
+//! disks select their targets this way, so that the wrong disk is not wiped.
+
+use super::{
+    super::{DiskError, Disks},
+    Disk,
+};
+use std::{fmt, fs, path::PathBuf, str::FromStr};
+
+/// Selects a disk by its device path, or by one of its stable identifiers.
+///
+/// Written as `serial=ID`, `wwn=ID`, or `model=NAME`, or as a path such as `/dev/sda` or
+/// `/dev/disk/by-id/wwn-0x5002538d403d649a`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiskSelector {
+    Path(PathBuf),
+    /// Either the serial recorded by udev, or the serial printed on the label of the disk, which
+    /// udev appends to the model.
+    Serial(String),
+    /// The World Wide Name, with or without its `0x` prefix.
+    Wwn(String),
+    Model(String),
+}
+
+impl DiskSelector {
+    /// Whether the selector identifies the given disk.
+    pub fn matches(&self, disk: &Disk) -> bool {
+        match self {
+            DiskSelector::Path(path) => {
+                disk.device_path == *path
+                    || fs::canonicalize(path).map_or(false, |path| disk.device_path == path)
+            }
+            DiskSelector::Serial(serial) => {
+                !disk.serial.is_empty()
+                    && (disk.serial == *serial
+                        || disk.serial.ends_with(&["_", serial.as_str()].concat()))
+            }
+            DiskSelector::Wwn(wwn) => !disk.wwn.is_empty() && normalize_wwn(&disk.wwn) == *wwn,
+            DiskSelector::Model(model) => {
+                let name = normalize_model(&disk.model_name);
+                name == *model || name.ends_with(&[" ", model.as_str()].concat())
+            }
+        }
+    }
+
+    /// The index of the only disk which the selector identifies.
+    fn position(&self, disks: &[Disk]) -> Result<usize, DiskError> {
+        let mut matches = disks.iter().enumerate().filter(|(_, disk)| self.matches(disk));
+        match (matches.next(), matches.count()) {
+            (Some((id, _)), 0) => Ok(id),
+            (first, rest) => Err(DiskError::DiskSelect {
+                selector: self.to_string(),
+                count:    first.map_or(0, |_| rest + 1),
+            }),
+        }
+    }
+}
+
+impl FromStr for DiskSelector {
+    type Err = DiskError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let selector = match input.find('=').map(|pos| (&input[..pos], input[pos + 1..].trim())) {
+            Some(("serial", serial)) => DiskSelector::Serial(serial.to_owned()),
+            Some(("wwn", wwn)) => DiskSelector::Wwn(normalize_wwn(wwn)),
+            Some(("model", model)) => DiskSelector::Model(normalize_model(model)),
+            _ => return Ok(DiskSelector::Path(PathBuf::from(input))),
+        };
+
+        let empty = match selector {
+            DiskSelector::Serial(ref id) | DiskSelector::Wwn(ref id) => id.is_empty(),
+            DiskSelector::Model(ref name) => name.is_empty(),
+            DiskSelector::Path(_) => false,
+        };
+
+        if empty {
+            return Err(DiskError::DiskSelectorEmpty { selector: input.to_owned() });
+        }
+
+        Ok(selector)
+    }
+}
+
+impl fmt::Display for DiskSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiskSelector::Path(path) => write!(f, "{}", path.display()),
+            DiskSelector::Serial(serial) => write!(f, "serial={}", serial),
+            DiskSelector::Wwn(wwn) => write!(f, "wwn=0x{}", wwn),
+            DiskSelector::Model(model) => write!(f, "model={}", model),
+        }
+    }
+}
+
+impl Disks {
+    /// Returns the disk of the configuration which the selector identifies, failing if it
+    /// identifies none, or more than one.
+    pub fn select_disk(&self, selector: &DiskSelector) -> Result<&Disk, DiskError> {
+        selector.position(&self.physical).map(move |id| &self.physical[id])
+    }
+
+    /// Returns the disk of the configuration which the selector identifies, failing if it
+    /// identifies none, or more than one. Mutable variant.
+    pub fn select_disk_mut(&mut self, selector: &DiskSelector) -> Result<&mut Disk, DiskError> {
+        selector.position(&self.physical).map(move |id| &mut self.physical[id])
+    }
+}
+
+impl Disk {
+    /// Obtains the disk which the selector identifies.
+    ///
+    /// Selectors other than paths are matched against the disks of the system, which must have
+    /// exactly one match, so that an identifier which several disks share selects none of them.
+    pub fn from_selector(selector: &DiskSelector) -> Result<Disk, DiskError> {
+        if let DiskSelector::Path(ref path) = *selector {
+            return Disk::from_name(path);
+        }
+
+        let disks = Disks::probe_devices_unprivileged()?;
+        let path = disks.select_disk(selector)?.device_path.clone();
+        info!("{} selected {}", selector, path.display());
+        Disk::from_name(&path).and_then(|disk| {
+            // The disk may have been swapped since the system was probed.
+            if selector.matches(&disk) {
+                Ok(disk)
+            } else {
+                Err(DiskError::DiskSelect { selector: selector.to_string(), count: 0 })
+            }
+        })
+    }
+}
+
+/// WWNs are compared in lowercase, without the `0x` prefix of udev.
+fn normalize_wwn(wwn: &str) -> String { wwn.to_lowercase().trim_start_matches("0x").to_owned() }
+
+/// Models are compared in lowercase, with the underscores that udev substitutes for spaces
+/// restored, as names such as `Samsung_SSD_850_EVO_500GB` are copied from udev.
+fn normalize_model(model: &str) -> String {
+    model.replace('_', " ").split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(path: &str, model: &str, serial: &str, wwn: &str) -> Disk {
+        Disk {
+            device_path: path.into(),
+            model_name:  model.into(),
+            serial:      serial.into(),
+            wwn:         wwn.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stable_selectors() {
+        let disks = [
+            disk(
+                "/dev/sda",
+                "ATA Samsung SSD 850",
+                "Samsung_SSD_850_EVO_500GB_S21HNXAG806916N",
+                "0x5002538d403d649a",
+            ),
+            disk("/dev/sdb", "ATA ST4000DM004-2CV1", "ST4000DM004-2CV104_ZFN1ABCD", ""),
+            disk("/dev/sdc", "ATA ST4000DM004-2CV1", "ST4000DM004-2CV104_ZFN1EFGH", ""),
+        ];
+
+        let select = |input: &str| input.parse::<DiskSelector>().unwrap().position(&disks);
+
+        assert_eq!(select("/dev/sdb").unwrap(), 1);
+        assert_eq!(select("serial=S21HNXAG806916N").unwrap(), 0);
+        assert_eq!(select("serial=ST4000DM004-2CV104_ZFN1EFGH").unwrap(), 2);
+        assert_eq!(select("wwn=0x5002538D403D649A").unwrap(), 0);
+        assert_eq!(select("wwn=5002538d403d649a").unwrap(), 0);
+        assert_eq!(select("model=samsung_ssd_850").unwrap(), 0);
+
+        match select("model=ST4000DM004-2CV1") {
+            Err(DiskError::DiskSelect { count: 2, .. }) => (),
+            result => panic!("two disks of the same model were not ambiguous: {:?}", result),
+        }
+
+        match select("serial=ZFN1") {
+            Err(DiskError::DiskSelect { count: 0, .. }) => (),
+            result => panic!("a partial serial selected a disk: {:?}", result),
+        }
+
+        assert!("wwn=".parse::<DiskSelector>().is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Unlocks the existing encrypted volumes of the disks with passphrases which a frontend is
+//! asked for, so that an install alongside, or a reinstall which keeps `/home`, may be applied
+//! to an encrypted disk without its volumes being unlocked beforehand.
+
+use super::{
+    super::{DecryptionError, DiskError, Disks},
+    LvmEncryption, PartitionInfo,
+};
+use disk_types::{BlockDeviceExt, FileSystem};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// How many times the passphrase of a volume is asked for before it is left locked.
+pub const UNLOCK_ATTEMPTS: u32 = 3;
+
+/// A request for the passphrase of an encrypted volume.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassphraseRequest<'a> {
+    /// The partition which contains the volume.
+    pub device:  &'a Path,
+    /// The label of the partition, if it has one.
+    pub label:   Option<&'a str>,
+    /// The attempt to unlock the volume, which exceeds 1 after a wrong passphrase was given.
+    pub attempt: u32,
+}
+
+impl Disks {
+    /// Probes the disks as `probe_devices` does, and then unlocks their encrypted volumes as
+    /// `unlock_encrypted` does.
+    pub fn probe_devices_with_passphrases<F>(passphrase: F) -> Result<Disks, DiskError>
+    where
+        F: FnMut(&PassphraseRequest) -> Option<String>,
+    {
+        let mut disks = Disks::probe_devices()?;
+        disks.unlock_encrypted(passphrase);
+        Ok(disks)
+    }
+
+    /// Unlocks each LUKS partition which is not already open, asking `passphrase` for its
+    /// passphrase up to `UNLOCK_ATTEMPTS` times. A volume is left locked if `passphrase`
+    /// returns `None`, such as when the user chooses to skip it.
+    ///
+    /// The contents of each volume which is unlocked are added as logical devices, and the
+    /// partitions of the unlocked volumes are returned.
+    pub fn unlock_encrypted<F>(&mut self, mut passphrase: F) -> Vec<PathBuf>
+    where
+        F: FnMut(&PassphraseRequest) -> Option<String>,
+    {
+        let locked: Vec<(PathBuf, Option<String>)> = self
+            .get_physical_partitions()
+            .filter(|part| part.filesystem == Some(FileSystem::Luks) && !self.is_unlocked(part))
+            .map(|part| (part.get_device_path().to_path_buf(), part.name.clone()))
+            .collect();
+
+        let mut unlocked = Vec::new();
+        for (device, label) in locked {
+            let physical_volume = mapper_name(&device);
+            for attempt in 1..=UNLOCK_ATTEMPTS {
+                let label = label.as_deref();
+                let request = PassphraseRequest { device: &device, label, attempt };
+                let password = match passphrase(&request) {
+                    Some(password) => password,
+                    None => {
+                        info!("leaving {} locked", device.display());
+                        break;
+                    }
+                };
+
+                // The volume keeps its own copy of the passphrase, and this one is wiped.
+                let mut enc = LvmEncryption::new(physical_volume.clone(), Some(password), None);
+                let result = self.decrypt_partition(&device, &enc);
+                enc.wipe_password();
+
+                match result {
+                    Ok(()) => {
+                        unlocked.push(device.clone());
+                        break;
+                    }
+                    Err(DecryptionError::Open { why, .. }) => {
+                        let device = device.display();
+                        warn!("unable to unlock {} on attempt {}: {}", device, attempt, why);
+                    }
+                    Err(why) => {
+                        warn!("unable to use the unlocked volume of {}: {}", device.display(), why);
+                        break;
+                    }
+                }
+            }
+        }
+
+        unlocked
+    }
+
+    /// Whether the volume of a LUKS partition is already open, either by this probe or by the
+    /// system before it.
+    fn is_unlocked(&self, part: &PartitionInfo) -> bool {
+        let path = part.get_device_path();
+        part.volume_group.is_some()
+            || self.get_logical_devices().iter().any(|dev| dev.luks_parent.as_deref() == Some(path))
+            || fs::read_dir(part.sys_block_path().join("holders"))
+                .map_or(false, |mut holders| holders.next().is_some())
+    }
+}
+
+/// The device mapper name of the unlocked volume of `device`, following the `sda3_crypt`
+/// convention of Debian's installer.
+fn mapper_name(device: &Path) -> String { unused_name(Path::new("/dev/mapper"), device) }
+
+/// The mapper name of `device` which is not yet in use in `mapper`. A number is appended to the
+/// name if a mapping of another device already has it.
+fn unused_name(mapper: &Path, device: &Path) -> String {
+    let name = device.file_name().map_or_else(
+        || device.to_string_lossy().replace('/', "_"),
+        |name| name.to_string_lossy().into_owned(),
+    );
+
+    let name = [name.as_str(), "_crypt"].concat();
+    let mut candidate = name.clone();
+    let mut number = 1;
+    while fs::symlink_metadata(mapper.join(&candidate)).is_ok() {
+        candidate = format!("{}{}", name, number);
+        number += 1;
+    }
+
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn mapper_names() {
+        let mapper = TempDir::new("distinst").unwrap();
+        assert_eq!(unused_name(mapper.path(), Path::new("/dev/sda3")), "sda3_crypt");
+        assert_eq!(unused_name(mapper.path(), Path::new("/dev/nvme0n1p2")), "nvme0n1p2_crypt");
+
+        fs::write(mapper.path().join("sda3_crypt"), "").unwrap();
+        fs::write(mapper.path().join("sda3_crypt1"), "").unwrap();
+        assert_eq!(unused_name(mapper.path(), Path::new("/dev/sda3")), "sda3_crypt2");
+    }
+}
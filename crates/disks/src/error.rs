@@ -1,6 +1,10 @@
+use super::{Capability, GptDamage};
 pub use disk_types::PartitionSizeError;
 use disk_types::{FileSystem, PartitionTableError};
-use std::{io, path::PathBuf};
+use std::{
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
 
 /// Defines a variety of errors that may arise from configuring and committing changes to disks.
 #[rustfmt::skip]
@@ -10,14 +14,22 @@ pub enum DiskError {
     Decryption { why: DecryptionError },
     #[fail(display = "unable to get device at {:?}: {}", device, why)]
     DeviceGet { device: PathBuf, why: io::Error },
+    #[fail(display = "{:?} is protected by '{}', and may not be modified", device, rule)]
+    DeviceProtected { device: PathBuf, rule: String },
     #[fail(display = "unable to probe for devices")]
     DeviceProbe,
+    #[fail(display = "probing for devices was cancelled")]
+    ProbeCancelled,
     #[fail(display = "unable to commit changes to disk ({:?}): {}", device, why)]
     DiskCommit { device: PathBuf, why: io::Error },
     #[fail(display = "unable to format partition table on {:?}: {}", device, why)]
     DiskFresh { device: PathBuf, why: io::Error },
     #[fail(display = "unable to find disk at {:?}", device)]
     DiskGet { device: PathBuf },
+    #[fail(display = "'{}' selected {} disks, rather than one", selector, count)]
+    DiskSelect { selector: String, count: usize },
+    #[fail(display = "disk selector '{}' lacks an identifier", selector)]
+    DiskSelectorEmpty { selector: String },
     #[fail(display = "unable to open disk at {:?}: {}", device, why)]
     DiskNew {device: PathBuf,  why: io::Error },
     #[fail(display = "unable to sync disk changes with OS: {}", why)]
@@ -32,6 +44,10 @@ pub enum DiskError {
     InvalidSerial,
     #[fail(display = "{}", why)]
     IO { why: io::Error },
+    #[fail(display = "{:?}: {}, and must be repaired before the disk is modified", device, damage)]
+    GptDamaged { device: PathBuf, damage: GptDamage },
+    #[fail(display = "unable to repair the GPT of {:?}: {}", device, why)]
+    GptRepair { device: PathBuf, why: io::Error },
     #[fail(display = "failed to create partition geometry: {}", why)]
     GeometryCreate { why: io::Error },
     #[fail(display = "failed to duplicate partition geometry")]
@@ -68,6 +84,12 @@ pub enum DiskError {
     PartitionRemoveBySector { sector: u64, why: io::Error },
     #[fail(display = "{}", why)]
     PartitionTable { why: PartitionTableError },
+    #[fail(
+        display = "permission denied while {}: the {} capability, or access to the device, is \
+                   required",
+        operation, capability
+    )]
+    PermissionDenied { operation: &'static str, capability: Capability },
     #[fail(display = "unable to create physical volume from '{}': {}", volume, why)]
     PhysicalVolumeCreate { volume: String, why: io::Error },
     #[fail(display = "multiple devices had the same volume group: currently unsupported")]
@@ -119,6 +141,8 @@ pub enum DecryptionError {
     DecryptedLacksVG { device: PathBuf },
     #[fail(display = "LUKS partition at '{:?}' was not found", device)]
     LuksNotFound { device: PathBuf },
+    #[fail(display = "LUKS partition at '{:?}' contains a volume group to format instead", device)]
+    ContainsVolumeGroup { device: PathBuf },
 }
 
 impl From<DecryptionError> for DiskError {
@@ -137,7 +161,13 @@ impl From<io::Error> for DiskError {
 
 impl From<DiskError> for io::Error {
     fn from(err: DiskError) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, format!("an I/O error occurred: {}", err))
+        match err {
+            DiskError::PermissionDenied { .. } => {
+                CodedError::new(ErrorCode::PermissionDenied, None, format!("{}", err))
+                    .into_io(io::ErrorKind::PermissionDenied)
+            }
+            _ => io::Error::new(io::ErrorKind::Other, format!("an I/O error occurred: {}", err)),
+        }
     }
 }
 
@@ -157,3 +187,96 @@ impl From<PartitionSizeError> for PartitionError {
 impl From<PartitionTableError> for DiskError {
     fn from(why: PartitionTableError) -> DiskError { DiskError::PartitionTable { why } }
 }
+
+/// Identifies a specific failure, so that frontends may react to it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ErrorCode {
+    Unknown,
+    Cancelled,
+    HostnameInvalid,
+    SquashfsMissing,
+    RootUndefined,
+    RootFileSystemInvalid,
+    BootUndefined,
+    BootFileSystemInvalid,
+    BootOnLogicalDevice,
+    EspNotGpt,
+    EspFlagMissing,
+    EspTooSmall,
+    RecoveryTooSmall,
+    InsufficientSpace,
+    PermissionDenied,
+    BiosGrubMissing,
+    EfiVariablesUnavailable,
+    InitramfsIncomplete,
+    BootEncrypted,
+    BootOnRaid,
+    DeviceProtected,
+    GptDamaged,
+}
+
+impl ErrorCode {
+    /// A description of the failure, which frontends may use when they lack their own.
+    pub fn describe(self) -> &'static str {
+        match self {
+            ErrorCode::Unknown => "an unknown error occurred",
+            ErrorCode::Cancelled => "the install was cancelled",
+            ErrorCode::HostnameInvalid => "the hostname is not valid",
+            ErrorCode::SquashfsMissing => "the image to install from was not found",
+            ErrorCode::RootUndefined => "a root partition was not defined",
+            ErrorCode::RootFileSystemInvalid => "the root partition has an invalid file system",
+            ErrorCode::BootUndefined => "a boot partition was not defined",
+            ErrorCode::BootFileSystemInvalid => "the boot partition has an invalid file system",
+            ErrorCode::BootOnLogicalDevice => "the boot partition cannot be on a logical device",
+            ErrorCode::EspNotGpt => "EFI installs require a disk with a GPT partition table",
+            ErrorCode::EspFlagMissing => "the EFI partition does not have the ESP flag set",
+            ErrorCode::EspTooSmall => "the EFI partition is too small",
+            ErrorCode::RecoveryTooSmall => "the recovery partition is too small",
+            ErrorCode::InsufficientSpace => "there is not enough space for the install",
+            ErrorCode::PermissionDenied => "the installer must be authorized to modify disks",
+            ErrorCode::BiosGrubMissing => {
+                "BIOS installs to GPT disks require a partition with the BIOS boot flag"
+            }
+            ErrorCode::EfiVariablesUnavailable => {
+                "the boot order cannot be modified, as the system was not booted with EFI"
+            }
+            ErrorCode::InitramfsIncomplete => {
+                "the initramfs lacks the programs or modules which are needed to open the root"
+            }
+            ErrorCode::BootEncrypted => "GRUB cannot read the boot partition, as it is encrypted",
+            ErrorCode::BootOnRaid => "the boot partition cannot be on a RAID array",
+            ErrorCode::DeviceProtected => "the layout modifies a device which is protected",
+            ErrorCode::GptDamaged => "the backup partition table of the disk must be repaired",
+        }
+    }
+}
+
+/// An error which carries an `ErrorCode`, and the device that it concerns.
+///
+/// This is carried within an `io::Error`, and may be retrieved from it with `CodedError::of`.
+#[derive(Debug)]
+pub struct CodedError {
+    pub code:    ErrorCode,
+    pub device:  Option<PathBuf>,
+    pub message: String,
+}
+
+impl CodedError {
+    pub fn new<S: Into<String>>(code: ErrorCode, device: Option<&Path>, message: S) -> Self {
+        CodedError { code, device: device.map(Path::to_path_buf), message: message.into() }
+    }
+
+    /// Wraps this error within an `io::Error` of the given kind.
+    pub fn into_io(self, kind: io::ErrorKind) -> io::Error { io::Error::new(kind, self) }
+
+    /// Obtains the coded error from an `io::Error`, if it has one.
+    pub fn of(error: &io::Error) -> Option<&CodedError> {
+        error.get_ref().and_then(|error| error.downcast_ref::<CodedError>())
+    }
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(&self.message) }
+}
+
+impl error::Error for CodedError {}
@@ -2,9 +2,10 @@
 
 pub use crate::config::deactivate_devices;
 pub use external_::*;
-use misc;
+use misc::{self, namespace::THREAD_MOUNTS};
 use proc_mounts::{MountList, SwapList};
 use std::{
+    ffi::OsString,
     fs::Permissions,
     io::{self, Read, Write},
     os::unix::fs::PermissionsExt,
@@ -15,7 +16,8 @@ use tempdir::TempDir;
 use crate::LvmEncryption;
 
 fn remove_encrypted_device(device: &Path) -> io::Result<()> {
-    let mounts = MountList::new().expect("failed to get mounts in deactivate_device_maps");
+    let mounts = MountList::new_from_file(THREAD_MOUNTS)
+        .expect("failed to get mounts in deactivate_device_maps");
     let swaps = SwapList::new().expect("failed to get swaps in deactivate_device_maps");
     let umount = move |vg: &str| -> io::Result<()> {
         for lv in lvs(vg)? {
@@ -59,27 +61,32 @@ fn remove_encrypted_device(device: &Path) -> io::Result<()> {
 }
 
 /// Creates a LUKS partition from a physical partition. This could be either a LUKS on LVM
-/// configuration, or a LVM on LUKS configurations.
-pub fn cryptsetup_encrypt(device: &Path, enc: &LvmEncryption) -> io::Result<()> {
+/// configuration, or a LVM on LUKS configurations. The phases of cryptsetup are reported to
+/// `observer`.
+pub fn cryptsetup_encrypt(
+    device: &Path,
+    enc: &LvmEncryption,
+    observer: LuksObserver,
+) -> io::Result<()> {
     remove_encrypted_device(device)?;
 
     info!("cryptsetup is encrypting {} with {:?}", device.display(), enc);
 
     match (enc.password.as_ref(), enc.keydata.as_ref()) {
         (Some(_password), Some(_keydata)) => unimplemented!(),
-        (Some(password), None) => exec(
-            "cryptsetup",
-            Some(&append_newline(password.as_bytes())),
-            None,
-            &[
+        (Some(password), None) => {
+            let luks_type = if enc.grub_readable { "luks1" } else { "luks2" };
+            let args: Vec<OsString> = vec![
                 "-s".into(),
                 "512".into(),
                 "luksFormat".into(),
                 "--type".into(),
-                "luks2".into(),
+                luks_type.into(),
                 device.into(),
-            ],
-        ),
+            ];
+            let stdin = append_newline(password.as_bytes());
+            cryptsetup_phased(device, LuksPhase::Formatting, Some(&stdin), &args, observer)
+        }
         (None, Some(&(_, ref keydata))) => {
             let keydata = keydata.as_ref().expect("field should have been populated");
             let tmpfs = TempDir::new("distinst")?;
@@ -91,9 +98,9 @@ pub fn cryptsetup_encrypt(device: &Path, enc: &LvmEncryption) -> io::Result<()>
             generate_keyfile(&keypath)?;
             info!("keypath exists: {}", keypath.is_file());
 
-            exec(
-                "cryptsetup",
-                None,
+            cryptsetup_phased(
+                device,
+                LuksPhase::Formatting,
                 None,
                 &[
                     "-s".into(),
@@ -104,24 +111,31 @@ pub fn cryptsetup_encrypt(device: &Path, enc: &LvmEncryption) -> io::Result<()>
                     device.into(),
                     tmpfs.path().join(&enc.physical_volume).into(),
                 ],
+                observer,
             )
         }
         (None, None) => unimplemented!(),
     }
 }
 
-/// Opens an encrypted partition and maps it to the pv name.
-pub fn cryptsetup_open(device: &Path, enc: &LvmEncryption) -> io::Result<()> {
+/// Opens an encrypted partition and maps it to the pv name, reporting the phases of cryptsetup
+/// to `observer`.
+pub fn cryptsetup_open(
+    device: &Path,
+    enc: &LvmEncryption,
+    observer: LuksObserver,
+) -> io::Result<()> {
     deactivate_devices(&[device])?;
     let pv = &enc.physical_volume;
     info!("cryptsetup is opening {} with pv {} and {:?}", device.display(), pv, enc);
     match (enc.password.as_ref(), enc.keydata.as_ref()) {
         (Some(_password), Some(_keydata)) => unimplemented!(),
-        (Some(password), None) => exec(
-            "cryptsetup",
+        (Some(password), None) => cryptsetup_phased(
+            device,
+            LuksPhase::Opening,
             Some(&append_newline(password.as_bytes())),
-            None,
             &["open".into(), device.into(), pv.into()],
+            observer,
         ),
         (None, Some(&(_, ref keydata))) => {
             let keydata = keydata.as_ref().expect("field should have been populated");
@@ -132,11 +146,12 @@ pub fn cryptsetup_open(device: &Path, enc: &LvmEncryption) -> io::Result<()> {
             let keypath = tmpfs.path().join(&enc.physical_volume);
             info!("keypath exists: {}", keypath.is_file());
 
-            exec(
-                "cryptsetup",
-                None,
+            cryptsetup_phased(
+                device,
+                LuksPhase::Opening,
                 None,
                 &["open".into(), device.into(), pv.into(), "--key-file".into(), keypath.into()],
+                observer,
             )
         }
         (None, None) => unimplemented!(),
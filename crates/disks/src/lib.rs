@@ -15,11 +15,14 @@ extern crate libparted;
 #[macro_use]
 extern crate log;
 pub extern crate distinst_disk_ops as operations;
+extern crate once_cell;
 extern crate os_detect;
 extern crate partition_identity;
 extern crate proc_mounts;
 extern crate rand;
 extern crate rayon;
+#[macro_use]
+extern crate serde_json;
 extern crate sys_mount;
 extern crate sysfs_class;
 extern crate tempdir;
@@ -27,11 +30,16 @@ extern crate tempdir;
 mod config;
 mod error;
 pub mod external;
+mod privileges;
 mod serial;
+mod udev;
 
 pub use self::{
     config::*,
-    error::{DecryptionError, DiskError, PartitionError, PartitionSizeError},
+    error::{
+        CodedError, DecryptionError, DiskError, ErrorCode, PartitionError, PartitionSizeError,
+    },
+    privileges::{has_capability, require_capability, Capability},
 };
-pub use bootloader::{Bootloader, FORCE_BOOTLOADER};
+pub use bootloader::{Bootloader, BootloaderOverride, KernelLoader, FORCE_BOOTLOADER};
 pub use libparted::PartitionFlag;
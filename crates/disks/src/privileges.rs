@@ -0,0 +1,100 @@
+//! Probing partition tables, and every operation which modifies a disk, requires privileges
+//! that frontends typically lack until the user has authenticated. Their absence is reported as
+//! a `DiskError::PermissionDenied`, rather than as an obscure failure from libparted.
+//!
+//! Operations which modify disks check for the capability that they require up front. Probing
+//! instead attempts to open the devices, as members of the `disk` group may read them without
+//! any capability.
+
+use super::DiskError;
+use std::{
+    fmt,
+    fs::{self, File},
+    io,
+    path::Path,
+};
+
+/// A capability which a privileged operation requires.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Capability {
+    /// Reading the partition tables and volume groups of block devices.
+    DacReadSearch,
+    /// Modifying partition tables, formatting, and mounting.
+    SysAdmin,
+}
+
+impl Capability {
+    fn bit(self) -> u32 {
+        match self {
+            Capability::DacReadSearch => 2,
+            Capability::SysAdmin => 21,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Capability::DacReadSearch => "CAP_DAC_READ_SEARCH",
+            Capability::SysAdmin => "CAP_SYS_ADMIN",
+        })
+    }
+}
+
+/// Checks if the effective capabilities of this process include `capability`.
+pub fn has_capability(capability: Capability) -> bool {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| effective_capabilities(&status))
+        .map_or(false, |set| set & (1 << capability.bit()) != 0)
+}
+
+/// Returns `DiskError::PermissionDenied` if this process lacks the `capability` which the
+/// `operation` requires.
+pub fn require_capability(
+    capability: Capability,
+    operation: &'static str,
+) -> Result<(), DiskError> {
+    if has_capability(capability) {
+        Ok(())
+    } else {
+        Err(DiskError::PermissionDenied { operation, capability })
+    }
+}
+
+/// Opens `device` for reading, as probing it would, and returns `DiskError::PermissionDenied`
+/// if this process was refused access. Other errors are left for the operation to report.
+pub fn require_access(
+    device: &Path,
+    capability: Capability,
+    operation: &'static str,
+) -> Result<(), DiskError> {
+    match File::open(device) {
+        Err(ref why) if why.kind() == io::ErrorKind::PermissionDenied => {
+            Err(DiskError::PermissionDenied { operation, capability })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn effective_capabilities(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|set| u64::from_str_radix(set.trim(), 16).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities() {
+        let root = "Uid:\t0\t0\t0\t0\nCapInh:\t0000000000000000\nCapEff:\t000001ffffffffff\n";
+        let user = "Uid:\t1000\t1000\t1000\t1000\nCapEff:\t0000000000000000\n";
+        let set = effective_capabilities(root).unwrap();
+        assert!(set & (1 << Capability::SysAdmin.bit()) != 0);
+        assert_eq!(effective_capabilities(user), Some(0));
+        assert_eq!(effective_capabilities("Uid:\t0\n"), None);
+    }
+}
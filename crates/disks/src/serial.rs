@@ -3,7 +3,8 @@
 //! unlikely, that a user could hot swap drives after obtaining device
 //! information, but before writing their changes to the disk.
 
-use std::{io, path::Path, process::Command};
+use external_::runner::runner;
+use std::{io, path::Path};
 
 const PATTERN: &str = "E: ID_SERIAL=";
 
@@ -12,10 +13,8 @@ const PATTERN: &str = "E: ID_SERIAL=";
 /// The `path` should be a value like `/dev/sda`.
 pub fn get_serial(path: &Path) -> io::Result<String> {
     info!("obtaining serial model from {}", path.display());
-    Command::new("udevadm")
-        .args(&["info", "--query=all", &format!("--name={}", path.display())])
-        .output()
-        .and_then(|output| parse_serial(&output.stdout))
+    let args = ["info".into(), "--query=all".into(), format!("--name={}", path.display()).into()];
+    runner().run("udevadm", &args, None, true).and_then(|output| parse_serial(&output.stdout))
 }
 
 fn parse_serial(data: &[u8]) -> io::Result<String> {
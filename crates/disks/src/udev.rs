@@ -0,0 +1,87 @@
+//! The properties that udev records for each block device are world-readable, and may be
+//! used to describe devices without opening them, which would require root.
+
+use disk_types::{FileSystem, PartitionTable};
+use std::{collections::BTreeMap, fs, str::FromStr};
+
+/// The properties of a block device, as recorded in the udev database.
+#[derive(Debug, Default)]
+pub struct Properties(BTreeMap<String, String>);
+
+impl Properties {
+    /// Reads the properties of the block device with the given `major:minor` number.
+    ///
+    /// Devices which udev has not processed will have no properties.
+    pub fn of(dev: &str) -> Properties {
+        fs::read_to_string(["/run/udev/data/b", dev].concat())
+            .map(|data| Properties::parse(&data))
+            .unwrap_or_default()
+    }
+
+    fn parse(data: &str) -> Properties {
+        Properties(
+            data.lines()
+                .filter_map(|line| line.strip_prefix("E:"))
+                .filter_map(|line| {
+                    let mut fields = line.splitn(2, '=');
+                    Some((fields.next()?.to_owned(), fields.next()?.to_owned()))
+                })
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> { self.0.get(key).map(String::as_str) }
+
+    /// The World Wide Name of the device, with its extension, if it has one.
+    pub fn wwn(&self) -> Option<&str> {
+        self.get("ID_WWN_WITH_EXTENSION").or_else(|| self.get("ID_WWN"))
+    }
+
+    /// The file system on the device, if blkid recognized it.
+    pub fn file_system(&self) -> Option<FileSystem> {
+        match self.get("ID_FS_TYPE")? {
+            "vfat" => match self.get("ID_FS_VERSION") {
+                Some("FAT12") | Some("FAT16") => Some(FileSystem::Fat16),
+                _ => Some(FileSystem::Fat32),
+            },
+            fs => FileSystem::from_str(fs).ok(),
+        }
+    }
+
+    /// The partition table on the device, if it has one.
+    pub fn partition_table(&self) -> Option<PartitionTable> {
+        match self.get("ID_PART_TABLE_TYPE")? {
+            "gpt" => Some(PartitionTable::Gpt),
+            "dos" => Some(PartitionTable::Msdos),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "S:disk/by-uuid/4A1C-2B5D
+I:1564088
+E:ID_FS_UUID=4A1C-2B5D
+E:ID_FS_VERSION=FAT32
+E:ID_FS_TYPE=vfat
+E:ID_FS_USAGE=filesystem
+E:ID_PART_TABLE_TYPE=gpt
+E:ID_PART_ENTRY_TYPE=c12a7328-f81f-11d2-ba4b-00a0c93ec93b
+G:systemd";
+
+    #[test]
+    fn properties() {
+        let properties = Properties::parse(SAMPLE);
+        assert_eq!(properties.get("ID_FS_UUID"), Some("4A1C-2B5D"));
+        assert_eq!(properties.get("ID_FS_LABEL"), None);
+        assert_eq!(properties.file_system(), Some(FileSystem::Fat32));
+        assert_eq!(properties.partition_table(), Some(PartitionTable::Gpt));
+
+        let luks = Properties::parse("E:ID_FS_TYPE=crypto_LUKS\nE:ID_FS_VERSION=2");
+        assert_eq!(luks.file_system(), Some(FileSystem::Luks));
+        assert_eq!(luks.partition_table(), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Reads and edits env-style files of `KEY=value` assignments, such as `recovery.conf`.
+//!
+//! Comments, blank lines, the order of keys, and the formatting of values which are not changed
+//! are preserved. Files are written atomically, so that a loss of power while writing leaves
+//! either the old file or the new one, and never a truncated file.
+//!
+//! ```rust,no_run
+//! extern crate envfile;
+//!
+//! use envfile::EnvFile;
+//!
+//! pub fn main() {
+//!     let mut env = EnvFile::new("/cdrom/recovery.conf").unwrap();
+//!     if env.get("OEM_MODE") == Some("1") {
+//!         env.update("OEM_MODE", "0");
+//!     }
+//!     env.remove("MODE");
+//!     env.write().unwrap();
+//! }
+//! ```
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Line {
+    /// An assignment, with the line that it was parsed from, if it has not been changed.
+    Entry { key: String, value: String, raw: Option<String> },
+    /// A comment, a blank line, or a line which is not an assignment.
+    Other(String),
+}
+
+/// An env-style file of `KEY=value` assignments.
+#[derive(Clone, Debug)]
+pub struct EnvFile {
+    /// Where the file is read from, and written to.
+    pub path: PathBuf,
+    lines:    Vec<Line>,
+}
+
+impl EnvFile {
+    /// Reads the file at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let contents = fs::read_to_string(&path)?;
+        Ok(EnvFile::parse(path, &contents))
+    }
+
+    /// Parses the `contents` of the file which is written to `path`.
+    pub fn parse<P: Into<PathBuf>>(path: P, contents: &str) -> Self {
+        let lines = contents
+            .lines()
+            .map(|line| match parse_line(line) {
+                Some((key, value)) => {
+                    Line::Entry { key: key.to_owned(), value, raw: Some(line.to_owned()) }
+                }
+                None => Line::Other(line.to_owned()),
+            })
+            .collect();
+
+        EnvFile { path: path.into(), lines }
+    }
+
+    /// The value of `key`. If it is assigned more than once, the last assignment is returned,
+    /// as it would be by a shell.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries().filter(|&(name, _)| name == key).last().map(|(_, value)| value)
+    }
+
+    /// The assignments of the file, in the order that they appear.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => Some((key.as_str(), value.as_str())),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// Sets the value of `key`, where it was last assigned, or else at the end of the file.
+    pub fn update(&mut self, key: &str, value: &str) -> &mut Self {
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            Line::Entry { key: name, value: existing, raw } if name == key => Some((existing, raw)),
+            _ => None,
+        });
+
+        match existing {
+            Some((existing, raw)) => {
+                if *existing != value {
+                    *existing = value.to_owned();
+                    *raw = None;
+                }
+            }
+            None => self.lines.push(Line::Entry {
+                key:   key.to_owned(),
+                value: value.to_owned(),
+                raw:   None,
+            }),
+        }
+
+        self
+    }
+
+    /// Removes every assignment of `key`, returning the value that it had.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let value = self.get(key).map(String::from);
+        self.lines.retain(|line| match line {
+            Line::Entry { key: name, .. } => name != key,
+            Line::Other(_) => true,
+        });
+        value
+    }
+
+    /// Atomically replaces the file at `path` with the changes which were made.
+    pub fn write(&mut self) -> io::Result<()> {
+        write_atomic(&self.path, self.to_string().as_bytes())
+    }
+}
+
+impl fmt::Display for EnvFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Entry { raw: Some(raw), .. } | Line::Other(raw) => writeln!(f, "{}", raw)?,
+                Line::Entry { key, value, raw: None } => writeln!(f, "{}={}", key, quote(value))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes `contents` to a temporary file beside `path`, which is synced to the disk and then
+/// renamed over `path`, so that `path` is never left partially written. The directory is then
+/// synced, so that the rename itself survives a loss of power.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "path to write does not name a file")
+    })?;
+
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+
+    let mut temporary = name.to_owned();
+    temporary.push(".tmp");
+    let temporary = parent.join(temporary);
+
+    let mode = fs::metadata(path).map_or(0o644, |metadata| metadata.permissions().mode());
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(mode)
+            .open(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+        return result;
+    }
+
+    File::open(parent)?.sync_all()
+}
+
+/// Parses an assignment, returning `None` for comments, blank lines, and other lines.
+fn parse_line(line: &str) -> Option<(&str, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let line = line.strip_prefix("export ").map_or(line, str::trim_start);
+    let mut assignment = line.splitn(2, '=');
+    let key = assignment.next()?.trim_end();
+    let value = assignment.next()?.trim_start();
+
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    Some((key, unquote(value)))
+}
+
+/// Removes the quotes around a value, and the escapes within double quotes. Unquoted values
+/// are taken as they are.
+fn unquote(value: &str) -> String {
+    let quoted = |q: char| value.len() >= 2 && value.starts_with(q) && value.ends_with(q);
+
+    if quoted('\'') {
+        return value[1..value.len() - 1].to_owned();
+    }
+
+    if !quoted('"') {
+        return value.to_owned();
+    }
+
+    let mut unquoted = String::with_capacity(value.len());
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(character) = chars.next() {
+        match character {
+            '\\' => match chars.next() {
+                Some(escaped @ '"') | Some(escaped @ '\\') | Some(escaped @ '$')
+                | Some(escaped @ '`') => unquoted.push(escaped),
+                Some(other) => {
+                    unquoted.push('\\');
+                    unquoted.push(other);
+                }
+                None => unquoted.push('\\'),
+            },
+            character => unquoted.push(character),
+        }
+    }
+
+    unquoted
+}
+
+/// Quotes a value if a shell would not read it as it is.
+fn quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-.,:/+@%".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        return value.to_owned();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for character in value.chars() {
+        if let '"' | '\\' | '$' | '`' = character {
+            quoted.push('\\');
+        }
+        quoted.push(character);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECOVERY_CONF: &str = r#"# Recovery settings
+HOSTNAME=pop-os
+LANG=en_US.UTF-8
+
+KBD_LAYOUT='us'
+MODE="refresh"
+OEM_MODE=1
+"#;
+
+    #[test]
+    fn envfile_edits() {
+        let mut env = EnvFile::parse("recovery.conf", RECOVERY_CONF);
+        assert_eq!(env.get("HOSTNAME"), Some("pop-os"));
+        assert_eq!(env.get("KBD_LAYOUT"), Some("us"));
+        assert_eq!(env.get("MODE"), Some("refresh"));
+        assert_eq!(env.to_string(), RECOVERY_CONF);
+
+        env.update("OEM_MODE", "0").update("LANG", "en_US.UTF-8").update("EFI_UUID", "");
+        assert_eq!(env.remove("MODE").as_deref(), Some("refresh"));
+        env.update("HOSTNAME", "pop os \"$HOST\"");
+
+        assert_eq!(
+            env.to_string(),
+            "# Recovery settings\nHOSTNAME=\"pop os \\\"\\$HOST\\\"\"\nLANG=en_US.UTF-8\n\n\
+             KBD_LAYOUT='us'\nOEM_MODE=0\nEFI_UUID=\"\"\n"
+        );
+
+        let env = EnvFile::parse("recovery.conf", &env.to_string());
+        assert_eq!(env.get("HOSTNAME"), Some("pop os \"$HOST\""));
+        assert_eq!(env.get("EFI_UUID"), Some(""));
+    }
+}
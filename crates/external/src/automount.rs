@@ -0,0 +1,141 @@
+//! Inhibits udisks, and thereby the desktop's automounter, from mounting the partitions of the
+//! disks being installed to, which it may otherwise do whenever a partition is formatted, along
+//! with the LUKS and LVM volumes created on them, which are device maps rather than partitions.
+
+use super::exec;
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+const RULES_DIR: &str = "/run/udev/rules.d";
+
+/// Hides the given disks and their partitions, and the device maps of the install, from udisks
+/// until dropped.
+pub struct AutomountInhibitor {
+    disks: Vec<PathBuf>,
+    rules: PathBuf,
+}
+
+impl AutomountInhibitor {
+    /// Installs a udev rule which marks the disks, and their partitions, as ignored by udisks,
+    /// along with the LUKS volumes named `maps`, and the logical volumes of `volume_groups`.
+    ///
+    /// The rule is named after the process, so that the rule of one installer is not removed
+    /// by another.
+    pub fn new<P: AsRef<Path>, S: AsRef<str>>(
+        disks: &[P],
+        maps: &[S],
+        volume_groups: &[S],
+    ) -> io::Result<Self> {
+        let disks: Vec<PathBuf> = disks.iter().map(|disk| disk.as_ref().to_path_buf()).collect();
+        let rules = Path::new(RULES_DIR)
+            .join(format!("90-distinst-inhibit-automount-{}.rules", process::id()));
+
+        fs::create_dir_all(RULES_DIR)?;
+        fs::write(&rules, self::rules(&disks, maps, volume_groups))?;
+
+        let inhibitor = AutomountInhibitor { disks, rules };
+        inhibitor.reload()?;
+        Ok(inhibitor)
+    }
+
+    /// Reloads the rules, and applies them to devices which already exist.
+    fn reload(&self) -> io::Result<()> {
+        exec("udevadm", None, None, &["control".into(), "--reload".into()])?;
+
+        for name in self.disks.iter().filter_map(|disk| disk.file_name()) {
+            let mut parent = OsString::from("--parent-match=");
+            parent.push(Path::new("/sys/class/block").join(name));
+            let args = [
+                "trigger".into(),
+                "--action=change".into(),
+                "--subsystem-match=block".into(),
+                parent,
+            ];
+
+            exec("udevadm", None, None, &args)?;
+        }
+
+        // Device maps are not children of the disks that they are stored on.
+        let args = [
+            "trigger".into(),
+            "--action=change".into(),
+            "--subsystem-match=block".into(),
+            "--sysname-match=dm-*".into(),
+        ];
+        exec("udevadm", None, None, &args)?;
+
+        exec("udevadm", None, None, &["settle".into()])
+    }
+}
+
+impl Drop for AutomountInhibitor {
+    fn drop(&mut self) {
+        info!("allowing udisks to manage {:?} again", self.disks);
+        if let Err(why) = fs::remove_file(&self.rules) {
+            warn!("unable to remove {}: {}", self.rules.display(), why);
+        }
+
+        if let Err(why) = self.reload() {
+            warn!("unable to reload udev rules: {}", why);
+        }
+    }
+}
+
+fn rules<S: AsRef<str>>(disks: &[PathBuf], maps: &[S], volume_groups: &[S]) -> String {
+    const IGNORE: &str = "ENV{UDISKS_IGNORE}=\"1\", ENV{UDISKS_AUTO}=\"0\"";
+
+    let mut rules = String::from("# Created by distinst for the duration of an install.\n");
+    for name in disks.iter().filter_map(|disk| disk.file_name()) {
+        // KERNELS matches the disk itself, as well as its partitions.
+        rules.push_str(&format!(
+            "SUBSYSTEM==\"block\", KERNELS==\"{}\", {}\n",
+            name.to_string_lossy(),
+            IGNORE
+        ));
+    }
+
+    // The names of device maps, and of volume groups, are set by the rules of dm and LVM.
+    for map in maps {
+        rules.push_str(&format!(
+            "SUBSYSTEM==\"block\", KERNEL==\"dm-*\", ENV{{DM_NAME}}==\"{}\", {}\n",
+            map.as_ref(),
+            IGNORE
+        ));
+    }
+
+    for group in volume_groups {
+        rules.push_str(&format!(
+            "SUBSYSTEM==\"block\", KERNEL==\"dm-*\", ENV{{DM_VG_NAME}}==\"{}\", {}\n",
+            group.as_ref(),
+            IGNORE
+        ));
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inhibit_rules() {
+        let disks = [PathBuf::from("/dev/sda"), PathBuf::from("/dev/nvme0n1")];
+        assert_eq!(
+            rules(&disks, &["cryptdata"], &["data"]),
+            "# Created by distinst for the duration of an install.\n\
+             SUBSYSTEM==\"block\", KERNELS==\"sda\", ENV{UDISKS_IGNORE}=\"1\", \
+             ENV{UDISKS_AUTO}=\"0\"\n\
+             SUBSYSTEM==\"block\", KERNELS==\"nvme0n1\", ENV{UDISKS_IGNORE}=\"1\", \
+             ENV{UDISKS_AUTO}=\"0\"\n\
+             SUBSYSTEM==\"block\", KERNEL==\"dm-*\", ENV{DM_NAME}==\"cryptdata\", \
+             ENV{UDISKS_IGNORE}=\"1\", ENV{UDISKS_AUTO}=\"0\"\n\
+             SUBSYSTEM==\"block\", KERNEL==\"dm-*\", ENV{DM_VG_NAME}==\"data\", \
+             ENV{UDISKS_IGNORE}=\"1\", ENV{UDISKS_AUTO}=\"0\"\n"
+        );
+    }
+}
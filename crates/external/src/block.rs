@@ -1,12 +1,11 @@
 use self::FileSystem::*;
-use super::exec;
-use disk_types::FileSystem;
+use super::{capture, exec, runner::runner, status};
+use disk_types::{fat_cluster_size, FatOptions, FileSystem, FormatOptions, Quota};
 use crate::retry::Retry;
 use std::{
     ffi::{OsStr, OsString},
     io,
-    path::Path,
-    process::{Command, Stdio},
+    path::{Path, PathBuf},
 };
 
 /// Erase all signatures on a disk
@@ -30,13 +29,7 @@ pub fn blockdev<P: AsRef<Path>, S: AsRef<OsStr>, I: IntoIterator<Item = S>>(
 
 /// Obtains the file system on a partition via blkid
 pub fn blkid_partition<P: AsRef<Path>>(part: P) -> Option<FileSystem> {
-    let output = Command::new("blkid")
-        .arg(part.as_ref())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .ok()?
-        .stdout;
+    let output = capture("blkid", &[part.as_ref().into()]).ok()?;
 
     for field in String::from_utf8_lossy(&output).split_whitespace() {
         if field.starts_with("TYPE=") {
@@ -64,16 +57,52 @@ pub fn fsck<P: AsRef<Path>>(part: P, cmd: Option<(&str, &str)>) -> io::Result<()
 
 /// Formats the supplied `part` device with the file system specified.
 pub fn mkfs<P: AsRef<Path>>(part: P, kind: FileSystem) -> io::Result<()> {
+    mkfs_with(part, kind, &FormatOptions::default())
+}
+
+/// Formats the supplied `part` device with the file system specified, and the `options` which
+/// that file system supports.
+pub fn mkfs_with<P: AsRef<Path>>(
+    part: P,
+    kind: FileSystem,
+    options: &FormatOptions,
+) -> io::Result<()> {
     let (cmd, args): (&'static str, &'static [&'static str]) = match kind {
         Btrfs => ("mkfs.btrfs", &["-f"]),
         // Exfat => ("mkfs.exfat", &[]),
         Exfat => unimplemented!("exfat is not supported, yet"),
-        Ext2 => ("mkfs.ext2", &["-F", "-q"]),
-        Ext3 => ("mkfs.ext3", &["-F", "-q"]),
-        Ext4 => ("mkfs.ext4", &["-F", "-q", "-E", "lazy_itable_init"]),
+        Ext2 | Ext3 | Ext4 => {
+            let cmd = match kind {
+                Ext2 => "mkfs.ext2",
+                Ext3 => "mkfs.ext3",
+                _ => "mkfs.ext4",
+            };
+
+            let mut args: Vec<OsString> =
+                ext_args(kind, options).into_iter().map(OsString::from).collect();
+            args.push(part.as_ref().into());
+            return exec(cmd, None, None, &args);
+        }
         F2fs => ("mkfs.f2fs", &["-q", "-O", "extra_attr,inode_checksum,sb_checksum,compression"]),
-        Fat16 => ("mkfs.fat", &["-F", "16"]),
-        Fat32 => ("mkfs.fat", &["-F", "32"]),
+        Fat16 | Fat32 => {
+            let part = part.as_ref();
+            let size = blockdev_query(part, "--getsize64")?;
+            let sector_size = blockdev_query(part, "--getss")?;
+            let partuuid = capture(
+                "blkid",
+                &["-s".into(), "PARTUUID".into(), "-o".into(), "value".into(), part.into()],
+            )
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output).trim().to_owned());
+
+            let mut args: Vec<OsString> =
+                fat_args(kind, size, sector_size, partuuid.as_deref(), &options.fat)?
+                    .into_iter()
+                    .map(OsString::from)
+                    .collect();
+            args.push(part.into());
+            return exec("mkfs.fat", None, None, &args);
+        }
         Ntfs => ("mkfs.ntfs", &["-FQ", "-q"]),
         Swap => {
             if swap_exists(part.as_ref()) {
@@ -86,6 +115,10 @@ pub fn mkfs<P: AsRef<Path>>(part: P, kind: FileSystem) -> io::Result<()> {
         Luks | Lvm => return Ok(()),
     };
 
+    if options.has_ext_options() {
+        warn!("{} does not support ext format options, which will be ignored: {:?}", kind, options);
+    }
+
     exec(cmd, None, None, &{
         let mut args = args.iter().map(Into::into).collect::<Vec<OsString>>();
         args.push(part.as_ref().into());
@@ -93,18 +126,152 @@ pub fn mkfs<P: AsRef<Path>>(part: P, kind: FileSystem) -> io::Result<()> {
     })
 }
 
+/// The arguments of `mke2fs` for the given ext file system and options.
+fn ext_args(kind: FileSystem, options: &FormatOptions) -> Vec<String> {
+    let mut args = vec!["-F".to_owned(), "-q".to_owned()];
+    let mut extended = Vec::new();
+
+    if kind == Ext4 {
+        extended.push("lazy_itable_init".to_owned());
+    }
+
+    if let Some(percent) = options.reserved_blocks {
+        args.push("-m".into());
+        args.push(percent.to_string());
+    }
+
+    if let Some(ratio) = options.inode_ratio {
+        args.push("-i".into());
+        args.push(ratio.to_string());
+    }
+
+    let mut features = Vec::new();
+    if !options.quotas.is_empty() {
+        if kind == Ext4 {
+            features.push("quota".to_owned());
+            if options.quotas.contains(&Quota::Project) {
+                features.push("project".to_owned());
+            }
+
+            let types = options
+                .quotas
+                .iter()
+                .map(|quota| match quota {
+                    Quota::User => "usrquota",
+                    Quota::Group => "grpquota",
+                    Quota::Project => "prjquota",
+                })
+                .collect::<Vec<_>>();
+
+            extended.push(format!("quotatype={}", types.join(":")));
+        } else {
+            warn!("quotas require ext4, and will not be enabled on {}", kind);
+        }
+    }
+
+    if !options.features.is_empty() {
+        if kind == Ext4 {
+            features.extend(options.features.iter().map(|&(feature, enabled)| {
+                if enabled {
+                    feature.name().to_owned()
+                } else {
+                    ["^", feature.name()].concat()
+                }
+            }));
+        } else {
+            warn!("ext4 features cannot be set on {}: {:?}", kind, options.features);
+        }
+    }
+
+    if !features.is_empty() {
+        args.push("-O".into());
+        args.push(features.join(","));
+    }
+
+    // Only the last `-E` argument takes effect, so extended options are combined.
+    if !extended.is_empty() {
+        args.push("-E".into());
+        args.push(extended.join(","));
+    }
+
+    args
+}
+
+/// The arguments of `mkfs.fat`, for a file system of `size` bytes on a device with logical
+/// sectors of `sector_size` bytes.
+///
+/// The cluster size is chosen explicitly, as firmwares refuse to boot from ESPs with too few
+/// clusters for their FAT type. Unless one is given, the volume ID is derived from the GPT UUID
+/// of the partition, so that formatting the same partition always yields the same ID.
+fn fat_args(
+    kind: FileSystem,
+    size: u64,
+    sector_size: u64,
+    partuuid: Option<&str>,
+    options: &FatOptions,
+) -> io::Result<Vec<String>> {
+    let bits = if kind == Fat16 { "16" } else { "32" };
+    let cluster = fat_cluster_size(kind, size, sector_size).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} bytes is not a valid size for a FAT{} file system", size, bits),
+        )
+    })?;
+
+    let mut args = vec!["-F".to_owned(), bits.to_owned()];
+    args.push("-s".into());
+    args.push((cluster / sector_size).to_string());
+
+    let volume_id = options.volume_id.or_else(|| partuuid.and_then(volume_id_from_uuid));
+    if let Some(id) = volume_id {
+        args.push("-i".into());
+        args.push(format!("{:08X}", id));
+    }
+
+    if let Some(ref label) = options.label {
+        args.push("-n".into());
+        args.push(label.clone());
+    }
+
+    Ok(args)
+}
+
+/// Folds the hex digits of a UUID into a FAT volume ID.
+fn volume_id_from_uuid(uuid: &str) -> Option<u32> {
+    let digits: Vec<u32> = uuid.chars().filter_map(|c| c.to_digit(16)).collect();
+    if digits.len() != 32 {
+        return None;
+    }
+
+    let id = digits
+        .chunks(8)
+        .map(|chunk| chunk.iter().fold(0, |acc, &digit| (acc << 4) | digit))
+        .fold(0, |acc, word| acc ^ word);
+
+    Some(id)
+}
+
+/// Obtains a numeric property of a block device, such as its size, from `blockdev`.
+fn blockdev_query(device: &Path, query: &str) -> io::Result<u64> {
+    let output = capture("blockdev", &[query.into(), device.into()])?;
+    String::from_utf8_lossy(&output).trim().parse::<u64>().map_err(|why| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("blockdev {} gave an invalid number for {}: {}", query, device.display(), why),
+        )
+    })
+}
+
 /// Get the label from the given partition, if it exists.
 pub fn get_label<P: AsRef<Path>>(part: P, kind: FileSystem) -> Option<String> {
     let (cmd, args) = get_label_cmd(kind)?;
 
-    let output = Command::new(cmd)
-        .args(args)
-        .arg(part.as_ref())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .ok()?
-        .stdout;
+    let output = capture(cmd, &{
+        let mut args = args.iter().map(Into::into).collect::<Vec<OsString>>();
+        args.push(part.as_ref().into());
+        args
+    })
+    .ok()?;
 
     let output: String = String::from_utf8_lossy(&output).into();
 
@@ -139,10 +306,127 @@ fn get_label_cmd(kind: FileSystem) -> Option<(&'static str, &'static [&'static s
     Some(cmd)
 }
 
+/// Attaches a file to the first unused loop device, with partition scanning enabled.
+pub fn losetup_attach<P: AsRef<Path>>(file: P) -> io::Result<PathBuf> {
+    info!("attaching {} to a loop device", file.as_ref().display());
+    let args = &["--find".into(), "--show".into(), "--partscan".into(), file.as_ref().into()];
+    let output = runner().run("losetup", args, None, true)?;
+    let device = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+    if !output.success() || device.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("losetup failed to attach {}", file.as_ref().display()),
+        ));
+    }
+
+    Ok(PathBuf::from(device))
+}
+
+/// Dumps the partition table of a disk in the format of sfdisk, from which `sfdisk <disk>`
+/// restores it.
+pub fn sfdisk_dump<P: AsRef<Path>>(disk: P) -> io::Result<String> {
+    let args = &["--dump".into(), disk.as_ref().into()];
+    let output = runner().run("sfdisk", args, None, true)?;
+
+    if !output.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("sfdisk failed to dump the partition table of {}", disk.as_ref().display()),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Rewrites the backup GPT header, and its table, at the end of a disk, from the primary.
+pub fn sfdisk_relocate_gpt<P: AsRef<Path>>(disk: P) -> io::Result<()> {
+    exec(
+        "sfdisk",
+        None,
+        None,
+        &["--relocate".into(), "gpt-bak-std".into(), disk.as_ref().into()],
+    )
+}
+
+/// Detaches a loop device from its file.
+pub fn losetup_detach<P: AsRef<Path>>(device: P) -> io::Result<()> {
+    exec("losetup", None, None, &["--detach".into(), device.as_ref().into()])
+}
+
 pub fn remount_rw<P: AsRef<Path>>(path: P) -> io::Result<()> {
     exec("mount", None, None, &[path.as_ref().into(), "-o".into(), "remount,rw".into()])
 }
 
 fn swap_exists(path: &Path) -> bool {
-    Command::new("swaplabel").arg(path).status().ok().map_or(false, |stat| stat.success())
+    status("swaplabel", &[path.into()]).ok().and_then(|code| code).map_or(false, |code| code == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disk_types::Ext4Feature;
+
+    #[test]
+    fn ext_format_options() {
+        assert_eq!(ext_args(Ext2, &FormatOptions::default()), vec!["-F", "-q"]);
+        assert_eq!(
+            ext_args(Ext4, &FormatOptions::default()),
+            vec!["-F", "-q", "-E", "lazy_itable_init"]
+        );
+
+        let options = FormatOptions {
+            reserved_blocks: Some(1),
+            inode_ratio:     Some(65536),
+            quotas:          vec![Quota::User, Quota::Group, Quota::Project],
+            ..FormatOptions::default()
+        };
+
+        assert_eq!(
+            ext_args(Ext4, &options),
+            vec![
+                "-F",
+                "-q",
+                "-m",
+                "1",
+                "-i",
+                "65536",
+                "-O",
+                "quota,project",
+                "-E",
+                "lazy_itable_init,quotatype=usrquota:grpquota:prjquota",
+            ]
+        );
+
+        assert_eq!(ext_args(Ext3, &options), vec!["-F", "-q", "-m", "1", "-i", "65536"]);
+
+        let mut options = FormatOptions::default();
+        options.set_feature(Ext4Feature::Bit64, false);
+        options.set_feature(Ext4Feature::Casefold, true);
+        assert_eq!(
+            ext_args(Ext4, &options),
+            vec!["-F", "-q", "-O", "^64bit,casefold", "-E", "lazy_itable_init"]
+        );
+    }
+
+    #[test]
+    fn fat_format_args() {
+        const MIB: u64 = 1024 * 1024;
+        let uuid = Some("0a1b2c3d-0000-0000-0000-000000000001");
+        let options = FatOptions { label: Some("EFI".into()), volume_id: None };
+
+        assert_eq!(
+            fat_args(Fat32, 512 * MIB, 512, uuid, &options).unwrap(),
+            vec!["-F", "32", "-s", "8", "-i", "0A1B2C3C", "-n", "EFI"]
+        );
+
+        let options = FatOptions { label: None, volume_id: Some(0xDEAD_BEEF) };
+        assert_eq!(
+            fat_args(Fat32, 512 * MIB, 4096, None, &options).unwrap(),
+            vec!["-F", "32", "-s", "1", "-i", "DEADBEEF"]
+        );
+
+        assert!(fat_args(Fat32, 256 * MIB, 4096, None, &options).is_err());
+        assert_eq!(volume_id_from_uuid("not a uuid"), None);
+    }
 }
@@ -0,0 +1,70 @@
+//! Configuration of Btrfs file systems once they have been created and mounted.
+
+use super::exec;
+use std::{fs, io, os::unix::fs::MetadataExt, path::Path};
+
+/// The inode of the root directory of every Btrfs subvolume.
+const SUBVOLUME_INODE: u64 = 256;
+
+/// Enables quota groups on the Btrfs file system mounted at `mount`.
+pub fn btrfs_quota_enable(mount: &Path) -> io::Result<()> {
+    exec("btrfs", None, None, &["quota".into(), "enable".into(), mount.into()])
+}
+
+/// Creates a subvolume at `path`.
+pub fn btrfs_subvolume_create(path: &Path) -> io::Result<()> {
+    exec("btrfs", None, None, &["subvolume".into(), "create".into(), path.into()])
+}
+
+/// Whether `path` is the root of a Btrfs subvolume.
+pub fn btrfs_is_subvolume(path: &Path) -> io::Result<bool> {
+    Ok(fs::symlink_metadata(path)?.ino() == SUBVOLUME_INODE)
+}
+
+/// Creates a subvolume at `path`, in which files are created with copy-on-write disabled,
+/// unless a subvolume already exists there.
+pub fn btrfs_nodatacow_subvolume(path: &Path) -> io::Result<()> {
+    match btrfs_is_subvolume(path) {
+        Ok(true) => info!("{} is already a subvolume", path.display()),
+        Ok(false) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists, and is not a subvolume", path.display()),
+            ))
+        }
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            btrfs_subvolume_create(path)?;
+        }
+        Err(why) => return Err(why),
+    }
+
+    // Copy-on-write may only be disabled for files which are empty, so the attribute is set
+    // on the directory, and inherited by the files which are created within it.
+    exec("chattr", None, None, &["+C".into(), path.into()])
+}
+
+/// Rewrites the files of the Btrfs file system mounted at `path` compressed with zstd at
+/// `level`, without descending into other file systems which are mounted beneath it.
+///
+/// The defragmentation compresses at the level of the mount, so the file system is remounted
+/// with it first, which also compresses the files that are written to it afterwards.
+pub fn btrfs_defragment_compress(path: &Path, level: u8) -> io::Result<()> {
+    let options = format!("remount,compress=zstd:{}", level);
+    exec("mount", None, None, &["-o".into(), options.into(), path.into()])?;
+    exec(
+        "btrfs",
+        None,
+        None,
+        &["filesystem".into(), "defragment".into(), "-r".into(), "-czstd".into(), path.into()],
+    )
+}
+
+/// Commits the pending changes of the Btrfs file system mounted at `mount`, so that its usage
+/// is reported accurately.
+pub fn btrfs_sync(mount: &Path) -> io::Result<()> {
+    exec("btrfs", None, None, &["filesystem".into(), "sync".into(), mount.into()])
+}
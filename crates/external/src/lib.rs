@@ -4,6 +4,7 @@ extern crate disk_types;
 extern crate distinst_utils as misc;
 #[macro_use]
 extern crate log;
+extern crate once_cell;
 extern crate proc_mounts;
 extern crate rand;
 #[macro_use]
@@ -11,18 +12,17 @@ extern crate smart_default;
 extern crate sys_mount;
 extern crate tempdir;
 
+pub mod automount;
 pub mod block;
+pub mod btrfs;
 pub mod luks;
 pub mod lvm;
 pub(crate) mod retry;
 
-pub use self::{block::*, luks::*, lvm::*};
+pub use self::{automount::*, block::*, btrfs::*, luks::*, lvm::*};
+pub use misc::runner::{self, *};
 
-use std::{
-    ffi::OsString,
-    io::{self, Write},
-    process::{Command, Stdio},
-};
+use std::{ffi::OsString, io};
 
 /// A generic function for executing a variety of external commands.
 pub fn exec(
@@ -33,20 +33,10 @@ pub fn exec(
 ) -> io::Result<()> {
     info!("executing {} with {:?}", cmd, args);
 
-    let mut child = Command::new(cmd)
-        .args(args)
-        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
-        .stdout(Stdio::null())
-        .spawn()?;
-
-    if let Some(stdin) = stdin {
-        child.stdin.as_mut().expect("stdin not obtained").write_all(stdin)?;
-    }
-
-    let status = child.wait()?;
-    let success = status.success()
+    let output = runner::runner().run(cmd, args, stdin, false)?;
+    let success = output.success()
         || valid_codes
-            .map_or(false, |codes| status.code().map_or(false, |code| codes.contains(&code)));
+            .map_or(false, |codes| output.code.map_or(false, |code| codes.contains(&code)));
 
     if success {
         Ok(())
@@ -56,7 +46,7 @@ pub fn exec(
             format!(
                 "{} failed with status: {}",
                 cmd,
-                match status.code() {
+                match output.code {
                     Some(code) => format!("{} ({})", code, io::Error::from_raw_os_error(code)),
                     None => "unknown".into(),
                 }
@@ -65,4 +55,92 @@ pub fn exec(
     }
 }
 
+/// Executes a command, and captures its standard output regardless of its exit status.
+pub(crate) fn capture(cmd: &str, args: &[OsString]) -> io::Result<Vec<u8>> {
+    runner::runner().run(cmd, args, None, true).map(|output| output.stdout)
+}
+
+/// Executes a command, and obtains its exit code.
+pub(crate) fn status(cmd: &str, args: &[OsString]) -> io::Result<Option<i32>> {
+    runner::runner().run(cmd, args, None, false).map(|output| output.code)
+}
+
+/// Downloads the document at `url`, giving up after `timeout` seconds.
+pub fn fetch(url: &str, timeout: u32) -> io::Result<Vec<u8>> {
+    info!("fetching {}", url);
+    let args = ["-fsSL".into(), "--max-time".into(), timeout.to_string().into(), url.into()];
+    let output = runner::runner().run("curl", &args, None, true)?;
+    if output.success() {
+        Ok(output.stdout)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("unable to fetch {}: curl exited with {:?}", url, output.code),
+        ))
+    }
+}
+
+/// Posts a JSON document to `url`, giving up after `timeout` seconds.
+pub fn post_json(url: &str, body: &[u8], timeout: u32) -> io::Result<()> {
+    info!("posting to {}", url);
+    let args = [
+        "-fsS".into(),
+        "--max-time".into(),
+        timeout.to_string().into(),
+        "-H".into(),
+        "Content-Type: application/json".into(),
+        "--data-binary".into(),
+        "@-".into(),
+        url.into(),
+    ];
+
+    let output = runner::runner().run("curl", &args, Some(body), false)?;
+    if output.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("unable to post to {}: curl exited with {:?}", url, output.code),
+        ))
+    }
+}
+
 fn mebibytes(bytes: u64) -> String { format!("{}", bytes / (1024 * 1024)) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disk_types::FileSystem;
+    use std::sync::Arc;
+
+    #[test]
+    fn mock_runner() {
+        let mock = Arc::new(MockRunner::default());
+        mock.respond("cryptsetup", CommandOutput { code: Some(4), ..CommandOutput::default() });
+        let previous = set_runner(mock.clone());
+
+        let formatted = mkfs("/dev/sdz1", FileSystem::Ext4);
+        let closed = cryptsetup_close(CloseBy::Name("cryptdata"));
+        let status = exec("cryptsetup", None, None, &["status".into(), "cryptdata".into()]);
+        set_runner(previous);
+
+        assert!(formatted.is_ok());
+        assert!(closed.is_ok());
+        assert!(status.is_err());
+
+        let command = |cmd: &str, args: &[&str]| ExecutedCommand {
+            cmd:   cmd.into(),
+            args:  args.iter().map(|&arg| arg.into()).collect(),
+            stdin: None,
+        };
+
+        assert_eq!(
+            mock.executed(),
+            vec![
+                command("mkfs.ext4", &["-F", "-q", "-E", "lazy_itable_init", "/dev/sdz1"]),
+                command("cryptsetup", &["close", "cryptdata"]),
+                command("cryptsetup", &["status", "cryptdata"]),
+            ]
+        );
+    }
+}
@@ -1,26 +1,39 @@
 use super::*;
 use std::{
-    io::{self, BufRead, BufReader},
+    ffi::OsString,
+    fs::Permissions,
+    io::{self, BufRead, Write},
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
     thread,
     time::Duration,
 };
+use tempdir::TempDir;
+
+/// Receives the phases of the cryptsetup commands which format and open LUKS volumes. It is
+/// shared by the volumes which are formatted in parallel.
+pub type LuksObserver<'a> = &'a (dyn Fn(&Path, LuksPhase) + Sync);
+
+/// What a cryptsetup command is doing, as the key derivation of LUKS2 is benchmarked for
+/// seconds, and with much memory, before a volume is formatted, and is repeated each time that
+/// a volume is opened, so that slow devices may seem to hang.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LuksPhase {
+    /// Measuring the key derivation, so that unlocking takes as long as cryptsetup intends.
+    Benchmarking,
+    /// Writing the header and key slots of a new volume.
+    Formatting,
+    /// Deriving the key from the passphrase, and mapping the volume.
+    Opening,
+}
 
 /// Get a vector of encrypted devices
 pub fn encrypted_devices() -> io::Result<Vec<String>> {
     let mut current_line = String::with_capacity(64);
     let mut output = Vec::new();
 
-    let mut reader = BufReader::new(
-        Command::new("dmsetup")
-            .args(&["ls", "--target", "crypt"])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?
-            .stdout
-            .expect("failed to execute dmsetup command"),
-    );
+    let stdout = capture("dmsetup", &["ls".into(), "--target".into(), "crypt".into()])?;
+    let mut reader = stdout.as_slice();
 
     reader.read_line(&mut current_line)?;
     if current_line.starts_with("No devices found") {
@@ -54,14 +67,9 @@ pub fn encrypted_devices() -> io::Result<Vec<String>> {
 pub fn is_encrypted(device: &Path) -> bool {
     let mut attempts = 0;
     loop {
-        let res = Command::new("cryptsetup")
-            .stdout(Stdio::null())
-            .arg("luksDump")
-            .arg(device)
-            .status()
-            .ok();
-
-        match res.and_then(|stat| stat.code()) {
+        let res = status("cryptsetup", &["luksDump".into(), device.into()]).ok();
+
+        match res.and_then(|code| code) {
             Some(0) => return true,
             // An exit status of 4 can happen if the partition is scanned too hastily.
             Some(4) => {
@@ -94,6 +102,205 @@ pub fn cryptsetup_close(device: CloseBy) -> io::Result<()> {
     exec("cryptsetup", None, Some(&[4]), args)
 }
 
+/// Adds `key` to the given key slot of a LUKS device, which is unlocked with `password`.
+///
+/// The key is passed to cryptsetup through a file which only root may read, and which is
+/// removed once the key has been added.
+pub fn cryptsetup_add_key(
+    device: &Path,
+    password: &str,
+    key_slot: u32,
+    key: &str,
+) -> io::Result<()> {
+    let tempdir = TempDir::new("distinst")?;
+    let keyfile = tempdir.path().join("key");
+    let mut file = misc::create(&keyfile)?;
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(key.as_bytes())?;
+    file.sync_all()?;
+
+    let mut stdin = password.as_bytes().to_owned();
+    stdin.push(b'\n');
+
+    exec(
+        "cryptsetup",
+        Some(&stdin),
+        None,
+        &[
+            "luksAddKey".into(),
+            "--key-slot".into(),
+            key_slot.to_string().into(),
+            device.into(),
+            keyfile.into(),
+        ],
+    )
+}
+
+/// Adds the key which `keyfile` holds to a free key slot of a LUKS device, which is unlocked
+/// with `password`.
+pub fn cryptsetup_add_keyfile(device: &Path, password: &str, keyfile: &Path) -> io::Result<()> {
+    let mut stdin = password.as_bytes().to_owned();
+    stdin.push(b'\n');
+
+    exec("cryptsetup", Some(&stdin), None, &["luksAddKey".into(), device.into(), keyfile.into()])
+}
+
+/// Writes the dm-verity hash tree of the `data` device to the `hash` device, and returns the
+/// root hash, which the data is verified against when it is opened.
+pub fn veritysetup_format(data: &Path, hash: &Path) -> io::Result<String> {
+    let args = &["format".into(), data.into(), hash.into()];
+    info!("executing veritysetup with {:?}", args);
+
+    let output = runner::runner().run("veritysetup", args, None, true)?;
+    if !output.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("veritysetup failed with status: {:?}", output.code),
+        ));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("Root hash:").map(|hash| hash.trim().to_owned()))
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "veritysetup did not report a root hash")
+        })
+}
+
+/// Whether `device` has a LUKS header. Errors other than the device not being LUKS, such as
+/// it being inaccessible, are returned.
+pub fn cryptsetup_is_luks(device: &Path) -> io::Result<bool> {
+    let args = &["isLuks".into(), device.into()];
+    let output = runner::runner().run("cryptsetup", args, None, true)?;
+    match output.code {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        code => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("unable to check {} for a LUKS header: status {:?}", device.display(), code),
+        )),
+    }
+}
+
+/// The header of a LUKS device, as `cryptsetup luksDump` describes it.
+pub fn cryptsetup_dump(device: &Path) -> io::Result<String> {
+    let args = &["luksDump".into(), device.into()];
+    let output = runner::runner().run("cryptsetup", args, None, true)?;
+    if !output.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} does not have a LUKS header", device.display()),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runs `cryptsetup reencrypt` on `device` with `args`, unlocking it with `password`, or
+/// encrypting it with `password` if `args` contains `--encrypt`.
+///
+/// Each percentage of progress which cryptsetup reports is given to `progress`. A reencryption
+/// which is interrupted may be resumed by running it again with `--resume-only`.
+pub fn cryptsetup_reencrypt<F: FnMut(u8)>(
+    device: &Path,
+    password: &str,
+    args: &[OsString],
+    mut progress: F,
+) -> io::Result<()> {
+    let mut args = args.to_vec();
+    args.insert(0, "reencrypt".into());
+    args.extend_from_slice(&["--progress-frequency".into(), "1".into(), device.into()]);
+    info!("executing cryptsetup with {:?}", args);
+
+    let mut stdin = password.as_bytes().to_vec();
+    stdin.push(b'\n');
+
+    let output = runner::runner().run_lines("cryptsetup", &args, Some(&stdin), &mut |line| {
+        if let Some(percent) = reencrypt_progress(line) {
+            progress(percent);
+        }
+    })?;
+
+    if output.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "cryptsetup reencrypt failed with status {:?}: {}",
+                output.code,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+/// Runs cryptsetup on `device`, beginning in `phase`, and reports each phase that it enters to
+/// `observer`. The phases are read from cryptsetup's debug messages, and its errors are
+/// returned with the status that it exits with.
+pub fn cryptsetup_phased(
+    device: &Path,
+    phase: LuksPhase,
+    stdin: Option<&[u8]>,
+    args: &[OsString],
+    observer: LuksObserver,
+) -> io::Result<()> {
+    let mut args = args.to_vec();
+    args.insert(0, "--debug".into());
+    info!("executing cryptsetup with {:?}", args);
+
+    let notify = |phase: LuksPhase| {
+        info!("{}: {:?}", device.display(), phase);
+        observer(device, phase);
+    };
+
+    notify(phase);
+    let mut current = phase;
+    let output = runner::runner().run_lines("cryptsetup", &args, stdin, &mut |line| {
+        match debug_phase(line) {
+            Some(next) if next != current => {
+                current = next;
+                notify(next);
+            }
+            _ => (),
+        }
+    })?;
+
+    if output.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "cryptsetup failed with status {:?}: {}",
+                output.code,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+/// The phase which a debug message of cryptsetup begins, such as
+/// `# Running argon2id() benchmark.`
+fn debug_phase(line: &str) -> Option<LuksPhase> {
+    let message = line.strip_prefix('#')?.trim();
+    if message.starts_with("Running") && message.ends_with("benchmark.") {
+        Some(LuksPhase::Benchmarking)
+    } else if message.starts_with("Benchmark returns") {
+        Some(LuksPhase::Formatting)
+    } else {
+        None
+    }
+}
+
+/// The percentage of a progress line of `cryptsetup reencrypt`, such as
+/// `Progress:  42.1%, ETA 01:23, 1024 MiB written, speed 100.0 MiB/s`.
+fn reencrypt_progress(line: &str) -> Option<u8> {
+    let percent = line.trim().strip_prefix("Progress:")?.split(',').next()?;
+    let percent = percent.trim().strip_suffix('%')?.parse::<f32>().ok()?;
+    Some(percent.max(0.0).min(100.0) as u8)
+}
+
 /// Deactivate all logical devies found on the system.
 pub fn deactivate_logical_devices() -> io::Result<()> {
     let mut res = Ok(());
@@ -119,3 +326,31 @@ pub fn deactivate_logical_devices() -> io::Result<()> {
 
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reencrypt_progress_lines() {
+        let line = "Progress:  42.1%, ETA 01:23, 1024 MiB written, speed 100.0 MiB/s";
+        assert_eq!(reencrypt_progress(line), Some(42));
+        assert_eq!(reencrypt_progress("Progress: 100.0%, ETA 00:00, 20480 MiB written"), Some(100));
+        assert_eq!(reencrypt_progress("Finished, time 03:21.004, 20480 MiB written"), None);
+        assert_eq!(reencrypt_progress(""), None);
+    }
+
+    #[test]
+    fn debug_phases() {
+        assert_eq!(debug_phase("# Running argon2id() benchmark."), Some(LuksPhase::Benchmarking));
+        assert_eq!(
+            debug_phase(
+                "# Benchmark returns argon2id() 4 iterations, 1048576 memory, 4 threads \
+                 (for 512-bits key)."
+            ),
+            Some(LuksPhase::Formatting)
+        );
+        assert_eq!(debug_phase("# Formatting LUKS2 device /dev/sda3."), None);
+        assert_eq!(debug_phase("Running argon2id() benchmark."), None);
+    }
+}
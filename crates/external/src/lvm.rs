@@ -1,19 +1,19 @@
 use super::*;
-use misc::{concat_osstr, device_maps, read_dirs};
-use proc_mounts::{MOUNTS, SWAPS};
+use misc::{concat_osstr, device_maps, namespace::THREAD_MOUNTS, read_dirs};
+use proc_mounts::{MountList, SWAPS};
 use rand::{self, distributions::Alphanumeric, Rng};
 use std::{
     collections::BTreeMap,
     ffi::OsStr,
     fs::read_link,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
 };
 use sys_mount::{swapoff, unmount, UnmountFlags};
 
 pub fn deactivate_devices<P: AsRef<Path>>(devices: &[P]) -> io::Result<()> {
-    let mounts = MOUNTS.read().expect("failed to get mounts in deactivate_devices");
+    let mounts = MountList::new_from_file(THREAD_MOUNTS)
+        .expect("failed to get mounts in deactivate_devices");
     let swaps = SWAPS.read().expect("failed to get swaps in deactivate_devices");
     let umount = move |vg: &str| -> io::Result<()> {
         for lv in lvs(vg)? {
@@ -81,15 +81,8 @@ pub fn dmlist() -> io::Result<Vec<String>> {
     let mut current_line = String::with_capacity(64);
     let mut output = Vec::new();
 
-    let mut reader = BufReader::new(
-        Command::new("dmsetup")
-            .arg("ls")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?
-            .stdout
-            .expect("failed to execute dmsetup command"),
-    );
+    let stdout = capture("dmsetup", &["ls".into()])?;
+    let mut reader = stdout.as_slice();
 
     // Parse the output of `dmsetup ls`, only taking the first field from each line.
     while reader.read_line(&mut current_line)? != 0 {
@@ -160,15 +153,8 @@ pub fn lvs(vg: &str) -> io::Result<Vec<PathBuf>> {
     let mut current_line = String::with_capacity(128);
     let mut output = Vec::new();
 
-    let mut reader = BufReader::new(
-        Command::new("lvs")
-            .arg(vg)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?
-            .stdout
-            .expect("failed to execute lvs command"),
-    );
+    let stdout = capture("lvs", &[vg.into()])?;
+    let mut reader = stdout.as_slice();
 
     // Skip the first line of output
     let _ = reader.read_line(&mut current_line);
@@ -210,14 +196,8 @@ pub fn pvs() -> io::Result<BTreeMap<PathBuf, Option<String>>> {
     let mut current_line = String::with_capacity(64);
     let mut output = BTreeMap::new();
 
-    let mut reader = BufReader::new(
-        Command::new("pvs")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?
-            .stdout
-            .expect("failed to execute pvs command"),
-    );
+    let stdout = capture("pvs", &[])?;
+    let mut reader = stdout.as_slice();
 
     // Skip the first line of output
     let _ = reader.read_line(&mut current_line);
@@ -273,15 +253,8 @@ fn vgdisplay() -> io::Result<Vec<String>> {
     let mut current_line = String::with_capacity(64);
     let mut output = Vec::new();
 
-    let mut reader = BufReader::new(
-        Command::new("vgdisplay")
-            .arg("-s")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()?
-            .stdout
-            .expect("failed to execute vgdisplay command"),
-    );
+    let stdout = capture("vgdisplay", &["-s".into()])?;
+    let mut reader = stdout.as_slice();
 
     while reader.read_line(&mut current_line)? != 0 {
         if let Some(dm) = current_line.split_whitespace().next() {
@@ -1,5 +1,6 @@
 
 use partition_identity::{PartitionID, PartitionSource};
+use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
 use disk_types::FileSystem;
@@ -11,17 +12,18 @@ pub struct BlockInfo<'a> {
     pub uid:     PartitionID,
     mount:       Option<PathBuf>,
     pub fs:      &'static str,
-    pub options: &'a str,
+    pub options: Cow<'a, str>,
     pub dump:    bool,
-    pub pass:    bool,
+    /// The order in which fsck checks the file system at boot, or `0` if it is not checked.
+    pub pass:    u8,
 }
 
 impl<'a> BlockInfo<'a> {
-    pub fn new(
+    pub fn new<O: Into<Cow<'a, str>>>(
         uid: PartitionID,
         fs: FileSystem,
         target: Option<&Path>,
-        options: &'a str,
+        options: O,
     ) -> Self {
         BlockInfo {
             uid,
@@ -35,9 +37,9 @@ impl<'a> BlockInfo<'a> {
                 FileSystem::Swap => "swap",
                 _ => fs.into(),
             },
-            options,
+            options: options.into(),
             dump: false,
-            pass: false,
+            pass: fsck_pass(fs, target),
         }
     }
 
@@ -59,11 +61,11 @@ impl<'a> BlockInfo<'a> {
         fstab.push("  ");
         fstab.push(&self.fs);
         fstab.push("  ");
-        fstab.push(&self.options);
+        fstab.push(&*self.options);
         fstab.push("  ");
         fstab.push(if self.dump { "1" } else { "0" });
         fstab.push("  ");
-        fstab.push(if self.pass { "1" } else { "0" });
+        fstab.push(self.pass.to_string());
         fstab.push("\n");
     }
 
@@ -87,6 +89,25 @@ impl<'a> BlockInfo<'a> {
     }
 }
 
+/// The root is checked first, and then the other file systems that fsck checks. Swap, and file
+/// systems such as Btrfs and XFS, which check themselves when mounted, are not checked.
+fn fsck_pass(fs: FileSystem, target: Option<&Path>) -> u8 {
+    match fs {
+        FileSystem::Ext2
+        | FileSystem::Ext3
+        | FileSystem::Ext4
+        | FileSystem::Fat16
+        | FileSystem::Fat32 => {
+            if target == Some(Path::new("/")) {
+                1
+            } else {
+                2
+            }
+        }
+        _ => 0,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,8 +130,8 @@ mod tests {
         assert_eq!(
             *fstab,
             OsString::from(r#"UUID=SWAP  none  swap  sw  0  0
-PARTUUID=EFI  /boot/efi  vfat  defaults  0  0
-UUID=ROOT  /  ext4  defaults  0  0
+PARTUUID=EFI  /boot/efi  vfat  defaults  0  2
+UUID=ROOT  /  ext4  defaults  0  1
 "#)
         );
     }
@@ -131,9 +152,9 @@ UUID=ROOT  /  ext4  defaults  0  0
                 },
                 mount: None,
                 fs: "swap",
-                options: "sw",
+                options: "sw".into(),
                 dump: false,
-                pass: false,
+                pass: 0,
             }
         );
         assert_eq!(swap.mount(), OsStr::new("none"));
@@ -155,9 +176,9 @@ UUID=ROOT  /  ext4  defaults  0  0
                 },
                 mount: Some(PathBuf::from("/boot/efi")),
                 fs: "vfat",
-                options: "defaults",
+                options: "defaults".into(),
                 dump: false,
-                pass: false,
+                pass: 2,
             }
         );
         assert_eq!(efi.mount(), OsStr::new("/boot/efi"));
@@ -179,9 +200,9 @@ UUID=ROOT  /  ext4  defaults  0  0
                 },
                 mount: Some(PathBuf::from("/")),
                 fs: FileSystem::Ext4.into(),
-                options: "defaults",
+                options: "defaults".into(),
                 dump: false,
-                pass: false,
+                pass: 1,
             }
         );
         assert_eq!(root.mount(), OsStr::new("/"));
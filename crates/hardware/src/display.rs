@@ -0,0 +1,125 @@
+//! Detection of HiDPI panels, from the EDID of the connected displays.
+
+use std::{fs, path::Path};
+
+/// The minimum density, in dots per inch, at which a panel is scaled twice over. This is the
+/// threshold which GNOME uses.
+const HIDPI_THRESHOLD: f64 = 192.0;
+
+/// The minimum height, in pixels, of a panel which is scaled, so that small displays keep a
+/// usable amount of space.
+const HIDPI_MIN_HEIGHT: u32 = 1200;
+
+/// The preferred mode and physical size of a display, from its EDID.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Panel {
+    pub width_px:  u32,
+    pub height_px: u32,
+    pub width_mm:  u32,
+    pub height_mm: u32,
+}
+
+impl Panel {
+    /// Parses the first detailed timing descriptor of an EDID, which is its preferred mode.
+    pub fn from_edid(edid: &[u8]) -> Option<Panel> {
+        const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+        if edid.len() < 128 || edid[..8] != HEADER {
+            return None;
+        }
+
+        let dtd = &edid[54..72];
+        if dtd[0] == 0 && dtd[1] == 0 {
+            return None;
+        }
+
+        let panel = Panel {
+            width_px:  u32::from(dtd[2]) | (u32::from(dtd[4] & 0xF0) << 4),
+            height_px: u32::from(dtd[5]) | (u32::from(dtd[7] & 0xF0) << 4),
+            width_mm:  u32::from(dtd[12]) | (u32::from(dtd[14] & 0xF0) << 4),
+            height_mm: u32::from(dtd[13]) | (u32::from(dtd[14] & 0x0F) << 8),
+        };
+
+        // Projectors, and some panels, report no physical size.
+        if panel.width_mm == 0 || panel.height_mm == 0 {
+            return None;
+        }
+
+        Some(panel)
+    }
+
+    /// The horizontal density of the panel, in dots per inch.
+    pub fn dpi(&self) -> f64 { f64::from(self.width_px) / (f64::from(self.width_mm) / 25.4) }
+
+    /// The integer factor which the desktop should be scaled by.
+    pub fn scale_factor(&self) -> u32 {
+        if self.dpi() >= HIDPI_THRESHOLD && self.height_px >= HIDPI_MIN_HEIGHT {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// The connected displays. Internal panels, such as those of laptops, are listed first.
+pub fn panels() -> Vec<Panel> {
+    let mut connectors: Vec<(bool, Panel)> = fs::read_dir("/sys/class/drm")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let connector = name.splitn(2, '-').nth(1)?;
+            let internal = ["eDP", "LVDS", "DSI"].iter().any(|kind| connector.starts_with(kind));
+            let panel = connected_panel(&entry.path())?;
+            Some((internal, panel))
+        })
+        .collect();
+
+    connectors.sort_by_key(|&(internal, _)| !internal);
+    connectors.into_iter().map(|(_, panel)| panel).collect()
+}
+
+/// The factor which the desktop should be scaled by, for the first internal panel, or else the
+/// first display.
+pub fn scale_factor() -> u32 {
+    let factor = panels().first().map_or(1, Panel::scale_factor);
+    info!("detected a display scale factor of {}", factor);
+    factor
+}
+
+fn connected_panel(connector: &Path) -> Option<Panel> {
+    let status = fs::read_to_string(connector.join("status")).ok()?;
+    if status.trim() != "connected" {
+        return None;
+    }
+
+    Panel::from_edid(&fs::read(connector.join("edid")).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edid_panel() {
+        let mut edid = vec![0u8; 128];
+        edid[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+        // A 3840x2400 panel, 345mm by 215mm, as on a 16-inch laptop.
+        edid[54..72].copy_from_slice(&[
+            0x1A, 0x36, 0x00, 0x80, 0xF0, 0x60, 0x00, 0x90, 0, 0, 0, 0, 0x59, 0xD7, 0x10, 0, 0, 0,
+        ]);
+
+        let panel = Panel::from_edid(&edid).unwrap();
+        assert_eq!(
+            panel,
+            Panel { width_px: 3840, height_px: 2400, width_mm: 345, height_mm: 215 }
+        );
+        assert_eq!(panel.scale_factor(), 2);
+
+        let panel = Panel { width_px: 1920, height_px: 1080, ..panel };
+        assert_eq!(panel.scale_factor(), 1);
+
+        edid[0] = 0xFF;
+        assert_eq!(Panel::from_edid(&edid), None);
+    }
+}
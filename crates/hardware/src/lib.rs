@@ -11,7 +11,9 @@ use os_release::OsRelease;
 use raw_cpuid::CpuId;
 use std::io::Read;
 
+pub mod display;
 pub mod switchable_graphics;
+pub mod virtualization;
 #[macro_use]
 mod macros;
 
@@ -101,10 +103,29 @@ fn vendor_support(os_release: &OsRelease) -> Option<&'static str> {
     None
 }
 
-fn vendor() -> Option<String> {
-    let mut vendor = String::new();
-    misc::open("/sys/class/dmi/id/sys_vendor")
-        .and_then(|mut file| file.read_to_string(&mut vendor))
+/// Kernel flavors which enable specific hardware, as `(sys_vendor, product_name, flavor)`,
+/// where the product name is matched by its prefix.
+// NOTE: Vendors whose hardware is enabled by a kernel flavor should add it here.
+const KERNEL_FLAVORS: &[(&str, &str, &str)] = &[("Dell Inc.", "XPS 13 93", "oem")];
+
+/// The kernel flavor, such as `oem`, which this hardware should boot by default, if any.
+pub fn kernel_flavor() -> Option<&'static str> {
+    let vendor = vendor()?;
+    let product = dmi("product_name")?;
+    KERNEL_FLAVORS
+        .iter()
+        .find(|&&(sys_vendor, product_name, _)| {
+            vendor.trim() == sys_vendor && product.trim().starts_with(product_name)
+        })
+        .map(|&(_, _, flavor)| flavor)
+}
+
+fn vendor() -> Option<String> { dmi("sys_vendor") }
+
+fn dmi(field: &str) -> Option<String> {
+    let mut value = String::new();
+    misc::open(&["/sys/class/dmi/id/", field].concat())
+        .and_then(|mut file| file.read_to_string(&mut value))
         .ok()
-        .map(|_| vendor)
+        .map(|_| value)
 }
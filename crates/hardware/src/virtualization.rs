@@ -0,0 +1,125 @@
+//! Detection of the hypervisor which the installer runs within, whose guest agents and drivers
+//! are installed alongside the system, so that the guest resizes its display, shares its
+//! clipboard, and shuts down cleanly when the host asks it to.
+
+use os_release::OsRelease;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use raw_cpuid::{CpuId, Hypervisor as CpuIdHypervisor};
+
+/// A hypervisor for which guest tools are available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hypervisor {
+    Kvm,
+    VMware,
+    VirtualBox,
+    HyperV,
+}
+
+impl Hypervisor {
+    /// The hypervisor that this system is a guest of, if any. The DMI tables name the virtual
+    /// hardware, and VirtualBox in particular is only known by them, as it presents the CPUID
+    /// leaves of KVM or Hyper-V to its guests.
+    pub fn detect() -> Option<Hypervisor> {
+        let product = super::dmi("product_name").unwrap_or_default();
+        super::vendor().and_then(|vendor| from_dmi(&vendor, &product)).or_else(from_cpuid)
+    }
+
+    /// The guest agents, and drivers, for this hypervisor.
+    // NOTE: Distributions should provide the guest tools for each hypervisor here.
+    pub fn guest_packages(self, os_release: &OsRelease) -> &'static [&'static str] {
+        let debian_like = os_release.id == "debian"
+            || os_release.id_like.split_whitespace().any(|like| like == "debian");
+        if !debian_like {
+            return &[];
+        }
+
+        match self {
+            Hypervisor::Kvm => &["qemu-guest-agent", "spice-vdagent"],
+            Hypervisor::VMware => &["open-vm-tools", "open-vm-tools-desktop"],
+            Hypervisor::VirtualBox if &os_release.id == "debian" => &[],
+            Hypervisor::VirtualBox => &["virtualbox-guest-utils", "virtualbox-guest-x11"],
+            Hypervisor::HyperV if &os_release.id == "debian" => &["hyperv-daemons"],
+            Hypervisor::HyperV => &["linux-cloud-tools-virtual"],
+        }
+    }
+
+    /// Kernel options which mirror the console to the serial port, which is how the guests of
+    /// KVM and Hyper-V are administered when they run headless. The console of the display is
+    /// given last, so that it remains the console of the system.
+    pub fn kernel_options(self) -> Option<&'static str> {
+        match self {
+            Hypervisor::Kvm | Hypervisor::HyperV => Some("console=ttyS0,115200n8 console=tty0"),
+            Hypervisor::VMware | Hypervisor::VirtualBox => None,
+        }
+    }
+}
+
+fn from_dmi(vendor: &str, product: &str) -> Option<Hypervisor> {
+    match (vendor.trim(), product.trim()) {
+        ("QEMU", _) => Some(Hypervisor::Kvm),
+        (_, product) if product.starts_with("KVM") => Some(Hypervisor::Kvm),
+        ("VMware, Inc.", _) => Some(Hypervisor::VMware),
+        ("innotek GmbH", _) | (_, "VirtualBox") => Some(Hypervisor::VirtualBox),
+        ("Microsoft Corporation", "Virtual Machine") => Some(Hypervisor::HyperV),
+        _ => None,
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn from_cpuid() -> Option<Hypervisor> {
+    match CpuId::new().get_hypervisor_info()?.identify() {
+        CpuIdHypervisor::KVM => Some(Hypervisor::Kvm),
+        CpuIdHypervisor::VMware => Some(Hypervisor::VMware),
+        CpuIdHypervisor::HyperV => Some(Hypervisor::HyperV),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn from_cpuid() -> Option<Hypervisor> { None }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hypervisor_from_dmi() {
+        assert_eq!(from_dmi("QEMU\n", "Standard PC (Q35 + ICH9, 2009)\n"), Some(Hypervisor::Kvm));
+        assert_eq!(from_dmi("Red Hat", "KVM"), Some(Hypervisor::Kvm));
+        assert_eq!(from_dmi("VMware, Inc.", "VMware7,1"), Some(Hypervisor::VMware));
+        assert_eq!(from_dmi("innotek GmbH", "VirtualBox"), Some(Hypervisor::VirtualBox));
+        assert_eq!(
+            from_dmi("Microsoft Corporation", "Virtual Machine"),
+            Some(Hypervisor::HyperV)
+        );
+        assert_eq!(from_dmi("Microsoft Corporation", "Surface Laptop 4"), None);
+        assert_eq!(from_dmi("System76", "Lemur Pro"), None);
+    }
+
+    #[test]
+    fn guest_packages_of_derivatives() {
+        let pop = OsRelease::parse("ID=pop\nID_LIKE=\"ubuntu debian\"\n");
+        let ubuntu = OsRelease::parse("ID=ubuntu\nID_LIKE=debian\n");
+        for os_release in &[pop, ubuntu] {
+            assert_eq!(
+                Hypervisor::Kvm.guest_packages(os_release),
+                &["qemu-guest-agent", "spice-vdagent"]
+            );
+            assert_eq!(
+                Hypervisor::VirtualBox.guest_packages(os_release),
+                &["virtualbox-guest-utils", "virtualbox-guest-x11"]
+            );
+            assert_eq!(
+                Hypervisor::HyperV.guest_packages(os_release),
+                &["linux-cloud-tools-virtual"]
+            );
+        }
+
+        let debian = OsRelease::parse("ID=debian\n");
+        assert_eq!(Hypervisor::HyperV.guest_packages(&debian), &["hyperv-daemons"]);
+        assert!(Hypervisor::VirtualBox.guest_packages(&debian).is_empty());
+
+        let fedora = OsRelease::parse("ID=fedora\n");
+        assert!(Hypervisor::Kvm.guest_packages(&fedora).is_empty());
+    }
+}
@@ -39,31 +39,92 @@ pub enum OS {
         info: OsRelease,
         partitions: Vec<PartitionID>,
         targets: Vec<PathBuf>,
+        /// The releases of the installed kernels, newest first.
+        kernels: Vec<String>,
+        /// Whether the install is stored on, or unlocks at boot, an encrypted volume.
+        encrypted: bool,
     },
     MacOs(String)
 }
 
-/// Mounts the partition to a temporary directory and checks for the existence of an
-/// installed operating system.
+impl OS {
+    /// Mounts the partition read-only to a temporary directory, and inspects the OS which is
+    /// installed on it, if any.
+    pub fn detect<'a, F: Into<FilesystemType<'a>>>(device: &Path, fs: F) -> Option<OS> {
+        detect_os_from_device(device, fs)
+    }
+
+    /// The name of the OS, for display.
+    pub fn name(&self) -> &str {
+        match *self {
+            OS::Linux { ref info, .. } => info.pretty_name.as_str(),
+            OS::Windows(ref name) | OS::MacOs(ref name) => name.as_str(),
+        }
+    }
+}
+
+/// Mounts the partition read-only to a temporary directory and checks for the existence of an
+/// installed operating system. Journals are not replayed, so the device is left as it was found,
+/// even if the OS on it was not shut down cleanly.
 ///
 /// If the installed operating system is Linux, it will also report back the location
 /// of the home partition.
 pub fn detect_os_from_device<'a, F: Into<FilesystemType<'a>>>(device: &Path, fs: F) -> Option<OS> {
     info!("detecting OS from device: {:?}", device);
+    let fs = fs.into();
+    let data = match fs {
+        FilesystemType::Manual(name) => no_replay_options(name),
+        _ => None,
+    };
+
     // Create a temporary directoy where we will mount the FS.
-    TempDir::new("distinst").ok().and_then(|tempdir| {
+    let os = TempDir::new("distinst").ok().and_then(|tempdir| {
         // Mount the FS to the temporary directory
         let base = tempdir.path();
-        Mount::new(device, base, fs, MountFlags::empty(), None)
+        Mount::new(device, base, fs, MountFlags::RDONLY, data)
             .map(|m| m.into_unmount_drop(UnmountFlags::DETACH))
             .ok()
             .and_then(|_mount| detect_os_from_path(base))
-    })
+    });
+
+    os.map(|os| with_device_encryption(os, device))
+}
+
+/// Detects the OS of `device`, which is already mounted at `mount`, rather than mounting it a
+/// second time.
+pub fn detect_os_from_mount(device: &Path, mount: &Path) -> Option<OS> {
+    detect_os_from_path(mount).map(|os| with_device_encryption(os, device))
+}
+
+/// Options for a read-only mount of `fs`, which keep it from replaying its journal, as a
+/// read-only mount of ext4, XFS, or btrfs otherwise writes to the device to do.
+pub fn no_replay_options(fs: &str) -> Option<&'static str> {
+    match fs {
+        "ext3" | "ext4" => Some("noload"),
+        "xfs" => Some("norecovery"),
+        "btrfs" => Some("nologreplay"),
+        _ => None,
+    }
+}
+
+fn with_device_encryption(os: OS, device: &Path) -> OS {
+    match os {
+        OS::Linux { info, partitions, targets, kernels, encrypted } => OS::Linux {
+            info,
+            partitions,
+            targets,
+            kernels,
+            encrypted: encrypted || device_is_encrypted(device),
+        },
+        os => os,
+    }
 }
 
 /// Detects the existence of an OS at a defined path.
 ///
 /// This function is called by `detect_os_from_device`, after having temporarily mounted it.
+/// The encryption of the device itself is not known from its path, so an install is only
+/// reported as encrypted here if its crypttab unlocks a volume at boot.
 pub fn detect_os_from_path(base: &Path) -> Option<OS> {
     info!("detecting OS from {:?}", base);
     if let Ok(mut dir) = std::fs::read_dir(base) {
@@ -79,12 +140,15 @@ pub fn detect_os_from_path(base: &Path) -> Option<OS> {
 
 /// Detect if Linux is installed at the given path.
 pub fn detect_linux(base: &Path) -> Option<OS> {
-    let path = base.join("etc/os-release");
+    let path = os_release::path_in_root(base);
     if path.exists() {
         info!("found OS Release: {}", std::fs::read_to_string(&path).unwrap());
         if let Ok(info) = OsRelease::new_from(path) {
             let (partitions, targets) = find_linux_parts(base);
-            return Some(OS::Linux { info, partitions, targets });
+            let kernels = installed_kernels(base);
+            let encrypted = std::fs::read_to_string(base.join("etc/crypttab"))
+                .map_or(false, |crypttab| crypttab_has_volumes(&crypttab));
+            return Some(OS::Linux { info, partitions, targets, kernels, encrypted });
         }
     }
 
@@ -139,6 +203,65 @@ fn find_linux_parts(base: &Path) -> (Vec<PartitionID>, Vec<PathBuf>) {
     (partitions, targets)
 }
 
+/// The releases of the kernels whose modules are installed, newest first.
+pub fn installed_kernels(base: &Path) -> Vec<String> {
+    let mut kernels: Vec<String> = std::fs::read_dir(base.join("lib/modules"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        // Directories of removed kernels may linger, with modules built by DKMS.
+        .filter(|entry| entry.path().join("kernel").is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+
+    sort_kernels(&mut kernels);
+    kernels
+}
+
+/// Sorts kernel releases, such as `5.15.0-56-generic`, by their numbers, newest first.
+fn sort_kernels(kernels: &mut [String]) {
+    kernels.sort_by_cached_key(|release| {
+        release
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|number| number.parse::<u64>().ok())
+            .collect::<Vec<u64>>()
+    });
+    kernels.reverse();
+}
+
+/// Whether the crypttab unlocks a volume at boot, excluding swap and tmp, which are encrypted with
+/// a random key on every boot, and so hold nothing of the install.
+fn crypttab_has_volumes(crypttab: &str) -> bool {
+    crypttab.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).any(
+        |line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let key = fields.get(2).cloned().unwrap_or("none");
+            let options = fields.get(3).cloned().unwrap_or("");
+            let ephemeral = key.starts_with("/dev/urandom") || key.starts_with("/dev/random");
+            !ephemeral && !options.split(',').any(|option| option == "swap" || option == "tmp")
+        },
+    )
+}
+
+/// Whether the device is a dm-crypt mapping, or is built upon one, as a logical volume on LUKS is.
+fn device_is_encrypted(device: &Path) -> bool {
+    fn is_crypt(block: &Path) -> bool {
+        let uuid = std::fs::read_to_string(block.join("dm/uuid")).unwrap_or_default();
+        uuid.starts_with("CRYPT-")
+            || std::fs::read_dir(block.join("slaves"))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .any(|slave| is_crypt(&slave.path()))
+    }
+
+    device
+        .canonicalize()
+        .ok()
+        .and_then(|device| device.file_name().map(|name| Path::new("/sys/class/block").join(name)))
+        .map_or(false, |block| is_crypt(&block))
+}
+
 fn parse_plist<R: BufRead>(file: R) -> Option<String> {
     // The plist is an XML file, but we don't need complex XML parsing for this.
     let mut product_name: Option<String> = None;
@@ -223,6 +346,21 @@ mod tests {
 </dict>
 </plist>"#;
 
+    #[test]
+    fn linux_details() {
+        let mut kernels: Vec<String> =
+            vec!["5.4.0-42-generic".into(), "5.15.0-56-generic".into(), "5.15.0-9-generic".into()];
+        sort_kernels(&mut kernels);
+        assert_eq!(kernels, ["5.15.0-56-generic", "5.15.0-9-generic", "5.4.0-42-generic"]);
+
+        assert!(crypttab_has_volumes("# <target> <source>\ncryptdata UUID=1234 none luks\n"));
+        assert!(!crypttab_has_volumes("# <target name> <source device>\n\n"));
+        assert!(!crypttab_has_volumes(
+            "cryptswap /dev/sda3 /dev/urandom swap,cipher=aes-xts-plain64\n"
+        ));
+        assert!(no_replay_options("vfat").is_none());
+    }
+
     #[test]
     fn mac_plist_parsing() {
         assert_eq!(
@@ -0,0 +1,357 @@
+//! Parses the os-release files of the running system, or of another root such as the target of
+//! an install, and edits them without disturbing the fields and comments which are not changed.
+//!
+//! ```rust,no_run
+//! extern crate os_release;
+//!
+//! use os_release::{OsRelease, OsReleaseBuilder};
+//!
+//! pub fn main() {
+//!     let release = OsRelease::from_root("/target").unwrap();
+//!     println!("{} {}", release.name, release.version_id);
+//!
+//!     OsReleaseBuilder::open(os_release::path_in_root("/target"))
+//!         .unwrap()
+//!         .field("VENDOR_NAME", "Acme Systems")
+//!         .write(os_release::path_in_root("/target"))
+//!         .unwrap();
+//! }
+//! ```
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs, io,
+    iter::FromIterator,
+    path::{Path, PathBuf},
+};
+
+lazy_static! {
+    /// The os-release of the running system.
+    pub static ref OS_RELEASE: io::Result<OsRelease> = OsRelease::new();
+}
+
+/// The locations of os-release, relative to a root, in the order that they are read.
+const PATHS: &[&str] = &["etc/os-release", "usr/lib/os-release"];
+
+/// The fields of an os-release file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OsRelease {
+    pub bug_report_url:     String,
+    pub home_url:           String,
+    pub id_like:            String,
+    pub id:                 String,
+    pub name:               String,
+    pub pretty_name:        String,
+    pub privacy_policy_url: String,
+    pub support_url:        String,
+    pub version_codename:   String,
+    pub version_id:         String,
+    pub version:            String,
+    /// Fields which are not otherwise parsed, such as those which vendors add.
+    pub extra:              BTreeMap<String, String>,
+}
+
+impl OsRelease {
+    /// Reads the os-release of the running system.
+    pub fn new() -> io::Result<OsRelease> { OsRelease::from_root("/") }
+
+    /// Reads an os-release file at the given path.
+    pub fn new_from<P: AsRef<Path>>(path: P) -> io::Result<OsRelease> {
+        let path = path.as_ref();
+        fs::read_to_string(path).map(|contents| OsRelease::parse(&contents)).map_err(|why| {
+            io::Error::new(why.kind(), format!("unable to read {}: {}", path.display(), why))
+        })
+    }
+
+    /// Reads the os-release of the system at `root`, such as a chroot or a mounted partition.
+    pub fn from_root<P: AsRef<Path>>(root: P) -> io::Result<OsRelease> {
+        OsRelease::new_from(path_in_root(root))
+    }
+
+    /// Parses the contents of an os-release file. Invalid lines are ignored.
+    pub fn parse(contents: &str) -> OsRelease { contents.lines().collect() }
+}
+
+impl<'a> FromIterator<&'a str> for OsRelease {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(lines: I) -> Self {
+        let mut release = OsRelease::default();
+
+        for (key, value) in lines.into_iter().filter_map(parse_line) {
+            let field = match key {
+                "BUG_REPORT_URL" => &mut release.bug_report_url,
+                "HOME_URL" => &mut release.home_url,
+                "ID_LIKE" => &mut release.id_like,
+                "ID" => &mut release.id,
+                "NAME" => &mut release.name,
+                "PRETTY_NAME" => &mut release.pretty_name,
+                "PRIVACY_POLICY_URL" => &mut release.privacy_policy_url,
+                "SUPPORT_URL" => &mut release.support_url,
+                "VERSION_CODENAME" => &mut release.version_codename,
+                "VERSION_ID" => &mut release.version_id,
+                "VERSION" => &mut release.version,
+                _ => {
+                    release.extra.insert(key.to_owned(), value);
+                    continue;
+                }
+            };
+
+            *field = value;
+        }
+
+        release
+    }
+}
+
+impl FromIterator<String> for OsRelease {
+    fn from_iter<I: IntoIterator<Item = String>>(lines: I) -> Self {
+        let lines: Vec<String> = lines.into_iter().collect();
+        lines.iter().map(String::as_str).collect()
+    }
+}
+
+/// The path of the os-release file of the system at `root`.
+///
+/// `/etc/os-release` is commonly an absolute link to `/usr/lib/os-release`, which is resolved
+/// within `root` rather than to the file of the running system.
+pub fn path_in_root<P: AsRef<Path>>(root: P) -> PathBuf {
+    let root = root.as_ref();
+    for path in PATHS {
+        let mut path = root.join(path);
+        if let Ok(link) = fs::read_link(&path) {
+            path = match link.strip_prefix("/") {
+                Ok(link) => root.join(link),
+                Err(_) => path.parent().expect("os-release without parent").join(link),
+            };
+        }
+
+        if path.exists() {
+            return path;
+        }
+    }
+
+    root.join(PATHS[0])
+}
+
+/// Parses an assignment of an os-release file, returning `None` for comments, blank lines,
+/// and lines which are not valid.
+pub fn parse_line(line: &str) -> Option<(&str, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let mut assignment = line.splitn(2, '=');
+    let key = assignment.next()?;
+    let value = assignment.next()?;
+    if !is_valid_key(key) {
+        return None;
+    }
+
+    unquote(value).map(|value| (key, value))
+}
+
+/// Whether `key` is a valid name of an os-release field: uppercase letters, digits, and
+/// underscores, not beginning with a digit.
+pub fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    let first = chars.next().map_or(false, |c| c.is_ascii_uppercase() || c == '_');
+    first && chars.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Removes the quoting and escapes of a value, as the shell would.
+///
+/// Single-quoted text is taken literally. Within double quotes, a backslash only escapes
+/// `"`, `\`, `$`, and `` ` ``. Outside of quotes, a backslash escapes any character. Returns
+/// `None` if a quote is not terminated, or the value has unquoted whitespace.
+pub fn unquote(value: &str) -> Option<String> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Quote {
+        None,
+        Single,
+        Double,
+    }
+
+    let mut unquoted = String::with_capacity(value.len());
+    let mut quote = Quote::None;
+    let mut chars = value.chars();
+
+    while let Some(character) = chars.next() {
+        match (quote, character) {
+            (Quote::None, '\'') => quote = Quote::Single,
+            (Quote::None, '"') => quote = Quote::Double,
+            (Quote::None, '\\') => unquoted.push(chars.next()?),
+            (Quote::None, c) if c.is_whitespace() => return None,
+            (Quote::Single, '\'') | (Quote::Double, '"') => quote = Quote::None,
+            (Quote::Double, '\\') => match chars.next()? {
+                escaped @ '"' | escaped @ '\\' | escaped @ '$' | escaped @ '`' => {
+                    unquoted.push(escaped)
+                }
+                other => {
+                    unquoted.push('\\');
+                    unquoted.push(other);
+                }
+            },
+            (_, c) => unquoted.push(c),
+        }
+    }
+
+    if quote == Quote::None {
+        Some(unquoted)
+    } else {
+        None
+    }
+}
+
+/// Quotes a value as the shell would, as os-release requires.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for character in value.chars() {
+        if let '"' | '\\' | '$' | '`' = character {
+            quoted.push('\\');
+        }
+        quoted.push(character);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Edits the fields of an os-release file, such as to add those of an OEM to the target of an
+/// install. Lines which are not changed are written back as they were.
+#[derive(Clone, Debug, Default)]
+pub struct OsReleaseBuilder {
+    lines:   Vec<String>,
+    invalid: Vec<String>,
+}
+
+impl OsReleaseBuilder {
+    /// Begins editing the given contents of an os-release file.
+    pub fn new(contents: &str) -> OsReleaseBuilder {
+        let lines = contents.lines().map(String::from).collect();
+        OsReleaseBuilder { lines, invalid: Vec::new() }
+    }
+
+    /// Begins editing the os-release file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<OsReleaseBuilder> {
+        let path = path.as_ref();
+        fs::read_to_string(path).map(|contents| OsReleaseBuilder::new(&contents)).map_err(|why| {
+            io::Error::new(why.kind(), format!("unable to read {}: {}", path.display(), why))
+        })
+    }
+
+    /// Sets a field, replacing its existing assignment, or else appending it.
+    pub fn field(mut self, key: &str, value: &str) -> OsReleaseBuilder {
+        if !is_valid_key(key) {
+            self.invalid.push(key.to_owned());
+            return self;
+        }
+
+        let line = [key, "=", &quote(value)].concat();
+        let existing = self
+            .lines
+            .iter_mut()
+            .find(|line| parse_line(line).map_or(false, |(existing, _)| existing == key));
+
+        match existing {
+            Some(existing) => *existing = line,
+            None => self.lines.push(line),
+        }
+
+        self
+    }
+
+    /// The contents of the edited file, or an error if an invalid field name was given.
+    pub fn build(self) -> io::Result<String> {
+        if !self.invalid.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid os-release field names: {}", self.invalid.join(", ")),
+            ));
+        }
+
+        let mut contents = self.lines.join("\n");
+        contents.push('\n');
+        Ok(contents)
+    }
+
+    /// Writes the edited file to `path`.
+    ///
+    /// The file is written beside `path`, and renamed over it, so that a symlink at `path` is
+    /// replaced rather than written through, as it may point outside of the root being edited.
+    pub fn write<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let contents = self.build()?;
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "os-release path does not name a file")
+        })?;
+
+        let mut temporary = OsString::from(".");
+        temporary.push(name);
+        temporary.push(".new");
+        let temporary = path.with_file_name(temporary);
+
+        fs::write(&temporary, contents).and_then(|_| fs::rename(&temporary, path)).map_err(|why| {
+            let _ = fs::remove_file(&temporary);
+            io::Error::new(why.kind(), format!("unable to write {}: {}", path.display(), why))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POP_OS: &str = r#"NAME="Pop!_OS"
+VERSION="22.04 LTS"
+ID=pop
+ID_LIKE="ubuntu debian"
+# A comment, which is ignored.
+PRETTY_NAME='Pop!_OS 22.04 LTS'
+VERSION_ID="22.04"
+HOME_URL="https://pop.system76.com"
+VENDOR_NAME="Acme \"Systems\" \$1"
+BROKEN="unterminated
+"#;
+
+    #[test]
+    fn os_release_parsing() {
+        let release = OsRelease::parse(POP_OS);
+        assert_eq!(release.name, "Pop!_OS");
+        assert_eq!(release.version, "22.04 LTS");
+        assert_eq!(release.id, "pop");
+        assert_eq!(release.id_like, "ubuntu debian");
+        assert_eq!(release.pretty_name, "Pop!_OS 22.04 LTS");
+        assert_eq!(release.extra.get("VENDOR_NAME").unwrap(), "Acme \"Systems\" $1");
+        assert!(!release.extra.contains_key("BROKEN"));
+
+        assert_eq!(unquote(r#"a\ b"#).as_deref(), Some("a b"));
+        assert_eq!(unquote(r#""a\nb""#).as_deref(), Some("a\\nb"));
+        assert_eq!(unquote("two words"), None);
+        assert_eq!(parse_line("lowercase=value"), None);
+    }
+
+    #[test]
+    fn os_release_builder() {
+        let contents = "# Header\nNAME=\"Pop!_OS\"\nVENDOR_NAME=\"System76\"\n";
+        let edited = OsReleaseBuilder::new(contents)
+            .field("VENDOR_NAME", "Acme \"Systems\"")
+            .field("VENDOR_URL", "https://acme.example/")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            edited,
+            "# Header\nNAME=\"Pop!_OS\"\nVENDOR_NAME=\"Acme \\\"Systems\\\"\"\n\
+             VENDOR_URL=\"https://acme.example/\"\n"
+        );
+
+        let release = OsRelease::parse(&edited);
+        assert_eq!(release.extra.get("VENDOR_NAME").unwrap(), "Acme \"Systems\"");
+
+        assert!(OsReleaseBuilder::new(contents).field("vendor", "Acme").build().is_err());
+    }
+}
@@ -1,8 +1,11 @@
+extern crate distinst_utils as misc;
 extern crate libc;
 #[macro_use]
 extern crate log;
 
+use misc::journal;
 use std::{
+    ffi::OsString,
     fs::File,
     io::{Error, ErrorKind, Read, Result},
     os::unix::{
@@ -10,8 +13,9 @@ use std::{
         process::CommandExt,
     },
     path::Path,
-    process::{Command, Stdio},
+    process::{Command, ExitStatus, Stdio},
     str,
+    time::{Duration, Instant},
 };
 
 fn getpty(columns: u32, lines: u32) -> (RawFd, String) {
@@ -96,9 +100,25 @@ fn before_exec() -> Result<()> {
     Ok(())
 }
 
-fn handle<F: FnMut(i32)>(mut master: File, mut callback: F) -> Result<()> {
+/// Reports each change of the progress that the command prints, and also the last progress
+/// after each `tick` in which nothing was printed, if ticks are requested.
+fn handle<F: FnMut(i32)>(mut master: File, tick: Option<Duration>, mut callback: F) -> Result<()> {
     let mut last_progress = 0;
     loop {
+        if let Some(tick) = tick {
+            let mut fd = libc::pollfd { fd: master.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            let timeout = tick.as_millis().min(i32::MAX as u128) as libc::c_int;
+            match unsafe { libc::poll(&mut fd, 1, timeout) } {
+                0 => {
+                    callback(last_progress);
+                    continue;
+                }
+                -1 if Error::last_os_error().kind() == ErrorKind::Interrupted => continue,
+                -1 => return Err(Error::last_os_error()),
+                _ => (),
+            }
+        }
+
         let mut data = [0; 0x1000];
         let count = master.read(&mut data)?;
         if count == 0 {
@@ -120,15 +140,92 @@ fn handle<F: FnMut(i32)>(mut master: File, mut callback: F) -> Result<()> {
     }
 }
 
+/// The offset of the inode count in the superblock of a squashfs image, after its magic.
+const INODE_COUNT_OFFSET: usize = 4;
+const SQUASHFS_MAGIC: &[u8] = b"hsqs";
+
+/// How often the progress of an extraction is reported while it does not change.
+const EXTRACT_TICK: Duration = Duration::from_secs(1);
+
+/// Reads the number of inodes in a squashfs image from its superblock, which is the number of
+/// files, directories, and links which its extraction creates.
+pub fn inode_count<P: AsRef<Path>>(archive: P) -> Result<u64> {
+    let mut superblock = [0; INODE_COUNT_OFFSET + 4];
+    File::open(archive.as_ref())?.read_exact(&mut superblock)?;
+    parse_inode_count(&superblock).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is not a squashfs image", archive.as_ref().display()),
+        )
+    })
+}
+
+fn parse_inode_count(superblock: &[u8]) -> Option<u64> {
+    if !superblock.starts_with(SQUASHFS_MAGIC) {
+        return None;
+    }
+
+    let mut count = [0; 4];
+    count.copy_from_slice(superblock.get(INODE_COUNT_OFFSET..INODE_COUNT_OFFSET + 4)?);
+    Some(u64::from(u32::from_le_bytes(count)))
+}
+
+/// The releases of the kernels in a squashfs image, such as `5.11.0-7614-generic`, which are
+/// the names of the directories of their modules.
+pub fn kernel_releases<P: AsRef<Path>>(archive: P) -> Result<Vec<String>> {
+    let args: Vec<OsString> = vec![
+        "-l".into(),
+        archive.as_ref().into(),
+        "lib/modules".into(),
+        "usr/lib/modules".into(),
+    ];
+
+    let output = misc::runner::runner().run("unsquashfs", &args, None, true)?;
+    if !output.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("unable to list the contents of {}", archive.as_ref().display()),
+        ));
+    }
+
+    Ok(parse_kernel_releases(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_kernel_releases(listing: &str) -> Vec<String> {
+    let mut releases: Vec<String> = Vec::new();
+    for line in listing.lines() {
+        let modules = match line.find("lib/modules/") {
+            Some(pos) => &line[pos + "lib/modules/".len()..],
+            None => continue,
+        };
+
+        let release = modules.split('/').next().unwrap_or("");
+        if !release.is_empty() && !releases.iter().any(|known| known == release) {
+            releases.push(release.to_owned());
+        }
+    }
+
+    releases
+}
+
 enum ExtractFormat {
     Tar,
     Squashfs,
 }
 
 /// Extracts an image using either unsquashfs or tar.
+///
+/// Unsquashfs decompresses with a thread per CPU, unless limited by `threads`, but writes the
+/// files from a single thread, which `threads` does not change. Tar archives are always
+/// extracted by a single thread.
+///
+/// `callback` is given the percent of the image which was extracted whenever it changes, and
+/// again each second in which it does not, so that progress which is measured by other means
+/// may be sampled while the percent stalls, such as on many small files.
 pub fn extract<P: AsRef<Path>, Q: AsRef<Path>, F: FnMut(i32)>(
     archive: P,
     directory: Q,
+    threads: Option<usize>,
     callback: F,
 ) -> Result<()> {
     let archive = archive.as_ref().canonicalize()?;
@@ -150,21 +247,126 @@ pub fn extract<P: AsRef<Path>, Q: AsRef<Path>, F: FnMut(i32)>(
         .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid archive path"))?
         .replace("'", "'\"'\"'");
 
-    let mut command = match format {
+    // Extended attributes carry the ACLs, file capabilities, and security labels of the image.
+    let (program, args): (&str, Vec<OsString>) = match format {
         ExtractFormat::Squashfs => {
-            let mut command = Command::new("unsquashfs");
-            command.arg("-f").arg("-d").arg(directory).arg(archive);
-            command
-        }
-        ExtractFormat::Tar => {
-            let mut command = Command::new("tar");
-            command.arg("--overwrite").arg("-xf").arg(archive).arg("-C").arg(directory);
-            command
+            let mut args: Vec<OsString> = vec!["-f".into(), "-xattrs".into()];
+            if let Some(threads) = threads {
+                args.push("-processors".into());
+                args.push(threads.max(1).to_string().into());
+            }
+
+            args.push("-d".into());
+            args.push(directory.into());
+            args.push(archive.into());
+            ("unsquashfs", args)
         }
+        ExtractFormat::Tar => (
+            "tar",
+            vec![
+                "--overwrite".into(),
+                "--xattrs".into(),
+                "--xattrs-include=*".into(),
+                "--acls".into(),
+                "--selinux".into(),
+                "-xf".into(),
+                archive.into(),
+                "-C".into(),
+                directory.into(),
+            ],
+        ),
     };
 
+    let status = run_with_progress(program, &args, Some(EXTRACT_TICK), callback)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("archive extraction failed with status: {}", status),
+        ))
+    }
+}
+
+/// Creates a squashfs image of a directory using mksquashfs.
+///
+/// Paths in `exclude` are relative to the `directory` being captured.
+pub fn create<P: AsRef<Path>, Q: AsRef<Path>, F: FnMut(i32)>(
+    directory: P,
+    archive: Q,
+    exclude: &[&str],
+    callback: F,
+) -> Result<()> {
+    let directory = directory.as_ref().canonicalize()?;
+
+    let mut args: Vec<OsString> = vec![
+        directory.into(),
+        archive.as_ref().into(),
+        "-noappend".into(),
+        "-xattrs".into(),
+        "-comp".into(),
+        "xz".into(),
+    ];
+
+    if !exclude.is_empty() {
+        args.push("-e".into());
+        args.extend(exclude.iter().map(Into::into));
+    }
+
+    let status = run_with_progress("mksquashfs", &args, None, callback)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("archive creation failed with status: {}", status),
+        ))
+    }
+}
+
+/// Archives a directory into a tarball, which is compressed according to the extension of
+/// `archive`, such as `.tar.gz`, with the numeric owners, ACLs, and extended attributes of its
+/// files.
+pub fn create_tar<P: AsRef<Path>, Q: AsRef<Path>>(directory: P, archive: Q) -> Result<()> {
+    let args: Vec<OsString> = vec![
+        "--numeric-owner".into(),
+        "--xattrs".into(),
+        "--xattrs-include=*".into(),
+        "--acls".into(),
+        "--auto-compress".into(),
+        "-cf".into(),
+        archive.as_ref().into(),
+        "-C".into(),
+        directory.as_ref().canonicalize()?.into(),
+        ".".into(),
+    ];
+
+    let status = run_with_progress("tar", &args, None, |_| ())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("archive creation failed with status: {}", status),
+        ))
+    }
+}
+
+/// Spawns the command within a pty, and reports the progress that it prints.
+///
+/// The command is recorded in the command journal, without its output.
+fn run_with_progress<F: FnMut(i32)>(
+    program: &str,
+    args: &[OsString],
+    tick: Option<Duration>,
+    callback: F,
+) -> Result<ExitStatus> {
+    let mut command = Command::new(program);
+    command.args(args);
     debug!("{:?}", command);
 
+    let started = Instant::now();
+
     let (master_fd, tty_path) = getpty(80, 30);
     let mut child = {
         let (slave_stdin, slave_stdout, slave_stderr) = slave_stdio(&tty_path)?;
@@ -178,12 +380,16 @@ pub fn extract<P: AsRef<Path>, Q: AsRef<Path>, F: FnMut(i32)>(
                 .env("LINES", "")
                 .env("TERM", "xterm-256color")
                 .pre_exec(before_exec)
-                .spawn()?
+                .spawn()
+                .map_err(|why| {
+                    journal::record(program, args, started, None, "");
+                    why
+                })?
         }
     };
 
     let master = unsafe { File::from_raw_fd(master_fd) };
-    match handle(master, callback) {
+    match handle(master, tick, callback) {
         Ok(()) => (),
         Err(err) => match err.raw_os_error() {
             // EIO happens when slave end is closed
@@ -193,13 +399,33 @@ pub fn extract<P: AsRef<Path>, Q: AsRef<Path>, F: FnMut(i32)>(
         },
     }
 
-    let status = child.wait()?;
-    if status.success() {
-        Ok(())
-    } else {
-        Err(Error::new(
-            ErrorKind::Other,
-            format!("archive extraction failed with status: {}", status),
-        ))
+    let status = child.wait();
+    journal::record(program, args, started, status.as_ref().ok().and_then(|s| s.code()), "");
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn superblock_inode_count() {
+        let superblock = [b'h', b's', b'q', b's', 0x40, 0xe2, 0x01, 0x00];
+        assert_eq!(parse_inode_count(&superblock), Some(123_456));
+        assert_eq!(parse_inode_count(b"sqsh\0\0\0\0"), None);
+        assert_eq!(parse_inode_count(b"hsqs"), None);
+    }
+
+    #[test]
+    fn kernel_releases_of_listing() {
+        let listing = "squashfs-root\n\
+                       squashfs-root/usr/lib/modules\n\
+                       squashfs-root/usr/lib/modules/5.11.0-7614-generic\n\
+                       squashfs-root/usr/lib/modules/5.11.0-7614-generic/modules.dep\n\
+                       squashfs-root/usr/lib/modules/5.13.0-7620-generic/kernel\n";
+        assert_eq!(
+            parse_kernel_releases(listing),
+            vec!["5.11.0-7614-generic".to_owned(), "5.13.0-7620-generic".to_owned()]
+        );
     }
 }
@@ -0,0 +1,229 @@
+//! A file copy engine, which clones files on file systems that support reflinks, such as Btrfs
+//! and XFS, copies within the kernel where it can, and retains the holes of sparse files.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom},
+    os::unix::{
+        fs::{MetadataExt, OpenOptionsExt},
+        io::AsRawFd,
+    },
+    path::Path,
+};
+
+/// `_IOW(0x94, 9, int)`, which clones the file given as the argument into the target file.
+const FICLONE: libc::c_ulong = 0x4004_9409;
+
+/// Copies the file at `source` to `target`, along with its permissions, and returns its length.
+pub fn copy_file(source: &Path, target: &Path) -> io::Result<u64> {
+    let mut input = File::open(source)?;
+    let metadata = input.metadata()?;
+    let len = metadata.len();
+
+    let mut output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(metadata.mode())
+        .open(target)?;
+    output.set_permissions(metadata.permissions())?;
+
+    if unsafe { libc::ioctl(output.as_raw_fd(), FICLONE, input.as_raw_fd()) } == 0 {
+        return Ok(len);
+    }
+
+    // Files which occupy fewer blocks than their length have holes.
+    let sparse = metadata.blocks() * 512 < len;
+    if !sparse && len != 0 {
+        // Preallocation is an optimization, which not every file system supports.
+        let _ = unsafe { libc::fallocate(output.as_raw_fd(), 0, 0, len as libc::off_t) };
+    }
+
+    for (start, end) in data_segments(&input, len, sparse)? {
+        copy_range(&mut input, &mut output, start, end)?;
+    }
+
+    // Holes at the end of the file are restored by its length.
+    output.set_len(len)?;
+    Ok(len)
+}
+
+/// Recursively copies the directory at `source` to `target`, following symlinks, and returns
+/// the number of bytes copied.
+pub fn copy_tree(source: &Path, target: &Path) -> io::Result<u64> {
+    fs::create_dir_all(target)?;
+
+    let mut copied = 0;
+    for entry in source.read_dir()? {
+        let entry = entry?;
+        let (source, target) = (entry.path(), target.join(entry.file_name()));
+        if source.metadata()?.is_dir() {
+            copied += copy_tree(&source, &target)?;
+        } else {
+            copied += copy_file(&source, &target)?;
+        }
+    }
+
+    Ok(copied)
+}
+
+/// Copies the directory at `source` to `target` as `copy_tree` does, after removing the entries
+/// of `target` which are not in `source`, or which are not of the same kind, as
+/// `rsync --delete-before` would. Returns the number of bytes copied.
+pub fn sync_tree(source: &Path, target: &Path) -> io::Result<u64> {
+    fs::create_dir_all(target)?;
+
+    for entry in target.read_dir()? {
+        let entry = entry?;
+        let target = entry.path();
+        let is_dir = entry.file_type()?.is_dir();
+        let keep = match source.join(entry.file_name()).metadata() {
+            Ok(metadata) => metadata.is_dir() == is_dir,
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => false,
+            Err(why) => return Err(why),
+        };
+
+        if !keep {
+            if is_dir {
+                fs::remove_dir_all(&target)?;
+            } else {
+                fs::remove_file(&target)?;
+            }
+        }
+    }
+
+    let mut copied = 0;
+    for entry in source.read_dir()? {
+        let entry = entry?;
+        let (source, target) = (entry.path(), target.join(entry.file_name()));
+        if source.metadata()?.is_dir() {
+            copied += sync_tree(&source, &target)?;
+        } else {
+            copied += copy_file(&source, &target)?;
+        }
+    }
+
+    Ok(copied)
+}
+
+/// The ranges of the file which contain data.
+fn data_segments(input: &File, len: u64, sparse: bool) -> io::Result<Vec<(u64, u64)>> {
+    if !sparse {
+        return Ok(vec![(0, len)]);
+    }
+
+    let fd = input.as_raw_fd();
+    let mut segments = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let data = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            let why = io::Error::last_os_error();
+            match why.raw_os_error() {
+                // There is no more data after the offset.
+                Some(libc::ENXIO) => break,
+                // The file system cannot report holes, so the file is copied in its entirety.
+                Some(libc::EINVAL) => return Ok(vec![(0, len)]),
+                _ => return Err(why),
+            }
+        }
+
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        segments.push((data as u64, hole as u64));
+        offset = hole as u64;
+    }
+
+    Ok(segments)
+}
+
+/// Copies the bytes from `start` to `end` within the kernel, or through userspace if the
+/// kernel cannot copy between these files.
+fn copy_range(input: &mut File, output: &mut File, start: u64, end: u64) -> io::Result<()> {
+    let (mut off_in, mut off_out) = (start as libc::loff_t, start as libc::loff_t);
+    while (off_in as u64) < end {
+        let copied = unsafe {
+            libc::syscall(
+                libc::SYS_copy_file_range,
+                input.as_raw_fd(),
+                &mut off_in as *mut libc::loff_t,
+                output.as_raw_fd(),
+                &mut off_out as *mut libc::loff_t,
+                (end - off_in as u64) as libc::size_t,
+                0u32,
+            )
+        };
+
+        if copied == 0 {
+            // The file was truncated while it was being copied.
+            return Ok(());
+        } else if copied < 0 {
+            let why = io::Error::last_os_error();
+            match why.raw_os_error() {
+                Some(libc::ENOSYS)
+                | Some(libc::EXDEV)
+                | Some(libc::EINVAL)
+                | Some(libc::EOPNOTSUPP)
+                | Some(libc::EPERM) => {
+                    return copy_range_userspace(input, output, off_in as u64, end)
+                }
+                Some(libc::EINTR) => continue,
+                _ => return Err(why),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_range_userspace(
+    input: &mut File,
+    output: &mut File,
+    start: u64,
+    end: u64,
+) -> io::Result<()> {
+    input.seek(SeekFrom::Start(start))?;
+    output.seek(SeekFrom::Start(start))?;
+    io::copy(&mut input.take(end - start), output).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    #[test]
+    fn sparse_copy() {
+        let dir = TempDir::new("distinst-copy").unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+
+        let mut file = File::create(&source).unwrap();
+        file.set_len(4 * 1024 * 1024).unwrap();
+        file.seek(SeekFrom::Start(1024 * 1024)).unwrap();
+        file.write_all(b"data between holes").unwrap();
+        drop(file);
+
+        assert_eq!(copy_file(&source, &target).unwrap(), 4 * 1024 * 1024);
+        assert_eq!(fs::read(&source).unwrap(), fs::read(&target).unwrap());
+    }
+
+    #[test]
+    fn sync_removes_stale_entries() {
+        let dir = TempDir::new("distinst-sync").unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+        fs::create_dir_all(source.join("pool")).unwrap();
+        fs::write(source.join("pool/current.deb"), b"current").unwrap();
+        fs::create_dir_all(target.join("pool/stale")).unwrap();
+        fs::write(target.join("pool/old.deb"), b"old").unwrap();
+        fs::write(target.join("pool/stale/old.deb"), b"old").unwrap();
+
+        sync_tree(&source, &target).unwrap();
+        assert_eq!(fs::read(target.join("pool/current.deb")).unwrap(), b"current");
+        assert!(!target.join("pool/old.deb").exists());
+        assert!(!target.join("pool/stale").exists());
+    }
+}
@@ -0,0 +1,99 @@
+//! A process-wide journal of the external commands that have been executed.
+
+use once_cell::sync::Lazy;
+use std::{
+    collections::VecDeque,
+    ffi::OsStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The number of bytes of output that are retained for each command.
+pub const OUTPUT_LIMIT: usize = 4096;
+
+/// The number of commands that are retained, of which the oldest are dropped first. An install
+/// executes a few hundred, but a frontend may probe disks for as long as it runs.
+pub const ENTRY_LIMIT: usize = 4096;
+
+static JOURNAL: Lazy<Mutex<VecDeque<JournalEntry>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// An external command that was executed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    /// The program, followed by its arguments.
+    pub argv:     Vec<String>,
+    /// How long the command took to complete.
+    pub duration: Duration,
+    /// The exit code, or `None` if the command could not be spawned or was killed by a signal.
+    pub status:   Option<i32>,
+    /// The end of the command's output, limited to `OUTPUT_LIMIT` bytes.
+    pub output:   String,
+}
+
+/// Records a command that was started at `started`, and has since completed.
+pub fn record<P: AsRef<OsStr>, A: AsRef<OsStr>>(
+    program: P,
+    args: &[A],
+    started: Instant,
+    status: Option<i32>,
+    output: &str,
+) {
+    let argv = Some(program.as_ref())
+        .into_iter()
+        .chain(args.iter().map(AsRef::as_ref))
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+
+    let entry = JournalEntry {
+        argv,
+        duration: started.elapsed(),
+        status,
+        output: truncate(output).to_owned(),
+    };
+
+    let mut journal = JOURNAL.lock().expect("command journal lock poisoned");
+    if journal.len() == ENTRY_LIMIT {
+        journal.pop_front();
+    }
+
+    journal.push_back(entry);
+}
+
+/// Takes the entries that have been recorded so far, in the order that the commands completed.
+pub fn take() -> Vec<JournalEntry> {
+    JOURNAL.lock().expect("command journal lock poisoned").drain(..).collect()
+}
+
+/// The entries that have been recorded so far, which remain in the journal.
+pub fn entries() -> Vec<JournalEntry> {
+    JOURNAL.lock().expect("command journal lock poisoned").iter().cloned().collect()
+}
+
+/// Retains the last `OUTPUT_LIMIT` bytes of the output, as errors are typically reported last.
+pub fn truncate(output: &str) -> &str {
+    if output.len() <= OUTPUT_LIMIT {
+        return output;
+    }
+
+    let mut start = output.len() - OUTPUT_LIMIT;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+
+    &output[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_truncation() {
+        assert_eq!(truncate("short"), "short");
+
+        let output = ["é".repeat(OUTPUT_LIMIT), "end".into()].concat();
+        let truncated = truncate(&output);
+        assert!(truncated.len() <= OUTPUT_LIMIT);
+        assert!(truncated.starts_with('é') && truncated.ends_with("éend"));
+    }
+}
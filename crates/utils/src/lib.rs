@@ -1,10 +1,27 @@
 //! Miscellanious functions used by distinst and its crates.
 
+extern crate libc;
+#[macro_use]
+extern crate log;
+extern crate once_cell;
 extern crate sedregex;
+#[cfg(test)]
+extern crate tempdir;
+
+pub mod copy;
+pub mod journal;
+pub mod namespace;
+pub mod priority;
+pub mod runner;
+pub mod unmount;
+pub mod xattr;
 
 use std::{
-    fs::File,
+    ffi::CString,
+    fs::{File, OpenOptions},
     io::{self, Read, Write},
+    mem,
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt},
     path::Path,
 };
 
@@ -26,10 +43,18 @@ pub fn create<P: AsRef<Path>>(path: P) -> io::Result<File> {
     })
 }
 
+/// Creates a new file which only its owner may read and write. Fails if anything, including a
+/// symlink, already exists at `path`, so that secrets are never written through a link.
+pub fn create_private<P: AsRef<Path>>(path: P) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path).map_err(|why| {
+        io::Error::new(why.kind(), format!("unable to create file at {:?}: {}", path.as_ref(), why))
+    })
+}
+
 pub fn cp<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> io::Result<u64> {
     let src = src.as_ref();
     let dst = dst.as_ref();
-    io::copy(&mut open(src)?, &mut create(dst)?).map_err(|why| {
+    copy::copy_file(src, dst).map_err(|why| {
         io::Error::new(
             io::ErrorKind::Other,
             format!("failed to copy {:?} to {:?}: {}", src, dst, why),
@@ -48,6 +73,40 @@ pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result
     create(path).and_then(|mut file| file.write_all(contents.as_ref()))
 }
 
+/// Obtains the bytes used, and the bytes available, on the file system containing `path`.
+pub fn fs_usage<P: AsRef<Path>>(path: P) -> io::Result<(u64, u64)> {
+    let stat = statvfs(path.as_ref())?;
+    let fragment = stat.f_frsize as u64;
+    let used = (stat.f_blocks as u64 - stat.f_bfree as u64) * fragment;
+    let available = stat.f_bavail as u64 * fragment;
+    Ok((used, available))
+}
+
+/// Obtains the inodes used, and the inodes in total, on the file system containing `path`.
+/// File systems which allocate inodes dynamically, such as btrfs, have `0` in total.
+pub fn fs_inodes<P: AsRef<Path>>(path: P) -> io::Result<(u64, u64)> {
+    let stat = statvfs(path.as_ref())?;
+    let total = stat.f_files as u64;
+    Ok((total.saturating_sub(stat.f_ffree as u64), total))
+}
+
+fn statvfs(path: &Path) -> io::Result<libc::statvfs> {
+    let cpath = CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{:?} contains a null byte", path))
+    })?;
+
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(cpath.as_ptr(), &mut stat) } != 0 {
+        let why = io::Error::last_os_error();
+        return Err(io::Error::new(
+            why.kind(),
+            format!("unable to get file system usage of {:?}: {}", path, why),
+        ));
+    }
+
+    Ok(stat)
+}
+
 pub use self::layout::*;
 use sedregex::find_and_replace;
 use std::{
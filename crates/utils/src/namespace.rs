@@ -0,0 +1,66 @@
+//! A private mount namespace, in which the install mounts its targets.
+//!
+//! Mounts made within the namespace never appear in the host's mount table, so the desktop's
+//! automounter cannot interfere with them, and they are released by the kernel if the
+//! installer dies. The namespace belongs to the calling thread, and is inherited by the
+//! threads and processes that it spawns, so a frontend's other threads are unaffected.
+
+use std::{
+    env,
+    ffi::CString,
+    fs::File,
+    io,
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+    ptr,
+};
+
+/// The mounts of the calling thread's mount namespace. `/proc/mounts` and `/proc/self/mounts`
+/// list those of the process's main thread, which remains in the host's namespace.
+pub const THREAD_MOUNTS: &str = "/proc/thread-self/mounts";
+
+/// Returns the calling thread to the host's mount namespace when dropped.
+pub struct MountNamespace {
+    host: File,
+    cwd:  Option<PathBuf>,
+}
+
+impl MountNamespace {
+    /// Moves the calling thread into a new mount namespace.
+    pub fn enter() -> io::Result<Self> {
+        let host = File::open("/proc/thread-self/ns/mnt")?;
+        let cwd = env::current_dir().ok();
+
+        if unsafe { libc::unshare(libc::CLONE_NEWNS) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let namespace = MountNamespace { host, cwd };
+
+        // Mounts are not propagated back to the host, though the host's mounts, such as
+        // removable media which is inserted during the install, are still received.
+        let root = CString::new("/").expect("static string");
+        let flags = libc::MS_REC | libc::MS_SLAVE;
+        let result =
+            unsafe { libc::mount(ptr::null(), root.as_ptr(), ptr::null(), flags, ptr::null()) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(namespace)
+    }
+}
+
+impl Drop for MountNamespace {
+    fn drop(&mut self) {
+        if unsafe { libc::setns(self.host.as_raw_fd(), libc::CLONE_NEWNS) } != 0 {
+            error!("unable to return to the host mount namespace: {}", io::Error::last_os_error());
+            return;
+        }
+
+        // Entering a mount namespace moves the working directory to its root.
+        if let Some(ref cwd) = self.cwd {
+            let _ = env::set_current_dir(cwd);
+        }
+    }
+}
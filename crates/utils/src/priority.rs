@@ -0,0 +1,122 @@
+//! CPU and I/O scheduling priorities, which allow a long-running operation to leave the
+//! processor and disks to an interactive session.
+//!
+//! Priorities are applied to the calling thread rather than to the process, so that the
+//! threads of a frontend are unaffected. Processes spawned by the thread inherit them.
+
+use std::io;
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// An I/O scheduling class, as set by `ionice`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IoClass {
+    /// Served before all other classes, at a level from 0 (the highest) to 7.
+    RealTime(u8),
+    /// The default class, at a level from 0 (the highest) to 7.
+    BestEffort(u8),
+    /// Only served when no other process requires the disk.
+    Idle,
+}
+
+impl IoClass {
+    fn ioprio(self) -> libc::c_int {
+        match self {
+            IoClass::RealTime(level) => 1 << IOPRIO_CLASS_SHIFT | libc::c_int::from(level.min(7)),
+            IoClass::BestEffort(level) => {
+                2 << IOPRIO_CLASS_SHIFT | libc::c_int::from(level.min(7))
+            }
+            IoClass::Idle => 3 << IOPRIO_CLASS_SHIFT,
+        }
+    }
+}
+
+/// The CPU and I/O priorities of an operation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Priority {
+    /// The niceness, from -20 (the most favorable) to 19 (the least).
+    pub nice:     i32,
+    /// The I/O scheduling class, or `None` to retain the current class.
+    pub io_class: Option<IoClass>,
+}
+
+impl Priority {
+    /// Applies the priority to the calling thread until the returned guard is dropped.
+    pub fn apply(self) -> io::Result<PriorityGuard> {
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+
+        // Niceness may legitimately be -1, so errors are distinguished by errno.
+        let nice = unsafe {
+            *libc::__errno_location() = 0;
+            libc::getpriority(libc::PRIO_PROCESS, tid)
+        };
+        if nice == -1 && io::Error::last_os_error().raw_os_error() != Some(0) {
+            return Err(io::Error::last_os_error());
+        }
+
+        let ioprio = match self.io_class {
+            Some(class) => {
+                let previous =
+                    unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, tid) };
+                if previous < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                set_ioprio(tid, class.ioprio())?;
+                Some(previous as libc::c_int)
+            }
+            None => None,
+        };
+
+        let guard = PriorityGuard { tid, nice, ioprio };
+        set_nice(tid, self.nice)?;
+        Ok(guard)
+    }
+}
+
+/// Restores the priorities that the thread had before a `Priority` was applied.
+pub struct PriorityGuard {
+    tid:    libc::id_t,
+    nice:   libc::c_int,
+    ioprio: Option<libc::c_int>,
+}
+
+impl Drop for PriorityGuard {
+    fn drop(&mut self) {
+        // Raising the priority again requires root, which the installer has.
+        let _ = set_nice(self.tid, self.nice);
+        if let Some(ioprio) = self.ioprio {
+            let _ = set_ioprio(self.tid, ioprio);
+        }
+    }
+}
+
+fn set_nice(tid: libc::id_t, nice: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice.max(-20).min(19)) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn set_ioprio(tid: libc::id_t, ioprio: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_classes() {
+        assert_eq!(IoClass::RealTime(0).ioprio(), 0x2000);
+        assert_eq!(IoClass::BestEffort(4).ioprio(), 0x4004);
+        assert_eq!(IoClass::BestEffort(9).ioprio(), 0x4007);
+        assert_eq!(IoClass::Idle.ioprio(), 0x6000);
+    }
+}
@@ -0,0 +1,254 @@
+//! Indirection over the execution of external commands, so that the logic which drives them may
+//! be tested without root access or real disks.
+
+use crate::journal;
+use once_cell::sync::Lazy;
+use std::{
+    ffi::OsString,
+    io::{self, BufReader, Read, Write},
+    mem,
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::Instant,
+};
+
+static RUNNER: Lazy<RwLock<Arc<dyn CommandRunner>>> =
+    Lazy::new(|| RwLock::new(Arc::new(SystemRunner)));
+
+/// The outcome of an external command.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandOutput {
+    /// The exit code, or `None` if the command was terminated by a signal.
+    pub code:   Option<i32>,
+    /// Standard output, if it was captured.
+    pub stdout: Vec<u8>,
+    /// Standard error, which is only captured by `run_lines`.
+    pub stderr: Vec<u8>,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool { self.code == Some(0) }
+}
+
+/// Executes the external commands that this crate depends upon.
+pub trait CommandRunner: Send + Sync {
+    /// Runs `cmd` to completion, writing `stdin` to it if it was given.
+    ///
+    /// Standard output is captured if `capture` is set, and discarded otherwise.
+    fn run(
+        &self,
+        cmd: &str,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+        capture: bool,
+    ) -> io::Result<CommandOutput>;
+
+    /// Runs `cmd` to completion as `run` does, with its output captured, and gives each line of
+    /// its standard output to `line` as it is written. Lines may also end with carriage
+    /// returns, as those which report progress do.
+    ///
+    /// By default, the lines are given once the command has exited.
+    fn run_lines(
+        &self,
+        cmd: &str,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+        line: &mut dyn FnMut(&str),
+    ) -> io::Result<CommandOutput> {
+        let output = self.run(cmd, args, stdin, true)?;
+        String::from_utf8_lossy(&output.stdout)
+            .split(|c| c == '\n' || c == '\r')
+            .filter(|text| !text.is_empty())
+            .for_each(|text| line(text));
+        Ok(output)
+    }
+}
+
+/// Executes commands as child processes, which is the default.
+///
+/// Each command is recorded in the command journal.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(
+        &self,
+        cmd: &str,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+        capture: bool,
+    ) -> io::Result<CommandOutput> {
+        let started = Instant::now();
+        let result = (|| -> io::Result<CommandOutput> {
+            let mut child = Command::new(cmd)
+                .args(args)
+                .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+                .stdout(if capture { Stdio::piped() } else { Stdio::null() })
+                .stderr(if capture { Stdio::null() } else { Stdio::inherit() })
+                .spawn()?;
+
+            if let Some(stdin) = stdin {
+                child.stdin.as_mut().expect("stdin not obtained").write_all(stdin)?;
+            }
+
+            let output = child.wait_with_output()?;
+            Ok(CommandOutput {
+                code:   output.status.code(),
+                stdout: output.stdout,
+                stderr: Vec::new(),
+            })
+        })();
+
+        let (code, stdout) = match result {
+            Ok(ref output) => (output.code, String::from_utf8_lossy(&output.stdout)),
+            Err(_) => (None, "".into()),
+        };
+
+        journal::record(cmd, args, started, code, &stdout);
+        result
+    }
+
+    fn run_lines(
+        &self,
+        cmd: &str,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+        line: &mut dyn FnMut(&str),
+    ) -> io::Result<CommandOutput> {
+        let started = Instant::now();
+        let mut stdout = Vec::new();
+        let result = (|| -> io::Result<CommandOutput> {
+            let mut child = Command::new(cmd)
+                .args(args)
+                .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+
+            // The input is closed as it is dropped, once written.
+            if let Some(stdin) = stdin {
+                let written = child.stdin.take().expect("stdin not obtained").write_all(stdin);
+                if let Err(why) = written {
+                    reap(&mut child);
+                    return Err(why);
+                }
+            }
+
+            // Errors are read while the output is, so that neither pipe fills.
+            let mut stderr = child.stderr.take().expect("stderr not obtained");
+            let errors = thread::spawn(move || {
+                let mut errors = Vec::new();
+                let _ = stderr.read_to_end(&mut errors);
+                errors
+            });
+
+            let reader = BufReader::new(child.stdout.take().expect("stdout not obtained"));
+            let mut current = Vec::new();
+            for byte in reader.bytes() {
+                let byte = match byte {
+                    Ok(byte) => byte,
+                    Err(why) => {
+                        reap(&mut child);
+                        return Err(why);
+                    }
+                };
+
+                stdout.push(byte);
+                if byte != b'\n' && byte != b'\r' {
+                    current.push(byte);
+                } else if !current.is_empty() {
+                    line(&String::from_utf8_lossy(&current));
+                    current.clear();
+                }
+            }
+
+            if !current.is_empty() {
+                line(&String::from_utf8_lossy(&current));
+            }
+
+            let code = child.wait()?.code();
+            let stderr = errors.join().unwrap_or_default();
+            Ok(CommandOutput { code, stdout: mem::replace(&mut stdout, Vec::new()), stderr })
+        })();
+
+        let (code, output) = match result {
+            Ok(ref output) => (output.code, String::from_utf8_lossy(&output.stdout)),
+            Err(_) => (None, String::from_utf8_lossy(&stdout)),
+        };
+
+        journal::record(cmd, args, started, code, &output);
+        result
+    }
+}
+
+/// Kills a child whose output may no longer be handled, and waits for it, so that it does not
+/// linger as a zombie.
+fn reap(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// A command that was given to a `MockRunner`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutedCommand {
+    pub cmd:   String,
+    pub args:  Vec<OsString>,
+    pub stdin: Option<Vec<u8>>,
+}
+
+/// Records the commands that it is given, instead of executing them.
+///
+/// Commands succeed without output, unless a response was registered for them.
+#[derive(Default)]
+pub struct MockRunner {
+    executed:  Mutex<Vec<ExecutedCommand>>,
+    responses: Mutex<Vec<(String, CommandOutput)>>,
+}
+
+impl MockRunner {
+    /// Answers every later invocation of `cmd` with `output`.
+    pub fn respond(&self, cmd: &str, output: CommandOutput) {
+        let mut responses = self.responses.lock().expect("mock responses lock poisoned");
+        responses.retain(|&(ref command, _)| command != cmd);
+        responses.push((cmd.to_owned(), output));
+    }
+
+    /// The commands that have been given to this runner, in the order that they were given.
+    pub fn executed(&self) -> Vec<ExecutedCommand> {
+        self.executed.lock().expect("mock commands lock poisoned").clone()
+    }
+}
+
+impl CommandRunner for MockRunner {
+    fn run(
+        &self,
+        cmd: &str,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+        _capture: bool,
+    ) -> io::Result<CommandOutput> {
+        self.executed.lock().expect("mock commands lock poisoned").push(ExecutedCommand {
+            cmd:   cmd.to_owned(),
+            args:  args.to_vec(),
+            stdin: stdin.map(<[u8]>::to_vec),
+        });
+
+        let responses = self.responses.lock().expect("mock responses lock poisoned");
+        Ok(responses
+            .iter()
+            .find(|&&(ref command, _)| command == cmd)
+            .map_or(CommandOutput { code: Some(0), ..CommandOutput::default() }, |(_, output)| {
+                output.clone()
+            }))
+    }
+}
+
+/// Replaces the runner that external commands are executed with, and returns the previous one.
+pub fn set_runner(runner: Arc<dyn CommandRunner>) -> Arc<dyn CommandRunner> {
+    mem::replace(&mut *RUNNER.write().expect("command runner lock poisoned"), runner)
+}
+
+/// The runner that external commands are executed with.
+pub fn runner() -> Arc<dyn CommandRunner> {
+    RUNNER.read().expect("command runner lock poisoned").clone()
+}
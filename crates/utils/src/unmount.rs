@@ -0,0 +1,189 @@
+//! Unmounting which tolerates file systems that are briefly busy.
+//!
+//! Desktop services, such as gvfs and tracker, may open files on a freshly-mounted file system
+//! and hold them for a moment. An unmount that fails with `EBUSY` is therefore retried, and
+//! then performed lazily, so that the install does not fail at its very end. The processes
+//! which held the mount are reported either way.
+
+use std::{
+    cmp::Reverse,
+    ffi::CString,
+    fmt, fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// The number of times that a busy mount is unmounted before it is unmounted lazily.
+const ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// A process which holds a file open on a mount, or resides in it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Holder {
+    pub pid:  u32,
+    pub name: String,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.pid)
+    }
+}
+
+/// Unmounts the file system at `target`, which is not an error if it is not mounted.
+pub fn unmount(target: &Path) -> io::Result<()> {
+    let ctarget = CString::new(target.as_os_str().as_bytes()).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "mount target contains a null byte")
+    })?;
+
+    for attempt in 1..=ATTEMPTS {
+        let why = match umount2(&ctarget, 0) {
+            Ok(()) => return Ok(()),
+            Err(why) => why,
+        };
+
+        match why.raw_os_error() {
+            Some(libc::EINVAL) | Some(libc::ENOENT) => return Ok(()),
+            Some(libc::EBUSY) if attempt < ATTEMPTS => {
+                info!("{} is busy; retrying the unmount", target.display());
+                thread::sleep(RETRY_DELAY);
+            }
+            Some(libc::EBUSY) => break,
+            _ => return Err(why),
+        }
+    }
+
+    let holders = describe(&holders(target));
+    match umount2(&ctarget, libc::MNT_DETACH) {
+        Ok(()) => {
+            let target = target.display();
+            warn!("{} was busy, so it was unmounted lazily; it is held by {}", target, holders);
+            Ok(())
+        }
+        Err(why) => Err(io::Error::new(
+            why.kind(),
+            format!("unable to unmount {}: {}; it is held by {}", target.display(), why, holders),
+        )),
+    }
+}
+
+/// Unmounts every file system mounted at or beneath `base`, with the most deeply nested first.
+pub fn unmount_tree(base: &Path) -> io::Result<()> {
+    // The mounts of this thread, which may be in a different mount namespace than the process.
+    let mountinfo = fs::read_to_string("/proc/thread-self/mountinfo")?;
+    for target in mounts_beneath(&mountinfo, base) {
+        unmount(&target)?;
+    }
+
+    Ok(())
+}
+
+/// The processes that have a file open beneath `target`, or which reside there.
+pub fn holders(target: &Path) -> Vec<Holder> {
+    let mut holders = Vec::new();
+    let processes = match fs::read_dir("/proc") {
+        Ok(processes) => processes,
+        Err(_) => return holders,
+    };
+
+    for process in processes.flatten() {
+        let pid = match process.file_name().to_str().and_then(|pid| pid.parse::<u32>().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        let proc = process.path();
+        let links = ["cwd", "root", "exe"].iter().map(|link| proc.join(link));
+        let fds = proc.join("fd").read_dir().into_iter().flatten().flatten().map(|fd| fd.path());
+
+        let holds = links
+            .chain(fds)
+            .filter_map(|link| fs::read_link(link).ok())
+            .any(|path| path.starts_with(target));
+
+        if holds {
+            let name = fs::read_to_string(proc.join("comm")).unwrap_or_default();
+            holders.push(Holder { pid, name: name.trim().to_owned() });
+        }
+    }
+
+    holders
+}
+
+fn describe(holders: &[Holder]) -> String {
+    if holders.is_empty() {
+        return "no process that could be found".into();
+    }
+
+    holders.iter().map(Holder::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn umount2(target: &CString, flags: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::umount2(target.as_ptr(), flags) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// The mount points of a mountinfo table that are at or beneath `base`, in the order that they
+/// must be unmounted.
+fn mounts_beneath(mountinfo: &str, base: &Path) -> Vec<PathBuf> {
+    let mut targets: Vec<PathBuf> = mountinfo
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .map(|target| PathBuf::from(unescape(target)))
+        .filter(|target| target.starts_with(base))
+        .collect();
+
+    // Mounts made later may be stacked upon earlier mounts at the same target, and each of
+    // them must be unmounted in turn.
+    targets.reverse();
+    targets.sort_by_key(|target| Reverse(target.components().count()));
+    targets
+}
+
+/// Decodes the octal escapes of spaces, tabs, and newlines in mountinfo paths.
+fn unescape(field: &str) -> String {
+    field
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmount_order() {
+        let mountinfo = "\
+22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw
+90 22 8:17 / /tmp/distinst.ab12 rw,relatime - ext4 /dev/sdb1 rw
+91 90 8:18 / /tmp/distinst.ab12/home rw,relatime - ext4 /dev/sdb2 rw
+92 90 8:16 / /tmp/distinst.ab12/boot/efi rw,relatime - vfat /dev/sdb0 rw
+93 90 0:5 / /tmp/distinst.ab12/dev rw - devtmpfs udev rw
+94 93 0:6 / /tmp/distinst.ab12/dev/pts rw - devpts devpts rw
+95 22 8:19 / /media/My\\040Files rw,relatime - ext4 /dev/sdb3 rw
+";
+
+        assert_eq!(
+            mounts_beneath(mountinfo, Path::new("/tmp/distinst.ab12")),
+            vec![
+                PathBuf::from("/tmp/distinst.ab12/dev/pts"),
+                PathBuf::from("/tmp/distinst.ab12/boot/efi"),
+                PathBuf::from("/tmp/distinst.ab12/dev"),
+                PathBuf::from("/tmp/distinst.ab12/home"),
+                PathBuf::from("/tmp/distinst.ab12"),
+            ]
+        );
+
+        assert_eq!(
+            mounts_beneath(mountinfo, Path::new("/media/My Files")),
+            vec![PathBuf::from("/media/My Files")]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Extended attributes, which carry ACLs, file capabilities, and security labels.
+//!
+//! Symlinks are never followed, so that the attributes of a link are not confused with those
+//! of its target.
+
+use std::{
+    ffi::{CString, OsStr, OsString},
+    io,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::Path,
+    ptr,
+};
+
+/// The attribute which holds the file capabilities of a binary, such as `cap_net_raw`.
+pub const CAPABILITY: &str = "security.capability";
+
+fn cstr(bytes: &[u8]) -> io::Result<CString> {
+    CString::new(bytes).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "path or attribute contains a null byte")
+    })
+}
+
+/// Calls `func` with a buffer large enough for the attribute data, which is queried first.
+fn with_buffer<F: Fn(*mut libc::c_void, usize) -> isize>(func: F) -> io::Result<Vec<u8>> {
+    loop {
+        let size = func(ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buffer = vec![0u8; size as usize];
+        let read = func(buffer.as_mut_ptr() as *mut libc::c_void, buffer.len());
+        if read >= 0 {
+            buffer.truncate(read as usize);
+            return Ok(buffer);
+        }
+
+        // The attributes grew between the two calls.
+        let why = io::Error::last_os_error();
+        if why.raw_os_error() != Some(libc::ERANGE) {
+            return Err(why);
+        }
+    }
+}
+
+fn is_unsupported(why: &io::Error) -> bool {
+    why.raw_os_error() == Some(libc::ENOTSUP) || why.raw_os_error() == Some(libc::ENODATA)
+}
+
+/// Obtains the value of an attribute, or `None` if the file does not have it.
+pub fn get<N: AsRef<OsStr>>(path: &Path, name: N) -> io::Result<Option<Vec<u8>>> {
+    let cpath = cstr(path.as_os_str().as_bytes())?;
+    let cname = cstr(name.as_ref().as_bytes())?;
+    let result = with_buffer(|buffer, size| unsafe {
+        libc::lgetxattr(cpath.as_ptr(), cname.as_ptr(), buffer, size)
+    });
+
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ref why) if is_unsupported(why) => Ok(None),
+        Err(why) => Err(why),
+    }
+}
+
+/// Obtains the names and values of every attribute of the file.
+///
+/// File systems which do not support extended attributes yield none.
+pub fn list(path: &Path) -> io::Result<Vec<(OsString, Vec<u8>)>> {
+    let cpath = cstr(path.as_os_str().as_bytes())?;
+    let names = match with_buffer(|buffer, size| unsafe {
+        libc::llistxattr(cpath.as_ptr(), buffer as *mut libc::c_char, size)
+    }) {
+        Ok(names) => names,
+        Err(ref why) if is_unsupported(why) => return Ok(Vec::new()),
+        Err(why) => return Err(why),
+    };
+
+    let mut attributes = Vec::new();
+    for name in split_names(&names) {
+        // The attribute may have been removed since it was listed.
+        if let Some(value) = get(path, &name)? {
+            attributes.push((name, value));
+        }
+    }
+
+    Ok(attributes)
+}
+
+/// Sets the value of an attribute.
+pub fn set<N: AsRef<OsStr>>(path: &Path, name: N, value: &[u8]) -> io::Result<()> {
+    let cpath = cstr(path.as_os_str().as_bytes())?;
+    let cname = cstr(name.as_ref().as_bytes())?;
+    let result = unsafe {
+        libc::lsetxattr(
+            cpath.as_ptr(),
+            cname.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Applies each of the `attributes` to the file at `path`.
+///
+/// This should be done after the file's ownership has been set, as the kernel clears the file
+/// capabilities of a file when its owner changes.
+pub fn apply(path: &Path, attributes: &[(OsString, Vec<u8>)]) -> io::Result<()> {
+    for &(ref name, ref value) in attributes {
+        set(path, name, value).map_err(|why| {
+            io::Error::new(
+                why.kind(),
+                format!("unable to set {:?} on {}: {}", name, path.display(), why),
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+fn split_names(names: &[u8]) -> Vec<OsString> {
+    names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| OsString::from_vec(name.to_vec()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_names() {
+        let names = b"security.capability\0system.posix_acl_access\0user.comment\0";
+        assert_eq!(
+            split_names(names),
+            vec![
+                OsString::from("security.capability"),
+                OsString::from("system.posix_acl_access"),
+                OsString::from("user.comment"),
+            ]
+        );
+        assert!(split_names(b"").is_empty());
+    }
+}
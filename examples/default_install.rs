@@ -35,6 +35,7 @@ fn main() {
         lang:             "en_US.UTF-8".into(),
         remove:           "/cdrom/casper/filesystem.manifest-remove".into(),
         squashfs:         "/cdrom/casper/filesystem.squashfs".into(),
+        ..Config::default()
     };
 
     eprintln!("Options: {:#?}", options);
@@ -203,6 +204,9 @@ fn main() {
                     Step::Extract => "Extracting filesystem ",
                     Step::Configure => "Configuring installation",
                     Step::Bootloader => "Installing bootloader ",
+                    Step::FactoryImage => "Capturing factory image ",
+                    Step::Migrate => "Migrating user data ",
+                    Step::Export => "Exporting tarball ",
                 });
                 *pb_opt.borrow_mut() = Some(pb);
             }
@@ -1,12 +1,14 @@
 use libc;
 
-use super::{gen_object_ptr, get_str, null_check, DistinstDisks, DistinstOsRelease};
+use super::{
+    gen_object_ptr, get_str, null_check, DistinstConfigBuilder, DistinstDisks, DistinstOsRelease,
+};
 use distinst::{
     auto::{
         AlongsideMethod, AlongsideOption, EraseOption, InstallOption, InstallOptions,
         RecoveryOption, RefreshOption,
     },
-    Disks, OS,
+    Config, Disks, OS,
 };
 use std::{os::unix::ffi::OsStrExt, ptr};
 
@@ -226,6 +228,37 @@ pub unsafe extern "C" fn distinst_refresh_option_get_root_part(
     output.as_ptr()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_refresh_option_is_encrypted(
+    option: *const DistinstRefreshOption,
+) -> bool {
+    if null_check(option).is_err() {
+        return false;
+    }
+
+    (&*(option as *const RefreshOption)).encrypted
+}
+
+/// The release of the newest kernel which is installed, or null if none were found.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_refresh_option_get_kernel(
+    option: *const DistinstRefreshOption,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    if null_check(option).or_else(|_| null_check(len)).is_err() {
+        return ptr::null();
+    }
+
+    let option = &*(option as *const RefreshOption);
+    match option.kernels.first() {
+        Some(kernel) => {
+            *len = kernel.len() as libc::c_int;
+            kernel.as_ptr()
+        }
+        None => ptr::null(),
+    }
+}
+
 #[repr(C)]
 pub struct DistinstEraseOption;
 
@@ -600,6 +633,28 @@ pub unsafe extern "C" fn distinst_install_option_apply(
     }
 }
 
+/// As `distinst_install_option_apply`, with the partition sizes of the builder's config.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_option_apply_with_config(
+    option: *const DistinstInstallOption,
+    disks: *mut DistinstDisks,
+    builder: *const DistinstConfigBuilder,
+) -> libc::c_int {
+    if null_check(disks).or_else(|_| null_check(option)).or_else(|_| null_check(builder)).is_err()
+    {
+        return libc::EIO;
+    }
+
+    let sizes = &(*(builder as *const Config)).partition_sizes;
+    match InstallOption::from(&*option).apply_with_sizes(&mut *(disks as *mut Disks), sizes) {
+        Ok(()) => 0,
+        Err(why) => {
+            error!("failed to apply install option: {}", why);
+            -1
+        }
+    }
+}
+
 #[repr(C)]
 pub struct DistinstInstallOptions;
 
@@ -1,7 +1,13 @@
-use distinst::{Config, UserAccountCreate};
-use crate::get_str;
+use distinst::{
+    AptSources, BootDefault, BootloaderOverride, Branding, CifsCredentials, CloudInit, Config,
+    DirectoryEnrollment, FirewallPolicy, InitramfsCompression, InterfaceKind, IoClass,
+    JoinCredential, KernelSelection, KeyboardLayout, MountProtocol, NetworkConfig,
+    NetworkInterface, NetworkMount, OldBootPolicy, PartitionSizes, Priority, ReadOnlyRoot,
+    SerialConsole, SnapshotTool, SshServer, SudoPolicy, UserAccountCreate, WindowsMigration,
+};
+use crate::{gen_object_ptr, get_str, null_check};
 use libc;
-use std::io;
+use std::{io, net::IpAddr, path::PathBuf};
 
 /// Installer configuration
 #[repr(C)]
@@ -30,16 +36,1268 @@ impl DistinstConfig {
             old_root:         get_str(self.old_root).ok().map(String::from),
             remove:           get_str(self.remove)?.to_string(),
             flags:            self.flags,
+            ..Config::default()
         })
     }
 }
 
+/// Installer configuration which is constructed through setters, so that new options may be
+/// added without changing the layout of a C struct.
+///
+/// Setters return `0` on success, or `EINVAL` if the input was null or not UTF-8.
+#[repr(C)]
+pub struct DistinstConfigBuilder;
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_new() -> *mut DistinstConfigBuilder {
+    gen_object_ptr(Config::default()) as *mut DistinstConfigBuilder
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_destroy(builder: *mut DistinstConfigBuilder) {
+    if builder.is_null() {
+        error!("DistinstConfigBuilder was to be destroyed even though it is null");
+    } else {
+        Box::from_raw(builder as *mut Config);
+    }
+}
+
+/// Applies a string to the config of a `DistinstConfigBuilder`.
+unsafe fn builder_set<F: FnOnce(&mut Config, &str)>(
+    builder: *mut DistinstConfigBuilder,
+    value: *const libc::c_char,
+    action: F,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    match get_str(value) {
+        Ok(value) => {
+            action(&mut *(builder as *mut Config), value);
+            0
+        }
+        Err(_) => libc::EINVAL,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_hostname(
+    builder: *mut DistinstConfigBuilder,
+    hostname: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, hostname, |config, value| config.hostname = value.to_owned())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_keyboard_layout(
+    builder: *mut DistinstConfigBuilder,
+    layout: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, layout, |config, value| config.keyboard_layout = value.to_owned())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_keyboard_model(
+    builder: *mut DistinstConfigBuilder,
+    model: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, model, |config, value| config.keyboard_model = Some(value.to_owned()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_keyboard_variant(
+    builder: *mut DistinstConfigBuilder,
+    variant: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, variant, |config, value| {
+        config.keyboard_variant = Some(value.to_owned())
+    })
+}
+
+/// Adds a layout which may be switched to from the primary layout, after those which were
+/// added before it. The variant may be null.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_keyboard_layout(
+    builder: *mut DistinstConfigBuilder,
+    layout: *const libc::c_char,
+    variant: *const libc::c_char,
+) -> libc::c_int {
+    let variant = if variant.is_null() {
+        None
+    } else {
+        match get_str(variant) {
+            Ok(variant) => Some(variant.to_owned()),
+            Err(_) => return libc::EINVAL,
+        }
+    };
+
+    builder_set(builder, layout, |config, value| {
+        config.keyboard_layouts.push(KeyboardLayout { layout: value.to_owned(), variant })
+    })
+}
+
+/// Sets the XKB options, such as `grp:alt_shift_toggle`, which switch between the layouts.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_keyboard_options(
+    builder: *mut DistinstConfigBuilder,
+    options: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, options, |config, value| {
+        config.keyboard_options = Some(value.to_owned())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_old_root(
+    builder: *mut DistinstConfigBuilder,
+    uuid: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, uuid, |config, value| config.old_root = Some(value.to_owned()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_lang(
+    builder: *mut DistinstConfigBuilder,
+    lang: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, lang, |config, value| config.lang = value.to_owned())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_remove(
+    builder: *mut DistinstConfigBuilder,
+    remove: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, remove, |config, value| config.remove = value.to_owned())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_squashfs(
+    builder: *mut DistinstConfigBuilder,
+    squashfs: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, squashfs, |config, value| config.squashfs = value.to_owned())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_flags(
+    builder: *mut DistinstConfigBuilder,
+    flags: u8,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).flags = flags;
+    0
+}
+
+/// Limits the number of threads which decompress the image. `0` uses one thread per CPU.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_extract_threads(
+    builder: *mut DistinstConfigBuilder,
+    threads: u32,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).extract_threads =
+        if threads == 0 { None } else { Some(threads as usize) };
+    0
+}
+
+/// Compresses the extracted files of the Btrfs file systems which are reused, rather than
+/// formatted with compression, reclaiming the space of files which were written uncompressed.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_btrfs_recompress(
+    builder: *mut DistinstConfigBuilder,
+    recompress: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).btrfs_recompress = recompress;
+    0
+}
+
+/// The I/O scheduling class of a priority.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_IO_CLASS {
+    /// Retains the current class.
+    NONE = 0,
+    REALTIME = 1,
+    BEST_EFFORT = 2,
+    IDLE = 3,
+}
+
+/// Sets the niceness and I/O class of the heavy steps of the install, such as extraction,
+/// which keeps a live session responsive while the install proceeds in the background.
+///
+/// The `level` of the realtime and best-effort classes ranges from 0 (the highest) to 7.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_priority(
+    builder: *mut DistinstConfigBuilder,
+    nice: i32,
+    io_class: DISTINST_IO_CLASS,
+    level: u8,
+) -> libc::c_int {
+    if null_check(builder).is_err() || nice < -20 || nice > 19 || level > 7 {
+        return libc::EINVAL;
+    }
+
+    let io_class = match io_class {
+        DISTINST_IO_CLASS::NONE => None,
+        DISTINST_IO_CLASS::REALTIME => Some(IoClass::RealTime(level)),
+        DISTINST_IO_CLASS::BEST_EFFORT => Some(IoClass::BestEffort(level)),
+        DISTINST_IO_CLASS::IDLE => Some(IoClass::Idle),
+    };
+
+    (*(builder as *mut Config)).priority = Some(Priority { nice, io_class });
+    0
+}
+
+/// Creates the chroot mount point and other temporary files in this directory, rather than
+/// in the system's temporary directory. It must not be on a disk that will be modified.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_temp_dir(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| config.temp_dir = Some(PathBuf::from(value)))
+}
+
+/// A tool which takes periodic snapshots of the installed system.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_SNAPSHOT_TOOL {
+    NONE = 0,
+    /// Requires the root file system to be Btrfs.
+    SNAPPER = 1,
+    TIMESHIFT = 2,
+}
+
+/// Installs and configures a tool which takes periodic snapshots of the root file system.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_snapshots(
+    builder: *mut DistinstConfigBuilder,
+    tool: DISTINST_SNAPSHOT_TOOL,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).snapshots = match tool {
+        DISTINST_SNAPSHOT_TOOL::NONE => None,
+        DISTINST_SNAPSHOT_TOOL::SNAPPER => Some(SnapshotTool::Snapper),
+        DISTINST_SNAPSHOT_TOOL::TIMESHIFT => Some(SnapshotTool::Timeshift),
+    };
+    0
+}
+
+fn apt_sources(config: &mut Config) -> &mut AptSources {
+    config.apt_sources.get_or_insert_with(|| AptSources::new(""))
+}
+
+/// Replaces the image's apt sources with this archive mirror, such as
+/// `http://archive.ubuntu.com/ubuntu`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_apt_mirror(
+    builder: *mut DistinstConfigBuilder,
+    mirror: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, mirror, |config, value| apt_sources(config).mirror = value.to_owned())
+}
+
+/// Enables a component of the apt mirror. The default components are enabled if none are added.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_apt_component(
+    builder: *mut DistinstConfigBuilder,
+    component: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, component, |config, value| {
+        apt_sources(config).components.push(value.to_owned())
+    })
+}
+
+/// Toggles the `-updates` and `-proposed` pockets of the apt mirror.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_apt_pockets(
+    builder: *mut DistinstConfigBuilder,
+    updates: bool,
+    proposed: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let sources = apt_sources(&mut *(builder as *mut Config));
+    sources.updates = updates;
+    sources.proposed = proposed;
+    0
+}
+
+/// Synchronizes the clock of the installed system with this NTP server.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_ntp_server(
+    builder: *mut DistinstConfigBuilder,
+    server: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, server, |config, value| config.ntp_servers.push(value.to_owned()))
+}
+
+/// The time that the hardware clock is kept in.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_RTC {
+    /// Local time if Windows remains installed, as Windows expects it, or UTC otherwise.
+    AUTO = 0,
+    UTC = 1,
+    LOCAL = 2,
+}
+
+/// Keeps the hardware clock of the installed system in local time or UTC.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_rtc(
+    builder: *mut DistinstConfigBuilder,
+    rtc: DISTINST_RTC,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).local_rtc = match rtc {
+        DISTINST_RTC::AUTO => None,
+        DISTINST_RTC::UTC => Some(false),
+        DISTINST_RTC::LOCAL => Some(true),
+    };
+    0
+}
+
+/// Sets the font of the consoles, such as `Terminus` and `16x32` for HiDPI displays. Either may
+/// be null, which keeps the image's default.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_console_font(
+    builder: *mut DistinstConfigBuilder,
+    face: *const libc::c_char,
+    size: *const libc::c_char,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let optional = |field: *const libc::c_char| -> io::Result<Option<String>> {
+        if field.is_null() {
+            Ok(None)
+        } else {
+            get_str(field).map(|string| Some(string.to_string()))
+        }
+    };
+
+    let (face, size) = match (optional(face), optional(size)) {
+        (Ok(face), Ok(size)) => (face, size),
+        _ => return libc::EINVAL,
+    };
+
+    let console = (*(builder as *mut Config)).console.get_or_insert_with(Default::default);
+    console.font_face = face;
+    console.font_size = size;
+    0
+}
+
+/// The state of numlock once the installed system boots.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_NUMLOCK {
+    /// Left as the image and the firmware set it.
+    UNCHANGED = 0,
+    ON = 1,
+    OFF = 2,
+}
+
+/// Turns numlock on or off at boot, on the consoles and the login screen.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_numlock(
+    builder: *mut DistinstConfigBuilder,
+    numlock: DISTINST_NUMLOCK,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let console = (*(builder as *mut Config)).console.get_or_insert_with(Default::default);
+    console.numlock = match numlock {
+        DISTINST_NUMLOCK::UNCHANGED => None,
+        DISTINST_NUMLOCK::ON => Some(true),
+        DISTINST_NUMLOCK::OFF => Some(false),
+    };
+    0
+}
+
+/// Boots with the console on the serial port `port`, such as `ttyS0`, at `baud`, such as
+/// 115200, and starts a getty on it. A null port disables the serial console.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_serial_console(
+    builder: *mut DistinstConfigBuilder,
+    port: *const libc::c_char,
+    baud: u32,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let console = (*(builder as *mut Config)).console.get_or_insert_with(Default::default);
+    if port.is_null() {
+        console.serial = None;
+        return 0;
+    }
+
+    match get_str(port) {
+        Ok(port) => {
+            console.serial = Some(SerialConsole { port: port.to_owned(), baud });
+            0
+        }
+        Err(_) => libc::EINVAL,
+    }
+}
+
+/// The entry of the boot menu which is booted once its timeout expires.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_BOOT_DEFAULT {
+    INSTALLED = 0,
+    LAST_BOOTED = 1,
+}
+
+/// Shows the boot menu for `timeout` seconds, or boots the default entry at once if it is 0.
+/// A negative timeout shows the menu for a few seconds if another system remains installed,
+/// and otherwise leaves it as the image configured it.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_boot_timeout(
+    builder: *mut DistinstConfigBuilder,
+    timeout: libc::c_int,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).boot_menu.timeout =
+        if timeout < 0 { None } else { Some(timeout as u32) };
+    0
+}
+
+/// Selects the entry of the boot menu which is booted by default.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_boot_default(
+    builder: *mut DistinstConfigBuilder,
+    entry: DISTINST_BOOT_DEFAULT,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).boot_menu.default = match entry {
+        DISTINST_BOOT_DEFAULT::INSTALLED => BootDefault::Installed,
+        DISTINST_BOOT_DEFAULT::LAST_BOOTED => BootDefault::LastBooted,
+    };
+    0
+}
+
+/// The policy of the firewall towards incoming connections.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_FIREWALL {
+    /// The firewall is not enabled.
+    NONE = 0,
+    ALLOW = 1,
+    DENY = 2,
+    REJECT = 3,
+}
+
+/// Installs and enables ufw, with the given policy towards incoming connections.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_firewall(
+    builder: *mut DistinstConfigBuilder,
+    policy: DISTINST_FIREWALL,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).firewall = match policy {
+        DISTINST_FIREWALL::NONE => None,
+        DISTINST_FIREWALL::ALLOW => Some(FirewallPolicy::Allow),
+        DISTINST_FIREWALL::DENY => Some(FirewallPolicy::Deny),
+        DISTINST_FIREWALL::REJECT => Some(FirewallPolicy::Reject),
+    };
+    0
+}
+
+/// Installs and enables an OpenSSH server.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_ssh_server(
+    builder: *mut DistinstConfigBuilder,
+    enable: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let config = &mut *(builder as *mut Config);
+    if !enable {
+        config.ssh_server = None;
+    } else if config.ssh_server.is_none() {
+        config.ssh_server = Some(SshServer::default());
+    }
+    0
+}
+
+/// Seeds the image with cloud-init, which creates the users, and sets the hostname, on the
+/// first boot of each instance. The config must be in image mode.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_cloud_init(
+    builder: *mut DistinstConfigBuilder,
+    enable: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let config = &mut *(builder as *mut Config);
+    if !enable {
+        config.cloud_init = None;
+    } else if config.cloud_init.is_none() {
+        config.cloud_init = Some(CloudInit::default());
+    }
+    0
+}
+
+/// Adds a datasource, such as `NoCloud` or `Ec2`, to those which cloud-init searches, in
+/// order, and seeds the image with cloud-init.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_cloud_init_datasource(
+    builder: *mut DistinstConfigBuilder,
+    datasource: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, datasource, |config, value| {
+        config.cloud_init.get_or_insert_with(CloudInit::default).datasources.push(value.to_owned())
+    })
+}
+
+/// Installs the cloud-config file at `path` to `/etc/cloud/cloud.cfg.d` of the image, and
+/// seeds the image with cloud-init.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_cloud_init_config(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| {
+        config.cloud_init.get_or_insert_with(CloudInit::default).config =
+            Some(PathBuf::from(value))
+    })
+}
+
+/// Scales the desktop and enlarges the console font if a HiDPI panel is detected.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_hidpi(
+    builder: *mut DistinstConfigBuilder,
+    enable: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).hidpi = enable;
+    0
+}
+
+/// Logs the created user in automatically, through the display manager of the image.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_autologin(
+    builder: *mut DistinstConfigBuilder,
+    enable: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).autologin = enable;
+    0
+}
+
+/// Copies the desktop settings which the live user, whose home is `home`, changed during the
+/// live session, such as the theme and the dock, to the created user.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_live_settings(
+    builder: *mut DistinstConfigBuilder,
+    home: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, home, |config, value| config.live_settings = Some(PathBuf::from(value)))
+}
+
+/// Embeds a keyfile in the initramfs, which unlocks the encrypted volumes once GRUB has
+/// unlocked the encrypted `/boot`, so that the passphrase is typed only once at boot.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_initrd_keyfile(
+    builder: *mut DistinstConfigBuilder,
+    enable: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).initrd_keyfile = enable;
+    0
+}
+
+/// Benchmarks writes to the target media before extracting the image, warning with
+/// `SLOW_MEDIA` if they are too slow.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_benchmark_target(
+    builder: *mut DistinstConfigBuilder,
+    enable: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).benchmark_target = enable;
+    0
+}
+
+/// Authorizes a public key to log in as the created user over SSH, and enables the server.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_ssh_key(
+    builder: *mut DistinstConfigBuilder,
+    key: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, key, |config, value| {
+        config
+            .ssh_server
+            .get_or_insert_with(SshServer::default)
+            .authorized_keys
+            .push(value.to_owned())
+    })
+}
+
+/// Applies a value to the named interface of the static network configuration, which is
+/// defined as an ethernet interface if it was not already defined.
+///
+/// Returns `EINVAL` if an input was null or not UTF-8, or if `action` rejects the value.
+unsafe fn network_set<F: FnOnce(&mut NetworkInterface, &str) -> Option<()>>(
+    builder: *mut DistinstConfigBuilder,
+    iface: *const libc::c_char,
+    value: *const libc::c_char,
+    action: F,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let (iface, value) = match (get_str(iface), get_str(value)) {
+        (Ok(iface), Ok(value)) => (iface, value),
+        _ => return libc::EINVAL,
+    };
+
+    let config = &mut *(builder as *mut Config);
+    let network = config.network.get_or_insert_with(NetworkConfig::default);
+    match action(network.interface(iface), value) {
+        Some(()) => 0,
+        None => libc::EINVAL,
+    }
+}
+
+/// Defines a bond of the comma-separated ethernet `interfaces`, with a bonding `mode` such as
+/// `802.3ad`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_network_bond(
+    builder: *mut DistinstConfigBuilder,
+    name: *const libc::c_char,
+    mode: *const libc::c_char,
+    interfaces: *const libc::c_char,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let (name, mode, interfaces) = match (get_str(name), get_str(mode), get_str(interfaces)) {
+        (Ok(name), Ok(mode), Ok(interfaces)) => (name, mode, interfaces),
+        _ => return libc::EINVAL,
+    };
+
+    let config = &mut *(builder as *mut Config);
+    let network = config.network.get_or_insert_with(NetworkConfig::default);
+    let members: Vec<String> = interfaces.split(',').map(String::from).collect();
+    for member in &members {
+        network.interface(member);
+    }
+
+    network.interface(name).kind = InterfaceKind::Bond { interfaces: members, mode: mode.into() };
+    0
+}
+
+/// Defines a VLAN with the given `id` on the `link` interface.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_network_vlan(
+    builder: *mut DistinstConfigBuilder,
+    name: *const libc::c_char,
+    id: u16,
+    link: *const libc::c_char,
+) -> libc::c_int {
+    network_set(builder, name, link, |iface, link| {
+        iface.kind = InterfaceKind::Vlan { id, link: link.to_owned() };
+        Some(())
+    })
+}
+
+/// Assigns a static address, such as `192.0.2.10/24`, to an interface.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_network_address(
+    builder: *mut DistinstConfigBuilder,
+    iface: *const libc::c_char,
+    address: *const libc::c_char,
+) -> libc::c_int {
+    network_set(builder, iface, address, |iface, address| {
+        let mut address = address.splitn(2, '/');
+        let ip = address.next()?.parse::<IpAddr>().ok()?;
+        let prefix = address.next()?.parse::<u8>().ok()?;
+        iface.addresses.push((ip, prefix));
+        Some(())
+    })
+}
+
+/// Routes the traffic of an interface's address family through a default gateway.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_network_gateway(
+    builder: *mut DistinstConfigBuilder,
+    iface: *const libc::c_char,
+    gateway: *const libc::c_char,
+) -> libc::c_int {
+    network_set(builder, iface, gateway, |iface, gateway| {
+        iface.gateways.push(gateway.parse().ok()?);
+        Some(())
+    })
+}
+
+/// Adds a DNS server of an interface.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_network_nameserver(
+    builder: *mut DistinstConfigBuilder,
+    iface: *const libc::c_char,
+    server: *const libc::c_char,
+) -> libc::c_int {
+    network_set(builder, iface, server, |iface, server| {
+        iface.nameservers.push(server.parse().ok()?);
+        Some(())
+    })
+}
+
+/// Adds a domain which is searched for unqualified host names on an interface.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_network_search(
+    builder: *mut DistinstConfigBuilder,
+    iface: *const libc::c_char,
+    domain: *const libc::c_char,
+) -> libc::c_int {
+    network_set(builder, iface, domain, |iface, domain| {
+        iface.search.push(domain.to_owned());
+        Some(())
+    })
+}
+
+/// The protocol which a network mount is served with.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_MOUNT_PROTOCOL {
+    NFS = 0,
+    CIFS = 1,
+}
+
+/// Adds a network share to the fstab, which is mounted at `target` with `_netdev,nofail`.
+///
+/// `options` are comma-separated mount options, and may be null.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_network_mount(
+    builder: *mut DistinstConfigBuilder,
+    protocol: DISTINST_MOUNT_PROTOCOL,
+    source: *const libc::c_char,
+    target: *const libc::c_char,
+    options: *const libc::c_char,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let (source, target) = match (get_str(source), get_str(target)) {
+        (Ok(source), Ok(target)) => (source, target),
+        _ => return libc::EINVAL,
+    };
+
+    let options = match get_str(options) {
+        Ok(options) => options.split(',').map(String::from).collect(),
+        Err(_) => Vec::new(),
+    };
+
+    (*(builder as *mut Config)).network_mounts.push(NetworkMount {
+        protocol: match protocol {
+            DISTINST_MOUNT_PROTOCOL::NFS => MountProtocol::Nfs,
+            DISTINST_MOUNT_PROTOCOL::CIFS => MountProtocol::Cifs,
+        },
+        source: source.to_owned(),
+        target: PathBuf::from(target),
+        options,
+        credentials: None,
+    });
+    0
+}
+
+/// Mounts the CIFS share at `target` with the given account. `domain` may be null.
+///
+/// Returns `ENOENT` if no share was added with that target.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_network_mount_credentials(
+    builder: *mut DistinstConfigBuilder,
+    target: *const libc::c_char,
+    username: *const libc::c_char,
+    password: *const libc::c_char,
+    domain: *const libc::c_char,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let (target, username, password) =
+        match (get_str(target), get_str(username), get_str(password)) {
+            (Ok(target), Ok(username), Ok(password)) => (target, username, password),
+            _ => return libc::EINVAL,
+        };
+
+    let config = &mut *(builder as *mut Config);
+    match config.network_mounts.iter_mut().find(|mount| mount.target.as_os_str() == target) {
+        Some(mount) => {
+            mount.credentials = Some(CifsCredentials {
+                username: username.to_owned(),
+                password: password.to_owned(),
+                domain:   get_str(domain).ok().map(String::from),
+            });
+            0
+        }
+        None => libc::ENOENT,
+    }
+}
+
+/// Joins a directory domain with realmd once the installed system is online, using an
+/// account which may join computers to the domain. `computer_ou` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_directory_password(
+    builder: *mut DistinstConfigBuilder,
+    domain: *const libc::c_char,
+    user: *const libc::c_char,
+    password: *const libc::c_char,
+    computer_ou: *const libc::c_char,
+) -> libc::c_int {
+    let credential = match (get_str(user), get_str(password)) {
+        (Ok(user), Ok(password)) => {
+            JoinCredential::Password { user: user.to_owned(), password: password.to_owned() }
+        }
+        _ => return libc::EINVAL,
+    };
+
+    set_directory(builder, domain, credential, computer_ou)
+}
+
+/// Joins a directory domain with realmd once the installed system is online, using a
+/// one-time password which was issued for this computer. `computer_ou` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_directory_otp(
+    builder: *mut DistinstConfigBuilder,
+    domain: *const libc::c_char,
+    password: *const libc::c_char,
+    computer_ou: *const libc::c_char,
+) -> libc::c_int {
+    let credential = match get_str(password) {
+        Ok(password) => JoinCredential::OneTimePassword(password.to_owned()),
+        Err(_) => return libc::EINVAL,
+    };
+
+    set_directory(builder, domain, credential, computer_ou)
+}
+
+unsafe fn set_directory(
+    builder: *mut DistinstConfigBuilder,
+    domain: *const libc::c_char,
+    credential: JoinCredential,
+    computer_ou: *const libc::c_char,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let domain = match get_str(domain) {
+        Ok(domain) => domain.to_owned(),
+        Err(_) => return libc::EINVAL,
+    };
+
+    (*(builder as *mut Config)).directory = Some(DirectoryEnrollment {
+        domain,
+        credential,
+        computer_ou: get_str(computer_ou).ok().map(String::from),
+    });
+    0
+}
+
+/// Adds a random recovery key to each LUKS volume which is created with a password.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_recovery_key(
+    builder: *mut DistinstConfigBuilder,
+    enable: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).recovery_key = enable;
+    0
+}
+
+/// Writes the recovery keys to a file which is ready to be printed, and enables them.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_recovery_key_file(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| {
+        config.recovery_key = true;
+        config.recovery_key_file = Some(PathBuf::from(value));
+    })
+}
+
+/// Installs a root which is read-only and verified by dm-verity, whose hash tree is written
+/// to `hash_partition`, and whose changes are written to the formatted `overlay_partition`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_read_only_root(
+    builder: *mut DistinstConfigBuilder,
+    hash_partition: *const libc::c_char,
+    overlay_partition: *const libc::c_char,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let (hash, overlay) = match (get_str(hash_partition), get_str(overlay_partition)) {
+        (Ok(hash), Ok(overlay)) => (hash, overlay),
+        _ => return libc::EINVAL,
+    };
+
+    (*(builder as *mut Config)).read_only_root = Some(ReadOnlyRoot {
+        hash_partition:    PathBuf::from(hash),
+        overlay_partition: PathBuf::from(overlay),
+    });
+    0
+}
+
+fn branding(config: &mut Config) -> &mut Branding {
+    config.branding.get_or_insert_with(Branding::default)
+}
+
+/// Writes the name of the system's vendor to `/etc/os-release` as `VENDOR_NAME`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_vendor_name(
+    builder: *mut DistinstConfigBuilder,
+    name: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, name, |config, value| branding(config).vendor_name = Some(value.into()))
+}
+
+/// Writes the website of the system's vendor to `/etc/os-release` as `VENDOR_URL`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_vendor_url(
+    builder: *mut DistinstConfigBuilder,
+    url: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, url, |config, value| branding(config).vendor_url = Some(value.into()))
+}
+
+/// Installs a dconf keyfile, whose settings become the defaults of every user.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_dconf_defaults(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| branding(config).dconf = Some(PathBuf::from(value)))
+}
+
+/// Installs an image as the default wallpaper.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_wallpaper(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| {
+        branding(config).wallpaper = Some(PathBuf::from(value))
+    })
+}
+
+/// Installs the text of the vendor's license agreement to `/usr/share/oem/EULA.txt`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_eula(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| branding(config).eula = Some(PathBuf::from(value)))
+}
+
+/// Installs the vendor's `.deb` packages in `path`, such as `/cdrom/pool/oem`, along with
+/// their dependencies.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_oem_packages(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| config.oem_packages = Some(PathBuf::from(value)))
+}
+
+/// Adds a file or directory to carry over from the old root.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_preserved_path(
+    builder: *mut DistinstConfigBuilder,
+    path: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, path, |config, value| config.preserved_paths.push(PathBuf::from(value)))
+}
+
+/// Copies the data of the given Windows user on `device` into the home of the new user.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_windows_migration(
+    builder: *mut DistinstConfigBuilder,
+    device: *const libc::c_char,
+    user: *const libc::c_char,
+) -> libc::c_int {
+    let user = match get_str(user) {
+        Ok(user) => user.to_owned(),
+        Err(_) => return libc::EINVAL,
+    };
+
+    builder_set(builder, device, |config, value| {
+        config.windows_migration = Some(WindowsMigration { device: PathBuf::from(value), user })
+    })
+}
+
+/// Sets the sizes, in bytes, of the ESP, recovery, and swap partitions which the auto install
+/// options create. A recovery or swap size of `0` creates no such partition.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_partition_sizes(
+    builder: *mut DistinstConfigBuilder,
+    esp: u64,
+    recovery: u64,
+    swap: u64,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let sizes = PartitionSizes { esp, recovery, swap };
+    if sizes.validate().is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).partition_sizes = sizes;
+    0
+}
+
+/// What becomes of the ESP boot files of the installs which a recovery reinstall replaces.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_OLD_BOOTS {
+    REMOVE = 0,
+    /// Logs the boot files which would be removed, without removing them.
+    DRY_RUN = 1,
+    SKIP = 2,
+}
+
+/// Sets what becomes of the boot files of replaced installs. The boot files of the `keep` most
+/// recently replaced installs are kept, unless the policy is to skip their removal entirely.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_old_boots(
+    builder: *mut DistinstConfigBuilder,
+    policy: DISTINST_OLD_BOOTS,
+    keep: u32,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let keep = keep as usize;
+    (*(builder as *mut Config)).old_boots = match policy {
+        DISTINST_OLD_BOOTS::REMOVE => OldBootPolicy::Remove { keep },
+        DISTINST_OLD_BOOTS::DRY_RUN => OldBootPolicy::DryRun { keep },
+        DISTINST_OLD_BOOTS::SKIP => OldBootPolicy::Skip,
+    };
+    0
+}
+
+/// The mode which the install is performed for.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_BOOTLOADER_OVERRIDE {
+    /// The mode which the firmware booted the running system in.
+    DETECT = 0,
+    BIOS = 1,
+    EFI = 2,
+}
+
+/// Installs for BIOS or EFI, rather than for the mode which the firmware booted in. The install
+/// fails with `BIOS_GRUB_MISSING` or `EFI_VARIABLES_UNAVAILABLE` if the forced mode could not
+/// boot.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_bootloader(
+    builder: *mut DistinstConfigBuilder,
+    bootloader: DISTINST_BOOTLOADER_OVERRIDE,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).bootloader = match bootloader {
+        DISTINST_BOOTLOADER_OVERRIDE::DETECT => BootloaderOverride::Detect,
+        DISTINST_BOOTLOADER_OVERRIDE::BIOS => BootloaderOverride::Bios,
+        DISTINST_BOOTLOADER_OVERRIDE::EFI => BootloaderOverride::Efi,
+    };
+    0
+}
+
+/// Selects the flavor of the image's kernels, such as `oem`, which boots by default, or the
+/// flavor which the hardware prefers if `flavor` is null. The kernels of other flavors are
+/// removed if `remove_others` is true.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_kernel(
+    builder: *mut DistinstConfigBuilder,
+    flavor: *const libc::c_char,
+    remove_others: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    let flavor = if flavor.is_null() {
+        None
+    } else {
+        match get_str(flavor) {
+            Ok(flavor) => Some(flavor.to_owned()),
+            Err(_) => return libc::EINVAL,
+        }
+    };
+
+    let selection = KernelSelection { flavor, remove_others };
+    if selection.validate().is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).kernel = Some(selection);
+    0
+}
+
+/// Generates the initramfs of the given kernel release, in place of those of all kernels.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_add_initramfs_kernel(
+    builder: *mut DistinstConfigBuilder,
+    release: *const libc::c_char,
+) -> libc::c_int {
+    builder_set(builder, release, |config, value| config.initramfs.kernels.push(value.to_owned()))
+}
+
+/// The compression of the initramfs.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_INITRAMFS_COMPRESSION {
+    /// The default of initramfs-tools or dracut.
+    DEFAULT = 0,
+    GZIP = 1,
+    LZ4 = 2,
+    XZ = 3,
+    ZSTD = 4,
+}
+
+/// Sets the compression of the initramfs, which is kept for its later generations.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_initramfs_compression(
+    builder: *mut DistinstConfigBuilder,
+    compression: DISTINST_INITRAMFS_COMPRESSION,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).initramfs.compression = match compression {
+        DISTINST_INITRAMFS_COMPRESSION::DEFAULT => None,
+        DISTINST_INITRAMFS_COMPRESSION::GZIP => Some(InitramfsCompression::Gzip),
+        DISTINST_INITRAMFS_COMPRESSION::LZ4 => Some(InitramfsCompression::Lz4),
+        DISTINST_INITRAMFS_COMPRESSION::XZ => Some(InitramfsCompression::Xz),
+        DISTINST_INITRAMFS_COMPRESSION::ZSTD => Some(InitramfsCompression::Zstd),
+    };
+    0
+}
+
+/// Removes the directories of the reused ESP which belong to OSes that no longer exist, as
+/// `distinst_installer_plan_esp_cleanup` plans.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_set_esp_cleanup(
+    builder: *mut DistinstConfigBuilder,
+    cleanup: bool,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    (*(builder as *mut Config)).esp_cleanup = cleanup;
+    0
+}
+
+/// Validates the config, returning `0` if it may be installed, or an errno value otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_config_builder_validate(
+    builder: *const DistinstConfigBuilder,
+) -> libc::c_int {
+    if null_check(builder).is_err() {
+        return libc::EINVAL;
+    }
+
+    match (*(builder as *const Config)).validate() {
+        Ok(()) => 0,
+        Err(why) => {
+            error!("config is not valid: {}", why);
+            why.raw_os_error().unwrap_or(libc::EINVAL)
+        }
+    }
+}
+
+/// The user which the installer creates. The installer zeroes it before the callback fills it,
+/// and fields are only appended to it, so that callbacks which were built against an earlier
+/// version leave the fields they lack unset. Appending a field bumps the version of the library.
 #[repr(C)]
 pub struct DistinstUserAccountCreate {
     pub username: *const libc::c_char,
     pub realname: *const libc::c_char,
     pub password: *const libc::c_char,
     pub profile_icon: *const libc::c_char,
+    pub password_hash: *const libc::c_char,
+    /// Comma-separated groups, in place of the defaults, if not null.
+    pub groups: *const libc::c_char,
+    pub sudo: DISTINST_SUDO_POLICY,
+    /// The date, as `YYYY-MM-DD`, after which the account is disabled.
+    pub expires: *const libc::c_char,
+}
+
+/// Whether the created user may use sudo, and whether sudo asks for their password.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_SUDO_POLICY {
+    PASSWORD = 0,
+    NOPASSWD = 1,
+    NONE = 2,
 }
 
 impl DistinstUserAccountCreate {
@@ -48,6 +1306,16 @@ impl DistinstUserAccountCreate {
             username: get_str(self.username)?.to_owned(),
             realname: get_str(self.realname).ok().map(String::from),
             password: get_str(self.password).ok().map(String::from),
+            password_hash: get_str(self.password_hash).ok().map(String::from),
+            groups: get_str(self.groups).ok().map(|groups| {
+                groups.split(',').filter(|group| !group.is_empty()).map(String::from).collect()
+            }),
+            sudo: match self.sudo {
+                DISTINST_SUDO_POLICY::PASSWORD => SudoPolicy::Password,
+                DISTINST_SUDO_POLICY::NOPASSWD => SudoPolicy::NoPassword,
+                DISTINST_SUDO_POLICY::NONE => SudoPolicy::Denied,
+            },
+            expires: get_str(self.expires).ok().map(String::from),
             profile_icon: get_str(self.profile_icon).ok().map(String::from),
         })
     }
@@ -5,18 +5,24 @@ use std::{
     os::unix::ffi::OsStrExt,
     path::Path,
     ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
 };
 
 use distinst::{
-    BlockDeviceExt, DecryptionError, Disk, DiskExt, Disks, FileSystem, LogicalDevice,
+    BlockDeviceExt, DecryptionError, Disk, DiskExt, Disks, FileSystem, GptDamage, LogicalDevice,
     LvmEncryption, PartitionBuilder, PartitionInfo, PartitionTable, PartitionTableExt, Sector,
     SectorExt,
 };
 
-use super::{get_str, null_check};
+use super::{get_str, null_check, to_cstr};
 use crate::ffi::AsMutPtr;
 use crate::filesystem::DISTINST_FILE_SYSTEM;
 use crate::gen_object_ptr;
+use crate::SendPtr;
 use crate::lvm::{DistinstLvmDevice, DistinstLvmEncryption};
 use crate::partition::{
     DistinstPartition, DistinstPartitionAndDiskPath, DistinstPartitionBuilder,
@@ -103,6 +109,36 @@ pub unsafe extern "C" fn distinst_disk_get_serial(
     serial.as_bytes().as_ptr()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disk_get_wwn(
+    disk: *mut DistinstDisk,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    if null_check(disk).or_else(|_| null_check(len)).is_err() {
+        return ptr::null_mut();
+    }
+
+    let disk = &mut *(disk as *mut Disk);
+    let wwn = disk.get_wwn();
+    *len = wwn.len() as libc::c_int;
+    wwn.as_bytes().as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disk_get_bus(
+    disk: *mut DistinstDisk,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    if null_check(disk).or_else(|_| null_check(len)).is_err() {
+        return ptr::null_mut();
+    }
+
+    let disk = &mut *(disk as *mut Disk);
+    let bus = disk.get_bus();
+    *len = bus.len() as libc::c_int;
+    bus.as_bytes().as_ptr()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_disk_get_partition(
     disk: *mut DistinstDisk,
@@ -202,6 +238,34 @@ pub unsafe extern "C" fn distinst_disk_list_partitions(
     Box::into_raw(output.into_boxed_slice()) as *mut *mut DistinstPartition
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disk_partitions_len(disk: *const DistinstDisk) -> libc::c_int {
+    if null_check(disk).is_err() {
+        return 0;
+    }
+
+    (&*(disk as *const Disk)).get_partitions().len() as libc::c_int
+}
+
+/// distinst_disk_partitions_get_nth:
+/// @disk: the physical device
+/// @index: the index of the partition
+///
+/// Returns: (transfer none) (nullable): the partition, which is owned by @disk, or null if
+/// @disk is null or the index is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disk_partitions_get_nth(
+    disk: *mut DistinstDisk,
+    index: libc::c_int,
+) -> *mut DistinstPartition {
+    if null_check(disk).is_err() || index < 0 {
+        return ptr::null_mut();
+    }
+
+    let disk = &mut *(disk as *mut Disk);
+    disk.get_partitions_mut().get_mut(index as usize).as_mut_ptr() as *mut DistinstPartition
+}
+
 #[no_mangle]
 /// TODO: This is to be used with vectors returned from
 /// `distinst_disk_list_partitions`.
@@ -394,6 +458,58 @@ pub unsafe extern "C" fn distinst_disk_format_partition(
     }
 }
 
+/// The damage to the backup GPT header of a disk.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum DISTINST_GPT_DAMAGE {
+    NONE = 0,
+    BACKUP_MISPLACED = 1,
+    BACKUP_MISSING = 2,
+    BACKUP_CORRUPT = 3,
+    TABLE_MISMATCH = 4,
+}
+
+/// Reads the GPT headers of the disk, returning `NONE` if its backup header is intact, or if
+/// the headers could not be read.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disk_gpt_damage(
+    disk: *const DistinstDisk,
+) -> DISTINST_GPT_DAMAGE {
+    if null_check(disk).is_err() {
+        return DISTINST_GPT_DAMAGE::NONE;
+    }
+
+    let disk = &*(disk as *const Disk);
+    match disk.gpt_damage() {
+        Ok(Some(GptDamage::BackupMisplaced)) => DISTINST_GPT_DAMAGE::BACKUP_MISPLACED,
+        Ok(Some(GptDamage::BackupMissing)) => DISTINST_GPT_DAMAGE::BACKUP_MISSING,
+        Ok(Some(GptDamage::BackupCorrupt)) => DISTINST_GPT_DAMAGE::BACKUP_CORRUPT,
+        Ok(Some(GptDamage::TableMismatch)) => DISTINST_GPT_DAMAGE::TABLE_MISMATCH,
+        Ok(None) => DISTINST_GPT_DAMAGE::NONE,
+        Err(why) => {
+            info!("unable to read the GPT of {}: {}", disk.path().display(), why);
+            DISTINST_GPT_DAMAGE::NONE
+        }
+    }
+}
+
+/// Rewrites the backup GPT header of the disk at its end, from the primary header.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disk_repair_gpt(disk: *mut DistinstDisk) -> libc::c_int {
+    if null_check(disk).is_err() {
+        return -1;
+    }
+
+    let disk = &mut *(disk as *mut Disk);
+
+    if let Err(why) = disk.repair_gpt() {
+        info!("unable to repair the GPT of {}: {}", disk.path().display(), why);
+        -1
+    } else {
+        0
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_disk_commit(disk: *mut DistinstDisk) -> libc::c_int {
     if null_check(disk).is_err() {
@@ -455,6 +571,165 @@ pub unsafe extern "C" fn distinst_disks_probe() -> *mut DistinstDisks {
     }
 }
 
+/// Probes the disks in the system without requiring root, for display before authentication.
+///
+/// Volume groups and encrypted volumes are not detected. On error, a null pointer will be
+/// returned.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_probe_unprivileged() -> *mut DistinstDisks {
+    match Disks::probe_devices_unprivileged() {
+        Ok(disks) => gen_object_ptr(disks) as *mut DistinstDisks,
+        Err(why) => {
+            info!("unable to probe devices: {}", why);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Invoked with the probed disks, or a null pointer if probing failed or was cancelled.
+///
+/// Ownership of the disks is transferred to the callee.
+pub type DistinstDisksProbeCallback =
+    extern "C" fn(disks: *mut DistinstDisks, user_data: *mut libc::c_void);
+
+/// A handle to an asynchronous probe, which may be used to cancel it.
+#[repr(C)]
+pub struct DistinstDisksProbeHandle;
+
+/// Probes the disks in a background thread, to avoid blocking the caller's main loop.
+///
+/// The callback is invoked from the background thread, so GTK frontends should hand the
+/// result back to the main loop (via `GLib.Idle.add`) before using it. Other disk operations
+/// should not be performed until the callback has been invoked.
+///
+/// The returned handle must be freed with `distinst_disks_probe_handle_destroy`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_probe_async(
+    callback: DistinstDisksProbeCallback,
+    user_data: *mut libc::c_void,
+) -> *mut DistinstDisksProbeHandle {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let handle = cancel.clone();
+    let user_data = SendPtr(user_data);
+
+    thread::spawn(move || {
+        let disks = match Disks::probe_devices_cancellable(&cancel) {
+            Ok(_) if cancel.load(Ordering::SeqCst) => ptr::null_mut(),
+            Ok(disks) => gen_object_ptr(disks) as *mut DistinstDisks,
+            Err(why) => {
+                info!("unable to probe devices: {}", why);
+                ptr::null_mut()
+            }
+        };
+
+        callback(disks, user_data.0);
+    });
+
+    gen_object_ptr(handle) as *mut DistinstDisksProbeHandle
+}
+
+/// Requests that an asynchronous probe be cancelled.
+///
+/// The device currently being probed will complete first, after which the callback is
+/// invoked with a null pointer.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_probe_handle_cancel(
+    handle: *const DistinstDisksProbeHandle,
+) {
+    if null_check(handle).is_err() {
+        return;
+    }
+
+    (*(handle as *const Arc<AtomicBool>)).store(true, Ordering::SeqCst);
+}
+
+/// A destructor for a `DistinstDisksProbeHandle`. This does not cancel the probe.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_probe_handle_destroy(
+    handle: *mut DistinstDisksProbeHandle,
+) {
+    if handle.is_null() {
+        error!("DistinstDisksProbeHandle was to be destroyed even though it is null");
+    } else {
+        Box::from_raw(handle as *mut Arc<AtomicBool>);
+    }
+}
+
+/// Invoked for the passphrase of an encrypted volume, with the partition that contains it, its
+/// label or a null pointer, and the attempt to unlock it, which exceeds 1 after a wrong
+/// passphrase was given.
+///
+/// The passphrase is returned as a string allocated with `malloc`, which is freed by the
+/// caller. A null pointer leaves the volume locked.
+pub type DistinstPassphraseCallback = extern "C" fn(
+    device: *const libc::c_char,
+    label: *const libc::c_char,
+    attempt: u32,
+    user_data: *mut libc::c_void,
+) -> *mut libc::c_char;
+
+/// Unlocks the LUKS partitions which are not already open, asking the callback for their
+/// passphrases, so that their contents are added as logical devices.
+///
+/// Returns the number of volumes which were unlocked, or `-1` on error.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_unlock_encrypted(
+    disks: *mut DistinstDisks,
+    callback: DistinstPassphraseCallback,
+    user_data: *mut libc::c_void,
+) -> libc::c_int {
+    if null_check(disks).is_err() {
+        return -1;
+    }
+
+    let disks = &mut *(disks as *mut Disks);
+    let unlocked = disks.unlock_encrypted(|request| {
+        let device = CString::new(request.device.as_os_str().as_bytes()).ok()?;
+        let label = request.label.and_then(|label| CString::new(label).ok());
+        let label = label.as_ref().map_or(ptr::null(), |label| label.as_ptr());
+
+        let passphrase = callback(device.as_ptr(), label, request.attempt, user_data);
+        if passphrase.is_null() {
+            return None;
+        }
+
+        let string = CStr::from_ptr(passphrase).to_string_lossy().into_owned();
+        libc::free(passphrase as *mut libc::c_void);
+        Some(string)
+    });
+
+    unlocked.len() as libc::c_int
+}
+
+/// Generates a name for a new LUKS volume or volume group from `prefix`, such as `cryptdata`
+/// or `cryptdata_1`, which collides with no map that exists, nor with any that the disks'
+/// configuration creates. Returns null on error.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_unique_dm_name(
+    disks: *const DistinstDisks,
+    prefix: *const libc::c_char,
+) -> *mut libc::c_char {
+    if null_check(disks).is_err() {
+        return ptr::null_mut();
+    }
+
+    let prefix = match get_str(prefix) {
+        Ok(prefix) => prefix,
+        Err(why) => {
+            error!("distinst_disks_unique_dm_name: {}", why);
+            return ptr::null_mut();
+        }
+    };
+
+    match (*(disks as *const Disks)).unique_dm_name(prefix) {
+        Ok(name) => to_cstr(name),
+        Err(why) => {
+            error!("unable to generate a device map name: {}", why);
+            ptr::null_mut()
+        }
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_disks_contains_luks(disks: *const DistinstDisks) -> bool {
     if null_check(disks).is_err() {
@@ -465,6 +740,16 @@ pub unsafe extern "C" fn distinst_disks_contains_luks(disks: *const DistinstDisk
     disks.contains_luks()
 }
 
+/// The devices and partitions as JSON, which must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_to_json(disks: *const DistinstDisks) -> *mut libc::c_char {
+    if null_check(disks).is_err() {
+        return ptr::null_mut();
+    }
+
+    to_cstr((*(disks as *const Disks)).to_json())
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_disks_get_disk_with_mount(
     disks: *mut DistinstDisks,
@@ -577,6 +862,34 @@ pub unsafe extern "C" fn distinst_disks_list(
     Box::into_raw(output.into_boxed_slice()) as *mut *mut DistinstDisk
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_len(disks: *const DistinstDisks) -> libc::c_int {
+    if null_check(disks).is_err() {
+        return 0;
+    }
+
+    (&*(disks as *const Disks)).get_physical_devices().len() as libc::c_int
+}
+
+/// distinst_disks_get_nth:
+/// @disks: the disk configuration
+/// @index: the index of the physical device
+///
+/// Returns: (transfer none) (nullable): the device, which is owned by @disks, or null if
+/// @disks is null or the index is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_get_nth(
+    disks: *mut DistinstDisks,
+    index: libc::c_int,
+) -> *mut DistinstDisk {
+    if null_check(disks).is_err() || index < 0 {
+        return ptr::null_mut();
+    }
+
+    let disks = &mut *(disks as *mut Disks);
+    disks.get_physical_devices_mut().get_mut(index as usize).as_mut_ptr() as *mut DistinstDisk
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_disks_get_physical_device(
     disks: *mut DistinstDisks,
@@ -618,6 +931,34 @@ pub unsafe extern "C" fn distinst_disks_list_logical(
     Box::into_raw(output.into_boxed_slice()) as *mut *mut DistinstLvmDevice
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_logical_len(disks: *const DistinstDisks) -> libc::c_int {
+    if null_check(disks).is_err() {
+        return 0;
+    }
+
+    (&*(disks as *const Disks)).get_logical_devices().len() as libc::c_int
+}
+
+/// distinst_disks_get_nth_logical:
+/// @disks: the disk configuration
+/// @index: the index of the logical device
+///
+/// Returns: (transfer none) (nullable): the device, which is owned by @disks, or null if
+/// @disks is null or the index is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_get_nth_logical(
+    disks: *mut DistinstDisks,
+    index: libc::c_int,
+) -> *mut DistinstLvmDevice {
+    if null_check(disks).is_err() || index < 0 {
+        return ptr::null_mut();
+    }
+
+    let disks = &mut *(disks as *mut Disks);
+    disks.get_logical_devices_mut().get_mut(index as usize).as_mut_ptr() as *mut DistinstLvmDevice
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_disks_find_partition(
     disks: *mut DistinstDisks,
@@ -673,16 +1014,58 @@ pub unsafe extern "C" fn distinst_disks_decrypt_partition(
                 let disks = &mut *(disks as *mut Disks);
                 match disks.decrypt_partition(&Path::new(path), &enc) {
                     Ok(_) => 0,
-                    Err(why) => {
-                        error!("decryption error: {}", why);
-                        match why {
-                            DecryptionError::Open { .. } => 4,
-                            DecryptionError::DecryptedLacksVG { .. } => 5,
-                            DecryptionError::LuksNotFound { .. } => 6,
-                        }
-                    }
+                    Err(why) => decryption_error_code(why),
+                }
+            }
+        })
+    })
+}
+
+/// Unlocks an existing LUKS partition, and installs into it without creating a new container,
+/// keeping its keyslots. If `fs` is not `NONE`, the file system within it is replaced.
+///
+/// Returns the same values as `distinst_disks_decrypt_partition`, and 7 if a file system was
+/// given for a volume which contains a volume group.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_reuse_encrypted(
+    disks: *mut DistinstDisks,
+    path: *const libc::c_char,
+    enc: *mut DistinstLvmEncryption,
+    fs: DISTINST_FILE_SYSTEM,
+) -> libc::c_int {
+    if null_check(disks)
+        .or_else(|_| null_check(path))
+        .or_else(|_| null_check(enc))
+        .or_else(|_| null_check((*enc).physical_volume))
+        .is_err()
+    {
+        return 1;
+    }
+
+    get_str(path).ok().map_or(2, |path| {
+        get_str((*enc).physical_volume).ok().map_or(2, |pv| {
+            let password = get_str((*enc).password).ok().map(String::from);
+            let keydata = get_str((*enc).keydata).ok().map(String::from);
+            if password.is_none() && keydata.is_none() {
+                3
+            } else {
+                let enc = LvmEncryption::new(pv.into(), password, keydata);
+                let disks = &mut *(disks as *mut Disks);
+                match disks.reuse_encrypted(&Path::new(path), &enc, fs.into()) {
+                    Ok(_) => 0,
+                    Err(why) => decryption_error_code(why),
                 }
             }
         })
     })
 }
+
+fn decryption_error_code(why: DecryptionError) -> libc::c_int {
+    error!("decryption error: {}", why);
+    match why {
+        DecryptionError::Open { .. } => 4,
+        DecryptionError::DecryptedLacksVG { .. } => 5,
+        DecryptionError::LuksNotFound { .. } => 6,
+        DecryptionError::ContainsVolumeGroup { .. } => 7,
+    }
+}
@@ -0,0 +1,98 @@
+use libc;
+
+use super::{gen_object_ptr, get_str, null_check, DistinstConfig};
+use distinst::{estimated_install_size, InstallSizeEstimate};
+use std::{path::Path, ptr};
+
+#[repr(C)]
+pub struct DistinstInstallSizeEstimate;
+
+/// Estimates the space that an install with the given configuration will require.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_estimated_install_size(
+    config: *const DistinstConfig,
+) -> *mut DistinstInstallSizeEstimate {
+    if null_check(config).is_err() {
+        return ptr::null_mut();
+    }
+
+    match (*config).as_config() {
+        Ok(config) => {
+            gen_object_ptr(estimated_install_size(&config)) as *mut DistinstInstallSizeEstimate
+        }
+        Err(why) => {
+            error!("distinst_estimated_install_size: {}", why);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_destroy(
+    estimate: *mut DistinstInstallSizeEstimate,
+) {
+    if !estimate.is_null() {
+        Box::from_raw(estimate as *mut InstallSizeEstimate);
+    } else {
+        error!("DistinstInstallSizeEstimate was to be destroyed even though it is null");
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_get_root(
+    estimate: *const DistinstInstallSizeEstimate,
+) -> u64 {
+    (*(estimate as *const InstallSizeEstimate)).root
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_get_esp(
+    estimate: *const DistinstInstallSizeEstimate,
+) -> u64 {
+    (*(estimate as *const InstallSizeEstimate)).esp
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_get_recovery(
+    estimate: *const DistinstInstallSizeEstimate,
+) -> u64 {
+    (*(estimate as *const InstallSizeEstimate)).recovery
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_get_swap(
+    estimate: *const DistinstInstallSizeEstimate,
+) -> u64 {
+    (*(estimate as *const InstallSizeEstimate)).swap
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_get_total(
+    estimate: *const DistinstInstallSizeEstimate,
+) -> u64 {
+    (*(estimate as *const InstallSizeEstimate)).total()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_get_total_sectors(
+    estimate: *const DistinstInstallSizeEstimate,
+) -> u64 {
+    (*(estimate as *const InstallSizeEstimate)).total_sectors()
+}
+
+/// Bytes that the image places beneath the given mount point, or 0 if it is unknown.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_install_size_estimate_get_mount(
+    estimate: *const DistinstInstallSizeEstimate,
+    mount: *const libc::c_char,
+) -> u64 {
+    match get_str(mount) {
+        Ok(mount) => {
+            (*(estimate as *const InstallSizeEstimate)).mount(Path::new(mount)).unwrap_or(0)
+        }
+        Err(why) => {
+            error!("distinst_install_size_estimate_get_mount: {}", why);
+            0
+        }
+    }
+}
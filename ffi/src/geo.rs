@@ -0,0 +1,92 @@
+use crate::{gen_object_ptr, null_check};
+use distinst::geo::{self, SuggestedDefaults};
+use libc;
+use std::ptr;
+
+/// Defaults which are suggested for the location of the system. They are never applied to
+/// the install unless the frontend applies them.
+#[repr(C)]
+pub struct DistinstSuggestedDefaults;
+
+/// Looks up the location of the system through its network connection, and suggests defaults
+/// for it. Returns null if the system is offline, or if the lookup failed.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_suggested_defaults_new() -> *mut DistinstSuggestedDefaults {
+    match geo::suggested_defaults() {
+        Some(defaults) => gen_object_ptr(defaults) as *mut DistinstSuggestedDefaults,
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_suggested_defaults_destroy(
+    defaults: *mut DistinstSuggestedDefaults,
+) {
+    if defaults.is_null() {
+        error!("DistinstSuggestedDefaults was to be destroyed even though it is null");
+    } else {
+        Box::from_raw(defaults as *mut SuggestedDefaults);
+    }
+}
+
+unsafe fn field<F: FnOnce(&SuggestedDefaults) -> Option<&str>>(
+    defaults: *const DistinstSuggestedDefaults,
+    len: *mut libc::c_int,
+    func: F,
+) -> *const u8 {
+    if null_check(defaults).or_else(|_| null_check(len)).is_err() {
+        return ptr::null();
+    }
+
+    match func(&*(defaults as *const SuggestedDefaults)) {
+        Some(value) => {
+            *len = value.len() as libc::c_int;
+            value.as_ptr()
+        }
+        None => {
+            *len = 0;
+            ptr::null()
+        }
+    }
+}
+
+/// The ISO 3166 code of the country, such as `US`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_suggested_defaults_country(
+    defaults: *const DistinstSuggestedDefaults,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    field(defaults, len, |defaults| Some(&defaults.country))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_suggested_defaults_timezone(
+    defaults: *const DistinstSuggestedDefaults,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    field(defaults, len, |defaults| defaults.timezone.as_deref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_suggested_defaults_locale(
+    defaults: *const DistinstSuggestedDefaults,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    field(defaults, len, |defaults| defaults.locale.as_deref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_suggested_defaults_keyboard_layout(
+    defaults: *const DistinstSuggestedDefaults,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    field(defaults, len, |defaults| defaults.keyboard_layout.as_deref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_suggested_defaults_apt_mirror(
+    defaults: *const DistinstSuggestedDefaults,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    field(defaults, len, |defaults| defaults.apt_mirror.as_deref())
+}
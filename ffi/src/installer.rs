@@ -1,11 +1,15 @@
 use libc;
 
-use std::{io, mem};
+use std::{ffi::CString, io, mem, os::unix::ffi::OsStrExt, path::Path, ptr, thread};
 
-use crate::config::DistinstConfig;
+use crate::config::{distinst_config_builder_destroy, DistinstConfig, DistinstConfigBuilder};
 use crate::disk::DistinstDisks;
-use distinst::{timezones::Region, Disks, Error, Installer, Status, Step};
-use crate::gen_object_ptr;
+use distinst::{
+    timezones::Region, Cipher, CodedError, Config, Disks, Error, ErrorCode, FileProgress,
+    HttpReporter, Installer, RecoveryKey, Status, Step, Substep, Warning,
+};
+use crate::{gen_object_ptr, get_str, null_check, to_cstr, SendPtr};
+use crate::DistinstLvmEncryption;
 use crate::DistinstRegion;
 use crate::DistinstUserAccountCreate;
 
@@ -19,6 +23,9 @@ pub enum DISTINST_STEP {
     EXTRACT,
     CONFIGURE,
     BOOTLOADER,
+    FACTORY_IMAGE,
+    MIGRATE,
+    EXPORT,
 }
 
 impl From<DISTINST_STEP> for Step {
@@ -31,6 +38,9 @@ impl From<DISTINST_STEP> for Step {
             EXTRACT => Step::Extract,
             CONFIGURE => Step::Configure,
             BOOTLOADER => Step::Bootloader,
+            FACTORY_IMAGE => Step::FactoryImage,
+            MIGRATE => Step::Migrate,
+            EXPORT => Step::Export,
         }
     }
 }
@@ -45,34 +55,303 @@ impl From<Step> for DISTINST_STEP {
             Step::Extract => EXTRACT,
             Step::Configure => CONFIGURE,
             Step::Bootloader => BOOTLOADER,
+            Step::FactoryImage => FACTORY_IMAGE,
+            Step::Migrate => MIGRATE,
+            Step::Export => EXPORT,
+        }
+    }
+}
+
+/// Tasks performed within an installer step
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub enum DISTINST_SUBSTEP {
+    NONE,
+    FSTAB,
+    LOCALES,
+    PACKAGES,
+    DRIVERS,
+    RECOVERY,
+    BOOTLOADER,
+    KEYBOARD,
+    INITRAMFS,
+    LUKS_BENCHMARK,
+    LUKS_FORMAT,
+    LUKS_OPEN,
+}
+
+impl From<DISTINST_SUBSTEP> for Option<Substep> {
+    fn from(substep: DISTINST_SUBSTEP) -> Self {
+        use DISTINST_SUBSTEP::*;
+        match substep {
+            NONE => None,
+            FSTAB => Some(Substep::Fstab),
+            LOCALES => Some(Substep::Locales),
+            PACKAGES => Some(Substep::Packages),
+            DRIVERS => Some(Substep::Drivers),
+            RECOVERY => Some(Substep::Recovery),
+            BOOTLOADER => Some(Substep::Bootloader),
+            KEYBOARD => Some(Substep::Keyboard),
+            INITRAMFS => Some(Substep::Initramfs),
+            LUKS_BENCHMARK => Some(Substep::LuksBenchmark),
+            LUKS_FORMAT => Some(Substep::LuksFormat),
+            LUKS_OPEN => Some(Substep::LuksOpen),
+        }
+    }
+}
+
+impl From<Option<Substep>> for DISTINST_SUBSTEP {
+    fn from(substep: Option<Substep>) -> Self {
+        use DISTINST_SUBSTEP::*;
+        match substep {
+            None => NONE,
+            Some(Substep::Fstab) => FSTAB,
+            Some(Substep::Locales) => LOCALES,
+            Some(Substep::Packages) => PACKAGES,
+            Some(Substep::Drivers) => DRIVERS,
+            Some(Substep::Recovery) => RECOVERY,
+            Some(Substep::Bootloader) => BOOTLOADER,
+            Some(Substep::Keyboard) => KEYBOARD,
+            Some(Substep::Initramfs) => INITRAMFS,
+            Some(Substep::LuksBenchmark) => LUKS_BENCHMARK,
+            Some(Substep::LuksFormat) => LUKS_FORMAT,
+            Some(Substep::LuksOpen) => LUKS_OPEN,
+        }
+    }
+}
+
+/// Identifies specific installer failures
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub enum DISTINST_ERROR_CODE {
+    UNKNOWN,
+    CANCELLED,
+    HOSTNAME_INVALID,
+    SQUASHFS_MISSING,
+    ROOT_UNDEFINED,
+    ROOT_FILE_SYSTEM_INVALID,
+    BOOT_UNDEFINED,
+    BOOT_FILE_SYSTEM_INVALID,
+    BOOT_ON_LOGICAL_DEVICE,
+    ESP_NOT_GPT,
+    ESP_FLAG_MISSING,
+    ESP_TOO_SMALL,
+    RECOVERY_TOO_SMALL,
+    INSUFFICIENT_SPACE,
+    PERMISSION_DENIED,
+    BIOS_GRUB_MISSING,
+    EFI_VARIABLES_UNAVAILABLE,
+    INITRAMFS_INCOMPLETE,
+    BOOT_ENCRYPTED,
+    BOOT_ON_RAID,
+    DEVICE_PROTECTED,
+    GPT_DAMAGED,
+}
+
+impl From<DISTINST_ERROR_CODE> for ErrorCode {
+    fn from(code: DISTINST_ERROR_CODE) -> Self {
+        use DISTINST_ERROR_CODE::*;
+        match code {
+            UNKNOWN => ErrorCode::Unknown,
+            CANCELLED => ErrorCode::Cancelled,
+            HOSTNAME_INVALID => ErrorCode::HostnameInvalid,
+            SQUASHFS_MISSING => ErrorCode::SquashfsMissing,
+            ROOT_UNDEFINED => ErrorCode::RootUndefined,
+            ROOT_FILE_SYSTEM_INVALID => ErrorCode::RootFileSystemInvalid,
+            BOOT_UNDEFINED => ErrorCode::BootUndefined,
+            BOOT_FILE_SYSTEM_INVALID => ErrorCode::BootFileSystemInvalid,
+            BOOT_ON_LOGICAL_DEVICE => ErrorCode::BootOnLogicalDevice,
+            ESP_NOT_GPT => ErrorCode::EspNotGpt,
+            ESP_FLAG_MISSING => ErrorCode::EspFlagMissing,
+            ESP_TOO_SMALL => ErrorCode::EspTooSmall,
+            RECOVERY_TOO_SMALL => ErrorCode::RecoveryTooSmall,
+            INSUFFICIENT_SPACE => ErrorCode::InsufficientSpace,
+            PERMISSION_DENIED => ErrorCode::PermissionDenied,
+            BIOS_GRUB_MISSING => ErrorCode::BiosGrubMissing,
+            EFI_VARIABLES_UNAVAILABLE => ErrorCode::EfiVariablesUnavailable,
+            INITRAMFS_INCOMPLETE => ErrorCode::InitramfsIncomplete,
+            BOOT_ENCRYPTED => ErrorCode::BootEncrypted,
+            BOOT_ON_RAID => ErrorCode::BootOnRaid,
+            DEVICE_PROTECTED => ErrorCode::DeviceProtected,
+            GPT_DAMAGED => ErrorCode::GptDamaged,
+        }
+    }
+}
+
+impl From<ErrorCode> for DISTINST_ERROR_CODE {
+    fn from(code: ErrorCode) -> Self {
+        use DISTINST_ERROR_CODE::*;
+        match code {
+            ErrorCode::Unknown => UNKNOWN,
+            ErrorCode::Cancelled => CANCELLED,
+            ErrorCode::HostnameInvalid => HOSTNAME_INVALID,
+            ErrorCode::SquashfsMissing => SQUASHFS_MISSING,
+            ErrorCode::RootUndefined => ROOT_UNDEFINED,
+            ErrorCode::RootFileSystemInvalid => ROOT_FILE_SYSTEM_INVALID,
+            ErrorCode::BootUndefined => BOOT_UNDEFINED,
+            ErrorCode::BootFileSystemInvalid => BOOT_FILE_SYSTEM_INVALID,
+            ErrorCode::BootOnLogicalDevice => BOOT_ON_LOGICAL_DEVICE,
+            ErrorCode::EspNotGpt => ESP_NOT_GPT,
+            ErrorCode::EspFlagMissing => ESP_FLAG_MISSING,
+            ErrorCode::EspTooSmall => ESP_TOO_SMALL,
+            ErrorCode::RecoveryTooSmall => RECOVERY_TOO_SMALL,
+            ErrorCode::InsufficientSpace => INSUFFICIENT_SPACE,
+            ErrorCode::PermissionDenied => PERMISSION_DENIED,
+            ErrorCode::BiosGrubMissing => BIOS_GRUB_MISSING,
+            ErrorCode::EfiVariablesUnavailable => EFI_VARIABLES_UNAVAILABLE,
+            ErrorCode::InitramfsIncomplete => INITRAMFS_INCOMPLETE,
+            ErrorCode::BootEncrypted => BOOT_ENCRYPTED,
+            ErrorCode::BootOnRaid => BOOT_ON_RAID,
+            ErrorCode::DeviceProtected => DEVICE_PROTECTED,
+            ErrorCode::GptDamaged => GPT_DAMAGED,
         }
     }
 }
 
 /// Installer error message
+///
+/// The `device` and `message` strings are only valid for the duration of the error callback.
+///
+/// Frontends also allocate it, to emit errors of their own, and so its layout is fixed once it
+/// is released. `distinst_installer_emit_error` reads the layout which precedes `code`, and
+/// `distinst_installer_emit_error_v2` reads the whole of it.
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct DistinstError {
+    step:    DISTINST_STEP,
+    err:     libc::c_int,
+    code:    DISTINST_ERROR_CODE,
+    /// The device that the error concerns, or null if it is not known.
+    device:  *const libc::c_char,
+    message: *const libc::c_char,
+}
+
+/// The layout of `DistinstError` before the code, device, and message were appended, which
+/// frontends built against older headers allocate.
+#[repr(C)]
+struct DistinstErrorV1 {
     step: DISTINST_STEP,
     err:  libc::c_int,
 }
 
+extern "C" {
+    fn dgettext(domain: *const libc::c_char, msgid: *const libc::c_char) -> *mut libc::c_char;
+}
+
+/// The message of the error, translated by its code within the `distinst` text domain, if a
+/// translation is installed. Errors without a code, and errors whose code lacks a translation,
+/// keep the message which they were raised with.
+///
+/// The returned string must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_error_get_message(
+    error: *const DistinstError,
+) -> *mut libc::c_char {
+    if null_check(error).is_err() {
+        return ptr::null_mut();
+    }
+
+    let error = &*error;
+    let message = if error.message.is_null() {
+        None
+    } else {
+        get_str(error.message).ok().map(String::from)
+    };
+
+    let msgid = match error.code {
+        DISTINST_ERROR_CODE::UNKNOWN => None,
+        code => CString::new(ErrorCode::from(code).describe()).ok(),
+    };
+
+    let translated = msgid.and_then(|msgid| {
+        let domain = b"distinst\0".as_ptr() as *const libc::c_char;
+        let translated = dgettext(domain, msgid.as_ptr());
+        // The message ID itself is returned when there is no translation.
+        if translated as *const libc::c_char == msgid.as_ptr() {
+            None
+        } else {
+            get_str(translated).ok().map(String::from)
+        }
+    });
+
+    match translated.or(message) {
+        Some(message) => to_cstr(message),
+        None => ptr::null_mut(),
+    }
+}
+
 /// Installer error callback
+///
+/// Invoked on the thread which is running the install. See `distinst_installer_install_async`.
 pub type DistinstErrorCallback =
     extern "C" fn(status: *const DistinstError, user_data: *mut libc::c_void);
 
 /// Installer status message
+///
+/// Frontends also allocate it, to emit statuses of their own, and so its layout is fixed once
+/// it is released. Fields which are appended to it are only read by a new entry point, such as
+/// `distinst_installer_emit_status_v2`, while the old one keeps reading the old layout.
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct DistinstStatus {
+    step:            DISTINST_STEP,
+    percent:         libc::c_int,
+    substep:         DISTINST_SUBSTEP,
+    overall:         libc::c_int,
+    /// Files of the image which were written during the extract step.
+    files_processed: u64,
+    /// Files in the image, or `0` if the files which were written are not counted.
+    files_total:     u64,
+}
+
+/// The layout of `DistinstStatus` before the counts of files were appended, which frontends
+/// built against older headers allocate.
+#[repr(C)]
+struct DistinstStatusV1 {
     step:    DISTINST_STEP,
     percent: libc::c_int,
+    substep: DISTINST_SUBSTEP,
+    overall: libc::c_int,
 }
 
 /// Installer status callback
+///
+/// Invoked on the thread which is running the install. The status is a plain value, which may
+/// be copied and handed to the main loop of the frontend.
 pub type DistinstStatusCallback =
     extern "C" fn(status: *const DistinstStatus, user_data: *mut libc::c_void);
 
+/// The kind of an installer warning.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub enum DISTINST_WARNING_KIND {
+    /// The system is running from a battery, and `value` is its charge in percent.
+    LOW_BATTERY = 0,
+    /// Writes to the target media are slow, and `value` is their throughput in KiB/s.
+    SLOW_MEDIA = 1,
+    /// The EFI variables may be too full to store the boot entry, and `value` is their KiB.
+    EFI_VARIABLES_FULL = 2,
+    /// The clock is behind the build of the image, and `value` is the days by which it is.
+    CLOCK_BEHIND = 3,
+    /// The clock is years ahead of the build of the image, and `value` is the days by which it
+    /// is.
+    CLOCK_AHEAD = 4,
+}
+
+/// A condition which does not prevent the install, but which should be presented to the user.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct DistinstWarning {
+    kind:  DISTINST_WARNING_KIND,
+    value: libc::c_int,
+}
+
+/// Installer warning callback
+///
+/// Invoked on the thread which is running the install.
+pub type DistinstWarningCallback =
+    extern "C" fn(warning: *const DistinstWarning, user_data: *mut libc::c_void);
+
 /// Installer timezone callback
 pub type DistinstTimezoneCallback =
     extern "C" fn(user_data: *mut libc::c_void) -> *const DistinstRegion;
@@ -81,6 +360,9 @@ pub type DistinstTimezoneCallback =
 pub type DistinstUserAccountCallback =
     extern "C" fn(user_account_create: *mut DistinstUserAccountCreate, user_data: *mut libc::c_void);
 
+/// Invoked once an asynchronous install has finished, with `0` on success, or an errno value.
+pub type DistinstInstallCallback = extern "C" fn(result: libc::c_int, user_data: *mut libc::c_void);
+
 /// An installer object
 #[repr(C)]
 pub struct DistinstInstaller;
@@ -91,16 +373,47 @@ pub unsafe extern "C" fn distinst_installer_new() -> *mut DistinstInstaller {
     gen_object_ptr(Installer::default()) as *mut DistinstInstaller
 }
 
-/// Send an installer status message
+/// Send an installer error message
+///
+/// Only the `step` and `err` fields are read, as frontends which were built before the code,
+/// device, and message were added allocate an error without them. Use
+/// `distinst_installer_emit_error_v2` to emit those as well.
 #[no_mangle]
 pub unsafe extern "C" fn distinst_installer_emit_error(
     installer: *mut DistinstInstaller,
     error: *const DistinstError,
 ) {
-    (*(installer as *mut Installer)).emit_error(&Error {
-        step: (*error).step.into(),
-        err:  io::Error::from_raw_os_error((*error).err),
-    });
+    let error = &*(error as *const DistinstErrorV1);
+    let err = io::Error::from_raw_os_error(error.err);
+    (*(installer as *mut Installer)).emit_error(&Error { step: error.step.into(), err });
+}
+
+/// Send an installer error message, with its code, device, and message
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_emit_error_v2(
+    installer: *mut DistinstInstaller,
+    error: *const DistinstError,
+) {
+    let error = &*error;
+    let err = match error.code {
+        DISTINST_ERROR_CODE::UNKNOWN => io::Error::from_raw_os_error(error.err),
+        code => {
+            let device = if error.device.is_null() {
+                None
+            } else {
+                get_str(error.device).ok().map(Path::new)
+            };
+            let message = if error.message.is_null() {
+                ErrorCode::from(code).describe()
+            } else {
+                get_str(error.message).unwrap_or_else(|_| ErrorCode::from(code).describe())
+            };
+
+            CodedError::new(code.into(), device, message).into_io(io::ErrorKind::Other)
+        }
+    };
+
+    (*(installer as *mut Installer)).emit_error(&Error { step: error.step.into(), err });
 }
 
 /// Set the installer status callback
@@ -111,10 +424,16 @@ pub unsafe extern "C" fn distinst_installer_on_error(
     user_data: *mut libc::c_void,
 ) {
     (*(installer as *mut Installer)).on_error(move |error| {
+        let device = error.device().and_then(|path| CString::new(path.as_os_str().as_bytes()).ok());
+        let message = CString::new(format!("{}", error.err)).unwrap_or_default();
+
         callback(
             &DistinstError {
-                step: error.step.into(),
-                err:  error.err.raw_os_error().unwrap_or(libc::EIO),
+                step:    error.step.into(),
+                err:     error.err.raw_os_error().unwrap_or(libc::EIO),
+                code:    error.code().into(),
+                device:  device.as_ref().map_or(ptr::null(), |device| device.as_ptr()),
+                message: message.as_ptr(),
             } as *const DistinstError,
             user_data,
         )
@@ -122,13 +441,43 @@ pub unsafe extern "C" fn distinst_installer_on_error(
 }
 
 /// Send an installer status message
+///
+/// Only the fields which precede `files_processed` are read, as frontends which were built
+/// before the counts of files were added allocate a status without them. Use
+/// `distinst_installer_emit_status_v2` to emit the counts as well.
 #[no_mangle]
 pub unsafe extern "C" fn distinst_installer_emit_status(
     installer: *mut DistinstInstaller,
     status: *const DistinstStatus,
 ) {
-    (*(installer as *mut Installer))
-        .emit_status(Status { step: (*status).step.into(), percent: (*status).percent });
+    let status = &*(status as *const DistinstStatusV1);
+    (*(installer as *mut Installer)).emit_status(Status {
+        step:    status.step.into(),
+        substep: status.substep.into(),
+        percent: status.percent,
+        overall: status.overall,
+        files:   None,
+    });
+}
+
+/// Send an installer status message, with the counts of files which were written
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_emit_status_v2(
+    installer: *mut DistinstInstaller,
+    status: *const DistinstStatus,
+) {
+    let files = match (*status).files_total {
+        0 => None,
+        total => Some(FileProgress { processed: (*status).files_processed, total }),
+    };
+
+    (*(installer as *mut Installer)).emit_status(Status {
+        step:    (*status).step.into(),
+        substep: (*status).substep.into(),
+        percent: (*status).percent,
+        overall: (*status).overall,
+        files,
+    });
 }
 
 /// Set the installer status callback
@@ -140,13 +489,54 @@ pub unsafe extern "C" fn distinst_installer_on_status(
 ) {
     (*(installer as *mut Installer)).on_status(move |status| {
         callback(
-            &DistinstStatus { step: status.step.into(), percent: status.percent }
-                as *const DistinstStatus,
+            &DistinstStatus {
+                step:            status.step.into(),
+                percent:         status.percent,
+                substep:         status.substep.into(),
+                overall:         status.overall,
+                files_processed: status.files.map_or(0, |files| files.processed),
+                files_total:     status.files.map_or(0, |files| files.total),
+            } as *const DistinstStatus,
             user_data,
         )
     });
 }
 
+/// Set the installer warning callback
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_on_warning(
+    installer: *mut DistinstInstaller,
+    callback: DistinstWarningCallback,
+    user_data: *mut libc::c_void,
+) {
+    (*(installer as *mut Installer)).on_warning(move |warning| {
+        let warning = match *warning {
+            Warning::LowBattery(percent) => DistinstWarning {
+                kind:  DISTINST_WARNING_KIND::LOW_BATTERY,
+                value: libc::c_int::from(percent),
+            },
+            Warning::SlowMedia(rate) => DistinstWarning {
+                kind:  DISTINST_WARNING_KIND::SLOW_MEDIA,
+                value: rate.min(libc::c_int::max_value() as u32) as libc::c_int,
+            },
+            Warning::EfiVariablesFull(kib) => DistinstWarning {
+                kind:  DISTINST_WARNING_KIND::EFI_VARIABLES_FULL,
+                value: kib.min(libc::c_int::max_value() as u32) as libc::c_int,
+            },
+            Warning::ClockBehind(days) => DistinstWarning {
+                kind:  DISTINST_WARNING_KIND::CLOCK_BEHIND,
+                value: days.min(libc::c_int::max_value() as u32) as libc::c_int,
+            },
+            Warning::ClockAhead(days) => DistinstWarning {
+                kind:  DISTINST_WARNING_KIND::CLOCK_AHEAD,
+                value: days.min(libc::c_int::max_value() as u32) as libc::c_int,
+            },
+        };
+
+        callback(&warning as *const DistinstWarning, user_data)
+    });
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_installer_set_timezone_callback(
     installer: *mut DistinstInstaller,
@@ -170,6 +560,25 @@ pub unsafe extern "C" fn distinst_installer_set_user_callback(
     });
 }
 
+/// Opts in to posting an anonymous summary of each install to `endpoint`.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_set_telemetry_endpoint(
+    installer: *mut DistinstInstaller,
+    endpoint: *const libc::c_char,
+) -> libc::c_int {
+    if null_check(installer).is_err() {
+        return libc::EINVAL;
+    }
+
+    match get_str(endpoint) {
+        Ok(endpoint) => {
+            (*(installer as *mut Installer)).set_telemetry_reporter(HttpReporter::new(endpoint));
+            0
+        }
+        Err(_) => libc::EINVAL,
+    }
+}
+
 /// Install using this installer, whilst retaining home & user accounts.
 #[no_mangle]
 pub unsafe extern "C" fn distinst_installer_install(
@@ -200,6 +609,359 @@ pub unsafe extern "C" fn distinst_installer_install(
     }
 }
 
+/// Repairs the boot of the existing install whose root is on `target_root`, without reinstalling
+/// it. If the root is encrypted, `target_root` is its LUKS partition, which `encryption`
+/// unlocks. Otherwise, `encryption` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_repair_bootloader(
+    installer: *mut DistinstInstaller,
+    target_root: *const libc::c_char,
+    encryption: *const DistinstLvmEncryption,
+) -> libc::c_int {
+    if null_check(installer).is_err() {
+        return libc::EIO;
+    }
+
+    let target_root = match get_str(target_root) {
+        Ok(target_root) => Path::new(target_root),
+        Err(_) => return libc::EINVAL,
+    };
+
+    let encryption = if encryption.is_null() {
+        None
+    } else {
+        match (*encryption).as_encryption() {
+            Ok(encryption) => Some(encryption),
+            Err(_) => return libc::EINVAL,
+        }
+    };
+
+    let installer = &mut *(installer as *mut Installer);
+    match installer.repair_bootloader(target_root, encryption.as_ref()) {
+        Ok(()) => 0,
+        Err(err) => {
+            info!("Repair error: {}", err);
+            err.raw_os_error().unwrap_or(libc::EIO)
+        }
+    }
+}
+
+/// Encrypts the root of the existing install on `target_root` in place, with `encryption`,
+/// and configures the install to unlock it as it boots. An encryption which was interrupted is
+/// resumed by calling this again with the same passphrase.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_encrypt_root(
+    installer: *mut DistinstInstaller,
+    target_root: *const libc::c_char,
+    encryption: *const DistinstLvmEncryption,
+) -> libc::c_int {
+    if null_check(installer).and_then(|_| null_check(encryption)).is_err() {
+        return libc::EIO;
+    }
+
+    let target_root = match get_str(target_root) {
+        Ok(target_root) => Path::new(target_root),
+        Err(_) => return libc::EINVAL,
+    };
+
+    let encryption = match (*encryption).as_encryption() {
+        Ok(encryption) => encryption,
+        Err(_) => return libc::EINVAL,
+    };
+
+    let installer = &mut *(installer as *mut Installer);
+    match installer.encrypt_root(target_root, &encryption) {
+        Ok(()) => 0,
+        Err(err) => {
+            info!("Encryption error: {}", err);
+            err.raw_os_error().unwrap_or(libc::EIO)
+        }
+    }
+}
+
+/// Reencrypts the LUKS2 volume on `device` with `cipher`, unlocking it with `encryption`. A
+/// `key_size` of `0` uses the default key size of the cipher. A reencryption which was
+/// interrupted is resumed by calling this again.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_reencrypt(
+    installer: *mut DistinstInstaller,
+    device: *const libc::c_char,
+    encryption: *const DistinstLvmEncryption,
+    cipher: *const libc::c_char,
+    key_size: u32,
+) -> libc::c_int {
+    if null_check(installer).and_then(|_| null_check(encryption)).is_err() {
+        return libc::EIO;
+    }
+
+    let (device, cipher) = match (get_str(device), get_str(cipher)) {
+        (Ok(device), Ok(cipher)) => (Path::new(device), cipher),
+        _ => return libc::EINVAL,
+    };
+
+    let encryption = match (*encryption).as_encryption() {
+        Ok(encryption) => encryption,
+        Err(_) => return libc::EINVAL,
+    };
+
+    let cipher = Cipher {
+        name:     cipher.to_owned(),
+        key_size: if key_size == 0 { None } else { Some(key_size) },
+    };
+
+    let installer = &mut *(installer as *mut Installer);
+    match installer.reencrypt(device, &encryption, &cipher) {
+        Ok(()) => 0,
+        Err(err) => {
+            info!("Reencryption error: {}", err);
+            err.raw_os_error().unwrap_or(libc::EIO)
+        }
+    }
+}
+
+/// Install using a config that was created with a `DistinstConfigBuilder`
+///
+/// The config is validated before the install begins.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_install_with_config(
+    installer: *mut DistinstInstaller,
+    disks: *mut DistinstDisks,
+    config: *const DistinstConfigBuilder,
+) -> libc::c_int {
+    let disks: Box<Disks> = if disks.is_null() || installer.is_null() || config.is_null() {
+        return libc::EIO;
+    } else {
+        Box::from_raw(disks as *mut Disks)
+    };
+
+    let config = &*(config as *const Config);
+    let result = config
+        .validate()
+        .map_err(|err| {
+            info!("Config error: {}", err);
+            let errno = err.raw_os_error().unwrap_or(libc::EIO);
+            (*(installer as *mut Installer)).emit_error(&Error { step: Step::Init, err });
+            errno
+        })
+        .and_then(|_| {
+            (*(installer as *mut Installer)).install(*disks, config).map_err(|err| {
+                info!("Install error: {}", err);
+                err.raw_os_error().unwrap_or(libc::EIO)
+            })
+        });
+
+    match result {
+        Ok(()) => 0,
+        Err(errno) => errno,
+    }
+}
+
+/// Installs to the directory at `target`, for a container, with a config that was created with
+/// a `DistinstConfigBuilder`, rather than partitioning disks and installing a bootloader. The
+/// directory is created as a Btrfs subvolume if it does not exist and `subvolume` is set.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_install_container(
+    installer: *mut DistinstInstaller,
+    target: *const libc::c_char,
+    subvolume: bool,
+    config: *const DistinstConfigBuilder,
+) -> libc::c_int {
+    if null_check(installer).or_else(|_| null_check(config)).is_err() {
+        return libc::EIO;
+    }
+
+    let target = match get_str(target) {
+        Ok(target) => Path::new(target),
+        Err(_) => return libc::EINVAL,
+    };
+
+    let config = &*(config as *const Config);
+    match (*(installer as *mut Installer)).install_container(target, subvolume, config) {
+        Ok(()) => 0,
+        Err(err) => {
+            info!("Install error: {}", err);
+            err.raw_os_error().unwrap_or(libc::EIO)
+        }
+    }
+}
+
+/// Exports the image, configured for Windows Subsystem for Linux, to the tarball at `tarball`,
+/// which `wsl --import` installs. The tarball is compressed according to its extension.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_export_wsl(
+    installer: *mut DistinstInstaller,
+    tarball: *const libc::c_char,
+    config: *const DistinstConfigBuilder,
+) -> libc::c_int {
+    if null_check(installer).or_else(|_| null_check(config)).is_err() {
+        return libc::EIO;
+    }
+
+    let tarball = match get_str(tarball) {
+        Ok(tarball) => Path::new(tarball),
+        Err(_) => return libc::EINVAL,
+    };
+
+    let config = &*(config as *const Config);
+    match (*(installer as *mut Installer)).export_wsl(tarball, config) {
+        Ok(()) => 0,
+        Err(err) => {
+            info!("Export error: {}", err);
+            err.raw_os_error().unwrap_or(libc::EIO)
+        }
+    }
+}
+
+/// Installs from a dedicated thread, so that the caller's main loop is not blocked.
+///
+/// Every callback registered on the installer, and the `callback` given here, will be invoked
+/// from the install thread. GTK frontends should marshal them to the main loop with
+/// `GLib.Idle.add`. The installer must not be used or destroyed until `callback` is invoked.
+///
+/// Ownership of the disks and the config is transferred to the installer.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_install_async(
+    installer: *mut DistinstInstaller,
+    disks: *mut DistinstDisks,
+    config: *mut DistinstConfigBuilder,
+    callback: DistinstInstallCallback,
+    user_data: *mut libc::c_void,
+) -> libc::c_int {
+    if installer.is_null() || disks.is_null() || config.is_null() {
+        return libc::EIO;
+    }
+
+    let (thread_installer, thread_disks, thread_config, thread_data) =
+        (SendPtr(installer), SendPtr(disks), SendPtr(config), SendPtr(user_data));
+
+    let spawned = thread::Builder::new().name("distinst-install".into()).spawn(move || {
+        let result = distinst_installer_install_with_config(
+            thread_installer.0,
+            thread_disks.0,
+            thread_config.0,
+        );
+        distinst_config_builder_destroy(thread_config.0);
+        callback(result, thread_data.0);
+    });
+
+    match spawned {
+        Ok(_) => 0,
+        Err(why) => {
+            error!("unable to spawn the install thread: {}", why);
+            Box::from_raw(disks as *mut Disks);
+            distinst_config_builder_destroy(config);
+            why.raw_os_error().unwrap_or(libc::EIO)
+        }
+    }
+}
+
+/// The external commands which the last install executed, with their exit status, duration,
+/// and the end of their output, as they are logged to `/var/log/installer/journal`.
+///
+/// The returned string must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_get_journal(
+    installer: *const DistinstInstaller,
+) -> *mut libc::c_char {
+    if null_check(installer).is_err() {
+        return ptr::null_mut();
+    }
+
+    to_cstr((*(installer as *const Installer)).journal_log())
+}
+
+/// A recovery key which the last install added to a LUKS volume.
+#[repr(C)]
+pub struct DistinstRecoveryKey;
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_recovery_keys_len(
+    installer: *const DistinstInstaller,
+) -> libc::c_int {
+    if null_check(installer).is_err() {
+        return 0;
+    }
+
+    (*(installer as *const Installer)).recovery_keys().len() as libc::c_int
+}
+
+/// distinst_installer_get_recovery_key:
+/// @installer: the installer
+/// @index: the index of the key
+///
+/// Returns: (transfer none) (nullable): the key, which is owned by @installer until its next
+/// install, or null if the index is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_get_recovery_key(
+    installer: *const DistinstInstaller,
+    index: libc::c_int,
+) -> *const DistinstRecoveryKey {
+    if null_check(installer).is_err() || index < 0 {
+        return ptr::null();
+    }
+
+    match (*(installer as *const Installer)).recovery_keys().get(index as usize) {
+        Some(key) => key as *const RecoveryKey as *const DistinstRecoveryKey,
+        None => ptr::null(),
+    }
+}
+
+/// The path of the LUKS partition which the key unlocks.
+///
+/// The returned string must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_recovery_key_get_device(
+    key: *const DistinstRecoveryKey,
+) -> *mut libc::c_char {
+    if null_check(key).is_err() {
+        return ptr::null_mut();
+    }
+
+    to_cstr((*(key as *const RecoveryKey)).device.to_string_lossy().into_owned())
+}
+
+/// The UUID of the LUKS partition, or null if it is not known.
+///
+/// The returned string must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_recovery_key_get_uuid(
+    key: *const DistinstRecoveryKey,
+) -> *mut libc::c_char {
+    if null_check(key).is_err() {
+        return ptr::null_mut();
+    }
+
+    match (*(key as *const RecoveryKey)).uuid {
+        Some(ref uuid) => to_cstr(uuid.clone()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// The key, as it is typed at the passphrase prompt.
+///
+/// The returned string must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_recovery_key_get_key(
+    key: *const DistinstRecoveryKey,
+) -> *mut libc::c_char {
+    if null_check(key).is_err() {
+        return ptr::null_mut();
+    }
+
+    to_cstr((*(key as *const RecoveryKey)).key.clone())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_recovery_key_get_key_slot(
+    key: *const DistinstRecoveryKey,
+) -> libc::c_int {
+    if null_check(key).is_err() {
+        return -1;
+    }
+
+    (*(key as *const RecoveryKey)).key_slot as libc::c_int
+}
+
 /// Destroy an installer object
 #[no_mangle]
 pub unsafe extern "C" fn distinst_installer_destroy(installer: *mut DistinstInstaller) {
@@ -10,18 +10,23 @@ extern crate partition_identity;
 
 use std::{
     ffi::{CStr, CString},
+    path::Path,
     ptr,
 };
 
 pub use self::{
-    auto::*, config::*, dbus::*, disk::*, filesystem::*, installer::*, keyboard_layout::*,
-    locale::*, lvm::*, os::*, partition::*, sector::*, timezones::*, upgrade::*,
+    auto::*, config::*, dbus::*, disk::*, estimate::*, filesystem::*, geo::*, installer::*,
+    keyboard_layout::*, locale::*, lvm::*, os::*, partition::*, plan::*, rescue::*, sector::*,
+    timezones::*, upgrade::*,
 };
 
 pub const DISTINST_MODIFY_BOOT_ORDER: u8 = 0b1;
 pub const DISTINST_INSTALL_HARDWARE_SUPPORT: u8 = 0b10;
 pub const DISTINST_KEEP_OLD_ROOT: u8 = 0b100;
 pub const DISTINST_RUN_UBUNTU_DRIVERS: u8 = 0b1000;
+pub const DISTINST_CAPTURE_FACTORY_IMAGE: u8 = 0b1_0000;
+pub const DISTINST_RESTORE_PACKAGES: u8 = 0b10_0000;
+pub const DISTINST_IMAGE_MODE: u8 = 0b100_0000;
 
 use std::io;
 
@@ -29,18 +34,27 @@ mod auto;
 mod config;
 mod dbus;
 mod disk;
+mod estimate;
 mod ffi;
 mod filesystem;
+mod geo;
 mod installer;
 mod keyboard_layout;
 mod locale;
 mod lvm;
 mod os;
 mod partition;
+mod plan;
+mod rescue;
 mod sector;
 mod timezones;
 mod upgrade;
 
+/// Carries a pointer supplied by the caller, such as `user_data`, into a background thread.
+pub(crate) struct SendPtr<T>(pub *mut T);
+
+unsafe impl<T> Send for SendPtr<T> {}
+
 /// In comes a stack-allocated struct, and out goes a heap-allocated object.
 pub fn gen_object_ptr<T>(obj: T) -> *mut T { Box::into_raw(Box::new(obj)) as *mut T }
 
@@ -80,6 +94,41 @@ pub unsafe extern "C" fn distinst_device_map_exists(name: *const libc::c_char) -
     }
 }
 
+/// Protects a device from being modified by any layout, in addition to those already protected.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_protect_device(device: *const libc::c_char) -> libc::c_int {
+    let device = match get_str(device) {
+        Ok(device) => device,
+        Err(why) => {
+            error!("distinst_protect_device: {}", why);
+            return -1;
+        }
+    };
+
+    match device.parse::<distinst::ProtectedDevice>() {
+        Ok(device) => {
+            let mut devices = distinst::protected_devices();
+            devices.push(device);
+            distinst::protect_devices(devices);
+            0
+        }
+        Err(why) => {
+            error!("distinst_protect_device: {}", why);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn distinst_clear_protected_devices() { distinst::protect_devices(Vec::new()); }
+
+/// Returns true if this process has the privileges required to probe and modify disks.
+#[no_mangle]
+pub extern "C" fn distinst_privileged() -> bool {
+    use distinst::{has_capability, Capability};
+    has_capability(Capability::DacReadSearch) && has_capability(Capability::SysAdmin)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_generate_unique_id(
     prefix: *const libc::c_char,
@@ -90,6 +139,19 @@ pub unsafe extern "C" fn distinst_generate_unique_id(
         .unwrap_or(ptr::null_mut())
 }
 
+/// Hashes `password` with the scheme which the live system prefers, for
+/// `DistinstUserAccountCreate.password_hash`. Returns null if it could not be hashed.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_hash_password(
+    password: *const libc::c_char,
+) -> *mut libc::c_char {
+    let scheme = distinst::crypt::Scheme::preferred(Path::new("/"));
+    get_str(password)
+        .ok()
+        .and_then(|password| distinst::crypt::hash_password(password, scheme).ok().map(to_cstr))
+        .unwrap_or(ptr::null_mut())
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_validate_hostname(hostname: *const libc::c_char) -> bool {
     get_str(hostname).ok().map_or(false, |hostname| distinst::hostname::is_valid(hostname))
@@ -128,6 +190,9 @@ pub type DistinstLogCallback = extern "C" fn(
 );
 
 /// Initialize logging
+///
+/// The callback is invoked from whichever thread the message was logged on, including the
+/// background threads of the installer. The `user_data` pointer may be null.
 #[no_mangle]
 pub unsafe extern "C" fn distinst_log(
     callback: DistinstLogCallback,
@@ -136,10 +201,6 @@ pub unsafe extern "C" fn distinst_log(
     use log::Level;
     use DISTINST_LOG_LEVEL::*;
 
-    if let Err(why) = null_check(user_data) {
-        return why.raw_os_error().unwrap_or(-1);
-    }
-
     let user_data_sync = user_data as usize;
     match distinst::log(move |level, message| {
         let c_level = match level {
@@ -1,6 +1,6 @@
 use distinst::{
-    BlockDeviceExt, DiskExt, Disks, LogicalDevice, PartitionBuilder, PartitionInfo, Sector,
-    SectorExt,
+    BlockDeviceExt, DiskExt, Disks, LogicalDevice, LvmEncryption, PartitionBuilder,
+    PartitionInfo, Sector, SectorExt,
 };
 use external::luks::deactivate_logical_devices;
 use crate::ffi::AsMutPtr;
@@ -9,7 +9,7 @@ use libc;
 use super::{
     get_str, null_check, DistinstDisks, DistinstPartition, DistinstPartitionBuilder, DistinstSector,
 };
-use std::{os::unix::ffi::OsStrExt, path::Path, ptr};
+use std::{io, os::unix::ffi::OsStrExt, path::Path, ptr};
 
 #[no_mangle]
 pub unsafe extern "C" fn distinst_deactivate_logical_devices() -> libc::c_int {
@@ -84,9 +84,48 @@ pub unsafe extern "C" fn distinst_disks_get_logical_device_within_pv(
     }
 }
 
+/// Removes the logical device of the given volume group from the configuration.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_disks_remove_logical_device(
+    disks: *mut DistinstDisks,
+    volume_group: *const libc::c_char,
+) {
+    if null_check(disks).is_err() {
+        return;
+    }
+
+    match get_str(volume_group) {
+        Ok(vg) => (&mut *(disks as *mut Disks)).remove_logical_device(vg),
+        Err(why) => error!("libdistinst: volume_group is not UTF-8: {}", why),
+    }
+}
+
 #[repr(C)]
 pub struct DistinstLvmDevice;
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_lvm_device_get_volume_group(
+    device: *const DistinstLvmDevice,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    if null_check(device).or_else(|_| null_check(len)).is_err() {
+        return ptr::null();
+    }
+
+    let device = &*(device as *const LogicalDevice);
+    *len = device.volume_group.len() as libc::c_int;
+    device.volume_group.as_bytes().as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_lvm_device_is_encrypted(device: *const DistinstLvmDevice) -> bool {
+    if null_check(device).is_err() {
+        return false;
+    }
+
+    (&*(device as *const LogicalDevice)).encryption.is_some()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_lvm_device_get_device_path(
     device: *const DistinstLvmDevice,
@@ -277,6 +316,36 @@ pub unsafe extern "C" fn distinst_lvm_device_list_partitions(
     Box::into_raw(output.into_boxed_slice()) as *mut *mut DistinstPartition
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_lvm_device_partitions_len(
+    device: *const DistinstLvmDevice,
+) -> libc::c_int {
+    if null_check(device).is_err() {
+        return 0;
+    }
+
+    (&*(device as *const LogicalDevice)).get_partitions().len() as libc::c_int
+}
+
+/// distinst_lvm_device_partitions_get_nth:
+/// @device: the logical device
+/// @index: the index of the partition
+///
+/// Returns: (transfer none) (nullable): the partition, which is owned by @device, or null if
+/// @device is null or the index is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_lvm_device_partitions_get_nth(
+    device: *mut DistinstLvmDevice,
+    index: libc::c_int,
+) -> *mut DistinstPartition {
+    if null_check(device).is_err() || index < 0 {
+        return ptr::null_mut();
+    }
+
+    let device = &mut *(device as *mut LogicalDevice);
+    device.get_partitions_mut().get_mut(index as usize).as_mut_ptr() as *mut DistinstPartition
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_lvm_device_contains_mount(
     device: *const DistinstLvmDevice,
@@ -304,6 +373,24 @@ pub struct DistinstLvmEncryption {
     pub keydata:         *mut libc::c_char,
 }
 
+impl DistinstLvmEncryption {
+    pub unsafe fn as_encryption(&self) -> io::Result<LvmEncryption> {
+        let optional = |field: *mut libc::c_char| -> io::Result<Option<String>> {
+            if field.is_null() {
+                Ok(None)
+            } else {
+                get_str(field).map(|string| Some(string.to_string()))
+            }
+        };
+
+        Ok(LvmEncryption::new(
+            get_str(self.physical_volume)?.to_string(),
+            optional(self.password)?,
+            optional(self.keydata)?,
+        ))
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_lvm_encryption_copy(
     src: *const DistinstLvmEncryption,
@@ -3,13 +3,14 @@ use libc;
 use std::{ffi::CString, io, os::unix::ffi::OsStrExt, path::PathBuf, ptr};
 
 use distinst::{
-    BlockDeviceExt, Bootloader, FileSystem, LvmEncryption, PartitionBuilder, PartitionExt,
-    PartitionFlag, PartitionInfo, PartitionTable, PartitionType,
+    BlockDeviceExt, Bootloader, Ext4Feature, FileSystem, PartitionBuilder, PartitionExt,
+    PartitionFlag, PartitionInfo, PartitionTable, PartitionType, Quota, OS,
 };
 use crate::filesystem::DISTINST_FILE_SYSTEM;
 use crate::gen_object_ptr;
 use crate::get_str;
 use crate::null_check;
+use crate::to_cstr;
 use crate::DistinstLvmEncryption;
 
 #[repr(C)]
@@ -66,6 +67,44 @@ impl From<DISTINST_PARTITION_TYPE> for PartitionType {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_QUOTA {
+    USER = 1,
+    GROUP = 2,
+    PROJECT = 3,
+}
+
+impl From<DISTINST_QUOTA> for Quota {
+    fn from(quota: DISTINST_QUOTA) -> Quota {
+        match quota {
+            DISTINST_QUOTA::USER => Quota::User,
+            DISTINST_QUOTA::GROUP => Quota::Group,
+            DISTINST_QUOTA::PROJECT => Quota::Project,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DISTINST_EXT4_FEATURE {
+    BIT64 = 1,
+    METADATA_CSUM = 2,
+    CASEFOLD = 3,
+    ENCRYPT = 4,
+}
+
+impl From<DISTINST_EXT4_FEATURE> for Ext4Feature {
+    fn from(feature: DISTINST_EXT4_FEATURE) -> Ext4Feature {
+        match feature {
+            DISTINST_EXT4_FEATURE::BIT64 => Ext4Feature::Bit64,
+            DISTINST_EXT4_FEATURE::METADATA_CSUM => Ext4Feature::MetadataCsum,
+            DISTINST_EXT4_FEATURE::CASEFOLD => Ext4Feature::Casefold,
+            DISTINST_EXT4_FEATURE::ENCRYPT => Ext4Feature::Encrypt,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[allow(non_camel_case_types)]
@@ -261,33 +300,105 @@ pub unsafe extern "C" fn distinst_partition_builder_logical_volume(
     let encryption = if encryption.is_null() {
         None
     } else {
-        let pv = match get_str((*encryption).physical_volume) {
-            Ok(string) => string.to_string(),
+        match (*encryption).as_encryption() {
+            Ok(encryption) => Some(encryption),
             Err(_) => return builder,
-        };
+        }
+    };
 
-        let password = if (*encryption).password.is_null() {
-            None
-        } else {
-            match get_str((*encryption).password) {
-                Ok(string) => Some(string.to_string()),
-                Err(_) => return builder,
-            }
-        };
+    builder_action(builder, |builder| builder.logical_volume(group, encryption))
+}
 
-        let keydata = if (*encryption).keydata.is_null() {
-            None
-        } else {
-            match get_str((*encryption).keydata) {
-                Ok(string) => Some(string.to_string()),
-                Err(_) => return builder,
-            }
-        };
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_reserved_blocks(
+    builder: *mut DistinstPartitionBuilder,
+    percent: u8,
+) -> *mut DistinstPartitionBuilder {
+    builder_action(builder, |builder| builder.reserved_blocks(percent))
+}
 
-        Some(LvmEncryption::new(pv, password, keydata))
-    };
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_inode_ratio(
+    builder: *mut DistinstPartitionBuilder,
+    bytes: u32,
+) -> *mut DistinstPartitionBuilder {
+    builder_action(builder, |builder| builder.inode_ratio(bytes))
+}
 
-    builder_action(builder, |builder| builder.logical_volume(group, encryption))
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_quota(
+    builder: *mut DistinstPartitionBuilder,
+    quota: DISTINST_QUOTA,
+) -> *mut DistinstPartitionBuilder {
+    builder_action(builder, |builder| builder.quota(quota.into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_ext4_feature(
+    builder: *mut DistinstPartitionBuilder,
+    feature: DISTINST_EXT4_FEATURE,
+    enabled: bool,
+) -> *mut DistinstPartitionBuilder {
+    builder_action(builder, |builder| builder.ext4_feature(feature.into(), enabled))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_btrfs_compression(
+    builder: *mut DistinstPartitionBuilder,
+    level: u8,
+) -> *mut DistinstPartitionBuilder {
+    let level = if level == 0 { None } else { Some(level) };
+    builder_action(builder, |builder| builder.btrfs_compression(level))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_btrfs_quotas(
+    builder: *mut DistinstPartitionBuilder,
+    enabled: bool,
+) -> *mut DistinstPartitionBuilder {
+    builder_action(builder, |builder| builder.btrfs_quotas(enabled))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_btrfs_space_cache_v2(
+    builder: *mut DistinstPartitionBuilder,
+    enabled: bool,
+) -> *mut DistinstPartitionBuilder {
+    builder_action(builder, |builder| builder.btrfs_space_cache_v2(enabled))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_btrfs_nodatacow(
+    builder: *mut DistinstPartitionBuilder,
+    subvolume: *const libc::c_char,
+) -> *mut DistinstPartitionBuilder {
+    match get_str(subvolume) {
+        Ok(string) => builder_action(builder, move |builder| {
+            builder.btrfs_nodatacow(PathBuf::from(string.to_string()))
+        }),
+        Err(_) => builder,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_fat_label(
+    builder: *mut DistinstPartitionBuilder,
+    label: *const libc::c_char,
+) -> *mut DistinstPartitionBuilder {
+    match get_str(label) {
+        Ok(string) => {
+            builder_action(builder, move |builder| builder.fat_label(string.to_string()))
+        }
+        Err(_) => builder,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_builder_fat_volume_id(
+    builder: *mut DistinstPartitionBuilder,
+    id: u32,
+) -> *mut DistinstPartitionBuilder {
+    builder_action(builder, |builder| builder.fat_volume_id(id))
 }
 
 #[repr(C)]
@@ -389,6 +500,43 @@ pub unsafe extern "C" fn distinst_partition_get_mount_point(
     ptr::null()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_get_uuid(
+    partition: *const DistinstPartition,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    if null_check(partition).or_else(|_| null_check(len)).is_err() {
+        return ptr::null();
+    }
+
+    let part = &*(partition as *const PartitionInfo);
+    if let Some(ref uuid) = part.identifiers.uuid {
+        *len = uuid.len() as libc::c_int;
+        return uuid.as_bytes().as_ptr();
+    }
+
+    ptr::null()
+}
+
+/// Mounts the partition to detect the name of the OS installed on it.
+///
+/// Returns null if the partition does not contain an OS. The string must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_get_detected_os(
+    partition: *const DistinstPartition,
+) -> *mut libc::c_char {
+    if null_check(partition).is_err() {
+        return ptr::null_mut();
+    }
+
+    let part = &*(partition as *const PartitionInfo);
+    match part.probe_os() {
+        Some(OS::Linux { ref info, .. }) => to_cstr(info.pretty_name.clone()),
+        Some(OS::Windows(name)) | Some(OS::MacOs(name)) => to_cstr(name),
+        None => ptr::null_mut(),
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_partition_get_start_sector(
     partition: *const DistinstPartition,
@@ -461,6 +609,37 @@ pub unsafe extern "C" fn distinst_partition_associate_keyfile(
     part.associate_keyfile(keyid);
 }
 
+/// Assigns an existing partition to be a physical volume of the given volume group, which
+/// will be encrypted with LUKS if `encryption` is not null.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_partition_set_volume_group(
+    partition: *mut DistinstPartition,
+    group: *const libc::c_char,
+    encryption: *const DistinstLvmEncryption,
+) -> libc::c_int {
+    if null_check(partition).is_err() {
+        return -1;
+    }
+
+    let group = match get_str(group) {
+        Ok(string) => string.to_string(),
+        Err(_) => return -1,
+    };
+
+    let encryption = if encryption.is_null() {
+        None
+    } else {
+        match (*encryption).as_encryption() {
+            Ok(encryption) => Some(encryption),
+            Err(_) => return -1,
+        }
+    };
+
+    let part = &mut *(partition as *mut PartitionInfo);
+    part.set_volume_group(group, encryption);
+    0
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn distinst_partition_set_flags(
     partition: *mut DistinstPartition,
@@ -0,0 +1,184 @@
+use libc;
+
+use super::{gen_object_ptr, null_check, to_cstr, DistinstDisks, DISTINST_FILE_SYSTEM};
+use distinst::{Disks, OperationKind, PlannedOperation};
+use std::{os::unix::ffi::OsStrExt, ptr};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub enum DISTINST_OPERATION_KIND {
+    MKLABEL,
+    REMOVE,
+    RESIZE,
+    CREATE,
+    CREATE_VOLUME_GROUP,
+    CREATE_VOLUME,
+    FORMAT,
+    REMOVE_ESP_DIRECTORY,
+}
+
+impl From<OperationKind> for DISTINST_OPERATION_KIND {
+    fn from(kind: OperationKind) -> Self {
+        match kind {
+            OperationKind::Mklabel => DISTINST_OPERATION_KIND::MKLABEL,
+            OperationKind::Remove => DISTINST_OPERATION_KIND::REMOVE,
+            OperationKind::Resize => DISTINST_OPERATION_KIND::RESIZE,
+            OperationKind::Create => DISTINST_OPERATION_KIND::CREATE,
+            OperationKind::CreateVolumeGroup => DISTINST_OPERATION_KIND::CREATE_VOLUME_GROUP,
+            OperationKind::CreateVolume => DISTINST_OPERATION_KIND::CREATE_VOLUME,
+            OperationKind::Format => DISTINST_OPERATION_KIND::FORMAT,
+            OperationKind::RemoveEspDirectory => DISTINST_OPERATION_KIND::REMOVE_ESP_DIRECTORY,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct DistinstPlan;
+
+#[repr(C)]
+pub struct DistinstPlannedOperation;
+
+/// distinst_installer_simulate:
+/// @disks: the disk configuration to install to
+///
+/// Plans the operations that installing to the given disks will perform, in order.
+///
+/// Returns: (transfer full) (nullable): the plan, which is freed with `distinst_plan_destroy`,
+/// or null if @disks is null, or if its devices could not be probed.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_simulate(
+    disks: *const DistinstDisks,
+) -> *mut DistinstPlan {
+    if null_check(disks).is_err() {
+        return ptr::null_mut();
+    }
+
+    match (*(disks as *const Disks)).plan() {
+        Ok(plan) => gen_object_ptr(plan) as *mut DistinstPlan,
+        Err(why) => {
+            error!("distinst_installer_simulate: {}", why);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// distinst_installer_plan_esp_cleanup:
+/// @disks: the disk configuration to install to
+///
+/// Plans the removal of the directories of the reused ESP which belong to OSes that will no
+/// longer exist on any disk, which an install performs if its config enables it.
+///
+/// Returns: (transfer full) (nullable): the plan, or null if @disks is null.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_installer_plan_esp_cleanup(
+    disks: *const DistinstDisks,
+) -> *mut DistinstPlan {
+    if null_check(disks).is_err() {
+        return ptr::null_mut();
+    }
+
+    gen_object_ptr((*(disks as *const Disks)).plan_esp_cleanup()) as *mut DistinstPlan
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_plan_destroy(plan: *mut DistinstPlan) {
+    if !plan.is_null() {
+        Box::from_raw(plan as *mut Vec<PlannedOperation>);
+    } else {
+        error!("DistinstPlan was to be destroyed even though it is null");
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_plan_len(plan: *const DistinstPlan) -> libc::c_int {
+    if null_check(plan).is_err() {
+        return 0;
+    }
+
+    (*(plan as *const Vec<PlannedOperation>)).len() as libc::c_int
+}
+
+/// distinst_plan_get:
+/// @plan: the plan
+/// @index: the index of the operation
+///
+/// Returns: (transfer none) (nullable): the operation, which is owned by @plan, or null if
+/// @plan is null or the index is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_plan_get(
+    plan: *const DistinstPlan,
+    index: libc::c_int,
+) -> *const DistinstPlannedOperation {
+    if null_check(plan).is_err() || index < 0 {
+        return ptr::null();
+    }
+
+    match (*(plan as *const Vec<PlannedOperation>)).get(index as usize) {
+        Some(operation) => operation as *const PlannedOperation as *const DistinstPlannedOperation,
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_planned_operation_get_kind(
+    operation: *const DistinstPlannedOperation,
+) -> DISTINST_OPERATION_KIND {
+    (*(operation as *const PlannedOperation)).kind.into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_planned_operation_get_device(
+    operation: *const DistinstPlannedOperation,
+    len: *mut libc::c_int,
+) -> *const u8 {
+    if null_check(operation).or_else(|_| null_check(len)).is_err() {
+        return ptr::null();
+    }
+
+    let device = (*(operation as *const PlannedOperation)).device.as_os_str().as_bytes();
+    *len = device.len() as libc::c_int;
+    device.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_planned_operation_get_start_sector(
+    operation: *const DistinstPlannedOperation,
+) -> u64 {
+    (*(operation as *const PlannedOperation)).start_sector
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_planned_operation_get_end_sector(
+    operation: *const DistinstPlannedOperation,
+) -> u64 {
+    (*(operation as *const PlannedOperation)).end_sector
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_planned_operation_get_file_system(
+    operation: *const DistinstPlannedOperation,
+) -> DISTINST_FILE_SYSTEM {
+    match (*(operation as *const PlannedOperation)).file_system {
+        Some(fs) => DISTINST_FILE_SYSTEM::from(fs),
+        None => DISTINST_FILE_SYSTEM::NONE,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn distinst_planned_operation_causes_data_loss(
+    operation: *const DistinstPlannedOperation,
+) -> bool {
+    (*(operation as *const PlannedOperation)).data_loss
+}
+
+/// A human-readable description of the operation, which must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_planned_operation_describe(
+    operation: *const DistinstPlannedOperation,
+) -> *mut libc::c_char {
+    if null_check(operation).is_err() {
+        return ptr::null_mut();
+    }
+
+    to_cstr(format!("{}", *(operation as *const PlannedOperation)))
+}
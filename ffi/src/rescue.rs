@@ -0,0 +1,85 @@
+use libc;
+
+use super::{gen_object_ptr, get_str, null_check, to_cstr, DistinstLvmEncryption};
+use distinst::RescueSession;
+use std::{path::Path, ptr};
+
+#[repr(C)]
+pub struct DistinstRescueSession;
+
+/// distinst_rescue_session_open:
+/// @target_root: the root partition of the install, or its LUKS partition
+/// @encryption: (nullable): unlocks the LUKS partition, if the root is encrypted
+///
+/// Mounts an existing install, with the binds that a chroot requires, so that a terminal may
+/// be opened within it with `chroot PATH`.
+///
+/// Returns: (transfer full) (nullable): the session, or null if the install was not mounted.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_rescue_session_open(
+    target_root: *const libc::c_char,
+    encryption: *const DistinstLvmEncryption,
+) -> *mut DistinstRescueSession {
+    let target_root = match get_str(target_root) {
+        Ok(target_root) => Path::new(target_root),
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let encryption = if encryption.is_null() {
+        None
+    } else {
+        match (*encryption).as_encryption() {
+            Ok(encryption) => Some(encryption),
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+
+    match RescueSession::open(target_root, encryption.as_ref()) {
+        Ok(session) => gen_object_ptr(session) as *mut DistinstRescueSession,
+        Err(why) => {
+            error!("distinst_rescue_session_open: {}", why);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The directory which the install is mounted to, which must be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_rescue_session_path(
+    session: *const DistinstRescueSession,
+) -> *mut libc::c_char {
+    if null_check(session).is_err() {
+        return ptr::null_mut();
+    }
+
+    let path = (*(session as *const RescueSession)).path();
+    to_cstr(path.to_string_lossy().into_owned())
+}
+
+/// Unmounts the install and frees the session, returning `0` if it was unmounted.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_rescue_session_close(
+    session: *mut DistinstRescueSession,
+) -> libc::c_int {
+    if null_check(session).is_err() {
+        return libc::EINVAL;
+    }
+
+    match Box::from_raw(session as *mut RescueSession).close() {
+        Ok(()) => 0,
+        Err(why) => {
+            error!("unable to close the rescue session: {}", why);
+            why.raw_os_error().unwrap_or(libc::EIO)
+        }
+    }
+}
+
+/// Frees the session, unmounting the install if it was not closed.
+#[no_mangle]
+pub unsafe extern "C" fn distinst_rescue_session_destroy(session: *mut DistinstRescueSession) {
+    if session.is_null() {
+        error!("DistinstRescueSession was to be destroyed even though it is null");
+    } else {
+        Box::from_raw(session as *mut RescueSession);
+    }
+}
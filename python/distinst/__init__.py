@@ -0,0 +1,407 @@
+"""Python bindings for libdistinst, for scripting installs and QA automation.
+
+These bindings use cffi's ABI mode over the C API, and therefore only require that
+`libdistinst.so` be installed. Probing, planning, and installing require root.
+
+    import distinst
+
+    disks = distinst.Disks.probe()
+    for operation in disks.plan():
+        print(operation)
+
+    installer = distinst.Installer()
+    installer.on_status(lambda status: print(status))
+    installer.install(disks, distinst.Config(hostname="pop-os", lang="en_US.UTF-8"))
+"""
+
+from collections import namedtuple
+
+from cffi import FFI
+
+ffi = FFI()
+ffi.cdef(
+    """
+    typedef enum {
+        DISTINST_STEP_BACKUP,
+        DISTINST_STEP_INIT,
+        DISTINST_STEP_PARTITION,
+        DISTINST_STEP_EXTRACT,
+        DISTINST_STEP_CONFIGURE,
+        DISTINST_STEP_BOOTLOADER,
+        DISTINST_STEP_FACTORY_IMAGE,
+        DISTINST_STEP_MIGRATE,
+        DISTINST_STEP_EXPORT,
+    } DISTINST_STEP;
+
+    typedef enum {
+        DISTINST_SUBSTEP_NONE,
+        DISTINST_SUBSTEP_FSTAB,
+        DISTINST_SUBSTEP_LOCALES,
+        DISTINST_SUBSTEP_PACKAGES,
+        DISTINST_SUBSTEP_DRIVERS,
+        DISTINST_SUBSTEP_RECOVERY,
+        DISTINST_SUBSTEP_BOOTLOADER,
+        DISTINST_SUBSTEP_KEYBOARD,
+        DISTINST_SUBSTEP_INITRAMFS,
+        DISTINST_SUBSTEP_LUKS_BENCHMARK,
+        DISTINST_SUBSTEP_LUKS_FORMAT,
+        DISTINST_SUBSTEP_LUKS_OPEN,
+    } DISTINST_SUBSTEP;
+
+    typedef enum {
+        DISTINST_OPERATION_KIND_MKLABEL,
+        DISTINST_OPERATION_KIND_REMOVE,
+        DISTINST_OPERATION_KIND_RESIZE,
+        DISTINST_OPERATION_KIND_CREATE,
+        DISTINST_OPERATION_KIND_CREATE_VOLUME_GROUP,
+        DISTINST_OPERATION_KIND_CREATE_VOLUME,
+        DISTINST_OPERATION_KIND_FORMAT,
+    } DISTINST_OPERATION_KIND;
+
+    typedef int DISTINST_ERROR_CODE;
+
+    typedef struct {
+        DISTINST_STEP step;
+        int percent;
+        DISTINST_SUBSTEP substep;
+        int overall;
+        uint64_t files_processed;
+        uint64_t files_total;
+    } DistinstStatus;
+
+    typedef struct {
+        DISTINST_STEP step;
+        int err;
+        DISTINST_ERROR_CODE code;
+        const char *device;
+        const char *message;
+    } DistinstError;
+
+    typedef enum {
+        DISTINST_SUDO_POLICY_PASSWORD,
+        DISTINST_SUDO_POLICY_NOPASSWD,
+        DISTINST_SUDO_POLICY_NONE,
+    } DISTINST_SUDO_POLICY;
+
+    typedef struct {
+        const char *username;
+        const char *realname;
+        const char *password;
+        const char *profile_icon;
+        const char *password_hash;
+        const char *groups;
+        DISTINST_SUDO_POLICY sudo;
+        const char *expires;
+    } DistinstUserAccountCreate;
+
+    typedef struct DistinstDisks DistinstDisks;
+    typedef struct DistinstDisk DistinstDisk;
+    typedef struct DistinstPlan DistinstPlan;
+    typedef struct DistinstPlannedOperation DistinstPlannedOperation;
+    typedef struct DistinstConfigBuilder DistinstConfigBuilder;
+    typedef struct DistinstInstaller DistinstInstaller;
+
+    typedef void (*DistinstStatusCallback)(const DistinstStatus *, void *);
+    typedef void (*DistinstErrorCallback)(const DistinstError *, void *);
+    typedef void (*DistinstUserAccountCallback)(DistinstUserAccountCreate *, void *);
+
+    DistinstDisks *distinst_disks_probe(void);
+    void distinst_disks_destroy(DistinstDisks *);
+    int distinst_disks_len(const DistinstDisks *);
+    DistinstDisk *distinst_disks_get_nth(DistinstDisks *, int);
+    const uint8_t *distinst_disk_get_device_path(const DistinstDisk *, int *);
+    uint64_t distinst_disk_get_sectors(const DistinstDisk *);
+
+    DistinstPlan *distinst_installer_simulate(const DistinstDisks *);
+    void distinst_plan_destroy(DistinstPlan *);
+    int distinst_plan_len(const DistinstPlan *);
+    const DistinstPlannedOperation *distinst_plan_get(const DistinstPlan *, int);
+    DISTINST_OPERATION_KIND distinst_planned_operation_get_kind(const DistinstPlannedOperation *);
+    const uint8_t *distinst_planned_operation_get_device(const DistinstPlannedOperation *, int *);
+    uint64_t distinst_planned_operation_get_start_sector(const DistinstPlannedOperation *);
+    uint64_t distinst_planned_operation_get_end_sector(const DistinstPlannedOperation *);
+    bool distinst_planned_operation_causes_data_loss(const DistinstPlannedOperation *);
+    char *distinst_planned_operation_describe(const DistinstPlannedOperation *);
+
+    DistinstConfigBuilder *distinst_config_builder_new(void);
+    void distinst_config_builder_destroy(DistinstConfigBuilder *);
+    int distinst_config_builder_set_hostname(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_keyboard_layout(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_keyboard_model(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_keyboard_variant(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_old_root(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_lang(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_remove(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_squashfs(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_set_flags(DistinstConfigBuilder *, uint8_t);
+    int distinst_config_builder_add_preserved_path(DistinstConfigBuilder *, const char *);
+    int distinst_config_builder_validate(const DistinstConfigBuilder *);
+
+    DistinstInstaller *distinst_installer_new(void);
+    void distinst_installer_destroy(DistinstInstaller *);
+    void distinst_installer_on_status(DistinstInstaller *, DistinstStatusCallback, void *);
+    void distinst_installer_on_error(DistinstInstaller *, DistinstErrorCallback, void *);
+    void distinst_installer_set_user_callback(
+        DistinstInstaller *, DistinstUserAccountCallback, void *);
+    int distinst_installer_install_with_config(
+        DistinstInstaller *, DistinstDisks *, const DistinstConfigBuilder *);
+
+    void free(void *);
+    """
+)
+
+lib = ffi.dlopen("libdistinst.so")
+libc = ffi.dlopen(None)
+
+MODIFY_BOOT_ORDER = 0b1
+INSTALL_HARDWARE_SUPPORT = 0b10
+KEEP_OLD_ROOT = 0b100
+RUN_UBUNTU_DRIVERS = 0b1000
+CAPTURE_FACTORY_IMAGE = 0b1_0000
+RESTORE_PACKAGES = 0b10_0000
+IMAGE_MODE = 0b100_0000
+
+STEPS = [
+    "backup",
+    "init",
+    "partition",
+    "extract",
+    "configure",
+    "bootloader",
+    "factory_image",
+    "migrate",
+    "export",
+]
+
+SUBSTEPS = [
+    None,
+    "fstab",
+    "locales",
+    "packages",
+    "drivers",
+    "recovery",
+    "bootloader",
+    "keyboard",
+    "initramfs",
+    "luks_benchmark",
+    "luks_format",
+    "luks_open",
+]
+
+OPERATION_KINDS = [
+    "mklabel",
+    "remove",
+    "resize",
+    "create",
+    "create_volume_group",
+    "create_volume",
+    "format",
+]
+
+Status = namedtuple(
+    "Status", ["step", "substep", "percent", "overall", "files_processed", "files_total"]
+)
+PlannedOperation = namedtuple(
+    "PlannedOperation",
+    ["kind", "device", "start_sector", "end_sector", "data_loss", "description"],
+)
+
+
+class DistinstError(Exception):
+    """Raised when libdistinst reports a failure."""
+
+    def __init__(self, message, errno=None, step=None, code=0, device=None):
+        super().__init__(message)
+        self.errno = errno
+        self.step = step
+        self.code = code
+        self.device = device
+
+
+def _bytes(getter, obj):
+    length = ffi.new("int *")
+    ptr = getter(obj, length)
+    if ptr == ffi.NULL:
+        return None
+    return ffi.string(ffi.cast("const char *", ptr), length[0]).decode("utf-8")
+
+
+def _optional(ptr):
+    return None if ptr == ffi.NULL else ffi.string(ptr).decode("utf-8")
+
+
+class Disk:
+    def __init__(self, ptr, owner):
+        self._ptr = ptr
+        # Disks are owned by the configuration that they were obtained from.
+        self._owner = owner
+
+    @property
+    def device_path(self):
+        return _bytes(lib.distinst_disk_get_device_path, self._ptr)
+
+    @property
+    def sectors(self):
+        return lib.distinst_disk_get_sectors(self._ptr)
+
+    def __repr__(self):
+        return "Disk({!r})".format(self.device_path)
+
+
+class Disks:
+    """The physical and logical devices in the system."""
+
+    def __init__(self, ptr):
+        if ptr == ffi.NULL:
+            raise DistinstError("unable to probe devices")
+        self._ptr = ffi.gc(ptr, lib.distinst_disks_destroy)
+
+    @classmethod
+    def probe(cls):
+        return cls(lib.distinst_disks_probe())
+
+    def __len__(self):
+        return lib.distinst_disks_len(self._ptr)
+
+    def __iter__(self):
+        for index in range(len(self)):
+            yield Disk(lib.distinst_disks_get_nth(self._ptr, index), self)
+
+    def plan(self):
+        """Lists the operations that installing to these disks would perform."""
+        plan = lib.distinst_installer_simulate(self._ptr)
+        if plan == ffi.NULL:
+            raise DistinstError("the disk configuration is not valid")
+        plan = ffi.gc(plan, lib.distinst_plan_destroy)
+
+        operations = []
+        for index in range(lib.distinst_plan_len(plan)):
+            operation = lib.distinst_plan_get(plan, index)
+            description = lib.distinst_planned_operation_describe(operation)
+            operations.append(
+                PlannedOperation(
+                    kind=OPERATION_KINDS[lib.distinst_planned_operation_get_kind(operation)],
+                    device=_bytes(lib.distinst_planned_operation_get_device, operation),
+                    start_sector=lib.distinst_planned_operation_get_start_sector(operation),
+                    end_sector=lib.distinst_planned_operation_get_end_sector(operation),
+                    data_loss=lib.distinst_planned_operation_causes_data_loss(operation),
+                    description=_optional(description),
+                )
+            )
+            libc.free(description)
+
+        return operations
+
+    def _take(self):
+        # The installer takes ownership of the disks.
+        ptr = ffi.gc(self._ptr, None)
+        self._ptr = None
+        return ptr
+
+
+class Config:
+    """Options for an install, which are validated before the install begins."""
+
+    _SETTERS = {
+        "hostname": lib.distinst_config_builder_set_hostname,
+        "keyboard_layout": lib.distinst_config_builder_set_keyboard_layout,
+        "keyboard_model": lib.distinst_config_builder_set_keyboard_model,
+        "keyboard_variant": lib.distinst_config_builder_set_keyboard_variant,
+        "old_root": lib.distinst_config_builder_set_old_root,
+        "lang": lib.distinst_config_builder_set_lang,
+        "remove": lib.distinst_config_builder_set_remove,
+        "squashfs": lib.distinst_config_builder_set_squashfs,
+    }
+
+    def __init__(self, flags=0, preserved_paths=(), **options):
+        self._ptr = ffi.gc(lib.distinst_config_builder_new(), lib.distinst_config_builder_destroy)
+        options.setdefault("keyboard_layout", "us")
+        options.setdefault("remove", "/cdrom/casper/filesystem.manifest-remove")
+        options.setdefault("squashfs", "/cdrom/casper/filesystem.squashfs")
+
+        for name, value in options.items():
+            if name not in self._SETTERS:
+                raise TypeError("unknown config option: {}".format(name))
+            self._check(self._SETTERS[name](self._ptr, value.encode("utf-8")), name)
+
+        self._check(lib.distinst_config_builder_set_flags(self._ptr, flags), "flags")
+        for path in preserved_paths:
+            path = path.encode("utf-8")
+            self._check(lib.distinst_config_builder_add_preserved_path(self._ptr, path), path)
+
+    @staticmethod
+    def _check(result, name):
+        if result != 0:
+            raise DistinstError("invalid value for {}".format(name), errno=result)
+
+    def validate(self):
+        result = lib.distinst_config_builder_validate(self._ptr)
+        if result != 0:
+            raise DistinstError("config is not valid", errno=result)
+
+
+class Installer:
+    """Installs to a disk configuration, invoking callbacks as the install progresses.
+
+    Callbacks are invoked from the thread which calls `install`.
+    """
+
+    def __init__(self):
+        self._ptr = ffi.gc(lib.distinst_installer_new(), lib.distinst_installer_destroy)
+        self._callbacks = []
+        self._error = None
+
+        @ffi.callback("void(const DistinstError *, void *)")
+        def on_error(error, _user_data):
+            self._error = DistinstError(
+                _optional(error.message) or "install failed",
+                errno=error.err,
+                step=STEPS[error.step],
+                code=error.code,
+                device=_optional(error.device),
+            )
+
+        self._callbacks.append(on_error)
+        lib.distinst_installer_on_error(self._ptr, on_error, ffi.NULL)
+
+    def on_status(self, callback):
+        """Invokes `callback` with a `Status` whenever the install progresses."""
+
+        @ffi.callback("void(const DistinstStatus *, void *)")
+        def on_status(status, _user_data):
+            callback(
+                Status(
+                    step=STEPS[status.step],
+                    substep=SUBSTEPS[status.substep],
+                    percent=status.percent,
+                    overall=status.overall,
+                    files_processed=status.files_processed,
+                    files_total=status.files_total,
+                )
+            )
+
+        # cffi callbacks must be kept alive for as long as they may be invoked.
+        self._callbacks.append(on_status)
+        lib.distinst_installer_on_status(self._ptr, on_status, ffi.NULL)
+
+    def set_user(self, username, realname=None, password=None):
+        """Creates a user account with the given credentials in the new install."""
+        strings = [
+            ffi.new("char[]", value.encode("utf-8")) if value is not None else ffi.NULL
+            for value in (username, realname, password)
+        ]
+
+        @ffi.callback("void(DistinstUserAccountCreate *, void *)")
+        def on_user(user, _user_data):
+            user.username, user.realname, user.password = strings
+            user.profile_icon = ffi.NULL
+
+        self._callbacks.append((on_user, strings))
+        lib.distinst_installer_set_user_callback(self._ptr, on_user, ffi.NULL)
+
+    def install(self, disks, config):
+        """Installs to the disks, which may not be used afterwards."""
+        self._error = None
+        result = lib.distinst_installer_install_with_config(self._ptr, disks._take(), config._ptr)
+        if result != 0:
+            raise self._error or DistinstError("install failed", errno=result)
@@ -0,0 +1,10 @@
+from setuptools import setup
+
+setup(
+    name="distinst",
+    version="0.5.0",
+    description="Python bindings for libdistinst",
+    license="LGPL-3.0",
+    packages=["distinst"],
+    install_requires=["cffi>=1.0"],
+)
@@ -2,13 +2,20 @@
 
 mod accounts;
 mod options;
+mod packages;
+mod preserve;
 mod retain;
 
 pub(crate) use self::{
     accounts::{AccountFiles, UserData},
+    packages::{installed_size, PackageSelections},
+    preserve::PreservedFiles,
     retain::*,
 };
-pub use self::{options::*, retain::delete_old_install};
+pub use self::{
+    options::*,
+    retain::{delete_old_install, validate_before_removing, SpaceReport},
+};
 
 use disk_types::FileSystem;
 use std::{
@@ -34,6 +41,12 @@ pub enum ReinstallError {
     NoFilesystem,
     #[fail(display = "unable to {} pre-existing account files: {}", step, why)]
     AccountsObtain { why: io::Error, step: &'static str },
+    #[fail(display = "unable to get the list of installed packages: {}", why)]
+    PackagesObtain { why: io::Error },
+    #[fail(display = "unable to preserve {:?} from the old install: {}", path, why)]
+    PreserveObtain { path: PathBuf, why: io::Error },
+    #[fail(display = "not enough space to reinstall: {}", report)]
+    InsufficientSpace { report: SpaceReport },
     #[fail(display = "distinst failed to install: {}", why)]
     Install { why: io::Error },
     #[fail(display = "supplied disk configuration will format /home when it should not")]
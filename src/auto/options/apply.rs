@@ -76,10 +76,19 @@ impl<'a> InstallOption<'a> {
     ///
     /// If the option is to erase and install, the `disks` object will be replaced with a new one.
     pub fn apply(self, disks: &mut Disks) -> Result<(), InstallOptionError> {
+        self.apply_with_sizes(disks, &PartitionSizes::default())
+    }
+
+    /// As `apply`, creating the ESP, recovery, and swap partitions with the given sizes.
+    pub fn apply_with_sizes(
+        self,
+        disks: &mut Disks,
+        sizes: &PartitionSizes,
+    ) -> Result<(), InstallOptionError> {
         match self {
             // Install alongside another OS, taking `sectors` from the largest free partition.
             InstallOption::Alongside { option, password, sectors } => {
-                alongside_config(disks, option, password, sectors)
+                alongside_config(disks, option, password, sectors, sizes)
             }
             // Reuse existing partitions, without making any modifications.
             InstallOption::Refresh(option) => refresh_config(disks, option),
@@ -88,7 +97,9 @@ impl<'a> InstallOption<'a> {
                 recovery_config(disks, option, password)
             }
             // Reset the `disks` object and designate a disk to be wiped and installed.
-            InstallOption::Erase { option, password } => erase_config(disks, option, password),
+            InstallOption::Erase { option, password } => {
+                erase_config(disks, option, password, sizes)
+            }
             InstallOption::Upgrade(option) => upgrade_config(disks, option),
         }
     }
@@ -99,6 +110,7 @@ fn alongside_config(
     option: &AlongsideOption,
     password: Option<String>,
     sectors: u64,
+    sizes: &PartitionSizes,
 ) -> Result<(), InstallOptionError> {
     let mut tmp = Disks::default();
     mem::swap(&mut tmp, disks);
@@ -129,6 +141,27 @@ fn alongside_config(
     };
 
     let bootloader = Bootloader::detect();
+    let sector_size = device.get_logical_block_size();
+
+    // GRUB embeds its core image in a BIOS boot partition on GPT disks, which
+    // `verify_bios_grub` only accepts if it is large enough to hold it.
+    let needs_bios_grub = bootloader == Bootloader::Bios
+        && device.get_partition_table() == Some(PartitionTable::Gpt)
+        && !device.get_partitions().iter().any(|p| {
+            p.flags.contains(&PartitionFlag::PED_PARTITION_BIOS_GRUB)
+                && p.get_sectors() * sector_size >= BIOS_GRUB_SIZE
+        });
+
+    if needs_bios_grub {
+        let bios_grub_end = start + (BIOS_GRUB_SIZE + sector_size - 1) / sector_size;
+        device.add_partition(
+            PartitionBuilder::new(start, bios_grub_end, None)
+                .flag(PartitionFlag::PED_PARTITION_BIOS_GRUB)
+                .name("bios_grub".into()),
+        )?;
+
+        start = bios_grub_end;
+    }
 
     if bootloader == Bootloader::Efi {
         // NOTE: Logic that can enable re-using an existing EFI partition.
@@ -140,7 +173,7 @@ fn alongside_config(
         //     }
         // }
 
-        let esp_end = start + DEFAULT_ESP_SECTORS;
+        let esp_end = start + sizes.esp_sectors(sector_size);
 
         device.add_partition(
             PartitionBuilder::new(start, esp_end, Fat32)
@@ -150,25 +183,31 @@ fn alongside_config(
 
         start = esp_end;
 
-        let recovery_end = start + DEFAULT_RECOVER_SECTORS;
-        device.add_partition(
-            PartitionBuilder::new(start, recovery_end, Fat32)
-                .mount("/recovery".into())
-                .name("recovery".into()),
-        )?;
+        if sizes.recovery != 0 {
+            let recovery_end = start + sizes.recovery_sectors(sector_size);
+            device.add_partition(
+                PartitionBuilder::new(start, recovery_end, Fat32)
+                    .mount("/recovery".into())
+                    .name("recovery".into()),
+            )?;
+
+            start = recovery_end;
+        }
+    }
 
-        start = recovery_end;
-    } else if lvm.is_some() {
-        // BIOS systems with an encrypted root must have a separate boot partition.
-        let boot_end = start + DEFAULT_ESP_SECTORS;
+    // GRUB cannot read the kernel from an encrypted root, so it must have a separate boot
+    // partition. systemd-boot instead reads the kernel from the ESP.
+    if lvm.is_some() && kernel_loader(bootloader) == KernelLoader::Grub {
+        let boot_end = start + sizes.esp_sectors(sector_size);
 
-        device.add_partition(
-            PartitionBuilder::new(start, boot_end, Ext4)
+        let mut boot = PartitionBuilder::new(start, boot_end, Ext4).mount("/boot".into());
+        if bootloader == Bootloader::Bios {
+            boot = boot
                 .partition_type(PartitionType::Primary)
-                .flag(PartitionFlag::PED_PARTITION_BOOT)
-                .mount("/boot".into()),
-        )?;
+                .flag(PartitionFlag::PED_PARTITION_BOOT);
+        }
 
+        device.add_partition(boot)?;
         start = boot_end;
     }
 
@@ -180,10 +219,11 @@ fn alongside_config(
                 .logical_volume(root_vg, Some(enc)),
         )?;
     } else {
-        let swap = end - DEFAULT_SWAP_SECTORS;
+        let swap = end - sizes.swap_sectors(sector_size);
 
         // Only create a new unencrypted swap partition if a swap partition does not already exist.
-        let end = if !device.get_partitions().iter().any(|p| p.filesystem == Some(Swap)) {
+        let has_swap = device.get_partitions().iter().any(|p| p.filesystem == Some(Swap));
+        let end = if sizes.swap != 0 && !has_swap {
             device.add_partition(PartitionBuilder::new(swap, end, Swap))?;
             swap
         } else {
@@ -202,16 +242,24 @@ fn alongside_config(
             .ok_or(InstallOptionError::LogicalDeviceNotFound { vg: root_vg })?;
 
         let start = lvm_device.get_sector(Sector::Start);
-        let swap = lvm_device.get_sector(Sector::UnitFromEnd(DEFAULT_SWAP_SECTORS));
+        let swap_sectors = sizes.swap_sectors(lvm_device.sector_size);
+        let swap = lvm_device.get_sector(Sector::UnitFromEnd(swap_sectors));
         let end = lvm_device.get_sector(Sector::End);
 
-        lvm_device
-            .add_partition(
-                PartitionBuilder::new(start, swap, Ext4).name("root".into()).mount("/".into()),
-            )
-            .and_then(|_| {
-                lvm_device.add_partition(PartitionBuilder::new(swap, end, Swap).name("swap".into()))
-            })?;
+        if swap_sectors == 0 {
+            lvm_device.add_partition(
+                PartitionBuilder::new(start, end, Ext4).name("root".into()).mount("/".into()),
+            )?;
+        } else {
+            lvm_device
+                .add_partition(
+                    PartitionBuilder::new(start, swap, Ext4).name("root".into()).mount("/".into()),
+                )
+                .and_then(|_| {
+                    lvm_device
+                        .add_partition(PartitionBuilder::new(swap, end, Swap).name("swap".into()))
+                })?;
+        }
     }
 
     Ok(())
@@ -404,6 +452,7 @@ fn erase_config(
     disks: &mut Disks,
     option: &EraseOption,
     password: Option<String>,
+    sizes: &PartitionSizes,
 ) -> Result<(), InstallOptionError> {
     let mut tmp = Disks::default();
     mem::swap(&mut tmp, disks);
@@ -411,9 +460,6 @@ fn erase_config(
     let bootloader = Bootloader::detect();
 
     let start_sector = Sector::Start;
-    let boot_sector = Sector::Unit(DEFAULT_ESP_SECTORS);
-    let recovery_sector = Sector::Unit(DEFAULT_ESP_SECTORS + DEFAULT_RECOVER_SECTORS);
-    let swap_sector = Sector::UnitFromEnd(DEFAULT_SWAP_SECTORS);
     let end_sector = Sector::End;
 
     let (lvm, root_vg) = match generate_encryption(password)? {
@@ -426,6 +472,22 @@ fn erase_config(
             .ok()
             .ok_or(InstallOptionError::DeviceNotFound { path: option.device.clone() })?;
 
+        // The sizes are converted to the sectors of the device, relative to its first sector.
+        let sector_size = device.get_logical_block_size();
+        let first = device.get_sector(start_sector);
+        let boot_sector = Sector::Unit(first + sizes.esp_sectors(sector_size));
+        let recovery_sector = Sector::Unit(
+            first + sizes.esp_sectors(sector_size) + sizes.recovery_sectors(sector_size),
+        );
+        let efi_boot_sector = Sector::Unit(
+            first + sizes.esp_sectors(sector_size) * 2 + sizes.recovery_sectors(sector_size),
+        );
+        let swap_sector = Sector::UnitFromEnd(sizes.swap_sectors(sector_size));
+
+        // GRUB cannot read the kernel from an encrypted root, so it must have a separate boot
+        // partition. systemd-boot instead reads the kernel from the ESP.
+        let efi_needs_boot = lvm.is_some() && kernel_loader(bootloader) == KernelLoader::Grub;
+
         let result = match bootloader {
             Bootloader::Efi => {
                 device
@@ -441,8 +503,12 @@ fn erase_config(
                                 .mount("/boot/efi".into()),
                         )
                     })
-                    // Configure recovery partition
+                    // Configure recovery partition, unless it has been disabled
                     .and_then(|_| {
+                        if sizes.recovery == 0 {
+                            return Ok(());
+                        }
+
                         let start = device.get_sector(boot_sector);
                         let end = device.get_sector(recovery_sector);
                         device.add_partition(
@@ -451,7 +517,21 @@ fn erase_config(
                                 .mount("/recovery".into()),
                         )
                     })
-                    .map(|_| (device.get_sector(recovery_sector), device.get_sector(swap_sector)))
+                    // Configure boot partition, if the kernel loader requires one
+                    .and_then(|_| {
+                        if !efi_needs_boot {
+                            return Ok(recovery_sector);
+                        }
+
+                        let start = device.get_sector(recovery_sector);
+                        let end = device.get_sector(efi_boot_sector);
+                        device
+                            .add_partition(
+                                PartitionBuilder::new(start, end, Ext4).mount("/boot".into()),
+                            )
+                            .map(|_| efi_boot_sector)
+                    })
+                    .map(|start| (device.get_sector(start), device.get_sector(swap_sector)))
             }
             Bootloader::Bios => {
                 device
@@ -488,8 +568,12 @@ fn erase_config(
                     PartitionBuilder::new(start, end, Ext4).mount("/".into())
                 })
             })
-            // Configure swap partition
+            // Configure swap partition, unless it has been disabled
             .and_then(|_| {
+                if sizes.swap == 0 {
+                    return Ok(());
+                }
+
                 let start = device.get_sector(swap_sector);
                 let end = device.get_sector(end_sector);
                 device.add_partition(PartitionBuilder::new(start, end, Swap))
@@ -5,9 +5,11 @@ mod apply;
 mod erase_option;
 mod recovery_option;
 mod refresh_option;
+mod target;
 
 pub use self::{
     alongside_option::*, apply::*, erase_option::*, recovery_option::*, refresh_option::*,
+    target::*,
 };
 
 use super::super::*;
@@ -56,7 +58,14 @@ impl InstallOptions {
                     );
 
                     // Only consider Linux installs for refreshing.
-                    if let OS::Linux { ref info, ref partitions, ref targets } = os {
+                    if let OS::Linux {
+                        ref info,
+                        ref partitions,
+                        ref targets,
+                        ref kernels,
+                        ref encrypted,
+                    } = os
+                    {
                         let home = targets.iter().position(|t| t == Path::new("/home"));
                         let efi = targets.iter().position(|t| t == Path::new("/boot/efi"));
                         let recovery = targets.iter().position(|t| t == Path::new("/recovery"));
@@ -80,6 +89,8 @@ impl InstallOptions {
                             } else {
                                 false
                             },
+                            kernels:        kernels.clone(),
+                            encrypted:      *encrypted,
                         });
                     }
 
@@ -236,6 +247,10 @@ pub enum InstallOptionError {
     ProcMounts { why: io::Error },
     #[fail(display = "could not remount /cdrom as rewriteable: {}", _0)]
     RemountCdrom(io::Error),
+    #[fail(display = "'{}' is not a target selection strategy", term)]
+    TargetInvalid { term: String },
+    #[fail(display = "'{}' selected {} disks, rather than one", strategy, count)]
+    TargetSelect { strategy: String, count: usize },
 }
 
 impl From<DiskError> for InstallOptionError {
@@ -10,6 +10,10 @@ pub struct RefreshOption {
     pub efi_part:       Option<PartitionID>,
     pub recovery_part:  Option<PartitionID>,
     pub can_retain_old: bool,
+    /// The releases of the installed kernels, newest first.
+    pub kernels:        Vec<String>,
+    /// Whether the install is stored on, or unlocks at boot, an encrypted volume.
+    pub encrypted:      bool,
 }
 
 impl fmt::Display for RefreshOption {
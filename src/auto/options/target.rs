@@ -0,0 +1,206 @@
+//! Declarative strategies which select the disk that an unattended install erases, such as
+//! `bus=nvme,largest` or `empty`, so that one configuration may be used across machines whose
+//! disks differ, rather than naming a device path which only suits one of them.
+
+use super::{super::super::*, InstallOptionError};
+use std::{cmp::Reverse, fmt, str::FromStr};
+
+/// Narrows the disks which a strategy may select.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetFilter {
+    /// Disks which have no partitions, nor a file system.
+    Empty,
+    /// Disks attached by the given bus, such as `nvme`, `ata`, or `usb`.
+    Bus(String),
+    /// Disks which match a path, or a stable identifier such as a serial.
+    Selector(DiskSelector),
+}
+
+impl TargetFilter {
+    fn matches(&self, disk: &Disk) -> bool {
+        match self {
+            TargetFilter::Empty => disk.get_partitions().is_empty() && disk.file_system.is_none(),
+            TargetFilter::Bus(bus) => disk.get_bus().eq_ignore_ascii_case(bus),
+            TargetFilter::Selector(selector) => selector.matches(disk),
+        }
+    }
+}
+
+impl fmt::Display for TargetFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TargetFilter::Empty => f.write_str("empty"),
+            TargetFilter::Bus(bus) => write!(f, "bus={}", bus),
+            TargetFilter::Selector(selector) => write!(f, "{}", selector),
+        }
+    }
+}
+
+/// Chooses between the disks which remain once filtered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetPick {
+    /// Exactly one disk must remain.
+    Only,
+    Largest,
+    Smallest,
+}
+
+/// Selects the disk to erase, as a comma-separated list of filters, and of `largest` or
+/// `smallest` to choose between the disks which pass them, such as `empty,largest`.
+///
+/// Disks of the same size are chosen between by their serials, which, unlike their device
+/// paths, do not change across boots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetStrategy {
+    pub filters: Vec<TargetFilter>,
+    pub pick:    TargetPick,
+}
+
+impl TargetStrategy {
+    /// The selector of a strategy which names one disk, and which may be obtained without
+    /// probing the other disks.
+    pub fn selector(&self) -> Option<&DiskSelector> {
+        match (self.filters.as_slice(), self.pick) {
+            ([TargetFilter::Selector(selector)], TargetPick::Only) => Some(selector),
+            _ => None,
+        }
+    }
+
+    /// Selects a disk of the system which is writable, has at least `required` sectors, and
+    /// does not hold the live system.
+    pub fn select<'a>(
+        &self,
+        disks: &'a Disks,
+        required: u64,
+    ) -> Result<&'a Disk, InstallOptionError> {
+        let candidates = disks.get_physical_devices().iter().filter(|disk| {
+            !disk.is_read_only()
+                && disk.get_sectors() >= required
+                && !disk.contains_mount("/", disks)
+                && !disk.contains_mount("/cdrom", disks)
+        });
+
+        self.choose(candidates)
+    }
+
+    fn choose<'a, I>(&self, candidates: I) -> Result<&'a Disk, InstallOptionError>
+    where
+        I: Iterator<Item = &'a Disk>,
+    {
+        let mut matched: Vec<&Disk> = candidates
+            .filter(|disk| self.filters.iter().all(|filter| filter.matches(disk)))
+            .collect();
+
+        matched.sort_by(|a, b| a.get_serial().cmp(b.get_serial()));
+
+        let chosen = match self.pick {
+            TargetPick::Only if matched.len() == 1 => matched.first(),
+            TargetPick::Only => None,
+            TargetPick::Largest => matched.iter().min_by_key(|disk| Reverse(disk.get_sectors())),
+            TargetPick::Smallest => matched.iter().min_by_key(|disk| disk.get_sectors()),
+        };
+
+        chosen.copied().ok_or_else(|| InstallOptionError::TargetSelect {
+            strategy: self.to_string(),
+            count:    matched.len(),
+        })
+    }
+}
+
+impl FromStr for TargetStrategy {
+    type Err = InstallOptionError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut strategy = TargetStrategy { filters: Vec::new(), pick: TargetPick::Only };
+        for term in input.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let filter = match term {
+                "largest" => {
+                    strategy.pick = TargetPick::Largest;
+                    continue;
+                }
+                "smallest" => {
+                    strategy.pick = TargetPick::Smallest;
+                    continue;
+                }
+                "empty" => TargetFilter::Empty,
+                _ => match term.strip_prefix("bus=") {
+                    Some(bus) if !bus.is_empty() => TargetFilter::Bus(bus.to_lowercase()),
+                    None if term.starts_with('/') || term.contains('=') => {
+                        TargetFilter::Selector(term.parse()?)
+                    }
+                    _ => return Err(InstallOptionError::TargetInvalid { term: term.into() }),
+                },
+            };
+
+            strategy.filters.push(filter);
+        }
+
+        if strategy.filters.is_empty() && strategy.pick == TargetPick::Only {
+            return Err(InstallOptionError::TargetInvalid { term: input.into() });
+        }
+
+        Ok(strategy)
+    }
+}
+
+impl fmt::Display for TargetStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms: Vec<String> = self.filters.iter().map(ToString::to_string).collect();
+        match self.pick {
+            TargetPick::Only => (),
+            TargetPick::Largest => terms.push("largest".into()),
+            TargetPick::Smallest => terms.push("smallest".into()),
+        }
+
+        f.write_str(&terms.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn disk(path: &str, serial: &str, bus: &str, size: u64, empty: bool) -> Disk {
+        Disk {
+            device_path: path.into(),
+            serial:      serial.into(),
+            bus:         bus.into(),
+            size,
+            file_system: if empty {
+                None
+            } else {
+                Some(PartitionBuilder::new(0, size, FileSystem::Ext4).build())
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn target_strategies() {
+        let disks = [
+            disk("/dev/sda", "HDD_B", "ata", 8_000_000, true),
+            disk("/dev/sdb", "HDD_A", "ata", 8_000_000, true),
+            disk("/dev/nvme0n1", "NVME_A", "nvme", 1_000_000, false),
+            disk("/dev/nvme1n1", "NVME_B", "nvme", 2_000_000, true),
+        ];
+
+        let choose = |input: &str| {
+            let strategy = input.parse::<TargetStrategy>().unwrap();
+            strategy.choose(disks.iter()).map(|disk| disk.device_path.clone())
+        };
+
+        assert_eq!(choose("largest").unwrap(), Path::new("/dev/sdb"));
+        assert_eq!(choose("bus=NVMe,smallest").unwrap(), Path::new("/dev/nvme0n1"));
+        assert_eq!(choose("bus=nvme,empty").unwrap(), Path::new("/dev/nvme1n1"));
+        assert_eq!(choose("serial=HDD_B").unwrap(), Path::new("/dev/sda"));
+        assert!(choose("empty").is_err());
+        assert!(choose("bus=usb,largest").is_err());
+
+        let strategy = "empty, bus=nvme, largest".parse::<TargetStrategy>().unwrap();
+        assert_eq!(strategy.to_string(), "empty,bus=nvme,largest");
+        assert!("/dev/sda".parse::<TargetStrategy>().unwrap().selector().is_some());
+        assert!("biggest".parse::<TargetStrategy>().is_err());
+        assert!("".parse::<TargetStrategy>().is_err());
+    }
+}
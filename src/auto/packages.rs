@@ -0,0 +1,235 @@
+//! Carry the manually-installed packages, and their apt sources, over to a reinstall.
+
+use super::{mount_and_then, ReinstallError};
+use disk_types::FileSystem;
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io,
+    os::unix::fs::symlink,
+    path::Path,
+};
+
+use crate::misc;
+
+const DPKG_STATUS: &str = "var/lib/dpkg/status";
+const EXTENDED_STATES: &str = "var/lib/apt/extended_states";
+const SOURCES_DIR: &str = "etc/apt/sources.list.d";
+const KEYRINGS_DIR: &str = "etc/apt/trusted.gpg.d";
+const PACKAGE_LIST: &str = "var/lib/distinst/restore-packages";
+const SERVICE: &str = "distinst-restore-packages.service";
+
+/// Reinstalls the packages on first boot, once networking is available.
+const SERVICE_UNIT: &str = "[Unit]
+Description=Reinstall packages from the previous installation
+Wants=network-online.target
+After=network-online.target
+ConditionPathExists=/var/lib/distinst/restore-packages
+
+[Service]
+Type=oneshot
+Environment=DEBIAN_FRONTEND=noninteractive
+ExecStartPre=-/usr/bin/apt-get update
+ExecStart=-/usr/bin/xargs -a /var/lib/distinst/restore-packages -n 1 /usr/bin/apt-get install -y
+ExecStartPost=/bin/rm -f /var/lib/distinst/restore-packages
+
+[Install]
+WantedBy=multi-user.target
+";
+
+/// Packages which were manually installed on the previous install, and the apt sources
+/// that they were installed from.
+#[derive(Debug, Default)]
+pub struct PackageSelections {
+    pub manual:   Vec<String>,
+    pub sources:  Vec<(OsString, Vec<u8>)>,
+    pub keyrings: Vec<(OsString, Vec<u8>)>,
+}
+
+impl PackageSelections {
+    /// Collects the package selections from the install on the given device.
+    pub fn new(device: &Path, fs: FileSystem) -> Result<PackageSelections, ReinstallError> {
+        info!("retrieving manually-installed packages and apt sources");
+        mount_and_then(device, fs, |base| {
+            let status = fs::read_to_string(base.join(DPKG_STATUS))
+                .map_err(|why| ReinstallError::PackagesObtain { why })?;
+            let states = fs::read_to_string(base.join(EXTENDED_STATES)).unwrap_or_default();
+
+            Ok(PackageSelections {
+                manual:   manual_packages(&status, &states),
+                sources:  read_files(&base.join(SOURCES_DIR)),
+                keyrings: read_files(&base.join(KEYRINGS_DIR)),
+            })
+        })
+    }
+
+    /// Writes the apt sources into the new install, and schedules the packages that it lacks
+    /// to be installed on first boot.
+    pub fn restore(&self, base: &Path) -> io::Result<()> {
+        restore_files(&base.join(SOURCES_DIR), &self.sources)?;
+        restore_files(&base.join(KEYRINGS_DIR), &self.keyrings)?;
+
+        let status = fs::read_to_string(base.join(DPKG_STATUS)).unwrap_or_default();
+        let installed = installed_packages(&status);
+        let missing = self
+            .manual
+            .iter()
+            .filter(|package| !installed.contains(package.as_str()))
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        info!("scheduling packages to be reinstalled on first boot: {:?}", missing);
+        let list = base.join(PACKAGE_LIST);
+        fs::create_dir_all(list.parent().expect("package list without parent"))?;
+        misc::write(&list, [missing.join("\n").as_str(), "\n"].concat())?;
+
+        let system = base.join("etc/systemd/system");
+        let wants = system.join("multi-user.target.wants");
+        fs::create_dir_all(&wants)?;
+        misc::write(system.join(SERVICE), SERVICE_UNIT)?;
+
+        let link = wants.join(SERVICE);
+        if !link.exists() {
+            symlink(Path::new("/etc/systemd/system").join(SERVICE), link)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Packages which are installed, but were not marked as automatically installed by apt.
+fn manual_packages(status: &str, extended_states: &str) -> Vec<String> {
+    let auto = paragraphs(extended_states)
+        .filter(|entry| field(entry, "Auto-Installed") == Some("1"))
+        .filter_map(|entry| field(entry, "Package"))
+        .collect::<HashSet<&str>>();
+
+    let mut manual = installed_packages(status)
+        .into_iter()
+        .filter(|package| !auto.contains(package))
+        .map(String::from)
+        .collect::<Vec<String>>();
+
+    manual.sort();
+    manual
+}
+
+/// Packages that dpkg has recorded as being installed.
+fn installed_packages(status: &str) -> HashSet<&str> {
+    paragraphs(status)
+        .filter(|entry| field(entry, "Status").map_or(false, |s| s.ends_with(" installed")))
+        .filter_map(|entry| field(entry, "Package"))
+        .collect()
+}
+
+/// The combined installed size, in bytes, of the given packages.
+pub(crate) fn installed_size(status: &str, packages: &[&str]) -> u64 {
+    paragraphs(status)
+        .filter(|entry| field(entry, "Package").map_or(false, |name| packages.contains(&name)))
+        .filter(|entry| field(entry, "Status").map_or(false, |s| s.ends_with(" installed")))
+        .filter_map(|entry| field(entry, "Installed-Size"))
+        .filter_map(|size| size.parse::<u64>().ok())
+        .sum::<u64>()
+        * 1024
+}
+
+fn paragraphs(data: &str) -> impl Iterator<Item = &str> {
+    data.split("\n\n").filter(|entry| !entry.trim().is_empty())
+}
+
+fn field<'a>(paragraph: &'a str, key: &str) -> Option<&'a str> {
+    paragraph.lines().find_map(|line| {
+        if line.starts_with(key) && line[key.len()..].starts_with(':') {
+            Some(line[key.len() + 1..].trim())
+        } else {
+            None
+        }
+    })
+}
+
+fn read_files(directory: &Path) -> Vec<(OsString, Vec<u8>)> {
+    directory
+        .read_dir()
+        .ok()
+        .map(|entries| {
+            entries
+                .flat_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .filter_map(|file| misc::read(file.path()).ok().map(|data| (file.file_name(), data)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Files which already exist in the new install are left untouched.
+fn restore_files(directory: &Path, files: &[(OsString, Vec<u8>)]) -> io::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(directory)?;
+    for &(ref name, ref data) in files {
+        let path = directory.join(name);
+        if !path.exists() {
+            info!("restoring {:?}", path);
+            misc::write(&path, data)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "Package: vim
+Status: install ok installed
+Installed-Size: 3000
+Architecture: amd64
+
+Package: libgpm2
+Status: install ok installed
+Installed-Size: 50
+Architecture: amd64
+
+Package: emacs
+Status: deinstall ok config-files
+Installed-Size: 9000
+Architecture: amd64
+";
+
+    const EXTENDED_STATES: &str = "Package: libgpm2
+Architecture: amd64
+Auto-Installed: 1
+
+Package: vim
+Architecture: amd64
+Auto-Installed: 0
+";
+
+    #[test]
+    fn installed() {
+        let installed = installed_packages(STATUS);
+        assert!(installed.contains("vim"));
+        assert!(installed.contains("libgpm2"));
+        assert!(!installed.contains("emacs"));
+    }
+
+    #[test]
+    fn manual() {
+        assert_eq!(manual_packages(STATUS, EXTENDED_STATES), vec!["vim".to_owned()]);
+        assert_eq!(manual_packages(STATUS, ""), vec!["libgpm2".to_owned(), "vim".to_owned()]);
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(installed_size(STATUS, &["vim", "libgpm2"]), 3050 * 1024);
+        assert_eq!(installed_size(STATUS, &["emacs"]), 0);
+    }
+}
@@ -0,0 +1,152 @@
+//! Carry additional files and directories from the old install over to a reinstall.
+
+use super::{mount_and_then, ReinstallError};
+use disk_types::FileSystem;
+use libc;
+use std::{
+    ffi::{CString, OsString},
+    fs::{self, Permissions},
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{symlink, MetadataExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use crate::misc;
+
+#[derive(Debug)]
+enum EntryKind {
+    Directory,
+    File(Vec<u8>),
+    Symlink(PathBuf),
+}
+
+/// A file, directory, or symlink, relative to the root of the install.
+#[derive(Debug)]
+struct PreservedEntry {
+    path:   PathBuf,
+    kind:   EntryKind,
+    mode:   u32,
+    uid:    u32,
+    gid:    u32,
+    xattrs: Vec<(OsString, Vec<u8>)>,
+}
+
+/// Files collected from the old install, along with their ownership, permissions, and
+/// extended attributes, which hold their ACLs, file capabilities, and security labels.
+#[derive(Debug, Default)]
+pub struct PreservedFiles(Vec<PreservedEntry>);
+
+impl PreservedFiles {
+    /// Collects the given paths, recursively, from the install on the given device.
+    pub fn new<P: AsRef<Path>>(
+        device: &Path,
+        fs: FileSystem,
+        paths: &[P],
+    ) -> Result<PreservedFiles, ReinstallError> {
+        mount_and_then(device, fs, |base| {
+            let mut entries = Vec::new();
+            for path in paths {
+                let path = path.as_ref();
+                let relative = path.strip_prefix("/").unwrap_or(path);
+                collect(base, relative, &mut entries)
+                    .map_err(|why| ReinstallError::PreserveObtain { path: path.into(), why })?;
+            }
+
+            Ok(PreservedFiles(entries))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Writes the preserved entries into the install at `base`.
+    pub fn restore(&self, base: &Path) -> io::Result<()> {
+        for entry in &self.0 {
+            let path = base.join(&entry.path);
+            info!("restoring /{}", entry.path.display());
+
+            match entry.kind {
+                EntryKind::Directory => fs::create_dir_all(&path)?,
+                EntryKind::File(ref data) => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+
+                    misc::write(&path, data)?;
+                }
+                EntryKind::Symlink(ref target) => {
+                    if path.symlink_metadata().is_ok() {
+                        fs::remove_file(&path)?;
+                    }
+
+                    symlink(target, &path)?;
+                }
+            }
+
+            lchown(&path, entry.uid, entry.gid)?;
+
+            if let EntryKind::Symlink(_) = entry.kind {
+                continue;
+            }
+
+            fs::set_permissions(&path, Permissions::from_mode(entry.mode))?;
+            misc::xattr::apply(&path, &entry.xattrs)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn collect(base: &Path, relative: &Path, entries: &mut Vec<PreservedEntry>) -> io::Result<()> {
+    let path = base.join(relative);
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => {
+            info!("/{} was not found in the old install", relative.display());
+            return Ok(());
+        }
+        Err(why) => return Err(why),
+    };
+
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_symlink() {
+        EntryKind::Symlink(fs::read_link(&path)?)
+    } else if file_type.is_dir() {
+        EntryKind::Directory
+    } else if file_type.is_file() {
+        EntryKind::File(misc::read(&path)?)
+    } else {
+        return Ok(());
+    };
+
+    let is_dir = file_type.is_dir();
+    let xattrs = if file_type.is_symlink() { Vec::new() } else { misc::xattr::list(&path)? };
+
+    entries.push(PreservedEntry {
+        path: relative.to_path_buf(),
+        kind,
+        mode: metadata.mode(),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        xattrs,
+    });
+
+    if is_dir {
+        for entry in path.read_dir()? {
+            collect(base, &relative.join(entry?.file_name()), entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn lchown(path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::lchown(cpath.as_ptr(), uid, gid) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
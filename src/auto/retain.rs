@@ -4,20 +4,26 @@ use crate::bootloader::Bootloader;
 use disk_types::FileSystem;
 use crate::disks::Disks;
 
-use super::{mount_and_then, AccountFiles, ReinstallError, UserData};
+use super::{
+    mount_and_then, AccountFiles, PackageSelections, PreservedFiles, ReinstallError, UserData,
+};
 
 use crate::misc;
 use std::{
     ffi::{OsStr, OsString},
+    fmt,
     fs::{self, File, OpenOptions, Permissions},
     io::{self, Read, Seek, SeekFrom, Write},
     os::unix::{
         ffi::OsStrExt,
-        fs::{symlink, PermissionsExt},
+        fs::{symlink, MetadataExt, PermissionsExt},
     },
     path::{Path, PathBuf},
 };
 
+/// The number of directories to list in a `SpaceReport`.
+const LARGEST_DIRECTORIES: usize = 10;
+
 /// Removes all files in the chroot at `/`, except for `/home`.
 pub fn remove_root(root_path: &Path, root_fs: FileSystem) -> Result<(), ReinstallError> {
     info!("removing all files except /home. This may take a while...");
@@ -106,10 +112,117 @@ pub fn validate_backup_conditions<P: AsRef<Path>>(
     partition_configuration_is_valid(&disks).and_then(|_| install_media_exists(path.as_ref()))
 }
 
+/// Space required by the new install, versus the space available on a partition whose contents
+/// are being preserved.
+#[derive(Debug, Clone)]
+pub struct SpaceReport {
+    /// Where the partition is mounted in the install.
+    pub mount:     PathBuf,
+    /// Bytes required by the new install.
+    pub required:  u64,
+    /// Bytes available once the old system files have been removed.
+    pub available: u64,
+    /// The largest preserved directories, in bytes, which could be deleted to free space.
+    pub largest:   Vec<(PathBuf, u64)>,
+}
+
+impl SpaceReport {
+    pub fn is_sufficient(&self) -> bool { self.required <= self.available }
+}
+
+impl fmt::Display for SpaceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} requires {} bytes, but {} bytes are available",
+            self.mount.display(),
+            self.required,
+            self.available
+        )?;
+
+        for (id, &(ref path, size)) in self.largest.iter().enumerate() {
+            let sep = if id == 0 { "; largest directories: " } else { ", " };
+            write!(f, "{}{} ({} bytes)", sep, path.display(), size)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reports how much space the root partition will have for the new install, before anything
+/// on it is removed.
+///
+/// If `keep_old_root` is set, the old system files will be moved to `/linux.old` instead of
+/// being removed, and will therefore continue to occupy space.
+pub fn validate_before_removing(
+    root_path: &Path,
+    root_fs: FileSystem,
+    keep_old_root: bool,
+    required: u64,
+) -> Result<SpaceReport, ReinstallError> {
+    info!("validating that {:?} has space for the new install", root_path);
+    mount_and_then(root_path, root_fs, |base| {
+        let device = base.metadata()?.dev();
+        let (_, mut available) = misc::fs_usage(base)?;
+        let mut candidates = Vec::new();
+
+        for entry in base.read_dir()?.filter_map(Result::ok) {
+            let name = entry.file_name();
+            if name == "home" {
+                for user in entry.path().read_dir()?.filter_map(Result::ok) {
+                    let size = disk_usage(&user.path(), device);
+                    candidates.push((Path::new("/home").join(user.file_name()), size));
+                }
+            } else if keep_old_root || name == "linux.old" {
+                let size = disk_usage(&entry.path(), device);
+                candidates.push((Path::new("/").join(name), size));
+            } else {
+                available += disk_usage(&entry.path(), device);
+            }
+        }
+
+        candidates.sort_by(|a, b| b.1.cmp(&a.1));
+        candidates.truncate(LARGEST_DIRECTORIES);
+
+        Ok(SpaceReport { mount: PathBuf::from("/"), required, available, largest: candidates })
+    })
+}
+
+/// The number of bytes that an install from the given squashfs will require, if known.
+pub(crate) fn install_size(squashfs: &Path) -> Option<u64> {
+    fs::read_to_string(squashfs.with_file_name("filesystem.size"))
+        .ok()
+        .and_then(|size| size.trim().parse::<u64>().ok())
+}
+
+/// Disk usage of the path, in bytes, without crossing into other file systems.
+pub(crate) fn disk_usage(path: &Path, device: u64) -> u64 {
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+
+    if metadata.dev() != device {
+        return 0;
+    }
+
+    let mut total = metadata.blocks() * 512;
+    if metadata.is_dir() {
+        if let Ok(entries) = path.read_dir() {
+            for entry in entries.filter_map(Result::ok) {
+                total += disk_usage(&entry.path(), device);
+            }
+        }
+    }
+
+    total
+}
+
 /// Validate that the configuration in the disks structure is valid for installation.
 fn partition_configuration_is_valid(disks: &Disks) -> Result<(), ReinstallError> {
+    let bootloader = Bootloader::detect();
     disks
-        .verify_partitions(Bootloader::detect())
+        .verify_partitions(bootloader, crate::kernel_loader(bootloader), false)
         .map_err(|why| ReinstallError::InvalidPartitionConfiguration { why })
 }
 
@@ -150,6 +263,8 @@ pub struct Backup<'a> {
     pub localtime: Option<PathBuf>,
     pub timezone:  Option<Vec<u8>>,
     pub networks:  Option<Vec<(OsString, Vec<u8>)>>,
+    pub packages:  Option<PackageSelections>,
+    pub preserved: Option<PreservedFiles>,
 }
 
 impl<'a> Backup<'a> {
@@ -159,6 +274,8 @@ impl<'a> Backup<'a> {
         fs: FileSystem,
         is_root: bool,
         account_files: &'a AccountFiles,
+        mut packages: Option<PackageSelections>,
+        mut preserved: Option<PreservedFiles>,
     ) -> Result<Backup<'a>, ReinstallError> {
         mount_and_then(device, fs, |base| {
             info!("collecting list of user accounts");
@@ -196,7 +313,14 @@ impl<'a> Backup<'a> {
 
             let users = users.iter().filter_map(|user| account_files.get(user)).collect::<Vec<_>>();
 
-            Ok(Backup { users, localtime, timezone, networks })
+            Ok(Backup {
+                users,
+                localtime,
+                timezone,
+                networks,
+                packages: packages.take(),
+                preserved: preserved.take(),
+            })
         })
     }
 
@@ -300,6 +424,16 @@ impl<'a> Backup<'a> {
                 }
             }
 
+            if let Some(ref preserved) = self.preserved {
+                info!("restoring preserved files");
+                preserved.restore(base)?;
+            }
+
+            if let Some(ref packages) = self.packages {
+                info!("restoring apt sources and package selections");
+                packages.restore(base)?;
+            }
+
             Ok(())
         })
     }
@@ -0,0 +1,165 @@
+//! Hashes passwords for `/etc/shadow` with libcrypt, so that frontends need not link their own
+//! crypt implementation to supply pre-hashed passwords.
+
+use libc::{c_char, c_int, c_ulong, c_void};
+use std::{
+    ffi::{CStr, CString},
+    fs, io,
+    path::Path,
+    ptr,
+};
+
+#[link(name = "crypt")]
+extern "C" {
+    fn crypt_gensalt_ra(
+        prefix: *const c_char,
+        count: c_ulong,
+        rbytes: *const c_char,
+        nrbytes: c_int,
+    ) -> *mut c_char;
+
+    fn crypt_ra(
+        phrase: *const c_char,
+        setting: *const c_char,
+        data: *mut *mut c_void,
+        size: *mut c_int,
+    ) -> *mut c_char;
+}
+
+/// A scheme which passwords are hashed with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scheme {
+    Yescrypt,
+    Sha512Crypt,
+}
+
+impl Scheme {
+    /// The scheme which the system at `root` hashes passwords with, as configured for
+    /// `pam_unix`, or else by the `ENCRYPT_METHOD` of its `login.defs`. Systems which state
+    /// neither are given sha512crypt, which every libcrypt supports.
+    pub fn preferred(root: &Path) -> Scheme {
+        let read = |path: &str| fs::read_to_string(root.join(path)).unwrap_or_default();
+
+        Scheme::from_pam(&read("etc/pam.d/common-password"))
+            .or_else(|| Scheme::from_login_defs(&read("etc/login.defs")))
+            .unwrap_or(Scheme::Sha512Crypt)
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Scheme::Yescrypt => "$y$",
+            Scheme::Sha512Crypt => "$6$",
+        }
+    }
+
+    fn from_pam(config: &str) -> Option<Scheme> {
+        config
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#') && line.contains("pam_unix.so"))
+            .flat_map(|line| line.split_whitespace())
+            .find_map(|option| match option {
+                "yescrypt" => Some(Scheme::Yescrypt),
+                "sha512" => Some(Scheme::Sha512Crypt),
+                _ => None,
+            })
+    }
+
+    fn from_login_defs(defs: &str) -> Option<Scheme> {
+        defs.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("ENCRYPT_METHOD"), Some(method)) => match method {
+                    "YESCRYPT" => Some(Scheme::Yescrypt),
+                    "SHA512" => Some(Scheme::Sha512Crypt),
+                    _ => None,
+                },
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Hashes `password` with a random salt.
+pub fn hash_password(password: &str, scheme: Scheme) -> io::Result<String> {
+    let prefix = CString::new(scheme.prefix()).expect("prefix with a NUL byte");
+
+    // A null source of random bytes has libcrypt draw them from the OS.
+    let salt = unsafe { crypt_gensalt_ra(prefix.as_ptr(), 0, ptr::null(), 0) };
+    if salt.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    let result = unsafe { crypt(password, salt) };
+    unsafe { libc::free(salt as *mut c_void) };
+    result
+}
+
+/// Whether `password` is the password which `hash` was hashed from.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    let setting = match CString::new(hash) {
+        Ok(setting) => setting,
+        Err(_) => return false,
+    };
+
+    unsafe { crypt(password, setting.as_ptr()) }.map_or(false, |result| result == hash)
+}
+
+/// Whether `hash` has the form of a hash which may be given to `chpasswd -e`: a crypt hash, or a
+/// `!` or `*` which locks the password.
+pub fn is_hash(hash: &str) -> bool {
+    let locked = hash.starts_with('!') || hash.starts_with('*');
+    let hashed = hash.starts_with('$') && hash.len() > 3;
+    (locked || hashed) && !hash.contains(|c: char| c == ':' || c.is_whitespace() || c.is_control())
+}
+
+unsafe fn crypt(password: &str, setting: *const c_char) -> io::Result<String> {
+    let phrase = CString::new(password).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "password contains a NUL byte")
+    })?;
+
+    let (mut data, mut size) = (ptr::null_mut(), 0);
+    let hash = crypt_ra(phrase.as_ptr(), setting, &mut data, &mut size);
+
+    let result = if hash.is_null() || *hash == b'*' as c_char {
+        Err(io::Error::new(io::ErrorKind::Other, "libcrypt was unable to hash the password"))
+    } else {
+        Ok(CStr::from_ptr(hash).to_string_lossy().into_owned())
+    };
+
+    // The buffer holds the password as it was processed, so it is cleared before it is freed.
+    if !data.is_null() {
+        ptr::write_bytes(data as *mut u8, 0, size as usize);
+        libc::free(data);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferred_scheme() {
+        let pam = "# here are the per-package modules (the \"Primary\" block)\n\
+                   password [success=1 default=ignore] pam_unix.so obscure yescrypt\n";
+        assert_eq!(Scheme::from_pam(pam), Some(Scheme::Yescrypt));
+        assert_eq!(Scheme::from_pam("# pam_unix.so sha512\n"), None);
+
+        let defs = "# ENCRYPT_METHOD MD5\nENCRYPT_METHOD SHA512\n";
+        assert_eq!(Scheme::from_login_defs(defs), Some(Scheme::Sha512Crypt));
+    }
+
+    #[test]
+    fn hashes() {
+        assert!(is_hash("$6$salt$hash"));
+        assert!(is_hash("!"));
+        assert!(!is_hash("password"));
+        assert!(!is_hash("$6$salt$hash:0:99999"));
+
+        let hash = hash_password("password", Scheme::Sha512Crypt).unwrap();
+        assert!(hash.starts_with("$6$") && is_hash(&hash));
+        assert!(verify_password("password", &hash));
+        assert!(!verify_password("passw0rd", &hash));
+    }
+}
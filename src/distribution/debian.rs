@@ -1,11 +1,11 @@
 use crate::bootloader::Bootloader;
 use crate::chroot::Chroot;
+use crate::external::runner::runner;
 use crate::installer::{bitflags::FileSystemSupport, traits::InstallerDiskOps};
 use os_release::OsRelease;
 use std::{
     collections::HashSet,
     io::{self, BufRead},
-    process::Command,
 };
 
 pub fn check_language_support(lang: &str, chroot: &Chroot) -> io::Result<Option<String>> {
@@ -67,7 +67,8 @@ pub fn get_dependencies_from_list<P: AsRef<str>>(deps: &[P]) -> Option<Vec<Strin
 }
 
 fn get_dependencies_from_package<A: FnMut(&str), P: AsRef<str>>(dep: P, mut action: A) {
-    let output = Command::new("apt-cache").args(&["show", dep.as_ref()]).output().ok();
+    let args = ["show".into(), dep.as_ref().into()];
+    let output = runner().run("apt-cache", &args, None, true).ok();
 
     if let Some(output) = output {
         for line in io::Cursor::new(output.stdout).lines() {
@@ -1,4 +1,4 @@
-use crate::disks::DiskError;
+use crate::disks::{CodedError, DiskError};
 use std::{error::Error, fmt::Display, io};
 
 /// Extends `Option<T>` to be converted into an `io::Result<T>`.
@@ -24,9 +24,18 @@ pub trait IoContext<T> {
     fn with_context<F: FnMut(Box<dyn Display>) -> String>(self, func: F) -> io::Result<T>;
 }
 
+/// The code and device of a `CodedError` are retained through the added context.
 impl<T> IoContext<T> for io::Result<T> {
     fn with_context<F: FnMut(Box<dyn Display>) -> String>(self, mut func: F) -> io::Result<T> {
-        self.map_err(|why| io::Error::new(why.kind(), func(Box::new(why))))
+        self.map_err(|why| {
+            let kind = why.kind();
+            let coded = CodedError::of(&why).map(|coded| (coded.code, coded.device.clone()));
+            let message = func(Box::new(why));
+            match coded {
+                Some((code, device)) => CodedError { code, device, message }.into_io(kind),
+                None => io::Error::new(kind, message),
+            }
+        })
     }
 }
 
@@ -0,0 +1,117 @@
+//! Estimates the space that an install will require, so that frontends may display accurate
+//! requirements for each layout choice.
+
+use crate::auto::{disk_usage, installed_size};
+use crate::{
+    Bootloader, Config, CAPTURE_FACTORY_IMAGE, INSTALL_HARDWARE_SUPPORT, RUN_UBUNTU_DRIVERS,
+};
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+/// The size of the uncompressed image, as recorded on the live media.
+const FILESYSTEM_SIZE: &str = "/cdrom/casper/filesystem.size";
+
+/// The compressed image on the live media.
+const FILESYSTEM_SQUASHFS: &str = "/cdrom/casper/filesystem.squashfs";
+
+/// Where casper mounts the image in the live session.
+const LIVE_ROOT: &str = "/rofs";
+
+/// Used in the absence of `filesystem.size`.
+const DEFAULT_ROOT_SIZE: u64 = 5_000_000_000;
+
+/// Space reserved for drivers and hardware support packages, which are not on the image.
+const EXTRA_PACKAGES_SIZE: u64 = 1_073_741_824;
+
+/// Mount points which may be given a partition of their own.
+const SEPARABLE_MOUNTS: &[&str] = &["/boot", "/opt", "/srv", "/usr", "/var"];
+
+/// Space, in bytes, that an install will require.
+#[derive(Debug, Clone, Default)]
+pub struct InstallSizeEstimate {
+    /// Bytes required by the root file system, including any extra packages.
+    pub root:     u64,
+    /// Bytes required by the EFI system partition, if the system is EFI.
+    pub esp:      u64,
+    /// Bytes required by the recovery partition, if one will be created.
+    pub recovery: u64,
+    /// Bytes required by the swap partition.
+    pub swap:     u64,
+    /// Bytes that the image places beneath mount points that may be separated from the root.
+    pub mounts:   Vec<(PathBuf, u64)>,
+}
+
+impl InstallSizeEstimate {
+    /// The total number of bytes required.
+    pub fn total(&self) -> u64 { self.root + self.esp + self.recovery + self.swap }
+
+    /// The total number of sectors required.
+    pub fn total_sectors(&self) -> u64 { self.total() / 512 + 1 }
+
+    /// Bytes that the image places beneath the given mount point.
+    pub fn mount(&self, mount: &Path) -> Option<u64> {
+        self.mounts.iter().find(|&&(ref path, _)| path == mount).map(|&(_, size)| size)
+    }
+}
+
+/// Estimates the space that an install with the given configuration will require.
+///
+/// The per-mount breakdown is read from the image mounted by the live session, and will be
+/// empty when distinst is not running from live media.
+pub fn estimated_install_size(config: &Config) -> InstallSizeEstimate {
+    let image = read_size(FILESYSTEM_SIZE).unwrap_or(DEFAULT_ROOT_SIZE);
+
+    let removed = fs::read_to_string(&config.remove)
+        .ok()
+        .and_then(|remove| {
+            let packages = remove.split_whitespace().collect::<Vec<&str>>();
+            fs::read_to_string("/var/lib/dpkg/status")
+                .ok()
+                .map(|status| installed_size(&status, &packages))
+        })
+        .unwrap_or(0);
+
+    let extra = if config.flags & (INSTALL_HARDWARE_SUPPORT | RUN_UBUNTU_DRIVERS) != 0 {
+        EXTRA_PACKAGES_SIZE
+    } else {
+        0
+    };
+
+    let efi = Bootloader::detect() == Bootloader::Efi;
+
+    let sizes = &config.partition_sizes;
+    let mut recovery = if efi { sizes.recovery } else { 0 };
+    if config.flags & CAPTURE_FACTORY_IMAGE != 0 {
+        recovery += fs::metadata(FILESYSTEM_SQUASHFS).map(|m| m.len()).unwrap_or(image / 2);
+    }
+
+    InstallSizeEstimate {
+        root: image.saturating_sub(removed) + extra,
+        esp: if efi { sizes.esp } else { 0 },
+        recovery,
+        swap: sizes.swap,
+        mounts: mount_sizes(Path::new(LIVE_ROOT)),
+    }
+}
+
+fn read_size(path: &str) -> Option<u64> {
+    fs::read_to_string(path).ok().and_then(|size| size.trim().parse::<u64>().ok())
+}
+
+fn mount_sizes(base: &Path) -> Vec<(PathBuf, u64)> {
+    let device = match base.metadata() {
+        Ok(metadata) => metadata.dev(),
+        Err(_) => return Vec::new(),
+    };
+
+    SEPARABLE_MOUNTS
+        .iter()
+        .map(|mount| {
+            let size = disk_usage(&base.join(&mount[1..]), device);
+            (PathBuf::from(mount), size)
+        })
+        .collect()
+}
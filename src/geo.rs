@@ -0,0 +1,178 @@
+//! Suggests defaults for the install from the location of its network connection.
+//!
+//! The suggestions are only ever returned to the caller, which decides whether to present or
+//! apply them. Nothing is looked up unless the caller asks for suggestions.
+
+use crate::external::fetch;
+use crate::locale::{self, Locales};
+use std::{cmp::Reverse, fs, path::Path};
+
+const LOOKUP_URL: &str = "https://geoip.ubuntu.com/lookup";
+/// Seconds to wait for the lookup, so that a frontend is not held up by a slow network.
+const LOOKUP_TIMEOUT: u32 = 5;
+
+/// Defaults which are suggested for the location of the system.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SuggestedDefaults {
+    /// The ISO 3166 code of the country, such as `US`.
+    pub country:         String,
+    /// A timezone from the tz database, such as `America/Denver`.
+    pub timezone:        Option<String>,
+    /// A locale which is supported by the system, such as `en_US.UTF-8`.
+    pub locale:          Option<String>,
+    /// An XKB keyboard layout, such as `us`.
+    pub keyboard_layout: Option<String>,
+    /// The Ubuntu archive mirror of the country.
+    pub apt_mirror:      Option<String>,
+}
+
+/// Looks up the location of the system, if it has a network connection, and suggests defaults
+/// for it. Returns `None` if the system is offline, or if the lookup failed.
+pub fn suggested_defaults() -> Option<SuggestedDefaults> {
+    if !networking(Path::new("/sys/class/net")) {
+        info!("skipping the geoip lookup, as there is no network connection");
+        return None;
+    }
+
+    let response = match fetch(LOOKUP_URL, LOOKUP_TIMEOUT) {
+        Ok(response) => response,
+        Err(why) => {
+            warn!("geoip lookup failed: {}", why);
+            return None;
+        }
+    };
+
+    let layouts: Vec<String> = match locale::get_keyboard_layouts() {
+        Ok(layouts) => {
+            layouts.get_layouts().iter().map(|layout| layout.get_name().to_owned()).collect()
+        }
+        Err(why) => {
+            warn!("unable to read keyboard layouts: {}", why);
+            Vec::new()
+        }
+    };
+
+    let response = String::from_utf8_lossy(&response);
+    suggest(&response, &locale::LOCALES, &layouts, locale::get_main_country)
+}
+
+/// Whether any interface other than loopback is up.
+fn networking(interfaces: &Path) -> bool {
+    let interfaces = match interfaces.read_dir() {
+        Ok(interfaces) => interfaces,
+        Err(_) => return false,
+    };
+
+    interfaces.flatten().filter(|iface| iface.file_name() != "lo").any(|iface| {
+        fs::read_to_string(iface.path().join("operstate"))
+            .map_or(false, |state| state.trim() == "up")
+    })
+}
+
+fn suggest<'a, F: Fn(&str) -> Option<&'a str>>(
+    response: &str,
+    locales: &Locales,
+    layouts: &[String],
+    main_country: F,
+) -> Option<SuggestedDefaults> {
+    if element(response, "Status") != Some("OK") {
+        return None;
+    }
+
+    let country = element(response, "CountryCode").filter(|code| code.len() == 2)?;
+    let lower = country.to_lowercase();
+
+    Some(SuggestedDefaults {
+        country:         country.to_owned(),
+        timezone:        element(response, "TimeZone").map(String::from),
+        locale:          locale_of(country, locales, main_country),
+        keyboard_layout: layouts.iter().find(|layout| **layout == lower).cloned(),
+        apt_mirror:      Some(format!("http://{}.archive.ubuntu.com/ubuntu", lower)),
+    })
+}
+
+/// A UTF-8 locale of the country. Its language is preferably one which is spoken in several
+/// countries, and mainly in this one, and otherwise the most widely spoken, so that Canada is
+/// given English or French rather than Inuktitut.
+fn locale_of<'a, F: Fn(&str) -> Option<&'a str>>(
+    country: &str,
+    locales: &Locales,
+    main_country: F,
+) -> Option<String> {
+    let candidates: Vec<&str> = locales
+        .iter()
+        .filter(|&(_, locale)| {
+            locale.get(&Some(country.to_owned())).map_or(false, |codesets| {
+                codesets.iter().flatten().any(|codeset| codeset.variant == "UTF-8")
+            })
+        })
+        .map(|(lang, _)| lang.as_str())
+        .collect();
+
+    let countries = |lang: &str| locales.get(lang).map_or(0, |countries| countries.len());
+    let lang = candidates.iter().max_by_key(|&&lang| {
+        let countries = countries(lang);
+        let main = main_country(lang) == Some(country) && countries > 1;
+        // Languages without a two-letter code are rarely the language of a country.
+        (main, countries, lang.len() == 2, Reverse(lang))
+    })?;
+
+    Some(format!("{}_{}.UTF-8", lang, country))
+}
+
+/// The text of the first element with the given name, in the XML of the lookup.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = ["<", name, ">"].concat();
+    let close = ["</", name, ">"].concat();
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(xml[start..end].trim()).filter(|text| !text.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locale::Codeset;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn geoip_suggestions() {
+        let response = "<Response><Ip>192.0.2.1</Ip><Status>OK</Status>\
+                        <CountryCode>CA</CountryCode><CountryName>Canada</CountryName>\
+                        <TimeZone>America/Toronto</TimeZone></Response>";
+
+        let utf8 = || vec![Some(Codeset { variant: "UTF-8".into(), dot: true })];
+        let mut locales = Locales::new();
+        let pairs = [("en", "CA"), ("en", "US"), ("es", "US"), ("fr", "CA"), ("iu", "CA")];
+        for &(lang, country) in &pairs {
+            locales
+                .entry(lang.to_owned())
+                .or_insert_with(BTreeMap::new)
+                .insert(Some(country.to_owned()), utf8());
+        }
+
+        let layouts = vec!["us".to_owned(), "ca".to_owned()];
+        let main_country = |lang: &str| match lang {
+            "en" => Some("US"),
+            "fr" => Some("FR"),
+            "iu" => Some("CA"),
+            _ => None,
+        };
+
+        assert_eq!(
+            suggest(response, &locales, &layouts, main_country),
+            Some(SuggestedDefaults {
+                country:         "CA".into(),
+                timezone:        Some("America/Toronto".into()),
+                locale:          Some("en_CA.UTF-8".into()),
+                keyboard_layout: Some("ca".into()),
+                apt_mirror:      Some("http://ca.archive.ubuntu.com/ubuntu".into()),
+            })
+        );
+
+        assert_eq!(locale_of("US", &locales, main_country), Some("en_US.UTF-8".into()));
+
+        let failed = "<Response><Status>ERROR</Status></Response>";
+        assert_eq!(suggest(failed, &locales, &layouts, main_country), None);
+    }
+}
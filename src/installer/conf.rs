@@ -17,7 +17,7 @@ impl RecoveryEnv {
         Ok(Self(env))
     }
 
-    pub fn remove(&mut self, key: &str) { self.0.store.remove(key); }
+    pub fn remove(&mut self, key: &str) { self.0.remove(key); }
 
     pub fn write(&mut self) -> io::Result<()> {
         self.0
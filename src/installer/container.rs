@@ -0,0 +1,52 @@
+//! Installs to a directory, rather than to disks, for containers which are run by
+//! systemd-nspawn or LXC, and for testing the configuration of installs without partitioning.
+//!
+//! The image is extracted, and configured, as it is for an install to disks, but a container
+//! shares the kernel of its host, and is not booted by firmware, so neither a bootloader, a
+//! kernel, nor an initramfs is configured, and its fstab and crypttab are left empty.
+
+use super::{bitflags::FileSystemSupport, traits::InstallerDiskOps, BootMenu, Config};
+use crate::CAPTURE_FACTORY_IMAGE;
+use fstab_generate::BlockInfo;
+use std::{ffi::OsString, io};
+
+/// The disks of an install to a directory, of which there are none.
+pub(crate) struct ContainerDisks;
+
+impl InstallerDiskOps for ContainerDisks {
+    fn generate_fstabs(&self) -> (OsString, OsString) { (OsString::new(), OsString::new()) }
+
+    fn get_block_info_of(&self, mount: &str) -> io::Result<BlockInfo> {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("a container has no partition mounted at {}", mount),
+        ))
+    }
+
+    fn get_support_flags(&self) -> FileSystemSupport { FileSystemSupport::empty() }
+
+    fn is_container(&self) -> bool { true }
+}
+
+/// Refuses the options of a configuration which only apply to an install to disks.
+pub(crate) fn validate(config: &Config) -> io::Result<()> {
+    let options = [
+        ("a read-only root", config.read_only_root.is_some()),
+        ("snapshots", config.snapshots.is_some()),
+        ("a Windows migration", config.windows_migration.is_some()),
+        ("recovery keys", config.recovery_key),
+        ("an initramfs keyfile", config.initrd_keyfile),
+        ("a kernel selection", config.kernel.is_some()),
+        ("a factory image", config.flags & CAPTURE_FACTORY_IMAGE != 0),
+        ("a boot menu", config.boot_menu != BootMenu::default()),
+        ("a serial console", config.console.as_ref().map_or(false, |c| c.serial.is_some())),
+    ];
+
+    match options.iter().find(|&&(_, requested)| requested) {
+        Some(&(option, _)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} cannot be configured for a container", option),
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,163 @@
+//! Checks of the firmware, and of the clock, whose faults would otherwise fail the install long
+//! after the disks were partitioned: efibootmgr cannot write the boot entry when the EFI
+//! variables are mounted read-only, or when the storage of the firmware is full, and a clock
+//! which is years behind, or ahead, rejects the TLS certificates of the package mirrors as not
+//! yet, or no longer, valid.
+
+use super::{Config, Warning, MODIFY_BOOT_ORDER};
+use crate::disks::{Bootloader, CodedError, ErrorCode};
+use crate::misc::namespace::THREAD_MOUNTS;
+use crate::NO_EFI_VARIABLES;
+use std::{
+    fs, io,
+    path::Path,
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime},
+};
+
+const EFIVARS: &str = "/sys/firmware/efi/efivars";
+
+/// KiB of EFI variables beyond which the storage of many firmware is nearly full. The variable
+/// store of most firmware is 64 KiB, of which about a quarter must remain free for the firmware
+/// to reclaim the space of deleted variables, and so to write a new boot entry.
+pub const EFI_VARIABLES_FULL: u32 = 48;
+
+/// How far the clock may be behind the build of the image before it is warned against, as the
+/// image may have been built in another time zone.
+const CLOCK_TOLERANCE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How far the clock may be ahead of the build of the image before it is warned against. The
+/// releases of most distributions are supported for at most five years, past which an image is
+/// unlikely to be installed, and its clock is more likely wrong.
+const CLOCK_AHEAD_TOLERANCE: Duration = Duration::from_secs(5 * 366 * 24 * 60 * 60);
+
+/// Fails if the boot order is to be modified, but the EFI variables are read-only, and warns
+/// of full EFI variable storage, and of a clock which is behind the build of the image, or far
+/// ahead of it.
+pub fn check(config: &Config, bootloader: Bootloader) -> io::Result<Vec<Warning>> {
+    let mut warnings = Vec::new();
+    let writes_variables = bootloader == Bootloader::Efi
+        && Bootloader::firmware() == Bootloader::Efi
+        && config.flags & MODIFY_BOOT_ORDER != 0
+        && !NO_EFI_VARIABLES.load(Ordering::Relaxed);
+
+    if writes_variables {
+        let mounts = fs::read_to_string(THREAD_MOUNTS)?;
+        if mounted_read_only(&mounts, Path::new(EFIVARS)) {
+            return Err(CodedError::new(
+                ErrorCode::EfiVariablesUnavailable,
+                None,
+                "the EFI variables are mounted read-only; remount them with `mount -o \
+                 remount,rw /sys/firmware/efi/efivars`, or install without modifying the boot \
+                 order",
+            )
+            .into_io(io::ErrorKind::PermissionDenied));
+        }
+
+        match efi_variables_in(Path::new(EFIVARS)) {
+            Ok((kib, dumps)) if dumps || kib >= EFI_VARIABLES_FULL => {
+                warn!("{} KiB of EFI variables are stored, {} crash dumps", kib, dumps);
+                warnings.push(Warning::EfiVariablesFull(kib));
+            }
+            Ok(_) => (),
+            Err(why) => warn!("unable to read the EFI variables: {}", why),
+        }
+    }
+
+    let built = fs::metadata(&config.squashfs).and_then(|metadata| metadata.modified());
+    if let Ok(built) = built {
+        let warning = clock_warning(built, SystemTime::now());
+        if let Some(ref warning) = warning {
+            warn!("the clock is not set near the build of the image: {:?}", warning);
+        }
+
+        warnings.extend(warning);
+    }
+
+    Ok(warnings)
+}
+
+/// Warns of a clock, at `now`, which is behind the build of the image, at `built`, or which is
+/// years ahead of it.
+fn clock_warning(built: SystemTime, now: SystemTime) -> Option<Warning> {
+    let days = |duration: Duration| (duration.as_secs() / (24 * 60 * 60)) as u32;
+    match now.duration_since(built) {
+        Ok(ahead) if ahead > CLOCK_AHEAD_TOLERANCE => Some(Warning::ClockAhead(days(ahead))),
+        Ok(_) => None,
+        Err(why) if why.duration() > CLOCK_TOLERANCE => {
+            Some(Warning::ClockBehind(days(why.duration())))
+        }
+        Err(_) => None,
+    }
+}
+
+/// Whether the file system mounted at `target`, as listed in `/proc/thread-self/mounts`, is
+/// read-only.
+fn mounted_read_only(mounts: &str, target: &Path) -> bool {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            Some((fields.next()?, fields.nth(1)?))
+        })
+        .filter(|&(path, _)| Path::new(path) == target)
+        .last()
+        .map_or(false, |(_, options)| options.split(',').any(|option| option == "ro"))
+}
+
+/// The KiB which the EFI variables occupy, and whether any are crash dumps, which the kernel
+/// stores in the variables on a panic, and which are often what fills them.
+fn efi_variables_in(efivars: &Path) -> io::Result<(u32, bool)> {
+    let mut bytes = 0u64;
+    let mut dumps = false;
+    for variable in efivars.read_dir()? {
+        let variable = variable?;
+        // efivarfs prefixes the data of each variable with its four bytes of attributes.
+        bytes += variable.metadata()?.len().saturating_sub(4);
+        dumps |= variable.file_name().to_string_lossy().starts_with("dump-");
+    }
+
+    Ok(((bytes / 1024) as u32, dumps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn efivarfs_read_only() {
+        let mounts = "sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0\n\
+                      efivarfs /sys/firmware/efi/efivars efivarfs ro,nosuid,nodev,noexec 0 0\n";
+        assert!(mounted_read_only(mounts, Path::new(EFIVARS)));
+        assert!(!mounted_read_only(mounts, Path::new("/sys")));
+
+        let remounted = [mounts, "efivarfs /sys/firmware/efi/efivars efivarfs rw,nosuid 0 0\n"];
+        assert!(!mounted_read_only(&remounted.concat(), Path::new(EFIVARS)));
+    }
+
+    #[test]
+    fn efi_variable_storage() {
+        let tempdir = TempDir::new("distinst-efivars").unwrap();
+        let dir = tempdir.path();
+        fs::write(dir.join("Boot0000-8be4df61-93ca-11d2-aa0d-00e098032b8c"), vec![0; 2052])
+            .unwrap();
+        fs::write(dir.join("BootOrder-8be4df61-93ca-11d2-aa0d-00e098032b8c"), vec![0; 12])
+            .unwrap();
+        assert_eq!(efi_variables_in(&dir).unwrap(), (2, false));
+
+        let dump = "dump-type0-1-1-1600000000-C-cfc8fc79-be2e-4ddc-97f0-9f98bfe298a0";
+        fs::write(dir.join(dump), vec![0; 1028]).unwrap();
+        assert_eq!(efi_variables_in(&dir).unwrap(), (3, true));
+    }
+
+    #[test]
+    fn clock_tolerance() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let built = SystemTime::UNIX_EPOCH + day * 20_000;
+        assert_eq!(clock_warning(built, built + day * 365), None);
+        assert_eq!(clock_warning(built, built - day / 2), None);
+        assert_eq!(clock_warning(built, built - day * 3), Some(Warning::ClockBehind(3)));
+        assert_eq!(clock_warning(built, built + day * 10_000), Some(Warning::ClockAhead(10_000)));
+    }
+}
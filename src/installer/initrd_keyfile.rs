@@ -0,0 +1,192 @@
+//! A keyfile which is embedded in the initramfs, and which unlocks the encrypted volumes, so
+//! that the passphrase is only typed once at boot: to GRUB, which unlocks the encrypted `/boot`
+//! to read the kernel and the initramfs, rather than again to the initramfs for each volume.
+//!
+//! Anyone who may read the initramfs may read the keyfile, so the initramfs must be stored on
+//! an encrypted `/boot`, and is generated so that only root may read it.
+
+use crate::disks::{Disks, KernelLoader};
+use crate::external::cryptsetup_add_keyfile;
+use crate::misc;
+use disk_types::BlockDeviceExt;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+/// The path of the keyfile, relative to the root of the install.
+const KEYFILE: &str = "crypto_keyfile.bin";
+const KEYFILE_SIZE: usize = 2048;
+
+const CRYPTSETUP_CONF_HOOK: &str = "etc/cryptsetup-initramfs/conf-hook";
+const INITRAMFS_TOOLS_UMASK: &str = "etc/initramfs-tools/conf.d/distinst-keyfile";
+const DRACUT_KEYFILE: &str = "etc/dracut.conf.d/90-distinst-keyfile.conf";
+
+/// The keyscript which passes the keyfile to cryptsetup, as initramfs-tools expects.
+const KEYSCRIPT: &str = "keyscript=/bin/cat";
+
+/// Ensures that the initramfs will be stored on an encrypted `/boot` which GRUB can unlock, and
+/// has the volume which holds it formatted so that GRUB can unlock it.
+pub(crate) fn prepare(disks: &mut Disks, loader: KernelLoader) -> io::Result<()> {
+    if loader != KernelLoader::Grub {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "systemd-boot reads the initramfs from the unencrypted ESP, where the keyfile \
+             could be read by anyone",
+        ));
+    }
+
+    let boot_device = disks
+        .find_partition(Path::new("/boot"))
+        .or_else(|| disks.find_partition(Path::new("/")))
+        .map(|(device, _)| device.to_path_buf())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "root was not defined"))?;
+
+    let encryption = disks
+        .logical
+        .iter_mut()
+        .find(|device| device.get_device_path() == boot_device)
+        .and_then(|device| device.encryption.as_mut())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a keyfile may only be embedded in an initramfs which is stored on an \
+                 encrypted /boot, or an encrypted root without one",
+            )
+        })?;
+
+    if encryption.password.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "GRUB cannot unlock a /boot which is encrypted without a passphrase",
+        ));
+    }
+
+    encryption.grub_readable = true;
+    Ok(())
+}
+
+/// Adds a random keyfile to each LUKS volume which is unlocked with a passphrase, and
+/// configures the crypttab and the initramfs generator of the install at `root` to unlock the
+/// volumes with it.
+pub(crate) fn embed(disks: &Disks, root: &Path) -> io::Result<()> {
+    let volumes: Vec<(&Path, &str, &str)> = disks
+        .get_logical_devices()
+        .iter()
+        .filter_map(|device| {
+            let encryption = device.encryption.as_ref()?;
+            let password = encryption.password.as_ref()?;
+            let parent = device.luks_parent.as_ref()?;
+            Some((parent.as_path(), encryption.physical_volume.as_str(), password.as_str()))
+        })
+        .collect();
+
+    if volumes.is_empty() {
+        warn!("no volume is unlocked by a passphrase, so no keyfile was embedded");
+        return Ok(());
+    }
+
+    let keyfile = root.join(KEYFILE);
+    generate(&keyfile)?;
+
+    for &(device, _, password) in &volumes {
+        info!("adding the initramfs keyfile to {}", device.display());
+        cryptsetup_add_keyfile(device, password, &keyfile)?;
+    }
+
+    let crypttab_path = root.join("etc/crypttab");
+    let crypttab = fs::read_to_string(&crypttab_path)?;
+    let names: Vec<&str> = volumes.iter().map(|&(_, name, _)| name).collect();
+    misc::write(&crypttab_path, with_keyfile(&crypttab, &names))?;
+
+    if root.join("etc/initramfs-tools").is_dir() {
+        let hook = root.join(CRYPTSETUP_CONF_HOOK);
+        let mut conf = fs::read_to_string(&hook).unwrap_or_default();
+        conf.push_str(&["KEYFILE_PATTERN=\"/", KEYFILE, "\"\n"].concat());
+        write_conf(&hook, &conf)?;
+
+        // Keeps the initramfs, which holds the keyfile, from being readable by users.
+        write_conf(&root.join(INITRAMFS_TOOLS_UMASK), "UMASK=0077\n")?;
+    }
+
+    if root.join("etc/dracut.conf.d").is_dir() {
+        let conf = ["install_items+=\" /", KEYFILE, " \"\n"].concat();
+        write_conf(&root.join(DRACUT_KEYFILE), &conf)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a random keyfile which only root may read.
+fn generate(path: &Path) -> io::Result<()> {
+    let mut key = [0u8; KEYFILE_SIZE];
+    misc::open("/dev/urandom")?.read_exact(&mut key)?;
+
+    // The keyfile is created readable by root alone, so that it is never readable by others,
+    // not even before it is written. A keyfile from an earlier install is replaced.
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(0o400).open(path)?;
+    file.write_all(&key)?;
+    file.sync_all()
+}
+
+fn write_conf(path: &Path, conf: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    misc::write(path, conf)
+}
+
+/// The crypttab, with the entries of `volumes` which ask for a passphrase unlocked with the
+/// keyfile instead.
+fn with_keyfile(crypttab: &str, volumes: &[&str]) -> String {
+    let mut output = String::with_capacity(crypttab.len() + volumes.len() * 64);
+    for line in crypttab.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [name, source, "none", options @ ..] if volumes.contains(name) => {
+                let options = options.first().map_or("luks", |options| *options);
+                let keyfile = ["/", KEYFILE].concat();
+                let options = if options.contains("keyscript=") {
+                    options.to_owned()
+                } else {
+                    [options, ",", KEYSCRIPT].concat()
+                };
+
+                output.push_str(&[*name, *source, keyfile.as_str(), options.as_str()].join(" "));
+            }
+            _ => output.push_str(line),
+        }
+
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crypttab_keyfile() {
+        let crypttab = "cryptdata UUID=1234 none luks\n\
+                        cryptswap UUID=5678 /dev/urandom swap,plain,offset=1024\n\
+                        cryptkey UUID=9abc /etc/keys/cryptkey luks\n\
+                        # cryptold UUID=def0 none luks\n";
+
+        assert_eq!(
+            with_keyfile(crypttab, &["cryptdata", "cryptkey", "cryptold"]),
+            "cryptdata UUID=1234 /crypto_keyfile.bin luks,keyscript=/bin/cat\n\
+             cryptswap UUID=5678 /dev/urandom swap,plain,offset=1024\n\
+             cryptkey UUID=9abc /etc/keys/cryptkey luks\n\
+             # cryptold UUID=def0 none luks\n"
+        );
+    }
+}
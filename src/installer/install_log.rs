@@ -0,0 +1,177 @@
+//! A record of the install, which is written to `/var/log/installer` within the target, as
+//! the Debian installer does, so that support may reconstruct what the installer did.
+
+use super::{Config, ExtractStats, OemPackage, RecoveryKey};
+use crate::disks::{Bootloader, Disks, DISK_AUDIT_LOG};
+use crate::misc::{
+    self,
+    journal::{self, JournalEntry},
+};
+use disk_types::PartitionExt;
+use os_release::OsRelease;
+use std::{
+    fs::{self, Permissions},
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+const LOG_DIR: &str = "var/log/installer";
+
+/// What the installer was asked to do, and what it did.
+pub(crate) struct InstallLog<'a> {
+    pub config:           &'a Config,
+    pub disks:            &'a Disks,
+    pub bootloader:       Bootloader,
+    /// The os-release of the image which was extracted.
+    pub image:            &'a OsRelease,
+    pub removed_packages: &'a [String],
+    pub extract_stats:    Option<ExtractStats>,
+    pub oem_packages:     &'a [OemPackage],
+    pub recovery_keys:    &'a [RecoveryKey],
+}
+
+impl<'a> InstallLog<'a> {
+    /// Writes the log within `root`. Only root may read it, as the output of commands may
+    /// reveal details of the system. Secrets of the config are redacted by its `Debug` output,
+    /// and recovery keys are omitted.
+    pub fn write(&self, root: &Path) -> io::Result<()> {
+        let dir = root.join(LOG_DIR);
+        info!("writing the installer's logs to /{}", LOG_DIR);
+        fs::create_dir_all(&dir)?;
+        fs::set_permissions(&dir, Permissions::from_mode(0o700))?;
+
+        let oem_packages: String = self
+            .oem_packages
+            .iter()
+            .map(|package| {
+                let outcome = if package.installed { "installed" } else { "failed" };
+                format!("{}  {}\n", package.name, outcome)
+            })
+            .collect();
+
+        let removed_packages: String =
+            self.removed_packages.iter().map(|package| [package.as_str(), "\n"].concat()).collect();
+
+        let files = [
+            ("config", format!("{:#?}\n", self.config)),
+            ("journal", format_journal(&journal::entries())),
+            ("removed-packages", removed_packages),
+            ("oem-packages", oem_packages),
+            ("report", self.report()),
+            ("disk-audit.jsonl", fs::read_to_string(DISK_AUDIT_LOG).unwrap_or_default()),
+        ];
+
+        for (name, contents) in &files {
+            let mut file = misc::create(dir.join(name))?;
+            file.set_permissions(Permissions::from_mode(0o600))?;
+            file.write_all(contents.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn report(&self) -> String {
+        let mut report = format!(
+            "distinst {}\nimage: {}\nhostname: {}\nbootloader: {:?}\n",
+            env!("CARGO_PKG_VERSION"),
+            self.image.pretty_name,
+            self.config.hostname,
+            self.bootloader
+        );
+
+        if let Some(stats) = self.extract_stats {
+            report.push_str(&format!(
+                "extracted: {} bytes in {:.1}s, at {} bytes/s\n",
+                stats.bytes,
+                stats.duration.as_secs_f64(),
+                stats.throughput()
+            ));
+
+            if let Some(reclaimed) = stats.reclaimed {
+                report.push_str(&format!("recompressed: {} bytes reclaimed\n", reclaimed));
+            }
+        }
+
+        for key in self.recovery_keys {
+            report.push_str(&format!(
+                "recovery key: {} in slot {}\n",
+                key.device.display(),
+                key.key_slot
+            ));
+        }
+
+        report.push_str("\npartitions:\n");
+        for partition in self.disks.get_partitions() {
+            let target = match (&partition.target, &partition.volume_group) {
+                (Some(target), _) => target.display().to_string(),
+                (None, Some((group, _))) => format!("volume group {}", group),
+                (None, None) if partition.is_swap() => "swap".into(),
+                (None, None) => continue,
+            };
+
+            let fs = partition.filesystem.map_or("none", Into::into);
+            report.push_str(&format!(
+                "  {}  {}  {}\n",
+                partition.device_path.display(),
+                target,
+                fs
+            ));
+        }
+
+        report
+    }
+}
+
+/// Each command, with its exit status, duration, and the end of its output.
+pub(crate) fn format_journal(entries: &[JournalEntry]) -> String {
+    let mut journal = String::new();
+    for entry in entries {
+        let status = entry.status.map_or_else(|| "none".into(), |code| code.to_string());
+        journal.push_str(&format!(
+            "$ {}\n  status: {}, took {:.2}s\n",
+            entry.argv.join(" "),
+            status,
+            entry.duration.as_secs_f64()
+        ));
+
+        for line in entry.output.lines() {
+            journal.push_str("  | ");
+            journal.push_str(line);
+            journal.push('\n');
+        }
+    }
+
+    journal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn install_log_journal() {
+        let entries = [
+            JournalEntry {
+                argv:     vec!["mkfs.ext4".into(), "-F".into(), "/dev/sda2".into()],
+                duration: Duration::from_millis(1500),
+                status:   Some(0),
+                output:   "Creating filesystem\nWriting superblocks\n".into(),
+            },
+            JournalEntry {
+                argv:     vec!["apt-get".into(), "purge".into()],
+                duration: Duration::from_millis(10),
+                status:   None,
+                output:   String::new(),
+            },
+        ];
+
+        assert_eq!(
+            format_journal(&entries),
+            "$ mkfs.ext4 -F /dev/sda2\n  status: 0, took 1.50s\n\
+             \x20 | Creating filesystem\n  | Writing superblocks\n\
+             $ apt-get purge\n  status: none, took 0.01s\n"
+        );
+    }
+}
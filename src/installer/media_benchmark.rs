@@ -0,0 +1,66 @@
+//! A brief write benchmark of the root of the install, which detects media that is too slow to
+//! install to in reasonable time, such as a failing USB stick or a cheap SD card, before hours
+//! are spent extracting the image to it.
+
+use crate::misc;
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// A write throughput, in KiB/s, below which the media is warned against.
+pub const SLOW_MEDIA: u32 = 4 * 1024;
+
+const FILE: &str = ".distinst-benchmark";
+const CHUNK: usize = 4 * 1024 * 1024;
+const MAX_CHUNKS: u32 = 32;
+const TIME_LIMIT: Duration = Duration::from_secs(5);
+
+/// Measures the throughput, in KiB/s, at which data may be synced to the file system mounted
+/// at `root`, writing up to 128 MiB, or for about five seconds.
+pub fn write_throughput(root: &Path) -> io::Result<u32> {
+    // Random data, as a file system which compresses would write zeroes at an inflated rate.
+    let mut chunk = vec![0u8; CHUNK];
+    misc::open("/dev/urandom")?.read_exact(&mut chunk)?;
+
+    let path = root.join(FILE);
+    let result = (|| {
+        let mut file = misc::create(&path)?;
+        let started = Instant::now();
+        let mut written = 0u64;
+        for _ in 0..MAX_CHUNKS {
+            file.write_all(&chunk)?;
+            file.sync_data()?;
+            written += CHUNK as u64;
+            if started.elapsed() >= TIME_LIMIT {
+                break;
+            }
+        }
+
+        Ok(throughput(written, started.elapsed()))
+    })();
+
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// The throughput, in KiB/s, of writing `bytes` in the given time.
+fn throughput(bytes: u64, elapsed: Duration) -> u32 {
+    let millis = elapsed.as_millis().max(1);
+    (u128::from(bytes) * 1000 / 1024 / millis).min(u128::from(u32::MAX)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_throughput() {
+        assert_eq!(throughput(128 * 1024 * 1024, Duration::from_secs(4)), 32 * 1024);
+        assert_eq!(throughput(4 * 1024 * 1024, Duration::from_secs(5)), 819);
+        assert_eq!(throughput(CHUNK as u64, Duration::from_secs(0)), 4_096_000);
+        assert!(throughput(16 * 1024 * 1024, Duration::from_secs(5)) < SLOW_MEDIA);
+    }
+}
@@ -2,39 +2,80 @@ pub mod bitflags;
 pub mod traits;
 
 mod conf;
+mod container;
+mod firmware;
+mod initrd_keyfile;
+mod install_log;
+mod media_benchmark;
+mod power;
+mod recovery_key;
 mod state;
+mod telemetry;
 
 pub(crate) mod steps;
 
-pub use self::{conf::RecoveryEnv, steps::Step};
+pub use self::{
+    conf::RecoveryEnv,
+    recovery_key::{RecoveryKey, RECOVERY_KEY_SLOT},
+    telemetry::{HardwareClass, HttpReporter, InstallSummary, TelemetryReporter},
+    steps::{
+        AptSources, BootDefault, BootMenu, Branding, CifsCredentials, Cipher, CloudInit,
+        ConsoleSettings, DirectoryEnrollment, ExtractStats, FileProgress, FirewallPolicy,
+        FstabChanges, InitramfsCompression, InitramfsOptions, InterfaceKind, JoinCredential,
+        KernelSelection, KeyboardLayout, MountProtocol, NetworkConfig, NetworkInterface,
+        NetworkMount, OemPackage, OldBootPolicy, ReadOnlyRoot, SerialConsole, SnapshotTool,
+        SshServer, Step, Substep, SudoPolicy, WindowsMigration, DEFAULT_GROUPS, DUAL_BOOT_TIMEOUT,
+    },
+};
 
-use self::state::InstallerState;
+use self::{
+    container::ContainerDisks,
+    install_log::{format_journal, InstallLog},
+    state::InstallerState,
+};
 
 use crate::auto::{
-    delete_old_install, move_root, recover_root, remove_root, validate_backup_conditions,
-    AccountFiles, Backup, ReinstallError,
+    delete_old_install, install_size, move_root, recover_root, remove_root,
+    validate_backup_conditions, validate_before_removing, AccountFiles, Backup, PackageSelections,
+    PreservedFiles, ReinstallError,
+};
+use disk_types::{BlockDeviceExt, FileSystem};
+use crate::crypt::{self, Scheme};
+use crate::dbus_interfaces::LoginManager;
+use crate::disks::{
+    remove_esp_directories, Bootloader, BootloaderOverride, CodedError, Disks, ErrorCode,
+    LvmEncryption,
 };
-use disk_types::BlockDeviceExt;
-use crate::disks::{Bootloader, Disks};
 use crate::errors::IoContext;
-use crate::external::luks::deactivate_logical_devices;
+use crate::external::{luks::deactivate_logical_devices, AutomountInhibitor};
 use crate::hostname;
+use crate::misc::{
+    journal::{self, JournalEntry},
+    namespace::MountNamespace,
+    priority::Priority,
+    unmount,
+};
 use os_release::OsRelease;
 use partition_identity::PartitionID;
 use crate::squashfs;
 use std::{
-    io,
+    env, fs, io,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
     sync::atomic::Ordering,
+    time::Instant,
 };
 use tempdir::TempDir;
 use crate::timezones::Region;
-use crate::PARTITIONING_TEST;
+use crate::{PartitionSizes, PARTITIONING_TEST};
 
 pub const MODIFY_BOOT_ORDER: u8 = 0b01;
 pub const INSTALL_HARDWARE_SUPPORT: u8 = 0b10;
 pub const KEEP_OLD_ROOT: u8 = 0b100;
 pub const RUN_UBUNTU_DRIVERS: u8 = 0b1000;
+pub const CAPTURE_FACTORY_IMAGE: u8 = 0b1_0000;
+pub const RESTORE_PACKAGES: u8 = 0b10_0000;
+pub const IMAGE_MODE: u8 = 0b100_0000;
 
 macro_rules! percent {
     ($steps:expr) => {
@@ -46,35 +87,446 @@ macro_rules! percent {
     };
 }
 
+macro_rules! substep {
+    ($steps:expr) => {
+        |substep, percent| {
+            $steps.status.substep = substep.into();
+            $steps.status.percent = percent;
+            let status = $steps.status;
+            $steps.emit_status(status);
+        }
+    };
+}
+
 /// Installer configuration
+#[derive(Debug, Default)]
 pub struct Config {
     /// Hostname to assign to the installed system.
-    pub hostname:         String,
+    pub hostname:          String,
     /// The keyboard layout to use with the installed system (such as "us").
-    pub keyboard_layout:  String,
+    pub keyboard_layout:   String,
     /// An optional keyboard model (such as "pc105") to define the keyboard's model.
-    pub keyboard_model:   Option<String>,
+    pub keyboard_model:    Option<String>,
     /// An optional variant of the keyboard (such as "dvorak").
-    pub keyboard_variant: Option<String>,
+    pub keyboard_variant:  Option<String>,
+    /// Further layouts, which may be switched to from the primary layout, in their order.
+    pub keyboard_layouts:  Vec<KeyboardLayout>,
+    /// XKB options, such as `grp:alt_shift_toggle`, which switch between the layouts.
+    pub keyboard_options:  Option<String>,
     /// The UUID of the old root partition, for retaining user accounts.
-    pub old_root:         Option<String>,
+    pub old_root:          Option<String>,
     /// The locale to use for the installed system.
-    pub lang:             String,
+    pub lang:              String,
     /// The file that contains a list of packages to remove.
-    pub remove:           String,
+    pub remove:            String,
     /// The archive (`tar` or `squashfs`) which contains the base system.
-    pub squashfs:         String,
+    pub squashfs:          String,
     /// Some flags to control the behavior of the installation.
-    pub flags:            u8,
+    pub flags:             u8,
+    /// Additional files and directories to carry over from the old root.
+    pub preserved_paths:   Vec<PathBuf>,
+    /// User data to copy from a Windows install into the home of the new user.
+    pub windows_migration: Option<WindowsMigration>,
+    /// The number of threads which decompress the image, or one per CPU if `None`. Files are
+    /// written by a single thread of unsquashfs, whatever the number, so this does not raise
+    /// the write throughput of fast targets, and tar archives ignore it.
+    pub extract_threads:   Option<usize>,
+    /// Compresses the extracted files of the Btrfs file systems which are reused, rather than
+    /// formatted with compression, as their files are written uncompressed. The space which is
+    /// reclaimed is recorded in the install's report.
+    pub btrfs_recompress:  bool,
+    /// The CPU and I/O priority of the heavy steps, which keeps a live session responsive
+    /// while the install proceeds in the background. External commands inherit it.
+    pub priority:          Option<Priority>,
+    /// The directory in which the chroot mount point and other temporary directories are
+    /// created, instead of the system's temporary directory, which may be a small tmpfs.
+    pub temp_dir:          Option<PathBuf>,
+    /// Installs a tool which takes periodic snapshots of the root file system.
+    pub snapshots:         Option<SnapshotTool>,
+    /// Vendor information and files, such as a wallpaper, to brand the installed system with.
+    pub branding:          Option<Branding>,
+    /// A directory of the vendor's packages, such as `/cdrom/pool/oem`, which are installed
+    /// into the target along with their dependencies.
+    pub oem_packages:      Option<PathBuf>,
+    /// The archive that the installed system installs its packages from, which replaces the
+    /// sources that the image carried.
+    pub apt_sources:       Option<AptSources>,
+    /// NTP servers which systemd-timesyncd synchronizes the clock with, instead of its own.
+    pub ntp_servers:       Vec<String>,
+    /// Keeps the hardware clock in local time, rather than UTC. If `None`, local time is kept
+    /// if Windows remains installed alongside the new install, as Windows expects it.
+    pub local_rtc:         Option<bool>,
+    /// Installs and enables ufw, with the given policy towards incoming connections.
+    pub firewall:          Option<FirewallPolicy>,
+    /// Installs and enables an OpenSSH server, which admits the created user.
+    pub ssh_server:        Option<SshServer>,
+    /// Installs cloud-init, which creates the users and sets the hostname on the first boot of
+    /// each instance of an image, in place of those of the install. Requires `IMAGE_MODE`.
+    pub cloud_init:        Option<CloudInit>,
+    /// The font of the consoles, such as a larger one for HiDPI displays, and the state of
+    /// numlock at boot.
+    pub console:           Option<ConsoleSettings>,
+    /// Detects a HiDPI panel, and if one is found, scales GNOME, KDE Plasma, and their login
+    /// screens by default, and enlarges the console font if no other was chosen.
+    pub hidpi:             bool,
+    /// Logs the created user in automatically, through the display manager of the image,
+    /// which may be GDM, LightDM, or SDDM.
+    pub autologin:         bool,
+    /// Static network configuration, such as addresses, VLANs, and bonds, for systems which
+    /// cannot rely upon DHCP.
+    pub network:           Option<NetworkConfig>,
+    /// NFS and CIFS shares which are added to the fstab, and mounted once the network is up.
+    pub network_mounts:    Vec<NetworkMount>,
+    /// A directory domain, such as Active Directory or FreeIPA, which the installed system
+    /// joins with realmd once its network is online.
+    pub directory:         Option<DirectoryEnrollment>,
+    /// Adds a random recovery key to each LUKS volume which is created with a password, so
+    /// that it may be escrowed. The keys are reported by `Installer::recovery_keys`.
+    pub recovery_key:      bool,
+    /// A file, such as one on a removable device, to which the recovery keys are written in
+    /// a form that is ready to be printed.
+    pub recovery_key_file: Option<PathBuf>,
+    /// Installs a root which is read-only and verified by dm-verity, with a writable overlay,
+    /// for kiosks and appliances.
+    pub read_only_root:    Option<ReadOnlyRoot>,
+    /// Sizes of the ESP, recovery, and swap partitions, which the auto planner creates, and
+    /// which the install's size is estimated with.
+    pub partition_sizes:   PartitionSizes,
+    /// What becomes of the boot files, on the ESP, of the install which a reinstall from the
+    /// recovery partition replaces.
+    pub old_boots:         OldBootPolicy,
+    /// Installs for BIOS or EFI, rather than for the mode which the firmware booted in. The
+    /// install fails before the disks are modified if the forced mode could not boot.
+    pub bootloader:        BootloaderOverride,
+    /// The timeout and the default entry of the boot menu, of systemd-boot or GRUB.
+    pub boot_menu:         BootMenu,
+    /// Selects which of the image's kernel flavors boots by default, and optionally removes
+    /// the others.
+    pub kernel:            Option<KernelSelection>,
+    /// Which kernels the initramfs is generated for, and how it is compressed. The install
+    /// fails if an initramfs lacks what its encryption, LVM, or RAID needs.
+    pub initramfs:         InitramfsOptions,
+    /// Removes the directories of the reused ESP which belong to OSes that no longer exist on
+    /// any disk, before the install's kernels are copied to it.
+    pub esp_cleanup:       bool,
+    /// The home of the live session's user, whose desktop settings, such as the theme, the
+    /// dock, and the arrangement of displays, are copied to the created user.
+    pub live_settings:     Option<PathBuf>,
+    /// Embeds a keyfile in the initramfs, which unlocks the encrypted volumes once GRUB has
+    /// unlocked the encrypted `/boot`, so that the passphrase is typed only once at boot.
+    pub initrd_keyfile:    bool,
+    /// Briefly benchmarks writes to the root of the install before extracting the image,
+    /// warning if the media is too slow to complete the install in reasonable time.
+    pub benchmark_target:  bool,
+}
+
+impl Config {
+    /// Ensures that the configuration is usable before an install begins.
+    pub fn validate(&self) -> io::Result<()> {
+        if !hostname::is_valid(&self.hostname) {
+            let why = "hostname is not valid";
+            return Err(CodedError::new(ErrorCode::HostnameInvalid, None, why)
+                .into_io(io::ErrorKind::InvalidInput));
+        }
+
+        let required = [
+            ("squashfs", &self.squashfs),
+            ("lang", &self.lang),
+            ("keyboard_layout", &self.keyboard_layout),
+        ];
+
+        for &(field, value) in &required {
+            if value.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} was not defined in the config", field),
+                ));
+            }
+        }
+
+        // The primary layout is listed before the others, and so must be a name of XKB alike.
+        // Frontends give an empty variant for the default variant of a layout.
+        let primary = KeyboardLayout {
+            layout:  self.keyboard_layout.clone(),
+            variant: self.keyboard_variant.clone().filter(|variant| !variant.is_empty()),
+        };
+
+        for layout in Some(&primary).into_iter().chain(&self.keyboard_layouts) {
+            layout.validate()?;
+        }
+
+        if let Some(ref options) = self.keyboard_options {
+            steps::validate_keyboard_options(options)?;
+        }
+
+        if let Some(ref branding) = self.branding {
+            branding.validate()?;
+        }
+
+        if let Some(ref sources) = self.apt_sources {
+            sources.validate()?;
+        }
+
+        if let Some(ref console) = self.console {
+            console.validate()?;
+        }
+
+        if let Some(ref kernel) = self.kernel {
+            kernel.validate()?;
+        }
+
+        self.initramfs.validate()?;
+
+        if let Some(ref server) = self.ssh_server {
+            server.validate()?;
+        }
+
+        if let Some(ref network) = self.network {
+            network.validate()?;
+        }
+
+        if let Some(ref cloud_init) = self.cloud_init {
+            if self.flags & IMAGE_MODE == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cloud-init may only be seeded into images, which are built in image mode",
+                ));
+            }
+
+            cloud_init.validate()?;
+        }
+
+        for mount in &self.network_mounts {
+            mount.validate()?;
+        }
+
+        if let Some(ref enrollment) = self.directory {
+            enrollment.validate()?;
+        }
+
+        self.partition_sizes.validate()?;
+
+        if self.recovery_key_file.is_some() && !self.recovery_key {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a recovery key file was given, but recovery keys were not enabled",
+            ));
+        }
+
+        let invalid_server =
+            self.ntp_servers.iter().any(|server| server.is_empty() || server.contains(' '));
+
+        if invalid_server {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "NTP servers must not be empty or contain spaces",
+            ));
+        }
+
+        if let Some(ref dir) = self.oem_packages {
+            if !dir.is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("vendor package directory {} was not found", dir.display()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The directory in which temporary directories are created.
+    pub fn temp_dir(&self) -> PathBuf { self.temp_dir.clone().unwrap_or_else(env::temp_dir) }
+
+    /// Ensures that the temporary directory exists, and that it is not on a disk which the
+    /// install will modify, as that disk may be repartitioned or formatted beneath it.
+    pub fn validate_temp_dir(&self, disks: &Disks) -> io::Result<()> {
+        let dir = self.temp_dir();
+        let metadata = dir
+            .metadata()
+            .with_context(|err| format!("temporary directory {}: {}", dir.display(), err))?;
+
+        if !metadata.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("temporary directory {} is not a directory", dir.display()),
+            ));
+        }
+
+        let device = device_number(metadata.dev());
+        let on_target = disks
+            .get_physical_devices()
+            .iter()
+            .any(|disk| disk_device_numbers(disk.get_device_path()).contains(&device));
+
+        if on_target {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("temporary directory {} is on a disk that will be modified", dir.display()),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Ensures that an install for a mode other than the one the firmware booted in is bootable,
+/// rather than leaving a system which cannot boot.
+fn verify_bootloader_override(config: &Config, bootloader: Bootloader) -> io::Result<()> {
+    if bootloader == Bootloader::firmware() {
+        return Ok(());
+    }
+
+    match bootloader {
+        // The BIOS boot partition of GPT disks is verified with the other partitions.
+        Bootloader::Bios => {
+            warn!("installing for BIOS from EFI firmware, which must support legacy boot");
+            Ok(())
+        }
+        Bootloader::Efi if config.flags & MODIFY_BOOT_ORDER != 0 => Err(CodedError::new(
+            ErrorCode::EfiVariablesUnavailable,
+            None,
+            "an EFI install cannot modify the boot order of a system which was not booted with \
+             EFI; install without modifying the boot order",
+        )
+        .into_io(io::ErrorKind::InvalidInput)),
+        Bootloader::Efi => {
+            warn!("installing for EFI from BIOS firmware, which must support EFI boot");
+            Ok(())
+        }
+    }
+}
+
+/// Formats a device number as `MAJOR:MINOR`, as it is written in sysfs.
+fn device_number(dev: u64) -> String {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    format!("{}:{}", major, minor)
+}
+
+/// Prevents the desktop from mounting the partitions of the disks being modified, and the
+/// LUKS and LVM volumes on them, which it may otherwise do as soon as they are formatted.
+fn inhibit_automount(disks: &Disks) -> Option<AutomountInhibitor> {
+    let paths: Vec<&Path> =
+        disks.get_physical_devices().iter().map(|disk| disk.get_device_path()).collect();
+
+    let (mut maps, mut groups) = (Vec::new(), Vec::new());
+    let volume_groups = disks.get_physical_partitions().filter_map(|p| p.volume_group.as_ref());
+    for (group, encryption) in volume_groups {
+        groups.push(group.as_str());
+        maps.extend(encryption.as_ref().map(|enc| enc.physical_volume.as_str()));
+    }
+
+    AutomountInhibitor::new(&paths, &maps, &groups)
+        .map_err(|why| warn!("unable to inhibit the automounting of {:?}: {}", paths, why))
+        .ok()
+}
+
+/// The device numbers of a disk, its partitions, and the devices that they hold, such as
+/// LUKS and LVM volumes.
+fn disk_device_numbers(disk: &Path) -> Vec<String> {
+    let mut numbers = Vec::new();
+    if let Some(name) = disk.file_name() {
+        collect_device_numbers(&Path::new("/sys/class/block").join(name), &mut numbers);
+    }
+    numbers
+}
+
+fn collect_device_numbers(sysfs: &Path, numbers: &mut Vec<String>) {
+    if let Ok(number) = fs::read_to_string(sysfs.join("dev")) {
+        numbers.push(number.trim().to_owned());
+    }
+
+    for holder in sysfs.join("holders").read_dir().into_iter().flatten().flatten() {
+        collect_device_numbers(&holder.path(), numbers);
+    }
+
+    for entry in sysfs.read_dir().into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.join("partition").exists() {
+            collect_device_numbers(&path, numbers);
+        }
+    }
+}
+
+/// The release of the newest kernel in the image, as `(major, minor)`, which the installed
+/// system boots. The kernel of the live environment may be older than those of the image.
+fn kernel_release(image: &Path) -> Option<(u32, u32)> {
+    let releases = squashfs::kernel_releases(image)
+        .map_err(|why| warn!("unable to find the kernels of {}: {}", image.display(), why))
+        .ok()?;
+
+    releases
+        .iter()
+        .filter_map(|release| {
+            let mut numbers = release.split(|c: char| !c.is_ascii_digit());
+            let major = numbers.next()?.parse().ok()?;
+            let minor = numbers.next()?.parse().ok()?;
+            Some((major, minor))
+        })
+        .max()
 }
 
 /// Credentials for creating a new user account.
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct UserAccountCreate {
     pub username: String,
     pub realname: Option<String>,
+    /// A plaintext password, which is hashed with the scheme that the install prefers.
     pub password: Option<String>,
+    /// A password which was already hashed, such as by `distinst::crypt::hash_password`.
+    pub password_hash: Option<String>,
     pub profile_icon: Option<String>,
+    /// The supplementary groups of the user, in place of `DEFAULT_GROUPS`.
+    pub groups: Option<Vec<String>>,
+    /// Adds the user to the sudo group, or removes it, according to the policy.
+    pub sudo: SudoPolicy,
+    /// The date, as `YYYY-MM-DD`, after which the account is disabled.
+    pub expires: Option<String>,
+}
+
+impl UserAccountCreate {
+    /// Ensures that the names and the expiry date may be given to `useradd`.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |why: String| Err(io::Error::new(io::ErrorKind::InvalidInput, why));
+
+        if !steps::is_valid_name(&self.username) {
+            return invalid(format!("username is not valid: {:?}", self.username));
+        }
+
+        let groups = self.groups.iter().flatten();
+        if let Some(group) = groups.clone().find(|group| !steps::is_valid_name(group)) {
+            return invalid(format!("group name is not valid: {:?}", group));
+        }
+
+        let mut admin = groups.clone().filter(|&group| group == "sudo" || group == "admin");
+        if let (SudoPolicy::Denied, Some(group)) = (self.sudo, admin.next()) {
+            return invalid(format!("user is denied sudo, but given the {} group", group));
+        }
+
+        match self.expires {
+            Some(ref date) if !steps::is_valid_date(date) => {
+                invalid(format!("account expiry is not a date of the form YYYY-MM-DD: {:?}", date))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The hash of the account's password, for a system whose preferred scheme is `scheme`.
+    pub(crate) fn password_hash(&self, scheme: Scheme) -> io::Result<Option<String>> {
+        match (&self.password, &self.password_hash) {
+            (Some(_), Some(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "both a password and a password hash were given for the user",
+            )),
+            (Some(password), None) => crypt::hash_password(password, scheme).map(Some),
+            (None, Some(hash)) if !crypt::is_hash(hash) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the user's password hash is not a crypt hash",
+            )),
+            (None, hash) => Ok(hash.clone()),
+        }
+    }
 }
 
 /// Installer error
@@ -84,19 +536,66 @@ pub struct Error {
     pub err:  io::Error,
 }
 
+impl Error {
+    /// Identifies the failure, if it is one that frontends may react to.
+    pub fn code(&self) -> ErrorCode {
+        CodedError::of(&self.err).map_or(ErrorCode::Unknown, |coded| coded.code)
+    }
+
+    /// The device that the failure concerns, if known.
+    pub fn device(&self) -> Option<&Path> {
+        CodedError::of(&self.err).and_then(|coded| coded.device.as_ref().map(PathBuf::as_path))
+    }
+}
+
+/// A condition which does not prevent the install, but which frontends should present to the
+/// user.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Warning {
+    /// The system is running from a battery, which is charged to the given percent.
+    LowBattery(u8),
+    /// Writes to the root of the install were synced at the given throughput, in KiB/s, which
+    /// is slow enough that the install could take hours.
+    SlowMedia(u32),
+    /// The EFI variables occupy the given KiB, or hold crash dumps, so that the firmware may
+    /// lack the space to store the boot entry.
+    EfiVariablesFull(u32),
+    /// The clock is behind the build of the image by the given number of days, so that TLS
+    /// certificates may be rejected as not yet valid.
+    ClockBehind(u32),
+    /// The clock is ahead of the build of the image by the given number of days, which are
+    /// years, so that TLS certificates may be rejected as expired.
+    ClockAhead(u32),
+}
+
 /// Installer status
 #[derive(Copy, Clone, Debug)]
 pub struct Status {
+    /// The step being performed.
     pub step:    Step,
+    /// The task within the step, if the step reports them.
+    pub substep: Option<Substep>,
+    /// Progress of the current step.
     pub percent: i32,
+    /// Progress of the install as a whole, weighted by the typical duration of each step.
+    pub overall: i32,
+    /// Files of the image which were written, during the extract step, if they can be counted.
+    pub files:   Option<FileProgress>,
 }
 
 /// An installer object
 pub struct Installer {
     error_cb:         Option<Box<dyn FnMut(&Error)>>,
     status_cb:        Option<Box<dyn FnMut(&Status)>>,
+    warning_cb:       Option<Box<dyn FnMut(&Warning)>>,
     timezone_cb:      Option<Box<dyn FnMut() -> Region>>,
     user_creation_cb: Option<Box<dyn FnMut() -> UserAccountCreate>>,
+    journal:          Vec<JournalEntry>,
+    extract_stats:    Option<ExtractStats>,
+    oem_packages:     Vec<OemPackage>,
+    recovery_keys:    Vec<RecoveryKey>,
+    telemetry:        Option<Box<dyn TelemetryReporter>>,
+    failed_step:      Option<Step>,
 }
 
 impl Default for Installer {
@@ -110,8 +609,15 @@ impl Default for Installer {
         Self {
             error_cb:         None,
             status_cb:        None,
+            warning_cb:       None,
             timezone_cb:      None,
             user_creation_cb: None,
+            journal:          Vec::new(),
+            extract_stats:    None,
+            oem_packages:     Vec::new(),
+            recovery_keys:    Vec::new(),
+            telemetry:        None,
+            failed_step:      None,
         }
     }
 }
@@ -138,59 +644,232 @@ impl Installer {
     /// will be applied when configuring the new installation.
     ///
     /// If `config.old_root` is set, then home at that location will be retained.
-    pub fn install(&mut self, mut disks: Disks, config: &Config) -> io::Result<()> {
+    ///
+    /// The system is prevented from sleeping or shutting down until the install has finished.
+    pub fn install(&mut self, disks: Disks, config: &Config) -> io::Result<()> {
+        // The lock is released when it is dropped, at the end of the install.
+        let _inhibitor = match LoginManager::new() {
+            Ok(manager) => manager
+                .connect()
+                .inhibit_suspend("Distinst Installer", "installing a distribution")
+                .map_err(|why| warn!("unable to inhibit suspend: {}", why))
+                .ok(),
+            Err(why) => {
+                warn!("unable to connect to logind: {}", why);
+                None
+            }
+        };
+
+        if let Some(percent) = power::battery_level().filter(|&level| level < power::LOW_BATTERY) {
+            warn!("installing on battery power, with {}% remaining", percent);
+            self.emit_warning(&Warning::LowBattery(percent));
+        }
+
+        self.extract_stats = None;
+        self.oem_packages.clear();
+        self.recovery_keys.clear();
+        self.failed_step = None;
+
+        let started = Instant::now();
+        let result = self.journaled(|installer| installer.run_install(disks, config));
+
+        if let Some(ref mut reporter) = self.telemetry {
+            let summary = InstallSummary::new(&result, self.failed_step, started.elapsed());
+            if let Err(why) = reporter.report(&summary) {
+                warn!("unable to report the install's summary: {}", why);
+            }
+        }
+
+        result
+    }
+
+    /// The external commands that the last install executed, in the order that they completed.
+    pub fn journal(&self) -> &[JournalEntry] { &self.journal }
+
+    /// The journal of the last install, as it is written to `/var/log/installer/journal`.
+    pub fn journal_log(&self) -> String { format_journal(&self.journal) }
+
+    /// The throughput of the last install's extraction, if it reached that step.
+    pub fn extract_stats(&self) -> Option<ExtractStats> { self.extract_stats }
+
+    /// The vendor's packages which the last install attempted, and whether each was installed.
+    pub fn oem_packages(&self) -> &[OemPackage] { &self.oem_packages }
+
+    /// The recovery keys which the last install added to its LUKS volumes.
+    pub fn recovery_keys(&self) -> &[RecoveryKey] { &self.recovery_keys }
+
+    /// Retains the external commands that `func` executes in the journal.
+    fn journaled<F: FnOnce(&mut Self) -> io::Result<()>>(&mut self, func: F) -> io::Result<()> {
+        let _ = journal::take();
+        let result = func(self);
+        self.journal = journal::take();
+        result
+    }
+
+    fn run_install(&mut self, mut disks: Disks, config: &Config) -> io::Result<()> {
         let mut recovery_conf = if Path::new("/cdrom/recovery.conf").exists() {
             Some(RecoveryEnv::new()?)
         } else {
             None
         };
 
+        // Windows may be installed on a disk that the install does not otherwise touch.
+        let retains_windows = disks.retains_windows();
+        let local_rtc = config.local_rtc.or_else(|| {
+            if retains_windows {
+                info!("Windows will remain installed, so the hardware clock is kept in local time");
+                Some(true)
+            } else {
+                None
+            }
+        });
+
+        // Any system which remains installed is booted from the menu, as Windows is.
+        let dual_boot = retains_windows || disks.retains_os();
+
         disks.remove_untouched_disks();
-        let steps = &mut InstallerState::new(self);
+        let _automount = inhibit_automount(&disks);
+        let steps = &mut InstallerState::new(self, config);
 
         Self::backup(disks, config, steps, |mut disks, config, steps| {
-            if !hostname::is_valid(&config.hostname) {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "hostname is not valid"));
-            }
+            config.validate()?;
+            config.validate_temp_dir(&disks)?;
 
+            let _bootloader_override = config.bootloader.apply();
             let bootloader = Bootloader::detect();
+            let loader = crate::kernel_loader(bootloader);
             disks
-                .verify_partitions(bootloader)
+                .verify_partitions(bootloader, loader, config.initrd_keyfile)
                 .with_context(|err| format!("partition validation: {}", err))?;
 
+            if config.initrd_keyfile {
+                initrd_keyfile::prepare(&mut disks, loader)?;
+            }
+
+            verify_bootloader_override(config, bootloader)?;
+
+            if let Some(ref root) = config.read_only_root {
+                root.validate_layout(&disks, bootloader)?;
+            }
+
+            if config.snapshots == Some(SnapshotTool::Snapper) {
+                let root = disks.find_partition(Path::new("/")).map(|(_, root)| root.filesystem);
+                if root != Some(Some(FileSystem::Btrfs)) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "snapper requires the root file system to be Btrfs",
+                    ));
+                }
+            }
+
+            disks.verify_fat_partitions()?;
+
+            match kernel_release(Path::new(&config.squashfs)) {
+                Some(kernel) => disks.verify_ext4_features(kernel)?,
+                None => warn!("unable to determine the kernel release to validate ext4 features"),
+            }
+
+            // The OSes of the disks are detected before partitions are removed or formatted.
+            let stale_esp_directories = if config.esp_cleanup && bootloader == Bootloader::Efi {
+                disks.stale_esp_directories()
+            } else {
+                Vec::new()
+            };
+
             let (squashfs, remove_pkgs) = steps.apply(Step::Init, "initializing", |steps| {
+                for warning in firmware::check(config, bootloader)? {
+                    steps.installer.emit_warning(&warning);
+                }
+
                 Installer::initialize(&mut disks, config, percent!(steps))
             })?;
 
             steps.apply(Step::Partition, "partitioning", |steps| {
-                Installer::partition(&mut disks, percent!(steps))
+                Installer::partition(&mut disks, substep!(steps))
             })?;
 
+            if let Some(ref root) = config.read_only_root {
+                root.validate_partitions(&disks)?;
+            }
+
+            if config.recovery_key {
+                let keys = recovery_key::add_recovery_keys(&disks)
+                    .with_context(|err| format!("adding recovery keys: {}", err))?;
+
+                if let Some(ref path) = config.recovery_key_file {
+                    recovery_key::write_keys(path, &config.hostname, &keys)
+                        .with_context(|err| format!("writing recovery keys: {}", err))?;
+                }
+
+                steps.installer.recovery_keys = keys;
+            }
+
+            // Targets are mounted within a private mount namespace, which hides them from the
+            // desktop, and releases them if the installer dies.
+            let _namespace = MountNamespace::enter()
+                .map_err(|why| warn!("unable to create a private mount namespace: {}", why))
+                .ok();
+
             // Mount the temporary directory, and all of our mount targets.
-            info!("mounting temporary chroot directory at {}", Self::CHROOT_ROOT);
+            let temp_dir = config.temp_dir();
+            info!("mounting temporary chroot directory in {}", temp_dir.display());
 
-            let mount_dir = TempDir::new(Self::CHROOT_ROOT)
+            let mount_dir = TempDir::new_in(&temp_dir, Self::CHROOT_ROOT)
                 .with_context(|err| format!("chroot root temp mount: {}", err))?;
 
             info!("mounting all targets to the temporary chroot");
 
-            let mut mounts = disks
+            let mounts = disks
                 .mount_all_targets(mount_dir.path())
                 .with_context(|err| format!("mounting all targets: {}", err))?;
 
+            steps::configure_btrfs(&disks, mount_dir.path())?;
+
+            remove_esp_directories(&mount_dir.path().join("boot/efi"), &stale_esp_directories);
+
             if PARTITIONING_TEST.load(Ordering::SeqCst) {
                 info!("PARTITION_TEST enabled: exiting before unsquashing");
                 return Ok(());
             }
 
-            let iso_os_release = steps.apply(Step::Extract, "extracting", |steps| {
-                Installer::extract(squashfs.as_path(), mount_dir.path(), percent!(steps))
-            })?;
+            if config.benchmark_target {
+                match media_benchmark::write_throughput(mount_dir.path()) {
+                    Ok(rate) if rate < media_benchmark::SLOW_MEDIA => {
+                        warn!("the target media is slow, writing at {} KiB/s", rate);
+                        steps.installer.emit_warning(&Warning::SlowMedia(rate));
+                    }
+                    Ok(rate) => info!("the target media writes at {} KiB/s", rate),
+                    Err(why) => warn!("unable to benchmark the target media: {}", why),
+                }
+            }
+
+            let (iso_os_release, extract_stats) =
+                steps.apply(Step::Extract, "extracting", |steps| {
+                    let (os_release, mut stats) = Installer::extract(
+                        squashfs.as_path(),
+                        mount_dir.path(),
+                        config.extract_threads,
+                        |percent, files| {
+                            steps.status.percent = percent;
+                            steps.status.files = files;
+                            let status = steps.status;
+                            steps.emit_status(status);
+                        },
+                    )?;
+
+                    if config.btrfs_recompress {
+                        stats.reclaimed = Some(steps::recompress_btrfs(&disks, mount_dir.path()));
+                    }
+
+                    Ok((os_release, stats))
+                })?;
+
+            steps.installer.extract_stats = Some(extract_stats);
 
             let timezone = steps.installer.timezone_cb.as_mut().map(|func| func());
             let user = steps.installer.user_creation_cb.as_mut().map(|func| func());
 
-            steps.apply(Step::Configure, "configuring chroot", |steps| {
+            let oem_packages = steps.apply(Step::Configure, "configuring chroot", |steps| {
                 Installer::configure(
                     recovery_conf.as_mut(),
                     &disks,
@@ -200,10 +879,20 @@ impl Installer {
                     timezone.as_ref(),
                     user.as_ref(),
                     &remove_pkgs,
-                    percent!(steps),
+                    local_rtc,
+                    dual_boot,
+                    substep!(steps),
                 )
             })?;
 
+            steps.installer.oem_packages = oem_packages;
+
+            // The initramfs, which the keyfile is embedded in, is generated with the bootloader.
+            if config.initrd_keyfile {
+                initrd_keyfile::embed(&disks, mount_dir.path())
+                    .with_context(|err| format!("embedding the initramfs keyfile: {}", err))?;
+            }
+
             steps.apply(Step::Bootloader, "configuring bootloader", |steps| {
                 Installer::bootloader(
                     &disks,
@@ -215,10 +904,58 @@ impl Installer {
                 )
             })?;
 
-            mounts.unmount(false).with_context(|err| format!("chroot unmount: {}", err))?;
-            mount_dir.close().with_context(|err| format!("closing mount directory: {}", err))
+            if let Some(ref migration) = config.windows_migration {
+                match user {
+                    Some(ref user) => {
+                        steps.apply(Step::Migrate, "migrating user data", |steps| {
+                            Installer::migrate_windows(
+                                migration,
+                                mount_dir.path(),
+                                &temp_dir,
+                                user,
+                                percent!(steps),
+                            )
+                        })?;
+                    }
+                    None => warn!("a new user is required to migrate Windows user data"),
+                }
+            }
+
+            if config.flags & CAPTURE_FACTORY_IMAGE != 0 {
+                steps.apply(Step::FactoryImage, "capturing factory image", |steps| {
+                    Installer::factory_image(mount_dir.path(), percent!(steps))
+                })?;
+            }
+
+            let log = InstallLog {
+                config,
+                disks: &disks,
+                bootloader,
+                image: &iso_os_release,
+                removed_packages: &remove_pkgs,
+                extract_stats: steps.installer.extract_stats,
+                oem_packages: &steps.installer.oem_packages,
+                recovery_keys: &steps.installer.recovery_keys,
+            };
+
+            if let Err(why) = log.write(mount_dir.path()) {
+                warn!("unable to write the installer's logs: {}", why);
+            }
+
+            // Busy mounts are retried, and then unmounted lazily, rather than failing the install.
+            unmount::unmount_tree(mount_dir.path())
+                .with_context(|err| format!("chroot unmount: {}", err))?;
+            drop(mounts);
+            mount_dir.close().with_context(|err| format!("closing mount directory: {}", err))?;
+
+            if let Some(ref root) = config.read_only_root {
+                steps::seal_read_only_root(&disks, root)?;
+            }
+
+            Ok(())
         })?;
 
+        steps.finish();
         let _ = deactivate_logical_devices();
 
         if let Some(conf) = recovery_conf.as_mut() {
@@ -229,6 +966,313 @@ impl Installer {
         Ok(())
     }
 
+    /// Applies the partitioning and formatting changes in `disks`, without installing a system.
+    ///
+    /// This is useful for provisioning data disks, or for preparing targets for other imaging
+    /// tools. A root partition is not required, but disks that the running system is mounted
+    /// from will not be modified.
+    pub fn partition_only(&mut self, disks: Disks) -> io::Result<()> {
+        self.journaled(|installer| installer.run_partition_only(disks))
+    }
+
+    fn run_partition_only(&mut self, mut disks: Disks) -> io::Result<()> {
+        disks.remove_untouched_disks();
+        let _automount = inhibit_automount(&disks);
+        let steps = &mut InstallerState::with_steps(self, &[Step::Init, Step::Partition]);
+
+        steps.apply(Step::Init, "preparing disks", |steps| {
+            disks.verify_protected()?;
+            disks.verify_gpt()?;
+            steps::prepare_disks(&mut disks, percent!(steps))
+        })?;
+
+        steps.apply(Step::Partition, "partitioning", |steps| {
+            Installer::partition(&mut disks, substep!(steps))
+        })?;
+
+        let _ = deactivate_logical_devices();
+        Ok(())
+    }
+
+    /// Installs the image to the directory at `target`, rather than to disks, for a container
+    /// which is run by systemd-nspawn or LXC. The directory must be empty if it exists, and is
+    /// otherwise created, as a Btrfs subvolume if `subvolume` is set.
+    ///
+    /// The image is configured as it is for an install to disks, except that neither a
+    /// bootloader, a kernel, nor an initramfs is configured. Options which only apply to disks,
+    /// such as a read-only root, are refused.
+    pub fn install_container(
+        &mut self,
+        target: &Path,
+        subvolume: bool,
+        config: &Config,
+    ) -> io::Result<()> {
+        self.extract_stats = None;
+        self.oem_packages.clear();
+        self.failed_step = None;
+        self.journaled(|installer| installer.run_install_container(target, subvolume, config))
+    }
+
+    fn run_install_container(
+        &mut self,
+        target: &Path,
+        subvolume: bool,
+        config: &Config,
+    ) -> io::Result<()> {
+        config.validate()?;
+        container::validate(config)?;
+
+        let steps = &mut InstallerState::with_steps(
+            self,
+            &[Step::Init, Step::Extract, Step::Configure],
+        );
+
+        Installer::populate_directory(steps, target, subvolume, config).map(|_| ())
+    }
+
+    /// Exports the image, configured for Windows Subsystem for Linux, as a tarball which is
+    /// imported with `wsl --import`. The compression of the tarball is chosen by its extension.
+    ///
+    /// The image is configured as it is for a container, and `/etc/wsl.conf` starts it with
+    /// systemd, as the user which was created, if any.
+    pub fn export_wsl(&mut self, tarball: &Path, config: &Config) -> io::Result<()> {
+        self.extract_stats = None;
+        self.oem_packages.clear();
+        self.failed_step = None;
+        self.journaled(|installer| installer.run_export_wsl(tarball, config))
+    }
+
+    fn run_export_wsl(&mut self, tarball: &Path, config: &Config) -> io::Result<()> {
+        config.validate()?;
+        container::validate(config)?;
+
+        let root = TempDir::new_in(config.temp_dir(), "distinst-wsl")
+            .with_context(|err| format!("creating the WSL root: {}", err))?;
+
+        let steps = &mut InstallerState::with_steps(
+            self,
+            &[Step::Init, Step::Extract, Step::Configure, Step::Export],
+        );
+
+        let user = Installer::populate_directory(steps, root.path(), false, config)?;
+
+        steps.apply(Step::Export, "exporting WSL tarball", |steps| {
+            let username = user.as_ref().map(|user| user.username.as_str());
+            steps::export_wsl(root.path(), tarball, &config.hostname, username, percent!(steps))
+        })?;
+
+        root.close().with_context(|err| format!("removing the WSL root: {}", err))
+    }
+
+    /// Extracts, and configures, the image in a directory, as a container, returning the user
+    /// which was created within it.
+    fn populate_directory(
+        steps: &mut InstallerState,
+        target: &Path,
+        subvolume: bool,
+        config: &Config,
+    ) -> io::Result<Option<UserAccountCreate>> {
+        let (squashfs, remove_pkgs) = steps.apply(Step::Init, "initializing", |steps| {
+            steps::initialize_container(target, subvolume, config, percent!(steps))
+        })?;
+
+        // The mounts of the chroot are released if the installer dies.
+        let _namespace = MountNamespace::enter()
+            .map_err(|why| warn!("unable to create a private mount namespace: {}", why))
+            .ok();
+
+        let (iso_os_release, extract_stats) = steps.apply(Step::Extract, "extracting", |steps| {
+            Installer::extract(
+                squashfs.as_path(),
+                target,
+                config.extract_threads,
+                |percent, files| {
+                    steps.status.percent = percent;
+                    steps.status.files = files;
+                    let status = steps.status;
+                    steps.emit_status(status);
+                },
+            )
+        })?;
+
+        steps.installer.extract_stats = Some(extract_stats);
+
+        let timezone = steps.installer.timezone_cb.as_mut().map(|func| func());
+        let user = steps.installer.user_creation_cb.as_mut().map(|func| func());
+
+        let oem_packages = steps.apply(Step::Configure, "configuring container", |steps| {
+            steps::configure(
+                None,
+                &ContainerDisks,
+                target,
+                config,
+                &iso_os_release,
+                timezone.as_ref(),
+                user.as_ref(),
+                &remove_pkgs,
+                config.local_rtc,
+                false,
+                substep!(steps),
+            )
+        })?;
+
+        steps.installer.oem_packages = oem_packages;
+        Ok(user)
+    }
+
+    /// Repairs the boot of an existing install, whose root is on `target_root`, without
+    /// reinstalling it. The bootloader is reinstalled, the initramfs is regenerated, and the
+    /// fstab entries of the root, boot, and EFI partitions are corrected.
+    ///
+    /// If the root is encrypted, `target_root` is its LUKS partition, which is unlocked with
+    /// `encryption`, and the logical volumes within it are activated.
+    pub fn repair_bootloader(
+        &mut self,
+        target_root: &Path,
+        encryption: Option<&LvmEncryption>,
+    ) -> io::Result<()> {
+        self.journaled(|installer| installer.run_repair_bootloader(target_root, encryption))
+    }
+
+    fn run_repair_bootloader(
+        &mut self,
+        target_root: &Path,
+        encryption: Option<&LvmEncryption>,
+    ) -> io::Result<()> {
+        let bootloader = Bootloader::detect();
+        let config = Config { flags: MODIFY_BOOT_ORDER, ..Config::default() };
+        let steps = &mut InstallerState::with_steps(self, &[Step::Init, Step::Bootloader]);
+
+        let (disks, os_release) = steps.apply(Step::Init, "opening the install", |_| {
+            let (mut disks, os_release) = steps::open_install(target_root, encryption)?;
+            steps::assign_esp(&mut disks, target_root, bootloader)?;
+            Ok((disks, os_release))
+        })?;
+
+        let result = TempDir::new("distinst").and_then(|mount_dir| {
+            let mount_dir = mount_dir.path();
+            let _mounts = disks.mount_all_targets(mount_dir)?;
+
+            steps.apply(Step::Bootloader, "repairing the bootloader", |steps| {
+                steps::repair_fstab(mount_dir, &disks)?;
+                Installer::bootloader(
+                    &disks,
+                    mount_dir,
+                    bootloader,
+                    &config,
+                    &os_release,
+                    percent!(steps),
+                )
+            })
+        });
+
+        let _ = deactivate_logical_devices();
+        result
+    }
+
+    /// Encrypts the root of the existing install on `target_root` in place, with the passphrase
+    /// of `encryption`, whose physical volume names the unlocked root. The install is then
+    /// configured to unlock its root as it boots.
+    ///
+    /// The file systems of the install must not be mounted. An encryption which was interrupted
+    /// is resumed by calling this again with the same passphrase.
+    pub fn encrypt_root(
+        &mut self,
+        target_root: &Path,
+        encryption: &LvmEncryption,
+    ) -> io::Result<()> {
+        self.journaled(|installer| installer.run_encrypt_root(target_root, encryption))
+    }
+
+    fn run_encrypt_root(
+        &mut self,
+        target_root: &Path,
+        encryption: &LvmEncryption,
+    ) -> io::Result<()> {
+        let password = encryption.password.as_deref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "a passphrase is required to encrypt")
+        })?;
+
+        let bootloader = Bootloader::detect();
+        let config = Config::default();
+        let steps = &mut InstallerState::with_steps(
+            self,
+            &[Step::Init, Step::Partition, Step::Bootloader],
+        );
+
+        // An encryption which was interrupted is completed, and an install whose encryption was
+        // completed is configured again, as it may have been interrupted before then.
+        let state = steps::Reencryption::of(target_root)?;
+        if state == steps::Reencryption::Unencrypted {
+            steps.apply(Step::Init, "checking that the install may be encrypted", |_| {
+                let (mut disks, os_release) = steps::open_install(target_root, None)?;
+                steps::assign_esp(&mut disks, target_root, bootloader)?;
+
+                let mount_dir = TempDir::new("distinst")?;
+                let mount_dir = mount_dir.path();
+                let result = disks.mount_all_targets(mount_dir).and_then(|_mounts| {
+                    let root = target_root;
+                    steps::verify_encryptable(&disks, mount_dir, root, bootloader, &os_release)
+                });
+
+                let _ = deactivate_logical_devices();
+                result
+            })?;
+        }
+
+        if state != steps::Reencryption::Complete {
+            steps.apply(Step::Partition, "encrypting the root", |steps| {
+                steps::encrypt_in_place(target_root, password, percent!(steps))
+            })?;
+        }
+
+        let result = steps.apply(Step::Bootloader, "configuring the install to unlock", |steps| {
+            let (mut disks, os_release) = steps::open_install(target_root, Some(encryption))?;
+            steps::assign_esp(&mut disks, target_root, bootloader)?;
+
+            let mount_dir = TempDir::new("distinst")?;
+            let mount_dir = mount_dir.path();
+            let _mounts = disks.mount_all_targets(mount_dir)?;
+
+            steps::regenerate_fstabs(mount_dir, &disks)?.write()?;
+            Installer::bootloader(
+                &disks,
+                mount_dir,
+                bootloader,
+                &config,
+                &os_release,
+                percent!(steps),
+            )
+        });
+
+        if result.is_ok() {
+            steps.finish();
+        }
+
+        let _ = deactivate_logical_devices();
+        result
+    }
+
+    /// Reencrypts the LUKS2 volume on `device` with `cipher`, unlocking it with the passphrase
+    /// of `encryption`. A reencryption which was interrupted is resumed by calling this again.
+    pub fn reencrypt(
+        &mut self,
+        device: &Path,
+        encryption: &LvmEncryption,
+        cipher: &Cipher,
+    ) -> io::Result<()> {
+        self.journaled(|installer| {
+            let password = encryption.password.as_deref().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "a passphrase is required to unlock")
+            })?;
+
+            let steps = &mut InstallerState::with_steps(installer, &[Step::Partition]);
+            steps.apply(Step::Partition, "reencrypting the volume", |steps| {
+                steps::change_cipher(device, password, cipher, percent!(steps))
+            })
+        })
+    }
+
     /// Create a backup of key data on the system, execute the given functi on, and then restore
     /// that backup. If a backup is not requested for the configuration, then it will just
     /// execute the given function.
@@ -269,13 +1313,55 @@ impl Installer {
 
             account_files = AccountFiles::new(old_root_path, old_root_fs)?;
 
+            let mut packages = if config.flags & RESTORE_PACKAGES != 0 {
+                match PackageSelections::new(old_root_path, old_root_fs) {
+                    Ok(packages) => Some(packages),
+                    Err(why) => {
+                        warn!("package selections will not be restored: {}", why);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            let mut preserved = if config.preserved_paths.is_empty() {
+                None
+            } else {
+                Some(PreservedFiles::new(old_root_path, old_root_fs, &config.preserved_paths)?)
+            };
+
             let backup = steps.apply(Step::Backup, "backing up", |steps| {
                 let mut callback = percent!(steps);
 
-                let backup = Backup::new(home_path, home_fs, home_is_root, &account_files)?;
+                let backup = Backup::new(
+                    home_path,
+                    home_fs,
+                    home_is_root,
+                    &account_files,
+                    packages.take(),
+                    preserved.take(),
+                )?;
                 callback(25);
 
                 validate_backup_conditions(&disks, &config.squashfs)?;
+
+                match install_size(Path::new(&config.squashfs)) {
+                    Some(required) => {
+                        let report = validate_before_removing(
+                            old_root_path,
+                            old_root_fs,
+                            config.flags & KEEP_OLD_ROOT != 0,
+                            required,
+                        )?;
+
+                        if !report.is_sufficient() {
+                            return Err(ReinstallError::InsufficientSpace { report }.into());
+                        }
+                    }
+                    None => warn!("install size is unknown: skipping disk space validation"),
+                }
+
                 callback(50);
 
                 if config.flags & KEEP_OLD_ROOT != 0 {
@@ -335,6 +1421,7 @@ impl Installer {
     /// });
     /// ```
     pub fn emit_error(&mut self, error: &Error) {
+        self.failed_step = Some(error.step);
         if let Some(ref mut cb) = self.error_cb {
             cb(error);
         }
@@ -358,7 +1445,10 @@ impl Installer {
     /// let mut installer = Installer::new();
     /// installer.emit_status(&Status {
     ///     step:    Step::Extract,
+    ///     substep: None,
     ///     percent: 50,
+    ///     overall: 60,
+    ///     files:   None,
     /// });
     /// ```
     pub fn emit_status(&mut self, status: Status) {
@@ -378,6 +1468,24 @@ impl Installer {
         self.status_cb = Some(Box::new(callback));
     }
 
+    /// Send a warning
+    pub fn emit_warning(&mut self, warning: &Warning) {
+        if let Some(ref mut cb) = self.warning_cb {
+            cb(warning);
+        }
+    }
+
+    /// Set the warning callback
+    pub fn on_warning<F: FnMut(&Warning) + 'static>(&mut self, callback: F) {
+        self.warning_cb = Some(Box::new(callback));
+    }
+
+    /// Submits an anonymous summary of each install to `reporter`, once the install has
+    /// completed or failed. Nothing is reported unless a reporter is set.
+    pub fn set_telemetry_reporter<R: TelemetryReporter + 'static>(&mut self, reporter: R) {
+        self.telemetry = Some(Box::new(reporter));
+    }
+
     /// Set the timezone callback
     pub fn set_timezone_callback<F: FnMut() -> Region + 'static>(&mut self, callback: F) {
         self.timezone_cb = Some(Box::new(callback));
@@ -397,7 +1505,7 @@ impl Installer {
 
     /// Apply all partitioning and formatting changes to the disks
     /// configuration specified.
-    fn partition<F: FnMut(i32)>(disks: &mut Disks, callback: F) -> io::Result<()> {
+    fn partition<F: FnMut(Option<Substep>, i32)>(disks: &mut Disks, callback: F) -> io::Result<()> {
         steps::partition(disks, callback)
     }
 
@@ -405,21 +1513,25 @@ impl Installer {
     ///
     /// We get the os-release data here because the host that is installing the image may differ
     /// from the image that is being installed, and thus may be a completely different distro.
-    fn extract<P: AsRef<Path>, F: FnMut(i32)>(
+    fn extract<P: AsRef<Path>, F: FnMut(i32, Option<FileProgress>)>(
         squashfs: P,
         mount_dir: P,
+        threads: Option<usize>,
         callback: F,
-    ) -> io::Result<OsRelease> {
+    ) -> io::Result<(OsRelease, ExtractStats)> {
         info!("Extracting {}", squashfs.as_ref().display());
         let mount_dir = mount_dir.as_ref();
-        squashfs::extract(squashfs, mount_dir, callback)?;
-        OsRelease::new_from(&mount_dir.join("etc/os-release")).with_context(|why| {
+        let stats = steps::extract(squashfs.as_ref(), mount_dir, threads, callback)?;
+        steps::verify_capabilities(Path::new("/"), mount_dir)?;
+        let os_release = OsRelease::from_root(mount_dir).with_context(|why| {
             format!("failed to parse /etc/os-release from extracted image: {}", why)
-        })
+        })?;
+
+        Ok((os_release, stats))
     }
 
     /// Configures the new install after it has been extracted.
-    fn configure<P: AsRef<Path>, S: AsRef<str>, F: FnMut(i32)>(
+    fn configure<P: AsRef<Path>, S: AsRef<str>, F: FnMut(Substep, i32)>(
         recovery_conf: Option<&mut RecoveryEnv>,
         disks: &Disks,
         mount_dir: P,
@@ -428,8 +1540,10 @@ impl Installer {
         region: Option<&Region>,
         user: Option<&UserAccountCreate>,
         remove_pkgs: &[S],
+        local_rtc: Option<bool>,
+        dual_boot: bool,
         callback: F,
-    ) -> io::Result<()> {
+    ) -> io::Result<Vec<OemPackage>> {
         steps::configure(
             recovery_conf,
             disks,
@@ -439,6 +1553,8 @@ impl Installer {
             region,
             user,
             remove_pkgs,
+            local_rtc,
+            dual_boot,
             callback,
         )
     }
@@ -454,10 +1570,33 @@ impl Installer {
     ) -> io::Result<()> {
         steps::bootloader(disks, mount_dir, bootloader, config, iso_os_release, callback)
     }
+
+    /// Copies user data from a Windows install into the home of the new user.
+    fn migrate_windows<F: FnMut(i32)>(
+        migration: &WindowsMigration,
+        mount_dir: &Path,
+        temp_dir: &Path,
+        user: &UserAccountCreate,
+        callback: F,
+    ) -> io::Result<()> {
+        steps::migrate_windows(migration, mount_dir, temp_dir, user, callback)
+    }
+
+    /// Captures the finished install into a squashfs on the recovery partition.
+    fn factory_image<F: FnMut(i32)>(mount_dir: &Path, callback: F) -> io::Result<()> {
+        steps::factory_image(mount_dir, callback)
+    }
 }
 
 impl From<ReinstallError> for io::Error {
     fn from(why: ReinstallError) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, format!("{}", why))
+        let message = format!("{}", why);
+        match why {
+            ReinstallError::InsufficientSpace { .. } => {
+                CodedError::new(ErrorCode::InsufficientSpace, None, message)
+                    .into_io(io::ErrorKind::Other)
+            }
+            _ => io::Error::new(io::ErrorKind::Other, message),
+        }
     }
 }
@@ -0,0 +1,71 @@
+//! Power supply checks, so that a laptop is not left to run out of battery mid-install.
+
+use std::{fs, path::Path};
+
+/// A battery level, in percent, below which installing without AC power is warned against.
+pub const LOW_BATTERY: u8 = 25;
+
+/// The charge of the battery, in percent, if the system is running from its battery.
+pub fn battery_level() -> Option<u8> { battery_level_in(Path::new("/sys/class/power_supply")) }
+
+fn battery_level_in(supplies: &Path) -> Option<u8> {
+    let mut level = None;
+    for supply in supplies.read_dir().ok()?.flatten() {
+        let supply = supply.path();
+        let read = |attribute: &str| {
+            fs::read_to_string(supply.join(attribute)).ok().map(|value| value.trim().to_owned())
+        };
+
+        match read("type").as_deref() {
+            Some("Mains") if read("online").as_deref() == Some("1") => return None,
+            // Peripherals, such as wireless mice, report their batteries with a device scope.
+            Some("Battery") if read("scope").as_deref() != Some("Device") => {
+                if read("status").as_deref() != Some("Discharging") {
+                    continue;
+                }
+
+                let capacity = read("capacity").and_then(|value| value.parse::<u8>().ok());
+                if let Some(capacity) = capacity {
+                    level = Some(level.map_or(capacity, |level: u8| level.min(capacity)));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn supply(dir: &Path, name: &str, attributes: &[(&str, &str)]) {
+        let supply = dir.join(name);
+        fs::create_dir_all(&supply).unwrap();
+        for &(attribute, value) in attributes {
+            fs::write(supply.join(attribute), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    #[test]
+    fn battery_levels() {
+        let tempdir = TempDir::new("distinst-power").unwrap();
+        let dir = tempdir.path();
+        let battery = [("type", "Battery"), ("status", "Discharging"), ("capacity", "18")];
+        let mouse = [
+            ("type", "Battery"),
+            ("scope", "Device"),
+            ("status", "Discharging"),
+            ("capacity", "5"),
+        ];
+        supply(dir, "BAT0", &battery);
+        supply(dir, "hidpp_battery_0", &mouse);
+        supply(dir, "AC", &[("type", "Mains"), ("online", "0")]);
+        assert_eq!(battery_level_in(dir), Some(18));
+
+        supply(dir, "AC", &[("type", "Mains"), ("online", "1")]);
+        assert_eq!(battery_level_in(dir), None);
+    }
+}
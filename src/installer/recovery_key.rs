@@ -0,0 +1,139 @@
+//! Recovery keys which are added to the LUKS volumes that an install creates, so that an
+//! organization may escrow a key which unlocks the volume if its password is forgotten.
+
+use crate::disks::Disks;
+use crate::external::cryptsetup_add_key;
+use crate::misc;
+use partition_identity::PartitionID;
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The key slot which recovery keys are added to, as the password occupies the first slot.
+pub const RECOVERY_KEY_SLOT: u32 = 1;
+
+/// A recovery key which was added to a LUKS volume.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryKey {
+    /// The LUKS partition which the key unlocks.
+    pub device:   PathBuf,
+    /// The UUID of the LUKS partition, by which the key may be found in escrow.
+    pub uuid:     Option<String>,
+    /// The key, as groups of hexadecimal digits, such as `3f9a2-...`.
+    pub key:      String,
+    pub key_slot: u32,
+}
+
+/// Adds a random recovery key to each LUKS volume which was created with a password.
+pub(crate) fn add_recovery_keys(disks: &Disks) -> io::Result<Vec<RecoveryKey>> {
+    let mut keys = Vec::new();
+    for logical in disks.get_logical_devices() {
+        let (device, encryption) = match (&logical.luks_parent, &logical.encryption) {
+            (Some(device), Some(encryption)) if !logical.is_source => (device, encryption),
+            _ => continue,
+        };
+
+        let password = match encryption.password {
+            Some(ref password) => password,
+            None => {
+                let device = device.display();
+                warn!("{} is unlocked by a keyfile, so no recovery key was added", device);
+                continue;
+            }
+        };
+
+        info!("adding a recovery key to {}", device.display());
+        let key = generate()?;
+        cryptsetup_add_key(device, password, RECOVERY_KEY_SLOT, &key)?;
+
+        keys.push(RecoveryKey {
+            device: device.clone(),
+            uuid: PartitionID::get_uuid(device).map(|uuid| uuid.id),
+            key,
+            key_slot: RECOVERY_KEY_SLOT,
+        });
+    }
+
+    Ok(keys)
+}
+
+/// Writes the keys to a file which is ready to be printed, and which only root may read.
+pub(crate) fn write_keys(path: &Path, hostname: &str, keys: &[RecoveryKey]) -> io::Result<()> {
+    info!("writing recovery keys to {}", path.display());
+    let mut file = misc::create_private(path)?;
+    file.write_all(print_ready(hostname, keys).as_bytes())?;
+    file.sync_all()
+}
+
+/// A random key of 160 bits.
+fn generate() -> io::Result<String> {
+    let mut bytes = [0u8; 20];
+    misc::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(format_key(&bytes))
+}
+
+/// Formats the bytes as groups of five hexadecimal digits, so that the key may be read aloud
+/// and typed at the passphrase prompt.
+fn format_key(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let mut key = String::with_capacity(hex.len() + hex.len() / 5);
+    for (id, digit) in hex.chars().enumerate() {
+        if id != 0 && id % 5 == 0 {
+            key.push('-');
+        }
+
+        key.push(digit);
+    }
+
+    key
+}
+
+fn print_ready(hostname: &str, keys: &[RecoveryKey]) -> String {
+    let mut text = format!(
+        "Disk encryption recovery keys of {}\n\n\
+         Type the recovery key at the disk's passphrase prompt to unlock it.\n",
+        hostname
+    );
+
+    for key in keys {
+        text.push_str(&format!(
+            "\nDevice:   {}\nUUID:     {}\nKey slot: {}\nKey:      {}\n",
+            key.device.display(),
+            key.uuid.as_deref().unwrap_or("unknown"),
+            key.key_slot,
+            key.key
+        ));
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_key_format() {
+        let bytes: Vec<u8> = (0..20).map(|byte| byte * 13).collect();
+        let key = format_key(&bytes);
+        assert_eq!(key, "000d1-a2734-414e5-b6875-828f9-ca9b6-c3d0d-deaf7");
+
+        let keys = [RecoveryKey {
+            device:   PathBuf::from("/dev/sda3"),
+            uuid:     Some("5e7b3a1c".into()),
+            key:      key.clone(),
+            key_slot: RECOVERY_KEY_SLOT,
+        }];
+
+        assert_eq!(
+            print_ready("lab-01", &keys),
+            format!(
+                "Disk encryption recovery keys of lab-01\n\n\
+                 Type the recovery key at the disk's passphrase prompt to unlock it.\n\n\
+                 Device:   /dev/sda3\nUUID:     5e7b3a1c\nKey slot: 1\nKey:      {}\n",
+                key
+            )
+        );
+    }
+}
@@ -1,16 +1,66 @@
-use super::{Error, Installer, Status, Step};
+use super::{Config, Error, Installer, Status, Step, CAPTURE_FACTORY_IMAGE};
 use libc;
 use std::{io, sync::atomic::Ordering};
+use crate::disks::{CodedError, ErrorCode};
+use crate::misc::priority::Priority;
 use crate::KILL_SWITCH;
 
 pub struct InstallerState<'a> {
     pub installer: &'a mut Installer,
     pub status:    Status,
+    /// Combined weight of the steps that have been completed.
+    completed:     u32,
+    /// Weight of the step in progress.
+    current:       u32,
+    /// Combined weight of all steps that the install is expected to perform.
+    total:         u32,
+    /// The priority with which heavy steps are performed.
+    priority:      Option<Priority>,
 }
 
 impl<'a> InstallerState<'a> {
-    pub fn new(installer: &'a mut Installer) -> Self {
-        Self { installer, status: Status { step: Step::Init, percent: 0 } }
+    pub fn new(installer: &'a mut Installer, config: &Config) -> Self {
+        let mut steps = vec![
+            Step::Init,
+            Step::Partition,
+            Step::Extract,
+            Step::Configure,
+            Step::Bootloader,
+        ];
+
+        if config.old_root.is_some() {
+            steps.push(Step::Backup);
+        }
+
+        if config.windows_migration.is_some() {
+            steps.push(Step::Migrate);
+        }
+
+        if config.flags & CAPTURE_FACTORY_IMAGE != 0 {
+            steps.push(Step::FactoryImage);
+        }
+
+        let mut state = Self::with_steps(installer, &steps);
+        state.priority = config.priority;
+        state
+    }
+
+    /// Tracks the progress of an installer which only performs the given steps.
+    pub fn with_steps(installer: &'a mut Installer, steps: &[Step]) -> Self {
+        Self {
+            installer,
+            status: Status {
+                step:    Step::Init,
+                substep: None,
+                percent: 0,
+                overall: 0,
+                files:   None,
+            },
+            completed: 0,
+            current: 0,
+            total: steps.iter().cloned().map(Step::weight).sum(),
+            priority: None,
+        }
     }
 
     pub fn apply<T, F>(&mut self, step: Step, msg: &str, mut action: F) -> io::Result<T>
@@ -22,17 +72,44 @@ impl<'a> InstallerState<'a> {
         }
 
         if KILL_SWITCH.load(Ordering::SeqCst) {
-            return Err(io::Error::new(io::ErrorKind::Interrupted, "process killed"));
+            return Err(CodedError::new(ErrorCode::Cancelled, None, "process killed")
+                .into_io(io::ErrorKind::Interrupted));
         }
 
+        self.completed += self.current;
+        self.current = step.weight();
+
         self.status.step = step;
+        self.status.substep = None;
         self.status.percent = 0;
+        self.status.files = None;
         let status = self.status;
         self.emit_status(status);
 
+        // The priority is restored when the guard is dropped at the end of the step.
+        let _priority = match self.priority {
+            Some(priority) if step.is_heavy() => match priority.apply() {
+                Ok(guard) => Some(guard),
+                Err(why) => {
+                    warn!("unable to set the priority of the {} step: {}", msg, why);
+                    None
+                }
+            },
+            _ => None,
+        };
+
         info!("starting {} step", msg);
         match action(self) {
-            Ok(value) => Ok(value),
+            Ok(value) => {
+                // Steps which report their progress in coarse increments may not report the end.
+                if self.status.percent != 100 {
+                    self.status.percent = 100;
+                    let status = self.status;
+                    self.emit_status(status);
+                }
+
+                Ok(value)
+            }
             Err(err) => {
                 error!("{} error: {}", msg, err);
                 let error = Error { step: self.status.step, err };
@@ -42,7 +119,28 @@ impl<'a> InstallerState<'a> {
         }
     }
 
-    pub fn emit_status(&mut self, status: Status) { self.installer.emit_status(status); }
+    pub fn emit_status(&mut self, mut status: Status) {
+        status.overall = self.overall(status.percent);
+        self.installer.emit_status(status);
+    }
 
     pub fn emit_error(&mut self, error: &Error) { self.installer.emit_error(&error); }
+
+    /// Reports the install as complete, as the steps which were expected may not all have been
+    /// performed, such as a migration without a user to migrate to.
+    pub fn finish(&mut self) {
+        self.completed = self.total;
+        self.current = 0;
+        let status = self.status;
+        self.emit_status(status);
+    }
+
+    fn overall(&self, percent: i32) -> i32 {
+        if self.total == 0 {
+            return percent;
+        }
+
+        let percent = percent.max(0).min(100) as u32;
+        ((self.completed * 100 + self.current * percent) / self.total).min(100) as i32
+    }
 }
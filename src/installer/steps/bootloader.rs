@@ -1,5 +1,5 @@
 use crate::chroot::Chroot;
-use crate::disks::{Bootloader, Disks};
+use crate::disks::{Bootloader, Disks, KernelLoader};
 use crate::errors::IoContext;
 use libc;
 use os_release::OsRelease;
@@ -12,7 +12,7 @@ use std::{
 use crate::Config;
 use crate::MODIFY_BOOT_ORDER;
 
-use super::mount_efivars;
+use super::{initramfs::{self, Feature}, mount_efivars};
 
 pub fn bootloader<F: FnMut(i32)>(
     disks: &Disks,
@@ -58,9 +58,27 @@ pub fn bootloader<F: FnMut(i32)>(
         {
             let mut chroot = Chroot::new(mount_dir)?;
             let efivars_mount = mount_efivars(&mount_dir)?;
+            let features = Feature::of(disks);
+            let generate_initramfs = |chroot: &Chroot| {
+                initramfs::generate(chroot, mount_dir, &config.initramfs, &features)
+                    .with_context(|why| format!("generating the initramfs: {}", why))
+            };
+
+            // GRUB must be able to unlock an encrypted `/boot`, whether it boots from the MBR
+            // or from the ESP, and so the option is set before either is installed.
+            let enable_cryptodisk = |chroot: &Chroot| {
+                chroot
+                    .command(
+                        "/usr/bin/env",
+                        &["bash", "-c", "echo GRUB_ENABLE_CRYPTODISK=y >> /etc/default/grub"],
+                    )
+                    .run()
+            };
 
             match bootloader {
                 Bootloader::Bios => {
+                    enable_cryptodisk(&chroot)?;
+
                     chroot
                         .command(
                             "grub-install",
@@ -75,12 +93,18 @@ pub fn bootloader<F: FnMut(i32)>(
                         )
                         .run()?;
 
-                    chroot.command("update-initramfs", &["-c", "-k", "all"]).run()?;
+                    // The menu is regenerated, as the EFI path does, so that it reflects the
+                    // defaults which were set since it was first generated.
+                    let args: &[&str] = &[];
+                    chroot.command("update-grub", args).run()?;
+
+                    generate_initramfs(&chroot)?;
                 }
                 Bootloader::Efi => {
                     // Grub disallows whitespaces in the name.
                     let name = super::normalize_os_release_name(&iso_os_release.name);
-                    if &name == "Pop!_OS" {
+                    let kernel_loader = KernelLoader::of(bootloader, &iso_os_release.name);
+                    if kernel_loader == KernelLoader::SystemdBoot {
                         chroot
                             .command(
                                 "bootctl",
@@ -95,16 +119,7 @@ pub fn bootloader<F: FnMut(i32)>(
                             )
                             .run()?;
                     } else {
-                        chroot
-                            .command(
-                                "/usr/bin/env",
-                                &[
-                                    "bash",
-                                    "-c",
-                                    "echo GRUB_ENABLE_CRYPTODISK=y >> /etc/default/grub",
-                                ],
-                            )
-                            .run()?;
+                        enable_cryptodisk(&chroot)?;
 
                         chroot
                             .command(
@@ -128,11 +143,11 @@ pub fn bootloader<F: FnMut(i32)>(
                             .run()?;
                     }
 
-                    chroot.command("update-initramfs", &["-c", "-k", "all"]).run()?;
+                    generate_initramfs(&chroot)?;
 
                     if config.flags & MODIFY_BOOT_ORDER != 0 {
                         let efi_part_num = efi_part_num.to_string();
-                        let loader = if &name == "Pop!_OS" {
+                        let loader = if kernel_loader == KernelLoader::SystemdBoot {
                             "\\EFI\\systemd\\systemd-bootx64.efi".into()
                         } else {
                             format!("\\EFI\\{}\\shimx64.efi", name)
@@ -0,0 +1,129 @@
+//! The apt archive of the installed system, which replaces the sources that the image carried,
+//! so that regional deployments may point their installs at a local mirror.
+
+use std::io;
+
+/// The components which are enabled if none were given.
+const DEFAULT_COMPONENTS: &[&str] = &["main", "restricted", "universe", "multiverse"];
+
+/// The sources of the distribution's archive, which are written to the installed system.
+#[derive(Debug, Clone, Default)]
+pub struct AptSources {
+    /// The URI of the archive, such as `http://archive.ubuntu.com/ubuntu`.
+    pub mirror:     String,
+    /// The components of the archive to enable, or the default components if empty.
+    pub components: Vec<String>,
+    /// Enables the `-updates` pocket.
+    pub updates:    bool,
+    /// Enables the `-proposed` pocket.
+    pub proposed:   bool,
+}
+
+impl AptSources {
+    /// Sources for the given mirror, with the default components and the `-updates` pocket.
+    pub fn new<S: Into<String>>(mirror: S) -> Self {
+        AptSources { mirror: mirror.into(), updates: true, ..AptSources::default() }
+    }
+
+    /// Ensures that the sources can be written as a valid apt source.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |why: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, why));
+
+        if self.mirror.is_empty() || self.mirror.contains(char::is_whitespace) {
+            return invalid("apt mirror must be a URI without whitespace");
+        }
+
+        if !self.mirror.contains("://") {
+            return invalid("apt mirror must be a URI, such as http://archive.ubuntu.com/ubuntu");
+        }
+
+        let invalid_component = self
+            .components
+            .iter()
+            .any(|component| component.is_empty() || component.contains(char::is_whitespace));
+
+        if invalid_component {
+            return invalid("apt components must not be empty or contain whitespace");
+        }
+
+        Ok(())
+    }
+
+    /// The suites of the release with the given codename which are enabled.
+    fn suites(&self, codename: &str) -> Vec<String> {
+        let mut suites = vec![codename.to_owned(), [codename, "-security"].concat()];
+        if self.updates {
+            suites.push([codename, "-updates"].concat());
+        }
+
+        if self.proposed {
+            suites.push([codename, "-proposed"].concat());
+        }
+
+        suites
+    }
+
+    fn components(&self) -> String {
+        if self.components.is_empty() {
+            DEFAULT_COMPONENTS.join(" ")
+        } else {
+            self.components.join(" ")
+        }
+    }
+
+    /// The sources as the one-line format of `/etc/apt/sources.list`.
+    pub(crate) fn sources_list(&self, codename: &str) -> String {
+        let components = self.components();
+        let mut list = String::from("# Written by the installer.\n");
+        for suite in self.suites(codename) {
+            list.push_str(&format!("deb {} {} {}\n", self.mirror, suite, components));
+        }
+
+        list
+    }
+
+    /// The sources as the deb822 format of `/etc/apt/sources.list.d/*.sources`.
+    pub(crate) fn deb822(&self, codename: &str) -> String {
+        format!(
+            "X-Repolib-Name: System Sources\nEnabled: yes\nTypes: deb\nURIs: {}\nSuites: {}\n\
+             Components: {}\n",
+            self.mirror,
+            self.suites(codename).join(" "),
+            self.components()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apt_source_formats() {
+        let sources = AptSources {
+            components: vec!["main".into(), "universe".into()],
+            proposed: true,
+            ..AptSources::new("http://mirror.example.org/ubuntu")
+        };
+
+        assert_eq!(
+            sources.sources_list("jammy"),
+            "# Written by the installer.\n\
+             deb http://mirror.example.org/ubuntu jammy main universe\n\
+             deb http://mirror.example.org/ubuntu jammy-security main universe\n\
+             deb http://mirror.example.org/ubuntu jammy-updates main universe\n\
+             deb http://mirror.example.org/ubuntu jammy-proposed main universe\n"
+        );
+
+        let sources = AptSources { updates: false, ..AptSources::new("http://mirror/ubuntu") };
+        assert_eq!(
+            sources.deb822("jammy"),
+            "X-Repolib-Name: System Sources\nEnabled: yes\nTypes: deb\n\
+             URIs: http://mirror/ubuntu\nSuites: jammy jammy-security\n\
+             Components: main restricted universe multiverse\n"
+        );
+
+        assert!(AptSources::new("mirror.example.org").validate().is_err());
+        assert!(AptSources::new("http://mirror/ubuntu").validate().is_ok());
+    }
+}
@@ -0,0 +1,80 @@
+//! Automatic login of the created user, through whichever display manager the image ships.
+
+use super::conf_files;
+use std::{fs, io, path::Path};
+
+/// The display managers which autologin may be configured for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum DisplayManager {
+    Gdm3,
+    LightDm,
+    Sddm,
+}
+
+impl DisplayManager {
+    const ALL: &'static [DisplayManager] =
+        &[DisplayManager::Gdm3, DisplayManager::LightDm, DisplayManager::Sddm];
+
+    fn binary(self) -> &'static str {
+        match self {
+            DisplayManager::Gdm3 => "usr/sbin/gdm3",
+            DisplayManager::LightDm => "usr/sbin/lightdm",
+            DisplayManager::Sddm => "usr/bin/sddm",
+        }
+    }
+
+    /// The display manager of the system at `root`: the default which Debian records, or else
+    /// the first which is installed.
+    pub(crate) fn detect(root: &Path) -> Option<DisplayManager> {
+        let default = fs::read_to_string(root.join("etc/X11/default-display-manager"))
+            .ok()
+            .and_then(|path| {
+                let path = path.trim().trim_start_matches('/').to_owned();
+                Self::ALL.iter().cloned().find(|dm| dm.binary() == path)
+            });
+
+        default.or_else(|| Self::ALL.iter().cloned().find(|dm| root.join(dm.binary()).exists()))
+    }
+
+    /// Writes the config which logs `user` in automatically.
+    pub(crate) fn autologin(self, root: &Path, user: &str) -> io::Result<()> {
+        let (path, section, keys) = match self {
+            DisplayManager::Gdm3 => (
+                "etc/gdm3/custom.conf",
+                "daemon",
+                vec![("AutomaticLoginEnable", "true"), ("AutomaticLogin", user)],
+            ),
+            DisplayManager::LightDm => (
+                "etc/lightdm/lightdm.conf.d/50-distinst-autologin.conf",
+                "Seat:*",
+                vec![("autologin-user", user), ("autologin-user-timeout", "0")],
+            ),
+            DisplayManager::Sddm => {
+                let session = sddm_session(root).unwrap_or_default();
+                let keys = vec![("User", user), ("Session", session.as_str())];
+                let path = root.join("etc/sddm.conf.d/distinst-autologin.conf");
+                return conf_files::update_ini(&path, "Autologin", &keys);
+            }
+        };
+
+        conf_files::update_ini(&root.join(path), section, &keys)
+    }
+}
+
+/// The session which SDDM logs into, which it requires for autologin: Plasma if it is
+/// installed, or else the first X11 session.
+fn sddm_session(root: &Path) -> Option<String> {
+    let sessions = root.join("usr/share/xsessions");
+    if sessions.join("plasma.desktop").exists() {
+        return Some("plasma".into());
+    }
+
+    let mut names: Vec<String> = fs::read_dir(&sessions)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| name.strip_suffix(".desktop").map(String::from))
+        .collect();
+
+    names.sort();
+    names.into_iter().next()
+}
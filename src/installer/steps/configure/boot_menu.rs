@@ -0,0 +1,160 @@
+//! The timeout and the default entry of the boot menu, which are written to the configuration
+//! of systemd-boot and of GRUB alike, whichever of them boots the install. A kiosk boots its
+//! only system at once, while a dual boot shows the menu long enough to choose another.
+//!
+//! GRUB's defaults are written before its menu is generated, which they are read by, while the
+//! `loader.conf` of systemd-boot is written once kernelstub has written it.
+
+use super::conf_files;
+use crate::misc;
+use std::{fs, io, path::Path, str::FromStr};
+
+/// Seconds for which the menu is shown when another system remains installed, unless a timeout
+/// was configured.
+pub const DUAL_BOOT_TIMEOUT: u32 = 5;
+
+const LOADER_CONF: &str = "boot/efi/loader/loader.conf";
+const GRUB_DEFAULTS: &str = "etc/default/grub";
+
+/// The entry of the boot menu which is booted once the timeout expires.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BootDefault {
+    /// The installed system, as the bootloader is configured by default.
+    Installed,
+    /// The entry which was booted last, such as Windows on a dual boot.
+    LastBooted,
+}
+
+impl Default for BootDefault {
+    fn default() -> Self { BootDefault::Installed }
+}
+
+impl FromStr for BootDefault {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "installed" => Ok(BootDefault::Installed),
+            "last-booted" => Ok(BootDefault::LastBooted),
+            _ => Err("invalid default boot entry"),
+        }
+    }
+}
+
+/// The boot menu of the installed system.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BootMenu {
+    /// Seconds for which the menu is shown, where `0` boots the default entry at once. If
+    /// `None`, the menu is shown for `DUAL_BOOT_TIMEOUT` seconds if another system remains
+    /// installed, and is otherwise left as the image configured it.
+    pub timeout: Option<u32>,
+    pub default: BootDefault,
+}
+
+impl BootMenu {
+    /// Writes the menu to the `loader.conf` of systemd-boot on the target at `root`, which
+    /// boots alongside another system if `dual_boot` is set.
+    pub(crate) fn configure_loader(&self, root: &Path, dual_boot: bool) -> io::Result<()> {
+        let loader = root.join(LOADER_CONF);
+        if !loader.exists() {
+            return Ok(());
+        }
+
+        let timeout = self.timeout(dual_boot);
+        let mut keys = Vec::new();
+        keys.extend(timeout.as_deref().map(|timeout| ("timeout", timeout)));
+        if self.default == BootDefault::LastBooted {
+            keys.push(("default", "@saved"));
+        }
+
+        if !keys.is_empty() {
+            info!("setting {:?} in /{}", keys, LOADER_CONF);
+            misc::write(&loader, set_loader_keys(&fs::read_to_string(&loader)?, &keys))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the menu to the defaults of GRUB on the target at `root`, which boots alongside
+    /// another system if `dual_boot` is set.
+    pub(crate) fn configure_grub(&self, root: &Path, dual_boot: bool) -> io::Result<()> {
+        let grub = root.join(GRUB_DEFAULTS);
+        if !grub.exists() {
+            return Ok(());
+        }
+
+        let timeout = self.timeout(dual_boot);
+        let mut vars = Vec::new();
+        if let Some(ref timeout) = timeout {
+            let style = if timeout == "0" { "hidden" } else { "menu" };
+            vars.push(("GRUB_TIMEOUT", timeout.as_str()));
+            vars.push(("GRUB_TIMEOUT_STYLE", style));
+        }
+
+        if self.default == BootDefault::LastBooted {
+            vars.push(("GRUB_DEFAULT", "saved"));
+            vars.push(("GRUB_SAVEDEFAULT", "true"));
+        }
+
+        if !vars.is_empty() {
+            info!("setting {:?} in /{}", vars, GRUB_DEFAULTS);
+            conf_files::update_vars(&grub, &vars)?;
+        }
+
+        Ok(())
+    }
+
+    fn timeout(&self, dual_boot: bool) -> Option<String> {
+        let timeout = self.timeout.or(if dual_boot { Some(DUAL_BOOT_TIMEOUT) } else { None });
+        timeout.map(|timeout| timeout.to_string())
+    }
+}
+
+/// Sets the keys of systemd-boot's `loader.conf`, whose lines are a key and its value, separated
+/// by whitespace. Keys which are commented out, as `bootctl` writes `#timeout 3`, are replaced.
+fn set_loader_keys(config: &str, keys: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(config.len() + 32);
+    let mut written = vec![false; keys.len()];
+
+    for line in config.lines() {
+        let key = line.trim_start_matches('#').split_whitespace().next().unwrap_or("");
+        match keys.iter().position(|&(wanted, _)| wanted == key) {
+            Some(index) if !written[index] => {
+                written[index] = true;
+                output.push_str(&[keys[index].0, " ", keys[index].1].concat());
+            }
+            Some(_) => continue,
+            None => output.push_str(line),
+        }
+
+        output.push('\n');
+    }
+
+    for (&(key, value), _) in keys.iter().zip(written).filter(|&(_, written)| !written) {
+        output.push_str(&[key, " ", value, "\n"].concat());
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loader_conf_keys() {
+        let conf = "#timeout 3\ndefault Pop_OS-current\n";
+        assert_eq!(
+            set_loader_keys(conf, &[("timeout", "0")]),
+            "timeout 0\ndefault Pop_OS-current\n"
+        );
+        assert_eq!(
+            set_loader_keys(conf, &[("default", "@saved"), ("console-mode", "max")]),
+            "#timeout 3\ndefault @saved\nconsole-mode max\n"
+        );
+        assert_eq!(
+            set_loader_keys("timeout 5\ntimeout 10\n", &[("timeout", "5")]),
+            "timeout 5\n"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Vendor branding of the installed system, so that system builders may brand a stock image
+//! for each of their customers, rather than building an image for each of them.
+
+use crate::misc;
+use os_release::OsReleaseBuilder;
+use std::{
+    ffi::CString,
+    fs::{self, Permissions},
+    io,
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+pub(crate) const DCONF_PROFILE: &str = "etc/dconf/profile/user";
+const DCONF_DEFAULTS: &str = "etc/dconf/db/local.d/50-vendor";
+const DCONF_WALLPAPER: &str = "etc/dconf/db/local.d/40-vendor-wallpaper";
+const WALLPAPERS: &str = "usr/share/backgrounds/vendor";
+const EULA: &str = "usr/share/oem/EULA.txt";
+
+/// Vendor-provided files and information which are installed into the target.
+#[derive(Debug, Clone, Default)]
+pub struct Branding {
+    /// Written to `/etc/os-release` as `VENDOR_NAME`.
+    pub vendor_name: Option<String>,
+    /// Written to `/etc/os-release` as `VENDOR_URL`.
+    pub vendor_url:  Option<String>,
+    /// A dconf keyfile of settings, which become the defaults of every user.
+    pub dconf:       Option<PathBuf>,
+    /// An image which becomes the default wallpaper.
+    pub wallpaper:   Option<PathBuf>,
+    /// The text of the vendor's license agreement, which is installed to
+    /// `/usr/share/oem/EULA.txt`.
+    pub eula:        Option<PathBuf>,
+}
+
+impl Branding {
+    /// Ensures that the vendor's files exist before the install begins.
+    pub fn validate(&self) -> io::Result<()> {
+        let files = [&self.dconf, &self.wallpaper, &self.eula];
+        for file in files.iter().filter_map(|file| file.as_ref()) {
+            if !file.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("branding file {} was not found", file.display()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Installs the vendor's files into the target at `root`, owned by root and readable by all.
+///
+/// Returns `true` if the dconf database must be updated.
+pub(crate) fn install(root: &Path, branding: &Branding) -> io::Result<bool> {
+    if branding.vendor_name.is_some() || branding.vendor_url.is_some() {
+        info!("writing vendor information to /etc/os-release");
+        // Commonly a link to `/usr/lib/os-release`, which must not resolve to the host's.
+        let path = os_release::path_in_root(root);
+        set_vendor_fields(OsReleaseBuilder::open(&path)?, branding).write(&path)?;
+    }
+
+    if let Some(ref eula) = branding.eula {
+        install_file(eula, &root.join(EULA))?;
+    }
+
+    let mut dconf = false;
+
+    if let Some(ref keyfile) = branding.dconf {
+        install_file(keyfile, &root.join(DCONF_DEFAULTS))?;
+        dconf = true;
+    }
+
+    if let Some(ref wallpaper) = branding.wallpaper {
+        let name = wallpaper.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "wallpaper path does not name a file")
+        })?;
+
+        let target = Path::new(WALLPAPERS).join(name);
+        install_file(wallpaper, &root.join(&target))?;
+        let keyfile = wallpaper_keyfile(&Path::new("/").join(&target));
+        write_file(&root.join(DCONF_WALLPAPER), &keyfile)?;
+        dconf = true;
+    }
+
+    if dconf {
+        enable_system_db(&root.join(DCONF_PROFILE))?;
+    }
+
+    Ok(dconf)
+}
+
+/// Ensures that the user profile of dconf reads the local system database.
+pub(crate) fn enable_system_db(profile: &Path) -> io::Result<()> {
+    let contents = match fs::read_to_string(profile) {
+        Ok(contents) => contents,
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => "user-db:user\n".into(),
+        Err(why) => return Err(why),
+    };
+
+    if contents.lines().any(|line| line.trim() == "system-db:local") {
+        return Ok(());
+    }
+
+    let mut contents = contents;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str("system-db:local\n");
+    write_file(profile, &contents)
+}
+
+/// Sets the `VENDOR_NAME` and `VENDOR_URL` fields of an os-release file.
+fn set_vendor_fields(os_release: OsReleaseBuilder, branding: &Branding) -> OsReleaseBuilder {
+    let fields = [("VENDOR_NAME", &branding.vendor_name), ("VENDOR_URL", &branding.vendor_url)];
+    fields.iter().fold(os_release, |os_release, &(key, value)| match value {
+        Some(value) => os_release.field(key, value),
+        None => os_release,
+    })
+}
+
+fn wallpaper_keyfile(wallpaper: &Path) -> String {
+    let uri = ["file://", &wallpaper.to_string_lossy()].concat();
+    format!(
+        "[org/gnome/desktop/background]\npicture-uri='{0}'\npicture-uri-dark='{0}'\n\n\
+         [org/gnome/desktop/screensaver]\npicture-uri='{0}'\n",
+        uri
+    )
+}
+
+fn install_file(source: &Path, target: &Path) -> io::Result<()> {
+    info!("installing {} to {}", source.display(), target.display());
+    create_parent(target)?;
+    misc::cp(source, target)?;
+    set_root_owned(target)
+}
+
+pub(crate) fn write_file(target: &Path, contents: &str) -> io::Result<()> {
+    create_parent(target)?;
+    misc::write(target, contents)?;
+    set_root_owned(target)
+}
+
+fn create_parent(target: &Path) -> io::Result<()> {
+    fs::create_dir_all(target.parent().expect("target without parent"))
+}
+
+fn set_root_owned(path: &Path) -> io::Result<()> {
+    fs::set_permissions(path, Permissions::from_mode(0o644))?;
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::chown(cpath.as_ptr(), 0, 0) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vendor_fields() {
+        let branding = Branding {
+            vendor_name: Some("Acme \"Systems\"".into()),
+            vendor_url:  Some("https://acme.example/".into()),
+            ..Branding::default()
+        };
+
+        let os_release = "NAME=\"Pop!_OS\"\nVERSION_ID=\"22.04\"\nVENDOR_NAME=\"System76\"\n";
+        assert_eq!(
+            set_vendor_fields(OsReleaseBuilder::new(os_release), &branding).build().unwrap(),
+            "NAME=\"Pop!_OS\"\nVERSION_ID=\"22.04\"\nVENDOR_NAME=\"Acme \\\"Systems\\\"\"\n\
+             VENDOR_URL=\"https://acme.example/\"\n"
+        );
+    }
+}
@@ -1,17 +1,38 @@
 use crate::chroot::{Chroot, Command};
 use crate::errors::{IoContext, IntoIoResult};
 use crate::misc;
+use envfile::EnvFile;
 use partition_identity::PartitionID;
 use proc_mounts::MountList;
+use super::apt_sources::AptSources;
+use super::autologin::DisplayManager;
+use super::branding::{self, Branding};
+use super::clock::{self, ADJTIME, TIMESYNCD_CONF};
+use super::cloud_init::CloudInit;
+use super::console::{self, ConsoleSettings};
+use super::hidpi;
+use super::keyboard::{self, Keymap};
+use super::live_settings;
+use super::directory::DirectoryEnrollment;
+use super::network::NetworkConfig;
+use super::oem_packages::{self, OemPackage};
+use super::server::{FirewallPolicy, SshServer};
+use super::users::{self, SudoPolicy};
+use super::snapshots::{
+    timeshift_config, SnapshotTool, SNAPPER_SETTINGS, TIMESHIFT_CONFIG, TIMESHIFT_CRON,
+    TIMESHIFT_CRON_JOB,
+};
 use std::{
     fs,
     io::{self, Write},
-    path::Path,
+    iter,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
     process::Stdio,
 };
 use sys_mount::*;
 use crate::timezones::Region;
-use crate::Config;
+use crate::{Config, UserAccountCreate, IMAGE_MODE};
 
 const APT_OPTIONS: &[&str] = &[
     "-o",
@@ -28,6 +49,66 @@ const BOOT_OPTIONS: &str = "quiet loglevel=0 systemd.show_status=false splash";
 // For a reliable boot when using recovery, we show all output and do not use plymouth
 const RECOVERY_BOOT_OPTIONS: &str = "";
 
+const SOURCES_LIST: &str = "etc/apt/sources.list";
+const SYSTEM_SOURCES: &str = "etc/apt/sources.list.d/system.sources";
+
+const UFW_CONF: &str = "etc/ufw/ufw.conf";
+const NETPLAN_CONF: &str = "etc/netplan/90-installer.yaml";
+
+const SSH_KEYS_SERVICE: &str = "distinst-regenerate-ssh-keys.service";
+const SWAP_UUID_SERVICE: &str = "distinst-regenerate-swap-uuid.service";
+const SWAP_UUID_SCRIPT: &str = "usr/lib/distinst/regenerate-swap-uuid";
+const REALM_JOIN_SERVICE: &str = "distinst-realm-join.service";
+const REALM_JOIN_SCRIPT: &str = "usr/lib/distinst/realm-join";
+const REALM_JOIN_CREDENTIALS: &str = "etc/distinst/realm-join";
+
+// Host keys are generated once the machine ID has been committed on first boot.
+const SSH_KEYS_UNIT: &str = "[Unit]
+Description=Regenerate SSH host keys
+ConditionFirstBoot=yes
+ConditionPathExists=/usr/bin/ssh-keygen
+Before=ssh.service
+
+[Service]
+Type=oneshot
+ExecStart=/usr/bin/ssh-keygen -A
+
+[Install]
+WantedBy=multi-user.target
+";
+
+// Swap UUIDs must be changed before crypttab is processed, and before swap is enabled.
+const SWAP_UUID_UNIT: &str = "[Unit]
+Description=Regenerate swap UUIDs
+DefaultDependencies=no
+ConditionFirstBoot=yes
+After=systemd-remount-fs.service
+Before=cryptsetup-pre.target swap.target
+Wants=cryptsetup-pre.target
+
+[Service]
+Type=oneshot
+ExecStart=/usr/lib/distinst/regenerate-swap-uuid
+
+[Install]
+WantedBy=sysinit.target
+";
+
+// Retried on each boot until the join succeeds and the credentials are removed.
+const REALM_JOIN_UNIT: &str = "[Unit]
+Description=Join the directory domain given at install time
+ConditionPathExists=/etc/distinst/realm-join
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=oneshot
+ExecStart=/usr/lib/distinst/realm-join
+
+[Install]
+WantedBy=multi-user.target
+";
+
 pub struct ChrootConfigurator<'a> {
     chroot: Chroot<'a>,
 }
@@ -52,6 +133,28 @@ impl<'a> ChrootConfigurator<'a> {
         command.run()
     }
 
+    /// Replaces the sources of the distribution's archive which the image carried. Images
+    /// which describe their archive in the deb822 format are written in that format.
+    pub fn apt_sources(&self, sources: &AptSources, codename: &str) -> io::Result<()> {
+        if codename.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the image's os-release does not define VERSION_CODENAME",
+            ));
+        }
+
+        let list = self.chroot.path.join(SOURCES_LIST);
+        let system = self.chroot.path.join(SYSTEM_SOURCES);
+        if system.exists() {
+            info!("writing apt sources for {} to /{}", sources.mirror, SYSTEM_SOURCES);
+            misc::write(&system, sources.deb822(codename))?;
+            misc::write(&list, "")
+        } else {
+            info!("writing apt sources for {} to /{}", sources.mirror, SOURCES_LIST);
+            misc::write(&list, sources.sources_list(codename))
+        }
+    }
+
     /// Remove the given packages from the system, if they are installed.
     pub fn apt_remove(&self, packages: &[&str]) -> io::Result<()> {
         info!("removing packages: {:?}", packages);
@@ -68,30 +171,88 @@ impl<'a> ChrootConfigurator<'a> {
         self.chroot.command("apt-get", &["autoremove", "-y", "--purge"]).run()
     }
 
-    /// Configure the bootloader on the system.
-    pub fn bootloader(&self) -> io::Result<()> {
+    /// Installs the vendor's branding, and rebuilds the dconf database if it was extended.
+    pub fn branding(&self, branding: &Branding) -> io::Result<()> {
+        if branding::install(&self.chroot.path, branding)? {
+            info!("updating the dconf database");
+            self.chroot.command("dconf", &["update"]).run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the font of the consoles, the state of numlock at boot, and the serial getty.
+    pub fn console(&self, settings: &ConsoleSettings) -> io::Result<()> {
+        let dconf = console::configure(&self.chroot.path, settings)?;
+
+        if settings.numlock.is_some() {
+            self.chroot.command("systemctl", &["enable", "numlock.service"]).run()?;
+        }
+
+        if let Some(ref serial) = settings.serial {
+            info!("enabling a getty on {}, at {} BAUD", serial.port, serial.baud);
+            self.chroot.command("systemctl", &["enable", &serial.getty()]).run()?;
+        }
+
+        if dconf {
+            info!("updating the dconf database");
+            self.chroot.command("dconf", &["update"]).run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Scales the desktop and the login screen by `factor` by default.
+    pub fn hidpi(&self, factor: u32) -> io::Result<()> {
+        if hidpi::configure(&self.chroot.path, factor)? {
+            info!("updating the dconf database");
+            self.chroot.command("dconf", &["update"]).run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Configure the bootloader on the system, which boots the `kernel` release, if one was
+    /// selected, or else the newest kernel, with the kernel `options` in addition to the
+    /// defaults.
+    pub fn bootloader(&self, kernel: Option<&str>, options: Option<&str>) -> io::Result<()> {
         info!("configuring bootloader");
-        let result = self
-            .chroot
-            .command(
-                "kernelstub",
-                &[
-                    "--esp-path",
-                    "/boot/efi",
-                    "--add-options",
-                    BOOT_OPTIONS,
-                    "--loader",
-                    "--manage-only",
-                    "--force-update",
-                    "--verbose",
-                ],
-            )
-            .run();
+        let boot_options = match options {
+            Some(options) => [BOOT_OPTIONS, " ", options].concat(),
+            None => BOOT_OPTIONS.to_owned(),
+        };
+
+        let mut args: Vec<String> = [
+            "--esp-path",
+            "/boot/efi",
+            "--add-options",
+            &boot_options,
+            "--loader",
+            "--manage-only",
+            "--force-update",
+            "--verbose",
+        ]
+        .iter()
+        .map(|&arg| arg.to_owned())
+        .collect();
+
+        if let Some(release) = kernel {
+            args.push("--kernel-path".into());
+            args.push(["/boot/vmlinuz-", release].concat());
+            args.push("--initrd-path".into());
+            args.push(["/boot/initrd.img-", release].concat());
+        }
+
+        let result = self.chroot.command("kernelstub", &args).run();
 
         match result {
             Ok(()) => Ok(()),
             // If kernelstub was not found, use grub instead.
             Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                if let Some(options) = options {
+                    self.grub_options(options)?;
+                }
+
                 let args: &[&str] = &[];
                 self.chroot.command("update-grub", args).run()
             }
@@ -99,6 +260,41 @@ impl<'a> ChrootConfigurator<'a> {
         }
     }
 
+    /// Appends `options` to the kernel command line of each of GRUB's entries, including those
+    /// of recovery, and shows GRUB's menu on the serial console which they give, if any.
+    fn grub_options(&self, options: &str) -> io::Result<()> {
+        let path = self.chroot.path.join("etc/default/grub");
+        let mut grub = EnvFile::new(&path)
+            .with_context(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+        let mut cmdline = grub.get("GRUB_CMDLINE_LINUX").unwrap_or("").trim().to_owned();
+        let missing: Vec<&str> = options
+            .split_whitespace()
+            .filter(|option| !cmdline.split_whitespace().any(|existing| existing == *option))
+            .collect();
+
+        if !missing.is_empty() {
+            info!("adding {:?} to the kernel options of GRUB", missing);
+            for option in missing {
+                if !cmdline.is_empty() {
+                    cmdline.push(' ');
+                }
+
+                cmdline.push_str(option);
+            }
+
+            grub.update("GRUB_CMDLINE_LINUX", &cmdline);
+        }
+
+        if let Some(serial) = console::grub_serial_command(options) {
+            info!("showing the menu of GRUB on the serial console: {}", serial);
+            grub.update("GRUB_TERMINAL", "console serial");
+            grub.update("GRUB_SERIAL_COMMAND", &serial);
+        }
+
+        grub.write()
+    }
+
     /// Add the apt repository on the image, so that packages may be installed from it.
     pub fn cdrom_add(&self) -> io::Result<()> {
         if Path::new("/cdrom").exists() {
@@ -161,40 +357,53 @@ impl<'a> ChrootConfigurator<'a> {
         }
     }
 
-    /// Create a new user account.
-    pub fn create_user(
-        &self,
-        user: &str,
-        pass: Option<&str>,
-        fullname: Option<&str>,
-        profile_icon: Option<&str>,
-    ) -> io::Result<()> {
+    /// Create a new user account, whose password is set from its crypt hash.
+    pub fn create_user(&self, account: &UserAccountCreate, hash: Option<&str>) -> io::Result<()> {
+        account.validate()?;
+        let user = account.username.as_str();
+
+        // Each group must already be defined by the install, as useradd will not create them.
+        let groups = users::user_groups(account.groups.as_deref(), account.sudo);
+        let group_file = fs::read_to_string(self.chroot.path.join("etc/group"))?;
+        let defined: Vec<&str> = users::defined_groups(&group_file).collect();
+        if let Some(missing) = groups.iter().find(|group| !defined.contains(&group.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("group {} is not defined by the install", missing),
+            ));
+        }
+
         // Add the user to the system.
         {
-            const DEFAULT_USERADD_FLAGS: &[&str] = &[
-                "-m",
-                "-G", "adm,sudo,lpadmin",
-                "-s", "/bin/bash"
-            ];
+            let groups = groups.join(",");
+            let mut command = self.chroot.command("useradd", &["-m", "-s", "/bin/bash"]);
+            command.args(&["-G", groups.as_str()]);
 
-            let mut command = self.chroot.command("useradd", DEFAULT_USERADD_FLAGS);
+            if let Some(ref name) = account.realname {
+                command.args(&["-c", name.as_str()]);
+            }
 
-            if let Some(name) = fullname {
-                command.args(&["-c", name]);
+            if let Some(ref date) = account.expires {
+                command.args(&["-e", date.as_str()]);
             }
 
             command.arg(user).run()?;
         }
 
-        // Set the password for the newly-created user.
-        if let Some(pass) = pass {
-            let pass = &[pass, "\n", pass, "\n"].concat();
-            self.chroot.command("passwd", &[user]).stdin_input(pass).run()?;
+        if account.sudo == SudoPolicy::NoPassword {
+            self.sudo_nopasswd(user)?;
+        }
+
+        // Set the password for the newly-created user. The hash is given on the standard input,
+        // so that it is not exposed in the arguments of the process.
+        if let Some(hash) = hash {
+            let entry = &[user, ":", hash, "\n"].concat();
+            self.chroot.command("chpasswd", &["-e"]).stdin_input(entry).run()?;
         }
 
         // Copy the profile icon to `/var/lib/AccountsService/icons/{user}` and assign that in
         // the config file at `/var/lib/AccountsService/users/{user}`.
-        if let Some(path) = profile_icon {
+        if let Some(ref path) = account.profile_icon {
             let mut dest = self.chroot.path.join(&["var/lib/AccountsService/icons/", user].concat());
 
             if fs::copy(&path, &dest).is_err() {
@@ -216,6 +425,90 @@ impl<'a> ChrootConfigurator<'a> {
         Ok(())
     }
 
+    /// Creates the XDG user directories of `user`, such as `~/Documents`, named in the language
+    /// of `lang`, and records them in `~/.config/user-dirs.dirs`. A home which was restored
+    /// with its own `user-dirs.dirs` keeps the names that it already has.
+    pub fn user_dirs(&self, user: &str, lang: &str) -> io::Result<()> {
+        if !self.chroot.path.join("usr/bin/xdg-user-dirs-update").exists() {
+            return Ok(());
+        }
+
+        info!("creating the user directories of {} for {}", user, lang);
+        let home = ["HOME=/home/", user].concat();
+        let language = ["LANGUAGE=", lang.split('.').next().unwrap_or(lang)].concat();
+        let lang = ["LANG=", lang].concat();
+
+        // The environment of the user is given, as the directories are named by it.
+        self.chroot
+            .command("runuser", &["-u", user, "--", "env", "-i"])
+            .args(&[home.as_str(), lang.as_str(), language.as_str()])
+            .arg("/usr/bin/xdg-user-dirs-update")
+            .run()
+    }
+
+    /// Carries the desktop settings which were changed in the live session of the user whose
+    /// home is `live_home` over to `user`. The database of the live user is read by the dconf of
+    /// the install, so that it is compiled by the same version which the new user will run.
+    pub fn live_settings(&self, live_home: &Path, user: &str) -> io::Result<()> {
+        let root = &self.chroot.path;
+        if !root.join("usr/bin/dconf").exists() {
+            return Ok(());
+        }
+
+        info!("copying the settings of the live session to {}", user);
+        let staging = ["/", live_settings::STAGING].concat();
+        let result = (|| -> io::Result<()> {
+            let mut copied = live_settings::copy_monitors(live_home, root, user)?;
+
+            if live_settings::stage(live_home, root)? {
+                let profile = ["DCONF_PROFILE=", &staging, "/profile"].concat();
+                let config = ["XDG_CONFIG_HOME=", &staging].concat();
+                let dump = self
+                    .chroot
+                    .command("env", &[profile.as_str(), config.as_str(), "dconf", "dump", "/"])
+                    .run_with_stdout()?;
+
+                if live_settings::write_keyfile(root, &dump)? {
+                    let database = live_settings::user_database(user);
+                    let keyfiles = [staging.as_str(), "/keyfiles"].concat();
+                    fs::create_dir_all(root.join("home").join(user).join(".config/dconf"))?;
+
+                    self.chroot.command("dconf", &["compile", &database, &keyfiles]).run()?;
+                    copied = true;
+                }
+            }
+
+            if copied {
+                let config = ["/home/", user, "/.config"].concat();
+                let owner = [user, ":"].concat();
+                self.chroot.command("chown", &["-R", owner.as_str(), config.as_str()]).run()?;
+            }
+
+            Ok(())
+        })();
+
+        let _ = fs::remove_dir_all(root.join(live_settings::STAGING));
+        result
+    }
+
+    /// Admits the user to run any command through sudo without a password. The rule is checked
+    /// with visudo, as a rule which sudo could not parse would leave it unusable.
+    fn sudo_nopasswd(&self, user: &str) -> io::Result<()> {
+        info!("allowing {} to use sudo without a password", user);
+        let rule = ["etc/sudoers.d/", user].concat();
+        let path = self.chroot.path.join(&rule);
+        misc::write(&path, users::sudoers_nopasswd(user))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o440))?;
+
+        let target = ["/", &rule].concat();
+        let checked = self.chroot.command("visudo", &["-cf", target.as_str()]).run();
+        if checked.is_err() {
+            let _ = fs::remove_file(&path);
+        }
+
+        checked.with_context(|err| format!("sudoers rule for {} is not valid: {}", user, err))
+    }
+
     /// Disable the nvidia fallback service.
     pub fn disable_nvidia_fallback(&self) {
         info!("attempting to disable nvidia-fallback.service");
@@ -239,6 +532,124 @@ impl<'a> ChrootConfigurator<'a> {
         self.chroot.command("ln", &["-sf", "/etc/machine-id", "/var/lib/dbus/machine-id"]).run()
     }
 
+    /// Leave the machine ID empty, so that systemd generates it on first boot.
+    pub fn defer_machine_id(&self) -> io::Result<()> {
+        info!("deferring machine id generation to first boot");
+        misc::write(self.chroot.path.join("etc/machine-id"), "")?;
+        self.chroot.command("ln", &["-sf", "/etc/machine-id", "/var/lib/dbus/machine-id"]).run()
+    }
+
+    /// Remove the SSH host keys, and schedule them and the swap UUIDs to be regenerated on
+    /// first boot, so that clones of this install will not share them.
+    pub fn defer_host_keys(&self) -> io::Result<()> {
+        info!("deferring SSH host key and swap UUID generation to first boot");
+        if let Ok(entries) = self.chroot.path.join("etc/ssh").read_dir() {
+            for entry in entries.filter_map(Result::ok) {
+                if entry.file_name().to_string_lossy().starts_with("ssh_host_") {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+
+        let script = self.chroot.path.join(SWAP_UUID_SCRIPT);
+        fs::create_dir_all(script.parent().expect("script without parent"))?;
+        misc::write(&script, &include_bytes!("../../../scripts/regenerate-swap-uuid.sh")[..])?;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+
+        let system = self.chroot.path.join("etc/systemd/system");
+        misc::write(system.join(SSH_KEYS_SERVICE), SSH_KEYS_UNIT)?;
+        misc::write(system.join(SWAP_UUID_SERVICE), SWAP_UUID_UNIT)?;
+
+        self.chroot.command("systemctl", &["enable", SSH_KEYS_SERVICE, SWAP_UUID_SERVICE]).run()
+    }
+
+    /// Stages the system to join a directory domain once its network is online, and creates
+    /// the homes of the domain's users when they first log in.
+    pub fn directory(&self, enrollment: &DirectoryEnrollment) -> io::Result<()> {
+        info!("staging a join of the {} domain", enrollment.domain);
+        let credentials = self.chroot.path.join(REALM_JOIN_CREDENTIALS);
+        let dir = credentials.parent().expect("credentials without parent");
+        fs::create_dir_all(dir)?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+
+        let mut file = misc::create_private(&credentials)?;
+        file.write_all(enrollment.join_env().as_bytes())?;
+        file.sync_all()?;
+
+        let script = self.chroot.path.join(REALM_JOIN_SCRIPT);
+        fs::create_dir_all(script.parent().expect("script without parent"))?;
+        misc::write(&script, &include_bytes!("../../../scripts/realm-join.sh")[..])?;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+
+        let unit = self.chroot.path.join("etc/systemd/system").join(REALM_JOIN_SERVICE);
+        misc::write(unit, REALM_JOIN_UNIT)?;
+        self.chroot.command("pam-auth-update", &["--enable", "mkhomedir"]).run()?;
+        self.chroot.command("systemctl", &["enable", REALM_JOIN_SERVICE]).run()
+    }
+
+    /// Configures the servers that the clock is synchronized with, if any were given, and
+    /// whether the hardware clock is kept in local time, if that was decided.
+    pub fn clock(&self, ntp_servers: &[String], local_rtc: Option<bool>) -> io::Result<()> {
+        if !ntp_servers.is_empty() {
+            info!("synchronizing the clock with {:?}", ntp_servers);
+            let conf = self.chroot.path.join(TIMESYNCD_CONF);
+            fs::create_dir_all(conf.parent().expect("timesyncd config without parent"))?;
+            misc::write(&conf, clock::timesyncd_conf(ntp_servers))?;
+        }
+
+        if let Some(local) = local_rtc {
+            info!("keeping the hardware clock in {}", if local { "local time" } else { "UTC" });
+            misc::write(self.chroot.path.join(ADJTIME), clock::adjtime(local))?;
+        }
+
+        Ok(())
+    }
+
+    /// Enables ufw with the given policy towards incoming connections, and admits SSH
+    /// connections if `allow_ssh` is set.
+    pub fn firewall(&self, policy: FirewallPolicy, allow_ssh: bool) -> io::Result<()> {
+        info!("enabling ufw, which will {} incoming connections", policy.as_str());
+        self.chroot.command("ufw", &["default", policy.as_str(), "incoming"]).run()?;
+        if allow_ssh {
+            self.chroot.command("ufw", &["allow", "22/tcp"]).run()?;
+        }
+
+        // `ufw enable` would load the rules into the kernel of the live system, so ufw is
+        // instead enabled for the first boot of the new install.
+        misc::sed(&self.chroot.path.join(UFW_CONF), "s/^ENABLED=.*/ENABLED=yes/")?;
+        self.chroot.command("systemctl", &["enable", "ufw.service"]).run()
+    }
+
+    /// Writes the static network configuration as netplan, which is rendered by
+    /// NetworkManager if the image has it, and by systemd-networkd otherwise. Images without
+    /// netplan are left to configure their network as they would.
+    pub fn network(&self, network: &NetworkConfig) -> io::Result<()> {
+        let root = &self.chroot.path;
+        if !root.join("usr/sbin/netplan").exists() {
+            warn!("netplan is not installed in the image, so the network was not configured");
+            return Ok(());
+        }
+
+        let renderer = if root.join("usr/sbin/NetworkManager").exists() {
+            "NetworkManager"
+        } else {
+            "networkd"
+        };
+
+        info!("writing the network configuration to /{}, rendered by {}", NETPLAN_CONF, renderer);
+        let conf = root.join(NETPLAN_CONF);
+        fs::create_dir_all(conf.parent().expect("netplan config without parent"))?;
+        misc::write(&conf, network.netplan(renderer))?;
+        // The configuration may hold the addresses of private networks.
+        fs::set_permissions(&conf, fs::Permissions::from_mode(0o600))?;
+
+        if renderer == "networkd" {
+            self.chroot.command("systemctl", &["enable", "systemd-networkd.service"]).run()?;
+        }
+
+        Ok(())
+    }
+
     /// Set the hostname of the new install.
     pub fn hostname(&self, hostname: &str) -> io::Result<()> {
         info!("setting hostname to {}", hostname);
@@ -294,18 +705,25 @@ impl<'a> ChrootConfigurator<'a> {
             Mount::new(&self.chroot.path.join("etc"), "/etc", "none", MountFlags::BIND, None)?
                 .into_unmount_drop(UnmountFlags::DETACH);
 
+        let keymap = Keymap::of(config);
+        let (layouts, variants) = (keymap.layout_list(), keymap.variant_list());
         self.chroot
             .command(
                 "localectl",
-                &[
-                    "set-x11-keymap",
-                    &config.keyboard_layout,
-                    config.keyboard_model.as_deref().unwrap_or(""),
-                    config.keyboard_variant.as_deref().unwrap_or(""),
-                ],
+                &["set-x11-keymap", &layouts, keymap.model, &variants, keymap.options],
             )
             .run()?;
 
+        // localectl does not write the console's config on every system, and the initramfs
+        // needs the keymap to prompt for passphrases with the layout.
+        keyboard::write_layout(&self.chroot.path, &keymap)?;
+
+        // GNOME keeps its own list of layouts, which it does not take from the XKB config.
+        if keyboard::write_input_sources(&self.chroot.path, &keymap)? {
+            info!("updating the dconf database");
+            self.chroot.command("dconf", &["update"]).run()?;
+        }
+
         self.chroot
             .command(
                 "/usr/bin/env",
@@ -391,7 +809,7 @@ impl<'a> ChrootConfigurator<'a> {
             return Ok(());
         }
 
-        let mounts = MountList::new()?;
+        let mounts = MountList::new_from_file(misc::namespace::THREAD_MOUNTS)?;
         let recovery_mount = mounts
             .get_mount_by_dest(&recovery_path)
             .into_io_result(|| "/recovery is mount not associated with block device")?;
@@ -443,16 +861,17 @@ impl<'a> ChrootConfigurator<'a> {
         let casper = ["casper-", &recovery_uuid.id].concat();
         let recovery = ["Recovery-", &recovery_uuid.id].concat();
         if recovery_uuid.id != cdrom_uuid {
-            self.chroot
-                .command(
-                    "rsync",
-                    &["-KLavc", "--delete-before", "/cdrom/.disk", "/cdrom/dists", "/cdrom/pool", "/recovery"],
-                )
-                .run()?;
-
-            self.chroot
-                .command("rsync", &["-KLavc", casper_data, &["/recovery/", &casper].concat()])
-                .run()?;
+            let recovery_dir = self.chroot.path.join("recovery");
+            let copies = [".disk", "dists", "pool"]
+                .iter()
+                .map(|dir| (Path::new("/cdrom").join(dir), recovery_dir.join(dir)))
+                .chain(iter::once((PathBuf::from(casper_data), recovery_dir.join(&casper))));
+
+            for (source, target) in copies {
+                misc::copy::sync_tree(&source, &target).with_context(|err| {
+                    format!("failed to copy {:?} to the recovery partition: {}", source, err)
+                })?;
+            }
         }
 
         // Create recovery file.
@@ -481,9 +900,7 @@ OEM_MODE=0
 
         // Copy initrd and vmlinuz to EFI partition
         let recovery_path = self.chroot.path.join("recovery/recovery.conf");
-        let mut recovery_file = misc::create(&recovery_path)?;
-        recovery_file
-            .write_all(recovery_data.as_bytes())
+        envfile::write_atomic(&recovery_path, recovery_data.as_bytes())
             .with_context(|err| format!("failed to write recovery file: {}", err))?;
 
         let efi_recovery = ["boot/efi/EFI/", recovery.as_str()].concat();
@@ -499,7 +916,9 @@ OEM_MODE=0
         // If the NVIDIA DKMS driver is installed, force it to load in the recovery partition
         // This test must not use /proc or /sys for detection since the installer can run inside a
         // chroot where those come from the host environment.
-        let has_nvidia = Path::new("/var/lib/dkms/nvidia").exists();
+        // Images are not tied to the hardware that they were installed from.
+        let has_nvidia = config.flags & IMAGE_MODE == 0
+            && Path::new("/var/lib/dkms/nvidia").exists();
 
         let rec_entry_data = format!(
             r#"title {0} recovery
@@ -528,6 +947,145 @@ options {2} boot=casper hostname=recovery userfullname=Recovery username=recover
         Ok(())
     }
 
+    /// Installs each of the vendor's packages in `dir`, along with their dependencies, after
+    /// copying them to `staging` within the chroot. A package which cannot be installed is
+    /// reported, rather than failing the install.
+    pub fn oem_packages(&self, dir: &Path, staging: &Path) -> io::Result<Vec<OemPackage>> {
+        let debs = oem_packages::debs(dir)
+            .with_context(|why| format!("unable to read {}: {}", dir.display(), why))?;
+
+        let staging = staging.join("oem");
+        fs::create_dir_all(&staging)?;
+
+        let mut packages = Vec::with_capacity(debs.len());
+        for deb in debs {
+            let name = deb.file_name().expect("deb without file name").to_string_lossy();
+            let target = staging.join(&*name);
+            misc::cp(&deb, &target)?;
+
+            let path = Path::new("/").join(
+                target.strip_prefix(&self.chroot.path).expect("staging is not within the chroot"),
+            );
+
+            let installed = match self.apt_install(&[&*path.to_string_lossy()]) {
+                Ok(()) => true,
+                Err(why) => {
+                    warn!("unable to install the vendor package {}: {}", name, why);
+                    // Leaves dpkg in a consistent state for the packages which follow.
+                    let _ = self
+                        .chroot
+                        .command(
+                            "apt-get",
+                            &cascade! {
+                                Vec::with_capacity(APT_OPTIONS.len() + 3);
+                                ..extend_from_slice(&["install", "-f", "-y"]);
+                                ..extend_from_slice(APT_OPTIONS);
+                            },
+                        )
+                        .run();
+                    false
+                }
+            };
+
+            packages.push(OemPackage { name: name.into_owned(), installed });
+        }
+
+        Ok(packages)
+    }
+
+    /// Enables the OpenSSH server, and admits `user` with the server's authorized keys.
+    pub fn ssh_server(&self, server: &SshServer, user: Option<&str>) -> io::Result<()> {
+        info!("enabling the OpenSSH server");
+        self.chroot.command("systemctl", &["enable", "ssh.service"]).run()?;
+
+        if server.authorized_keys.is_empty() {
+            return Ok(());
+        }
+
+        let user = match user {
+            Some(user) => user,
+            None => {
+                warn!("no user account was created, so no SSH keys were authorized");
+                return Ok(());
+            }
+        };
+
+        info!("authorizing {} SSH keys for {}", server.authorized_keys.len(), user);
+        let ssh = Path::new("/home").join(user).join(".ssh");
+        let target = self.chroot.path.join(ssh.strip_prefix("/").expect("absolute path"));
+        fs::create_dir_all(&target)?;
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o700))?;
+
+        let keys = target.join("authorized_keys");
+        misc::write(&keys, server.authorized_keys())?;
+        fs::set_permissions(&keys, fs::Permissions::from_mode(0o600))?;
+
+        // Owned by the user, and the user's login group.
+        let owner = [user, ":"].concat();
+        self.chroot.command("chown", &["-R", &owner, &ssh.to_string_lossy()]).run()
+    }
+
+    /// Seeds the image with the datasources and configuration of cloud-init, and clears any
+    /// state which it kept while the packages were installed, so that the first boot of each
+    /// instance is treated as such.
+    pub fn cloud_init(&self, cloud_init: &CloudInit) -> io::Result<()> {
+        info!("seeding cloud-init with {:?}", cloud_init.datasources);
+        cloud_init.configure(&self.chroot.path)?;
+        self.chroot.command("cloud-init", &["clean", "--logs"]).run()
+    }
+
+    /// Logs `user` in automatically, through the display manager which the image ships.
+    pub fn autologin(&self, user: &str) -> io::Result<()> {
+        let dm = match DisplayManager::detect(&self.chroot.path) {
+            Some(dm) => dm,
+            None => {
+                warn!("no supported display manager was found, so autologin was not enabled");
+                return Ok(());
+            }
+        };
+
+        info!("enabling autologin of {} through {:?}", user, dm);
+        dm.autologin(&self.chroot.path, user)?;
+
+        // Some distributions admit only the members of this group to log in automatically.
+        let group_file = fs::read_to_string(self.chroot.path.join("etc/group"))?;
+        if users::defined_groups(&group_file).any(|group| group == "autologin") {
+            self.chroot.command("usermod", &["-aG", "autologin", user]).run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Configures periodic snapshots of the root file system, whose UUID is `root_uuid`.
+    pub fn snapshots(&self, tool: SnapshotTool, root_uuid: &str) -> io::Result<()> {
+        match tool {
+            SnapshotTool::Snapper => {
+                info!("configuring snapper to take timeline snapshots of /");
+                self.chroot
+                    .command("snapper", &["--no-dbus", "-c", "root", "create-config", "/"])
+                    .run()?;
+
+                self.chroot
+                    .command(
+                        "snapper",
+                        &cascade! {
+                            Vec::with_capacity(SNAPPER_SETTINGS.len() + 4);
+                            ..extend_from_slice(&["--no-dbus", "-c", "root", "set-config"]);
+                            ..extend_from_slice(SNAPPER_SETTINGS);
+                        },
+                    )
+                    .run()
+            }
+            SnapshotTool::Timeshift => {
+                info!("configuring timeshift to take daily snapshots of /");
+                let config = self.chroot.path.join(TIMESHIFT_CONFIG);
+                fs::create_dir_all(config.parent().expect("config without parent"))?;
+                misc::write(&config, timeshift_config(root_uuid))?;
+                misc::write(self.chroot.path.join(TIMESHIFT_CRON), TIMESHIFT_CRON_JOB)
+            }
+        }
+    }
+
     pub fn timezone(&self, region: &Region) -> io::Result<()> {
         self.chroot.command("rm", &["/etc/timezone"]).run()?;
 
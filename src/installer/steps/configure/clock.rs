@@ -0,0 +1,35 @@
+//! The hardware clock and time synchronization of the installed system.
+//!
+//! Windows keeps the hardware clock in local time, whereas Linux keeps it in UTC by default,
+//! so a system which dual boots with Windows is otherwise hours off after each switch.
+
+pub(crate) const ADJTIME: &str = "etc/adjtime";
+pub(crate) const TIMESYNCD_CONF: &str = "etc/systemd/timesyncd.conf.d/50-distinst.conf";
+
+/// The contents of `/etc/adjtime`, which defines whether the hardware clock is in local time.
+pub(crate) fn adjtime(local: bool) -> &'static str {
+    if local {
+        "0.0 0 0.0\n0\nLOCAL\n"
+    } else {
+        "0.0 0 0.0\n0\nUTC\n"
+    }
+}
+
+/// A drop-in of systemd-timesyncd, which synchronizes the clock with the given servers.
+pub(crate) fn timesyncd_conf(servers: &[String]) -> String {
+    format!("# Written by the installer.\n[Time]\nNTP={}\n", servers.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timesyncd_servers() {
+        let servers = vec!["ntp1.example.org".to_owned(), "10.0.0.1".to_owned()];
+        assert_eq!(
+            timesyncd_conf(&servers),
+            "# Written by the installer.\n[Time]\nNTP=ntp1.example.org 10.0.0.1\n"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! cloud-init, which creates the users, and sets the hostname, network, and SSH keys, of a cloud
+//! or VM image on its first boot, from the metadata of the instance which the provider serves.
+//! Images which are seeded with it are left without users or a hostname of their own.
+
+use crate::misc;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const PACKAGES: &[&str] = &["cloud-init"];
+
+const CLOUD_CFG_D: &str = "etc/cloud/cloud.cfg.d";
+const DATASOURCES_CFG: &str = "90_distinst_datasources.cfg";
+const CONFIG_CFG: &str = "99_distinst.cfg";
+const DISABLED: &str = "etc/cloud/cloud-init.disabled";
+
+/// The seeding of an image with cloud-init.
+#[derive(Clone, Debug, Default)]
+pub struct CloudInit {
+    /// The datasources which cloud-init searches for the metadata of the instance, in order,
+    /// such as `NoCloud`, `Ec2`, or `OpenStack`. Every datasource is searched if empty.
+    pub datasources: Vec<String>,
+    /// A configuration, in the cloud-config format, which is installed to
+    /// `/etc/cloud/cloud.cfg.d`, such as to define the default user, or the datasources.
+    pub config:      Option<PathBuf>,
+}
+
+impl CloudInit {
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = self.datasources.iter().find(|source| {
+            source.is_empty() || !source.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+
+        if let Some(source) = invalid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cloud-init datasource is not valid: {:?}", source),
+            ));
+        }
+
+        if let Some(ref config) = self.config {
+            if !config.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("cloud-init config {} was not found", config.display()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the datasources and the configuration to the image at `root`, and removes the
+    /// hostname of the image, which cloud-init sets from the metadata.
+    pub(crate) fn configure(&self, root: &Path) -> io::Result<()> {
+        let cfg_d = root.join(CLOUD_CFG_D);
+        fs::create_dir_all(&cfg_d)?;
+
+        if let Some(list) = datasource_list(&self.datasources) {
+            misc::write(cfg_d.join(DATASOURCES_CFG), list)?;
+        }
+
+        if let Some(ref config) = self.config {
+            fs::copy(config, cfg_d.join(CONFIG_CFG))?;
+        }
+
+        for path in &[root.join(DISABLED), root.join("etc/hostname")] {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The `datasource_list` of cloud-init, which ends with the `None` datasource, so that an
+/// instance whose metadata is not found still boots with the configuration of the image.
+fn datasource_list(datasources: &[String]) -> Option<String> {
+    if datasources.is_empty() {
+        return None;
+    }
+
+    let mut list = datasources.iter().map(String::as_str).collect::<Vec<_>>();
+    if !list.contains(&"None") {
+        list.push("None");
+    }
+
+    Some(format!("datasource_list: [ {} ]\n", list.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cloud_init_datasources() {
+        assert_eq!(datasource_list(&[]), None);
+        assert_eq!(
+            datasource_list(&["NoCloud".into(), "Ec2".into()]).unwrap(),
+            "datasource_list: [ NoCloud, Ec2, None ]\n"
+        );
+        assert_eq!(
+            datasource_list(&["ConfigDrive".into(), "None".into()]).unwrap(),
+            "datasource_list: [ ConfigDrive, None ]\n"
+        );
+
+        let invalid = CloudInit { datasources: vec!["Ec2, NoCloud".into()], config: None };
+        assert!(invalid.validate().is_err());
+    }
+}
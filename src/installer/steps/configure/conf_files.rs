@@ -0,0 +1,167 @@
+//! Edits of the config files of the target, which keep the comments and settings that they
+//! already hold, so that only the values which the install chooses are changed.
+
+use crate::misc;
+use std::{fs, io, path::Path};
+
+/// Assigns variables in a file of shell assignments, such as `/etc/default/keyboard`, which
+/// is created if it does not exist.
+pub(crate) fn update_vars(path: &Path, vars: &[(&str, &str)]) -> io::Result<()> {
+    let config = read(path)?;
+    misc::write(path, set_vars(&config, vars))
+}
+
+/// Sets the keys of a section of an INI file, which is created if it does not exist.
+pub(crate) fn update_ini(path: &Path, section: &str, keys: &[(&str, &str)]) -> io::Result<()> {
+    let config = read(path)?;
+    info!("setting [{}] of {}", section, path.display());
+    misc::write(path, set_ini_keys(&config, section, keys))
+}
+
+fn read(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(config) => Ok(config),
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            Ok(String::new())
+        }
+        Err(why) => Err(why),
+    }
+}
+
+/// Assigns variables in a file of shell assignments, replacing their values if they are already
+/// assigned. Comments and other assignments are kept.
+fn set_vars(config: &str, vars: &[(&str, &str)]) -> String {
+    let assignment = |&(key, value): &(&str, &str)| [key, "=\"", value, "\"\n"].concat();
+    let mut written = vec![false; vars.len()];
+    let mut output = String::with_capacity(config.len() + 64);
+
+    for line in config.lines() {
+        let key = line.split('=').next().unwrap_or("").trim();
+        if line.contains('=') {
+            if let Some(index) = vars.iter().position(|&(name, _)| name == key) {
+                if !written[index] {
+                    output.push_str(&assignment(&vars[index]));
+                    written[index] = true;
+                }
+                continue;
+            }
+        }
+
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    for (var, _) in vars.iter().zip(&written).filter(|&(_, &done)| !done) {
+        output.push_str(&assignment(var));
+    }
+
+    output
+}
+
+/// Sets the keys of an INI section, replacing their values if they are already set, and
+/// adding the section if it is missing. Other lines, including comments, are kept.
+fn set_ini_keys(config: &str, section: &str, keys: &[(&str, &str)]) -> String {
+    let header = ["[", section, "]"].concat();
+    let entry = |&(key, value): &(&str, &str)| [key, "=", value, "\n"].concat();
+    let key_of = |line: &str| line.split('=').next().unwrap_or("").trim().to_owned();
+
+    // Keys which the section already sets are replaced where they are, and the others are
+    // added beneath its header.
+    let mut present = Vec::new();
+    let mut in_section = false;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_section = line == header;
+        } else if in_section && !line.starts_with('#') && !line.starts_with(';') {
+            present.push(key_of(line));
+        }
+    }
+
+    let mut output = String::with_capacity(config.len() + 64);
+    let mut found = false;
+    in_section = false;
+    for line in config.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_section = trimmed == header;
+        } else if in_section {
+            if let Some(pair) = keys.iter().find(|&&(key, _)| key == key_of(trimmed)) {
+                output.push_str(&entry(pair));
+                continue;
+            }
+        }
+
+        output.push_str(line);
+        output.push('\n');
+
+        if in_section && !found {
+            found = true;
+            for pair in keys.iter().filter(|&&(key, _)| !present.iter().any(|k| k == key)) {
+                output.push_str(&entry(pair));
+            }
+        }
+    }
+
+    if !found {
+        if !output.is_empty() && !output.ends_with("\n\n") {
+            output.push('\n');
+        }
+
+        output.push_str(&header);
+        output.push('\n');
+        keys.iter().for_each(|pair| output.push_str(&entry(pair)));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_vars() {
+        let keyboard = "# KEYBOARD CONFIGURATION FILE\n\nXKBMODEL=\"pc105\"\nXKBLAYOUT=\"us\"\n\
+                        XKBVARIANT=\"\"\nXKBOPTIONS=\"\"\n\nBACKSPACE=\"guess\"\n";
+        let vars = [("XKBMODEL", "pc105"), ("XKBLAYOUT", "de"), ("XKBVARIANT", "nodeadkeys")];
+        assert_eq!(
+            set_vars(keyboard, &vars),
+            "# KEYBOARD CONFIGURATION FILE\n\nXKBMODEL=\"pc105\"\nXKBLAYOUT=\"de\"\n\
+             XKBVARIANT=\"nodeadkeys\"\nXKBOPTIONS=\"\"\n\nBACKSPACE=\"guess\"\n"
+        );
+
+        assert_eq!(
+            set_vars("# KEYMAP: [ y | n ]\nKEYMAP=n\nCOMPRESS=zstd\n", &[("KEYMAP", "y")]),
+            "# KEYMAP: [ y | n ]\nKEYMAP=\"y\"\nCOMPRESS=zstd\n"
+        );
+
+        assert_eq!(set_vars("", &[("XKBLAYOUT", "fr")]), "XKBLAYOUT=\"fr\"\n");
+    }
+
+    #[test]
+    fn ini_keys() {
+        let custom = "# GDM configuration storage\n\n[daemon]\n# AutomaticLoginEnable = true\n\
+                      AutomaticLogin = old\nWaylandEnable=false\n\n[security]\n";
+        let keys = [("AutomaticLoginEnable", "true"), ("AutomaticLogin", "kiosk")];
+        assert_eq!(
+            set_ini_keys(custom, "daemon", &keys),
+            "# GDM configuration storage\n\n[daemon]\nAutomaticLoginEnable=true\n\
+             # AutomaticLoginEnable = true\nAutomaticLogin=kiosk\nWaylandEnable=false\n\n\
+             [security]\n"
+        );
+
+        assert_eq!(
+            set_ini_keys("[security]\n", "daemon", &keys),
+            "[security]\n\n[daemon]\nAutomaticLoginEnable=true\nAutomaticLogin=kiosk\n"
+        );
+
+        assert_eq!(
+            set_ini_keys("", "Autologin", &[("User", "kiosk"), ("Session", "plasma")]),
+            "[Autologin]\nUser=kiosk\nSession=plasma\n"
+        );
+    }
+}
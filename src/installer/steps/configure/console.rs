@@ -0,0 +1,260 @@
+//! The font of the virtual consoles, which is too small to read on HiDPI displays by default,
+//! the state of numlock once the system boots, and the serial console of servers which are
+//! installed without a display.
+
+use super::{autologin::DisplayManager, branding, conf_files};
+use std::{io, path::Path, str::FromStr};
+
+const CONSOLE_SETUP: &str = "etc/default/console-setup";
+const NUMLOCK_SERVICE: &str = "etc/systemd/system/numlock.service";
+const DCONF_NUMLOCK: &str = "etc/dconf/db/local.d/60-numlock";
+const DCONF_GDM_NUMLOCK: &str = "etc/dconf/db/gdm.d/60-numlock";
+const DCONF_GDM_PROFILE: &str = "etc/dconf/profile/gdm";
+
+/// Settings of the consoles and the login screen of the installed system.
+#[derive(Clone, Debug, Default)]
+pub struct ConsoleSettings {
+    /// The console-setup font face, such as `Terminus` or `VGA`.
+    pub font_face: Option<String>,
+    /// The console-setup font size, such as `16x32` for HiDPI displays.
+    pub font_size: Option<String>,
+    /// Turns numlock on or off at boot, on the consoles and the login screen.
+    pub numlock:   Option<bool>,
+    /// Directs the kernel's console to a serial port, and starts a getty on it.
+    pub serial:    Option<SerialConsole>,
+}
+
+impl ConsoleSettings {
+    /// Ensures that the font may be written to `/etc/default/console-setup`.
+    pub fn validate(&self) -> io::Result<()> {
+        if let Some(ref face) = self.font_face {
+            if face.is_empty() || !face.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(invalid(format!("console font face is not valid: {:?}", face)));
+            }
+        }
+
+        if let Some(ref size) = self.font_size {
+            if !is_font_size(size) {
+                return Err(invalid(format!("console font size is not valid: {:?}", size)));
+            }
+        }
+
+        if let Some(ref serial) = self.serial {
+            serial.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// BAUD rates which the getty of systemd, and the console of the kernel, both accept.
+const BAUD_RATES: &[u32] = &[9600, 19200, 38400, 57600, 115_200];
+
+/// A serial port, such as `ttyS0`, and the BAUD rate at which its console is driven.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerialConsole {
+    pub port: String,
+    pub baud: u32,
+}
+
+impl Default for SerialConsole {
+    fn default() -> Self { SerialConsole { port: "ttyS0".into(), baud: 115_200 } }
+}
+
+impl SerialConsole {
+    pub fn validate(&self) -> io::Result<()> {
+        let (name, unit) = self.port.split_at(self.port.trim_end_matches(char::is_numeric).len());
+        if name.is_empty() || unit.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(invalid(format!("serial port is not valid: {:?}", self.port)));
+        }
+
+        if !BAUD_RATES.contains(&self.baud) {
+            return Err(invalid(format!("serial BAUD rate is not supported: {}", self.baud)));
+        }
+
+        Ok(())
+    }
+
+    /// Kernel options which keep the virtual consoles, but make the serial port the console
+    /// of the system, as it is the last that is given.
+    pub(crate) fn kernel_options(&self) -> String {
+        format!("console=tty0 console={},{}", self.port, self.baud)
+    }
+
+    pub(crate) fn getty(&self) -> String { format!("serial-getty@{}.service", self.port) }
+}
+
+/// Parses a serial console in the format of the kernel, as `PORT[,BAUD]`, such as `ttyS0,115200`.
+impl FromStr for SerialConsole {
+    type Err = io::Error;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut fields = string.splitn(2, ',');
+        let port = fields.next().unwrap_or("").to_owned();
+        let baud = match fields.next() {
+            // Options which follow the rate, such as `n8`, are the defaults of the kernel.
+            Some(baud) => baud
+                .trim_end_matches(|c: char| !c.is_ascii_digit())
+                .parse::<u32>()
+                .map_err(|_| invalid(format!("serial BAUD rate is not valid: {:?}", baud)))?,
+            None => SerialConsole::default().baud,
+        };
+
+        let console = SerialConsole { port, baud };
+        console.validate()?;
+        Ok(console)
+    }
+}
+
+/// Writes the settings to the target at `root`.
+///
+/// Returns `true` if the dconf database must be updated.
+pub(crate) fn configure(root: &Path, settings: &ConsoleSettings) -> io::Result<bool> {
+    let mut font = Vec::new();
+    font.extend(settings.font_face.as_deref().map(|face| ("FONTFACE", face)));
+    font.extend(settings.font_size.as_deref().map(|size| ("FONTSIZE", size)));
+    if !font.is_empty() {
+        info!("writing the console font to /{}", CONSOLE_SETUP);
+        conf_files::update_vars(&root.join(CONSOLE_SETUP), &font)?;
+    }
+
+    let numlock = match settings.numlock {
+        Some(numlock) => numlock,
+        None => return Ok(false),
+    };
+
+    info!("turning numlock {} at boot", on_off(numlock));
+    branding::write_file(&root.join(NUMLOCK_SERVICE), &numlock_service(numlock))?;
+
+    match DisplayManager::detect(root) {
+        Some(DisplayManager::Sddm) => {
+            let path = root.join("etc/sddm.conf.d/distinst-numlock.conf");
+            conf_files::update_ini(&path, "General", &[("Numlock", on_off(numlock))])?;
+        }
+        Some(DisplayManager::LightDm) if root.join("usr/bin/numlockx").exists() => {
+            let path = root.join("etc/lightdm/lightdm.conf.d/50-distinst-numlock.conf");
+            let script = ["/usr/bin/numlockx ", on_off(numlock)].concat();
+            conf_files::update_ini(&path, "Seat:*", &[("greeter-setup-script", script.as_str())])?;
+        }
+        _ => (),
+    }
+
+    // GNOME sessions, and the greeter of GDM, restore numlock from dconf.
+    if !root.join("usr/bin/dconf").exists() {
+        return Ok(false);
+    }
+
+    let keyfile = numlock_keyfile(numlock);
+    branding::write_file(&root.join(DCONF_NUMLOCK), &keyfile)?;
+    branding::enable_system_db(&root.join(branding::DCONF_PROFILE))?;
+    if root.join(DCONF_GDM_PROFILE).exists() {
+        branding::write_file(&root.join(DCONF_GDM_NUMLOCK), &keyfile)?;
+    }
+
+    Ok(true)
+}
+
+/// Font sizes of console-setup, which are a height, or a width and a height, such as `16x32`.
+fn is_font_size(size: &str) -> bool {
+    let number = |n: &str| !n.is_empty() && n.len() <= 2 && n.chars().all(|c| c.is_ascii_digit());
+    let mut dimensions = size.splitn(2, 'x');
+    match (dimensions.next(), dimensions.next()) {
+        (Some(height), None) => number(height),
+        (Some(width), Some(height)) => number(width) && number(height),
+        _ => false,
+    }
+}
+
+fn on_off(enable: bool) -> &'static str { if enable { "on" } else { "off" } }
+
+/// A service which sets numlock on each virtual console, before their gettys start.
+fn numlock_service(numlock: bool) -> String {
+    format!(
+        "[Unit]\nDescription=Set the numlock state of the virtual consoles\n\
+         Before=getty.target\n\n\
+         [Service]\nType=oneshot\n\
+         ExecStart=/bin/sh -c 'for tty in /dev/tty[1-6]; do setleds -D {}num < \"$tty\"; done'\n\n\
+         [Install]\nWantedBy=multi-user.target\n",
+        if numlock { '+' } else { '-' }
+    )
+}
+
+fn numlock_keyfile(numlock: bool) -> String {
+    format!(
+        "[org/gnome/desktop/peripherals/keyboard]\n\
+         numlock-state={}\nremember-numlock-state=false\n",
+        numlock
+    )
+}
+
+/// The `GRUB_SERIAL_COMMAND` which shows GRUB's menu on the serial port that is the console of
+/// the kernel `options`, the last which they give. GRUB only drives the PC serial ports, which
+/// it knows by their unit, so that `ttyS1,57600` is `serial --unit=1 --speed=57600`.
+pub(crate) fn grub_serial_command(options: &str) -> Option<String> {
+    let console = options
+        .split_whitespace()
+        .filter_map(|option| option.strip_prefix("console="))
+        .last()?;
+
+    let mut fields = console.splitn(2, ',');
+    let unit = fields.next()?.strip_prefix("ttyS")?.parse::<u32>().ok()?;
+    match fields.next() {
+        Some(baud) => {
+            let speed = baud.trim_end_matches(|c: char| !c.is_ascii_digit()).parse::<u32>().ok()?;
+            Some(format!("serial --unit={} --speed={}", unit, speed))
+        }
+        None => Some(format!("serial --unit={}", unit)),
+    }
+}
+
+fn invalid(why: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidInput, why) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn console_font() {
+        assert!(is_font_size("16"));
+        assert!(is_font_size("16x32"));
+        assert!(!is_font_size("16x"));
+        assert!(!is_font_size("large"));
+
+        let settings = ConsoleSettings {
+            font_face: Some("Terminus".into()),
+            font_size: Some("16x32".into()),
+            numlock:   Some(true),
+            serial:    None,
+        };
+        assert!(settings.validate().is_ok());
+
+        let settings = ConsoleSettings { font_face: Some("Ter\"minus".into()), ..settings };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn serial_console() {
+        let console = "ttyS1,57600".parse::<SerialConsole>().unwrap();
+        assert_eq!(console, SerialConsole { port: "ttyS1".into(), baud: 57600 });
+        assert_eq!(console.kernel_options(), "console=tty0 console=ttyS1,57600");
+        assert_eq!(console.getty(), "serial-getty@ttyS1.service");
+
+        assert_eq!("ttyAMA0".parse::<SerialConsole>().unwrap().baud, 115_200);
+        assert_eq!("ttyS0,115200n8".parse::<SerialConsole>().unwrap().baud, 115_200);
+        assert!("ttyS0,115201".parse::<SerialConsole>().is_err());
+        assert!("ttyS".parse::<SerialConsole>().is_err());
+        assert!("../ttyS0".parse::<SerialConsole>().is_err());
+    }
+
+    #[test]
+    fn grub_serial() {
+        assert_eq!(
+            grub_serial_command("quiet console=tty0 console=ttyS1,57600").unwrap(),
+            "serial --unit=1 --speed=57600"
+        );
+        assert_eq!(grub_serial_command("console=ttyS0,115200n8 console=tty0"), None);
+        assert_eq!(grub_serial_command("console=ttyS0").unwrap(), "serial --unit=0");
+        assert_eq!(grub_serial_command("console=tty0 console=ttyAMA0,115200"), None);
+        assert_eq!(grub_serial_command("quiet splash"), None);
+    }
+}
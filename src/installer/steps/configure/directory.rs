@@ -0,0 +1,134 @@
+//! Enrollment of the installed system in a directory, such as Active Directory or FreeIPA,
+//! with realmd and SSSD.
+//!
+//! realmd relies upon the system's D-Bus and network services, which do not run within the
+//! chroot, so the join is staged to be performed by a service once the installed system's
+//! network is online. The service retries on each boot until the join succeeds, after which
+//! the credentials are removed.
+
+use std::{fmt, io};
+
+/// Packages which realmd requires to join, and SSSD to authenticate against, a domain.
+pub(crate) const PACKAGES: &[&str] =
+    &["realmd", "sssd", "sssd-tools", "adcli", "libnss-sss", "libpam-sss", "samba-common-bin"];
+
+/// The credentials with which the system joins the domain.
+#[derive(Clone, PartialEq)]
+pub enum JoinCredential {
+    /// An account which is permitted to join computers to the domain.
+    Password { user: String, password: String },
+    /// A one-time password, which an administrator has issued for this computer.
+    OneTimePassword(String),
+}
+
+/// Passwords are redacted, as the config is written to the installer's logs.
+impl fmt::Debug for JoinCredential {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JoinCredential::Password { ref user, .. } => f
+                .debug_struct("Password")
+                .field("user", user)
+                .field("password", &"<redacted>")
+                .finish(),
+            JoinCredential::OneTimePassword(_) => {
+                f.debug_tuple("OneTimePassword").field(&"<redacted>").finish()
+            }
+        }
+    }
+}
+
+/// A domain which the installed system joins, once its network is online.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectoryEnrollment {
+    /// The name of the domain, such as `ad.example.org`.
+    pub domain:      String,
+    pub credential:  JoinCredential,
+    /// The organizational unit which the computer account is created in, if not the default.
+    pub computer_ou: Option<String>,
+}
+
+impl DirectoryEnrollment {
+    /// Ensures that a domain and credentials were given.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |why: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, why));
+
+        if self.domain.is_empty() || self.domain.contains(char::is_whitespace) {
+            return invalid("the domain to join must not be empty or contain whitespace");
+        }
+
+        let missing = match self.credential {
+            JoinCredential::Password { ref user, ref password } => {
+                user.is_empty() || password.is_empty()
+            }
+            JoinCredential::OneTimePassword(ref password) => password.is_empty(),
+        };
+
+        if missing {
+            return invalid("credentials are required to join the domain");
+        }
+
+        if self.fields().any(|(_, value)| value.contains('\0')) {
+            return invalid("the domain join contains a null byte");
+        }
+
+        Ok(())
+    }
+
+    fn fields(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        let credential = match self.credential {
+            JoinCredential::Password { ref user, ref password } => {
+                vec![("JOIN_USER", user.as_str()), ("PASSWORD", password.as_str())]
+            }
+            JoinCredential::OneTimePassword(ref password) => {
+                vec![("ONE_TIME_PASSWORD", password.as_str())]
+            }
+        };
+
+        Some(("DOMAIN", self.domain.as_str()))
+            .into_iter()
+            .chain(credential)
+            .chain(self.computer_ou.as_ref().map(|ou| ("COMPUTER_OU", ou.as_str())))
+    }
+
+    /// The variables which the join script sources, quoted for the shell.
+    pub(crate) fn join_env(&self) -> String {
+        let mut env = String::new();
+        for (name, value) in self.fields() {
+            env.push_str(&format!("{}='{}'\n", name, value.replace('\'', "'\\''")));
+        }
+
+        env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory_join_env() {
+        let enrollment = DirectoryEnrollment {
+            domain:      "ad.example.org".into(),
+            credential:  JoinCredential::Password {
+                user:     "joiner".into(),
+                password: "it's secret".into(),
+            },
+            computer_ou: Some("OU=Labs,DC=ad,DC=example,DC=org".into()),
+        };
+
+        assert!(enrollment.validate().is_ok());
+        assert_eq!(
+            enrollment.join_env(),
+            "DOMAIN='ad.example.org'\nJOIN_USER='joiner'\nPASSWORD='it'\\''s secret'\n\
+             COMPUTER_OU='OU=Labs,DC=ad,DC=example,DC=org'\n"
+        );
+
+        let enrollment = DirectoryEnrollment {
+            credential: JoinCredential::OneTimePassword(String::new()),
+            computer_ou: None,
+            ..enrollment
+        };
+
+        assert!(enrollment.validate().is_err());
+    }
+}
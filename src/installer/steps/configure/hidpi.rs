@@ -0,0 +1,52 @@
+//! Default scaling of the desktop and the login screen, so that systems with a HiDPI panel
+//! boot into a desktop which is readable.
+
+use super::{autologin::DisplayManager, branding, conf_files};
+use std::{io, path::Path};
+
+const DCONF_SCALING: &str = "etc/dconf/db/local.d/60-scaling";
+const DCONF_GDM_SCALING: &str = "etc/dconf/db/gdm.d/60-scaling";
+const DCONF_GDM_PROFILE: &str = "etc/dconf/profile/gdm";
+const KDEGLOBALS: &str = "etc/xdg/kdeglobals";
+
+/// The console font which is used on HiDPI panels, unless another was chosen.
+pub(crate) const HIDPI_CONSOLE_FONT: (&str, &str) = ("Terminus", "16x32");
+
+/// Scales GNOME, KDE Plasma, and their login screens by `factor`.
+///
+/// Returns `true` if the dconf database must be updated.
+pub(crate) fn configure(root: &Path, factor: u32) -> io::Result<bool> {
+    info!("scaling the desktop by {} by default", factor);
+    let scale = factor.to_string();
+    let dpi = (96 * factor).to_string();
+
+    // Plasma reads the defaults of its users from the XDG config directories.
+    if root.join("usr/bin/plasmashell").exists() {
+        let keys = [("ScaleFactor", scale.as_str())];
+        conf_files::update_ini(&root.join(KDEGLOBALS), "KScreen", &keys)?;
+    }
+
+    if DisplayManager::detect(root) == Some(DisplayManager::Sddm) {
+        let environment = ["QT_SCREEN_SCALE_FACTORS=", &scale, ",QT_FONT_DPI=", &dpi].concat();
+        let keys = [("GreeterEnvironment", environment.as_str())];
+        let path = root.join("etc/sddm.conf.d/distinst-scaling.conf");
+        conf_files::update_ini(&path, "General", &keys)?;
+    }
+
+    if !root.join("usr/bin/dconf").exists() {
+        return Ok(false);
+    }
+
+    let keyfile = scaling_keyfile(factor);
+    branding::write_file(&root.join(DCONF_SCALING), &keyfile)?;
+    branding::enable_system_db(&root.join(branding::DCONF_PROFILE))?;
+    if root.join(DCONF_GDM_PROFILE).exists() {
+        branding::write_file(&root.join(DCONF_GDM_SCALING), &keyfile)?;
+    }
+
+    Ok(true)
+}
+
+fn scaling_keyfile(factor: u32) -> String {
+    format!("[org/gnome/desktop/interface]\nscaling-factor=uint32 {}\n", factor)
+}
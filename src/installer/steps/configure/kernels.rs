@@ -0,0 +1,130 @@
+//! The kernel which the installed system boots by default, when the image provides kernels of
+//! several flavors, such as `generic`, `oem`, and `lowlatency`.
+
+use super::conf_files;
+use crate::hardware_support;
+use os_detect::installed_kernels;
+use std::{fs, io, path::Path};
+
+/// Which of the image's kernels boots by default.
+#[derive(Clone, Debug, Default)]
+pub struct KernelSelection {
+    /// The flavor which boots by default, such as `oem`. If `None`, the flavor which the
+    /// hardware prefers is chosen, if it has a preference, unless an image is being created,
+    /// which boots on hardware other than that of the installer.
+    pub flavor:        Option<String>,
+    /// Removes the kernels of the other flavors.
+    pub remove_others: bool,
+}
+
+impl KernelSelection {
+    /// Ensures that the flavor is one which a kernel release may end with.
+    pub fn validate(&self) -> io::Result<()> {
+        if let Some(ref flavor) = self.flavor {
+            let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+            if flavor.is_empty() || !flavor.chars().all(valid) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("kernel flavor is not valid: {:?}", flavor),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The release of the newest kernel in `root` of the selected flavor, such as
+/// `5.15.0-1033-oem`, or `None` if the default kernel is kept. The hardware is not consulted
+/// for the flavor of an `image`.
+pub(crate) fn select(root: &Path, selection: &KernelSelection, image: bool) -> Option<String> {
+    let flavor = match selection.flavor {
+        Some(ref flavor) => flavor.as_str(),
+        None if image => return None,
+        None => hardware_support::kernel_flavor()?,
+    };
+
+    let releases = installed_kernels(root);
+    match newest_of_flavor(&releases, flavor) {
+        Some(release) => {
+            info!("selected the {} kernel, {}, to boot by default", flavor, release);
+            Some(release.to_owned())
+        }
+        None => {
+            warn!("the image has no {} kernel, so the default kernel is kept", flavor);
+            None
+        }
+    }
+}
+
+/// The installed packages of the kernels other than `release`, which are to be removed.
+pub(crate) fn other_packages(root: &Path, release: &str) -> Vec<String> {
+    let others: Vec<String> =
+        installed_kernels(root).into_iter().filter(|other| other != release).collect();
+
+    let packages = fs::read_dir(root.join("var/lib/dpkg/info"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| name.strip_suffix(".list").map(String::from));
+
+    packages.filter(|package| is_kernel_package(package, &others)).collect()
+}
+
+/// Boots `release` by default from GRUB, which otherwise boots the newest kernel. Its entries
+/// are identified by the UUID of the root file system.
+pub(crate) fn grub_default(root: &Path, release: &str, root_uuid: &str) -> io::Result<()> {
+    let grub = root.join("etc/default/grub");
+    if !grub.exists() {
+        return Ok(());
+    }
+
+    let entry = format!("gnulinux-advanced-{0}>gnulinux-{1}-advanced-{0}", root_uuid, release);
+    conf_files::update_vars(&grub, &[("GRUB_DEFAULT", &entry)])
+}
+
+fn flavor(release: &str) -> &str { release.rsplit('-').next().unwrap_or(release) }
+
+fn newest_of_flavor<'a>(releases: &'a [String], wanted: &str) -> Option<&'a str> {
+    releases.iter().map(String::as_str).find(|release| flavor(release) == wanted)
+}
+
+/// Whether a package, such as `linux-modules-extra-5.15.0-56-generic`, belongs to one of the
+/// kernel `releases`. Meta packages which depend upon them are removed along with them.
+fn is_kernel_package(package: &str, releases: &[String]) -> bool {
+    package.starts_with("linux-")
+        && releases.iter().any(|release| {
+            package.strip_suffix(release.as_str()).map_or(false, |rest| rest.ends_with('-'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_flavors() {
+        let releases: Vec<String> = vec![
+            "5.17.0-1020-oem".into(),
+            "5.15.0-56-lowlatency".into(),
+            "5.15.0-56-generic".into(),
+        ];
+
+        assert_eq!(newest_of_flavor(&releases, "generic"), Some("5.15.0-56-generic"));
+        assert_eq!(newest_of_flavor(&releases, "oem"), Some("5.17.0-1020-oem"));
+        assert_eq!(newest_of_flavor(&releases, "aws"), None);
+
+        let others = &releases[1..];
+        assert!(is_kernel_package("linux-image-5.15.0-56-generic", others));
+        assert!(is_kernel_package("linux-modules-extra-5.15.0-56-lowlatency", others));
+        assert!(!is_kernel_package("linux-image-5.17.0-1020-oem", others));
+        assert!(!is_kernel_package("linux-firmware", others));
+
+        let selection = KernelSelection { flavor: Some("oem".into()), remove_others: true };
+        assert!(selection.validate().is_ok());
+        assert!(KernelSelection { flavor: Some("OEM kernel".into()), ..selection }
+            .validate()
+            .is_err());
+    }
+}
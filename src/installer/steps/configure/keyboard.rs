@@ -0,0 +1,204 @@
+//! The keyboard layouts of the console and the initramfs, so that the passphrase of an encrypted
+//! root is typed with the layout that it was chosen with, and of the desktop.
+//!
+//! Layouts besides the primary one are listed after it, in the comma-separated form of XKB, and
+//! are switched between with XKB options such as `grp:alt_shift_toggle`.
+
+use super::{branding, conf_files};
+use crate::Config;
+use std::{fs, io, path::Path};
+
+const DEFAULT_KEYBOARD: &str = "etc/default/keyboard";
+const VCONSOLE_CONF: &str = "etc/vconsole.conf";
+const INITRAMFS_CONF: &str = "etc/initramfs-tools/initramfs.conf";
+const CRYPTTAB: &str = "etc/crypttab";
+const DCONF_INPUT_SOURCES: &str = "etc/dconf/db/local.d/60-distinst-input-sources";
+
+/// A keyboard layout which may be switched to, besides the primary layout of the config.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyboardLayout {
+    /// An XKB layout, such as `ru`.
+    pub layout:  String,
+    /// An optional variant of the layout, such as `phonetic`.
+    pub variant: Option<String>,
+}
+
+impl KeyboardLayout {
+    /// Ensures that the layout may be listed among others.
+    pub fn validate(&self) -> io::Result<()> {
+        let names = Some(self.layout.as_str()).into_iter().chain(self.variant.as_deref());
+        for name in names {
+            if !is_xkb_name(name) {
+                let why = format!("keyboard layout is not valid: {:?}", name);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, why));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Ensures that XKB options, such as `grp:alt_shift_toggle`, may be written to the configs.
+pub(crate) fn validate_options(options: &str) -> io::Result<()> {
+    if options.split(',').all(|option| {
+        option.chars().all(|c| c.is_ascii_alphanumeric() || "_-:()".contains(c))
+    }) {
+        Ok(())
+    } else {
+        let why = format!("keyboard options are not valid: {:?}", options);
+        Err(io::Error::new(io::ErrorKind::InvalidInput, why))
+    }
+}
+
+/// The layouts, model, and options of the config, in the form of XKB.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Keymap<'a> {
+    /// Each layout with its variant, beginning with the primary layout.
+    pub layouts: Vec<(&'a str, &'a str)>,
+    pub model:   &'a str,
+    pub options: &'a str,
+}
+
+impl<'a> Keymap<'a> {
+    pub(crate) fn of(config: &'a Config) -> Self {
+        let primary = (config.keyboard_layout.as_str(), config.keyboard_variant.as_deref());
+        let others = config
+            .keyboard_layouts
+            .iter()
+            .map(|other| (other.layout.as_str(), other.variant.as_deref()));
+
+        Keymap {
+            layouts: Some(primary)
+                .into_iter()
+                .chain(others)
+                .map(|(layout, variant)| (layout, variant.unwrap_or("")))
+                .collect(),
+            model:   config.keyboard_model.as_deref().unwrap_or(""),
+            options: config.keyboard_options.as_deref().unwrap_or(""),
+        }
+    }
+
+    /// The layouts, such as `us,ru`.
+    pub(crate) fn layout_list(&self) -> String {
+        self.layouts.iter().map(|&(layout, _)| layout).collect::<Vec<_>>().join(",")
+    }
+
+    /// The variants of the layouts, such as `,phonetic`.
+    pub(crate) fn variant_list(&self) -> String {
+        self.layouts.iter().map(|&(_, variant)| variant).collect::<Vec<_>>().join(",")
+    }
+
+    /// Whether the desktop must be told of the layouts, as it does not read the XKB config.
+    fn is_multiple(&self) -> bool { self.layouts.len() > 1 || !self.options.is_empty() }
+
+    /// The keyfile of dconf which sets the input sources of GNOME to the layouts.
+    fn input_sources(&self) -> String {
+        let sources = self
+            .layouts
+            .iter()
+            .map(|&(layout, variant)| match variant {
+                "" => format!("('xkb', '{}')", layout),
+                variant => format!("('xkb', '{}+{}')", layout, variant),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let options = self
+            .options
+            .split(',')
+            .filter(|option| !option.is_empty())
+            .map(|option| format!("'{}'", option))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            "[org/gnome/desktop/input-sources]
+sources=[{}]
+xkb-options=[{}]
+",
+            sources, options
+        )
+    }
+}
+
+/// Writes the layouts to the configs which console-setup and systemd-vconsole-setup read. If
+/// the crypttab lists a volume to unlock at boot, the console's keymap is also included in the
+/// initramfs.
+pub(crate) fn write_layout(root: &Path, keymap: &Keymap) -> io::Result<()> {
+    let (layouts, variants) = (keymap.layout_list(), keymap.variant_list());
+    let model = if keymap.model.is_empty() { "pc105" } else { keymap.model };
+    let vars = [
+        ("XKBMODEL", model),
+        ("XKBLAYOUT", layouts.as_str()),
+        ("XKBVARIANT", variants.as_str()),
+        ("XKBOPTIONS", keymap.options),
+    ];
+
+    info!("writing the keyboard layout to /{} and /{}", DEFAULT_KEYBOARD, VCONSOLE_CONF);
+    conf_files::update_vars(&root.join(DEFAULT_KEYBOARD), &vars)?;
+    conf_files::update_vars(&root.join(VCONSOLE_CONF), &vars)?;
+
+    let crypttab = fs::read_to_string(root.join(CRYPTTAB)).unwrap_or_default();
+    let encrypted = crypttab.lines().any(|line| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#')
+    });
+
+    let initramfs_conf = root.join(INITRAMFS_CONF);
+    if encrypted && initramfs_conf.exists() {
+        info!("including the console keymap in the initramfs, for the passphrase prompt");
+        conf_files::update_vars(&initramfs_conf, &[("KEYMAP", "y")])?;
+    }
+
+    Ok(())
+}
+
+/// Sets the input sources of GNOME sessions to the layouts, if there are several, or options
+/// to switch between them.
+///
+/// Returns `true` if the dconf database must be updated.
+pub(crate) fn write_input_sources(root: &Path, keymap: &Keymap) -> io::Result<bool> {
+    if !keymap.is_multiple() || !root.join("usr/bin/dconf").exists() {
+        return Ok(false);
+    }
+
+    info!("writing the input sources of the desktop to /{}", DCONF_INPUT_SOURCES);
+    branding::write_file(&root.join(DCONF_INPUT_SOURCES), &keymap.input_sources())?;
+    branding::enable_system_db(&root.join(branding::DCONF_PROFILE))?;
+    Ok(true)
+}
+
+/// Names of XKB layouts and variants, which may not contain the separators of XKB's lists.
+fn is_xkb_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymap_lists() {
+        let keymap = Keymap {
+            layouts: vec![("us", ""), ("ru", "phonetic")],
+            model:   "",
+            options: "grp:alt_shift_toggle",
+        };
+
+        assert_eq!(keymap.layout_list(), "us,ru");
+        assert_eq!(keymap.variant_list(), ",phonetic");
+        assert_eq!(
+            keymap.input_sources(),
+            "[org/gnome/desktop/input-sources]\n\
+             sources=[('xkb', 'us'), ('xkb', 'ru+phonetic')]\n\
+             xkb-options=['grp:alt_shift_toggle']\n"
+        );
+
+        let single = Keymap { layouts: vec![("de", "nodeadkeys")], model: "pc105", options: "" };
+        assert!(!single.is_multiple());
+
+        assert!(KeyboardLayout { layout: "us,ru".into(), variant: None }.validate().is_err());
+        assert!(validate_options("grp:alt_shift_toggle,compose:ralt").is_ok());
+        assert!(validate_options("grp:toggle\"").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! The desktop settings which were changed in the live session, such as the theme, the dock, and
+//! the arrangement of displays, which are carried over to the new user, so that customizations
+//! made before the install are kept.
+//!
+//! The live user's dconf database is dumped and compiled again for the new user within the
+//! install, without the settings which only suit the live session, such as its disabled screen
+//! lock, and without the installer's launcher in the dock.
+
+use crate::misc;
+use std::{fs, io, path::Path};
+
+/// The directory of the install in which the live user's settings are dumped.
+pub(crate) const STAGING: &str = "tmp/distinst-live-settings";
+
+const DCONF_USER_DB: &str = ".config/dconf/user";
+const MONITORS_XML: &str = ".config/monitors.xml";
+
+/// Sections of dconf, and those beneath them, which the live session configures for itself.
+const LIVE_SECTIONS: &[&str] = &[
+    "org/gnome/desktop/lockdown",
+    "org/gnome/desktop/screensaver",
+    "org/gnome/desktop/session",
+    "org/gnome/settings-daemon/plugins/power",
+    "org/gnome/software",
+];
+
+/// Copies the live user's dconf database into the staging directory of the install at `root`,
+/// with a profile which reads only that database. Returns `false` if the live user has none.
+pub(crate) fn stage(live_home: &Path, root: &Path) -> io::Result<bool> {
+    let database = live_home.join(DCONF_USER_DB);
+    if !database.is_file() {
+        return Ok(false);
+    }
+
+    let staging = root.join(STAGING);
+    fs::create_dir_all(staging.join("dconf"))?;
+    fs::create_dir_all(staging.join("keyfiles"))?;
+    fs::copy(&database, staging.join("dconf/user"))?;
+    misc::write(staging.join("profile"), "user-db:user\n")?;
+    Ok(true)
+}
+
+/// Writes the settings of a dump of the live user's database as a keyfile, which dconf
+/// compiles into the new user's database.
+pub(crate) fn write_keyfile(root: &Path, dump: &str) -> io::Result<bool> {
+    let settings = user_settings(dump);
+    if settings.trim().is_empty() {
+        return Ok(false);
+    }
+
+    misc::write(root.join(STAGING).join("keyfiles/00-live-session"), settings)?;
+    Ok(true)
+}
+
+/// Copies the arrangement of displays of the live session into the home of `user`.
+pub(crate) fn copy_monitors(live_home: &Path, root: &Path, user: &str) -> io::Result<bool> {
+    let monitors = live_home.join(MONITORS_XML);
+    if !monitors.is_file() {
+        return Ok(false);
+    }
+
+    let target = root.join("home").join(user).join(MONITORS_XML);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::copy(&monitors, &target)?;
+    Ok(true)
+}
+
+/// The path of the new user's database, relative to the root of the install.
+pub(crate) fn user_database(user: &str) -> String { ["/home/", user, "/", DCONF_USER_DB].concat() }
+
+/// The settings of a dump of dconf, without those of `LIVE_SECTIONS`, and with the installer
+/// removed from the favorites of the dock.
+fn user_settings(dump: &str) -> String {
+    let mut output = String::with_capacity(dump.len());
+    let mut section = "";
+    let mut skip = false;
+
+    for line in dump.lines() {
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            section = name;
+            skip = LIVE_SECTIONS.iter().any(|live| {
+                name == *live || (name.starts_with(live) && name[live.len()..].starts_with('/'))
+            });
+        }
+
+        if skip {
+            continue;
+        }
+
+        match line.strip_prefix("favorite-apps=") {
+            Some(apps) if section == "org/gnome/shell" => {
+                output.push_str("favorite-apps=");
+                output.push_str(&without_installer(apps));
+            }
+            _ => output.push_str(line),
+        }
+
+        output.push('\n');
+    }
+
+    output
+}
+
+/// A list of desktop files, such as `['firefox.desktop', 'io.elementary.installer.desktop']`,
+/// without the launcher of the installer.
+fn without_installer(apps: &str) -> String {
+    let apps = apps.trim().trim_start_matches('[').trim_end_matches(']');
+    let apps: Vec<&str> = apps
+        .split(',')
+        .map(str::trim)
+        .filter(|app| !app.is_empty() && !app.contains("installer"))
+        .collect();
+
+    ["[", &apps.join(", "), "]"].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn live_session_settings() {
+        let dump = "[org/gnome/desktop/interface]\ngtk-theme='Pop-dark'\n\n\
+                    [org/gnome/desktop/screensaver]\nlock-enabled=false\n\n\
+                    [org/gnome/desktop/session]\nidle-delay=uint32 0\n\n\
+                    [org/gnome/desktop/sessions]\nkept=true\n\n\
+                    [org/gnome/shell]\nfavorite-apps=['firefox.desktop', \
+                    'io.elementary.installer.desktop', 'org.gnome.Nautilus.desktop']\n";
+
+        assert_eq!(
+            user_settings(dump),
+            "[org/gnome/desktop/interface]\ngtk-theme='Pop-dark'\n\n\
+             [org/gnome/desktop/sessions]\nkept=true\n\n\
+             [org/gnome/shell]\nfavorite-apps=['firefox.desktop', 'org.gnome.Nautilus.desktop']\n"
+        );
+
+        assert_eq!(without_installer("['io.elementary.installer.desktop']"), "[]");
+    }
+}
@@ -1,13 +1,55 @@
 use crate::bootloader::Bootloader;
+mod apt_sources;
+mod autologin;
+mod boot_menu;
+mod branding;
 mod chroot_conf;
+mod clock;
+mod cloud_init;
+mod conf_files;
+mod console;
+mod directory;
+mod hidpi;
+mod kernels;
+mod keyboard;
+mod live_settings;
+mod network;
+mod network_mounts;
+mod oem_packages;
+mod old_boots;
+mod server;
+mod snapshots;
+mod users;
 use self::chroot_conf::ChrootConfigurator;
-use super::{mount_cdrom, mount_efivars};
+pub(crate) use self::keyboard::validate_options as validate_keyboard_options;
+pub use self::{
+    apt_sources::AptSources,
+    boot_menu::{BootDefault, BootMenu, DUAL_BOOT_TIMEOUT},
+    branding::Branding,
+    cloud_init::CloudInit,
+    console::{ConsoleSettings, SerialConsole},
+    directory::{DirectoryEnrollment, JoinCredential},
+    kernels::KernelSelection,
+    keyboard::KeyboardLayout,
+    network::{InterfaceKind, NetworkConfig, NetworkInterface},
+    network_mounts::{CifsCredentials, MountProtocol, NetworkMount},
+    oem_packages::OemPackage,
+    old_boots::OldBootPolicy,
+    server::{FirewallPolicy, SshServer},
+    snapshots::SnapshotTool,
+    users::{is_valid_date, is_valid_name, SudoPolicy, DEFAULT_GROUPS},
+};
+use super::{
+    configure_read_only_root, mount_cdrom, mount_efivars, read_only_root_fstab, Substep,
+    READ_ONLY_ROOT_PACKAGES,
+};
 use crate::installer::{conf::RecoveryEnv, steps::normalize_os_release_name};
 use crate::chroot::Chroot;
+use crate::crypt::Scheme;
 use crate::distribution;
 use crate::errors::*;
 use crate::external::remount_rw;
-use crate::hardware_support;
+use crate::hardware_support::{self, virtualization::Hypervisor};
 use crate::installer::traits::InstallerDiskOps;
 use libc;
 use crate::misc;
@@ -24,7 +66,7 @@ use tempdir::TempDir;
 use crate::timezones::Region;
 use crate::Config;
 use crate::UserAccountCreate;
-use crate::INSTALL_HARDWARE_SUPPORT;
+use crate::{IMAGE_MODE, INSTALL_HARDWARE_SUPPORT};
 use crate::RUN_UBUNTU_DRIVERS;
 
 /// Self-explanatory -- the fstab file will be generated with this header.
@@ -64,7 +106,7 @@ macro_rules! map_errors {
     }
 }
 
-pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i32)>(
+pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(Substep, i32)>(
     recovery_conf: Option<&mut RecoveryEnv>,
     disks: &D,
     mount_dir: P,
@@ -73,20 +115,49 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
     region: Option<&Region>,
     user: Option<&UserAccountCreate>,
     remove_pkgs: &[S],
+    local_rtc: Option<bool>,
+    dual_boot: bool,
     mut callback: F,
-) -> io::Result<()> {
+) -> io::Result<Vec<OemPackage>> {
     let mount_dir = mount_dir.as_ref().canonicalize().unwrap();
     info!("Configuring on {}", mount_dir.display());
     let tpath = mount_dir.join("tmp");
     let configure_dir = TempDir::new_in(&tpath, "distinst")
         .with_context(|err| format!("creating tempdir at {:?}: {}", tpath, err))?;
 
+    // The users of an image which is seeded with cloud-init are created by it on first boot.
+    let user = match user {
+        Some(user) if config.cloud_init.is_some() => {
+            warn!("{} was not created, as cloud-init creates the users", user.username);
+            None
+        }
+        user => user,
+    };
+
+    // A container shares the kernel of its host, and is not booted by firmware.
+    let container = disks.is_container();
+    let bootloader_packages = if container {
+        &[][..]
+    } else {
+        distribution::debian::get_bootloader_packages(&iso_os_release)
+    };
+
+    let mut oem_packages = Vec::new();
     let install_pkgs = &mut cascade! {
         Vec::with_capacity(32);
-        ..extend_from_slice(distribution::debian::get_bootloader_packages(&iso_os_release));
+        ..extend_from_slice(bootloader_packages);
+        ..extend_from_slice(config.snapshots.map_or(&[][..], SnapshotTool::packages));
+        ..extend(config.firewall.map(|_| "ufw"));
+        ..extend(config.ssh_server.as_ref().map(|_| "openssh-server"));
+        ..extend_from_slice(config.cloud_init.as_ref().map_or(&[][..], |_| cloud_init::PACKAGES));
+        ..extend(config.network_mounts.iter().map(|mount| mount.protocol.package()));
+        ..extend_from_slice(config.directory.as_ref().map_or(&[][..], |_| directory::PACKAGES));
+        ..extend_from_slice(
+            config.read_only_root.as_ref().map_or(&[][..], |_| READ_ONLY_ROOT_PACKAGES)
+        );
     };
 
-    callback(5);
+    callback(Substep::Fstab, 5);
 
     let lvm_autodetection = || {
         // Ubuntu's LVM auto-detection doesn't seem to work for activating root volumes.
@@ -99,41 +170,65 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
 
     let generate_fstabs = || {
         let (crypttab, fstab) = disks.generate_fstabs();
+        let fstab = if config.read_only_root.is_some() {
+            read_only_root_fstab(&fstab.to_string_lossy()).into()
+        } else {
+            fstab
+        };
 
-        let (a, b) = rayon::join(
-            || {
-                info!("writing /etc/crypttab");
-                file_create!(&mount_dir.join("etc/crypttab"), [crypttab.as_bytes()]);
-                Ok(())
-            },
-            || {
-                info!("writing /etc/fstab");
-                file_create!(&mount_dir.join("etc/fstab"), [FSTAB_HEADER, fstab.as_bytes()]);
-                Ok(())
-            },
+        info!("writing /etc/crypttab");
+        file_create!(&mount_dir.join("etc/crypttab"), [crypttab.as_bytes()]);
+
+        let network_fstab = network_mounts::configure(&mount_dir, &config.network_mounts)?;
+
+        info!("writing /etc/fstab");
+        file_create!(
+            &mount_dir.join("etc/fstab"),
+            [FSTAB_HEADER, fstab.as_bytes(), network_fstab.as_bytes()]
         );
+        Ok(())
+    };
 
-        a.and(b)
+    // Guest tools are only installed for the hypervisor that the install will run within, which
+    // is not the one that an image is built within.
+    let hypervisor = if config.flags & (INSTALL_HARDWARE_SUPPORT | IMAGE_MODE)
+        == INSTALL_HARDWARE_SUPPORT
+        && !container
+    {
+        Hypervisor::detect()
+    } else {
+        None
     };
 
+    // The target is only mounted within the install's mount namespace, which the threads of
+    // rayon's global pool were spawned outside of, so a pool is spawned from this thread.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .build()
+        .map_err(|why| io::Error::new(io::ErrorKind::Other, why))?;
+
     let configure_graphics = {
         let mut b: io::Result<()> = Ok(());
         let mut c: io::Result<()> = Ok(());
         let mut configure_graphics = Ok(false);
 
-        rayon::scope(|s| {
-            s.spawn(|_| b = lvm_autodetection());
+        pool.scope(|s| {
+            if !container {
+                s.spawn(|_| b = lvm_autodetection());
+            }
             s.spawn(|_| c = generate_fstabs());
             s.spawn(|_| {
                 if config.flags & INSTALL_HARDWARE_SUPPORT != 0 {
                     hardware_support::append_packages(install_pkgs, &iso_os_release);
                 }
 
-                configure_graphics = hardware_support::switchable_graphics::configure_graphics(&mount_dir);
+                if config.flags & IMAGE_MODE == 0 && !container {
+                    configure_graphics =
+                        hardware_support::switchable_graphics::configure_graphics(&mount_dir);
+                }
             });
         });
 
-        callback(10);
+        callback(Substep::Fstab, 10);
         map_errors! {
             b => "lvm autodetection error";
             c => "failed to generate fstab / crypttab"
@@ -154,40 +249,43 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
             ..env("PATH", "/usr/sbin:/usr/bin:/sbin:/bin");
         };
 
-        let efivars_mount = mount_efivars(&mount_dir)?;
+        let efivars_mount = if container { None } else { mount_efivars(&mount_dir)? };
         let cdrom_mount = mount_cdrom(&mount_dir)?;
 
-        callback(15);
+        callback(Substep::Fstab, 15);
 
-        let root_entry = disks.get_block_info_of("/")?;
+        let root_entry = if container { None } else { Some(disks.get_block_info_of("/")?) };
         let _recovery_entry = disks.get_block_info_of("/recovery");
 
-        callback(20);
-
-        let luks_uuid = root_entry
-            .uid
-            .get_device_path()
-            .and_then(|ref path| {
-                misc::resolve_to_physical(path.file_name().unwrap().to_str().unwrap())
-            })
-            .and_then(PartitionID::get_uuid)
-            .and_then(|uuid| if uuid == root_entry.uid { None } else { Some(uuid) });
+        callback(Substep::Fstab, 20);
+
+        let luks_uuid = root_entry.as_ref().and_then(|root_entry| {
+            root_entry
+                .uid
+                .get_device_path()
+                .and_then(|ref path| {
+                    misc::resolve_to_physical(path.file_name().unwrap().to_str().unwrap())
+                })
+                .and_then(PartitionID::get_uuid)
+                .and_then(|uuid| if uuid == root_entry.uid { None } else { Some(uuid) })
+        });
 
-        callback(25);
+        callback(Substep::Fstab, 25);
 
-        let root_uuid = &root_entry.uid;
+        let root_uuid = root_entry.as_ref().map_or("", |root_entry| root_entry.uid.id.as_str());
         if let Some(conf) = recovery_conf {
             update_recovery_config(
                 conf,
                 &mount_dir,
-                &root_uuid.id,
+                root_uuid,
                 luks_uuid.as_ref().map(|x| x.id.as_str()),
+                config.old_boots,
             )?;
         }
 
-        callback(30);
+        callback(Substep::Locales, 30);
 
-        let (retain, lang_output) = rayon::join(
+        let (retain, lang_output) = pool.join(
             // Get packages required by this disk configuration.
             || distribution::debian::get_required_packages(disks, iso_os_release),
             // Attempt to run the check-language-support external command.
@@ -240,7 +338,7 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
             Bootloader::Efi => (),
         }
 
-        callback(35);
+        callback(Substep::Locales, 35);
 
         // TODO: use a macro to make this more manageable.
         let chroot = ChrootConfigurator::new(chroot);
@@ -249,10 +347,16 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
 
         let hostname = chroot.hostname(&config.hostname);
         let hosts = chroot.hosts(&config.hostname);
-        let machine_id = chroot.generate_machine_id();
+        let image_mode = config.flags & IMAGE_MODE != 0;
+        // systemd-nspawn and LXC give each container a machine ID, if it has none.
+        let machine_id = if image_mode || container {
+            chroot.defer_machine_id()
+        } else {
+            chroot.generate_machine_id()
+        };
         let netresolv = chroot.netresolve();
         let locale = chroot.generate_locale(&config.lang);
-        let kernel_copy = chroot.kernel_copy();
+        let kernel_copy = if container { Ok(()) } else { chroot.kernel_copy() };
 
         let timezone = if let Some(tz) = region {
             chroot.timezone(tz)
@@ -260,21 +364,72 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
             Ok(())
         };
 
+        let clock = chroot.clock(&config.ntp_servers, local_rtc);
+
         let useradd = if let Some(ref user) = user {
-            chroot.create_user(
-                &user.username,
-                user.password.as_deref(),
-                user.realname.as_deref(),
-                user.profile_icon.as_deref(),
-            )
+            let created = user.password_hash(Scheme::preferred(&mount_dir)).and_then(|hash| {
+                chroot.create_user(user, hash.as_deref())
+            });
+
+            // The names of the directories are a convenience, which the user may change.
+            if created.is_ok() {
+                if let Err(why) = chroot.user_dirs(&user.username, &config.lang) {
+                    warn!("unable to create the user directories: {}", why);
+                }
+
+                if let Some(ref live_home) = config.live_settings {
+                    if let Err(why) = chroot.live_settings(live_home, &user.username) {
+                        warn!("unable to copy the settings of the live session: {}", why);
+                    }
+                }
+            }
+
+            created
         } else {
             Ok(())
         };
 
+        callback(Substep::Packages, 40);
+
         let apt_install = chroot
             .cdrom_add()
             .and_then(|_| chroot.apt_install(&install_pkgs))
-            .and_then(|_| chroot.install_drivers(config.flags & RUN_UBUNTU_DRIVERS != 0))
+            .and_then(|_| {
+                // Adds snapper's snapshots to the GRUB menu, if the archive provides it.
+                if config.snapshots == Some(SnapshotTool::Snapper)
+                    && Bootloader::detect() == Bootloader::Bios
+                {
+                    if let Err(why) = chroot.apt_install(&["grub-btrfs"]) {
+                        warn!("unable to install grub-btrfs: {}", why);
+                    }
+                }
+
+                // The guest tools are installed apart from the other packages, as an archive
+                // which lacks them should not fail the install.
+                if let Some(hypervisor) = hypervisor {
+                    let packages = hypervisor.guest_packages(&iso_os_release);
+                    if !packages.is_empty() {
+                        info!("installing the guest tools of {:?}", hypervisor);
+                        if let Err(why) = chroot.apt_install(packages) {
+                            warn!("unable to install the guest tools of {:?}: {}", hypervisor, why);
+                        }
+                    }
+                }
+
+                Ok(())
+            })
+            .and_then(|_| {
+                callback(Substep::Drivers, 60);
+                chroot.install_drivers(config.flags & RUN_UBUNTU_DRIVERS != 0 && !container)
+            })
+            .and_then(|_| {
+                // Dependencies of the vendor's packages are resolved from the cdrom as well.
+                if let Some(ref dir) = config.oem_packages {
+                    oem_packages = chroot.oem_packages(dir, configure_dir.path())?;
+                }
+
+                Ok(())
+            })
             .and_then(|_| chroot.cdrom_disable());
 
         map_errors! {
@@ -286,42 +441,190 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
             apt_install => "error installing packages";
             kernel_copy => "error copying kernel from casper to chroot";
             timezone => "error setting timezone";
+            clock => "error configuring the clock";
             useradd => "error creating user account"
         }
 
-        callback(70);
+        if let Some(tool) = config.snapshots {
+            chroot.snapshots(tool, root_uuid).with_context(|why| {
+                format!("error configuring {:?} snapshots: {}", tool, why)
+            })?;
+        }
+
+        if let Some(ref sources) = config.apt_sources {
+            chroot
+                .apt_sources(sources, &iso_os_release.version_codename)
+                .with_context(|why| format!("error writing apt sources: {}", why))?;
+        }
+
+        if let Some(ref network) = config.network {
+            chroot
+                .network(network)
+                .with_context(|why| format!("error writing the network configuration: {}", why))?;
+        }
+
+        if let Some(ref server) = config.ssh_server {
+            let user = user.map(|user| user.username.as_str());
+            chroot
+                .ssh_server(server, user)
+                .with_context(|why| format!("error enabling the SSH server: {}", why))?;
+        }
+
+        if config.autologin {
+            match user {
+                Some(user) => chroot
+                    .autologin(&user.username)
+                    .with_context(|why| format!("error enabling autologin: {}", why))?,
+                None => warn!("no user account was created, so autologin was not enabled"),
+            }
+        }
+
+        if let Some(policy) = config.firewall {
+            chroot
+                .firewall(policy, config.ssh_server.is_some())
+                .with_context(|why| format!("error enabling the firewall: {}", why))?;
+        }
+
+        if let Some(ref enrollment) = config.directory {
+            chroot
+                .directory(enrollment)
+                .with_context(|why| format!("error staging the domain join: {}", why))?;
+        }
+
+        if let Some(ref root) = config.read_only_root {
+            configure_read_only_root(&mount_dir, root)
+                .with_context(|why| format!("error configuring the read-only root: {}", why))?;
+        }
+
+        if let Some(ref branding) = config.branding {
+            chroot
+                .branding(branding)
+                .with_context(|why| format!("error installing branding: {}", why))?;
+        }
+
+        callback(Substep::Packages, 70);
+
+        // The kernels are selected from once the drivers, which may bring their own, are in.
+        let kernel = config
+            .kernel
+            .as_ref()
+            .and_then(|kernel| kernels::select(&mount_dir, kernel, image_mode));
+        let kernel_packages = match (&kernel, &config.kernel) {
+            (Some(release), Some(selection)) if selection.remove_others => {
+                kernels::other_packages(&mount_dir, release)
+            }
+            _ => Vec::new(),
+        };
+        remove.extend(kernel_packages.iter().map(String::as_str));
 
         let apt_remove = chroot.apt_remove(&remove);
-        let recovery = chroot.recovery(
-            config,
-            &normalize_os_release_name(&iso_os_release.name),
-            &root_uuid.id,
-            luks_uuid.as_ref().map_or("", |ref uuid| uuid.id.as_str()),
-        );
+
+        callback(Substep::Recovery, 72);
+
+        let recovery = if container {
+            Ok(())
+        } else {
+            chroot.recovery(
+                config,
+                &normalize_os_release_name(&iso_os_release.name),
+                root_uuid,
+                luks_uuid.as_ref().map_or("", |ref uuid| uuid.id.as_str()),
+            )
+        };
 
         map_errors! {
             apt_remove => "error removing packages";
             recovery => "error creating recovery partition"
         }
 
-        callback(75);
+        callback(Substep::Bootloader, 75);
 
-        chroot.bootloader().with_context(|why| format!("error installing bootloader: {}", why))?;
+        if let Some(ref release) = kernel {
+            kernels::grub_default(&mount_dir, release, root_uuid)
+                .with_context(|why| format!("error selecting the default kernel: {}", why))?;
+        }
 
-        callback(80);
+        if !container {
+            // A serial console which was configured supersedes that of the hypervisor.
+            let serial = config.console.as_ref().and_then(|console| console.serial.as_ref());
+            let options = match serial {
+                Some(serial) => Some(serial.kernel_options()),
+                None => hypervisor.and_then(Hypervisor::kernel_options).map(String::from),
+            };
+
+            // GRUB's defaults are read as its menu is generated, and so are set before it is.
+            config
+                .boot_menu
+                .configure_grub(&mount_dir, dual_boot)
+                .with_context(|why| format!("error configuring the boot menu: {}", why))?;
+
+            chroot
+                .bootloader(kernel.as_deref(), options.as_deref())
+                .with_context(|why| format!("error installing bootloader: {}", why))?;
+
+            config
+                .boot_menu
+                .configure_loader(&mount_dir, dual_boot)
+                .with_context(|why| format!("error configuring the boot menu: {}", why))?;
+        }
+
+        callback(Substep::Keyboard, 80);
 
         if configure_graphics {
             chroot.disable_nvidia_fallback();
         }
 
+        // Displays are detected on this system, which is not the one that an image is for.
+        let scale = if config.hidpi && !image_mode && !container {
+            hardware_support::display::scale_factor()
+        } else {
+            1
+        };
+
+        if scale > 1 {
+            chroot
+                .hidpi(scale)
+                .with_context(|why| format!("error configuring display scaling: {}", why))?;
+        }
+
+        // The console's font is written before the keyboard layout, whose reload of
+        // console-setup applies it.
+        let mut console = config.console.clone();
+        if scale > 1 {
+            let console = console.get_or_insert_with(ConsoleSettings::default);
+            if console.font_face.is_none() && console.font_size.is_none() {
+                let (face, size) = hidpi::HIDPI_CONSOLE_FONT;
+                console.font_face = Some(face.into());
+                console.font_size = Some(size.into());
+            }
+        }
+
+        if let Some(ref console) = console {
+            chroot
+                .console(console)
+                .with_context(|why| format!("error configuring the console: {}", why))?;
+        }
+
         chroot
             .keyboard_layout(config)
             .with_context(|why| format!("error setting keyboard layout: {}", why))?;
-        callback(85);
+        callback(Substep::Initramfs, 85);
+
+        if let Some(ref cloud_init) = config.cloud_init {
+            chroot
+                .cloud_init(cloud_init)
+                .with_context(|why| format!("error seeding cloud-init: {}", why))?;
+        }
+
+        if image_mode {
+            chroot
+                .defer_host_keys()
+                .with_context(|why| format!("error deferring host keys: {}", why))?;
+        }
 
         chroot.initramfs_reenable()?;
 
-        callback(90);
+        callback(Substep::Initramfs, 90);
 
         // Sync to the disk before unmounting
         unsafe {
@@ -335,13 +638,13 @@ pub fn configure<D: InstallerDiskOps, P: AsRef<Path>, S: AsRef<str>, F: FnMut(i3
         }
 
         drop(efivars_mount);
-        callback(95);
+        callback(Substep::Initramfs, 95);
     }
 
     configure_dir.close()?;
-    callback(100);
+    callback(Substep::Initramfs, 100);
 
-    Ok(())
+    Ok(oem_packages)
 }
 
 fn update_recovery_config(
@@ -349,57 +652,37 @@ fn update_recovery_config(
     mount: &Path,
     root_uuid: &str,
     luks_uuid: Option<&str>,
+    policy: OldBootPolicy,
 ) -> io::Result<()> {
-    fn remove_boot(mount: &Path, uuid: &str) -> io::Result<()> {
-        let efi_path = mount.join("boot/efi/EFI");
-        let readdir = efi_path
-            .read_dir()
-            .with_context(|err| format!("error reading dir at {:?}: {}", efi_path, err))?;
-
-        for directory in readdir {
-            let entry =
-                directory.with_context(|err| format!("bad entry in {:?}: {}", efi_path, err))?;
-            let full_path = entry.path();
-            if let Some(path) = entry.file_name().to_str() {
-                if path.ends_with(uuid) {
-                    fs::remove_dir_all(&full_path).with_context(|err| {
-                        format!("error removing old boot files for {}: {}", path, err)
-                    })?;
-                }
-            }
-        }
-
-        Ok(())
-    }
-
     let recovery_path = Path::new("/cdrom/recovery.conf");
     if recovery_path.exists() {
         let luks_value = luks_uuid.map_or("", |uuid| if root_uuid == uuid { "" } else { uuid });
         recovery_conf.update("LUKS_UUID", luks_value);
 
         remount_rw("/cdrom")
-            .with_context(|err| format!("could not remount /cdrom as rw: {}", err))
-            .and_then(|_| {
-                recovery_conf.update("OEM_MODE", "0");
-                recovery_conf
-                    .get("ROOT_UUID")
-                    .into_io_result(|| "no ROOT_UUID found in /cdrom/recovery.conf")
-            })
-            .map(|old_uuid| {
-                let res = remove_boot(mount, old_uuid).with_context(|err| {
-                    format!("unable to remove an older boot from recovery.conf: {}", err)
-                });
+            .with_context(|err| format!("could not remount /cdrom as rw: {}", err))?;
+        recovery_conf.update("OEM_MODE", "0");
+
+        let old_uuid = recovery_conf
+            .get("ROOT_UUID")
+            .into_io_result(|| "no ROOT_UUID found in /cdrom/recovery.conf")?
+            .to_owned();
+
+        if old_uuid != root_uuid {
+            let previous = recovery_conf.get(old_boots::OLD_ROOTS).unwrap_or("").to_owned();
+            let efi = mount.join("boot/efi");
+            let kept = old_boots::retire(&efi, &previous, &old_uuid, policy);
+            if kept.is_empty() {
+                recovery_conf.remove(old_boots::OLD_ROOTS);
+            } else {
+                recovery_conf.update(old_boots::OLD_ROOTS, &kept);
+            }
+        }
 
-                if let Err(why) = res {
-                    warn!("{}", why);
-                }
-            })
-            .and_then(|_| {
-                recovery_conf.update("ROOT_UUID", root_uuid);
-                recovery_conf
-                    .write()
-                    .with_context(|err| format!("error writing recovery conf: {}", err))
-            })?;
+        recovery_conf.update("ROOT_UUID", root_uuid);
+        recovery_conf
+            .write()
+            .with_context(|err| format!("error writing recovery conf: {}", err))?;
     }
 
     Ok(())
@@ -0,0 +1,339 @@
+//! Static network configuration of the installed system, which is written as netplan, for
+//! servers which are installed where DHCP is not available.
+
+use crate::hostname;
+use std::{collections::HashSet, io, net::IpAddr};
+
+/// The bonding modes of the kernel, which netplan passes on as they are.
+const BOND_MODES: &[&str] = &[
+    "balance-rr",
+    "active-backup",
+    "balance-xor",
+    "broadcast",
+    "802.3ad",
+    "balance-tlb",
+    "balance-alb",
+];
+
+/// The kind of a network interface, and the interfaces that it is composed of.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InterfaceKind {
+    Ethernet,
+    /// Aggregates the given ethernet interfaces, with a bonding mode such as `802.3ad`.
+    Bond { interfaces: Vec<String>, mode: String },
+    /// Tags the traffic of the `link` interface with a VLAN ID.
+    Vlan { id: u16, link: String },
+}
+
+impl InterfaceKind {
+    /// The section of netplan that interfaces of this kind are defined in.
+    fn section(&self) -> &'static str {
+        match *self {
+            InterfaceKind::Ethernet => "ethernets",
+            InterfaceKind::Bond { .. } => "bonds",
+            InterfaceKind::Vlan { .. } => "vlans",
+        }
+    }
+}
+
+/// A network interface, and how it obtains its addresses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkInterface {
+    pub name:        String,
+    pub kind:        InterfaceKind,
+    /// Obtains addresses with DHCP, in addition to the static addresses.
+    pub dhcp:        bool,
+    /// Static addresses, with the lengths of their prefixes.
+    pub addresses:   Vec<(IpAddr, u8)>,
+    /// Default gateways, of which there may be one for each address family.
+    pub gateways:    Vec<IpAddr>,
+    pub nameservers: Vec<IpAddr>,
+    /// Domains which are searched for unqualified host names.
+    pub search:      Vec<String>,
+}
+
+impl NetworkInterface {
+    /// An interface without addresses, which may be the member of a bond.
+    pub fn new<S: Into<String>>(name: S, kind: InterfaceKind) -> Self {
+        NetworkInterface {
+            name: name.into(),
+            kind,
+            dhcp: false,
+            addresses: Vec::new(),
+            gateways: Vec::new(),
+            nameservers: Vec::new(),
+            search: Vec::new(),
+        }
+    }
+}
+
+/// The network interfaces of the installed system, which are otherwise configured by
+/// NetworkManager, with DHCP.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetworkConfig {
+    pub interfaces: Vec<NetworkInterface>,
+}
+
+impl NetworkConfig {
+    /// The interface with the given name, which is defined as an ethernet interface if it was
+    /// not already defined.
+    pub fn interface(&mut self, name: &str) -> &mut NetworkInterface {
+        let position = self.interfaces.iter().position(|iface| iface.name == name);
+        let position = position.unwrap_or_else(|| {
+            self.interfaces.push(NetworkInterface::new(name, InterfaceKind::Ethernet));
+            self.interfaces.len() - 1
+        });
+
+        &mut self.interfaces[position]
+    }
+
+    /// Ensures that the interfaces and the interfaces that they refer to are defined, and that
+    /// their addresses are valid.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |why: String| Err(io::Error::new(io::ErrorKind::InvalidInput, why));
+
+        let mut names = HashSet::new();
+        for iface in &self.interfaces {
+            if iface.name.is_empty() || !iface.name.chars().all(valid_name_char) {
+                return invalid(format!("network interface name {:?} is not valid", iface.name));
+            }
+
+            if !names.insert(iface.name.as_str()) {
+                return invalid(format!("network interface {} is defined twice", iface.name));
+            }
+        }
+
+        let ethernet = |name: &str| {
+            self.interfaces
+                .iter()
+                .any(|iface| iface.name == name && iface.kind == InterfaceKind::Ethernet)
+        };
+
+        for iface in &self.interfaces {
+            match iface.kind {
+                InterfaceKind::Ethernet => (),
+                InterfaceKind::Bond { ref interfaces, ref mode } => {
+                    if interfaces.is_empty() || mode.is_empty() {
+                        return invalid(format!("bond {} lacks interfaces or a mode", iface.name));
+                    }
+
+                    if !BOND_MODES.contains(&mode.as_str()) {
+                        return invalid(format!("{:?} is not a bonding mode", mode));
+                    }
+
+                    if let Some(member) = interfaces.iter().find(|member| !ethernet(member)) {
+                        return invalid(format!(
+                            "{}, a member of bond {}, is not a defined ethernet interface",
+                            member, iface.name
+                        ));
+                    }
+                }
+                InterfaceKind::Vlan { id, ref link } => {
+                    if id == 0 || id > 4094 {
+                        return invalid(format!("VLAN ID {} of {} is not valid", id, iface.name));
+                    }
+
+                    if !names.contains(link.as_str()) {
+                        return invalid(format!(
+                            "{}, the link of VLAN {}, is not a defined interface",
+                            link, iface.name
+                        ));
+                    }
+                }
+            }
+
+            for &(address, prefix) in &iface.addresses {
+                let max = if address.is_ipv4() { 32 } else { 128 };
+                if prefix > max {
+                    return invalid(format!("prefix of {}/{} is not valid", address, prefix));
+                }
+            }
+
+            if let Some(domain) = iface.search.iter().find(|domain| !hostname::is_valid(domain)) {
+                return invalid(format!(
+                    "search domain {:?} of {} is not valid",
+                    domain, iface.name
+                ));
+            }
+
+            let has_family = |gateway: &IpAddr| {
+                iface.addresses.iter().any(|&(address, _)| address.is_ipv4() == gateway.is_ipv4())
+            };
+
+            if let Some(gateway) = iface.gateways.iter().find(|gateway| !has_family(gateway)) {
+                return invalid(format!(
+                    "gateway {} of {} has no address of its family",
+                    gateway, iface.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The configuration as netplan, which is rendered by the given backend, such as
+    /// `NetworkManager` or `networkd`.
+    pub(crate) fn netplan(&self, renderer: &str) -> String {
+        let mut yaml = format!(
+            "# Written by the installer.\nnetwork:\n  version: 2\n  renderer: {}\n",
+            renderer
+        );
+
+        for &section in &["ethernets", "bonds", "vlans"] {
+            let mut interfaces =
+                self.interfaces.iter().filter(|iface| iface.kind.section() == section).peekable();
+
+            if interfaces.peek().is_none() {
+                continue;
+            }
+
+            yaml.push_str(&format!("  {}:\n", section));
+            for iface in interfaces {
+                push_interface(&mut yaml, iface);
+            }
+        }
+
+        yaml
+    }
+}
+
+fn push_interface(yaml: &mut String, iface: &NetworkInterface) {
+    yaml.push_str(&format!("    {}:\n", iface.name));
+
+    match iface.kind {
+        InterfaceKind::Ethernet => (),
+        InterfaceKind::Bond { ref interfaces, ref mode } => {
+            yaml.push_str(&format!(
+                "      interfaces: [{}]\n      parameters:\n        mode: {}\n",
+                interfaces.join(", "),
+                mode
+            ));
+        }
+        InterfaceKind::Vlan { id, ref link } => {
+            yaml.push_str(&format!("      id: {}\n      link: {}\n", id, link));
+        }
+    }
+
+    yaml.push_str(&format!("      dhcp4: {0}\n      dhcp6: {0}\n", iface.dhcp));
+
+    if !iface.addresses.is_empty() {
+        yaml.push_str("      addresses:\n");
+        for &(address, prefix) in &iface.addresses {
+            yaml.push_str(&format!("        - \"{}/{}\"\n", address, prefix));
+        }
+    }
+
+    if !iface.gateways.is_empty() {
+        yaml.push_str("      routes:\n");
+        for gateway in &iface.gateways {
+            let to = if gateway.is_ipv4() { "0.0.0.0/0" } else { "::/0" };
+            yaml.push_str(&format!("        - to: \"{}\"\n          via: \"{}\"\n", to, gateway));
+        }
+    }
+
+    if !iface.nameservers.is_empty() || !iface.search.is_empty() {
+        let servers: Vec<String> =
+            iface.nameservers.iter().map(|server| format!("\"{}\"", server)).collect();
+        yaml.push_str(&format!(
+            "      nameservers:\n        addresses: [{}]\n        search: [{}]\n",
+            servers.join(", "),
+            iface.search.join(", ")
+        ));
+    }
+}
+
+fn valid_name_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || "-_.@".contains(character)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> NetworkConfig {
+        let bond = InterfaceKind::Bond {
+            interfaces: vec!["eno1".into(), "eno2".into()],
+            mode:       "802.3ad".into(),
+        };
+
+        NetworkConfig {
+            interfaces: vec![
+                NetworkInterface::new("eno1", InterfaceKind::Ethernet),
+                NetworkInterface::new("eno2", InterfaceKind::Ethernet),
+                NetworkInterface::new("bond0", bond),
+                NetworkInterface {
+                    addresses: vec![("192.0.2.10".parse().unwrap(), 24)],
+                    gateways: vec!["192.0.2.1".parse().unwrap()],
+                    nameservers: vec!["192.0.2.53".parse().unwrap()],
+                    search: vec!["example.org".into()],
+                    ..NetworkInterface::new(
+                        "vlan10",
+                        InterfaceKind::Vlan { id: 10, link: "bond0".into() },
+                    )
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn netplan_yaml() {
+        let config = server();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.netplan("networkd"),
+            "# Written by the installer.
+network:
+  version: 2
+  renderer: networkd
+  ethernets:
+    eno1:
+      dhcp4: false
+      dhcp6: false
+    eno2:
+      dhcp4: false
+      dhcp6: false
+  bonds:
+    bond0:
+      interfaces: [eno1, eno2]
+      parameters:
+        mode: 802.3ad
+      dhcp4: false
+      dhcp6: false
+  vlans:
+    vlan10:
+      id: 10
+      link: bond0
+      dhcp4: false
+      dhcp6: false
+      addresses:
+        - \"192.0.2.10/24\"
+      routes:
+        - to: \"0.0.0.0/0\"
+          via: \"192.0.2.1\"
+      nameservers:
+        addresses: [\"192.0.2.53\"]
+        search: [example.org]
+"
+        );
+    }
+
+    #[test]
+    fn network_validation() {
+        let mut config = server();
+        config.interfaces.remove(1);
+        assert!(config.validate().is_err());
+
+        let mut config = server();
+        config.interface("vlan10").gateways = vec!["2001:db8::1".parse().unwrap()];
+        assert!(config.validate().is_err());
+
+        let mut config = server();
+        config.interface("vlan10").search = vec!["example.org]".into()];
+        assert!(config.validate().is_err());
+
+        let mut config = server();
+        config.interface("bond0").kind =
+            InterfaceKind::Bond { interfaces: vec!["eno1".into()], mode: "lacp\n".into() };
+        assert!(config.validate().is_err());
+    }
+}
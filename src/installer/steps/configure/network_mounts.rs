@@ -0,0 +1,248 @@
+//! Network file systems which the installed system mounts from its fstab, for lab and
+//! thin-client deployments whose homes or data are served over the network.
+
+use crate::misc;
+use std::{
+    fmt,
+    fs::{self, Permissions},
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The directory of the installed system in which CIFS credentials are stored.
+const CREDENTIALS_DIR: &str = "etc/cifs-credentials";
+
+/// The protocol which a network mount is served with.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MountProtocol {
+    Nfs,
+    Cifs,
+}
+
+impl MountProtocol {
+    /// The file system type of the protocol, as it is written in the fstab.
+    pub fn fs_type(self) -> &'static str {
+        match self {
+            MountProtocol::Nfs => "nfs",
+            MountProtocol::Cifs => "cifs",
+        }
+    }
+
+    /// The package which provides the mount helper of the protocol.
+    pub(crate) fn package(self) -> &'static str {
+        match self {
+            MountProtocol::Nfs => "nfs-common",
+            MountProtocol::Cifs => "cifs-utils",
+        }
+    }
+}
+
+impl FromStr for MountProtocol {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "nfs" => Ok(MountProtocol::Nfs),
+            "cifs" | "smb" => Ok(MountProtocol::Cifs),
+            _ => Err("invalid network mount protocol"),
+        }
+    }
+}
+
+/// The account which a CIFS share is mounted with.
+#[derive(Clone, Default, PartialEq)]
+pub struct CifsCredentials {
+    pub username: String,
+    pub password: String,
+    pub domain:   Option<String>,
+}
+
+/// The password is redacted, as the config is written to the installer's logs.
+impl fmt::Debug for CifsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CifsCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("domain", &self.domain)
+            .finish()
+    }
+}
+
+impl CifsCredentials {
+    /// The contents of the credentials file, as read by `mount.cifs`.
+    fn file(&self) -> String {
+        let mut file = format!("username={}\npassword={}\n", self.username, self.password);
+        if let Some(ref domain) = self.domain {
+            file.push_str(&format!("domain={}\n", domain));
+        }
+
+        file
+    }
+}
+
+/// A network file system which is mounted at boot, once the network is up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkMount {
+    pub protocol:    MountProtocol,
+    /// The share, such as `server:/export` for NFS, or `//server/share` for CIFS.
+    pub source:      String,
+    /// The absolute path that the share is mounted at.
+    pub target:      PathBuf,
+    /// Mount options, such as `vers=4.2` or `ro`, in addition to `_netdev,nofail`.
+    pub options:     Vec<String>,
+    /// The account of a CIFS share, which is written to a file that only root may read.
+    pub credentials: Option<CifsCredentials>,
+}
+
+impl NetworkMount {
+    /// Ensures that the mount may be written to the fstab.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |why: String| Err(io::Error::new(io::ErrorKind::InvalidInput, why));
+
+        let valid_source = match self.protocol {
+            MountProtocol::Nfs => self.source.contains(":/"),
+            MountProtocol::Cifs => self.source.starts_with("//"),
+        };
+
+        if !valid_source || self.source.contains('\n') {
+            return invalid(format!(
+                "{} is not a valid {} share",
+                self.source,
+                self.protocol.fs_type()
+            ));
+        }
+
+        let target = self.target.to_str().unwrap_or("");
+        if !self.target.is_absolute() || target.contains('\n') {
+            return invalid(format!("{} is not a valid mount point", self.target.display()));
+        }
+
+        let invalid_option = self.options.iter().find(|option| {
+            option.is_empty() || option.contains(|c: char| c == ',' || c.is_whitespace())
+        });
+
+        if let Some(option) = invalid_option {
+            return invalid(format!("mount option {:?} of {} is not valid", option, self.source));
+        }
+
+        if let Some(ref credentials) = self.credentials {
+            if self.protocol != MountProtocol::Cifs {
+                return invalid(format!("{} is not a CIFS share, but has credentials", self.source));
+            }
+
+            let mut fields = [&credentials.username, &credentials.password]
+                .iter()
+                .copied()
+                .chain(credentials.domain.as_ref());
+
+            if credentials.username.is_empty() || fields.any(|field| field.contains('\n')) {
+                return invalid(format!("credentials of {} are not valid", self.source));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The line of the fstab which mounts the share, with the given credentials file.
+    fn fstab_entry(&self, credentials: Option<&Path>) -> String {
+        let mut options = self.options.clone();
+        if let Some(path) = credentials {
+            options.push(format!("credentials={}", path.display()));
+        }
+
+        options.push("_netdev".into());
+        options.push("nofail".into());
+
+        format!(
+            "{}  {}  {}  {}  0  0\n",
+            escape(&self.source),
+            escape(&self.target.to_string_lossy()),
+            self.protocol.fs_type(),
+            options.join(",")
+        )
+    }
+}
+
+/// Creates the mount points and credentials files of the mounts within `root`, and returns
+/// their entries of the fstab.
+pub(crate) fn configure(root: &Path, mounts: &[NetworkMount]) -> io::Result<String> {
+    let mut fstab = String::new();
+    for (id, mount) in mounts.iter().enumerate() {
+        info!("adding {} to /etc/fstab", mount.source);
+        fs::create_dir_all(root.join(mount.target.strip_prefix("/").unwrap_or(&mount.target)))?;
+
+        let credentials = match mount.credentials {
+            Some(ref credentials) => {
+                let dir = root.join(CREDENTIALS_DIR);
+                fs::create_dir_all(&dir)?;
+                fs::set_permissions(&dir, Permissions::from_mode(0o700))?;
+
+                let name = format!("mount{}", id);
+                let mut file = misc::create_private(dir.join(&name))?;
+                file.write_all(credentials.file().as_bytes())?;
+                file.sync_all()?;
+                Some(Path::new("/").join(CREDENTIALS_DIR).join(name))
+            }
+            None => None,
+        };
+
+        fstab.push_str(&mount.fstab_entry(credentials.as_deref()));
+    }
+
+    Ok(fstab)
+}
+
+/// Escapes the whitespace of a field of the fstab, as described by fstab(5).
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\134").replace(' ', "\\040").replace('\t', "\\011")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_fstab_entries() {
+        let nfs = NetworkMount {
+            protocol:    MountProtocol::Nfs,
+            source:      "nas.example:/srv/home".into(),
+            target:      PathBuf::from("/home"),
+            options:     vec!["vers=4.2".into()],
+            credentials: None,
+        };
+
+        assert!(nfs.validate().is_ok());
+        assert_eq!(
+            nfs.fstab_entry(None),
+            "nas.example:/srv/home  /home  nfs  vers=4.2,_netdev,nofail  0  0\n"
+        );
+
+        let cifs = NetworkMount {
+            protocol:    MountProtocol::Cifs,
+            source:      "//nas.example/Lab Data".into(),
+            target:      PathBuf::from("/srv/lab data"),
+            options:     Vec::new(),
+            credentials: Some(CifsCredentials {
+                username: "lab".into(),
+                password: "secret".into(),
+                domain:   Some("EXAMPLE".into()),
+            }),
+        };
+
+        assert!(cifs.validate().is_ok());
+        assert_eq!(
+            cifs.fstab_entry(Some(Path::new("/etc/cifs-credentials/mount1"))),
+            "//nas.example/Lab\\040Data  /srv/lab\\040data  cifs  \
+             credentials=/etc/cifs-credentials/mount1,_netdev,nofail  0  0\n"
+        );
+        assert_eq!(
+            cifs.credentials.as_ref().unwrap().file(),
+            "username=lab\npassword=secret\ndomain=EXAMPLE\n"
+        );
+
+        let nfs_credentials = NetworkMount { protocol: MountProtocol::Nfs, ..cifs };
+        assert!(nfs_credentials.validate().is_err());
+    }
+}
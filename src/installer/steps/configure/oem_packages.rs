@@ -0,0 +1,52 @@
+//! Vendor packages which are shipped on the install media, such as out-of-tree drivers, and
+//! which are installed into the target along with their dependencies.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The outcome of installing one of the vendor's packages.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OemPackage {
+    /// The file name of the package.
+    pub name:      String,
+    /// Whether the package, and its dependencies, were installed.
+    pub installed: bool,
+}
+
+/// The `.deb` files within `dir`, in the order that they are installed.
+pub(crate) fn debs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut debs = Vec::new();
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().map_or(false, |ext| ext == "deb") {
+            debs.push(path);
+        }
+    }
+
+    debs.sort();
+    Ok(debs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn oem_debs() {
+        let tempdir = TempDir::new("distinst-oem").unwrap();
+        let dir = tempdir.path();
+        fs::create_dir_all(dir.join("nested.deb")).unwrap();
+        for file in &["wifi-dkms_1.0_amd64.deb", "Packages.gz", "audio-firmware_2.1_all.deb"] {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+
+        assert_eq!(
+            debs(dir).unwrap(),
+            vec![dir.join("audio-firmware_2.1_all.deb"), dir.join("wifi-dkms_1.0_amd64.deb")]
+        );
+    }
+}
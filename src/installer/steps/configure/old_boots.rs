@@ -0,0 +1,212 @@
+//! The boot files which replaced installs leave on the ESP: their directories of kernels, and
+//! the systemd-boot entries which load them.
+//!
+//! The roots of replaced installs whose boot files are kept are recorded in the recovery
+//! partition's config, newest first, so that a later install may remove them.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The key of the recovery config which records the roots of replaced installs.
+pub(crate) const OLD_ROOTS: &str = "OLD_ROOT_UUIDS";
+
+/// The most replaced installs which are recorded. The oldest are forgotten beyond it.
+const MAX_OLD_ROOTS: usize = 16;
+
+/// What becomes of the boot files of the installs which a reinstall replaces.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OldBootPolicy {
+    /// Removes the boot files of replaced installs, except those of the `keep` most recent.
+    Remove { keep: usize },
+    /// Lists the boot files which `Remove` would remove, without removing them.
+    DryRun { keep: usize },
+    /// Keeps the boot files of every replaced install.
+    Skip,
+}
+
+impl Default for OldBootPolicy {
+    fn default() -> Self { OldBootPolicy::Remove { keep: 0 } }
+}
+
+impl FromStr for OldBootPolicy {
+    type Err = &'static str;
+
+    /// Parses `remove`, `keep:N`, `dry-run`, `dry-run:N`, or `skip`.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut fields = string.splitn(2, ':');
+        let kind = fields.next().unwrap_or("");
+        let keep = match fields.next() {
+            Some(keep) => keep.parse::<usize>().map_err(|_| "invalid number of boots to keep")?,
+            None => 0,
+        };
+
+        match kind {
+            "remove" if keep == 0 => Ok(OldBootPolicy::Remove { keep }),
+            "keep" => Ok(OldBootPolicy::Remove { keep }),
+            "dry-run" => Ok(OldBootPolicy::DryRun { keep }),
+            "skip" if keep == 0 => Ok(OldBootPolicy::Skip),
+            _ => Err("invalid old boot policy"),
+        }
+    }
+}
+
+/// Applies the policy to the boot files of `replaced`, and of the installs which were replaced
+/// before it, as recorded in `previous`.
+///
+/// Returns the new record of replaced installs, which holds every install whose boot files
+/// remain on the ESP, whether they were kept, listed by a dry run, or could not be removed.
+pub(crate) fn retire(efi: &Path, previous: &str, replaced: &str, policy: OldBootPolicy) -> String {
+    let keep = match policy {
+        OldBootPolicy::Remove { keep } | OldBootPolicy::DryRun { keep } => keep,
+        OldBootPolicy::Skip => usize::max_value(),
+    };
+
+    let (mut kept, retired) = plan(previous, replaced, keep);
+
+    for uuid in retired {
+        let files = boot_files(efi, &uuid);
+        if files.is_empty() {
+            continue;
+        }
+
+        if let OldBootPolicy::DryRun { .. } = policy {
+            for file in &files {
+                info!("would remove the old boot file at {}", file.display());
+            }
+            kept.push(uuid);
+            continue;
+        }
+
+        // An install whose root still exists was not replaced, but installed alongside.
+        if Path::new("/dev/disk/by-uuid").join(&uuid).exists() {
+            warn!("keeping the boot files of {}, as its root partition still exists", uuid);
+            kept.push(uuid);
+            continue;
+        }
+
+        let mut removed = true;
+        for file in files {
+            info!("removing the old boot file at {}", file.display());
+            let result =
+                if file.is_dir() { fs::remove_dir_all(&file) } else { fs::remove_file(&file) };
+            if let Err(why) = result {
+                warn!("unable to remove an old boot file at {}: {}", file.display(), why);
+                removed = false;
+            }
+        }
+
+        if !removed {
+            kept.push(uuid);
+        }
+    }
+
+    kept.truncate(MAX_OLD_ROOTS);
+    kept.join(" ")
+}
+
+/// Divides the replaced installs, newest first, into those whose boot files are kept, and
+/// those whose boot files are retired.
+fn plan(previous: &str, replaced: &str, keep: usize) -> (Vec<String>, Vec<String>) {
+    let mut history: Vec<String> = vec![replaced.to_owned()];
+    for uuid in previous.split_whitespace() {
+        if !history.iter().any(|existing| existing == uuid) {
+            history.push(uuid.to_owned());
+        }
+    }
+
+    let retired = history.split_off(keep.min(history.len()));
+    (history, retired)
+}
+
+/// The directories on the ESP which belong to the root of `uuid`, and the loader entries which
+/// refer to them.
+fn boot_files(efi: &Path, uuid: &str) -> Vec<PathBuf> {
+    let dirs: Vec<_> = read_dir(&efi.join("EFI"))
+        .filter(|path| path.is_dir() && file_name(path).ends_with(uuid))
+        .collect();
+
+    let names: Vec<String> = dirs.iter().map(|dir| file_name(dir)).collect();
+    let entries = read_dir(&efi.join("loader/entries")).filter(|entry| {
+        let is_conf = entry.extension().map_or(false, |ext| ext == "conf");
+        is_conf
+            && (file_name(entry).trim_end_matches(".conf").ends_with(uuid)
+                || fs::read_to_string(entry)
+                    .map_or(false, |contents| entry_refers_to(&contents, &names)))
+    });
+
+    let mut files: Vec<PathBuf> = entries.collect();
+    files.extend(dirs);
+    files
+}
+
+/// Whether the kernel, initrd, or EFI binary of a loader entry is in one of the directories.
+fn entry_refers_to(entry: &str, dirs: &[String]) -> bool {
+    entry.lines().filter_map(|line| line.split_whitespace().nth(1)).any(|path| {
+        let path = path.trim_start_matches('/');
+        dirs.iter().any(|dir| {
+            path.strip_prefix("EFI/")
+                .and_then(|path| path.strip_prefix(dir.as_str()))
+                .map_or(false, |rest| rest.starts_with('/'))
+        })
+    })
+}
+
+fn read_dir(path: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(path).into_iter().flatten().flatten().map(|entry| entry.path())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn old_boot_policy() {
+        assert_eq!("remove".parse(), Ok(OldBootPolicy::Remove { keep: 0 }));
+        assert_eq!("keep:2".parse(), Ok(OldBootPolicy::Remove { keep: 2 }));
+        assert_eq!("dry-run".parse(), Ok(OldBootPolicy::DryRun { keep: 0 }));
+        assert_eq!("skip".parse(), Ok(OldBootPolicy::Skip));
+        assert!("keep:two".parse::<OldBootPolicy>().is_err());
+        assert!("skip:1".parse::<OldBootPolicy>().is_err());
+
+        let (kept, retired) = plan("bbbb cccc aaaa", "aaaa", 2);
+        assert_eq!(kept, ["aaaa", "bbbb"]);
+        assert_eq!(retired, ["cccc"]);
+
+        let (kept, retired) = plan("", "aaaa", 0);
+        assert!(kept.is_empty());
+        assert_eq!(retired, ["aaaa"]);
+
+        let entry = "title Pop!_OS\nlinux /EFI/Pop_OS-aaaa/vmlinuz.efi\n\
+                     initrd /EFI/Pop_OS-aaaa/initrd.img\noptions root=UUID=aaaa ro\n";
+        assert!(entry_refers_to(entry, &["Pop_OS-aaaa".into()]));
+        assert!(!entry_refers_to(entry, &["Pop_OS-aa".into()]));
+        assert!(!entry_refers_to(entry, &["Pop_OS-bbbb".into()]));
+    }
+
+    #[test]
+    fn old_boots_record() {
+        let efi = TempDir::new("distinst").unwrap();
+        fs::create_dir_all(efi.path().join("EFI/Pop_OS-aaaa")).unwrap();
+
+        let dry_run = OldBootPolicy::DryRun { keep: 0 };
+        assert_eq!(retire(efi.path(), "aaaa", "aaaa", dry_run), "aaaa");
+        assert!(efi.path().join("EFI/Pop_OS-aaaa").exists());
+
+        let remove = OldBootPolicy::Remove { keep: 0 };
+        assert_eq!(retire(efi.path(), "bbbb", "aaaa", remove), "");
+        assert!(!efi.path().join("EFI/Pop_OS-aaaa").exists());
+
+        let previous = (0..20).map(|n| format!("{:04}", n)).collect::<Vec<_>>().join(" ");
+        let kept = retire(efi.path(), &previous, "aaaa", OldBootPolicy::Skip);
+        assert_eq!(kept.split_whitespace().count(), MAX_OLD_ROOTS);
+        assert!(kept.starts_with("aaaa 0000 "));
+    }
+}
@@ -0,0 +1,97 @@
+//! A firewall and an SSH server, so that a machine which is provisioned without a display is
+//! reachable, and no more exposed than intended, on its first boot.
+
+use std::{io, str::FromStr};
+
+/// The policy of the firewall towards incoming connections.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FirewallPolicy {
+    Allow,
+    /// Silently drops incoming connections.
+    Deny,
+    /// Refuses incoming connections, so that their senders know they were refused.
+    Reject,
+}
+
+impl FirewallPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FirewallPolicy::Allow => "allow",
+            FirewallPolicy::Deny => "deny",
+            FirewallPolicy::Reject => "reject",
+        }
+    }
+}
+
+impl FromStr for FirewallPolicy {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "allow" => Ok(FirewallPolicy::Allow),
+            "deny" => Ok(FirewallPolicy::Deny),
+            "reject" => Ok(FirewallPolicy::Reject),
+            _ => Err("invalid firewall policy"),
+        }
+    }
+}
+
+/// An OpenSSH server, which admits the created user with the given keys.
+#[derive(Clone, Debug, Default)]
+pub struct SshServer {
+    /// Public keys, in the format of `authorized_keys`, such as `ssh-ed25519 AAAA... user@host`.
+    pub authorized_keys: Vec<String>,
+}
+
+impl SshServer {
+    /// Ensures that each key is a single line of `authorized_keys`.
+    pub fn validate(&self) -> io::Result<()> {
+        for key in &self.authorized_keys {
+            let key = key.trim();
+            if key.contains('\n') || key.split_whitespace().count() < 2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("SSH public key is not valid: {:?}", key),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The contents of the user's `~/.ssh/authorized_keys`.
+    pub(crate) fn authorized_keys(&self) -> String {
+        let mut keys = String::new();
+        for key in &self.authorized_keys {
+            keys.push_str(key.trim());
+            keys.push('\n');
+        }
+
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssh_authorized_keys() {
+        let server = SshServer {
+            authorized_keys: vec![
+                "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIE admin@example\n".into(),
+                "ecdsa-sha2-nistp256 AAAAE2VjZHNh".into(),
+            ],
+        };
+
+        assert!(server.validate().is_ok());
+        assert_eq!(
+            server.authorized_keys(),
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIE admin@example\n\
+             ecdsa-sha2-nistp256 AAAAE2VjZHNh\n"
+        );
+
+        let server = SshServer { authorized_keys: vec!["ssh-ed25519".into()] };
+        assert!(server.validate().is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Periodic snapshots of the installed system, so that it may be rolled back after an upgrade
+//! goes wrong.
+
+use std::str::FromStr;
+
+/// The tool which takes periodic snapshots of the installed system.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SnapshotTool {
+    /// Snapshots of a Btrfs root by snapper, which grub-btrfs adds to the GRUB menu.
+    Snapper,
+    /// Snapshots by timeshift, which copies the root with rsync, and so works on any file
+    /// system.
+    Timeshift,
+}
+
+impl SnapshotTool {
+    /// The packages which provide the tool.
+    pub fn packages(self) -> &'static [&'static str] {
+        match self {
+            SnapshotTool::Snapper => &["snapper"],
+            SnapshotTool::Timeshift => &["timeshift"],
+        }
+    }
+}
+
+impl FromStr for SnapshotTool {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "snapper" => Ok(SnapshotTool::Snapper),
+            "timeshift" => Ok(SnapshotTool::Timeshift),
+            _ => Err("invalid snapshot tool"),
+        }
+    }
+}
+
+/// Snapper's timeline, which keeps the last five hourly snapshots, and a week of daily snapshots.
+pub(crate) const SNAPPER_SETTINGS: &[&str] = &[
+    "TIMELINE_CREATE=yes",
+    "TIMELINE_CLEANUP=yes",
+    "TIMELINE_LIMIT_HOURLY=5",
+    "TIMELINE_LIMIT_DAILY=7",
+    "TIMELINE_LIMIT_WEEKLY=0",
+    "TIMELINE_LIMIT_MONTHLY=0",
+    "TIMELINE_LIMIT_YEARLY=0",
+    "NUMBER_LIMIT=10",
+];
+
+pub(crate) const TIMESHIFT_CONFIG: &str = "etc/timeshift/timeshift.json";
+pub(crate) const TIMESHIFT_CRON: &str = "etc/cron.d/timeshift-hourly";
+
+/// Timeshift checks its schedule hourly, as it would have configured itself on first run.
+pub(crate) const TIMESHIFT_CRON_JOB: &str = "SHELL=/bin/bash
+PATH=/usr/local/sbin:/usr/local/bin:/sbin:/bin:/usr/sbin:/usr/bin
+MAILTO=\"\"
+
+0 * * * * root timeshift --check --scripted
+";
+
+/// Timeshift's settings for daily and weekly snapshots of the file system with the given UUID.
+///
+/// Snapshots are taken with rsync, because timeshift's Btrfs mode requires the root to be
+/// within an `@` subvolume, which is not how the root is installed.
+pub(crate) fn timeshift_config(uuid: &str) -> String {
+    format!(
+        r#"{{
+  "backup_device_uuid" : "{}",
+  "parent_device_uuid" : "",
+  "do_first_run" : "false",
+  "btrfs_mode" : "false",
+  "include_btrfs_home_for_backup" : "false",
+  "include_btrfs_home_for_restore" : "false",
+  "stop_cron_emails" : "true",
+  "schedule_monthly" : "false",
+  "schedule_weekly" : "true",
+  "schedule_daily" : "true",
+  "schedule_hourly" : "false",
+  "schedule_boot" : "false",
+  "count_monthly" : "2",
+  "count_weekly" : "3",
+  "count_daily" : "5",
+  "count_hourly" : "6",
+  "count_boot" : "5",
+  "exclude" : [
+    "/root/**",
+    "/home/*/**"
+  ],
+  "exclude-apps" : []
+}}
+"#,
+        uuid
+    )
+}
@@ -0,0 +1,136 @@
+//! The groups, sudo policy, and expiry of the user account which is created, so that shared
+//! machines such as those of classrooms and kiosks may be given restricted accounts.
+
+use std::str::FromStr;
+
+/// The groups which the user is added to, if no others are given.
+pub const DEFAULT_GROUPS: &[&str] = &["adm", "sudo", "lpadmin"];
+
+/// The groups which grant administration through sudo on Debian and Ubuntu.
+const SUDO_GROUPS: &[&str] = &["sudo", "admin"];
+
+/// Whether, and how, the user may administer the system through sudo.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SudoPolicy {
+    /// A member of the sudo group, which authenticates with its password.
+    Password,
+    /// A member of the sudo group, which may run any command without a password.
+    NoPassword,
+    /// Not a member of any group which grants sudo.
+    Denied,
+}
+
+impl Default for SudoPolicy {
+    fn default() -> Self { SudoPolicy::Password }
+}
+
+impl SudoPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SudoPolicy::Password => "password",
+            SudoPolicy::NoPassword => "nopasswd",
+            SudoPolicy::Denied => "none",
+        }
+    }
+}
+
+impl FromStr for SudoPolicy {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "password" => Ok(SudoPolicy::Password),
+            "nopasswd" => Ok(SudoPolicy::NoPassword),
+            "none" => Ok(SudoPolicy::Denied),
+            _ => Err("invalid sudo policy"),
+        }
+    }
+}
+
+/// Whether `name` may be given to `useradd` as a user or group: it begins with a lowercase
+/// letter or an underscore, and is followed by lowercase letters, digits, underscores, and
+/// hyphens, with at most 32 characters.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first = chars.next().map_or(false, |c| c.is_ascii_lowercase() || c == '_');
+    let rest = chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    first && rest && name.len() <= 32
+}
+
+/// Whether `date` is a calendar date of the form `YYYY-MM-DD`, as `useradd -e` expects.
+pub fn is_valid_date(date: &str) -> bool {
+    let fields: Vec<&str> = date.split('-').collect();
+    let (year, month, day) = match fields.as_slice() {
+        [year, month, day] if year.len() == 4 && month.len() == 2 && day.len() == 2 => {
+            match (year.parse::<u32>(), month.parse::<u32>(), day.parse::<u32>()) {
+                (Ok(year), Ok(month), Ok(day)) => (year, month, day),
+                _ => return false,
+            }
+        }
+        _ => return false,
+    };
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return false,
+    };
+
+    year >= 1970 && day >= 1 && day <= days
+}
+
+/// The groups of the user, which are the given groups or the defaults, with the groups which
+/// grant sudo added or removed by the policy.
+pub(crate) fn user_groups(groups: Option<&[String]>, sudo: SudoPolicy) -> Vec<String> {
+    let mut groups: Vec<String> = match groups {
+        Some(groups) => groups.to_vec(),
+        None => DEFAULT_GROUPS.iter().map(|&group| group.to_owned()).collect(),
+    };
+
+    match sudo {
+        SudoPolicy::Denied => groups.retain(|group| !SUDO_GROUPS.contains(&group.as_str())),
+        _ if groups.iter().any(|group| SUDO_GROUPS.contains(&group.as_str())) => (),
+        _ => groups.push("sudo".into()),
+    }
+
+    groups
+}
+
+/// The names of the groups which are defined in an `/etc/group` file.
+pub(crate) fn defined_groups(group_file: &str) -> impl Iterator<Item = &str> {
+    group_file.lines().filter_map(|line| line.split(':').next()).filter(|name| !name.is_empty())
+}
+
+/// The sudoers rule which admits the user to run any command without a password.
+pub(crate) fn sudoers_nopasswd(user: &str) -> String {
+    [user, " ALL=(ALL:ALL) NOPASSWD: ALL\n"].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_policy() {
+        assert!(is_valid_name("student-01"));
+        assert!(!is_valid_name("Student"));
+        assert!(!is_valid_name("1student"));
+        assert!(!is_valid_name("stu,dent"));
+
+        assert!(is_valid_date("2024-02-29"));
+        assert!(!is_valid_date("2023-02-29"));
+        assert!(!is_valid_date("2024-13-01"));
+        assert!(!is_valid_date("24-01-01"));
+
+        assert_eq!(user_groups(None, SudoPolicy::Password), ["adm", "sudo", "lpadmin"]);
+        assert_eq!(user_groups(None, SudoPolicy::Denied), ["adm", "lpadmin"]);
+        let groups = vec!["plugdev".to_owned()];
+        assert_eq!(user_groups(Some(&groups), SudoPolicy::NoPassword), ["plugdev", "sudo"]);
+
+        let groups: Vec<_> = defined_groups("root:x:0:\nsudo:x:27:\n").collect();
+        assert_eq!(groups, ["root", "sudo"]);
+    }
+}
@@ -0,0 +1,264 @@
+//! Encrypts the root of an existing install in place, or reencrypts an existing LUKS volume with
+//! another cipher, with the online reencryption of LUKS2, so that encryption may be added to an
+//! install without reinstalling it.
+//!
+//! LUKS2 records the progress of a reencryption in its header, so one which was interrupted,
+//! such as by a loss of power, is resumed by performing it again with the same passphrase.
+
+use crate::disks::{get_size, Bootloader, CodedError, Disks, ErrorCode, KernelLoader};
+use crate::errors::IoContext;
+use crate::external::{
+    blkid_partition, cryptsetup_dump, cryptsetup_is_luks, cryptsetup_reencrypt, exec,
+};
+use crate::misc::namespace::THREAD_MOUNTS;
+use disk_types::FileSystem;
+use os_release::OsRelease;
+use proc_mounts::MountList;
+use std::{ffi::OsString, io, path::Path};
+use sys_mount::*;
+use tempdir::TempDir;
+
+/// The space which is taken from the end of a file system for the LUKS2 header, which is twice
+/// the size of the header, as cryptsetup recommends.
+const HEADER_SPACE: u64 = 32 * 1024 * 1024;
+
+/// The cipher which a LUKS volume is reencrypted with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cipher {
+    /// The name of the cipher, such as `aes-xts-plain64`.
+    pub name:     String,
+    /// The size of the volume key in bits, or the default of the cipher if `None`.
+    pub key_size: Option<u32>,
+}
+
+/// How far the encryption of a device has come.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Reencryption {
+    Unencrypted,
+    Pending,
+    Complete,
+}
+
+impl Reencryption {
+    /// Only a device without a LUKS header is unencrypted. A header which cannot be read is an
+    /// error, rather than a reason to encrypt the device again.
+    pub(crate) fn of(device: &Path) -> io::Result<Reencryption> {
+        if !cryptsetup_is_luks(device)? {
+            return Ok(Reencryption::Unencrypted);
+        }
+
+        let dump = cryptsetup_dump(device)?;
+        Ok(if reencryption_pending(&dump) { Reencryption::Pending } else { Reencryption::Complete })
+    }
+}
+
+/// Checks that the install on `root`, which is mounted at `mount_dir`, can still boot once its
+/// root is encrypted: the kernels must be loaded from outside of the root, and the initramfs
+/// must be able to unlock it.
+pub(crate) fn verify_encryptable(
+    disks: &Disks,
+    mount_dir: &Path,
+    root: &Path,
+    bootloader: Bootloader,
+    os_release: &OsRelease,
+) -> io::Result<()> {
+    let separate_boot = disks
+        .get_partition_with_target(Path::new("/boot"))
+        .map_or(false, |boot| boot.device_path != root);
+
+    if KernelLoader::of(bootloader, &os_release.name) == KernelLoader::Grub && !separate_boot {
+        return Err(CodedError::new(
+            ErrorCode::BootEncrypted,
+            Some(root),
+            "GRUB loads the kernels from /boot, which is on the root, and would be encrypted \
+             with it; /boot must be moved to its own partition first",
+        )
+        .into_io(io::ErrorKind::InvalidInput));
+    }
+
+    let unlocks = ["usr/share/initramfs-tools/hooks/cryptroot", "usr/lib/dracut/modules.d/90crypt"]
+        .iter()
+        .any(|path| mount_dir.join(path).exists());
+
+    if !unlocks {
+        return Err(CodedError::new(
+            ErrorCode::InitramfsIncomplete,
+            Some(root),
+            "the initramfs of the install cannot unlock an encrypted root; \
+             cryptsetup-initramfs must be installed within it first",
+        )
+        .into_io(io::ErrorKind::NotFound));
+    }
+
+    Ok(())
+}
+
+/// Encrypts the file system on `device` in place with `password`, or resumes its encryption if
+/// it was interrupted. The file system is shrunk to make room for the LUKS header.
+pub(crate) fn encrypt_in_place<F: FnMut(i32)>(
+    device: &Path,
+    password: &str,
+    mut callback: F,
+) -> io::Result<()> {
+    match Reencryption::of(device)? {
+        Reencryption::Pending => {
+            info!("resuming the encryption of {}", device.display());
+            return resume(device, password, callback);
+        }
+        Reencryption::Complete => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is already encrypted", device.display()),
+            ));
+        }
+        Reencryption::Unencrypted => (),
+    }
+
+    let mounted = MountList::new_from_file(THREAD_MOUNTS)?.get_mount_by_source(device).is_some();
+    if mounted {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} must be unmounted before it is encrypted", device.display()),
+        ));
+    }
+
+    shrink_for_header(device)
+        .with_context(|why| format!("shrinking {}: {}", device.display(), why))?;
+    callback(5);
+
+    info!("encrypting {} in place", device.display());
+    let args = &[
+        "--encrypt".into(),
+        "--type".into(),
+        "luks2".into(),
+        "--reduce-device-size".into(),
+        format!("{}M", HEADER_SPACE / (1024 * 1024)).into(),
+    ];
+
+    cryptsetup_reencrypt(device, password, args, |percent| {
+        callback(5 + i32::from(percent) * 95 / 100)
+    })
+}
+
+/// Reencrypts the LUKS2 volume on `device` with `cipher`, unlocking it with `password`, or
+/// resumes a reencryption which was interrupted.
+pub(crate) fn change_cipher<F: FnMut(i32)>(
+    device: &Path,
+    password: &str,
+    cipher: &Cipher,
+    callback: F,
+) -> io::Result<()> {
+    let dump = cryptsetup_dump(device)?;
+    if reencryption_pending(&dump) {
+        info!("resuming the reencryption of {}", device.display());
+        return resume(device, password, callback);
+    }
+
+    if luks_version(&dump) != Some(2) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is not LUKS2, and must first be converted with: cryptsetup convert --type \
+                 luks2 {}",
+                device.display(),
+                device.display()
+            ),
+        ));
+    }
+
+    info!("reencrypting {} with {}", device.display(), cipher.name);
+    let mut args: Vec<OsString> = vec!["--cipher".into(), cipher.name.as_str().into()];
+    if let Some(key_size) = cipher.key_size {
+        args.extend_from_slice(&["--key-size".into(), key_size.to_string().into()]);
+    }
+
+    reencrypt_with_progress(device, password, &args, callback)
+}
+
+fn resume<F: FnMut(i32)>(device: &Path, password: &str, callback: F) -> io::Result<()> {
+    reencrypt_with_progress(device, password, &["--resume-only".into()], callback)
+}
+
+fn reencrypt_with_progress<F: FnMut(i32)>(
+    device: &Path,
+    password: &str,
+    args: &[OsString],
+    mut callback: F,
+) -> io::Result<()> {
+    cryptsetup_reencrypt(device, password, args, |percent| callback(i32::from(percent)))
+}
+
+/// Shrinks the file system on `device` by `HEADER_SPACE`, so that its data may be shifted
+/// behind the LUKS header.
+fn shrink_for_header(device: &Path) -> io::Result<()> {
+    let size = get_size(device)? * 512;
+    let new_size = size.checked_sub(HEADER_SPACE).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "the partition is too small to encrypt")
+    })?;
+
+    match blkid_partition(device) {
+        Some(FileSystem::Ext2) | Some(FileSystem::Ext3) | Some(FileSystem::Ext4) => {
+            exec("e2fsck", None, Some(&[1]), &["-f".into(), "-y".into(), device.into()])?;
+            let new_size = format!("{}K", new_size / 1024);
+            exec("resize2fs", None, None, &[device.into(), new_size.into()])
+        }
+        Some(FileSystem::Btrfs) => {
+            let tempdir = TempDir::new("distinst")?;
+            let mount = Mount::new(device, tempdir.path(), "btrfs", MountFlags::empty(), None)?;
+
+            let args = &[
+                "filesystem".into(),
+                "resize".into(),
+                new_size.to_string().into(),
+                tempdir.path().into(),
+            ];
+            let resized = exec("btrfs", None, None, args);
+
+            // The device is encrypted as soon as this returns, so it must not remain mounted, as
+            // a lazy unmount would leave it until its writes were flushed.
+            let unmounted = mount
+                .unmount(UnmountFlags::empty())
+                .with_context(|why| format!("unmounting {}: {}", device.display(), why));
+            resized.and(unmounted)
+        }
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} cannot be shrunk to make room for the LUKS header", other),
+        )),
+    }
+}
+
+/// Whether the LUKS2 header which `dump` describes requires a reencryption to be completed.
+fn reencryption_pending(dump: &str) -> bool {
+    dump.lines().any(|line| {
+        let line = line.trim();
+        line.strip_prefix("Requirements:").unwrap_or(line).trim().starts_with("online-reencrypt")
+    })
+}
+
+fn luks_version(dump: &str) -> Option<u32> {
+    dump.lines().find_map(|line| line.trim().strip_prefix("Version:")?.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "LUKS header information\n\
+                        Version:       \t2\n\
+                        Epoch:         \t5\n\
+                        Metadata area: \t16384 [bytes]\n\
+                        UUID:          \t5b2f0a3c-1e4d-4f6a-9b8c-7d0e2f1a3b4c\n\
+                        Label:         \t(no label)\n\
+                        Flags:       \t(no flags)\n";
+
+    #[test]
+    fn luks_dumps() {
+        assert_eq!(luks_version(DUMP), Some(2));
+        assert_eq!(luks_version("Version:       \t1\n"), Some(1));
+        assert!(!reencryption_pending(DUMP));
+
+        let pending = [DUMP, "Requirements:\tonline-reencrypt-v2\n"].concat();
+        assert!(reencryption_pending(&pending));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::misc::{
+    self,
+    xattr::{self, CAPABILITY},
+};
+use crate::disks::Disks;
+use crate::external::{btrfs_defragment_compress, btrfs_sync};
+use crate::squashfs;
+use disk_types::FileSystem;
+use proc_mounts::MountIter;
+use std::{
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Binaries which are commonly granted file capabilities, rather than being setuid.
+const CAPABILITY_BINARIES: &[&str] = &[
+    "usr/bin/gnome-keyring-daemon",
+    "usr/bin/mtr-packet",
+    "usr/bin/ping",
+    "usr/lib/x86_64-linux-gnu/gstreamer1.0/gstreamer-1.0/gst-ptp-helper",
+];
+
+/// The level of zstd which reused Btrfs file systems are compressed at, unless another was
+/// configured for them, as it is the default level of Btrfs.
+const DEFAULT_ZSTD_LEVEL: u8 = 3;
+
+/// Measurements of an extraction, for judging the write throughput of the target.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExtractStats {
+    /// The bytes written to the file systems of the install.
+    pub bytes:     u64,
+    /// How long the extraction took.
+    pub duration:  Duration,
+    /// The number of threads that decompression was limited to, if it was limited.
+    pub threads:   Option<usize>,
+    /// The bytes which compressing the extracted files of Btrfs file systems reclaimed, if
+    /// they were compressed after the extraction.
+    pub reclaimed: Option<u64>,
+}
+
+/// How many of the files of the image were written to the install.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FileProgress {
+    /// The files, directories, and links which were created so far.
+    pub processed: u64,
+    /// The files, directories, and links in the image.
+    pub total:     u64,
+}
+
+impl ExtractStats {
+    /// The bytes written per second.
+    pub fn throughput(&self) -> u64 {
+        (u128::from(self.bytes) * 1000 / self.duration.as_millis().max(1)) as u64
+    }
+}
+
+/// Extracts the image into the install at `mount_dir`, and measures the throughput.
+///
+/// Along with the percent of the image which was extracted, `callback` is given the files which
+/// were created, if the image records how many it has, and the inodes of the install's file
+/// systems can be counted. It is called whenever either changes.
+pub fn extract<F: FnMut(i32, Option<FileProgress>)>(
+    squashfs: &Path,
+    mount_dir: &Path,
+    threads: Option<usize>,
+    mut callback: F,
+) -> io::Result<ExtractStats> {
+    let targets = mounted_beneath(mount_dir)?;
+    let used = || -> u64 {
+        targets.iter().filter_map(|target| misc::fs_usage(target).ok()).map(|(used, _)| used).sum()
+    };
+
+    let inodes = || -> Option<u64> {
+        let mut used = 0;
+        for target in &targets {
+            match misc::fs_inodes(target) {
+                Ok((_, 0)) | Err(_) => return None,
+                Ok((inodes, _)) => used += inodes,
+            }
+        }
+
+        Some(used)
+    };
+
+    let total = match squashfs::inode_count(squashfs) {
+        Ok(total) => Some(total),
+        Err(why) => {
+            info!("not reporting the files extracted: {}", why);
+            None
+        }
+    };
+
+    let inodes_before = total.and_then(|_| inodes());
+    let (before, started) = (used(), Instant::now());
+    let mut last = (-1, None);
+    squashfs::extract(squashfs, mount_dir, threads, |percent| {
+        let files = match (total, inodes_before) {
+            (Some(total), Some(before)) => inodes().map(|inodes| FileProgress {
+                processed: inodes.saturating_sub(before).min(total),
+                total,
+            }),
+            _ => None,
+        };
+
+        if (percent, files) != last {
+            last = (percent, files);
+            callback(percent, files);
+        }
+    })?;
+
+    let stats = ExtractStats {
+        bytes: used().saturating_sub(before),
+        duration: started.elapsed(),
+        threads,
+        reclaimed: None,
+    };
+
+    info!(
+        "extracted {} bytes in {:?} ({} bytes per second)",
+        stats.bytes,
+        stats.duration,
+        stats.throughput()
+    );
+
+    Ok(stats)
+}
+
+/// Compresses the extracted files of each Btrfs file system which the install reuses. Those
+/// which are formatted are mounted with their compression as the files are extracted, but the
+/// reused are mounted with the options they were created with, and so their files may be
+/// stored uncompressed. Returns the bytes which were reclaimed.
+///
+/// The install proceeds with the files as they are on a file system which fails to compress.
+pub fn recompress_btrfs(disks: &Disks, mount_dir: &Path) -> u64 {
+    let mounts = disks.get_partitions().filter_map(|part| {
+        let reused = part.filesystem == Some(FileSystem::Btrfs) && !part.will_format();
+        let target = part.target.as_ref().filter(|_| reused)?;
+        let level = part.mkfs_options.btrfs.compression.unwrap_or(DEFAULT_ZSTD_LEVEL);
+        Some((mount_dir.join(target.strip_prefix("/").unwrap_or(target)), level))
+    });
+
+    let mut reclaimed = 0;
+    for (mount, level) in mounts {
+        info!("compressing the extracted files of {} at level {}", mount.display(), level);
+        let result = misc::fs_usage(&mount).and_then(|(before, _)| {
+            btrfs_defragment_compress(&mount, level)?;
+            btrfs_sync(&mount)?;
+            misc::fs_usage(&mount).map(|(after, _)| before.saturating_sub(after))
+        });
+
+        match result {
+            Ok(bytes) => {
+                info!("reclaimed {} bytes on {}", bytes, mount.display());
+                reclaimed += bytes;
+            }
+            Err(why) => warn!("unable to compress the files of {}: {}", mount.display(), why),
+        }
+    }
+
+    reclaimed
+}
+
+/// The mount points of the file systems which the install is composed of.
+fn mounted_beneath(mount_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut targets = Vec::new();
+    for mount in MountIter::new_from_file(misc::namespace::THREAD_MOUNTS)? {
+        let mount = mount?;
+        if mount.dest.starts_with(mount_dir) {
+            targets.push(mount.dest);
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Verifies that the file capabilities of well-known binaries survived the extraction.
+///
+/// The live system is the image that was extracted, so each binary that the live system has
+/// is expected to have the same capabilities in the install. Binaries whose size or
+/// modification time differ are assumed to be from a different image, and are skipped.
+pub fn verify_capabilities(live: &Path, install: &Path) -> io::Result<()> {
+    for binary in CAPABILITY_BINARIES {
+        let (source, target) = (live.join(binary), install.join(binary));
+        let same_file = match (source.metadata(), target.metadata()) {
+            (Ok(source), Ok(target)) => {
+                source.len() == target.len() && source.mtime() == target.mtime()
+            }
+            _ => false,
+        };
+
+        if !same_file {
+            continue;
+        }
+
+        let expected = match xattr::get(&source, CAPABILITY)? {
+            Some(expected) => expected,
+            None => continue,
+        };
+
+        if xattr::get(&target, CAPABILITY)? != Some(expected) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("file capabilities of /{} were not preserved by the extraction", binary),
+            ));
+        }
+
+        info!("file capabilities of /{} were preserved", binary);
+    }
+
+    Ok(())
+}
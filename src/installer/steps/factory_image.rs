@@ -0,0 +1,78 @@
+use crate::errors::IoContext;
+use crate::misc;
+use crate::squashfs;
+use std::{fs, io, path::Path};
+
+/// Directories of the install which are not captured in the factory image.
+const EXCLUDE: &[&str] = &["boot/efi", "dev", "home", "proc", "recovery", "run", "sys", "tmp"];
+
+/// Used when the live media does not tell us how well its own image compressed.
+const DEFAULT_COMPRESSION_RATIO: f64 = 0.5;
+
+/// Captures the configured install into a squashfs on the recovery partition, so that
+/// a factory reset will restore exactly what was shipped.
+pub fn factory_image<F: FnMut(i32)>(mount_dir: &Path, mut callback: F) -> io::Result<()> {
+    let recovery = mount_dir.join("recovery");
+    if !recovery.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "a recovery partition is required to capture a factory image",
+        ));
+    }
+
+    let (used, _) = misc::fs_usage(mount_dir)?;
+    let (_, available) = misc::fs_usage(&recovery)?;
+    let required = estimated_image_size(used);
+
+    info!(
+        "factory image requires an estimated {} bytes; {} bytes are available on /recovery",
+        required, available
+    );
+
+    if required > available {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "recovery partition lacks space for a factory image: {} bytes required, {} \
+                 available",
+                required, available
+            ),
+        ));
+    }
+
+    callback(5);
+
+    let factory = recovery.join("factory");
+    fs::create_dir_all(&factory)
+        .with_context(|err| format!("failed to create {:?}: {}", factory, err))?;
+
+    squashfs::create(mount_dir, factory.join("filesystem.squashfs"), EXCLUDE, |percent| {
+        callback(5 + percent * 90 / 100)
+    })
+    .with_context(|err| format!("failed to capture factory image: {}", err))?;
+
+    misc::write(factory.join("filesystem.size"), format!("{}\n", used))?;
+
+    callback(100);
+    Ok(())
+}
+
+/// Estimates the size of the compressed image from the number of bytes used by the install.
+fn estimated_image_size(used: u64) -> u64 {
+    (used as f64 * compression_ratio()) as u64
+}
+
+/// The ratio of the live image's squashfs to its uncompressed size.
+fn compression_ratio() -> f64 {
+    let size = fs::read_to_string("/cdrom/casper/filesystem.size")
+        .ok()
+        .and_then(|size| size.trim().parse::<u64>().ok());
+
+    let compressed =
+        fs::metadata("/cdrom/casper/filesystem.squashfs").ok().map(|metadata| metadata.len());
+
+    match (compressed, size) {
+        (Some(compressed), Some(size)) if size != 0 => compressed as f64 / size as f64,
+        _ => DEFAULT_COMPRESSION_RATIO,
+    }
+}
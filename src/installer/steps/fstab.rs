@@ -0,0 +1,385 @@
+//! Regenerates the fstab and crypttab of an existing install from the partitions which are found
+//! in place of those that they refer to, such as after its disk was cloned, or moved to another
+//! controller.
+//!
+//! The tables are generated as they would be for a new install, and then merged into the
+//! install's own: the source of each entry is replaced, but its options are kept, and entries
+//! which were not generated, such as network mounts and unencrypted swap, are left as they are.
+
+use crate::disks::{Disks, PartitionInfo};
+use crate::installer::traits::InstallerDiskOps;
+use crate::misc;
+use disk_types::FileSystem;
+use partition_identity::PartitionID;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const FSTAB: &str = "etc/fstab";
+const CRYPTTAB: &str = "etc/crypttab";
+
+/// The fstab and crypttab of a mounted install, and what they would be regenerated as.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FstabChanges<'a> {
+    root:             &'a Path,
+    pub fstab:        String,
+    pub new_fstab:    String,
+    pub crypttab:     String,
+    pub new_crypttab: String,
+}
+
+impl<'a> FstabChanges<'a> {
+    /// Whether the tables are already up to date.
+    pub fn is_empty(&self) -> bool {
+        self.fstab == self.new_fstab && self.crypttab == self.new_crypttab
+    }
+
+    /// The changes, in the manner of a diff, so that they may be reviewed before they are
+    /// written.
+    pub fn diff(&self) -> String {
+        let fstab = diff(FSTAB, &self.fstab, &self.new_fstab);
+        fstab + &diff(CRYPTTAB, &self.crypttab, &self.new_crypttab)
+    }
+
+    /// Writes the regenerated tables. The originals are kept beside them, with a `.bak` suffix.
+    pub fn write(&self) -> io::Result<()> {
+        let tables = [
+            (FSTAB, &self.fstab, &self.new_fstab),
+            (CRYPTTAB, &self.crypttab, &self.new_crypttab),
+        ];
+
+        for &(table, old, new) in &tables {
+            if old == new {
+                continue;
+            }
+
+            info!("writing the regenerated /{}", table);
+            let path = self.root.join(table);
+            if path.exists() {
+                fs::copy(&path, path.with_extension("bak"))?;
+            }
+
+            misc::write(&path, new)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Regenerates the tables of the install which is mounted at `root`, whose partitions have been
+/// assigned as targets within `disks`.
+pub(crate) fn regenerate_fstabs<'a>(
+    root: &'a Path,
+    disks: &Disks,
+) -> io::Result<FstabChanges<'a>> {
+    let fstab = fs::read_to_string(root.join(FSTAB))?;
+    let crypttab = match fs::read_to_string(root.join(CRYPTTAB)) {
+        Ok(crypttab) => crypttab,
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(why) => return Err(why),
+    };
+
+    let install = install_partitions(disks, &fstab, &crypttab);
+    let (new_crypttab, new_fstab) = install.generate_fstabs();
+
+    Ok(FstabChanges {
+        root,
+        new_fstab: merge_table(&fstab, &new_fstab.to_string_lossy(), 0, fstab_key),
+        new_crypttab: merge_table(&crypttab, &new_crypttab.to_string_lossy(), 1, crypttab_key),
+        fstab,
+        crypttab,
+    })
+}
+
+/// The partitions of the install, with the stale entries of its tables assigned to the
+/// partitions which are found in their place. Partitions of other systems are left out, so that
+/// they are not added to the tables.
+fn install_partitions(disks: &Disks, fstab: &str, crypttab: &str) -> Disks {
+    let mut install = Disks { physical: disks.physical.clone(), logical: disks.logical.clone() };
+    let root_disk = root_disk(&install);
+    let mut swaps = Vec::new();
+
+    // Entries whose partitions are missing are assigned the only partition of their file system
+    // on the disk of the root, which is not otherwise used.
+    for fields in entries(fstab) {
+        let (source, target, fs) = match fields.as_slice() {
+            [source, target, fs, ..] => (*source, Path::new(target), *fs),
+            _ => continue,
+        };
+
+        let is_partition = source.contains('=') || source.starts_with("/dev/");
+        if !is_partition
+            || !target.is_absolute()
+            || resolve(source).is_some()
+            || install.get_partition_with_target(target).is_some()
+        {
+            continue;
+        }
+
+        match root_disk.as_ref().and_then(|disk| unused_partition(&install, disk, fs, &swaps)) {
+            Some(path) => {
+                info!("{} was not found, so {} is mounted there", source, path.display());
+                let partition = install.get_partition_by_path_mut(&path);
+                partition.expect("partition vanished").set_mount(target.to_path_buf());
+            }
+            None => warn!("{} of {} was not found, and is left as it is", target.display(), source),
+        }
+    }
+
+    // Swap which is encrypted with a random key is listed in the crypttab.
+    for fields in entries(crypttab) {
+        let source = match fields.as_slice() {
+            [_, source, _, options, ..] if options.split(',').any(|opt| opt == "swap") => *source,
+            _ => continue,
+        };
+
+        let swap = resolve(source).or_else(|| {
+            root_disk.as_ref().and_then(|disk| unused_partition(&install, disk, "swap", &swaps))
+        });
+
+        match swap {
+            Some(swap) => swaps.push(swap),
+            None => warn!("the swap of {} was not found, and is left as it is", source),
+        }
+    }
+
+    let in_use = |partition: &PartitionInfo| {
+        partition.target.is_some()
+            || swaps.iter().any(|swap| same_device(swap, &partition.device_path))
+            || partition.volume_group.as_ref().map_or(false, |(_, enc)| enc.is_some())
+    };
+
+    for disk in &mut install.physical {
+        disk.file_system = disk.file_system.take().filter(|fs| in_use(fs));
+        disk.partitions.retain(|partition| in_use(partition));
+    }
+
+    for device in &mut install.logical {
+        device.file_system = device.file_system.take().filter(|fs| in_use(fs));
+        device.partitions.retain(|partition| in_use(partition));
+    }
+
+    // The keys of the install are kept when its crypttab is merged, so these are placeholders.
+    for partition in install.get_partitions_mut() {
+        if let Some((_, Some(ref mut enc))) = partition.volume_group {
+            enc.password = Some(String::new());
+            enc.keydata = None;
+        }
+    }
+
+    install
+}
+
+/// The disk which holds the root, or the LUKS partition of the root.
+fn root_disk(disks: &Disks) -> Option<PathBuf> {
+    let root = disks.get_partition_with_target(Path::new("/"))?;
+    let root_path = disks
+        .get_logical_devices()
+        .iter()
+        .find(|device| device.partitions.iter().chain(&device.file_system).any(|p| p == root))
+        .and_then(|device| device.luks_parent.clone())
+        .unwrap_or_else(|| root.device_path.clone());
+
+    disks.get_physical_device_with_partition(&root_path).map(|disk| disk.device_path.clone())
+}
+
+/// The only partition on `disk` with the file system of an fstab entry, which is not in use.
+fn unused_partition(disks: &Disks, disk: &Path, fs: &str, swaps: &[PathBuf]) -> Option<PathBuf> {
+    let disk = disks.get_physical_device(disk)?;
+    let mut candidates = disk.partitions.iter().filter(|partition| {
+        partition.target.is_none()
+            && partition.volume_group.is_none()
+            && partition.filesystem.map(fstab_type) == Some(fs)
+            && !swaps.iter().any(|swap| same_device(swap, &partition.device_path))
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(partition), None) => Some(partition.device_path.clone()),
+        _ => None,
+    }
+}
+
+/// The name of a file system, as it is written in the fstab.
+fn fstab_type(fs: FileSystem) -> &'static str {
+    match fs {
+        FileSystem::Fat16 | FileSystem::Fat32 => "vfat",
+        FileSystem::Swap => "swap",
+        fs => fs.into(),
+    }
+}
+
+/// The device that the source of an entry refers to, if it exists.
+fn resolve(source: &str) -> Option<PathBuf> {
+    if source.starts_with('/') {
+        let path = Path::new(source);
+        return if path.exists() { Some(path.to_path_buf()) } else { None };
+    }
+
+    source.parse::<PartitionID>().ok().and_then(|id| id.get_device_path())
+}
+
+fn same_device(a: &Path, b: &Path) -> bool {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    canonical(a) == canonical(b)
+}
+
+/// The fields of each entry of a table, skipping comments and blank lines.
+fn entries<'a>(table: &'a str) -> impl Iterator<Item = Vec<&'a str>> + 'a {
+    table
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|fields| fields.first().map_or(false, |field| !field.starts_with('#')))
+}
+
+/// Entries of the fstab are identified by their mount point, or by their type if they are swap,
+/// which has no mount point, and whose source is what changes as it is regenerated.
+fn fstab_key<'a>(fields: &[&'a str]) -> Option<&'a str> {
+    match fields {
+        [_, _, "swap", ..] => Some("swap"),
+        [_, target, ..] => Some(*target),
+        _ => None,
+    }
+}
+
+/// Entries of the crypttab are identified by the name of their mapping.
+fn crypttab_key<'a>(fields: &[&'a str]) -> Option<&'a str> { fields.first().cloned() }
+
+/// Replaces the source, at `column`, of each entry of `old` with that of the first unused entry
+/// of `new` with the same key, and appends the entries of `new` which `old` lacks.
+fn merge_table(
+    old: &str,
+    new: &str,
+    column: usize,
+    key: for<'a> fn(&[&'a str]) -> Option<&'a str>,
+) -> String {
+    let new_entries: Vec<Vec<&str>> = entries(new).collect();
+    let mut merged = String::with_capacity(old.len() + new.len());
+    let mut used = vec![false; new_entries.len()];
+
+    for line in old.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let is_entry = fields.first().map_or(false, |field| !field.starts_with('#'));
+        let matching = key(&fields)
+            .filter(|_| is_entry)
+            .and_then(|old_key| {
+                new_entries
+                    .iter()
+                    .zip(&used)
+                    .position(|(entry, &used)| !used && key(entry) == Some(old_key))
+            });
+
+        match matching.and_then(|index| Some((index, *new_entries[index].get(column)?))) {
+            Some((index, source)) => {
+                used[index] = true;
+                merged.push_str(&replace_field(line, column, source));
+            }
+            None => merged.push_str(line),
+        }
+
+        merged.push('\n');
+    }
+
+    for (entry, &used) in new_entries.iter().zip(&used) {
+        if !used {
+            merged.push_str(&entry.join("  "));
+            merged.push('\n');
+        }
+    }
+
+    merged
+}
+
+/// Replaces the field at `index` of the line, retaining the whitespace between its fields.
+fn replace_field(line: &str, index: usize, value: &str) -> String {
+    match line.split_whitespace().nth(index) {
+        Some(field) => {
+            let start = field.as_ptr() as usize - line.as_ptr() as usize;
+            [&line[..start], value, &line[start + field.len()..]].concat()
+        }
+        None => line.to_owned(),
+    }
+}
+
+/// Each line which differs, as it was and as it is, in the manner of a unified diff. Lines are
+/// compared in order, as merging only replaces and appends entries.
+fn diff(name: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {0}\n+++ {0}\n", name);
+    let (mut old, mut new) = (old.lines(), new.lines());
+    loop {
+        match (old.next(), new.next()) {
+            (None, None) => break,
+            (Some(old), Some(new)) if old == new => (),
+            (old, new) => {
+                for (prefix, line) in [("-", old), ("+", new)].iter() {
+                    if let Some(line) = line {
+                        diff.push_str(prefix);
+                        diff.push_str(line);
+                        diff.push('\n');
+                    }
+                }
+            }
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fstab_merge() {
+        let fstab = "# <file system>  <mount point>  <type>  <options>  <dump>  <pass>\n\
+                     PARTUUID=old  /boot/efi  vfat  umask=0077  0  0\n\
+                     UUID=1234  /  ext4  noatime,errors=remount-ro  0  1\n\
+                     /dev/mapper/cryptswap  none  swap  defaults  0  0\n\
+                     server:/srv  /mnt/srv  nfs  defaults  0  0\n";
+
+        let generated = "UUID=5678  /  ext4  noatime,errors=remount-ro  0  1\n\
+                         UUID=ABCD  /boot/efi  vfat  umask=0077  0  2\n\
+                         UUID=EF01  /home  ext4  noatime  0  2\n\
+                         /dev/mapper/cryptswap  none  swap  defaults  0  0\n";
+
+        let merged = merge_table(fstab, generated, 0, fstab_key);
+        assert_eq!(
+            merged,
+            "# <file system>  <mount point>  <type>  <options>  <dump>  <pass>\n\
+             UUID=ABCD  /boot/efi  vfat  umask=0077  0  0\n\
+             UUID=5678  /  ext4  noatime,errors=remount-ro  0  1\n\
+             /dev/mapper/cryptswap  none  swap  defaults  0  0\n\
+             server:/srv  /mnt/srv  nfs  defaults  0  0\n\
+             UUID=EF01  /home  ext4  noatime  0  2\n"
+        );
+
+        assert_eq!(
+            diff("etc/fstab", fstab, &merged),
+            "--- etc/fstab\n+++ etc/fstab\n\
+             -PARTUUID=old  /boot/efi  vfat  umask=0077  0  0\n\
+             +UUID=ABCD  /boot/efi  vfat  umask=0077  0  0\n\
+             -UUID=1234  /  ext4  noatime,errors=remount-ro  0  1\n\
+             +UUID=5678  /  ext4  noatime,errors=remount-ro  0  1\n\
+             +UUID=EF01  /home  ext4  noatime  0  2\n"
+        );
+
+        let fstab = "UUID=old-swap  none  swap  sw  0  0\nUUID=old-swap2  none  swap  sw  0  0\n";
+        let generated = "UUID=new-swap  none  swap  defaults  0  0\n";
+        assert_eq!(
+            merge_table(fstab, generated, 0, fstab_key),
+            "UUID=new-swap  none  swap  sw  0  0\nUUID=old-swap2  none  swap  sw  0  0\n"
+        );
+
+        let crypttab =
+            "cryptdata UUID=1234 none luks\ncryptswap UUID=old /dev/urandom swap,plain\n";
+        let generated = "cryptdata UUID=5678 none luks\ncryptswap UUID=9ABC /dev/urandom swap\n";
+        assert_eq!(
+            merge_table(crypttab, generated, 1, crypttab_key),
+            "cryptdata UUID=5678 none luks\ncryptswap UUID=9ABC /dev/urandom swap,plain\n"
+        );
+    }
+}
@@ -1,11 +1,17 @@
 use crate::disks::*;
+use crate::errors::IoContext;
+use crate::external::btrfs_subvolume_create;
 use crate::misc;
+use disk_types::{BlockDeviceExt, SectorExt};
 use rayon;
 use std::{
+    fs,
     io::{self, BufRead},
     path::{Path, PathBuf},
+    ptr,
 };
 use crate::Config;
+use crate::CAPTURE_FACTORY_IMAGE;
 
 pub fn initialize<F: FnMut(i32)>(
     disks: &mut Disks,
@@ -13,93 +19,170 @@ pub fn initialize<F: FnMut(i32)>(
     mut callback: F,
 ) -> io::Result<(PathBuf, Vec<String>)> {
     info!("Initializing");
+    require_capability(Capability::SysAdmin, "installing")?;
 
-    let fetch_squashfs = || match Path::new(&config.squashfs).canonicalize() {
+    let verify_disks = |disks: &Disks| {
+        disks.verify_keyfile_paths()?;
+        if config.flags & CAPTURE_FACTORY_IMAGE != 0 {
+            verify_factory_image_space(disks)?;
+        }
+        Ok(())
+    };
+
+    let mut res_a = Ok(());
+    let mut res_b = Ok(Vec::new());
+    let mut res_c = Ok(());
+    let mut res_d = Ok(PathBuf::new());
+
+    rayon::scope(|s| {
+        s.spawn(|_| res_a = release_devices(disks));
+        s.spawn(|_| res_b = removed_packages(config));
+        s.spawn(|_| res_c = verify_disks(disks));
+        s.spawn(|_| res_d = squashfs_path(config));
+    });
+
+    let (remove_pkgs, squashfs) =
+        res_a.and(res_c).and(res_b).and_then(|pkgs| res_d.map(|squashfs| (pkgs, squashfs)))?;
+
+    unmount_targets(disks)?;
+    callback(100);
+
+    Ok((squashfs, remove_pkgs))
+}
+
+/// Prepares an install to a directory, for a container, which must be empty if it exists. If
+/// it does not exist, it is created, as a Btrfs subvolume if `subvolume` is set.
+pub fn initialize_container<F: FnMut(i32)>(
+    target: &Path,
+    subvolume: bool,
+    config: &Config,
+    mut callback: F,
+) -> io::Result<(PathBuf, Vec<String>)> {
+    info!("Initializing container at {}", target.display());
+    require_capability(Capability::SysAdmin, "installing")?;
+
+    if target.exists() {
+        if target.read_dir()?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", target.display()),
+            ));
+        }
+    } else if subvolume {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        btrfs_subvolume_create(target).with_context(|why| {
+            format!("failed to create subvolume at {}: {}", target.display(), why)
+        })?;
+    } else {
+        fs::create_dir_all(target)?;
+    }
+
+    let squashfs = squashfs_path(config)?;
+    let remove_pkgs = removed_packages(config)?;
+    callback(100);
+
+    Ok((squashfs, remove_pkgs))
+}
+
+fn squashfs_path(config: &Config) -> io::Result<PathBuf> {
+    match Path::new(&config.squashfs).canonicalize() {
         Ok(squashfs) => {
             if squashfs.exists() {
                 info!("config.squashfs: found at {}", squashfs.display());
                 Ok(squashfs)
             } else {
                 error!("config.squashfs: supplied file does not exist");
-                Err(io::Error::new(io::ErrorKind::NotFound, "invalid squashfs path"))
+                Err(CodedError::new(ErrorCode::SquashfsMissing, None, "invalid squashfs path")
+                    .into_io(io::ErrorKind::NotFound))
             }
         }
         Err(err) => {
             error!("config.squashfs: {}", err);
             Err(err)
         }
-    };
+    }
+}
 
-    let fetch_packages = || {
-        let mut remove_pkgs = Vec::new();
-        {
-            let file = match misc::open(&config.remove) {
-                Ok(file) => file,
+fn removed_packages(config: &Config) -> io::Result<Vec<String>> {
+    let mut remove_pkgs = Vec::new();
+    {
+        let file = match misc::open(&config.remove) {
+            Ok(file) => file,
+            Err(err) => {
+                error!("config.remove: {}", err);
+                return Err(err);
+            }
+        };
+
+        // Collects the packages that are to be removed from the install.
+        for line_res in io::BufReader::new(file).lines() {
+            match line_res {
+                // Only add package if it is not contained within lang_packs.
+                Ok(line) => remove_pkgs.push(line),
                 Err(err) => {
                     error!("config.remove: {}", err);
                     return Err(err);
                 }
-            };
-
-            // Collects the packages that are to be removed from the install.
-            for line_res in io::BufReader::new(file).lines() {
-                match line_res {
-                    // Only add package if it is not contained within lang_packs.
-                    Ok(line) => remove_pkgs.push(line),
-                    Err(err) => {
-                        error!("config.remove: {}", err);
-                        return Err(err);
-                    }
-                }
             }
         }
+    }
 
-        Ok(remove_pkgs)
-    };
-
-    let verify_disks = |disks: &Disks| {
-        disks.verify_keyfile_paths()?;
-        Ok(())
-    };
-
-    let mut res_a = Ok(());
-    let mut res_b = Ok(Vec::new());
-    let mut res_c = Ok(());
-    let mut res_d = Ok(PathBuf::new());
-
-    rayon::scope(|s| {
-        s.spawn(|_| {
-            // Deactivate any open logical volumes & close any encrypted partitions.
-            if let Err(why) = disks.deactivate_device_maps() {
-                error!("device map deactivation error: {}", why);
-                res_a = Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("device map deactivation error: {}", why),
-                ));
-                return;
-            }
-
-            // Unmount any mounted devices.
-            if let Err(why) = disks.unmount_devices() {
-                error!("device unmount error: {}", why);
-                res_a = Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("device unmount error: {}", why),
-                ));
-                return;
-            }
+    Ok(remove_pkgs)
+}
 
-            res_a = Ok(());
-        });
+/// Prepares the disks to be partitioned, for when a system will not be installed to them.
+///
+/// Disks which the running system is mounted from are refused.
+pub fn prepare_disks<F: FnMut(i32)>(disks: &mut Disks, mut callback: F) -> io::Result<()> {
+    info!("Preparing disks");
+    require_capability(Capability::SysAdmin, "partitioning")?;
+
+    if let Some(disk) =
+        disks.get_physical_devices().iter().find(|disk| disk.contains_mount("/", disks))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} contains the running system", disk.get_device_path().display()),
+        ));
+    }
+
+    disks.verify_keyfile_paths().map_err(io::Error::from)?;
+    release_devices(disks)?;
+    callback(50);
+
+    unmount_targets(disks)?;
+    callback(100);
 
-        s.spawn(|_| res_b = fetch_packages());
-        s.spawn(|_| res_c = verify_disks(disks));
-        s.spawn(|_| res_d = fetch_squashfs());
-    });
+    Ok(())
+}
 
-    let (remove_pkgs, squashfs) =
-        res_a.and(res_c).and(res_b).and_then(|pkgs| res_d.map(|squashfs| (pkgs, squashfs)))?;
+/// Deactivates any open logical volumes, closes any encrypted partitions, and unmounts any
+/// mounted devices.
+fn release_devices(disks: &Disks) -> io::Result<()> {
+    if let Err(why) = disks.deactivate_device_maps() {
+        error!("device map deactivation error: {}", why);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("device map deactivation error: {}", why),
+        ));
+    }
+
+    if let Err(why) = disks.unmount_devices() {
+        error!("device unmount error: {}", why);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("device unmount error: {}", why),
+        ));
+    }
+
+    Ok(())
+}
 
+/// Unmounts partitions with targets, except on disks which the running system is mounted from.
+fn unmount_targets(disks: &mut Disks) -> io::Result<()> {
     let unmount =
         disks.physical.iter().map(|disk| !disk.contains_mount("/", disks)).collect::<Vec<bool>>();
 
@@ -119,9 +202,54 @@ pub fn initialize<F: FnMut(i32)>(
 
             Ok(())
         })
-        .collect::<io::Result<()>>()?;
+        .collect::<io::Result<()>>()
+}
 
-    callback(100);
+/// The recovery partition must hold a copy of the live media, in addition to the factory image,
+/// which will be at least as large as the live media's squashfs.
+fn verify_factory_image_space(disks: &Disks) -> io::Result<()> {
+    let recovery = disks.get_partition_with_target(Path::new("/recovery")).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a recovery partition is required to capture a factory image",
+        )
+    })?;
+
+    let squashfs = fs::metadata("/cdrom/casper/filesystem.squashfs").map_or(0, |md| md.len());
+    let required = squashfs * 2;
+    let available = recovery.get_sectors() * sector_size_of(disks, recovery);
+
+    if required > available {
+        return Err(CodedError::new(
+            ErrorCode::RecoveryTooSmall,
+            Some(recovery.get_device_path()),
+            format!(
+                "recovery partition is too small for a factory image: {} bytes required, {} \
+                 available",
+                required, available
+            ),
+        )
+        .into_io(io::ErrorKind::InvalidInput));
+    }
+
+    Ok(())
+}
 
-    Ok((squashfs, remove_pkgs))
+/// The logical sector size of the device holding `partition`, in which its sectors are counted.
+fn sector_size_of(disks: &Disks, partition: &PartitionInfo) -> u64 {
+    let holds = |partitions: &[PartitionInfo]| partitions.iter().any(|p| ptr::eq(p, partition));
+
+    disks
+        .get_physical_devices()
+        .iter()
+        .find(|disk| holds(&disk.partitions))
+        .map(|disk| disk.get_logical_block_size())
+        .or_else(|| {
+            disks
+                .get_logical_devices()
+                .iter()
+                .find(|device| holds(&device.partitions))
+                .map(|device| device.sector_size)
+        })
+        .unwrap_or(512)
 }
@@ -0,0 +1,324 @@
+//! The generation of the installed system's initramfs, with initramfs-tools or dracut, and the
+//! verification that it holds what the install needs to mount its root.
+//!
+//! An initramfs which silently lacks cryptsetup or the device-mapper modules leaves an
+//! encrypted install which cannot boot, so the install fails instead.
+
+use crate::chroot::Chroot;
+use crate::disks::{CodedError, Disks, ErrorCode};
+use crate::errors::IoContext;
+use crate::misc;
+use os_detect::installed_kernels;
+use std::{fs, io, path::Path, str::FromStr};
+
+const INITRAMFS_TOOLS_COMPRESS: &str = "etc/initramfs-tools/conf.d/distinst-compress";
+const DRACUT_COMPRESS: &str = "etc/dracut.conf.d/90-distinst-compress.conf";
+
+/// The compression of the initramfs.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InitramfsCompression {
+    Gzip,
+    Lz4,
+    Xz,
+    Zstd,
+}
+
+impl InitramfsCompression {
+    /// The name of the compression, as both initramfs-tools and dracut know it.
+    pub fn name(self) -> &'static str {
+        match self {
+            InitramfsCompression::Gzip => "gzip",
+            InitramfsCompression::Lz4 => "lz4",
+            InitramfsCompression::Xz => "xz",
+            InitramfsCompression::Zstd => "zstd",
+        }
+    }
+}
+
+impl FromStr for InitramfsCompression {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "gzip" => Ok(InitramfsCompression::Gzip),
+            "lz4" => Ok(InitramfsCompression::Lz4),
+            "xz" => Ok(InitramfsCompression::Xz),
+            "zstd" => Ok(InitramfsCompression::Zstd),
+            _ => Err("expected 'gzip', 'lz4', 'xz', or 'zstd'"),
+        }
+    }
+}
+
+/// How the initramfs of the installed system is generated.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InitramfsOptions {
+    /// The releases of the kernels whose initramfs is generated, such as `5.15.0-56-generic`.
+    /// If empty, the initramfs of every installed kernel is generated.
+    pub kernels:     Vec<String>,
+    /// The compression, which is kept for later generations. If `None`, the generator's
+    /// default is used.
+    pub compression: Option<InitramfsCompression>,
+}
+
+impl InitramfsOptions {
+    /// Ensures that the kernel releases may be passed to the generator.
+    pub fn validate(&self) -> io::Result<()> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || "+-._~".contains(c);
+        for release in &self.kernels {
+            if release.is_empty() || !release.chars().all(valid) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("kernel release is not valid: {:?}", release),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the root must be opened with, and so what the initramfs must contain.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Feature {
+    Crypt,
+    Lvm,
+    Raid,
+}
+
+impl Feature {
+    /// The features which the disks of the install use.
+    pub(crate) fn of(disks: &Disks) -> Vec<Feature> {
+        let logical = disks.get_logical_devices();
+        let mut features = Vec::new();
+
+        let encrypted = logical
+            .iter()
+            .any(|device| device.encryption.is_some() || device.luks_parent.is_some());
+        if encrypted {
+            features.push(Feature::Crypt);
+        }
+
+        if !logical.is_empty() {
+            features.push(Feature::Lvm);
+        }
+
+        let on_raid = disks.get_partitions().any(|part| {
+            part.target.is_some()
+                && part
+                    .get_device_path()
+                    .file_name()
+                    .map_or(false, |name| name.to_string_lossy().starts_with("md"))
+        });
+
+        if on_raid {
+            features.push(Feature::Raid);
+        }
+
+        features
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Feature::Crypt => "encryption",
+            Feature::Lvm => "LVM",
+            Feature::Raid => "RAID",
+        }
+    }
+
+    /// The program which opens the root in the initramfs.
+    fn program(self) -> &'static str {
+        match self {
+            Feature::Crypt => "cryptsetup",
+            Feature::Lvm => "lvm",
+            Feature::Raid => "mdadm",
+        }
+    }
+
+    /// The module which the kernel needs, unless it is built in.
+    fn module(self) -> &'static str {
+        match self {
+            Feature::Crypt => "dm-crypt",
+            Feature::Lvm => "dm-mod",
+            Feature::Raid => "md-mod",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Generator {
+    InitramfsTools,
+    Dracut,
+}
+
+impl Generator {
+    fn detect(root: &Path) -> Option<Generator> {
+        let exists = |paths: &[&str]| paths.iter().any(|path| root.join(path).exists());
+        if exists(&["usr/sbin/update-initramfs", "sbin/update-initramfs"]) {
+            Some(Generator::InitramfsTools)
+        } else if exists(&["usr/bin/dracut", "usr/sbin/dracut"]) {
+            Some(Generator::Dracut)
+        } else {
+            None
+        }
+    }
+}
+
+/// Generates the initramfs of the kernels in the chroot at `root`, and verifies that each
+/// holds the programs and modules which the `features` of the install need.
+pub(crate) fn generate(
+    chroot: &Chroot,
+    root: &Path,
+    options: &InitramfsOptions,
+    features: &[Feature],
+) -> io::Result<()> {
+    let generator = Generator::detect(root).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "neither initramfs-tools nor dracut is installed")
+    })?;
+
+    if let Some(compression) = options.compression {
+        info!("compressing the initramfs with {}", compression.name());
+        let (path, setting) = match generator {
+            Generator::InitramfsTools => (INITRAMFS_TOOLS_COMPRESS, "COMPRESS="),
+            Generator::Dracut => (DRACUT_COMPRESS, "compress="),
+        };
+
+        let path = root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        misc::write(&path, [setting, compression.name(), "\n"].concat())
+            .with_context(|why| format!("writing {}: {}", path.display(), why))?;
+    }
+
+    let kernels = if options.kernels.is_empty() {
+        match generator {
+            Generator::InitramfsTools => {
+                chroot.command("update-initramfs", &["-c", "-k", "all"]).run()?
+            }
+            Generator::Dracut => {
+                chroot.command("dracut", &["--force", "--regenerate-all"]).run()?
+            }
+        }
+
+        installed_kernels(root)
+    } else {
+        for release in &options.kernels {
+            if !root.join("lib/modules").join(release).is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("kernel {} is not installed, so its initramfs cannot be made", release),
+                ));
+            }
+
+            match generator {
+                Generator::InitramfsTools => {
+                    chroot.command("update-initramfs", &["-c", "-k", release.as_str()]).run()?
+                }
+                Generator::Dracut => {
+                    chroot.command("dracut", &["--force", "--kver", release.as_str()]).run()?
+                }
+            }
+        }
+
+        options.kernels.clone()
+    };
+
+    if features.is_empty() {
+        return Ok(());
+    }
+
+    for release in &kernels {
+        let listing = match generator {
+            Generator::InitramfsTools => chroot
+                .command("lsinitramfs", &[["/boot/initrd.img-", release.as_str()].concat()])
+                .run_with_stdout(),
+            Generator::Dracut => {
+                chroot.command("lsinitrd", &["--kver", release.as_str()]).run_with_stdout()
+            }
+        }
+        .with_context(|why| format!("listing the initramfs of {}: {}", release, why))?;
+
+        let builtin = root.join("lib/modules").join(release).join("modules.builtin");
+        let builtin = fs::read_to_string(builtin).unwrap_or_default();
+
+        let missing = missing_components(&listing, &builtin, features);
+        if !missing.is_empty() {
+            return Err(CodedError::new(
+                ErrorCode::InitramfsIncomplete,
+                None,
+                format!(
+                    "the initramfs of {} lacks {}, without which the install cannot boot",
+                    release,
+                    missing.join(", ")
+                ),
+            )
+            .into_io(io::ErrorKind::NotFound));
+        }
+
+        info!("verified that the initramfs of {} can open the root", release);
+    }
+
+    Ok(())
+}
+
+/// The programs and modules of `features` which are missing from the listing of an initramfs,
+/// where modules may instead be built into the kernel.
+fn missing_components(listing: &str, builtin: &str, features: &[Feature]) -> Vec<String> {
+    let mut missing = Vec::new();
+    for &feature in features {
+        if !lists_file(listing, |name| name == feature.program()) {
+            missing.push(format!("{} ({})", feature.program(), feature.name()));
+        }
+
+        let module = |name: &str| {
+            name.strip_prefix(feature.module())
+                .map_or(false, |ext| ext == ".ko" || ext.starts_with(".ko."))
+        };
+
+        if !lists_file(listing, module) && !lists_file(builtin, module) {
+            missing.push(format!("the {} module ({})", feature.module(), feature.name()));
+        }
+    }
+
+    missing
+}
+
+/// Whether a listing of paths, whose lines may hold other fields before the path, has a file
+/// whose name matches.
+fn lists_file<F: Fn(&str) -> bool>(listing: &str, matches: F) -> bool {
+    listing
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .any(|path| matches(path.rsplit('/').next().unwrap_or(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initramfs_components() {
+        let listing = "usr/sbin/cryptsetup\nusr/sbin/lvm\n\
+                       usr/lib/modules/5.15.0-56-generic/kernel/drivers/md/dm-crypt.ko\n";
+        let builtin = "kernel/drivers/md/dm-mod.ko\n";
+        let features = [Feature::Crypt, Feature::Lvm];
+        assert!(missing_components(listing, builtin, &features).is_empty());
+        assert_eq!(
+            missing_components("usr/sbin/lvm\n", builtin, &features),
+            ["cryptsetup (encryption)", "the dm-crypt module (encryption)"]
+        );
+
+        // dracut's listing is in the long format of ls, and its modules may be compressed.
+        let listing = "-rwxr-xr-x 1 root root 1234 Nov 1 12:00 usr/sbin/cryptsetup\n\
+                       -rw-r--r-- 1 root root 5678 Nov 1 12:00 lib/modules/6.0/dm-crypt.ko.zst\n";
+        assert!(missing_components(listing, "", &[Feature::Crypt]).is_empty());
+        assert!(!missing_components("dm-crypted.ko\n", "", &[Feature::Crypt]).is_empty());
+
+        let options = InitramfsOptions {
+            kernels:     vec!["5.15.0-56-generic;".into()],
+            compression: "zstd".parse().ok(),
+        };
+        assert!(options.validate().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::errors::IoContext;
+use crate::misc;
+use crate::UserAccountCreate;
+use os_detect::{detect_os_from_path, OS};
+use std::{
+    ffi::CString,
+    fs, io,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+};
+use sys_mount::*;
+use tempdir::TempDir;
+
+/// The folders of a Windows user's profile which are copied into the new user's home.
+const FOLDERS: &[&str] = &["Documents", "Pictures", "Music"];
+
+/// Describes the user data to migrate from a Windows install.
+#[derive(Debug, Clone)]
+pub struct WindowsMigration {
+    /// The partition which contains the Windows install.
+    pub device: PathBuf,
+    /// The name of the Windows user whose data will be migrated.
+    pub user:   String,
+}
+
+/// Copies the documents, pictures, and music of a Windows user into the home of the new user.
+pub fn migrate_windows<F: FnMut(i32)>(
+    migration: &WindowsMigration,
+    mount_dir: &Path,
+    temp_dir: &Path,
+    user: &UserAccountCreate,
+    mut callback: F,
+) -> io::Result<()> {
+    let home = mount_dir.join("home").join(&user.username);
+    let metadata = home
+        .metadata()
+        .with_context(|err| format!("home of {} was not found: {}", user.username, err))?;
+    let (uid, gid) = (metadata.uid(), metadata.gid());
+
+    let tempdir = TempDir::new_in(temp_dir, "distinst")
+        .with_context(|err| format!("creating tempdir for migration: {}", err))?;
+    let base = tempdir.path();
+
+    info!("mounting {:?} to migrate user data", migration.device);
+    let _mount = Mount::new(&migration.device, base, "ntfs", MountFlags::RDONLY, None)
+        .with_context(|err| format!("failed to mount {:?}: {}", migration.device, err))?
+        .into_unmount_drop(UnmountFlags::DETACH);
+
+    match detect_os_from_path(base) {
+        Some(OS::Windows(_)) => (),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Windows was not found on {:?}", migration.device),
+            ))
+        }
+    }
+
+    let profile = base.join("Users").join(&migration.user);
+    if !profile.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Windows user {} was not found", migration.user),
+        ));
+    }
+
+    let required: u64 = FOLDERS.iter().map(|folder| size_of(&profile.join(folder))).sum();
+    let (_, available) = misc::fs_usage(&home)?;
+
+    info!(
+        "migrating {} bytes from {}; {} bytes are available",
+        required, migration.user, available
+    );
+
+    if required > available {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "not enough space to migrate user data: {} bytes required, {} available",
+                required, available
+            ),
+        ));
+    }
+
+    callback(5);
+
+    let mut copier = Copier { uid, gid, copied: 0, total: required.max(1), callback };
+    for folder in FOLDERS {
+        let source = profile.join(folder);
+        if source.is_dir() {
+            copier
+                .copy(&source, &home.join(folder))
+                .with_context(|err| format!("failed to migrate {}: {}", folder, err))?;
+        }
+    }
+
+    (copier.callback)(100);
+    Ok(())
+}
+
+/// Copies files recursively, assigning them to the new user, and reporting progress by bytes.
+struct Copier<F> {
+    uid:      u32,
+    gid:      u32,
+    copied:   u64,
+    total:    u64,
+    callback: F,
+}
+
+impl<F: FnMut(i32)> Copier<F> {
+    fn copy(&mut self, source: &Path, target: &Path) -> io::Result<()> {
+        fs::create_dir_all(target)?;
+        chown(target, self.uid, self.gid)?;
+
+        for entry in source.read_dir()? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let target = target.join(entry.file_name());
+
+            // Junctions and shortcuts on NTFS are exposed as symlinks, which point outside of
+            // the new install, and are therefore skipped.
+            if file_type.is_dir() {
+                self.copy(&entry.path(), &target)?;
+            } else if file_type.is_file() && !target.exists() {
+                self.copied += misc::copy::copy_file(&entry.path(), &target)?;
+                chown(&target, self.uid, self.gid)?;
+                (self.callback)(5 + (self.copied * 95 / self.total) as i32);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of bytes in all files beneath the path.
+fn size_of(path: &Path) -> u64 {
+    match path.symlink_metadata() {
+        Ok(ref metadata) if metadata.is_dir() => path
+            .read_dir()
+            .map(|entries| entries.filter_map(Result::ok).map(|e| size_of(&e.path())).sum())
+            .unwrap_or(0),
+        Ok(ref metadata) if metadata.is_file() => metadata.len(),
+        _ => 0,
+    }
+}
+
+fn chown(path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::chown(cpath.as_ptr(), uid, gid) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -1,9 +1,21 @@
 mod bootloader;
 mod configure;
+mod encrypt;
+mod extract;
+mod factory_image;
+mod fstab;
 mod initialize;
+mod initramfs;
+mod migrate;
 mod partition;
+mod repair;
+mod verity;
+mod wsl;
 
-pub use self::{bootloader::*, configure::*, initialize::*, partition::*};
+pub use self::{
+    bootloader::*, configure::*, encrypt::*, extract::*, factory_image::*, fstab::*, initialize::*,
+    initramfs::*, migrate::*, partition::*, repair::*, verity::*, wsl::*,
+};
 
 use std::{
     borrow::Cow,
@@ -24,6 +36,59 @@ pub enum Step {
     Extract,
     Configure,
     Bootloader,
+    FactoryImage,
+    Migrate,
+    Export,
+}
+
+impl Step {
+    /// The share of a typical install's duration that is spent in this step.
+    pub fn weight(self) -> u32 {
+        match self {
+            Step::Backup => 5,
+            Step::Init => 0,
+            Step::Partition => 5,
+            Step::Extract => 70,
+            Step::Configure => 20,
+            Step::Bootloader => 5,
+            Step::FactoryImage => 40,
+            Step::Migrate => 10,
+            Step::Export => 10,
+        }
+    }
+
+    /// Whether the step is dominated by disk and CPU usage, and thus performed with the
+    /// priority of the config, if one was given.
+    pub fn is_heavy(self) -> bool {
+        match self {
+            Step::Backup
+            | Step::Extract
+            | Step::Configure
+            | Step::FactoryImage
+            | Step::Migrate
+            | Step::Export => true,
+            Step::Init | Step::Partition | Step::Bootloader => false,
+        }
+    }
+}
+
+/// The task within a step that is currently being performed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Substep {
+    Fstab,
+    Locales,
+    Packages,
+    Drivers,
+    Recovery,
+    Bootloader,
+    Keyboard,
+    Initramfs,
+    /// The key derivation of a new LUKS volume is being benchmarked.
+    LuksBenchmark,
+    /// A new LUKS volume is being formatted.
+    LuksFormat,
+    /// A LUKS volume is being unlocked.
+    LuksOpen,
 }
 
 fn mount_cdrom(mount_dir: &Path) -> io::Result<Option<(UnmountDrop<Mount>, PathBuf)>> {
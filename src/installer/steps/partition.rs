@@ -1,11 +1,25 @@
-use crate::disks::{operations::FormatPartitions, Disks};
+use super::Substep;
+use crate::disks::{operations::FormatPartitions, DiskError, Disks, FileSystem};
 use crate::errors::IoContext;
-use crate::external::{blockdev, pvs, vgactivate, vgdeactivate};
+use crate::external::{
+    blockdev, btrfs_nodatacow_subvolume, btrfs_quota_enable, pvs, vgactivate, vgdeactivate,
+    LuksPhase,
+};
 use itertools::Itertools;
 use rayon::{self, prelude::*};
-use std::{collections::BTreeMap, io, path::PathBuf, thread::sleep, time::Duration};
+use std::{
+    collections::BTreeMap,
+    io, mem,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread::{self, sleep},
+    time::Duration,
+};
 
-pub fn partition<F: FnMut(i32)>(disks: &mut Disks, mut callback: F) -> io::Result<()> {
+pub fn partition<F: FnMut(Option<Substep>, i32)>(
+    disks: &mut Disks,
+    mut callback: F,
+) -> io::Result<()> {
     let (pvs_result, commit_result): (
         io::Result<BTreeMap<PathBuf, Option<String>>>,
         io::Result<()>,
@@ -38,7 +52,7 @@ pub fn partition<F: FnMut(i32)>(disks: &mut Disks, mut callback: F) -> io::Resul
 
     let pvs = commit_result.and(pvs_result)?;
 
-    callback(25);
+    callback(None, 25);
 
     // Utilizes the physical volume collection to generate a vector of volume
     // groups which we will need to deactivate pre-`blockdev`, and will be
@@ -57,7 +71,7 @@ pub fn partition<F: FnMut(i32)>(disks: &mut Disks, mut callback: F) -> io::Resul
 
     // Ensure that the logical volumes have had time to deactivate.
     sleep(Duration::from_secs(1));
-    callback(50);
+    callback(None, 50);
 
     // This is to ensure that everything's been written and the OS is ready to
     // proceed.
@@ -67,15 +81,89 @@ pub fn partition<F: FnMut(i32)>(disks: &mut Disks, mut callback: F) -> io::Resul
 
     // Give a bit of time to ensure that logical volumes can be re-activated.
     sleep(Duration::from_secs(1));
-    callback(75);
+    callback(None, 75);
 
     // Reactivate the logical volumes.
     vgs.iter().map(|vg| vgactivate(vg)).collect::<io::Result<()>>()?;
 
-    let res = disks
-        .commit_logical_partitions()
+    let res = commit_logical_partitions(disks, |phase| callback(Some(phase), 75))
         .with_context(|why| format!("failed to commit logical partitions: {}", why));
 
-    callback(100);
+    callback(None, 100);
     res
 }
+
+/// Commits the logical partitions on another thread, while the phases of the LUKS volumes
+/// which are formatted and opened are reported, as they may take long enough to seem hung.
+fn commit_logical_partitions<F: FnMut(Substep)>(
+    disks: &mut Disks,
+    mut callback: F,
+) -> Result<(), DiskError> {
+    let (sender, receiver) = mpsc::channel();
+
+    // The end of the phases is sent even if the thread panics, as the receiver would wait for
+    // the observer's sender otherwise.
+    struct Finished(mpsc::Sender<Option<LuksPhase>>);
+
+    impl Drop for Finished {
+        fn drop(&mut self) { let _ = self.0.send(None); }
+    }
+
+    let mut owned = mem::take(disks);
+    let committer = thread::spawn(move || {
+        let phases = Mutex::new(sender.clone());
+        let _finished = Finished(sender);
+        let result = owned.commit_logical_partitions(&|_: &Path, phase: LuksPhase| {
+            let _ = phases.lock().expect("LUKS phase sender poisoned").send(Some(phase));
+        });
+        (owned, result)
+    });
+
+    while let Ok(Some(phase)) = receiver.recv() {
+        callback(match phase {
+            LuksPhase::Benchmarking => Substep::LuksBenchmark,
+            LuksPhase::Formatting => Substep::LuksFormat,
+            LuksPhase::Opening => Substep::LuksOpen,
+        });
+    }
+
+    let (owned, result) = committer.join().expect("committing logical partitions panicked");
+    *disks = owned;
+    result
+}
+
+/// Enables the quotas and creates the subvolumes that were requested for Btrfs file systems,
+/// once they have been mounted beneath `mount_dir`. File systems which are reused, rather than
+/// formatted, are left as they were.
+pub fn configure_btrfs(disks: &Disks, mount_dir: &Path) -> io::Result<()> {
+    let partitions = disks.get_partitions().filter(|part| {
+        part.filesystem == Some(FileSystem::Btrfs)
+            && part.mount_point.is_none()
+            && part.will_format()
+    });
+
+    for partition in partitions {
+        let target = match partition.target {
+            Some(ref target) => target,
+            None => continue,
+        };
+
+        let mount = mount_dir.join(target.strip_prefix("/").unwrap_or(target));
+        let options = &partition.mkfs_options.btrfs;
+
+        if options.quotas {
+            btrfs_quota_enable(&mount).with_context(|why| {
+                format!("failed to enable quotas on {}: {}", mount.display(), why)
+            })?;
+        }
+
+        for subvolume in &options.nodatacow {
+            let path = mount.join(subvolume.strip_prefix("/").unwrap_or(subvolume));
+            btrfs_nodatacow_subvolume(&path).with_context(|why| {
+                format!("failed to create subvolume at {}: {}", path.display(), why)
+            })?;
+        }
+    }
+
+    Ok(())
+}
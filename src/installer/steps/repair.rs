@@ -0,0 +1,226 @@
+//! Repairs the boot of an existing install, so that recovery media may fix an install which no
+//! longer boots, without reinstalling it.
+//!
+//! The install is opened from its root partition, which is unlocked if it is encrypted, and the
+//! other partitions of its fstab are mounted with it. Entries of the fstab which refer to
+//! partitions that no longer exist, such as an ESP which was recreated, are pointed at the
+//! partitions which are found in their place.
+
+use crate::disks::{Bootloader, Disks, LvmEncryption, PartitionFlag, PartitionInfo};
+use crate::errors::IoContext;
+use crate::misc;
+use disk_types::PartitionExt;
+use os_detect::OS;
+use os_release::OsRelease;
+use partition_identity::PartitionID;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The targets of the install whose fstab entries are repaired, besides the root.
+const BOOT_TARGETS: &[&str] = &["/boot", "/boot/efi"];
+
+/// Probes the disks, and assigns the root of the install on `target_root` as a target, along
+/// with the other partitions of its fstab which are found. If `encryption` is given, `target_root`
+/// is the LUKS partition which holds the root, and it is unlocked with it.
+pub(crate) fn open_install(
+    target_root: &Path,
+    encryption: Option<&LvmEncryption>,
+) -> io::Result<(Disks, OsRelease)> {
+    let mut disks = Disks::probe_devices()
+        .with_context(|err| format!("disk probing error: {}", err))?;
+
+    let candidates: Vec<PathBuf> = match encryption {
+        Some(enc) => {
+            disks.decrypt_partition(target_root, enc).map_err(|why| {
+                io::Error::new(io::ErrorKind::Other, format!("unable to unlock the root: {}", why))
+            })?;
+
+            let device = disks
+                .get_logical_device_within_pv(&enc.physical_volume)
+                .expect("decrypted device was not added to the disks");
+
+            let partitions = device.file_system.iter().chain(device.partitions.iter());
+            partitions.map(|partition| partition.device_path.clone()).collect()
+        }
+        None => vec![target_root.to_path_buf()],
+    };
+
+    let (root, info, fstab) = candidates
+        .iter()
+        .filter_map(|path| disks.get_partition_by_path(path))
+        .find_map(|partition| match partition.probe_os() {
+            Some(OS::Linux { info, partitions, targets, .. }) => {
+                let fstab: Vec<_> = partitions.into_iter().zip(targets).collect();
+                Some((partition.device_path.clone(), info, fstab))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no Linux install was found on {}", target_root.display()),
+            )
+        })?;
+
+    info!("repairing {} on {}", info.pretty_name, root.display());
+    disks.get_partition_by_path_mut(&root).expect("root partition vanished").set_mount("/".into());
+
+    for (id, target) in fstab {
+        // Swap is listed without a mount point.
+        if !target.is_absolute() || target == Path::new("/") {
+            continue;
+        }
+
+        match disks.get_partition_by_id_mut(&id) {
+            Some(partition) => partition.set_mount(target),
+            None => warn!("{} of the install was not found at {:?}", target.display(), id),
+        }
+    }
+
+    Ok((disks, info))
+}
+
+/// If the fstab of an EFI install did not lead to its ESP, the ESP on the disk of its root is
+/// assigned in its place.
+pub(crate) fn assign_esp(
+    disks: &mut Disks,
+    target_root: &Path,
+    bootloader: Bootloader,
+) -> io::Result<()> {
+    let efi = Path::new("/boot/efi");
+    if bootloader == Bootloader::Efi && disks.get_partition_with_target(efi).is_none() {
+        let esp = find_esp(disks, target_root).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "the ESP of the install was not found")
+        })?;
+
+        info!("using {} as the ESP of the install", esp.display());
+        disks.get_partition_by_path_mut(&esp).expect("ESP vanished").set_mount(efi.into());
+    }
+
+    Ok(())
+}
+
+/// Points the fstab entries of the mounted install's root, boot, and EFI partitions at the
+/// partitions which are now mounted there, if they refer to partitions that no longer exist.
+pub(crate) fn repair_fstab(mount_dir: &Path, disks: &Disks) -> io::Result<()> {
+    let path = mount_dir.join("etc/fstab");
+    let fstab = fs::read_to_string(&path)
+        .with_context(|err| format!("unable to read the install's fstab: {}", err))?;
+
+    let mut sources = Vec::new();
+    for &target in ["/"].iter().chain(BOOT_TARGETS) {
+        let target = Path::new(target);
+        let partition = match disks.get_partition_with_target(target) {
+            Some(partition) => partition,
+            None => continue,
+        };
+
+        let current = fstab_source(&fstab, target);
+        if current.map_or(false, |source| source_is(source, partition)) {
+            continue;
+        }
+
+        let source = match partition.identifiers.uuid {
+            Some(ref uuid) => ["UUID=", uuid].concat(),
+            None => partition.device_path.display().to_string(),
+        };
+
+        info!("fstab: pointing {} at {}", target.display(), source);
+        sources.push((target, source));
+    }
+
+    if !sources.is_empty() {
+        misc::write(&path, fstab_with_sources(&fstab, &sources))?;
+    }
+
+    Ok(())
+}
+
+/// The ESP on the disk which holds the root, or the only ESP of the system.
+fn find_esp(disks: &Disks, target_root: &Path) -> Option<PathBuf> {
+    let is_esp =
+        |partition: &&PartitionInfo| partition.flags.contains(&PartitionFlag::PED_PARTITION_ESP);
+
+    if let Some(disk) = disks.get_physical_device_with_partition(target_root) {
+        if let Some(esp) = disk.partitions.iter().find(is_esp) {
+            return Some(esp.device_path.clone());
+        }
+    }
+
+    let mut esps = disks.get_physical_partitions().filter(is_esp);
+    match (esps.next(), esps.next()) {
+        (Some(esp), None) => Some(esp.device_path.clone()),
+        _ => None,
+    }
+}
+
+/// Whether the fstab's source refers to the partition.
+fn source_is(source: &str, partition: &PartitionInfo) -> bool {
+    let device = if source.starts_with('/') {
+        Some(PathBuf::from(source))
+    } else {
+        source.parse::<PartitionID>().ok().and_then(|id| id.get_device_path())
+    };
+
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    device.map_or(false, |device| canonical(&device) == canonical(&partition.device_path))
+}
+
+/// The source of the entry which mounts `target`.
+fn fstab_source<'a>(fstab: &'a str, target: &Path) -> Option<&'a str> {
+    fstab.lines().filter(|line| !line.trim_start().starts_with('#')).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some(source), Some(mount)) if Path::new(mount) == target => Some(source),
+            _ => None,
+        }
+    })
+}
+
+/// Replaces the source of each entry which mounts one of the targets in `sources`.
+fn fstab_with_sources(fstab: &str, sources: &[(&Path, String)]) -> String {
+    let mut output = String::with_capacity(fstab.len());
+    for line in fstab.lines() {
+        let mut fields = line.split_whitespace();
+        let replacement = match (fields.next(), fields.next()) {
+            (Some(source), Some(mount)) if !source.starts_with('#') => sources
+                .iter()
+                .find(|(target, _)| *target == Path::new(mount))
+                .map(|(_, new)| (source, new)),
+            _ => None,
+        };
+
+        match replacement {
+            Some((source, new)) => output.push_str(&line.replacen(source, new, 1)),
+            None => output.push_str(line),
+        }
+
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repair_fstab_sources() {
+        let fstab = "# /boot/efi was on /dev/sda1\n\
+                     PARTUUID=old  /boot/efi  vfat  umask=0077  0  0\n\
+                     UUID=1234  /  ext4  noatime,errors=remount-ro  0  1\n";
+
+        assert_eq!(fstab_source(fstab, Path::new("/boot/efi")), Some("PARTUUID=old"));
+        assert_eq!(fstab_source(fstab, Path::new("/boot")), None);
+
+        let sources = [(Path::new("/boot/efi"), "UUID=ABCD-EF01".to_owned())];
+        assert_eq!(
+            fstab_with_sources(fstab, &sources),
+            "# /boot/efi was on /dev/sda1\nUUID=ABCD-EF01  /boot/efi  vfat  umask=0077  0  0\n\
+             UUID=1234  /  ext4  noatime,errors=remount-ro  0  1\n"
+        );
+    }
+}
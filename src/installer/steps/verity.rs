@@ -0,0 +1,266 @@
+//! A read-only root, which is verified by dm-verity when it is opened, and whose changes are
+//! written to an overlay on a separate partition, for kiosks and appliances whose roots must
+//! be tamper-evident.
+//!
+//! The root is installed as usual, and then sealed once it has been unmounted, by writing its
+//! hash tree to the hash partition. The root hash is only known after sealing, so it is
+//! written to the kernel's command line on the EFI partition, which is outside of the root.
+
+use crate::disks::{Bootloader, Disks};
+use crate::errors::IoContext;
+use crate::external::veritysetup_format;
+use crate::misc;
+use partition_identity::PartitionID;
+use std::{
+    fs::{self, Permissions},
+    io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use sys_mount::*;
+use tempdir::TempDir;
+
+/// The device which the verified root is mapped to.
+const VERITY_ROOT: &str = "/dev/mapper/vroot";
+const OVERLAYROOT_CONF: &str = "etc/overlayroot.local.conf";
+const INITRAMFS_HOOK: &str = "etc/initramfs-tools/hooks/verity-root";
+const INITRAMFS_SCRIPT: &str = "etc/initramfs-tools/scripts/local-top/verity-root";
+
+/// Packages which open the verified root, and mount the overlay upon it, in the initramfs.
+pub(crate) const READ_ONLY_ROOT_PACKAGES: &[&str] = &["overlayroot", "cryptsetup-bin"];
+
+/// The partitions of a read-only root, besides the root partition itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadOnlyRoot {
+    /// The partition which the hash tree of the root is written to. Its contents are replaced.
+    pub hash_partition:    PathBuf,
+    /// A formatted partition which holds the writable overlay of the root.
+    pub overlay_partition: PathBuf,
+}
+
+impl ReadOnlyRoot {
+    /// Ensures that the layout of the install supports a read-only root, before the disks are
+    /// modified.
+    pub(crate) fn validate_layout(&self, disks: &Disks, bootloader: Bootloader) -> io::Result<()> {
+        if bootloader != Bootloader::Efi {
+            return invalid("a read-only root requires an EFI install".into());
+        }
+
+        let root = Path::new("/");
+        let physical_root = disks
+            .get_physical_partitions()
+            .any(|part| part.target.as_deref() == Some(root) && part.volume_group.is_none());
+
+        if !physical_root {
+            return invalid("a read-only root cannot be on LVM or LUKS".into());
+        }
+
+        if self.hash_partition == self.overlay_partition {
+            return invalid("the hash and overlay partitions must differ".into());
+        }
+
+        Ok(())
+    }
+
+    /// Ensures that the hash and overlay partitions may be used. They are found by their device
+    /// paths, which new partitions only have once they are created, so this is called once the
+    /// disks have been partitioned.
+    pub(crate) fn validate_partitions(&self, disks: &Disks) -> io::Result<()> {
+        for path in &[&self.hash_partition, &self.overlay_partition] {
+            let partition = match disks.get_partition_by_path(path) {
+                Some(partition) => partition,
+                None => return invalid(format!("{} was not found", path.display())),
+            };
+
+            if partition.target.is_some() || partition.volume_group.is_some() {
+                return invalid(format!("{} is already used by the install", path.display()));
+            }
+        }
+
+        let overlay = disks.get_partition_by_path(&self.overlay_partition);
+        if overlay.map_or(true, |partition| partition.filesystem.is_none()) {
+            let overlay = self.overlay_partition.display();
+            return invalid(format!("the overlay partition {} is not formatted", overlay));
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid(why: String) -> io::Result<()> { Err(io::Error::new(io::ErrorKind::InvalidInput, why)) }
+
+/// Mounts the root read-only from its verified device, and with it the overlay.
+pub(crate) fn read_only_root_fstab(fstab: &str) -> String {
+    let mut output = String::with_capacity(fstab.len());
+    for line in fstab.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [_, "/", fs, ..] if !line.starts_with('#') => {
+                output.push_str(&format!("{}  /  {}  ro  0  0\n", VERITY_ROOT, fs));
+            }
+            _ => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+
+    output
+}
+
+/// Installs the initramfs scripts which open the verified root, and points overlayroot at
+/// the overlay partition.
+pub(crate) fn configure_read_only_root(mount_dir: &Path, root: &ReadOnlyRoot) -> io::Result<()> {
+    info!("configuring a read-only root, with an overlay on {}", root.overlay_partition.display());
+    let overlay = PartitionID::get_partuuid(&root.overlay_partition).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "unable to find the PARTUUID of the overlay")
+    })?;
+
+    let scripts: [(&str, &[u8]); 2] = [
+        (INITRAMFS_HOOK, include_bytes!("../../scripts/verity-root-hook.sh")),
+        (INITRAMFS_SCRIPT, include_bytes!("../../scripts/verity-root.sh")),
+    ];
+
+    for &(path, script) in &scripts {
+        let path = mount_dir.join(path);
+        fs::create_dir_all(path.parent().expect("script without parent"))?;
+        misc::write(&path, script)?;
+        fs::set_permissions(&path, Permissions::from_mode(0o755))?;
+    }
+
+    misc::write(
+        mount_dir.join(OVERLAYROOT_CONF),
+        format!("overlayroot=\"device:dev=/dev/disk/by-partuuid/{},timeout=30\"\n", overlay.id),
+    )
+}
+
+/// Writes the hash tree of the unmounted root, and adds its root hash to the kernel's command
+/// line within the boot entries on the EFI partition.
+pub(crate) fn seal_read_only_root(disks: &Disks, root: &ReadOnlyRoot) -> io::Result<()> {
+    let ((_, root_part), efi) = disks.get_base_partitions(Bootloader::Efi);
+    let (_, efi_part) = efi.expect("EFI partition was validated");
+
+    info!("sealing {} with dm-verity", root_part.device_path.display());
+    let root_hash = veritysetup_format(&root_part.device_path, &root.hash_partition)
+        .with_context(|err| format!("writing the hash tree of the root: {}", err))?;
+
+    let partuuid = |path: &Path| {
+        PartitionID::get_partuuid(path).map(|id| id.id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unable to find the PARTUUID of {}", path.display()),
+            )
+        })
+    };
+
+    let options = format!(
+        "root={} ro verity.data=PARTUUID={} verity.hash=PARTUUID={} verity.roothash={}",
+        VERITY_ROOT,
+        partuuid(&root_part.device_path)?,
+        partuuid(&root.hash_partition)?,
+        root_hash
+    );
+
+    let efi_dir = TempDir::new("distinst")?;
+    let _efi_mount =
+        Mount::new(&efi_part.device_path, efi_dir.path(), "vfat", MountFlags::empty(), None)?
+            .into_unmount_drop(UnmountFlags::DETACH);
+
+    let mut entries = Vec::new();
+    // The recovery partition's entry boots the live system, rather than the root.
+    if let Ok(loader) = efi_dir.path().join("loader/entries").read_dir() {
+        let installed = loader
+            .flatten()
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with("Recovery"));
+        entries.extend(installed.map(|entry| (entry.path(), false)));
+    }
+
+    if let Ok(vendors) = efi_dir.path().join("EFI").read_dir() {
+        let grub = vendors.flatten().map(|vendor| vendor.path().join("grub/grub.cfg"));
+        entries.extend(grub.filter(|path| path.is_file()).map(|path| (path, true)));
+    }
+
+    if entries.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no boot entries were found on the EFI partition",
+        ));
+    }
+
+    for (path, grub) in entries {
+        info!("adding the root hash to {}", path.display());
+        let config = fs::read_to_string(&path)?;
+        misc::write(&path, kernel_options(&config, grub, &options))?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the root of each kernel command line in the boot entry with `options`.
+fn kernel_options(config: &str, grub: bool, options: &str) -> String {
+    let mut output = String::with_capacity(config.len() + options.len());
+    for line in config.lines() {
+        let trimmed = line.trim_start();
+        let is_cmdline = if grub {
+            trimmed.starts_with("linux ") || trimmed.starts_with("linux\t")
+        } else {
+            trimmed.starts_with("options ")
+        };
+
+        if is_cmdline {
+            output.push_str(&line[..line.len() - trimmed.len()]);
+            let kept = trimmed
+                .split_whitespace()
+                .filter(|arg| !arg.starts_with("root=") && *arg != "ro" && *arg != "rw");
+
+            for arg in kept {
+                output.push_str(arg);
+                output.push(' ');
+            }
+
+            output.push_str(options);
+        } else {
+            output.push_str(line);
+        }
+
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verity_kernel_options() {
+        let options = "root=/dev/mapper/vroot ro verity.roothash=ab12";
+
+        let entry = "title Pop!_OS\nlinux /EFI/Pop_OS/vmlinuz.efi\n\
+                     options root=UUID=1234 ro quiet splash\n";
+        assert_eq!(
+            kernel_options(entry, false, options),
+            "title Pop!_OS\nlinux /EFI/Pop_OS/vmlinuz.efi\n\
+             options quiet splash root=/dev/mapper/vroot ro verity.roothash=ab12\n"
+        );
+
+        let grub = "menuentry 'Ubuntu' {\n\tlinux\t/boot/vmlinuz root=UUID=1234 rw quiet\n}\n";
+        assert_eq!(
+            kernel_options(grub, true, options),
+            "menuentry 'Ubuntu' {\n\
+             \tlinux /boot/vmlinuz quiet root=/dev/mapper/vroot ro verity.roothash=ab12\n}\n"
+        );
+    }
+
+    #[test]
+    fn verity_fstab() {
+        let fstab = "# / was on /dev/sda2\nUUID=1234  /  ext4  noatime,errors=remount-ro  0  1\n\
+                     PARTUUID=5678  /boot/efi  vfat  umask=0077  0  0\n";
+        assert_eq!(
+            read_only_root_fstab(fstab),
+            "# / was on /dev/sda2\n/dev/mapper/vroot  /  ext4  ro  0  0\n\
+             PARTUUID=5678  /boot/efi  vfat  umask=0077  0  0\n"
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::errors::IoContext;
+use crate::misc;
+use crate::squashfs;
+use std::{
+    fs::{self, Permissions},
+    io,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+/// Applies the settings of WSL to a configured container, at `root`, and archives it into the
+/// tarball which `wsl --import` installs as a distribution.
+pub fn export_wsl<F: FnMut(i32)>(
+    root: &Path,
+    tarball: &Path,
+    hostname: &str,
+    user: Option<&str>,
+    mut callback: F,
+) -> io::Result<()> {
+    misc::write(root.join("etc/wsl.conf"), wsl_conf(hostname, user))
+        .with_context(|err| format!("failed to write wsl.conf: {}", err))?;
+
+    // WSL generates the hosts of the distribution as it starts, from the hostname above.
+    let hosts = root.join("etc/hosts");
+    if hosts.exists() {
+        fs::remove_file(&hosts).with_context(|err| format!("failed to remove hosts: {}", err))?;
+    }
+
+    // The root is archived as `.`, and would otherwise keep the mode of the temporary directory.
+    fs::set_permissions(root, Permissions::from_mode(0o755))?;
+    callback(10);
+
+    squashfs::create_tar(root, tarball)
+        .with_context(|err| format!("failed to create {}: {}", tarball.display(), err))?;
+
+    callback(100);
+    Ok(())
+}
+
+/// The `/etc/wsl.conf` of a distribution which is started with systemd, and which logs in as
+/// its first user, rather than as root, when one was created.
+fn wsl_conf(hostname: &str, user: Option<&str>) -> String {
+    let mut conf = format!(
+        "[boot]\nsystemd = true\n\n[network]\nhostname = {}\ngenerateHosts = true\n\
+         generateResolvConf = true\n",
+        hostname
+    );
+
+    if let Some(user) = user {
+        conf.push_str(&format!("\n[user]\ndefault = {}\n", user));
+    }
+
+    conf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wsl_conf_default_user() {
+        assert_eq!(
+            wsl_conf("pop-os", Some("jdoe")),
+            "[boot]\nsystemd = true\n\n[network]\nhostname = pop-os\ngenerateHosts = true\n\
+             generateResolvConf = true\n\n[user]\ndefault = jdoe\n"
+        );
+
+        assert!(!wsl_conf("pop-os", None).contains("[user]"));
+    }
+}
@@ -0,0 +1,176 @@
+//! An opt-in summary of each install, which distributions may collect to learn how often, and
+//! where, their installs fail. Nothing is reported unless a reporter is set on the installer.
+//!
+//! The summary is anonymous: it describes the class of the hardware, but contains nothing
+//! which identifies the user, the system, or its disks.
+
+use super::Step;
+use crate::disks::{Bootloader, CodedError, ErrorCode};
+use crate::external::post_json;
+use serde_json::Value;
+use std::{env, fs, io, time::Duration};
+
+/// Receives the summary of each install, once it has completed or failed.
+pub trait TelemetryReporter {
+    fn report(&mut self, summary: &InstallSummary) -> io::Result<()>;
+}
+
+/// Posts each summary as JSON to an HTTP endpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpReporter {
+    pub endpoint: String,
+    /// Seconds to wait for the endpoint before the summary is abandoned.
+    pub timeout:  u32,
+}
+
+impl HttpReporter {
+    pub fn new<S: Into<String>>(endpoint: S) -> Self {
+        Self { endpoint: endpoint.into(), timeout: 10 }
+    }
+}
+
+impl TelemetryReporter for HttpReporter {
+    fn report(&mut self, summary: &InstallSummary) -> io::Result<()> {
+        post_json(&self.endpoint, summary.to_json().to_string().as_bytes(), self.timeout)
+    }
+}
+
+/// The class of the hardware that the install was performed on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HardwareClass {
+    pub efi:             bool,
+    /// The architecture of the installer, such as `x86_64`.
+    pub arch:            &'static str,
+    /// The vendor of the CPU, such as `GenuineIntel`.
+    pub cpu_vendor:      Option<String>,
+    /// Memory, rounded up to whole GiB.
+    pub memory_gib:      Option<u64>,
+    /// The kind of chassis, such as `laptop` or `desktop`, according to the firmware.
+    pub chassis:         Option<&'static str>,
+    pub virtual_machine: bool,
+}
+
+impl HardwareClass {
+    /// Describes the hardware that the installer is running on.
+    pub fn detect() -> Self {
+        let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+        let (cpu_vendor, virtual_machine) = parse_cpuinfo(&cpuinfo);
+
+        HardwareClass {
+            efi: Bootloader::detect() == Bootloader::Efi,
+            arch: env::consts::ARCH,
+            cpu_vendor,
+            memory_gib: fs::read_to_string("/proc/meminfo")
+                .ok()
+                .and_then(|meminfo| parse_meminfo(&meminfo)),
+            chassis: fs::read_to_string("/sys/class/dmi/id/chassis_type")
+                .ok()
+                .and_then(|chassis| chassis.trim().parse::<u32>().ok())
+                .map(chassis_class),
+            virtual_machine,
+        }
+    }
+}
+
+/// An anonymous summary of an install.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstallSummary {
+    pub success:     bool,
+    /// How long the install took, to the second.
+    pub duration:    Duration,
+    /// The step which failed, if the failure occurred within a step.
+    pub failed_step: Option<Step>,
+    /// The reason for the failure, if it is a known one.
+    pub error:       Option<ErrorCode>,
+    pub hardware:    HardwareClass,
+}
+
+impl InstallSummary {
+    pub(crate) fn new(
+        result: &io::Result<()>,
+        failed_step: Option<Step>,
+        duration: Duration,
+    ) -> Self {
+        InstallSummary {
+            success: result.is_ok(),
+            duration: Duration::from_secs(duration.as_secs()),
+            failed_step: if result.is_ok() { None } else { failed_step },
+            error: result.as_ref().err().and_then(CodedError::of).map(|coded| coded.code),
+            hardware: HardwareClass::detect(),
+        }
+    }
+
+    /// The summary, as it is submitted by the `HttpReporter`.
+    pub fn to_json(&self) -> Value {
+        let debug = |value: &dyn std::fmt::Debug| format!("{:?}", value).to_lowercase();
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "success": self.success,
+            "duration_secs": self.duration.as_secs(),
+            "failed_step": self.failed_step.map(|step| debug(&step)),
+            "error": self.error.map(|code| debug(&code)),
+            "hardware": {
+                "firmware": if self.hardware.efi { "efi" } else { "bios" },
+                "arch": self.hardware.arch,
+                "cpu_vendor": self.hardware.cpu_vendor,
+                "memory_gib": self.hardware.memory_gib,
+                "chassis": self.hardware.chassis,
+                "virtual_machine": self.hardware.virtual_machine,
+            },
+        })
+    }
+}
+
+/// The vendor of the CPU, and whether it runs under a hypervisor.
+fn parse_cpuinfo(cpuinfo: &str) -> (Option<String>, bool) {
+    let field = |name: &str| {
+        cpuinfo.lines().find_map(|line| {
+            let mut fields = line.splitn(2, ':');
+            match (fields.next(), fields.next()) {
+                (Some(key), Some(value)) if key.trim() == name => Some(value.trim()),
+                _ => None,
+            }
+        })
+    };
+
+    let vendor = field("vendor_id").map(String::from);
+    let hypervisor =
+        field("flags").map_or(false, |flags| flags.split_whitespace().any(|f| f == "hypervisor"));
+    (vendor, hypervisor)
+}
+
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some((kib + (1 << 20) - 1) >> 20)
+}
+
+/// The SMBIOS chassis types, grouped into broad classes.
+fn chassis_class(chassis_type: u32) -> &'static str {
+    match chassis_type {
+        3..=7 | 13 | 15 | 16 | 24 | 35 | 36 => "desktop",
+        8..=10 | 14 | 31 | 32 => "laptop",
+        30 => "tablet",
+        17 | 23 | 25 | 28 | 29 => "server",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_hardware_class() {
+        let cpuinfo = "processor\t: 0\nvendor_id\t: AuthenticAMD\nflags\t\t: fpu vme hypervisor\n";
+        assert_eq!(parse_cpuinfo(cpuinfo), (Some("AuthenticAMD".into()), true));
+        assert!(!parse_cpuinfo("vendor_id\t: GenuineIntel\nflags\t\t: fpu\n").1);
+
+        let meminfo = "MemTotal:       16306432 kB\nMemFree:         1043552 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some(16));
+
+        assert_eq!(chassis_class(10), "laptop");
+        assert_eq!(chassis_class(3), "desktop");
+        assert_eq!(chassis_class(2), "other");
+    }
+}
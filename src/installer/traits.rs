@@ -11,6 +11,7 @@ use std::{
     borrow::Cow,
     ffi::{OsStr, OsString},
     io,
+    path::Path,
 };
 
 pub trait InstallerDiskOps: Sync {
@@ -22,6 +23,10 @@ pub trait InstallerDiskOps: Sync {
 
     /// Reports file systems that need to be supported in the install.
     fn get_support_flags(&self) -> FileSystemSupport;
+
+    /// Whether the install is to a directory, for a container, which is booted by neither a
+    /// bootloader nor a kernel of its own.
+    fn is_container(&self) -> bool { false }
 }
 
 impl InstallerDiskOps for Disks {
@@ -54,6 +59,7 @@ impl InstallerDiskOps for Disks {
 
         let mut swap_uuids: Vec<u64> = Vec::new();
         let mut crypt_ids: Vec<u64> = Vec::new();
+        let mut mounts: Vec<BlockInfo> = Vec::new();
 
         for (is_unencrypted, luks_parent, partition) in partitions {
             if let Some(&(_, Some(ref enc))) = partition.volume_group.as_ref() {
@@ -103,9 +109,7 @@ impl InstallerDiskOps for Disks {
                         }
                     }
                 }
-                if let Some(blockinfo) = partition.get_block_info() {
-                    blockinfo.write_entry(&mut fstab);
-                }
+                mounts.extend(partition.get_block_info());
             } else if partition.is_swap() {
                 if is_unencrypted {
                     match PartitionID::get_uuid(&partition.get_device_path()) {
@@ -137,10 +141,17 @@ impl InstallerDiskOps for Disks {
                     fstab.push("  none  swap  defaults  0  0\n");
                 }
             } else if let Some(blockinfo) = partition.get_block_info() {
-                blockinfo.write_entry(&mut fstab);
+                mounts.push(blockinfo);
             }
         }
 
+        // Parents must precede their children, such as `/var` before `/var/log`, as systemd
+        // and `mount -a` mount the entries of the fstab in order.
+        mounts.sort_by(|a, b| Path::new(a.mount()).cmp(Path::new(b.mount())));
+        for blockinfo in &mounts {
+            blockinfo.write_entry(&mut fstab);
+        }
+
         info!("generated the following crypttab data:\n{}", crypttab.to_string_lossy(),);
 
         info!("generated the following fstab data:\n{}", fstab.to_string_lossy());
@@ -31,7 +31,7 @@ extern crate derive_more;
 extern crate dirs;
 pub extern crate distinst_timezones as timezones;
 extern crate distinst_utils as misc;
-extern crate envfile;
+pub extern crate envfile;
 extern crate failure;
 #[macro_use]
 extern crate failure_derive;
@@ -46,24 +46,36 @@ extern crate libparted;
 extern crate log;
 extern crate logind_dbus;
 extern crate rayon;
+#[macro_use]
+extern crate serde_json;
 extern crate systemd_boot_conf;
 extern crate tempdir;
 
 pub use crate::bootloader::*;
 pub use disk_types::*;
 pub use crate::disks::*;
-pub use crate::misc::device_layout_hash;
+pub use crate::misc::{
+    device_layout_hash,
+    journal::JournalEntry,
+    priority::{IoClass, Priority},
+};
 pub use crate::upgrade::*;
 
 pub use self::installer::RecoveryEnv;
 
 mod distribution;
+mod estimate;
 mod installer;
 mod logging;
+mod rescue;
+mod sizes;
 mod upgrade;
 
 pub mod auto;
+pub mod crypt;
+pub mod geo;
 pub(crate) mod errors;
+pub mod testing;
 
 /// Useful DBus interfaces for installers to implement.
 pub mod dbus_interfaces {
@@ -82,7 +94,9 @@ use partition_identity::PartitionID;
 use sys_mount::*;
 use systemd_boot_conf::SystemdBootConf;
 
-pub use self::{installer::*, logging::log};
+pub use self::{
+    estimate::*, installer::*, logging::log, rescue::RescueSession, sizes::PartitionSizes,
+};
 
 /// When set to true, this will stop the installation process.
 pub static KILL_SWITCH: AtomicBool = AtomicBool::new(false);
@@ -95,12 +109,13 @@ pub static PARTITIONING_TEST: AtomicBool = AtomicBool::new(false);
 /// Even if the system is EFI, the efivars directory will not be mounted in the chroot.
 pub static NO_EFI_VARIABLES: AtomicBool = AtomicBool::new(false);
 
-/// 500 MiB EFI partition
+/// 500 MiB EFI partition, in 512-byte sectors, which `PartitionSizes` defaults to.
 pub const DEFAULT_ESP_SECTORS: u64 = 1_024_000;
 
-/// 4096 MiB recovery partition
+/// 4096 MiB recovery partition, in 512-byte sectors, which `PartitionSizes` defaults to.
 pub const DEFAULT_RECOVER_SECTORS: u64 = 8_388_608;
 
+/// 4096 MiB swap partition, in 512-byte sectors, which `PartitionSizes` defaults to.
 pub const DEFAULT_SWAP_SECTORS: u64 = DEFAULT_RECOVER_SECTORS;
 
 /// Checks if the given name already exists as a device in the device map list.
@@ -118,7 +133,14 @@ pub fn device_map_exists(name: &str) -> bool {
 /// - The size of a default recovery partition.
 ///
 /// The input parameter will undergo a max comparison to the estimated minimum requirement.
+///
+/// `estimated_install_size` should be preferred, as it accounts for the install configuration.
 pub fn minimum_disk_size(default: u64) -> u64 {
+    minimum_disk_size_with(default, &PartitionSizes::default())
+}
+
+/// As `minimum_disk_size`, with partitions of the given sizes instead of the defaults.
+pub fn minimum_disk_size_with(default: u64, sizes: &PartitionSizes) -> u64 {
     let casper = std::fs::read_to_string("/cdrom/casper/filesystem.size")
         .ok()
         // File contains a number in bytes
@@ -128,12 +150,18 @@ pub fn minimum_disk_size(default: u64) -> u64 {
 
     // EFI installs will contain an EFI partition with a recovery partition.
     let bootloader = if Bootloader::detect() == Bootloader::Efi {
-        DEFAULT_ESP_SECTORS + DEFAULT_RECOVER_SECTORS
+        sizes.esp_sectors(512) + sizes.recovery_sectors(512)
     } else {
         0
     };
 
-    casper + bootloader + DEFAULT_SWAP_SECTORS
+    casper + bootloader + sizes.swap_sectors(512)
+}
+
+/// The kernel loader which installs of the running system's distribution use.
+pub fn kernel_loader(bootloader: Bootloader) -> KernelLoader {
+    let name = os_release::OS_RELEASE.as_ref().map_or("", |release| release.name.as_str());
+    KernelLoader::of(bootloader, name)
 }
 
 pub fn unset_mode() -> anyhow::Result<()> {
@@ -0,0 +1,192 @@
+//! A session within an existing install, so that recovery frontends may offer a terminal in the
+//! installed system, with its file systems mounted as they are when it boots.
+
+use crate::chroot::Chroot;
+use crate::disks::{Disks, LvmEncryption};
+use crate::errors::IoContext;
+use crate::external::luks::deactivate_logical_devices;
+use crate::installer::steps::{mount_efivars, open_install, regenerate_fstabs, FstabChanges};
+use crate::misc::{self, journal, unmount};
+use os_release::OsRelease;
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+    time::Instant,
+};
+use sys_mount::{Mount, Mounts, UnmountDrop};
+use tempdir::TempDir;
+
+const RESOLV_CONF: &str = "etc/resolv.conf";
+const RESOLV_CONF_BACKUP: &str = "etc/resolv.conf.distinst";
+
+/// An existing install, which is mounted with the binds that a chroot requires until the
+/// session is closed or dropped.
+pub struct RescueSession {
+    disks:       Disks,
+    os_release:  OsRelease,
+    chroot:      Option<Chroot<'static>>,
+    efivars:     Option<UnmountDrop<Mount>>,
+    mounts:      Option<Mounts>,
+    /// Unlike a `TempDir`, this is only removed once the install has been unmounted from it.
+    mount_dir:   PathBuf,
+    resolv_conf: bool,
+}
+
+impl RescueSession {
+    /// Mounts the install whose root is on `target_root`, along with the partitions of its
+    /// fstab. If the root is encrypted, `target_root` is its LUKS partition, which is unlocked
+    /// with `encryption`.
+    pub fn open(target_root: &Path, encryption: Option<&LvmEncryption>) -> io::Result<Self> {
+        let (disks, os_release) = open_install(target_root, encryption)?;
+
+        let mount_dir = TempDir::new("distinst")
+            .with_context(|err| format!("rescue mount directory: {}", err))?
+            .into_path();
+
+        let mounts = disks.mount_all_targets(&mount_dir);
+        let mut session = RescueSession {
+            disks,
+            os_release,
+            chroot: None,
+            efivars: None,
+            mounts: None,
+            mount_dir,
+            resolv_conf: false,
+        };
+
+        // Should a step fail, the steps before it are undone as the session is dropped.
+        session.mounts = Some(mounts?);
+        session.chroot = Some(Chroot::new(&session.mount_dir)?);
+        session.efivars = mount_efivars(&session.mount_dir)?;
+        session.resolv_conf = replace_resolv_conf(&session.mount_dir)
+            .with_context(|err| format!("replacing resolv.conf: {}", err))?;
+
+        info!("opened a rescue session in {}", session.mount_dir.display());
+        Ok(session)
+    }
+
+    /// The directory which the install is mounted to.
+    pub fn path(&self) -> &Path { &self.mount_dir }
+
+    /// The os-release of the install.
+    pub fn os_release(&self) -> &OsRelease { &self.os_release }
+
+    /// The fstab and crypttab of the install, regenerated from the partitions which it is now
+    /// mounted from. Nothing is written until the changes are reviewed and written.
+    pub fn regenerate_fstabs(&self) -> io::Result<FstabChanges> {
+        regenerate_fstabs(&self.mount_dir, &self.disks)
+    }
+
+    /// Runs commands within the install, with their output captured.
+    pub fn chroot(&mut self) -> &mut Chroot<'static> {
+        self.chroot.as_mut().expect("rescue session without a chroot")
+    }
+
+    /// Runs `program` within the install. It inherits the standard streams of the caller, so
+    /// that it may be run interactively within a terminal, and so its output is not journaled.
+    pub fn interactive<S: AsRef<OsStr>>(
+        &self,
+        program: S,
+        args: &[&str],
+    ) -> io::Result<ExitStatus> {
+        let mut argv = vec![self.mount_dir.as_os_str(), program.as_ref()];
+        argv.extend(args.iter().map(OsStr::new));
+
+        let started = Instant::now();
+        let status = Command::new("chroot").args(&argv).status();
+        let code = status.as_ref().ok().and_then(ExitStatus::code);
+        journal::record("chroot", &argv, started, code, "");
+        status
+    }
+
+    /// Runs a login shell within the install, until it exits.
+    pub fn shell(&self) -> io::Result<ExitStatus> { self.interactive("/bin/bash", &["--login"]) }
+
+    /// Unmounts the install, and locks it again if it was encrypted.
+    pub fn close(mut self) -> io::Result<()> { self.teardown() }
+
+    /// Every step is attempted, even if one before it fails, so that as much of the install is
+    /// unmounted and locked as can be. The first error is returned.
+    fn teardown(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        let mut keep = |step: io::Result<()>| {
+            if let Err(why) = step {
+                if result.is_ok() {
+                    result = Err(why);
+                } else {
+                    error!("rescue teardown: {}", why);
+                }
+            }
+        };
+
+        if self.resolv_conf {
+            self.resolv_conf = false;
+            keep(
+                restore_resolv_conf(&self.mount_dir)
+                    .with_context(|err| format!("restoring resolv.conf: {}", err)),
+            );
+        }
+
+        self.efivars = None;
+        if let Some(mut chroot) = self.chroot.take() {
+            keep(chroot.unmount(false));
+        }
+
+        if let Some(mounts) = self.mounts.take() {
+            // Busy mounts are retried, and then unmounted lazily.
+            keep(
+                unmount::unmount_tree(&self.mount_dir)
+                    .with_context(|err| format!("rescue unmount: {}", err)),
+            );
+            drop(mounts);
+        }
+
+        if self.mount_dir.exists() {
+            keep(fs::remove_dir(&self.mount_dir));
+        }
+
+        keep(deactivate_logical_devices());
+        result
+    }
+}
+
+impl Drop for RescueSession {
+    fn drop(&mut self) {
+        if let Err(why) = self.teardown() {
+            error!("unable to close the rescue session: {}", why);
+        }
+    }
+}
+
+/// Names are resolved within the install by the resolver of the live system. The install's
+/// own config, which is often a link into `/run`, is kept aside until the session is closed.
+fn replace_resolv_conf(root: &Path) -> io::Result<bool> {
+    let resolv_conf = match fs::read("/etc/resolv.conf") {
+        Ok(resolv_conf) => resolv_conf,
+        Err(_) => return Ok(false),
+    };
+
+    let (path, backup) = (root.join(RESOLV_CONF), root.join(RESOLV_CONF_BACKUP));
+
+    // A backup which remains from a session that was not closed holds the original.
+    if fs::symlink_metadata(&backup).is_err() && fs::symlink_metadata(&path).is_ok() {
+        fs::rename(&path, &backup)?;
+    } else {
+        let _ = fs::remove_file(&path);
+    }
+
+    misc::write(&path, resolv_conf)?;
+    Ok(true)
+}
+
+fn restore_resolv_conf(root: &Path) -> io::Result<()> {
+    let (path, backup) = (root.join(RESOLV_CONF), root.join(RESOLV_CONF_BACKUP));
+    fs::remove_file(&path)?;
+    if fs::symlink_metadata(&backup).is_ok() {
+        fs::rename(&backup, &path)?;
+    }
+
+    Ok(())
+}
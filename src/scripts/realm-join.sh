@@ -0,0 +1,21 @@
+#!/bin/sh
+#
+# Joins the domain which was given at install time, and then removes the
+# credentials that it was joined with, so that the join is not repeated.
+
+set -e
+
+CREDENTIALS=/etc/distinst/realm-join
+
+. "$CREDENTIALS"
+
+set -- "$DOMAIN"
+[ -z "$COMPUTER_OU" ] || set -- "$@" --computer-ou="$COMPUTER_OU"
+
+if [ -n "$ONE_TIME_PASSWORD" ]; then
+    realm join --unattended --one-time-password="$ONE_TIME_PASSWORD" "$@"
+else
+    printf '%s\n' "$PASSWORD" | realm join --user="$JOIN_USER" "$@"
+fi
+
+rm -f "$CREDENTIALS"
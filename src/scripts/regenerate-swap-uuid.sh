@@ -0,0 +1,44 @@
+#!/bin/sh
+#
+# Assigns a new UUID to each swap partition that is referenced by
+# /etc/crypttab or /etc/fstab, and to each swapfile in /etc/fstab, so
+# that clones of an image do not share them.
+
+set -e
+
+regenerate() {
+    uuid="${2#UUID=}"
+    [ "$uuid" != "$2" ] || return 0
+    device="$(blkid -U "$uuid")" || return 0
+
+    new="$(uuidgen)"
+    swaplabel -U "$new" "$device"
+    sed -i "s/UUID=$uuid/UUID=$new/" "$1"
+}
+
+grep -v '^#' /etc/crypttab | while read -r name source _key options; do
+    case "$options" in
+        *swap*) regenerate /etc/crypttab "$source" ;;
+    esac
+done
+
+fstab="$(cat /etc/fstab)"
+
+grep -v '^#' /etc/fstab | while read -r source _target type _options; do
+    [ "$type" = swap ] || continue
+
+    case "$source" in
+        # A swapfile is referenced by its path, which keeps on matching it.
+        /*)
+            if [ -f "$source" ]; then
+                swaplabel -U "$(uuidgen)" "$source"
+            fi
+            ;;
+        UUID=*) regenerate /etc/fstab "$source" ;;
+    esac
+done
+
+# The swap units were generated from the UUIDs in fstab before they changed.
+if [ "$fstab" != "$(cat /etc/fstab)" ]; then
+    systemctl daemon-reload
+fi
@@ -0,0 +1,22 @@
+#!/bin/sh
+#
+# Adds veritysetup to the initramfs, so that a read-only root may be
+# verified by dm-verity before it is mounted.
+
+PREREQ=""
+
+prereqs() {
+    echo "$PREREQ"
+}
+
+case "$1" in
+    prereqs)
+        prereqs
+        exit 0
+        ;;
+esac
+
+. /usr/share/initramfs-tools/hook-functions
+
+copy_exec /sbin/veritysetup /sbin
+manual_add_modules dm_verity
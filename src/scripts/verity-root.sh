@@ -0,0 +1,38 @@
+#!/bin/sh
+#
+# Opens the dm-verity mapping of a read-only root, as given by the
+# verity.data, verity.hash, and verity.roothash parameters of the kernel's
+# command line, so that the root may be mounted from /dev/mapper/vroot.
+
+PREREQ=""
+
+prereqs() {
+    echo "$PREREQ"
+}
+
+case "$1" in
+    prereqs)
+        prereqs
+        exit 0
+        ;;
+esac
+
+. /scripts/functions
+
+for param in $(cat /proc/cmdline); do
+    case "$param" in
+        verity.data=*) data="${param#verity.data=}" ;;
+        verity.hash=*) hash="${param#verity.hash=}" ;;
+        verity.roothash=*) roothash="${param#verity.roothash=}" ;;
+    esac
+done
+
+[ -n "$roothash" ] || exit 0
+
+wait_for_udev 10
+data="$(resolve_device "$data")"
+hash="$(resolve_device "$hash")"
+
+if ! veritysetup open "$data" vroot "$hash" "$roothash"; then
+    panic "the root file system could not be verified"
+fi
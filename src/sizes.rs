@@ -0,0 +1,77 @@
+//! The sizes of the partitions which are created alongside the root, which distributions and
+//! firmwares may need to differ from the defaults.
+
+use crate::{DEFAULT_ESP_SECTORS, DEFAULT_RECOVER_SECTORS, DEFAULT_SWAP_SECTORS};
+use std::io;
+
+/// The smallest ESP which firmwares are known to accept, as `verify_partitions` requires.
+const MINIMUM_ESP_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Sizes, in bytes, of the partitions which the auto planner creates besides the root. Each
+/// is converted to the sectors of the device that it is created on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PartitionSizes {
+    /// The EFI system partition, or the boot partition of an encrypted BIOS install.
+    pub esp:      u64,
+    /// The recovery partition of an EFI install, or `0` to create none.
+    pub recovery: u64,
+    /// The swap partition, or `0` to create none.
+    pub swap:     u64,
+}
+
+impl Default for PartitionSizes {
+    fn default() -> Self {
+        PartitionSizes {
+            esp:      DEFAULT_ESP_SECTORS * 512,
+            recovery: DEFAULT_RECOVER_SECTORS * 512,
+            swap:     DEFAULT_SWAP_SECTORS * 512,
+        }
+    }
+}
+
+impl PartitionSizes {
+    /// Ensures that the ESP is large enough to be accepted by the firmware.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.esp < MINIMUM_ESP_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the ESP must be at least {} bytes", MINIMUM_ESP_SIZE),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The sectors of the ESP, on a device with sectors of `sector_size` bytes.
+    pub fn esp_sectors(&self, sector_size: u64) -> u64 { to_sectors(self.esp, sector_size) }
+
+    /// The sectors of the recovery partition, on a device with sectors of `sector_size` bytes.
+    pub fn recovery_sectors(&self, sector_size: u64) -> u64 {
+        to_sectors(self.recovery, sector_size)
+    }
+
+    /// The sectors of the swap partition, on a device with sectors of `sector_size` bytes.
+    pub fn swap_sectors(&self, sector_size: u64) -> u64 { to_sectors(self.swap, sector_size) }
+}
+
+/// The number of sectors which hold `bytes`, rounded up.
+fn to_sectors(bytes: u64, sector_size: u64) -> u64 { (bytes + sector_size - 1) / sector_size }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_size_sectors() {
+        let sizes = PartitionSizes::default();
+        assert_eq!(sizes.esp_sectors(512), DEFAULT_ESP_SECTORS);
+        assert_eq!(sizes.esp_sectors(4096), DEFAULT_ESP_SECTORS / 8);
+        assert_eq!(sizes.swap_sectors(512), DEFAULT_SWAP_SECTORS);
+
+        let sizes = PartitionSizes { esp: 1_000_000_001, recovery: 0, ..sizes };
+        assert_eq!(sizes.esp_sectors(4096), 244_141);
+        assert_eq!(sizes.recovery_sectors(512), 0);
+        assert!(sizes.validate().is_ok());
+        assert!(PartitionSizes { esp: 100 << 20, ..sizes }.validate().is_err());
+    }
+}
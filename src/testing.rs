@@ -0,0 +1,180 @@
+//! Loop devices with prepared layouts, for testing install flows against real block devices.
+//!
+//! Each `LoopDisk` is backed by a sparse file in a temporary directory, and is detached when it
+//! is dropped. Creating one requires root, as well as the tools that distinst itself requires.
+//!
+//! ```no_run
+//! use distinst::testing::{partitioning_test, LoopDisk, Topology};
+//! use distinst::*;
+//!
+//! let loop_disk = LoopDisk::new(16 * 1024 * 1024 * 1024, Topology::Windows).unwrap();
+//! let mut disks = Disks::default();
+//! disks.add(loop_disk.disk().unwrap());
+//!
+//! // Configure `disks`, and then run the install up to the extract step.
+//! let config = Config {
+//!     hostname: "pop-testing".into(),
+//!     keyboard_layout: "us".into(),
+//!     lang: "en_US.UTF-8".into(),
+//!     remove: "tests/filesystem.manifest-remove".into(),
+//!     squashfs: "tests/filesystem.squashfs".into(),
+//!     ..Config::default()
+//! };
+//!
+//! partitioning_test(disks, &config).unwrap();
+//! ```
+
+use crate::external::{losetup_attach, losetup_detach};
+use crate::{Config, Installer, PARTITIONING_TEST};
+use disk_types::{FileSystem, PartitionTable, PartitionTableExt, Sector, SectorExt};
+use crate::disks::{Disk, DiskExt, Disks, PartitionBuilder, PartitionFlag};
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+use tempdir::TempDir;
+
+/// A layout that a loop disk is prepared with, before a flow is tested against it.
+#[derive(Clone, Debug)]
+pub enum Topology {
+    /// A disk without a partition table.
+    Empty,
+    /// A partition table without any partitions.
+    Table(PartitionTable),
+    /// A GPT disk laid out in the manner of a Windows install: an ESP, a Microsoft reserved
+    /// partition, and a NTFS partition which fills the rest of the disk.
+    Windows,
+    /// A GPT disk with an ESP, and a physical volume of the given volume group which fills the
+    /// rest of the disk.
+    Lvm(String),
+}
+
+impl Topology {
+    fn prepare(&self, device: &Path) -> io::Result<()> {
+        let table = match *self {
+            Topology::Empty => return Ok(()),
+            Topology::Table(table) => table,
+            Topology::Windows | Topology::Lvm(_) => PartitionTable::Gpt,
+        };
+
+        let mut disk = Disk::from_name(device)?;
+        disk.mklabel(table)?;
+
+        let start = disk.get_sector(Sector::Start);
+        let esp_end = disk.get_sector(Sector::Megabyte(512));
+        let end = disk.get_sector(Sector::End);
+        let esp = PartitionBuilder::new(start, esp_end, FileSystem::Fat32)
+            .flag(PartitionFlag::PED_PARTITION_ESP);
+
+        match *self {
+            Topology::Windows => {
+                let reserved_end = disk.get_sector(Sector::Megabyte(528));
+                disk.add_partition(esp)?;
+                disk.add_partition(
+                    PartitionBuilder::new(esp_end, reserved_end, None)
+                        .flag(PartitionFlag::PED_PARTITION_MSFT_RESERVED),
+                )?;
+                disk.add_partition(PartitionBuilder::new(reserved_end, end, FileSystem::Ntfs))?;
+            }
+            Topology::Lvm(ref group) => {
+                disk.add_partition(esp)?;
+                disk.add_partition(
+                    PartitionBuilder::new(esp_end, end, FileSystem::Lvm)
+                        .logical_volume(group.clone(), None),
+                )?;
+            }
+            Topology::Empty | Topology::Table(_) => (),
+        }
+
+        let mut disks = Disks::default();
+        disks.add(disk);
+        disks.initialize_volume_groups()?;
+        Installer::default().partition_only(disks)
+    }
+}
+
+/// The layout of a disk, as it was found on the system.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub table:      Option<PartitionTable>,
+    /// The number and file system of each partition, in order.
+    pub partitions: Vec<(i32, Option<FileSystem>)>,
+}
+
+/// A sparse file attached to a loop device, which is detached and removed when dropped.
+pub struct LoopDisk {
+    device: PathBuf,
+    _dir:   TempDir,
+}
+
+impl LoopDisk {
+    /// Attaches a sparse file of `size` bytes to a loop device, and prepares its layout.
+    pub fn new(size: u64, topology: Topology) -> io::Result<Self> {
+        let dir = TempDir::new("distinst-loop")?;
+        let file = dir.path().join("disk.img");
+        File::create(&file)?.set_len(size)?;
+
+        let loop_disk = LoopDisk { device: losetup_attach(&file)?, _dir: dir };
+        topology.prepare(&loop_disk.device)?;
+        Ok(loop_disk)
+    }
+
+    /// The path of the loop device, such as `/dev/loop0`.
+    pub fn path(&self) -> &Path { &self.device }
+
+    /// Probes the loop device, for adding to a `Disks` configuration.
+    pub fn disk(&self) -> io::Result<Disk> { Disk::from_name(&self.device).map_err(Into::into) }
+
+    /// Probes the layout of the loop device, for comparing against the expected layout.
+    pub fn layout(&self) -> io::Result<Layout> {
+        let disk = self.disk()?;
+        Ok(Layout {
+            table:      disk.get_partition_table(),
+            partitions: disk
+                .get_partitions()
+                .iter()
+                .map(|partition| (partition.number, partition.filesystem))
+                .collect(),
+        })
+    }
+}
+
+impl Drop for LoopDisk {
+    fn drop(&mut self) {
+        if let Err(why) = losetup_detach(&self.device) {
+            error!("failed to detach {}: {}", self.device.display(), why);
+        }
+    }
+}
+
+/// Runs an install in `PARTITIONING_TEST` mode, which stops before the image is extracted.
+pub fn partitioning_test(disks: Disks, config: &Config) -> io::Result<()> {
+    let previous = PARTITIONING_TEST.swap(true, Ordering::SeqCst);
+    let result = Installer::default().install(disks, config);
+    PARTITIONING_TEST.store(previous, Ordering::SeqCst);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn windows_topology() {
+        let loop_disk = LoopDisk::new(8 * 1024 * 1024 * 1024, Topology::Windows).unwrap();
+        assert_eq!(
+            loop_disk.layout().unwrap(),
+            Layout {
+                table:      Some(PartitionTable::Gpt),
+                partitions: vec![
+                    (1, Some(FileSystem::Fat32)),
+                    (2, None),
+                    (3, Some(FileSystem::Ntfs)),
+                ],
+            }
+        );
+    }
+}